@@ -0,0 +1,44 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, BenchmarkId, Criterion,
+};
+use rand_core::OsRng;
+use upa_circuits::{
+    batch_verify::universal::types::{
+        BatchEntries, UniversalBatchVerifierInputs,
+    },
+    utils::benchmarks::batch_entries::{
+        assign_batch_entries, assign_batch_entries_config,
+    },
+};
+
+/// Benchmarks the serial and parallel assignment of the universal batch
+/// verifier batch entries.
+pub fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("UBV_Assign_Batch_Entries");
+    group.sample_size(10);
+
+    let config = black_box(assign_batch_entries_config());
+    let mut rng = black_box(OsRng);
+    let ubv_inputs = black_box(UniversalBatchVerifierInputs::sample_mixed(
+        &config, &mut rng,
+    ));
+    let entries =
+        BatchEntries::from_ubv_inputs_and_config(&ubv_inputs, &config);
+
+    for parallel in [false, true] {
+        let name = if parallel { "parallel" } else { "serial" };
+        group.bench_with_input(
+            BenchmarkId::new(name, config.inner_batch_size),
+            &entries,
+            |bencher, entries| {
+                bencher.iter(|| {
+                    black_box(assign_batch_entries(&config, entries, parallel))
+                })
+            },
+        );
+    }
+    group.finish()
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, BenchmarkId, Criterion,
+};
+use rand_core::OsRng;
+use upa_circuits::batch_verify::common::native::unsafe_proof_generation::{
+    create_proofs_and_inputs, UnsafeVerificationKey,
+};
+
+/// Number of proofs generated in each benchmark iteration
+const NUM_PROOFS: usize = 256;
+
+/// Number of public inputs of the fake VKs
+const NUM_PUBLIC_INPUTS: usize = 16;
+
+/// Benchmarks the serial and parallel generation of fake Groth16 proofs, as
+/// done by `groth16 generate-proofs`.
+pub fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Generate_Proofs");
+    group.sample_size(10);
+
+    let mut rng = OsRng;
+    let uvks = black_box(vec![
+        UnsafeVerificationKey::sample(NUM_PUBLIC_INPUTS, false, &mut rng),
+        UnsafeVerificationKey::sample(NUM_PUBLIC_INPUTS, true, &mut rng),
+    ]);
+
+    for parallel in [false, true] {
+        let name = if parallel { "parallel" } else { "serial" };
+        group.bench_with_input(
+            BenchmarkId::new(name, NUM_PROOFS),
+            &uvks,
+            |bencher, uvks| {
+                bencher.iter(|| {
+                    black_box(create_proofs_and_inputs(
+                        uvks,
+                        NUM_PROOFS,
+                        Some(0),
+                        parallel,
+                    ))
+                })
+            },
+        );
+    }
+    group.finish()
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
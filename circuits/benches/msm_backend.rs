@@ -0,0 +1,37 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, BenchmarkId, Criterion,
+};
+use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+use rand_core::OsRng;
+use upa_circuits::{
+    batch_verify::common::msm::{CpuMsmBackend, MsmBackend},
+    utils::benchmarks::msm::{
+        sample_points_and_scalars, scale_points, MSM_BATCH_SIZE,
+    },
+};
+
+/// Benchmarks the witness generation of `MSM_BATCH_SIZE` G1 scalar
+/// multiplications in `BatchVerifierChip::scale_pairs`, for each available
+/// `MsmBackend`.
+pub fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("MSM_Backend");
+    group.sample_size(10);
+
+    let (points, scalars) =
+        sample_points_and_scalars(MSM_BATCH_SIZE, &mut OsRng);
+    let backends: [(&str, &dyn MsmBackend<Fr>); 1] = [("cpu", &CpuMsmBackend)];
+    for (name, backend) in backends {
+        group.bench_with_input(
+            BenchmarkId::new("scale_pairs", format!("{name}/{MSM_BATCH_SIZE}")),
+            &(&points, &scalars),
+            |bencher, (points, scalars)| {
+                bencher
+                    .iter(|| black_box(scale_points(backend, points, scalars)))
+            },
+        );
+    }
+    group.finish()
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, BenchmarkId, Criterion,
+};
+use rand_core::OsRng;
+use upa_circuits::{
+    batch_verify::universal::types::{
+        BatchEntries, UniversalBatchVerifierInputs,
+    },
+    keccak::inputs::KeccakCircuitInputs,
+    utils::benchmarks::{
+        no_commitment::{
+            keccak_num_perms, no_commitment_keccak_config,
+            no_commitment_ubv_config,
+        },
+        window_bits::{ubv_advice_cell_count, ubv_witness_gen},
+    },
+};
+
+/// Reports the number of advice cells of the universal batch verifier
+/// circuit and the number of keccak permutations of the keccak circuit, for
+/// a batch without commitments, with and without `allow_commitments`.  Also
+/// benchmarks the witness generation of the universal batch verifier
+/// circuit in each case.
+pub fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("UBV_No_Commitment");
+    group.sample_size(10);
+
+    // The inputs do not depend on `allow_commitments`.
+    let mut rng = OsRng;
+    let ubv_inputs = UniversalBatchVerifierInputs::sample(
+        &no_commitment_ubv_config(true),
+        false,
+        &mut rng,
+    );
+    let keccak_inputs = KeccakCircuitInputs::sample(
+        &no_commitment_keccak_config(true),
+        &mut rng,
+    );
+
+    for allow_commitments in [true, false] {
+        let config = no_commitment_ubv_config(allow_commitments);
+        let entries =
+            BatchEntries::from_ubv_inputs_and_config(&ubv_inputs, &config);
+        println!(
+            "allow_commitments: {allow_commitments}, inner batch size: {}, \
+             UBV advice cells: {}, keccak permutations: {}",
+            config.inner_batch_size,
+            ubv_advice_cell_count(&config, &entries),
+            keccak_num_perms(
+                &no_commitment_keccak_config(allow_commitments),
+                &keccak_inputs
+            )
+        );
+        group.bench_with_input(
+            BenchmarkId::new("witness_gen", allow_commitments),
+            &entries,
+            |bencher, entries| {
+                bencher.iter(|| black_box(ubv_witness_gen(&config, entries)))
+            },
+        );
+    }
+    group.finish()
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
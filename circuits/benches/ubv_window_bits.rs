@@ -0,0 +1,50 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, BenchmarkId, Criterion,
+};
+use rand_core::OsRng;
+use upa_circuits::{
+    batch_verify::universal::types::{
+        BatchEntries, UniversalBatchVerifierInputs,
+    },
+    utils::benchmarks::window_bits::{
+        ubv_advice_cell_count, ubv_witness_gen, window_bits_config,
+        WINDOW_BITS_RANGE,
+    },
+};
+
+/// Reports the number of advice cells of the universal batch verifier
+/// circuit, and benchmarks its witness generation, for each window size in
+/// `WINDOW_BITS_RANGE`.
+pub fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("UBV_Window_Bits");
+    group.sample_size(10);
+
+    // The inputs do not depend on the window size.
+    let mut rng = OsRng;
+    let ubv_inputs = UniversalBatchVerifierInputs::sample_mixed(
+        &window_bits_config(WINDOW_BITS_RANGE[0]),
+        &mut rng,
+    );
+
+    for window_bits in WINDOW_BITS_RANGE {
+        let config = window_bits_config(window_bits);
+        let entries =
+            BatchEntries::from_ubv_inputs_and_config(&ubv_inputs, &config);
+        println!(
+            "window_bits: {window_bits}, inner batch size: {}, advice cells: {}",
+            config.inner_batch_size,
+            ubv_advice_cell_count(&config, &entries)
+        );
+        group.bench_with_input(
+            BenchmarkId::new("witness_gen", window_bits),
+            &entries,
+            |bencher, entries| {
+                bencher.iter(|| black_box(ubv_witness_gen(&config, entries)))
+            },
+        );
+    }
+    group.finish()
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
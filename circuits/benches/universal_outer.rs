@@ -99,7 +99,12 @@ fn outer_input_setup(
         )
     };
 
-    UniversalOuterCircuitInputs::new(outer_config, bv_snarks, keccak_snark)
+    UniversalOuterCircuitInputs::new(
+        outer_config,
+        keccak_config,
+        bv_snarks,
+        keccak_snark,
+    )
 }
 
 /// Generates a proving key for `outer_config`, together with consistent [`UniversalOuterCircuitInputs`].
@@ -232,7 +237,7 @@ pub fn bench(c: &mut Criterion) {
     let configs =
         black_box(load_json::<Vec<UpaConfig>>(UNIVERSAL_OUTER_CONFIG_FILE));
     for outer_config in configs {
-        let keccak_config: KeccakConfig = (&outer_config).into();
+        let keccak_config = outer_config.keccak_config();
         black_box(println!(
             "Proving Outer Circuit with config: {outer_config:#?}"
         ));
@@ -22,8 +22,7 @@ use snark_verifier::{
     system::halo2::{compile, transcript::evm::EvmTranscript, Config},
 };
 use snark_verifier_sdk::{
-    halo2::{PoseidonTranscript, POSEIDON_SPEC},
-    CircuitExt, Snark, SHPLONK,
+    halo2::PoseidonTranscript, CircuitExt, Snark, SHPLONK,
 };
 use std::time::Instant;
 use upa_circuits::{
@@ -33,7 +32,7 @@ use upa_circuits::{
     },
     keccak::{
         inputs::KeccakCircuitInputs, utils::keccak_inputs_from_ubv_instances,
-        KeccakCircuit, KeccakConfig,
+        KeccakCircuit,
     },
     outer::{
         universal, utils::gen_outer_evm_verifier, OuterCircuitInputs,
@@ -44,6 +43,7 @@ use upa_circuits::{
             keygen, CONTRACT_BYTE_LIMIT, UNIVERSAL_OUTER_CONFIG_FILE,
         },
         file::load_json,
+        hashing::SECURE_MDS,
         upa_config::UpaConfig,
     },
     SafeCircuit,
@@ -87,9 +87,8 @@ pub fn bench(c: &mut Criterion) {
             );
             let instances = circuit.instances();
             let mut transcript =
-                PoseidonTranscript::<NativeLoader, Vec<u8>>::from_spec(
+                PoseidonTranscript::<NativeLoader, Vec<u8>>::new::<SECURE_MDS>(
                     vec![],
-                    POSEIDON_SPEC.clone(),
                 );
             let proof = {
                 create_proof::<
@@ -126,7 +125,7 @@ pub fn bench(c: &mut Criterion) {
                 .collect();
 
         // Compute Keccak proof, report
-        let keccak_config: KeccakConfig = config.into();
+        let keccak_config = config.keccak_config();
         println!("Begin Keccak with config {keccak_config:?}");
         let keccak_inputs = KeccakCircuitInputs::from_inputs_and_config(
             keccak_inputs_from_ubv_instances(
@@ -150,9 +149,8 @@ pub fn bench(c: &mut Criterion) {
             );
             let instances = circuit.instances();
             let mut transcript =
-                PoseidonTranscript::<NativeLoader, Vec<u8>>::from_spec(
+                PoseidonTranscript::<NativeLoader, Vec<u8>>::new::<SECURE_MDS>(
                     vec![],
-                    POSEIDON_SPEC.clone(),
                 );
             let proof = {
                 create_proof::<
@@ -220,8 +218,12 @@ pub fn bench(c: &mut Criterion) {
         let outer_srs = gen_srs(config.outer_config.degree_bits);
         let outer_keygen_inputs =
             OuterKeygenInputs::new(&bv_srs, &keccak_srs, &outer_srs);
-        let outer_inputs =
-            UniversalOuterCircuitInputs::new(config, bv_snarks, keccak_snark);
+        let outer_inputs = UniversalOuterCircuitInputs::new(
+            config,
+            &keccak_config,
+            bv_snarks,
+            keccak_snark,
+        );
         let (outer_pk, outer_gate_config, outer_break_points) =
             keygen::<UniversalOuterCircuit>(
                 config,
@@ -1,5 +1,6 @@
 use itertools::Itertools;
 use upa_circuits::{
+    batch_verify::common::chip::{WINDOW_BITS, WINDOW_BITS_G2_SUBGROUP_CHECK},
     keccak::{KeccakConfig, KECCAK_LOOKUP_BITS},
     outer::OuterConfig,
     utils::{
@@ -71,6 +72,12 @@ fn write_upa_configs_fixed_size() {
                                     *outer_degree_bits,
                                 ),
                             output_submission_id,
+                            allow_commitments: true,
+                            public_num_proof_ids: false,
+                            include_vk_digest: false,
+                            window_bits: WINDOW_BITS,
+                            g2_subgroup_window_bits:
+                                WINDOW_BITS_G2_SUBGROUP_CHECK,
                         });
                     }
                 }
@@ -105,6 +112,11 @@ fn write_keccak_configs() {
                     },
                     outer_config: CircuitWithLimbsConfig::from_degree_bits(1),
                     output_submission_id,
+                    allow_commitments: true,
+                    public_num_proof_ids: false,
+                    include_vk_digest: false,
+                    window_bits: WINDOW_BITS,
+                    g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK,
                 });
             }
         }
@@ -137,6 +149,11 @@ fn write_bv_configs(is_universal: bool) {
                 },
                 outer_config: CircuitWithLimbsConfig::from_degree_bits(1),
                 output_submission_id,
+                allow_commitments: true,
+                public_num_proof_ids: false,
+                include_vk_digest: false,
+                window_bits: WINDOW_BITS,
+                g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK,
             });
         }
     }
@@ -192,6 +209,11 @@ fn write_outer_configs() {
                             *degree_bits,
                         ),
                         output_submission_id,
+                        allow_commitments: true,
+                        public_num_proof_ids: false,
+                        include_vk_digest: false,
+                        window_bits: WINDOW_BITS,
+                        g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK,
                     });
                 }
             }
@@ -249,6 +271,11 @@ fn write_outer_configs_fixed_size() {
                             *degree_bits,
                         ),
                         output_submission_id,
+                        allow_commitments: true,
+                        public_num_proof_ids: false,
+                        include_vk_digest: false,
+                        window_bits: WINDOW_BITS,
+                        g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK,
                     });
                 }
             }
@@ -301,6 +328,11 @@ fn write_universal_outer_configs_fixed_size() {
                             *degree_bits,
                         ),
                         output_submission_id,
+                        allow_commitments: true,
+                        public_num_proof_ids: false,
+                        include_vk_digest: false,
+                        window_bits: WINDOW_BITS,
+                        g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK,
                     });
                 }
             }
@@ -336,14 +368,25 @@ fn write_keccak_configs_from_outer_configs() {
             for (inner_batch_size, outer_batch_size, output_submission_id) in
                 batch_sizes.iter()
             {
-                configs.push(KeccakConfig {
-                    degree_bits: *degree_bits,
-                    num_app_public_inputs: num_pub_in,
+                // Use unchecked `UpaConfig` constructor. We will
+                // only use the Keccak config.
+                configs.push(KeccakConfig::from(&UpaConfig {
+                    max_num_app_public_inputs: num_pub_in,
                     inner_batch_size: *inner_batch_size,
                     outer_batch_size: *outer_batch_size,
-                    lookup_bits: KECCAK_LOOKUP_BITS,
+                    bv_config: CircuitWithLimbsConfig::from_degree_bits(1),
+                    keccak_config: CircuitConfig {
+                        degree_bits: *degree_bits,
+                        lookup_bits: KECCAK_LOOKUP_BITS,
+                    },
+                    outer_config: CircuitWithLimbsConfig::from_degree_bits(1),
                     output_submission_id: *output_submission_id,
-                })
+                    allow_commitments: true,
+                    public_num_proof_ids: false,
+                    include_vk_digest: false,
+                    window_bits: WINDOW_BITS,
+                    g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK,
+                }))
             }
         }
     }
@@ -10,6 +10,7 @@ use crate::{
             get_assigned_value_g1point, get_assigned_value_g2point,
             G1InputPoint, G1Point, G2InputPoint, G2Point,
         },
+        msm::{CpuMsmBackend, MsmBackend},
         types::{Proof, PublicInputs, VerificationKey},
     },
     utils::{
@@ -22,6 +23,7 @@ use crate::{
     },
     EccPrimeField,
 };
+use core::iter;
 use halo2_base::{
     gates::{GateChip, GateInstructions},
     halo2_proofs::halo2curves::bn256::{Fq, Fq12, Fq2, Fr, G1Affine, G2Affine},
@@ -30,7 +32,7 @@ use halo2_base::{
 use halo2_ecc::{
     bigint::ProperCrtUint,
     bn254::{self, pairing::PairingChip, Fp12Chip, Fp2Chip, FqPoint},
-    ecc::{check_is_on_curve, scalar_multiply, EcPoint, EccChip},
+    ecc::{check_is_on_curve, EcPoint, EccChip},
     fields::{
         fp::{FpChip, Reduced},
         vector::FieldVector,
@@ -39,7 +41,11 @@ use halo2_ecc::{
 };
 use itertools::Itertools;
 
+/// Default window size for the G1 scalar multiplications in
+/// [`BatchVerifierChip::scale_pairs`].
 pub const WINDOW_BITS: usize = 4;
+/// Default window size for the G2 scalar multiplication in
+/// [`BatchVerifierChip::assert_g2_subgroup_membership`].
 pub const WINDOW_BITS_G2_SUBGROUP_CHECK: usize = 3;
 
 pub struct BatchVerifierChip<'a, F>
@@ -47,6 +53,15 @@ where
     F: EccPrimeField,
 {
     fp_chip: &'a FpChip<'a, F, Fq>,
+    /// Window size for the G1 scalar multiplications in `scale_pairs`
+    window_bits: usize,
+    /// Window size for the G2 scalar multiplication in
+    /// `assert_g2_subgroup_membership`
+    g2_subgroup_window_bits: usize,
+    /// Whether to also check the subgroup membership of G1 points
+    strict_g1_subgroup: bool,
+    /// Computes the G1 scalar multiplications in `scale_pairs`
+    msm_backend: &'a dyn MsmBackend<F>,
 }
 
 impl<'a, F> BatchVerifierChip<'a, F>
@@ -54,7 +69,57 @@ where
     F: EccPrimeField,
 {
     pub fn new(fp_chip: &'a FpChip<F, Fq>) -> BatchVerifierChip<'a, F> {
-        BatchVerifierChip { fp_chip }
+        Self::with_window_bits(fp_chip, WINDOW_BITS)
+    }
+
+    /// Creates a chip which uses windows of `window_bits` bits for the G1
+    /// scalar multiplications in `scale_pairs`.
+    pub fn with_window_bits(
+        fp_chip: &'a FpChip<F, Fq>,
+        window_bits: usize,
+    ) -> BatchVerifierChip<'a, F> {
+        assert!(window_bits > 0, "window_bits must be positive");
+        BatchVerifierChip {
+            fp_chip,
+            window_bits,
+            g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK,
+            strict_g1_subgroup: false,
+            msm_backend: &CpuMsmBackend,
+        }
+    }
+
+    /// Sets the window size for the G2 scalar multiplication in
+    /// [`assert_g2_subgroup_membership`](Self::assert_g2_subgroup_membership).
+    /// [`WINDOW_BITS_G2_SUBGROUP_CHECK`] by default.
+    pub fn with_g2_subgroup_window_bits(
+        mut self,
+        g2_subgroup_window_bits: usize,
+    ) -> Self {
+        assert!(
+            g2_subgroup_window_bits > 0,
+            "g2_subgroup_window_bits must be positive"
+        );
+        self.g2_subgroup_window_bits = g2_subgroup_window_bits;
+        self
+    }
+
+    /// Sets whether the G1 points of proofs and verification keys are
+    /// checked for subgroup membership (see
+    /// [`assert_g1_subgroup_membership`](Self::assert_g1_subgroup_membership)),
+    /// in addition to being checked to be on the curve.  Off by default.
+    pub fn with_strict_g1_subgroup(mut self, strict_g1_subgroup: bool) -> Self {
+        self.strict_g1_subgroup = strict_g1_subgroup;
+        self
+    }
+
+    /// Sets the backend computing the G1 scalar multiplications in
+    /// `scale_pairs`.  [`CpuMsmBackend`] by default.
+    pub fn with_msm_backend(
+        mut self,
+        msm_backend: &'a dyn MsmBackend<F>,
+    ) -> Self {
+        self.msm_backend = msm_backend;
+        self
     }
 
     pub fn fp_chip(&self) -> &FpChip<'a, F, Fq> {
@@ -81,6 +146,50 @@ where
         ])
     }
 
+    /// Assigns the constant `value`.  As for [`Self::assign_fq_reduced`],
+    /// the result is reduced.
+    pub(crate) fn assign_fq_reduced_constant(
+        &self,
+        ctx: &mut Context<F>,
+        value: Fq,
+    ) -> Reduced<ProperCrtUint<F>, Fq> {
+        let assigned = self.fp_chip.load_constant(ctx, value);
+        self.fp_chip.enforce_less_than(ctx, assigned)
+    }
+
+    pub(crate) fn assign_fq2_reduced_constant(
+        &self,
+        ctx: &mut Context<F>,
+        value: Fq2,
+    ) -> FieldVector<Reduced<ProperCrtUint<F>, Fq>> {
+        FieldVector(vec![
+            self.assign_fq_reduced_constant(ctx, value.c0),
+            self.assign_fq_reduced_constant(ctx, value.c1),
+        ])
+    }
+
+    pub(crate) fn assign_g1_reduced_constant(
+        &self,
+        ctx: &mut Context<F>,
+        value: G1Affine,
+    ) -> EcPoint<F, Reduced<ProperCrtUint<F>, Fq>> {
+        EcPoint::new(
+            self.assign_fq_reduced_constant(ctx, value.x),
+            self.assign_fq_reduced_constant(ctx, value.y),
+        )
+    }
+
+    pub(crate) fn assign_g2_reduced_constant(
+        &self,
+        ctx: &mut Context<F>,
+        value: G2Affine,
+    ) -> EcPoint<F, FieldVector<Reduced<ProperCrtUint<F>, Fq>>> {
+        EcPoint::new(
+            self.assign_fq2_reduced_constant(ctx, value.x),
+            self.assign_fq2_reduced_constant(ctx, value.y),
+        )
+    }
+
     pub(crate) fn assign_g1_reduced(
         &self,
         ctx: &mut Context<F>,
@@ -129,6 +238,40 @@ where
         result
     }
 
+    /// Same as [`Self::assign_verification_key`] for a `vk` without
+    /// commitment.  Its (padding) commitment keys `h1, h2` are assigned as
+    /// constants, so they are not checked.
+    pub fn assign_verification_key_without_commitment(
+        &self,
+        ctx: &mut Context<F>,
+        vk: &VerificationKey,
+    ) -> AssignedVerificationKey<F> {
+        assert!(vk.has_commitment(), "vk must be padded already");
+        assert!(
+            vk.h1[0] == G2Affine::generator()
+                && vk.h2[0] == G2Affine::generator(),
+            "vk has a commitment"
+        );
+
+        let result = AssignedVerificationKey {
+            alpha: self.assign_g1_reduced(ctx, vk.alpha),
+            beta: self.assign_g2_reduced(ctx, vk.beta),
+            gamma: self.assign_g2_reduced(ctx, vk.gamma),
+            delta: self.assign_g2_reduced(ctx, vk.delta),
+            s: vk
+                .s
+                .iter()
+                .copied()
+                .map(|s| self.assign_g1_reduced(ctx, s))
+                .collect(),
+            h1: self.assign_g2_reduced_constant(ctx, G2Affine::generator()),
+            h2: self.assign_g2_reduced_constant(ctx, G2Affine::generator()),
+        };
+
+        self.assert_vk_points_on_curve_without_commitment(ctx, &result);
+        result
+    }
+
     pub fn assign_public_inputs(
         &self,
         ctx: &mut Context<F>,
@@ -163,6 +306,30 @@ where
         result
     }
 
+    /// Same as [`Self::assign_proof`] for a `proof` without commitment.  Its
+    /// (padding) commitment `m` and proof of knowledge `pok` are assigned as
+    /// constants, so they are not checked.
+    pub fn assign_proof_without_commitment(
+        &self,
+        ctx: &mut Context<F>,
+        proof: &Proof,
+    ) -> AssignedProof<F> {
+        assert!(
+            proof.m == [G1Affine::generator()]
+                && proof.pok == [-G1Affine::generator()],
+            "proof has a commitment, or was not padded prior to assignment"
+        );
+        let result = AssignedProof {
+            a: self.assign_g1_reduced(ctx, proof.a),
+            b: self.assign_g2_reduced(ctx, proof.b),
+            c: self.assign_g1_reduced(ctx, proof.c),
+            m: self.assign_g1_reduced_constant(ctx, G1Affine::generator()),
+            pok: self.assign_g1_reduced_constant(ctx, -G1Affine::generator()),
+        };
+        self.assert_proof_points_on_curve_without_commitment(ctx, &result);
+        result
+    }
+
     /// Asserts `g1_point` is a valid [`G1Affine`] point.
     pub(crate) fn assert_g1_point_is_on_curve(
         &self,
@@ -201,18 +368,50 @@ where
         &self,
         ctx: &mut Context<F>,
         proof: &AssignedProof<F>,
+    ) {
+        self.assert_proof_points_on_curve_impl(ctx, proof, true)
+    }
+
+    /// Same as [`Self::assert_proof_points_on_curve`], except that the
+    /// commitment `m` and proof of knowledge `pok` are not checked.
+    fn assert_proof_points_on_curve_without_commitment(
+        &self,
+        ctx: &mut Context<F>,
+        proof: &AssignedProof<F>,
+    ) {
+        self.assert_proof_points_on_curve_impl(ctx, proof, false)
+    }
+
+    fn assert_proof_points_on_curve_impl(
+        &self,
+        ctx: &mut Context<F>,
+        proof: &AssignedProof<F>,
+        check_commitment: bool,
     ) {
         self.assert_g1_point_is_on_curve(ctx, &proof.a);
         self.assert_g2_point_is_on_curve(ctx, &proof.b);
         self.assert_g1_point_is_on_curve(ctx, &proof.c);
-        self.assert_g1_point_is_on_curve(ctx, &proof.m);
-        self.assert_g1_point_is_on_curve(ctx, &proof.pok);
+        let mut g1_points = vec![&proof.a, &proof.c];
+        if check_commitment {
+            self.assert_g1_point_is_on_curve(ctx, &proof.m);
+            self.assert_g1_point_is_on_curve(ctx, &proof.pok);
+            g1_points.extend([&proof.m, &proof.pok]);
+        }
 
         // Subgroup check for the g2 point
         self.assert_g2_subgroup_membership(
             ctx,
             &FromReduced::from_reduced(&proof.b),
         );
+
+        if self.strict_g1_subgroup {
+            for g1_point in g1_points {
+                self.assert_g1_subgroup_membership(
+                    ctx,
+                    &g1_input_point_to_inner(g1_point),
+                );
+            }
+        }
     }
 
     /// Asserts that all points in a [`VerificationKey`] are valid affine
@@ -226,6 +425,25 @@ where
         &self,
         ctx: &mut Context<F>,
         vk: &AssignedVerificationKey<F>,
+    ) {
+        self.assert_vk_points_on_curve_impl(ctx, vk, true)
+    }
+
+    /// Same as [`Self::assert_vk_points_on_curve`], except that the
+    /// commitment keys `h1, h2` are not checked.
+    fn assert_vk_points_on_curve_without_commitment(
+        &self,
+        ctx: &mut Context<F>,
+        vk: &AssignedVerificationKey<F>,
+    ) {
+        self.assert_vk_points_on_curve_impl(ctx, vk, false)
+    }
+
+    fn assert_vk_points_on_curve_impl(
+        &self,
+        ctx: &mut Context<F>,
+        vk: &AssignedVerificationKey<F>,
+        check_commitment_keys: bool,
     ) {
         self.assert_g1_point_is_on_curve(ctx, &vk.alpha);
         self.assert_g2_point_is_on_curve(ctx, &vk.beta);
@@ -234,21 +452,29 @@ where
         for s in vk.s.iter() {
             self.assert_g1_point_is_on_curve(ctx, s);
         }
-        self.assert_g2_point_is_on_curve(ctx, &vk.h1);
-        self.assert_g2_point_is_on_curve(ctx, &vk.h2);
+        let mut g2_points = vec![&vk.beta, &vk.gamma, &vk.delta];
+        if check_commitment_keys {
+            self.assert_g2_point_is_on_curve(ctx, &vk.h1);
+            self.assert_g2_point_is_on_curve(ctx, &vk.h2);
+            g2_points.extend([&vk.h1, &vk.h2]);
+        }
 
         // Subgroup check for G2 points
-        let beta = FromReduced::from_reduced(&vk.beta);
-        let gamma = FromReduced::from_reduced(&vk.gamma);
-        let delta = FromReduced::from_reduced(&vk.delta);
-        let h1 = FromReduced::from_reduced(&vk.h1);
-        let h2 = FromReduced::from_reduced(&vk.h2);
+        for g2_point in g2_points {
+            self.assert_g2_subgroup_membership(
+                ctx,
+                &FromReduced::from_reduced(g2_point),
+            );
+        }
 
-        self.assert_g2_subgroup_membership(ctx, &beta);
-        self.assert_g2_subgroup_membership(ctx, &gamma);
-        self.assert_g2_subgroup_membership(ctx, &delta);
-        self.assert_g2_subgroup_membership(ctx, &h1);
-        self.assert_g2_subgroup_membership(ctx, &h2);
+        if self.strict_g1_subgroup {
+            for g1_point in iter::once(&vk.alpha).chain(vk.s.iter()) {
+                self.assert_g1_subgroup_membership(
+                    ctx,
+                    &g1_input_point_to_inner(g1_point),
+                );
+            }
+        }
     }
 
     /// Return r^0, r, ... r^{len - 1}
@@ -279,21 +505,20 @@ where
         scalars: &[AssignedValue<F>],
         pairs: &[(G1Point<F>, G2Point<F>)],
     ) -> Vec<(G1Point<F>, G2Point<F>)> {
-        let mut result = Vec::with_capacity(pairs.len());
-        for ((g1, g2), scalar) in pairs.iter().zip_eq(scalars.iter()) {
-            result.push((
-                scalar_multiply::<_, FpChip<F, Fq>, G1Affine>(
-                    self.fp_chip,
-                    ctx,
-                    g1.clone(),
-                    vec![*scalar],
-                    F::NUM_BITS as usize,
-                    WINDOW_BITS,
-                ),
-                g2.clone(),
-            ))
-        }
-        result
+        let g1_points =
+            pairs.iter().map(|(g1, _)| g1.clone()).collect::<Vec<_>>();
+        let scaled = self.msm_backend.scalar_multiply_batch(
+            self.fp_chip,
+            ctx,
+            &g1_points,
+            scalars,
+            self.window_bits,
+        );
+        scaled
+            .into_iter()
+            .zip_eq(pairs.iter())
+            .map(|(g1, (_, g2))| (g1, g2.clone()))
+            .collect()
     }
 
     pub(crate) fn multi_pairing(
@@ -330,6 +555,37 @@ where
         fp12_chip.assert_equal(ctx, final_exp_out, fp12_one);
     }
 
+    /// Asserts that `point` belongs to the subgroup of G1 of order equal to
+    /// that of `F`.  Since the point at infinity has no affine
+    /// representation, `[r]P = O` is checked as `[r-1]P = -P`.
+    ///
+    /// # Note
+    ///
+    /// The G1 cofactor of BN254 is 1, so any point on the curve belongs to
+    /// the subgroup.  This check is only performed if the chip was created
+    /// with `strict_g1_subgroup` set.
+    pub fn assert_g1_subgroup_membership(
+        &self,
+        ctx: &mut Context<F>,
+        point: &G1Point<F>,
+    ) {
+        let ec_chip = EccChip::new(self.fp_chip());
+        // Load and assign `r-1`
+        let r_minus_one = ctx.load_constant(F::zero() - F::one());
+        // [r-1]P
+        let r_minus_one_p = ec_chip.scalar_mult::<G1Affine>(
+            ctx,
+            point.clone(),
+            vec![r_minus_one],
+            F::NUM_BITS as usize,
+            self.window_bits,
+        );
+        // - P
+        let minus_p = ec_chip.negate(ctx, point.clone());
+        // [r-1] P = - P
+        ec_chip.assert_equal(ctx, r_minus_one_p, minus_p);
+    }
+
     /// Applies the Frobenius endomorphism to `point`.
     /// This is the untwist-Frobenius-twist `\psi = \phi^{-1} \circ \pi \circ \phi`
     /// automorphism as mentioned in https://eprint.iacr.org/2022/352.pdf page 4.
@@ -352,7 +608,9 @@ where
     /// Asserts that `point` belongs to the subgroup of G2 of order
     /// equal to that of `F`. This is the fully optimized check that asserts
     /// `[x+1]P + \psi([x]P) + \psi^2([x]P) = \psi^3([2x]P)` as in
-    /// https://eprint.iacr.org/2022/348.pdf.
+    /// https://eprint.iacr.org/2022/348.pdf.  The scalar multiplication
+    /// uses windows of `g2_subgroup_window_bits` bits (see
+    /// [`with_g2_subgroup_window_bits`](Self::with_g2_subgroup_window_bits)).
     pub fn assert_g2_subgroup_membership(
         &self,
         ctx: &mut Context<F>,
@@ -370,7 +628,7 @@ where
             point.clone(),
             vec![x],
             BN254_CURVE_PARAMETER_BITS,
-            WINDOW_BITS_G2_SUBGROUP_CHECK,
+            self.g2_subgroup_window_bits,
         );
         // [2x]P
         let two_xp = ec_chip.double(ctx, xp.clone());
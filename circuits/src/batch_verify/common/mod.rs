@@ -3,5 +3,6 @@ pub const MINIMUM_ROWS: usize = 20;
 
 pub mod chip;
 pub mod ecc;
+pub mod msm;
 pub mod native;
 pub mod types;
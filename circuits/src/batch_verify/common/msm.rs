@@ -0,0 +1,67 @@
+//! Backends for the G1 scalar multiplications of
+//! [`BatchVerifierChip::scale_pairs`](super::chip::BatchVerifierChip).
+//!
+//! Only [`CpuMsmBackend`] is provided.  A GPU MSM computes only the final
+//! points, whereas the circuit witness holds every intermediate double and
+//! add of each windowed multiplication, so a GPU backend would first need a
+//! way to assign those from precomputed values.
+
+use crate::{batch_verify::common::ecc::G1Point, EccPrimeField};
+use halo2_base::{
+    halo2_proofs::halo2curves::bn256::{Fq, G1Affine},
+    AssignedValue, Context,
+};
+use halo2_ecc::{ecc::scalar_multiply, fields::fp::FpChip};
+use itertools::Itertools;
+
+/// Computes (and constrains) a batch of G1 scalar multiplications.
+///
+/// # Note
+///
+/// Implementations must return the same assigned points (including their
+/// limb representations) as [`CpuMsmBackend`], so that the choice of backend
+/// is invisible to the circuit.  They may only differ in how the witness is
+/// computed.
+pub trait MsmBackend<F: EccPrimeField>: Sync {
+    /// Returns `scalars[i] * points[i]` for each `i`, where each scalar is a
+    /// full `F` element, using windows of `window_bits` bits.
+    fn scalar_multiply_batch(
+        &self,
+        fp_chip: &FpChip<F, Fq>,
+        ctx: &mut Context<F>,
+        points: &[G1Point<F>],
+        scalars: &[AssignedValue<F>],
+        window_bits: usize,
+    ) -> Vec<G1Point<F>>;
+}
+
+/// The default [`MsmBackend`], which computes each multiplication in turn
+/// with [`scalar_multiply`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CpuMsmBackend;
+
+impl<F: EccPrimeField> MsmBackend<F> for CpuMsmBackend {
+    fn scalar_multiply_batch(
+        &self,
+        fp_chip: &FpChip<F, Fq>,
+        ctx: &mut Context<F>,
+        points: &[G1Point<F>],
+        scalars: &[AssignedValue<F>],
+        window_bits: usize,
+    ) -> Vec<G1Point<F>> {
+        points
+            .iter()
+            .zip_eq(scalars.iter())
+            .map(|(point, scalar)| {
+                scalar_multiply::<_, FpChip<F, Fq>, G1Affine>(
+                    fp_chip,
+                    ctx,
+                    point.clone(),
+                    vec![*scalar],
+                    F::NUM_BITS as usize,
+                    window_bits,
+                )
+            })
+            .collect()
+    }
+}
@@ -2,13 +2,14 @@
 use crate::{
     batch_verify::common::types::VerificationKey,
     utils::{
-        hashing::{compute_domain_tag, WrongFieldHasher},
+        hashing::{compute_domain_tag, WrongFieldHasher, SECURE_MDS},
         keccak_hasher::KeccakHasher,
     },
     CircuitWithLimbsConfig, EccPrimeField,
 };
 use halo2_base::utils::CurveAffineExt;
 use halo2_ecc::fields::FieldExtConstructor;
+use itertools::Itertools;
 
 // Keccak hash of VK, for CircuitID calculations in UBV
 pub(crate) fn compute_vk_keccak_hash_with_domain_tag<
@@ -43,9 +44,9 @@ where
     }
 
     // Absorb the commitment terms if present.
-    if vk.has_commitment() {
-        hasher.absorb_g2(&vk.h1[0]);
-        hasher.absorb_g2(&vk.h2[0]);
+    for (h1, h2) in vk.h1.iter().zip_eq(vk.h2.iter()) {
+        hasher.absorb_g2(h1);
+        hasher.absorb_g2(h2);
     }
 
     hasher.finalize()
@@ -65,7 +66,8 @@ where
     C1::Base: EccPrimeField,
     C2::Base: FieldExtConstructor<C1::Base, DEGREE>,
 {
-    let mut hasher = WrongFieldHasher::<C1, C2>::new(circuit_config, None);
+    let mut hasher =
+        WrongFieldHasher::<C1, C2>::new(circuit_config, None, SECURE_MDS);
     hasher.absorb_g1(&vk.alpha);
     hasher.absorb_g2(&vk.beta);
     hasher.absorb_g2(&vk.gamma);
@@ -111,7 +113,9 @@ pub mod unsafe_proof_generation {
         halo2curves::bn256::{Fr, G1Affine, G2Affine},
     };
     use itertools::Itertools;
-    use rand_core::RngCore;
+    use rand_chacha::ChaCha20Rng;
+    use rand_core::{OsRng, RngCore, SeedableRng};
+    use rayon::prelude::*;
     use serde::{Deserialize, Serialize};
 
     /// Unsafe Verification Key
@@ -316,6 +320,36 @@ pub mod unsafe_proof_generation {
         }
     }
 
+    /// Creates `num_proofs` proofs (and their public inputs), using the keys
+    /// in `uvks` in turn.  Each proof is generated with its own RNG: if `seed`
+    /// is given, the `i`-th proof uses a `ChaCha20Rng` seeded with `seed + i`,
+    /// otherwise the system RNG is used.  The output is therefore the same
+    /// whether or not the proofs are generated in `parallel`.
+    pub fn create_proofs_and_inputs(
+        uvks: &[UnsafeVerificationKey],
+        num_proofs: usize,
+        seed: Option<u64>,
+        parallel: bool,
+    ) -> Vec<(Proof, PublicInputs)> {
+        assert!(!uvks.is_empty(), "At least one vk is required");
+        let create = |i: usize| {
+            let uvk = &uvks[i % uvks.len()];
+            match seed {
+                Some(seed) => uvk.create_proof_and_inputs(
+                    &mut ChaCha20Rng::seed_from_u64(
+                        seed.wrapping_add(i as u64),
+                    ),
+                ),
+                None => uvk.create_proof_and_inputs(&mut OsRng),
+            }
+        };
+        if parallel {
+            (0..num_proofs).into_par_iter().map(create).collect()
+        } else {
+            (0..num_proofs).map(create).collect()
+        }
+    }
+
     /// Test helper that returns `num_proofs` application circuit proofs, public inputs,
     /// and verification keys.
     ///
@@ -352,13 +386,14 @@ pub mod unsafe_proof_generation {
 pub mod json {
     use crate::{
         batch_verify::common::types::{Proof, PublicInputs, VerificationKey},
-        utils::file::load_json,
+        utils::file::{load_json, open_file_for_read},
         EccPrimeField,
     };
     use halo2_base::halo2_proofs::halo2curves::bn256::{
         Fq2, G1Affine, G2Affine,
     };
-    use serde::{Deserialize, Serialize};
+    use serde::{de::DeserializeOwned, Deserialize, Serialize};
+    use std::io::Read;
 
     /// Accepts a hex string, strips any leading 0x, and extends to be an even
     /// number of chars.  Converts to a [u8; 32] in reverse order, padded at the
@@ -542,6 +577,65 @@ pub mod json {
         }
     }
 
+    /// A Groth16 proof in the `proof.json` format written by snarkjs.  Points
+    /// are given in projective (Jacobian) coordinates, normalized so that `z`
+    /// is `1`.  The G2 point `pi_b` is a list of 3 coordinates (rather than
+    /// the 2 of [`JsonProof`]), each with its `Fq2` components in the natural
+    /// `[c0, c1]` order.  (Note that the Solidity calldata exported by snarkjs
+    /// transposes these to `[c1, c0]`, which is NOT supported here.)
+    ///
+    /// snarkjs does not support Pedersen commitments, so the proof has none.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct JsonSnarkjsProof {
+        pub pi_a: [String; 3],
+        pub pi_b: [[String; 2]; 3],
+        pub pi_c: [String; 3],
+        pub protocol: Option<String>,
+        pub curve: Option<String>,
+    }
+
+    /// Returns the affine coordinates of the snarkjs projective point `json`,
+    /// asserting that it is normalized.
+    fn snarkjs_affine<T>(json: &[T; 3], one: &T, desc: &str) -> [T; 2]
+    where
+        T: Clone + PartialEq + core::fmt::Debug,
+    {
+        assert_eq!(&json[2], one, "{desc}: expected normalized z coordinate");
+        [json[0].clone(), json[1].clone()]
+    }
+
+    impl From<&JsonSnarkjsProof> for JsonProof {
+        fn from(json: &JsonSnarkjsProof) -> Self {
+            if let Some(protocol) = &json.protocol {
+                assert_eq!(protocol, "groth16", "unsupported snarkjs protocol");
+            }
+            if let Some(curve) = &json.curve {
+                assert_eq!(curve, "bn128", "unsupported snarkjs curve");
+            }
+            let one = "1".to_string();
+            let fq2_one = [one.clone(), "0".to_string()];
+            JsonProof {
+                pi_a: snarkjs_affine(&json.pi_a, &one, "pi_a"),
+                pi_b: snarkjs_affine(&json.pi_b, &fq2_one, "pi_b"),
+                pi_c: snarkjs_affine(&json.pi_c, &one, "pi_c"),
+                m: vec![],
+                pok: vec![],
+            }
+        }
+    }
+
+    /// Converts a snarkjs proof and its public signals (the contents of the
+    /// `public.json` file) into a [`Proof`] and [`PublicInputs`].
+    pub fn from_snarkjs(
+        proof: &JsonSnarkjsProof,
+        public_signals: &JsonPublicInputs,
+    ) -> (Proof, PublicInputs) {
+        (
+            Proof::from(&JsonProof::from(proof)),
+            PublicInputs::from(public_signals),
+        )
+    }
+
     #[derive(Clone, Debug, Serialize, Deserialize)]
     pub struct JsonPublicInputs(Vec<String>);
 
@@ -563,7 +657,10 @@ pub mod json {
 
     pub fn load_vk(filename: &str) -> VerificationKey {
         let vk_json: JsonVerificationKey = load_json(filename);
-        VerificationKey::from(&vk_json)
+        let vk = VerificationKey::from(&vk_json);
+        vk.validate()
+            .unwrap_or_else(|e| panic!("Invalid vk in {filename}: {e}"));
+        vk
     }
 
     #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -584,6 +681,17 @@ pub mod json {
         )
     }
 
+    /// Loads a proof and public inputs from the snarkjs `proof.json` and
+    /// `public.json` files.  See [`JsonSnarkjsProof`].
+    pub fn load_snarkjs_proof_and_inputs(
+        proof_filename: &str,
+        public_filename: &str,
+    ) -> (Proof, PublicInputs) {
+        let proof_json: JsonSnarkjsProof = load_json(proof_filename);
+        let public_json: JsonPublicInputs = load_json(public_filename);
+        from_snarkjs(&proof_json, &public_json)
+    }
+
     pub fn load_proof_and_inputs_batch(
         filename: &str,
     ) -> Vec<(Proof, PublicInputs)> {
@@ -598,4 +706,35 @@ pub mod json {
             })
             .collect()
     }
+
+    /// Returns an iterator over the values of a JSONL stream (one JSON value
+    /// per line).  Values are deserialized as the iterator is consumed, so
+    /// only the current value is held in memory.
+    pub fn iter_jsonl<T, R>(
+        reader: R,
+    ) -> impl Iterator<Item = Result<T, serde_json::Error>>
+    where
+        T: DeserializeOwned,
+        R: Read,
+    {
+        serde_json::Deserializer::from_reader(reader).into_iter::<T>()
+    }
+
+    /// Streaming version of [`load_proof_and_inputs_batch`], for JSONL files
+    /// holding one [`JsonProofAndInputs`] per line.
+    pub fn iter_proof_and_inputs_jsonl(
+        filename: &str,
+    ) -> impl Iterator<Item = (Proof, PublicInputs)> + '_ {
+        iter_jsonl::<JsonProofAndInputs, _>(open_file_for_read(filename))
+            .enumerate()
+            .map(move |(i, proof_pi_json)| {
+                let proof_pi_json = proof_pi_json.unwrap_or_else(|e| {
+                    panic!("{filename}: failed to parse entry {i}: {e}")
+                });
+                (
+                    Proof::from(&proof_pi_json.proof),
+                    PublicInputs::from(&proof_pi_json.inputs),
+                )
+            })
+    }
 }
@@ -1,11 +1,16 @@
 extern crate alloc;
 
 use crate::{
-    utils::commitment_point::commitment_hash_bytes_from_g1_point, EccPrimeField,
+    utils::{
+        commitment_point::commitment_hash_bytes_from_g1_point,
+        field_elements_hex,
+    },
+    EccPrimeField,
 };
+use core::fmt;
 use halo2_base::halo2_proofs::halo2curves::{
     bn256::{Fr, G1Affine, G2Affine},
-    CurveAffineExt,
+    CurveAffine, CurveAffineExt,
 };
 use serde::{Deserialize, Serialize};
 
@@ -20,9 +25,11 @@ where
     pub gamma: C2,
     pub delta: C2,
     pub s: Vec<C1>,
-    /// Commitment key. Valid lengths are 0, 1.
+    /// Commitment key. Valid lengths are 0, 1 (the keccak circuit allows up to
+    /// `KeccakConfig::max_commitments`).
     pub h1: Vec<C2>,
-    /// Commitment key. Valid lengths are 0, 1.
+    /// Commitment key. Valid lengths are 0, 1 (the keccak circuit allows up to
+    /// `KeccakConfig::max_commitments`).
     pub h2: Vec<C2>,
 }
 
@@ -51,6 +58,12 @@ impl<C1: CurveAffineExt, C2: CurveAffineExt> VerificationKey<C1, C2> {
 
     /// Pads the public input points of `self` to `total_len` using the generator.
     /// Pads the Pedersen commitment key with default values, if not already present.
+    ///
+    /// After padding, `self.s.len() == total_len + 1` and `self.h1.len() ==
+    /// self.h2.len() >= 1`, which is the form expected by the UBV and keccak
+    /// circuits (see [`Proof::pad_commitments`] for the corresponding
+    /// proof).  Padding an already padded vk to the same `total_len` leaves
+    /// it unchanged.
     pub fn pad(&mut self, total_len: usize) {
         assert!(total_len + 1 >= self.s.len(), "VK over total length");
         let padding = (self.s.len()..total_len + 1)
@@ -68,6 +81,16 @@ impl<C1: CurveAffineExt, C2: CurveAffineExt> VerificationKey<C1, C2> {
         }
     }
 
+    /// Returns the number of Pedersen commitment keys in `self`.
+    pub fn num_commitments(&self) -> usize {
+        assert_eq!(
+            self.h1.len(),
+            self.h2.len(),
+            "Invalid VK. Inconsistent h1, h2"
+        );
+        self.h1.len()
+    }
+
     /// Check consistency
     pub fn is_well_formed(&self) -> bool {
         let commitment_length = self.h1.len();
@@ -85,6 +108,105 @@ impl<C1: CurveAffineExt, C2: CurveAffineExt> VerificationKey<C1, C2> {
     }
 }
 
+/// Structural errors in a [`VerificationKey`], reported by
+/// [`VerificationKey::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VkError {
+    /// The named point (e.g. `"s[2]"`) is not on the curve.
+    NotOnCurve(String),
+    /// The named G2 point is on the curve, but not in the prime-order
+    /// subgroup.
+    NotInSubgroup(String),
+    /// `s` is empty, so the vk cannot account for the constant public input.
+    EmptyS,
+    /// `h1` and `h2` must both be empty or both have length 1.
+    InvalidCommitmentKey { h1_len: usize, h2_len: usize },
+}
+
+impl fmt::Display for VkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VkError::NotOnCurve(point) => {
+                write!(f, "vk point {point} is not on the curve")
+            }
+            VkError::NotInSubgroup(point) => {
+                write!(f, "vk point {point} is not in the G2 subgroup")
+            }
+            VkError::EmptyS => write!(f, "vk has no s points"),
+            VkError::InvalidCommitmentKey { h1_len, h2_len } => write!(
+                f,
+                "vk has {h1_len} h1 and {h2_len} h2 points (expected 0 or 1 of each)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VkError {}
+
+impl VerificationKey {
+    /// Checks the structural invariants of `self`: all points are on the
+    /// curve, G2 points are in the prime-order subgroup, `s` has an entry for
+    /// the constant public input (so that `s.len() == num_public_inputs + 1`)
+    /// and the commitment key `h1`, `h2` is either absent or a single pair.
+    ///
+    /// # Note
+    ///
+    /// BN254 G1 has cofactor 1, so on-curve G1 points are always in the
+    /// subgroup.
+    pub fn validate(&self) -> Result<(), VkError> {
+        let named_g1 = [("alpha".to_string(), &self.alpha)].into_iter().chain(
+            self.s
+                .iter()
+                .enumerate()
+                .map(|(i, s_i)| (format!("s[{i}]"), s_i)),
+        );
+        for (name, point) in named_g1 {
+            if !bool::from(point.is_on_curve()) {
+                return Err(VkError::NotOnCurve(name));
+            }
+        }
+
+        let named_g2 = [
+            ("beta".to_string(), &self.beta),
+            ("gamma".to_string(), &self.gamma),
+            ("delta".to_string(), &self.delta),
+        ]
+        .into_iter()
+        .chain(
+            self.h1
+                .iter()
+                .enumerate()
+                .map(|(i, h)| (format!("h1[{i}]"), h)),
+        )
+        .chain(
+            self.h2
+                .iter()
+                .enumerate()
+                .map(|(i, h)| (format!("h2[{i}]"), h)),
+        );
+        for (name, point) in named_g2 {
+            if !bool::from(point.is_on_curve()) {
+                return Err(VkError::NotOnCurve(name));
+            }
+            // [r] P = O  <=>  [r - 1] P = -P
+            if G2Affine::from(*point * -Fr::from(1)) != -*point {
+                return Err(VkError::NotInSubgroup(name));
+            }
+        }
+
+        if self.s.is_empty() {
+            return Err(VkError::EmptyS);
+        }
+        if self.h1.len() != self.h2.len() || self.h1.len() > 1 {
+            return Err(VkError::InvalidCommitmentKey {
+                h1_len: self.h1.len(),
+                h2_len: self.h2.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Proof {
     pub a: G1Affine,
@@ -116,15 +238,24 @@ impl Proof {
     pub(crate) fn pad(&mut self, has_commitment: bool) {
         assert_eq!(
             self.m.len(),
-            self.pok.len(),
-            "Invalid proof. Inconsistent m, pok."
+            has_commitment as usize,
+            "Invalid proof. Not consistent with VK."
         );
+        self.pad_commitments();
+    }
+
+    /// Inserts padding values for the Pedersen commitment and its proof of
+    /// knowledge, if absent.  After padding, `self.m.len() ==
+    /// self.pok.len() == 1`.  Padding an already padded proof leaves it
+    /// unchanged.
+    pub fn pad_commitments(&mut self) {
         assert_eq!(
             self.m.len(),
-            has_commitment as usize,
-            "Invalid proof. Not consistent with VK."
+            self.pok.len(),
+            "Invalid proof. Inconsistent m, pok."
         );
-        if !has_commitment {
+        assert!(self.m.len() <= 1, "Invalid proof. Too many commitments.");
+        if self.m.is_empty() {
             self.m.push(G1Affine::generator());
             self.pok.push(-G1Affine::generator());
         }
@@ -156,3 +287,19 @@ impl<F: EccPrimeField> PublicInputs<F> {
         self.0.extend(padding)
     }
 }
+
+impl<F: EccPrimeField<Repr = [u8; 32]>> PublicInputs<F> {
+    /// Returns the inputs as `0x`-prefixed big-endian hex strings, matching
+    /// the EVM `uint256[]` encoding.
+    pub fn to_hex_vec(&self) -> Vec<String> {
+        field_elements_hex::to_hex_vec(&self.0)
+    }
+
+    /// Inverse of [`Self::to_hex_vec`].  Fails if any string is not
+    /// `0x`-prefixed hex of a canonical field element.
+    pub fn from_hex_vec<S: AsRef<str>>(
+        hex_strings: &[S],
+    ) -> Result<Self, String> {
+        field_elements_hex::from_hex_vec(hex_strings).map(PublicInputs)
+    }
+}
@@ -1,2 +1,10 @@
+//! Batch verification of Groth16 proofs.
+//!
+//! Only the universal circuit is supported.  The fixed `BatchVerifyCircuit`,
+//! which baked the exact number of application public inputs into keygen,
+//! has been removed.  Batches mixing public input lengths are handled by
+//! [`universal`], which takes a `len` witness per proof, zero-pads the inputs
+//! up to `max_num_public_inputs` and binds `len` into the instance.
+
 pub mod common;
 pub mod universal;
@@ -16,7 +16,7 @@ use crate::{
     utils::{
         advice_cell_count,
         bitmask::{first_i_bits_bitmask, ith_bit_bitmask},
-        hashing::PoseidonHasher,
+        hashing::{PoseidonHasher, SECURE_MDS},
         reduced::FromReduced,
     },
     EccPrimeField,
@@ -24,8 +24,8 @@ use crate::{
 use core::iter::once;
 use halo2_base::{
     gates::{
-        builder::GateThreadBuilder, GateChip, GateInstructions, RangeChip,
-        RangeInstructions,
+        builder::{parallelize_in, GateThreadBuilder},
+        GateChip, GateInstructions, RangeChip, RangeInstructions,
     },
     halo2_proofs::halo2curves::bn256::{G1Affine, G2Affine},
     AssignedValue, Context, QuantumCell,
@@ -37,21 +37,36 @@ use halo2_ecc::{
     fields::{fp2::Fp2Chip, FieldChip},
 };
 use itertools::{multiunzip, Itertools};
-use log::info;
+use tracing::{debug_span, info, Span};
 
 /// Universal Batch Verifier Chip
 pub struct UniversalBatchVerifierChip<'a, F: EccPrimeField> {
     batch_verifier_chip: &'a BatchVerifierChip<'a, F>,
+    /// Whether entries may have a Pedersen commitment.  See
+    /// [`UniversalBatchVerifierConfig::allow_commitments`].
+    ///
+    /// [`UniversalBatchVerifierConfig::allow_commitments`]: super::types::UniversalBatchVerifierConfig::allow_commitments
+    allow_commitments: bool,
 }
 
 impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
-    /// Creates a new [`UniversalBatchVerifierChip`] from `batch_verifier_chip`.
+    /// Creates a new [`UniversalBatchVerifierChip`] from `batch_verifier_chip`,
+    /// which allows entries with commitments.
     pub fn new(batch_verifier_chip: &'a BatchVerifierChip<'a, F>) -> Self {
         Self {
             batch_verifier_chip,
+            allow_commitments: true,
         }
     }
 
+    /// Sets whether entries may have a Pedersen commitment.  If not, the
+    /// commitment data of each entry is assigned as constant padding and
+    /// the commitment checks and pairs are omitted.
+    pub fn with_allow_commitments(mut self, allow_commitments: bool) -> Self {
+        self.allow_commitments = allow_commitments;
+        self
+    }
+
     pub fn bv_chip(&self) -> &BatchVerifierChip<'a, F> {
         self.batch_verifier_chip
     }
@@ -174,8 +189,11 @@ impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
         }
         */
 
-        self.check_vk_commitment_padding(ctx, entry);
-        self.check_proof_commitment_padding(ctx, entry)
+        // Without commitments, the commitment points are constant padding.
+        if self.allow_commitments {
+            self.check_vk_commitment_padding(ctx, entry);
+            self.check_proof_commitment_padding(ctx, entry)
+        }
     }
 
     /// Enforces that either `vk.h1, vk.h2` are both assigned
@@ -263,12 +281,16 @@ impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
         ctx: &mut Context<F>,
         entry: &BatchEntry<F>,
     ) -> AssignedBatchEntry<F> {
+        if !self.allow_commitments {
+            return self.assign_batch_entry_without_commitment(ctx, entry);
+        }
         let len = ctx.load_witness(*entry.len());
         // Cast boolean `has_commitment` to field element, assign and constrain
         // to boolean value.
         let has_commitment = ctx.load_witness(F::from(entry.has_commitment()));
         self.gate().assert_bit(ctx, has_commitment);
-        let vk = self.assign_verification_key(ctx, entry.vk());
+        let vk = debug_span!("assign_vk")
+            .in_scope(|| self.assign_verification_key(ctx, entry.vk()));
         let proof = self.assign_proof(ctx, entry.proof());
         let public_inputs = self.assign_public_inputs(ctx, entry.inputs());
         let commitment_hash = ctx.load_witness(*entry.commitment_hash());
@@ -285,6 +307,45 @@ impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
         result
     }
 
+    /// Assigns `entry`, which must not have a commitment, in a circuit
+    /// without commitments.  The commitment flag, hash and points are
+    /// constants.
+    ///
+    /// The commitment hash constraint of [`Self::constrain_commitment_hash`]
+    /// is implied by the padding check: the public input at index `len` is
+    /// either padding (hence zero) or does not exist.
+    fn assign_batch_entry_without_commitment(
+        &self,
+        ctx: &mut Context<F>,
+        entry: &BatchEntry<F>,
+    ) -> AssignedBatchEntry<F> {
+        assert!(
+            !entry.has_commitment(),
+            "entry has a commitment, which the circuit does not allow"
+        );
+        let len = ctx.load_witness(*entry.len());
+        let has_commitment = ctx.load_constant(F::zero());
+        let vk = debug_span!("assign_vk").in_scope(|| {
+            self.bv_chip()
+                .assign_verification_key_without_commitment(ctx, entry.vk())
+        });
+        let proof = self
+            .bv_chip()
+            .assign_proof_without_commitment(ctx, entry.proof());
+        let public_inputs = self.assign_public_inputs(ctx, entry.inputs());
+        let commitment_hash = ctx.load_constant(*entry.commitment_hash());
+        let result = AssignedBatchEntry {
+            len,
+            has_commitment,
+            vk,
+            proof,
+            public_inputs,
+            commitment_hash,
+        };
+        self.check_padding(ctx, &result);
+        result
+    }
+
     /// Constrains the index l public input in `entry` to be equal to
     /// `entry.commitment_hash` when `entry.has_commitment = true`
     /// and to equal zero otherwise.
@@ -327,6 +388,24 @@ impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
         )
     }
 
+    /// Assigns `entries`, each in a new [`Context`] of `builder` in phase 0,
+    /// so that witness generation for the entries runs in parallel. The
+    /// assigned entries are returned in the same order as `entries`.
+    pub(crate) fn assign_batch_entries_parallel(
+        &self,
+        builder: &mut GateThreadBuilder<F>,
+        entries: &BatchEntries<F>,
+    ) -> AssignedBatchEntries<F> {
+        // Spans entered in the worker threads are nested under the caller's.
+        let span = Span::current();
+        AssignedBatchEntries(parallelize_in(
+            0,
+            builder,
+            entries.0.iter().collect(),
+            |ctx, entry| span.in_scope(|| self.assign_batch_entry(ctx, entry)),
+        ))
+    }
+
     /// Computes the hash of the verifying key in `entry`.
     ///
     /// # Specification
@@ -338,7 +417,8 @@ impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
         ctx: &mut Context<F>,
         entry: &AssignedBatchEntry<F>,
     ) -> AssignedValue<F> {
-        let mut hasher = PoseidonHasher::new(ctx, self.gate(), None);
+        let mut hasher =
+            PoseidonHasher::new(ctx, self.gate(), None, SECURE_MDS);
         hasher.absorb(&entry.vk);
         hasher.squeeze(ctx)
     }
@@ -362,6 +442,7 @@ impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
             ctx,
             self.gate(),
             Some(UPA_V1_0_0_CHALLENGE_DOMAIN_TAG_STRING),
+            SECURE_MDS,
         );
         for (vk_hash, entry) in vk_hashes.into_iter().zip_eq(entries.0.iter()) {
             hasher.absorb(vk_hash);
@@ -390,14 +471,14 @@ impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
             .iter()
             .map(|entry| self.compute_vk_hash(builder.main(0), entry))
             .collect_vec();
-        info!("compute vk_hash: {:?}", advice_cell_count(builder));
+        info!(advice_cells = ?advice_cell_count(builder), "compute vk_hash");
         // Step 3: Compute challenge
         let challenge =
             self.compute_challenge_points(builder.main(0), &vk_hashes, entries);
-        info!("compute r: {:?}", advice_cell_count(builder));
+        info!(advice_cells = ?advice_cell_count(builder), "compute r");
         // Steps 4-7: Verify with challenge
         self.verify_with_challenge(builder, entries, challenge);
-        info!("verify with challenge: {:?}", advice_cell_count(builder));
+        info!(advice_cells = ?advice_cell_count(builder), "verify with challenge");
     }
 
     /// Verifies the proofs in `entries` against `challenge`.
@@ -414,7 +495,7 @@ impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
     ) {
         // Steps 4 and 5
         let prepared_proofs = self.prepare_proofs(builder, entries, challenge);
-        info!("prepare_proofs: {:?}", advice_cell_count(builder));
+        info!(advice_cells = ?advice_cell_count(builder), "prepare_proofs");
 
         let prepared_proofs = prepared_proofs.into_iter().collect_vec();
 
@@ -422,12 +503,12 @@ impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
         let pairing_output = self
             .bv_chip()
             .multi_pairing(builder.main(0), &prepared_proofs);
-        info!("pairing: {:?}", advice_cell_count(builder));
+        info!(advice_cells = ?advice_cell_count(builder), "pairing");
 
         // Step 7: check final result
         self.bv_chip()
             .check_pairing_result(builder.main(0), &pairing_output);
-        info!("check_pairing: {:?}", advice_cell_count(builder));
+        info!(advice_cells = ?advice_cell_count(builder), "check_pairing");
     }
 
     /// Prepares the proofs in `entries` for verification.
@@ -450,12 +531,12 @@ impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
             challenge.0,
             batch_size,
         );
-        info!("r_powers: {:?}", advice_cell_count(builder));
+        info!(advice_cells = ?advice_cell_count(builder), "r_powers");
 
         // Steps 4 and 5: compute other pairs
         let pairs =
             self.compute_pairs(builder, &r_powers, entries, challenge.1);
-        info!("compute_pairs: {:?}", advice_cell_count(builder));
+        info!(advice_cells = ?advice_cell_count(builder), "compute_pairs");
 
         AssignedPreparedProof {
             ab_pairs: pairs.scaled_ab_pairs,
@@ -483,7 +564,7 @@ impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
     ) -> Groth16Pairs<F> {
         // Step 4: compute public input pairs (PI, vk.gamma)
         let pi_pairs = self.compute_pi_pairs(builder, entries);
-        info!("public_input_pair: {:?}", advice_cell_count(builder));
+        info!(advice_cells = ?advice_cell_count(builder), "public_input_pair");
 
         let ctx = builder.main(0);
         let minus_r_powers = r_powers
@@ -518,7 +599,12 @@ impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
                 })
                 .multiunzip();
 
-        let (m_pairs, pok_pairs) = self.pedersen_pairs(entries);
+        // Without commitments, the Pedersen pairs of the padding points
+        // cancel out and are omitted.
+        let (m_pairs, pok_pairs) = match self.allow_commitments {
+            true => self.pedersen_pairs(entries),
+            false => (vec![], vec![]),
+        };
 
         let scaled_ab_pairs =
             self.bv_chip().scale_pairs(ctx, &minus_r_powers, &ab_pairs);
@@ -648,12 +734,20 @@ impl<'a, F: EccPrimeField> UniversalBatchVerifierChip<'a, F> {
                 let mut ss = Vec::<EcPoint<F, ProperCrtUint<F>>>::from_reduced(
                     &entry.vk.s,
                 );
-                ss.push(<EcPoint<F, ProperCrtUint<F>>>::from_reduced(
-                    &entry.proof.m,
-                ));
+                // Without commitments, `has_commitment` is zero and the
+                // commitment term is omitted.
+                let commitment_input = match self.allow_commitments {
+                    true => {
+                        ss.push(<EcPoint<F, ProperCrtUint<F>>>::from_reduced(
+                            &entry.proof.m,
+                        ));
+                        Some(&entry.has_commitment)
+                    }
+                    false => None,
+                };
                 let inputs: Vec<_> = once(&one)
                     .chain(entry.public_inputs.0.iter())
-                    .chain(once(&entry.has_commitment))
+                    .chain(commitment_input)
                     .map(|i| vec![*i])
                     .collect();
                 assert!(ss.len() > 1);
@@ -757,9 +851,9 @@ pub(crate) struct AssignedPreparedProof<F: EccPrimeField> {
     pub(crate) pi_gamma_pairs: Vec<EcPointPair<F>>,
     /// (alpha, beta) pairs
     pub(crate) alpha_beta_pairs: Vec<EcPointPair<F>>,
-    /// (M, h1) pairs
+    /// (M, h1) pairs.  Empty if the circuit does not allow commitments.
     pub(crate) m_h1_pairs: Vec<EcPointPair<F>>,
-    /// (pok, h2) pairs
+    /// (pok, h2) pairs.  Empty if the circuit does not allow commitments.
     pub(crate) pok_h2_pairs: Vec<EcPointPair<F>>,
 }
 
@@ -768,24 +862,44 @@ impl<F: EccPrimeField> AssignedPreparedProof<F> {
     /// matching the order in [`native`](super::native).
     #[cfg(test)]
     pub fn iter(&self) -> impl Iterator<Item = &EcPointPair<F>> {
+        self.check_pedersen_pairs_len();
+        let mut m_h1_pairs = self.m_h1_pairs.iter();
+        let mut pok_h2_pairs = self.pok_h2_pairs.iter();
         self.ab_pairs
             .iter()
             .zip_eq(self.alpha_beta_pairs.iter())
             .zip_eq(self.pi_gamma_pairs.iter())
             .zip_eq(self.cd_pairs.iter())
-            .zip_eq(self.m_h1_pairs.iter())
-            .zip_eq(self.pok_h2_pairs.iter())
-            .flat_map(|(((((a, b), c), d), e), f)| [a, b, c, d, e, f])
+            .flat_map(move |(((a, b), c), d)| {
+                [a, b, c, d]
+                    .into_iter()
+                    .chain(m_h1_pairs.next())
+                    .chain(pok_h2_pairs.next())
+            })
     }
 
     pub fn into_iter(self) -> impl Iterator<Item = EcPointPair<F>> {
+        self.check_pedersen_pairs_len();
+        let mut m_h1_pairs = self.m_h1_pairs.into_iter();
+        let mut pok_h2_pairs = self.pok_h2_pairs.into_iter();
         self.ab_pairs
             .into_iter()
             .zip_eq(self.alpha_beta_pairs.into_iter())
             .zip_eq(self.pi_gamma_pairs.into_iter())
             .zip_eq(self.cd_pairs.into_iter())
-            .zip_eq(self.m_h1_pairs.into_iter())
-            .zip_eq(self.pok_h2_pairs.into_iter())
-            .flat_map(|(((((a, b), c), d), e), f)| [a, b, c, d, e, f])
+            .flat_map(move |(((a, b), c), d)| {
+                [a, b, c, d]
+                    .into_iter()
+                    .chain(m_h1_pairs.next())
+                    .chain(pok_h2_pairs.next())
+            })
+    }
+
+    /// Asserts that there are either no Pedersen pairs, or one of each per
+    /// entry.
+    fn check_pedersen_pairs_len(&self) {
+        let pedersen_len = self.m_h1_pairs.len();
+        assert_eq!(pedersen_len, self.pok_h2_pairs.len());
+        assert!(pedersen_len == 0 || pedersen_len == self.ab_pairs.len());
     }
 }
@@ -9,14 +9,19 @@ use crate::{
             },
         },
     },
-    keccak::PaddedVerifyingKeyLimbs,
+    keccak::{
+        utils::{inputs_per_application_proof, padded_vk_limbs_len},
+        PaddedVerifyingKeyLimbs,
+    },
+    push_instance_segment,
     utils::{
         advice_cell_count,
         commitment_point::{g1affine_into_limbs, get_g1_point_limbs},
+        CellReport,
     },
     EccPrimeField, SafeCircuit,
 };
-use core::{iter::once, marker::PhantomData};
+use core::{iter::once, marker::PhantomData, ops::Range};
 use halo2_base::{
     gates::{
         builder::{
@@ -40,8 +45,8 @@ use halo2_base::{
     AssignedValue,
 };
 use halo2_ecc::bn254::FpChip;
-use log::info;
 use snark_verifier_sdk::CircuitExt;
+use tracing::{info, info_span};
 
 pub mod chip;
 pub mod native;
@@ -67,18 +72,30 @@ pub(crate) fn universal_batch_verify_circuit<F: EccPrimeField>(
     builder: &mut GateThreadBuilder<F>,
     fp_chip: &FpChip<F>,
     ubv_inputs: &BatchEntries<F>,
-    num_limbs: usize,
+    ubv_config: &UniversalBatchVerifierConfig,
 ) -> Vec<AssignedValue<F>> {
-    let batch_verifier_chip = BatchVerifierChip::new(fp_chip);
+    let num_limbs = ubv_config.num_limbs;
+    let batch_verifier_chip =
+        BatchVerifierChip::with_window_bits(fp_chip, ubv_config.window_bits)
+            .with_g2_subgroup_window_bits(ubv_config.g2_subgroup_window_bits)
+            .with_strict_g1_subgroup(ubv_config.strict_g1_subgroup);
     let universal_batch_verifier =
-        UniversalBatchVerifierChip::<F>::new(&batch_verifier_chip);
-    info!("begin: {:?}", advice_cell_count(builder));
-
-    let assigned_batch_entries = universal_batch_verifier
-        .assign_batch_entries(builder.main(0), ubv_inputs);
-    info!("assigned batch entries: {:?}", advice_cell_count(builder));
-
-    universal_batch_verifier.verify(builder, &assigned_batch_entries);
+        UniversalBatchVerifierChip::<F>::new(&batch_verifier_chip)
+            .with_allow_commitments(ubv_config.allow_commitments);
+    let batch_size = ubv_inputs.0.len();
+    let degree = ubv_config.degree_bits;
+    info!(advice_cells = ?advice_cell_count(builder), "begin");
+
+    let assigned_batch_entries =
+        info_span!("assign_proofs", batch_size, degree).in_scope(|| {
+            universal_batch_verifier
+                .assign_batch_entries_parallel(builder, ubv_inputs)
+        });
+    info!(advice_cells = ?advice_cell_count(builder), "assigned batch entries");
+
+    info_span!("verify", batch_size, degree).in_scope(|| {
+        universal_batch_verifier.verify(builder, &assigned_batch_entries)
+    });
 
     assigned_batch_entries
         .0
@@ -94,6 +111,14 @@ pub(crate) fn universal_batch_verify_circuit<F: EccPrimeField>(
         .collect()
 }
 
+/// Returns the number of public inputs of the `UniversalBatchVerifyCircuit`
+/// for `config` (see [`universal_batch_verify_circuit`] for the layout),
+/// without constructing the circuit.
+pub fn ubv_instance_len(config: &UniversalBatchVerifierConfig) -> usize {
+    config.inner_batch_size as usize
+        * inputs_per_application_proof(config.max_num_public_inputs as usize)
+}
+
 /// Universal Batch Verifier Circuit.
 /// Its public input set consists of, for each application proof:
 /// - The number of public inputs
@@ -118,6 +143,32 @@ where
     F: EccPrimeField + SerdeObject,
     C: CurveAffineExt<ScalarExt = F> + SerdeObject,
 {
+    /// Returns the advice and lookup cell counts of the populated builder,
+    /// along with the computed gate config.  Must be called before the
+    /// circuit is synthesized.
+    pub fn cell_report(&self) -> CellReport {
+        CellReport::new(&self.inner.circuit.0.builder.borrow(), &self.config)
+    }
+
+    /// Sets the environment variables (`FLEX_GATE_CONFIG_PARAMS` and
+    /// `LOOKUP_BITS`) which [`SafeCircuit::prover`] checks against
+    /// `gate_config` and `config`.  This is done by
+    /// [`SafeCircuit::read_proving_key`], but must be called explicitly
+    /// when proving with a `gate_config` loaded from a file (e.g. the one
+    /// written at keygen) in a process that did not read the proving key.
+    pub fn set_gate_config_env(
+        config: &UniversalBatchVerifierConfig,
+        gate_config: &FlexGateConfigParams,
+    ) {
+        std::env::set_var(
+            "FLEX_GATE_CONFIG_PARAMS",
+            serde_json::to_string(gate_config).unwrap_or_else(|e| {
+                panic!("failed to serialize FlexGateConfigParams: {e}")
+            }),
+        );
+        std::env::set_var("LOOKUP_BITS", config.lookup_bits.to_string());
+    }
+
     /// Performs the steps that are common to each initialization type.
     /// Namely, creates the GateThreadBuilder in the appropriate mode and
     /// populates it using the application vk and proofs passed in.  Returns
@@ -133,8 +184,10 @@ where
             CircuitBuilderStage::Keygen => GateThreadBuilder::keygen(),
             CircuitBuilderStage::Prover => GateThreadBuilder::prover(),
         };
+        // The UBV instance is hashed by the Keccak circuit, which assumes the
+        // default limb configuration.
+        ubv_config.circuit_config().assert_default_limbs();
         let range = RangeChip::<F>::default(ubv_config.lookup_bits);
-        let num_limbs = ubv_config.num_limbs;
         let fp_chip = FpChip::<F>::new(
             &range,
             ubv_config.limb_bits,
@@ -144,7 +197,7 @@ where
             &mut builder,
             &fp_chip,
             ubv_inputs,
-            num_limbs,
+            ubv_config,
         );
         (builder, instance)
     }
@@ -164,10 +217,17 @@ impl<'a> SafeCircuit<'a, Fr, G1Affine>
         config: &Self::CircuitConfig,
         inputs: &Self::CircuitInputs,
     ) -> Self {
+        let batch_entries =
+            BatchEntries::from_ubv_inputs_and_config(inputs, config);
+        debug_assert_eq!(
+            batch_entries.check_commitment_hashes(),
+            Ok(()),
+            "inconsistent commitment hash"
+        );
         let (builder, instance) = Self::create_builder_and_instance(
             CircuitBuilderStage::Mock,
             config,
-            &BatchEntries::from_ubv_inputs_and_config(inputs, config),
+            &batch_entries,
         );
 
         std::env::set_var("LOOKUP_BITS", config.lookup_bits.to_string());
@@ -192,7 +252,7 @@ impl<'a> SafeCircuit<'a, Fr, G1Affine>
             config,
             &dummy_inputs,
         );
-        info!("advice cells: {:?}", advice_cell_count(&builder));
+        info!(advice_cells = ?advice_cell_count(&builder), "built UBV circuit");
 
         std::env::set_var("LOOKUP_BITS", config.lookup_bits.to_string());
         let config =
@@ -249,12 +309,19 @@ impl<'a> SafeCircuit<'a, Fr, G1Affine>
             config.lookup_bits
         );
 
+        let batch_entries =
+            BatchEntries::from_ubv_inputs_and_config(inputs, config);
+        debug_assert_eq!(
+            batch_entries.check_commitment_hashes(),
+            Ok(()),
+            "inconsistent commitment hash"
+        );
         let (builder, instance) = Self::create_builder_and_instance(
             CircuitBuilderStage::Prover,
             config,
-            &BatchEntries::from_ubv_inputs_and_config(inputs, config),
+            &batch_entries,
         );
-        info!("advice cells: {:?}", advice_cell_count(&builder));
+        info!(advice_cells = ?advice_cell_count(&builder), "built UBV circuit");
 
         Self {
             inner: RangeWithInstanceCircuitBuilder::prover(
@@ -272,6 +339,9 @@ impl<'a> SafeCircuit<'a, Fr, G1Affine>
         inputs: &Self::InstanceInputs,
     ) -> Vec<Fr> {
         let circuit_config = config.circuit_config();
+        // The UBV instance layout (see `inputs_per_application_proof`)
+        // assumes the default limb configuration.
+        circuit_config.assert_default_limbs();
         let batch_entries =
             BatchEntries::from_ubv_inputs_and_config(inputs, config);
 
@@ -286,7 +356,14 @@ impl<'a> SafeCircuit<'a, Fr, G1Affine>
             .iter()
             .flat_map(|be| {
                 once(*be.len())
-                    .chain(PaddedVerifyingKeyLimbs::from_vk(be.vk()).flatten())
+                    .chain(
+                        PaddedVerifyingKeyLimbs::from_vk(
+                            be.vk(),
+                            circuit_config.limb_bits,
+                            circuit_config.num_limbs,
+                        )
+                        .flatten(),
+                    )
                     .chain(once(Fr::from(be.has_commitment())))
                     .chain(once(*be.commitment_hash()))
                     .chain(g1affine_into_limbs(
@@ -299,6 +376,31 @@ impl<'a> SafeCircuit<'a, Fr, G1Affine>
             .collect()
     }
 
+    fn describe_instance(
+        config: &Self::CircuitConfig,
+    ) -> Vec<(String, Range<usize>)> {
+        let num_pub_ins = config.max_num_public_inputs as usize;
+        let num_limbs = config.num_limbs;
+        let mut segments = Vec::new();
+        for i in 0..config.inner_batch_size as usize {
+            let mut push = |name: &str, len: usize| {
+                push_instance_segment(
+                    &mut segments,
+                    format!("entry{i}.{name}"),
+                    len,
+                )
+            };
+            push("len", 1);
+            push("vk", padded_vk_limbs_len(num_pub_ins, 1, num_limbs));
+            push("has_commitment", 1);
+            push("commitment_hash", 1);
+            // x and y coordinates of the commitment point
+            push("commitment_point", 2 * num_limbs);
+            push("inputs", num_pub_ins);
+        }
+        segments
+    }
+
     fn break_points(&self) -> MultiPhaseThreadBreakPoints {
         self.inner.break_points()
     }
@@ -316,16 +418,7 @@ impl<'a> SafeCircuit<'a, Fr, G1Affine>
         R: std::io::Read,
     {
         // Setup the environment
-        std::env::set_var(
-            "FLEX_GATE_CONFIG_PARAMS",
-            serde_json::to_string(gate_config).unwrap_or_else(|e| {
-                panic!("failed to serialize FlexGateConfigParams: {e}")
-            }),
-        );
-        std::env::set_var(
-            "LOOKUP_BITS",
-            circuit_config.lookup_bits.to_string(),
-        );
+        Self::set_gate_config_env(circuit_config, gate_config);
 
         ProvingKey::read::<_, Self>(reader, SerdeFormat::RawBytesUnchecked)
     }
@@ -346,6 +439,26 @@ impl<'a> SafeCircuit<'a, Fr, G1Affine>
 
         VerifyingKey::read::<_, Self>(reader, SerdeFormat::RawBytesUnchecked)
     }
+
+    fn write_proving_key<W>(
+        pk: &ProvingKey<G1Affine>,
+        writer: &mut W,
+    ) -> Result<(), std::io::Error>
+    where
+        W: std::io::Write,
+    {
+        pk.write(writer, SerdeFormat::RawBytesUnchecked)
+    }
+
+    fn write_verifying_key<W>(
+        vk: &VerifyingKey<G1Affine>,
+        writer: &mut W,
+    ) -> Result<(), std::io::Error>
+    where
+        W: std::io::Write,
+    {
+        vk.write(writer, SerdeFormat::RawBytesUnchecked)
+    }
 }
 
 impl<F, C> Circuit<F> for UniversalBatchVerifyCircuit<F, C>
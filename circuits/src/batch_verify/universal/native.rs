@@ -9,21 +9,22 @@ use crate::{
             types::{PublicInputs, VerificationKey},
         },
         universal::types::{
-            UniversalBatchVerifierInput,
+            DomainTagVersion, UniversalBatchVerifierInput,
+            UniversalBatchVerifierInputs,
             UPA_V1_0_0_CHALLENGE_DOMAIN_TAG_STRING,
-            UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING,
-            UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING_WITH_COMMITMENT,
         },
     },
     utils::{
-        commitment_point::be_bytes_to_field_element, hashing::WrongFieldHasher,
+        commitment_point::be_bytes_to_field_element,
+        hashing::{WrongFieldHasher, SECURE_MDS},
     },
     CircuitWithLimbsConfig,
 };
-use core::borrow::Borrow;
+use core::{borrow::Borrow, fmt};
 use halo2_base::halo2_proofs::halo2curves::{
-    bn256::{multi_miller_loop, Fr, G1Affine, G2Affine, Gt, G1},
+    bn256::{multi_miller_loop, Fr, G1Affine, G2Affine, G2Prepared, Gt, G1},
     pairing::MillerLoopResult,
+    CurveAffine,
 };
 use itertools::Itertools;
 use std::cell::RefCell;
@@ -50,13 +51,63 @@ impl PairingCheckPairs {
 /// as (r, t).
 pub(crate) type ChallengePoints = (Fr, Fr);
 
-/// Computes the vk hash of `vk`.
+/// Reason for which [`verify_batch`] rejected an entry. Each variant holds
+/// the index of the first failing entry in the batch.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BatchVerifyError {
+    /// The entry is not well formed (see
+    /// [`UniversalBatchVerifierInput::check_well_formed`]).
+    Malformed(usize),
+    /// A proof or verification key point is not on the curve.
+    PointNotOnCurve(usize),
+    /// A proof or verification key G2 point is on the curve, but not in the
+    /// prime-order subgroup.
+    PointNotInSubgroup(usize),
+    /// The Pedersen commitment proof of knowledge check failed.
+    PedersenPoK(usize),
+    /// The Groth16 pairing check failed.
+    Pairing(usize),
+}
+
+impl fmt::Display for BatchVerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchVerifyError::Malformed(i) => {
+                write!(f, "entry {i}: malformed entry")
+            }
+            BatchVerifyError::PointNotOnCurve(i) => {
+                write!(f, "entry {i}: point not on curve")
+            }
+            BatchVerifyError::PointNotInSubgroup(i) => {
+                write!(f, "entry {i}: G2 point not in subgroup")
+            }
+            BatchVerifyError::PedersenPoK(i) => {
+                write!(f, "entry {i}: Pedersen commitment PoK check failed")
+            }
+            BatchVerifyError::Pairing(i) => {
+                write!(f, "entry {i}: Groth16 pairing check failed")
+            }
+        }
+    }
+}
+
+/// Computes the vk hash of `vk`.  Panics if `vk` is not well-formed (see
+/// [`VerificationKey::is_well_formed`]).
 pub fn compute_circuit_id(vk: &VerificationKey) -> [u8; 32] {
     assert!(vk.is_well_formed());
-    let domain_tag = match vk.has_commitment() {
-        false => UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING,
-        true => UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING_WITH_COMMITMENT,
-    };
+    compute_circuit_id_with_domain_version(vk, DomainTagVersion::default())
+}
+
+/// Computes the vk hash of `vk`, using the domain tags of `version`.  If
+/// `vk` supports several Pedersen commitments (as allowed by the Keccak
+/// circuit), the pairs `(h1[i], h2[i])` are absorbed in order.  Panics if
+/// `h1` and `h2` have different lengths.
+pub fn compute_circuit_id_with_domain_version(
+    vk: &VerificationKey,
+    version: DomainTagVersion,
+) -> [u8; 32] {
+    let domain_tag =
+        version.circuit_id_domain_tag_string(vk.num_commitments() != 0);
     compute_vk_keccak_hash_with_domain_tag(vk, domain_tag)
 }
 
@@ -71,6 +122,7 @@ pub(crate) fn compute_challenge_points(
     let mut poseidon = WrongFieldHasher::new(
         &circuit_config,
         Some(UPA_V1_0_0_CHALLENGE_DOMAIN_TAG_STRING),
+        SECURE_MDS,
     );
     for entry in batch {
         let entry = entry.borrow();
@@ -216,6 +268,80 @@ where
     final_exp == Gt::identity()
 }
 
+/// Verifies each entry of `inputs` independently with the standard Groth16
+/// verifier, including the Pedersen commitment proof of knowledge check
+/// for entries with a commitment. Unlike
+/// [`verify_universal_groth16_batch`], no random linear combination is
+/// taken, so that the first failing entry and check can be identified.
+/// Like the circuit, checks that all G2 points are in the prime-order
+/// subgroup. Intended as a reference for debugging the circuit.
+pub fn verify_batch(
+    inputs: &UniversalBatchVerifierInputs<Fr>,
+) -> Result<(), BatchVerifyError> {
+    let pairing_check = |pairs: &[(G1Affine, G2Affine)]| {
+        let pairs = pairs
+            .iter()
+            .map(|(a, b)| (*a, G2Prepared::from(*b)))
+            .collect_vec();
+        multi_miller_loop(
+            pairs.iter().map(|(a, b)| (a, b)).collect_vec().as_slice(),
+        )
+        .final_exponentiation()
+            == Gt::identity()
+    };
+    for (i, entry) in inputs.0.iter().enumerate() {
+        if entry.check_well_formed().is_err() {
+            return Err(BatchVerifyError::Malformed(i));
+        }
+        let UniversalBatchVerifierInput { vk, proof, .. } = entry;
+        let g1_points_on_curve = [proof.a, proof.c, vk.alpha]
+            .iter()
+            .chain(proof.m.iter())
+            .chain(proof.pok.iter())
+            .chain(vk.s.iter())
+            .all(|p| bool::from(p.is_on_curve()));
+        let g2_points = [proof.b, vk.beta, vk.gamma, vk.delta]
+            .into_iter()
+            .chain(vk.h1.iter().copied())
+            .chain(vk.h2.iter().copied())
+            .collect_vec();
+        let g2_points_on_curve =
+            g2_points.iter().all(|p| bool::from(p.is_on_curve()));
+        if !(g1_points_on_curve && g2_points_on_curve) {
+            return Err(BatchVerifyError::PointNotOnCurve(i));
+        }
+        // [r] P = O  <=>  [r - 1] P = -P
+        if g2_points
+            .iter()
+            .any(|p| G2Affine::from(*p * -Fr::one()) != -*p)
+        {
+            return Err(BatchVerifyError::PointNotInSubgroup(i));
+        }
+
+        if entry.has_commitment()
+            && !pairing_check(&[
+                (proof.m[0], vk.h1[0]),
+                (proof.pok[0], vk.h2[0]),
+            ])
+        {
+            return Err(BatchVerifyError::PedersenPoK(i));
+        }
+
+        let entry = update_entry(entry);
+        let pi_term = compute_pi_term_for_entry(&entry);
+        let groth16_pairs = [
+            (-proof.a, proof.b),
+            (vk.alpha, vk.beta),
+            (G1Affine::from(pi_term), vk.gamma),
+            (proof.c, vk.delta),
+        ];
+        if !pairing_check(&groth16_pairs) {
+            return Err(BatchVerifyError::Pairing(i));
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn compute_pi_term_for_entry_without_commitment(
     vk_s: &[G1Affine],
     inputs: &PublicInputs,
@@ -315,10 +441,11 @@ pub mod json {
                 UniversalBatchVerifierInputs,
             },
         },
-        utils::file::load_json,
+        utils::{base64::Base64DecodeError, file::load_json},
     };
     use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
     use serde::{Deserialize, Serialize};
+    use std::path::Path;
 
     #[derive(Debug, Clone, Deserialize, Serialize)]
     pub struct JsonUniversalBatchVerifierInput {
@@ -340,12 +467,71 @@ pub mod json {
         }
     }
 
+    /// Like [`JsonUniversalBatchVerifierInput`], but with the vk, proof and
+    /// inputs as base64 blobs (see [`Proof::to_base64`] and friends).
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    pub struct Base64UniversalBatchVerifierInput {
+        pub vk: String,
+        pub proof: String,
+        pub inputs: String,
+    }
+
+    impl TryFrom<&Base64UniversalBatchVerifierInput>
+        for UniversalBatchVerifierInput<Fr>
+    {
+        type Error = Base64DecodeError;
+
+        fn try_from(
+            blobs: &Base64UniversalBatchVerifierInput,
+        ) -> Result<Self, Self::Error> {
+            Ok(UniversalBatchVerifierInput::new(
+                VerificationKey::from_base64(&blobs.vk)?,
+                Proof::from_base64(&blobs.proof)?,
+                PublicInputs::from_base64(&blobs.inputs)?,
+            ))
+        }
+    }
+
+    impl From<&UniversalBatchVerifierInput<Fr>>
+        for Base64UniversalBatchVerifierInput
+    {
+        fn from(input: &UniversalBatchVerifierInput<Fr>) -> Self {
+            Base64UniversalBatchVerifierInput {
+                vk: input.vk.to_base64(),
+                proof: input.proof.to_base64(),
+                inputs: input.inputs.to_base64(),
+            }
+        }
+    }
+
     /// Json version of UniversalBatchVerifierInputs.
     #[derive(Clone, Debug, Deserialize, Serialize)]
     pub struct JsonUniversalBatchVerifierInputs(
         pub Vec<JsonUniversalBatchVerifierInput>,
     );
 
+    impl From<&UniversalBatchVerifierInput<Fr>>
+        for JsonUniversalBatchVerifierInput
+    {
+        fn from(input: &UniversalBatchVerifierInput<Fr>) -> Self {
+            JsonUniversalBatchVerifierInput {
+                vk: input.vk().into(),
+                proof: input.proof().into(),
+                inputs: input.inputs().into(),
+            }
+        }
+    }
+
+    impl From<&UniversalBatchVerifierInputs<Fr>>
+        for JsonUniversalBatchVerifierInputs
+    {
+        fn from(inputs: &UniversalBatchVerifierInputs<Fr>) -> Self {
+            JsonUniversalBatchVerifierInputs(
+                inputs.0.iter().map(Into::into).collect(),
+            )
+        }
+    }
+
     /// Read UniversalBatchVerifierInputs from JsonUniversalBatchVerifierInputs
     impl From<JsonUniversalBatchVerifierInputs>
         for UniversalBatchVerifierInputs<Fr>
@@ -362,6 +548,47 @@ pub mod json {
         }
     }
 
+    /// Like [`JsonUniversalBatchVerifierInput`], but with the proof in the
+    /// snarkjs format (see [`JsonSnarkjsProof`]).  `inputs` holds the
+    /// contents of the snarkjs `public.json` file.
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    pub struct JsonSnarkjsUniversalBatchVerifierInput {
+        pub vk: JsonVerificationKey,
+        pub proof: JsonSnarkjsProof,
+        pub inputs: JsonPublicInputs,
+    }
+
+    impl From<&JsonSnarkjsUniversalBatchVerifierInput>
+        for JsonUniversalBatchVerifierInput
+    {
+        fn from(json: &JsonSnarkjsUniversalBatchVerifierInput) -> Self {
+            JsonUniversalBatchVerifierInput {
+                vk: json.vk.clone(),
+                proof: JsonProof::from(&json.proof),
+                inputs: json.inputs.clone(),
+            }
+        }
+    }
+
+    /// Json version of a batch of [`JsonSnarkjsUniversalBatchVerifierInput`]s.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct JsonSnarkjsUniversalBatchVerifierInputs(
+        pub Vec<JsonSnarkjsUniversalBatchVerifierInput>,
+    );
+
+    impl From<&JsonSnarkjsUniversalBatchVerifierInputs>
+        for JsonUniversalBatchVerifierInputs
+    {
+        fn from(json: &JsonSnarkjsUniversalBatchVerifierInputs) -> Self {
+            JsonUniversalBatchVerifierInputs(
+                json.0
+                    .iter()
+                    .map(JsonUniversalBatchVerifierInput::from)
+                    .collect(),
+            )
+        }
+    }
+
     pub fn load_app_vk_proof_and_inputs(
         filename: &str,
     ) -> UniversalBatchVerifierInput {
@@ -377,4 +604,74 @@ pub mod json {
             load_json(filename);
         UniversalBatchVerifierInputs::from(vks_proofs_pis_json)
     }
+
+    /// Index of a batch of [`JsonUniversalBatchVerifierInput`]s split
+    /// across several files (shards), each holding a
+    /// [`JsonUniversalBatchVerifierInputs`].  Shard paths are relative to the
+    /// directory of the index file.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct JsonShardIndex {
+        pub shards: Vec<String>,
+        /// Total number of entries in all shards
+        pub num_entries: usize,
+    }
+
+    /// Loads the shards listed in the [`JsonShardIndex`] file `index_path`
+    /// and concatenates them, in order.
+    pub fn load_sharded(index_path: &str) -> JsonUniversalBatchVerifierInputs {
+        let index: JsonShardIndex = load_json(index_path);
+        let dir = Path::new(index_path).parent().unwrap_or(Path::new(""));
+        let entries: Vec<JsonUniversalBatchVerifierInput> = index
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                let shard_path = dir.join(shard);
+                let shard_path = shard_path
+                    .to_str()
+                    .unwrap_or_else(|| panic!("invalid shard path {shard}"));
+                load_json::<JsonUniversalBatchVerifierInputs>(shard_path).0
+            })
+            .collect();
+        assert_eq!(
+            entries.len(),
+            index.num_entries,
+            "{index_path}: unexpected number of entries"
+        );
+        JsonUniversalBatchVerifierInputs(entries)
+    }
+
+    /// Like [`load_app_vk_proof_and_inputs_batch`], but does not check that
+    /// the entries are well formed, so that inconsistencies can be reported
+    /// by [`UniversalBatchVerifierInputs::check_consistent`].
+    pub fn load_app_vk_proof_and_inputs_batch_unchecked(
+        filename: &str,
+    ) -> UniversalBatchVerifierInputs<Fr> {
+        let vks_proofs_pis_json: JsonUniversalBatchVerifierInputs =
+            load_json(filename);
+        ubv_inputs_from_json_unchecked(&vks_proofs_pis_json)
+    }
+
+    /// Converts `json` into [`UniversalBatchVerifierInputs`] without checking
+    /// that the entries are well formed.  See
+    /// [`load_app_vk_proof_and_inputs_batch_unchecked`].
+    pub fn ubv_inputs_from_json_unchecked(
+        json: &JsonUniversalBatchVerifierInputs,
+    ) -> UniversalBatchVerifierInputs<Fr> {
+        UniversalBatchVerifierInputs(
+            json.0.iter().map(ubv_input_from_json_unchecked).collect(),
+        )
+    }
+
+    /// Converts a single entry `json` into a [`UniversalBatchVerifierInput`]
+    /// without checking that it is well formed.  See
+    /// [`ubv_inputs_from_json_unchecked`].
+    pub fn ubv_input_from_json_unchecked(
+        json: &JsonUniversalBatchVerifierInput,
+    ) -> UniversalBatchVerifierInput<Fr> {
+        UniversalBatchVerifierInput {
+            vk: VerificationKey::from(&json.vk),
+            proof: Proof::from(&json.proof),
+            inputs: PublicInputs::from(&json.inputs),
+        }
+    }
 }
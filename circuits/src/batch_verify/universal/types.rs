@@ -1,15 +1,31 @@
 use crate::{
     batch_verify::common::{
+        chip::{WINDOW_BITS, WINDOW_BITS_G2_SUBGROUP_CHECK},
         ecc::EcPointPair,
         native::unsafe_proof_generation::sample_proofs_inputs_vk,
         types::{Proof, PublicInputs, VerificationKey},
     },
-    utils::commitment_point::be_bytes_to_field_element,
-    CircuitWithLimbsConfig, EccPrimeField, UpaConfig,
+    keccak::{
+        utils::{inputs_per_application_proof, padded_vk_limbs_len},
+        PaddedVerifyingKeyLimbs,
+    },
+    utils::commitment_point::{
+        be_bytes_to_field_element, commitment_hash_from_commitment_point_limbs,
+        g1affine_into_limbs, limbs_into_g1affine,
+    },
+    CircuitWithLimbsConfig, EccPrimeField, UpaConfig, DEFAULT_LIMB_BITS,
+    DEFAULT_NUM_LIMBS,
+};
+use core::{fmt, str::FromStr};
+use halo2_base::{
+    halo2_proofs::halo2curves::{
+        bn256::{Fr, G1Affine},
+        CurveAffine,
+    },
+    AssignedValue,
 };
-use halo2_base::{halo2_proofs::halo2curves::bn256::Fr, AssignedValue};
 use itertools::Itertools;
-use rand::{Rng, RngCore};
+use rand::{seq::SliceRandom, Rng, RngCore};
 use serde::{Deserialize, Serialize};
 
 pub(crate) const UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING: &str =
@@ -19,6 +35,56 @@ pub(crate) const UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING_WITH_COMMITMENT: &str =
 pub(crate) const UPA_V1_0_0_CHALLENGE_DOMAIN_TAG_STRING: &str =
     "UPA v1.0.0 Challenge";
 
+/// Protocol version determining the domain tags used in the circuit id
+/// computation.  When the tags change, a variant is added for the new
+/// version, so that circuit ids computed under a previous version can be
+/// reproduced by selecting that version.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize,
+)]
+pub enum DomainTagVersion {
+    /// UPA v1.0.0.
+    #[default]
+    V1_0_0,
+}
+
+impl DomainTagVersion {
+    /// Returns the string from which the circuit id domain tag is derived,
+    /// for a verification key with or without a commitment.
+    pub fn circuit_id_domain_tag_string(
+        self,
+        has_commitment: bool,
+    ) -> &'static str {
+        match (self, has_commitment) {
+            (DomainTagVersion::V1_0_0, false) => {
+                UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING
+            }
+            (DomainTagVersion::V1_0_0, true) => {
+                UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING_WITH_COMMITMENT
+            }
+        }
+    }
+}
+
+impl FromStr for DomainTagVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_start_matches('v') {
+            "1.0.0" => Ok(DomainTagVersion::V1_0_0),
+            _ => Err(format!("unknown domain tag version: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for DomainTagVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DomainTagVersion::V1_0_0 => write!(f, "1.0.0"),
+        }
+    }
+}
+
 /// Parameters of the Universal Batch Verifier circuit
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct UniversalBatchVerifierConfig {
@@ -39,6 +105,45 @@ pub struct UniversalBatchVerifierConfig {
 
     /// Maximum number of public inputs allowed.
     pub max_num_public_inputs: u32,
+
+    /// Window size for the G1 scalar multiplications in the batched
+    /// pairing check.  Larger windows may reduce the number of advice cells
+    /// for some configurations.
+    #[serde(default = "default_window_bits")]
+    pub window_bits: usize,
+
+    /// Window size for the G2 scalar multiplication in the subgroup check of
+    /// the verification key points.  The check runs for each G2 point of
+    /// each vk, so this noticeably affects the number of advice cells.
+    #[serde(default = "default_g2_subgroup_window_bits")]
+    pub g2_subgroup_window_bits: usize,
+
+    /// Check that G1 points of the proofs and verification keys belong to
+    /// the prime order subgroup, in addition to being on the curve.  This
+    /// is redundant for BN254 (whose G1 cofactor is 1) and costly, so is
+    /// off by default.
+    #[serde(default)]
+    pub strict_g1_subgroup: bool,
+
+    /// Support proofs with a Pedersen commitment.  If unset, the circuit
+    /// only accepts batches without commitments, and is cheaper: the
+    /// commitment flag, hash and (padding) points of each entry are
+    /// constants, and the Pedersen pairs are omitted from the pairing check.
+    /// The instance layout is unchanged.  Set by default.
+    #[serde(default = "default_allow_commitments")]
+    pub allow_commitments: bool,
+}
+
+fn default_window_bits() -> usize {
+    WINDOW_BITS
+}
+
+fn default_g2_subgroup_window_bits() -> usize {
+    WINDOW_BITS_G2_SUBGROUP_CHECK
+}
+
+fn default_allow_commitments() -> bool {
+    true
 }
 
 impl UniversalBatchVerifierConfig {
@@ -54,6 +159,10 @@ impl UniversalBatchVerifierConfig {
             num_limbs: circuit_config.num_limbs,
             inner_batch_size: batch_size,
             max_num_public_inputs,
+            window_bits: WINDOW_BITS,
+            g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK,
+            strict_g1_subgroup: false,
+            allow_commitments: true,
         }
     }
 
@@ -69,6 +178,110 @@ impl UniversalBatchVerifierConfig {
     pub fn from_upa_config_file(config_file: &str) -> Self {
         UniversalBatchVerifierConfig::from(&UpaConfig::from_file(config_file))
     }
+
+    /// Parses `instance`, the instance of a `UniversalBatchVerifyCircuit`
+    /// for `self`, into its entries.  Panics if `instance` is malformed.
+    pub fn parse_instance(&self, instance: &[Fr]) -> Vec<ParsedEntry> {
+        // The UBV instance layout assumes the default limb configuration.
+        self.circuit_config().assert_default_limbs();
+        parse_ubv_instance(
+            instance,
+            self.max_num_public_inputs as usize,
+            self.inner_batch_size as usize,
+        )
+    }
+}
+
+/// An entry of a `UniversalBatchVerifyCircuit` instance, as recovered by
+/// [`UniversalBatchVerifierConfig::parse_instance`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedEntry {
+    /// Number of public inputs, excluding the commitment hash.
+    pub len: usize,
+    /// The verification key, without padding.
+    pub vk: VerificationKey,
+    pub has_commitment: bool,
+    /// The Pedersen commitment point, if `has_commitment`.
+    pub commitment_point: Option<G1Affine>,
+    /// The public inputs, excluding the commitment hash.
+    pub public_inputs: PublicInputs<Fr>,
+}
+
+/// Parses the instance of a `UniversalBatchVerifyCircuit` with
+/// `inner_batch_size` entries of up to `max_num_public_inputs` public inputs,
+/// using the default limb configuration.  See `universal_batch_verify_circuit`
+/// for the layout.  Panics if `instance` is malformed.
+pub(crate) fn parse_ubv_instance(
+    instance: &[Fr],
+    max_num_public_inputs: usize,
+    inner_batch_size: usize,
+) -> Vec<ParsedEntry> {
+    let inputs_per_proof = inputs_per_application_proof(max_num_public_inputs);
+    assert_eq!(
+        instance.len(),
+        inputs_per_proof * inner_batch_size,
+        "UBV instance length inconsistent with configuration"
+    );
+    instance
+        .chunks_exact(inputs_per_proof)
+        .map(|entry| parse_ubv_instance_entry(entry, max_num_public_inputs))
+        .collect()
+}
+
+/// Parses the instance values of a single entry.
+fn parse_ubv_instance_entry(
+    entry: &[Fr],
+    max_num_public_inputs: usize,
+) -> ParsedEntry {
+    const ZERO: Fr = Fr::zero();
+    const ONE: Fr = Fr::one();
+    let vk_limbs_len =
+        padded_vk_limbs_len(max_num_public_inputs, 1, DEFAULT_NUM_LIMBS);
+    let (len, entry) = entry.split_first().expect("Missing input length");
+    let len = len.get_lower_32() as usize;
+    let (vk_limbs, entry) = entry.split_at(vk_limbs_len);
+    let (has_commitment, entry) =
+        entry.split_first().expect("Missing has commitment flag");
+    let has_commitment = match *has_commitment {
+        ZERO => false,
+        ONE => true,
+        _ => panic!("has commitment can only be 0 or 1"),
+    };
+    let (_commitment_hash, entry) =
+        entry.split_first().expect("Missing commitment hash");
+    let (commitment_point_limbs, inputs) =
+        entry.split_at(2 * DEFAULT_NUM_LIMBS);
+    assert!(
+        len + has_commitment as usize <= inputs.len(),
+        "Invalid input length"
+    );
+
+    let mut vk = PaddedVerifyingKeyLimbs::from_limbs(
+        vk_limbs,
+        max_num_public_inputs + 1,
+        DEFAULT_NUM_LIMBS,
+    )
+    .vk(DEFAULT_LIMB_BITS, DEFAULT_NUM_LIMBS);
+    vk.s.truncate(len + 1 + has_commitment as usize);
+    if !has_commitment {
+        vk.h1.clear();
+        vk.h2.clear();
+    }
+    let commitment_point = has_commitment.then(|| {
+        limbs_into_g1affine(
+            commitment_point_limbs,
+            DEFAULT_LIMB_BITS,
+            DEFAULT_NUM_LIMBS,
+        )
+    });
+
+    ParsedEntry {
+        len,
+        vk,
+        has_commitment,
+        commitment_point,
+        public_inputs: PublicInputs(inputs[..len].to_vec()),
+    }
 }
 
 impl From<&UpaConfig> for UniversalBatchVerifierConfig {
@@ -80,6 +293,10 @@ impl From<&UpaConfig> for UniversalBatchVerifierConfig {
             num_limbs: config.bv_config.num_limbs,
             inner_batch_size: config.inner_batch_size,
             max_num_public_inputs: config.max_num_app_public_inputs,
+            window_bits: config.window_bits,
+            g2_subgroup_window_bits: config.g2_subgroup_window_bits,
+            strict_g1_subgroup: false,
+            allow_commitments: config.allow_commitments,
         }
     }
 }
@@ -88,11 +305,24 @@ impl From<&UpaConfig> for UniversalBatchVerifierConfig {
 impl core::fmt::Display for UniversalBatchVerifierConfig {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "UBV degree: {}", self.degree_bits)?;
+        writeln!(f, "Lookup bits: {}", self.lookup_bits)?;
+        writeln!(f, "Limb bits: {}", self.limb_bits)?;
+        writeln!(f, "Num limbs: {}", self.num_limbs)?;
+        writeln!(f, "Strict G1 subgroup: {}", self.strict_g1_subgroup)?;
         writeln!(
             f,
             "Max number of public inputs: {}",
             self.max_num_public_inputs
         )?;
+        writeln!(f, "Window bits: {}", self.window_bits)?;
+        writeln!(
+            f,
+            "G2 subgroup window bits: {}",
+            self.g2_subgroup_window_bits
+        )?;
+        if !self.allow_commitments {
+            writeln!(f, "No commitments")?;
+        }
         write!(f, "Inner batch size: {}", self.inner_batch_size)
     }
 }
@@ -164,24 +394,39 @@ impl<F: EccPrimeField> BatchEntry<F> {
         }
     }
 
-    /// Creates a dummy [`BatchEntry`] for `config`.
+    /// Creates a dummy [`BatchEntry`] for `config`.  It has a commitment if
+    /// `config` allows them.
     pub fn dummy(config: &UniversalBatchVerifierConfig) -> Self {
         let num_public_inputs = config.max_num_public_inputs as usize;
-        let len = F::from(num_public_inputs as u64 - 1);
-        let has_commitment = true;
-        let vk = VerificationKey::default_with_length(
+        let has_commitment = config.allow_commitments;
+        let len = F::from((num_public_inputs - has_commitment as usize) as u64);
+        let mut vk = VerificationKey::default_with_length(
             num_public_inputs,
             has_commitment,
         );
-        let proof = Proof::default_with_commitment(has_commitment);
+        let mut proof = Proof::default_with_commitment(has_commitment);
         let inputs = PublicInputs::default_with_length(num_public_inputs);
+        // Without commitments, the commitment data is assigned as constants,
+        // so it must be the padding used for real entries.
+        let commitment_hash = match has_commitment {
+            true => Default::default(),
+            false => {
+                vk.pad(num_public_inputs);
+                proof.pad_commitments();
+                be_bytes_to_field_element(
+                    &proof
+                        .compute_commitment_hash_bytes_from_commitment_point()
+                        .expect("failed to hash commitment point"),
+                )
+            }
+        };
         Self {
             len,
             has_commitment,
             vk,
             proof,
             inputs,
-            commitment_hash: Default::default(),
+            commitment_hash,
         }
     }
 
@@ -247,6 +492,61 @@ impl<F: EccPrimeField> BatchEntries<F> {
                 .collect(),
         )
     }
+
+    /// Checks that the commitment hash of each entry is the hash of its
+    /// (possibly padded) commitment point, as constrained by the circuit.
+    /// Returns the index of the first inconsistent entry otherwise.
+    ///
+    /// # Note
+    ///
+    /// The hash is computed from the bytes of the point, so it does not
+    /// depend on the limb decomposition used here.
+    pub fn check_commitment_hashes(&self) -> Result<(), usize> {
+        match self.0.iter().position(|entry| {
+            let limbs: Vec<F> = g1affine_into_limbs(
+                &entry.proof.m[0],
+                DEFAULT_LIMB_BITS,
+                DEFAULT_NUM_LIMBS,
+            );
+            let expected = commitment_hash_from_commitment_point_limbs(
+                &limbs,
+                DEFAULT_LIMB_BITS,
+                DEFAULT_NUM_LIMBS,
+            );
+            entry.commitment_hash != expected
+        }) {
+            Some(idx) => Err(idx),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the indices `i` such that entry `i` has the same vk, proof and
+    /// public inputs as entry `i - 1`.
+    pub fn adjacent_duplicates(&self) -> Vec<usize> {
+        self.0
+            .iter()
+            .tuple_windows()
+            .positions(|(prev, entry)| {
+                prev.len == entry.len
+                    && prev.vk == entry.vk
+                    && prev.proof == entry.proof
+                    && prev.inputs == entry.inputs
+            })
+            .map(|idx| idx + 1)
+            .collect()
+    }
+
+    /// Logs a warning for each entry identical to the previous one (see
+    /// [`Self::adjacent_duplicates`]) and returns their indices.  Such
+    /// entries are valid, but usually indicate a bug in the code producing
+    /// the batch.
+    pub fn warn_on_adjacent_duplicates(&self) -> Vec<usize> {
+        let duplicates = self.adjacent_duplicates();
+        for idx in &duplicates {
+            log::warn!("batch entry {idx} is identical to entry {}", idx - 1);
+        }
+        duplicates
+    }
 }
 
 /// Universal Batch Verifier Input.
@@ -280,42 +580,74 @@ impl<F: EccPrimeField> UniversalBatchVerifierInput<F> {
 
     /// Asserts `self` is well formed and consistent with `config`.
     pub fn assert_consistent(&self, config: &UniversalBatchVerifierConfig) {
-        self.assert_well_formed();
-        let num_commitments = self.vk.h1.len();
-        assert!(
-            self.inputs.0.len() + num_commitments
-                <= config.max_num_public_inputs as usize,
-            "Public input length exceeds maximum allowed"
-        );
+        self.check_consistent(config)
+            .unwrap_or_else(|e| panic!("{e}"));
     }
 
     /// Asserts `self` is well formed.
     pub fn assert_well_formed(&self) {
+        self.check_well_formed().unwrap_or_else(|e| panic!("{e}"));
+    }
+
+    /// Checks that `self` is well formed and consistent with `config`,
+    /// returning a description of the first inconsistency otherwise.
+    pub fn check_consistent(
+        &self,
+        config: &UniversalBatchVerifierConfig,
+    ) -> Result<(), String> {
+        self.check_well_formed()?;
+        let num_public_inputs = self.inputs.0.len();
         let num_commitments = self.vk.h1.len();
-        assert_eq!(
-            self.vk.s.len(),
-            self.inputs.0.len() + 1 + num_commitments,
-            "Verification key and public inputs lengths not compatible"
-        );
-        assert!(
-            num_commitments < 2,
-            "Number of commitments can only be one or zero"
-        );
-        assert_eq!(
-            num_commitments,
-            self.vk.h2.len(),
-            "Invalid VK. Inconsistent h1, h2."
-        );
-        assert_eq!(
-            self.proof.m.len(),
-            self.proof.pok.len(),
-            "Invalid proof. Inconsistent m, pok."
-        );
-        assert_eq!(
-            num_commitments,
-            self.proof.m.len(),
-            "Proof and VK have inconsistent Pedersen commitments."
-        );
+        if num_public_inputs + num_commitments
+            > config.max_num_public_inputs as usize
+        {
+            return Err(format!(
+                "{num_public_inputs} public inputs and {num_commitments} \
+                 commitments exceed the maximum of {} public inputs",
+                config.max_num_public_inputs
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that `self` is well formed, returning a description of the
+    /// first inconsistency otherwise.
+    pub fn check_well_formed(&self) -> Result<(), String> {
+        let num_public_inputs = self.inputs.0.len();
+        let num_commitments = self.vk.h1.len();
+        if self.vk.s.len() != num_public_inputs + 1 + num_commitments {
+            return Err(format!(
+                "vk.s length {} incompatible with {num_public_inputs} public \
+                 inputs and {num_commitments} commitments",
+                self.vk.s.len()
+            ));
+        }
+        if num_commitments > 1 {
+            return Err(format!(
+                "vk has {num_commitments} commitments, at most 1 is supported"
+            ));
+        }
+        if self.vk.h2.len() != num_commitments {
+            return Err(format!(
+                "vk.h1 length {num_commitments} and vk.h2 length {} differ",
+                self.vk.h2.len()
+            ));
+        }
+        if self.proof.m.len() != self.proof.pok.len() {
+            return Err(format!(
+                "proof.m length {} and proof.pok length {} differ",
+                self.proof.m.len(),
+                self.proof.pok.len()
+            ));
+        }
+        if self.proof.m.len() != num_commitments {
+            return Err(format!(
+                "proof.m length {} incompatible with {num_commitments} vk \
+                 commitments",
+                self.proof.m.len()
+            ));
+        }
+        Ok(())
     }
 
     /// Returns `true` if `self` has a commitment
@@ -343,6 +675,25 @@ impl<F: EccPrimeField> UniversalBatchVerifierInput<F> {
         let inputs = PublicInputs::default_with_length(num_public_inputs);
         Self { vk, proof, inputs }
     }
+
+    /// Creates a *valid* padding [`UniversalBatchVerifierInput`] for
+    /// `config`, without commitment and with all-zero public inputs.
+    ///
+    /// # Note
+    ///
+    /// Unlike [`Self::dummy`], the proof satisfies the Groth16 equation
+    /// `e(A, B) = e(alpha, beta) e(s_0, gamma) e(C, delta)`: all VK points
+    /// and `B`, `C` are generators, and `A = 3 * G1`.  It can therefore be
+    /// used to fill a batch.
+    pub fn padding(config: &UniversalBatchVerifierConfig) -> Self {
+        let Self {
+            vk,
+            mut proof,
+            inputs,
+        } = Self::dummy(config);
+        proof.a = G1Affine::from(G1Affine::generator() * Fr::from(3));
+        Self { vk, proof, inputs }
+    }
 }
 
 impl UniversalBatchVerifierInput {
@@ -378,6 +729,28 @@ pub struct UniversalBatchVerifierInputs<F: EccPrimeField>(
 );
 
 impl<F: EccPrimeField> UniversalBatchVerifierInputs<F> {
+    /// Checks that `self` contains `config.inner_batch_size` entries, each
+    /// consistent with `config`. Otherwise returns a description of the
+    /// first inconsistent entry.
+    pub fn check_consistent(
+        &self,
+        config: &UniversalBatchVerifierConfig,
+    ) -> Result<(), String> {
+        if self.0.len() != config.inner_batch_size as usize {
+            return Err(format!(
+                "batch has {} entries, expected inner batch size {}",
+                self.0.len(),
+                config.inner_batch_size
+            ));
+        }
+        for (i, input) in self.0.iter().enumerate() {
+            input
+                .check_consistent(config)
+                .map_err(|e| format!("entry {i}: {e}"))?;
+        }
+        Ok(())
+    }
+
     /// Creates dummy [`UniversalBatchVerifierInputs`] for `config`.
     pub fn dummy(config: &UniversalBatchVerifierConfig) -> Self {
         Self(
@@ -388,6 +761,52 @@ impl<F: EccPrimeField> UniversalBatchVerifierInputs<F> {
         )
     }
 
+    /// Returns the entries of `self` at the indices `entries`, in that
+    /// order, followed by [`UniversalBatchVerifierInput::padding`] entries up
+    /// to `config.inner_batch_size`.  Also returns, for each position of the
+    /// result, the index in `self` of the entry it holds, or `None` for
+    /// padding.
+    ///
+    /// Fails if an index is out of range or repeated, or if there are more
+    /// than `config.inner_batch_size` indices.
+    #[allow(clippy::type_complexity)]
+    pub fn select_with_padding(
+        &self,
+        entries: &[usize],
+        config: &UniversalBatchVerifierConfig,
+    ) -> Result<(Self, Vec<Option<usize>>), String> {
+        let batch_size = config.inner_batch_size as usize;
+        if entries.len() > batch_size {
+            return Err(format!(
+                "{} entries selected, exceeds inner batch size {batch_size}",
+                entries.len()
+            ));
+        }
+        if let Some(idx) = entries.iter().find(|idx| **idx >= self.0.len()) {
+            return Err(format!(
+                "entry {idx} selected, but there are only {} entries",
+                self.0.len()
+            ));
+        }
+        if let Some(idx) = entries.iter().duplicates().next() {
+            return Err(format!("entry {idx} selected more than once"));
+        }
+        let positions = entries
+            .iter()
+            .copied()
+            .map(Some)
+            .pad_using(batch_size, |_| None)
+            .collect_vec();
+        let inputs = positions
+            .iter()
+            .map(|idx| match idx {
+                Some(idx) => self.0[*idx].clone(),
+                None => UniversalBatchVerifierInput::padding(config),
+            })
+            .collect();
+        Ok((Self(inputs), positions))
+    }
+
     /// Returns the max public input length in the elements of `self`. This is
     /// the minimum `max_num_public_inputs` that a configuration compatible with
     /// `self` must have.
@@ -430,8 +849,10 @@ impl UniversalBatchVerifierInputs<Fr> {
         )
     }
 
-    /// Samples [`UniversalBatchVerifierInputs`] compatible with `config`, mixing
-    /// inputs with and without Pedersen commitments.
+    /// Samples [`UniversalBatchVerifierInputs`] compatible with `config`, for
+    /// use as test or load-testing inputs.  Equivalent to
+    /// [`Self::sample_with_commitment_fraction`] with half of the entries
+    /// having a Pedersen commitment.
     pub fn sample_mixed<R>(
         config: &UniversalBatchVerifierConfig,
         rng: &mut R,
@@ -439,17 +860,47 @@ impl UniversalBatchVerifierInputs<Fr> {
     where
         R: RngCore + ?Sized,
     {
-        let has_commitment_vec = (0..config.inner_batch_size)
-            .into_iter()
-            .map(|_| rng.gen())
-            .collect_vec();
+        Self::sample_with_commitment_fraction(config, 0.5, rng)
+    }
+
+    /// Samples [`UniversalBatchVerifierInputs`] compatible with `config`:
+    ///
+    /// - there are `config.inner_batch_size` entries, each a valid Groth16
+    ///   proof for its own randomly generated VK,
+    /// - `commitment_fraction` of the entries (rounded to the nearest
+    ///   integer), at random positions, have a Pedersen commitment,
+    /// - the number of public inputs of each entry is uniform in
+    ///   `1..=config.max_num_public_inputs`, less one for entries with a
+    ///   commitment (leaving room for the commitment hash).
+    ///
+    /// # Panics
+    ///
+    /// If `commitment_fraction` is not in `[0, 1]`.
+    pub fn sample_with_commitment_fraction<R>(
+        config: &UniversalBatchVerifierConfig,
+        commitment_fraction: f64,
+        rng: &mut R,
+    ) -> Self
+    where
+        R: RngCore + ?Sized,
+    {
+        assert!(
+            (0.0..=1.0).contains(&commitment_fraction),
+            "commitment fraction must be in [0, 1]"
+        );
+        let batch_size = config.inner_batch_size as usize;
+        let num_commitments =
+            (commitment_fraction * batch_size as f64).round() as usize;
+        let mut has_commitment_vec =
+            (0..batch_size).map(|i| i < num_commitments).collect_vec();
+        has_commitment_vec.shuffle(rng);
         Self(
-            (0..config.inner_batch_size)
+            has_commitment_vec
                 .into_iter()
-                .map(|i| {
+                .map(|has_commitment| {
                     UniversalBatchVerifierInput::sample(
                         config,
-                        has_commitment_vec[i as usize],
+                        has_commitment,
                         rng,
                     )
                 })
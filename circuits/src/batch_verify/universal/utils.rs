@@ -3,6 +3,7 @@ use crate::{
         types::{UniversalBatchVerifierConfig, UniversalBatchVerifierInputs},
         UniversalBatchVerifyCircuit,
     },
+    utils::hashing::SECURE_MDS,
     EccPrimeField, SafeCircuit,
 };
 use halo2_base::halo2_proofs::{
@@ -18,7 +19,6 @@ use halo2_base::halo2_proofs::{
     },
 };
 use itertools::Itertools;
-use log::info;
 use rand::rngs::StdRng;
 use rand_core::SeedableRng;
 use snark_verifier::{
@@ -27,10 +27,11 @@ use snark_verifier::{
 };
 use snark_verifier_sdk::{
     gen_pk,
-    halo2::{gen_snark, PoseidonTranscript, POSEIDON_SPEC},
+    halo2::{gen_snark, PoseidonTranscript},
     CircuitExt, Snark,
 };
 use std::time::Instant;
+use tracing::info;
 
 /// Returns a "dummy" snark for a `UniversalBatchVerify` circuit formed
 /// from default data of the right type. The proof it contains
@@ -76,11 +77,9 @@ where
         let now = Instant::now();
         info!("Compute UBV dummy proof");
         let instances = instances.iter().map(Vec::as_slice).collect_vec();
-        let mut transcript =
-            PoseidonTranscript::<NativeLoader, Vec<u8>>::from_spec(
-                vec![],
-                POSEIDON_SPEC.clone(),
-            );
+        let mut transcript = PoseidonTranscript::<NativeLoader, Vec<u8>>::new::<
+            SECURE_MDS,
+        >(vec![]);
         let rng = StdRng::from_seed(Default::default());
         create_proof::<_, P, _, _, _, _>(
             params,
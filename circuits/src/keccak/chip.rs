@@ -7,10 +7,16 @@ extern crate alloc;
 
 use crate::{
     keccak::{
-        multivar::KeccakMultiVarHasher, utils::bytes_to_keccak_padded_words,
+        multivar::KeccakMultiVarHasher,
+        utils::{
+            bytes_to_keccak_padded_words, encode_digest_as_field_elements,
+        },
         variable, KECCAK_OUTPUT_BYTES,
     },
-    utils::bitmask::ith_bit_bitmask,
+    utils::{
+        bitmask::ith_bit_bitmask,
+        cancellation::{CancellationToken, Cancelled},
+    },
     EccPrimeField,
 };
 use alloc::collections::BTreeMap;
@@ -91,14 +97,22 @@ pub struct KeccakFixedLenQuery<F: Field> {
     /// Input words
     input_words_assigned: Vec<AssignedValue<F>>,
     /// Output bytes
-    #[allow(dead_code)]
-    // This output byte representation can be handy for testing and debugging.
     output_bytes: [u8; NUM_BYTES_TO_SQUEEZE],
     /// Assigned output bytes
     output_bytes_assigned: Vec<AssignedValue<F>>,
 }
 
 impl<F: Field> KeccakFixedLenQuery<F> {
+    /// Returns the input bytes.
+    pub fn input_bytes(&self) -> &[u8] {
+        &self.input_bytes
+    }
+
+    /// Returns the output bytes, as computed natively.
+    pub fn output_bytes(&self) -> &[u8] {
+        &self.output_bytes
+    }
+
     /// Returns the output bytes.
     pub fn output_bytes_assigned(&self) -> &[AssignedValue<F>] {
         &self.output_bytes_assigned
@@ -117,8 +131,6 @@ pub struct KeccakVarLenQuery<F: Field> {
     /// Input words
     input_words_assigned: Vec<AssignedValue<F>>,
     /// Output bytes
-    #[allow(dead_code)]
-    // This output byte representation can be handy for testing and debugging.
     output_bytes: [u8; NUM_BYTES_TO_SQUEEZE],
     /// Assigned output bytes.
     ///
@@ -135,6 +147,17 @@ pub struct KeccakVarLenQuery<F: Field> {
 }
 
 impl<F: Field> KeccakVarLenQuery<F> {
+    /// Returns the input bytes, of which the first [`Self::num_bytes`] are
+    /// hashed.
+    pub fn input_bytes(&self) -> &[u8] {
+        &self.input_bytes
+    }
+
+    /// Returns the 32 output bytes of the query, as computed natively.
+    pub fn output_bytes(&self) -> &[u8] {
+        &self.output_bytes
+    }
+
     /// Returns the 32 output bytes of the query.
     pub fn output_bytes_assigned(&self) -> &[AssignedValue<F>] {
         &self.output_bytes_assigned
@@ -151,6 +174,40 @@ impl<F: Field> KeccakVarLenQuery<F> {
     }
 }
 
+/// Reference to a query of a [`KeccakChip`] (see
+/// [`KeccakChip::iter_queries`]).
+#[derive(Clone, Copy, Debug)]
+pub enum QueryRef<'a, F: Field> {
+    Fixed(&'a KeccakFixedLenQuery<F>),
+    Var(&'a KeccakVarLenQuery<F>),
+}
+
+impl<'a, F: Field> QueryRef<'a, F> {
+    /// Returns the bytes hashed by the query.
+    pub fn input_bytes(&self) -> &'a [u8] {
+        match self {
+            QueryRef::Fixed(query) => query.input_bytes(),
+            QueryRef::Var(query) => &query.input_bytes()[..query.num_bytes()],
+        }
+    }
+
+    /// Returns the output bytes of the query, as computed natively.
+    pub fn output_bytes(&self) -> &'a [u8] {
+        match self {
+            QueryRef::Fixed(query) => query.output_bytes(),
+            QueryRef::Var(query) => query.output_bytes(),
+        }
+    }
+
+    /// Returns the assigned output bytes of the query.
+    pub fn output_bytes_assigned(&self) -> &'a [AssignedValue<F>] {
+        match self {
+            QueryRef::Fixed(query) => query.output_bytes_assigned(),
+            QueryRef::Var(query) => query.output_bytes_assigned(),
+        }
+    }
+}
+
 /// Keccak Chip.
 ///
 /// # Note
@@ -192,9 +249,21 @@ impl<F: Field> KeccakChip<F> {
         &self.var_len_queries
     }
 
+    /// Returns an iterator over all queries: the variable length queries
+    /// followed by the fixed length queries, each in the order they were
+    /// made.  This is the order of [`KeccakCircuit::keccak_output_bytes`].
+    ///
+    /// [`KeccakCircuit::keccak_output_bytes`]: super::KeccakCircuit::keccak_output_bytes
+    pub fn iter_queries(&self) -> impl Iterator<Item = QueryRef<'_, F>> {
+        self.var_len_queries
+            .iter()
+            .map(QueryRef::Var)
+            .chain(self.fixed_len_queries.iter().map(QueryRef::Fixed))
+    }
+
     /// Returns the total number of Keccak-f permutations needed to
     /// compute all this chip's queries.
-    pub(super) fn total_keccak_perms(&self) -> usize {
+    pub fn total_keccak_perms(&self) -> usize {
         self.fixed_len_queries
             .iter()
             .map(|q| q.input_bytes_assigned.len())
@@ -249,6 +318,40 @@ impl<F: Field> KeccakChip<F> {
         });
     }
 
+    /// Same as [`Self::keccak_fixed_len`], returning the assigned output
+    /// bytes of the query.
+    pub fn keccak_fixed_len_output_bytes(
+        &mut self,
+        ctx: &mut Context<F>,
+        range: &RangeChip<F>,
+        input_bytes_assigned: Vec<AssignedValue<F>>,
+    ) -> [AssignedValue<F>; KECCAK_OUTPUT_BYTES] {
+        self.keccak_fixed_len(ctx, range, input_bytes_assigned);
+        self.fixed_len_queries
+            .last()
+            .expect("Retrieving the last keccak query is not allowed to fail")
+            .output_bytes_assigned
+            .clone()
+            .try_into()
+            .expect("Conversion from vector to array is not allowed to fail")
+    }
+
+    /// Same as [`Self::keccak_fixed_len`], returning the digest encoded as
+    /// two field elements (see [`encode_digest_as_field_elements`]).
+    pub fn keccak_fixed_len_as_field_elements(
+        &mut self,
+        ctx: &mut Context<F>,
+        range: &RangeChip<F>,
+        input_bytes_assigned: Vec<AssignedValue<F>>,
+    ) -> [AssignedValue<F>; 2] {
+        let output_bytes = self.keccak_fixed_len_output_bytes(
+            ctx,
+            range,
+            input_bytes_assigned,
+        );
+        encode_digest_as_field_elements(ctx, range, &output_bytes)
+    }
+
     /// Computes the keccak digest of `input_bytes_assigned[..len]`.
     /// - Updates `self` with `(output_assigned, output_bytes)`, where `output_bytes` is provided just for convenience.
     /// - This function only computes witnesses for output bytes.
@@ -339,7 +442,7 @@ impl<F: Field> KeccakChip<F> {
     /// Compute digest of a fixed length input and multiple variable length inputs.
     /// Fixed inputs must already be constrained to byte values. Variable length
     /// inputs must be flattened limb decompositions of vectors of Fq elements,
-    /// each encoded as `NUM_LIMBS` F elements.
+    /// each encoded as `num_limbs` F elements of `limb_bits` bits.
     ///
     /// # Note:
     ///
@@ -357,13 +460,15 @@ impl<F: Field> KeccakChip<F> {
         var_inputs: Vec<Vec<AssignedValue<F>>>,
         // The number of non-native limbs to absorb from each var length input
         var_input_lengths: Vec<AssignedValue<F>>,
+        limb_bits: usize,
+        num_limbs: usize,
     ) -> Vec<AssignedValue<F>> {
         assert_eq!(
             fixed_input.len() % 8,
             0,
             "Fixed input length must be a multiple of 8 bytes"
         );
-        let mut hasher = KeccakMultiVarHasher::new();
+        let mut hasher = KeccakMultiVarHasher::new(limb_bits, num_limbs);
         hasher.absorb_fixed(&fixed_input);
         for (input, len) in var_inputs.iter().zip_eq(&var_input_lengths) {
             hasher.absorb_var(input, *len);
@@ -529,6 +634,23 @@ impl<F: Field> KeccakChip<F> {
         region: &mut Region<F>,
         zkevm_keccak: &KeccakConfig<F>,
     ) -> (FixedLenCells, VarLenCells) {
+        self.assign_keccak_cells_cancellable(
+            region,
+            zkevm_keccak,
+            &CancellationToken::default(),
+        )
+        .expect("uncancellable token was cancelled")
+    }
+
+    /// Same as [`Self::assign_keccak_cells`], but checks `cancel` before
+    /// assigning the rows of each query, returning `Err(Cancelled)` if it
+    /// is cancelled.
+    pub fn assign_keccak_cells_cancellable(
+        &self,
+        region: &mut Region<F>,
+        zkevm_keccak: &KeccakConfig<F>,
+        cancel: &CancellationToken,
+    ) -> Result<(FixedLenCells, VarLenCells), Cancelled> {
         let mut num_rows_used = 0;
         // Dummy first rows so that the initial data is absorbed
         // The initial data doesn't really matter, `is_final` just needs to be disabled.
@@ -555,6 +677,7 @@ impl<F: Field> KeccakChip<F> {
             .chain(keccak_row_data_var.into_iter())
             .enumerate()
         {
+            cancel.check()?;
             let number_of_rows = rows.len();
             assert_eq!(
                 number_of_rows % ((NUM_ROUNDS + 1) * self.num_rows_per_round),
@@ -638,7 +761,7 @@ impl<F: Field> KeccakChip<F> {
             }
         }
         // Deref the flagged cells and return the results
-        (
+        Ok((
             (
                 fixed_output_bytes_cells
                     .into_iter()
@@ -659,7 +782,7 @@ impl<F: Field> KeccakChip<F> {
                     .map(|acell| *acell.cell())
                     .collect(),
             ),
-        )
+        ))
     }
 
     /// Returns the cells in `assignments` which contain the output byte vectors in `self`.
@@ -824,7 +947,7 @@ pub fn assign_prover<F: EccPrimeField>(
         region,
         break_points_gate,
     );
-    log::info!("End of FirstPhase");
+    tracing::info!("End of FirstPhase");
 }
 
 /// Converts field values to bytes. Each field element in `bytes_assigned` must have at most 8 non-zero bits,
@@ -846,13 +969,30 @@ pub(crate) fn get_assigned_bytes_values<F: EccPrimeField>(
 
 /// Computes the number of rows per round
 pub(crate) fn rows_per_round(max_rows: usize, num_keccak_f: usize) -> u32 {
-    log::info!("Number of keccak_f permutations: {num_keccak_f}");
-    let rows_per_round =
-        max_rows / (num_keccak_f * (NUM_ROUNDS + 1) + 1 + NUM_WORDS_TO_ABSORB);
-    log::info!("Optimal keccak rows per round: {rows_per_round}");
+    let rows_per_round = max_rows / keccak_num_rounds(num_keccak_f);
+    tracing::info!(
+        num_keccak_f,
+        rows_per_round,
+        "Optimal keccak rows per round"
+    );
     rows_per_round as u32
 }
 
+/// Returns the number of rows used by `num_keccak_f` permutations with
+/// `rows_per_round` rows per round.
+pub(crate) fn keccak_num_rows(
+    rows_per_round: usize,
+    num_keccak_f: usize,
+) -> usize {
+    rows_per_round * keccak_num_rounds(num_keccak_f)
+}
+
+/// Returns the number of rounds (including the dummy initial rounds) of
+/// `num_keccak_f` permutations.
+fn keccak_num_rounds(num_keccak_f: usize) -> usize {
+    num_keccak_f * (NUM_ROUNDS + 1) + 1 + NUM_WORDS_TO_ABSORB
+}
+
 /// Computes the keccak hash of `bytes`, skipping the padding step.
 fn keccak256_no_padding(bytes: &[u8]) -> [u8; KECCAK_OUTPUT_BYTES] {
     let mut output = [0u8; KECCAK_OUTPUT_BYTES];
@@ -93,7 +93,8 @@ where
         let total_batch_size =
             config.inner_batch_size * config.outer_batch_size;
         let num_proof_ids = config
-            .output_submission_id
+            .output_mode
+            .outputs_submission_id()
             .then_some(rng.gen_range(1..=total_batch_size) as u64);
         Self {
             inputs: (0..config.inner_batch_size * config.outer_batch_size)
@@ -104,7 +105,7 @@ where
     }
 
     /// Constructs a default [`KeccakCircuitInputs`] from `inputs`
-    /// compatible with `config.output_submission_id`.
+    /// compatible with `config.output_mode`.
     pub fn from_inputs_and_config(
         inputs: Vec<KeccakVarLenInput<F>>,
         config: &KeccakConfig,
@@ -112,7 +113,8 @@ where
         let total_batch_size =
             config.inner_batch_size * config.outer_batch_size;
         let num_proof_ids = config
-            .output_submission_id
+            .output_mode
+            .outputs_submission_id()
             .then_some(total_batch_size as u64);
         Self {
             inputs,
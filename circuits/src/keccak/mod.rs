@@ -10,38 +10,43 @@ use self::{
     utils::{
         byte_decomposition, byte_decomposition_list,
         compose_into_field_element, compute_final_digest, compute_proof_id,
-        compute_submission_id, digest_as_field_elements,
-        encode_digest_as_field_elements, g1_point_limbs_to_bytes,
-        g2_point_limbs_to_bytes,
+        compute_submission_id_with_domain_separation, compute_vk_digest,
+        digest_as_field_elements, encode_digest_as_field_elements,
+        g1_point_limbs_to_bytes, g2_point_limbs_to_bytes,
+        inputs_per_application_proof_with_commitments, padded_vk_limbs_len,
+        MERKLE_LEAF_DOMAIN_TAG, MERKLE_NODE_DOMAIN_TAG,
     },
 };
+pub use crate::utils::InsufficientRows;
 use crate::{
     batch_verify::{
         common::types::VerificationKey,
-        universal::{
-            self,
-            types::{
-                UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING,
-                UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING_WITH_COMMITMENT,
-            },
-        },
+        universal::{self, types::DomainTagVersion},
     },
+    push_instance_segment,
     utils::{
         bitmask::first_i_bits_bitmask,
+        cancellation::{CancellationToken, Cancelled},
         commitment_point::{
+            be_bytes_to_field_element,
             commitment_hash_from_commitment_point_limbs, g1affine_into_limbs,
             g2affine_into_limbs, limbs_into_g1affine, limbs_into_g2affine,
+            try_point_from_coords, CommitmentPointError,
         },
         hashing::compute_domain_tag,
         upa_config::UpaConfig,
+        CellReport,
     },
     EccPrimeField, SafeCircuit,
 };
 use chip::{
-    assign_prover, assigned_cell_from_assigned_value, rows_per_round,
-    KeccakChip,
+    assign_prover, assigned_cell_from_assigned_value,
+    get_assigned_bytes_values, keccak_num_rows, rows_per_round, KeccakChip,
 };
-use core::{cell::RefCell, fmt, marker::PhantomData, slice::Iter};
+use core::{
+    cell::RefCell, fmt, iter, marker::PhantomData, ops::Range, slice::Iter,
+};
+use ethers_core::utils::keccak256;
 use halo2_base::{
     gates::{
         builder::{
@@ -71,7 +76,10 @@ use serde::{Deserialize, Serialize};
 use snark_verifier_sdk::CircuitExt;
 use std::env::{set_var, var};
 use utils::compute_next_power_of_two_bit_decomposition;
-use zkevm_keccak::{util::eth_types::Field, KeccakConfig as KeccakBaseConfig};
+use zkevm_keccak::{
+    util::{eth_types::Field, NUM_BYTES_TO_SQUEEZE},
+    KeccakConfig as KeccakBaseConfig,
+};
 
 pub mod chip;
 pub mod inputs;
@@ -85,16 +93,24 @@ pub const KECCAK_LOOKUP_BITS: usize = 8;
 /// Keccak output bytes
 ///
 /// This is the number of bytes output by a single keccak
-/// query.
+/// query, and hence the length of proof ids, circuit ids, Merkle leaves and
+/// nodes, and of the submission id.  Code handling these digests should use
+/// this constant rather than a literal `32`.
 pub const KECCAK_OUTPUT_BYTES: usize = 32;
 
-/// Default unusable rows
+// The keccak chip squeezes exactly one digest per query.
+const _: () = assert!(KECCAK_OUTPUT_BYTES == NUM_BYTES_TO_SQUEEZE);
+
+/// Default unusable rows. This can be overridden by
+/// [`KeccakConfig::unusable_rows`].
 pub const DEFAULT_UNUSABLE_ROWS: usize = 109;
 
-/// Default number of limbs
+/// Default number of limbs.  This can be overridden by
+/// [`KeccakConfig::num_limbs`].
 pub const NUM_LIMBS: usize = 3;
 
-/// Default limb size in bits
+/// Default limb size in bits.  This can be overridden by
+/// [`KeccakConfig::limb_bits`].
 pub const LIMB_BITS: usize = 88;
 
 /// Number of bytes to represent an Fq element
@@ -105,17 +121,102 @@ pub const NUM_BYTES_FQ: usize = 32;
 /// # Note
 ///
 /// Empirically more than 50 rows per round makes the rotation offsets too large.
+/// This default can be overridden by [`KeccakConfig::max_rows_per_round`].
 pub const MAX_KECCAK_ROWS_PER_ROUND: u32 = 50;
 
+/// Default maximum number of Pedersen commitments per application proof
+pub const DEFAULT_MAX_COMMITMENTS: u32 = 1;
+
 /// Exposed instances type
 type ExposedInstances = (
     // Public inputs
     Vec<circuit::Cell>,
-    // Public output. This option should be `Some` in keygen mode and
-    // `None` in prover mode.
-    Option<[circuit::Cell; 2]>,
+    // Public output (final digest, followed by the vk digest if any). This
+    // option should be `Some` in keygen mode and `None` in prover mode.
+    Option<Vec<circuit::Cell>>,
 );
 
+/// Digest(s) of the proofIds output by the `KeccakCircuit`.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize,
+)]
+pub enum OutputMode {
+    /// Output the keccak hash of all proofIds.
+    #[default]
+    Linear,
+    /// Output the submissionId of the proofIds.
+    SubmissionId,
+    /// Output the keccak hash of all proofIds, followed by their
+    /// submissionId.  Intended for migrating between contracts expecting
+    /// either digest.
+    ///
+    /// Only supported by the standalone `KeccakCircuit`: the outer circuits
+    /// forward a single final digest, and
+    /// [`UpaConfig::check_keccak_config`](crate::utils::upa_config::UpaConfig::check_keccak_config)
+    /// rejects this mode.
+    Both,
+}
+
+impl OutputMode {
+    /// Returns `true` if the keccak hash of all proofIds is output.
+    pub fn outputs_linear_digest(self) -> bool {
+        matches!(self, OutputMode::Linear | OutputMode::Both)
+    }
+
+    /// Returns `true` if the submissionId is output.
+    pub fn outputs_submission_id(self) -> bool {
+        matches!(self, OutputMode::SubmissionId | OutputMode::Both)
+    }
+
+    /// Number of field elements in the public output (2 per digest).
+    pub fn num_output_field_elements(self) -> usize {
+        2 * (self.outputs_linear_digest() as usize
+            + self.outputs_submission_id() as usize)
+    }
+}
+
+/// Maps the legacy `output_submission_id` flag to an [`OutputMode`].
+impl From<bool> for OutputMode {
+    fn from(output_submission_id: bool) -> Self {
+        match output_submission_id {
+            true => OutputMode::SubmissionId,
+            false => OutputMode::Linear,
+        }
+    }
+}
+
+impl fmt::Display for OutputMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputMode::Linear => write!(f, "linear"),
+            OutputMode::SubmissionId => write!(f, "submission id"),
+            OutputMode::Both => write!(f, "linear and submission id"),
+        }
+    }
+}
+
+/// Deserializes an [`OutputMode`], also accepting the boolean used by the
+/// legacy `output_submission_id` field.
+fn deserialize_output_mode<'de, D>(
+    deserializer: D,
+) -> Result<OutputMode, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OutputModeOrBool {
+        Mode(OutputMode),
+        Bool(bool),
+    }
+    Ok(match OutputModeOrBool::deserialize(deserializer)? {
+        OutputModeOrBool::Mode(output_mode) => output_mode,
+        OutputModeOrBool::Bool(output_submission_id) => {
+            output_submission_id.into()
+        }
+    })
+}
+
 /// The circuit configuration. This should be sufficient to fully define the
 /// circuit structure and generate keys.
 #[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -132,10 +233,90 @@ pub struct KeccakConfig {
     /// Log-2 of the number of rows allocated for lookup tables. Usually
     /// `degree_bits - 1`.
     pub lookup_bits: usize,
-    /// Output submission Id. If `true`, the circuit outputs the submissionId of
-    /// the proofIds it computes as the final digest. If `false`, it outputs the
-    /// keccak hash of all proofIds.
-    pub output_submission_id: bool,
+    /// Digest(s) of the proofIds output as the final digest: their keccak
+    /// hash, their submissionId, or both (in that order).  Configs using the
+    /// legacy boolean `output_submission_id` field are still accepted.
+    #[serde(
+        alias = "output_submission_id",
+        deserialize_with = "deserialize_output_mode"
+    )]
+    pub output_mode: OutputMode,
+    /// Maximum number of Pedersen commitment points per application proof.
+    /// Must be at least 1. For `max_commitments = 1`, the instance layout
+    /// matches that of the `UniversalBatchVerifyCircuit`.  Crate-private
+    /// (and not read from config files) until the batch verifier and outer
+    /// circuits support more than one commitment: configs from outside the
+    /// crate always use [`DEFAULT_MAX_COMMITMENTS`].
+    #[serde(skip, default = "default_max_commitments")]
+    pub(crate) max_commitments: u32,
+    /// Expose `num_proof_ids` as a public input. Only valid when
+    /// `output_mode` outputs the submissionId. If set, `num_proof_ids` appears in
+    /// the instance immediately after the application inputs and before the
+    /// final digest, so that the size of the submission is bound by the
+    /// proof.
+    #[serde(default)]
+    pub public_num_proof_ids: bool,
+    /// Version of the domain tags used in the circuit id computation.
+    #[serde(default)]
+    pub domain_tag_version: DomainTagVersion,
+    /// Cap on the number of Keccak rows per round.  If `None`,
+    /// [`MAX_KECCAK_ROWS_PER_ROUND`] is used.
+    #[serde(default)]
+    pub max_rows_per_round: Option<u32>,
+    /// Include the vk digest. Only valid when `output_mode` is
+    /// [`OutputMode::Linear`]. If set, the keccak hash of the circuit ids of all entries
+    /// (in order) is exposed as a second pair of field elements, immediately
+    /// after the final digest.
+    #[serde(default)]
+    pub include_vk_digest: bool,
+    /// Number of rows at the end of the circuit reserved for blinding
+    /// factors, and hence unusable.  If `None`, [`DEFAULT_UNUSABLE_ROWS`] is
+    /// used.  Must be at least the number of blinding rows required by the
+    /// constraint system.
+    #[serde(default)]
+    pub unusable_rows: Option<usize>,
+    /// Size in bits of the limbs representing the `Fq` coordinates of the
+    /// verification keys and commitment points in the instance.  Must be a
+    /// multiple of 8.
+    #[serde(default = "default_limb_bits")]
+    pub limb_bits: usize,
+    /// Number of limbs representing each `Fq` coordinate in the instance.
+    #[serde(default = "default_num_limbs")]
+    pub num_limbs: usize,
+    /// Hash the leaves and internal nodes of the submission id Merkle tree
+    /// with distinct one-byte prefixes (`0x00` and `0x01` respectively), so
+    /// that an internal node cannot be presented as a leaf.  Only relevant
+    /// when `output_mode` outputs the submissionId.  Off by default, since
+    /// it changes the submission id.
+    #[serde(default)]
+    pub domain_separated_tree: bool,
+    /// Support application proofs with Pedersen commitments.  If unset,
+    /// `num_commitments` must be zero for every input, and the commitment
+    /// hash queries and the commitment keys in the circuit id are omitted.
+    /// The commitment hashes in the instance are then unconstrained by this
+    /// circuit: they must be bound by the batch verifier circuit (see
+    /// [`UniversalBatchVerifierConfig::allow_commitments`]).  The instance
+    /// layout is unchanged.  Set by default.
+    ///
+    /// [`UniversalBatchVerifierConfig::allow_commitments`]: crate::batch_verify::universal::types::UniversalBatchVerifierConfig::allow_commitments
+    #[serde(default = "default_allow_commitments")]
+    pub allow_commitments: bool,
+}
+
+fn default_max_commitments() -> u32 {
+    DEFAULT_MAX_COMMITMENTS
+}
+
+fn default_allow_commitments() -> bool {
+    true
+}
+
+fn default_limb_bits() -> usize {
+    LIMB_BITS
+}
+
+fn default_num_limbs() -> usize {
+    NUM_LIMBS
 }
 
 impl KeccakConfig {
@@ -152,7 +333,20 @@ impl From<&UpaConfig> for KeccakConfig {
             inner_batch_size: config.inner_batch_size,
             outer_batch_size: config.outer_batch_size,
             lookup_bits: config.keccak_config.lookup_bits,
-            output_submission_id: config.output_submission_id,
+            output_mode: config.output_submission_id.into(),
+            // The UBV circuit supports at most one commitment.
+            max_commitments: DEFAULT_MAX_COMMITMENTS,
+            public_num_proof_ids: config.public_num_proof_ids,
+            domain_tag_version: DomainTagVersion::default(),
+            max_rows_per_round: None,
+            include_vk_digest: config.include_vk_digest,
+            unusable_rows: None,
+            // The keccak circuit hashes the limbs of the UBV instances.
+            limb_bits: config.bv_config.limb_bits,
+            num_limbs: config.bv_config.num_limbs,
+            // The submission id must match the one computed on-chain.
+            domain_separated_tree: false,
+            allow_commitments: config.allow_commitments,
         }
     }
 }
@@ -162,14 +356,87 @@ impl fmt::Display for KeccakConfig {
         writeln!(f, "Keccak degree: {}", self.degree_bits)?;
         writeln!(f, "Num app public inputs: {}", self.num_app_public_inputs)?;
         writeln!(f, "Inner batch size: {}", self.inner_batch_size)?;
-        write!(f, "Outer batch size: {}", self.outer_batch_size)
+        writeln!(f, "Outer batch size: {}", self.outer_batch_size)?;
+        writeln!(f, "Output mode: {}", self.output_mode)?;
+        writeln!(f, "Max commitments: {}", self.max_commitments)?;
+        writeln!(f, "Public num proof ids: {}", self.public_num_proof_ids)?;
+        writeln!(f, "Include vk digest: {}", self.include_vk_digest)?;
+        writeln!(f, "Limb bits: {}", self.limb_bits)?;
+        writeln!(f, "Num limbs: {}", self.num_limbs)?;
+        writeln!(f, "Domain separated tree: {}", self.domain_separated_tree)?;
+        writeln!(f, "Allow commitments: {}", self.allow_commitments)?;
+        write!(f, "Domain tag version: {}", self.domain_tag_version)
+    }
+}
+
+/// Returns the number of public inputs of the `KeccakCircuit` for `config`,
+/// without constructing the circuit.  The instance consists of the inputs for
+/// each of the `inner_batch_size * outer_batch_size` application proofs,
+/// followed by `num_proof_ids` (if `public_num_proof_ids` is set), the
+/// final digest (as 2 field elements, or 4 in [`OutputMode::Both`]) and the
+/// vk digest (as 2 field elements, if `include_vk_digest` is set).
+pub fn keccak_instance_len(config: &KeccakConfig) -> usize {
+    let num_proofs = config.inner_batch_size * config.outer_batch_size;
+    num_proofs as usize
+        * inputs_per_application_proof_with_commitments(
+            config.num_app_public_inputs as usize,
+            config.max_commitments as usize,
+            config.num_limbs,
+        )
+        + config.public_num_proof_ids as usize
+        + config.output_mode.num_output_field_elements()
+        + 2 * config.include_vk_digest as usize
+}
+
+/// Order of the [`VerificationKey`] elements in the flattened limbs of a
+/// [`PaddedVerifyingKeyLimbs`] (see [`PaddedVerifyingKeyLimbs::flatten`]).
+/// G1 points (`alpha` and each `s[i]`) take `2 * num_limbs` field elements
+/// (x, y) and G2 points (`beta`, `gamma`, `delta` and each `h1[i]`, `h2[i]`)
+/// take `4 * num_limbs` (x.c0, x.c1, y.c0, y.c1).  `s` contains
+/// `max_num_public_inputs + 1` points, `h1` and `h2` contain
+/// `max_commitments` points each.
+pub const VK_LIMBS_ORDER: [&str; 7] =
+    ["alpha", "beta", "gamma", "delta", "s", "h1", "h2"];
+
+/// Reason for which a slice of limbs is not a valid encoding of a
+/// [`PaddedVerifyingKeyLimbs`].  See [`PaddedVerifyingKeyLimbs::try_from_limbs`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LimbError {
+    /// The length of `s` is zero.
+    ZeroLenS,
+    /// The number of limbs is inconsistent with `len_s`.  `expected` is the
+    /// closest valid number of limbs (for at least one commitment key).
+    InvalidLength {
+        len_s: usize,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for LimbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimbError::ZeroLenS => write!(f, "length of s can't be zero"),
+            LimbError::InvalidLength {
+                len_s,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "expected {expected} limbs for len_s = {len_s}, got {actual}"
+            ),
+        }
     }
 }
 
+impl std::error::Error for LimbError {}
+
 /// VerificationKey that has been padded, where all elements are represented as
-/// limbs.
+/// limbs. The Pedersen commitment keys are padded to the maximum number of
+/// commitments, and `h1` (resp. `h2`) holds the concatenated limbs of all
+/// `vk.h1` (resp. `vk.h2`) points.
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub(crate) struct PaddedVerifyingKeyLimbs<F>
+pub struct PaddedVerifyingKeyLimbs<F>
 where
     F: EccPrimeField<Repr = [u8; 32]>,
 {
@@ -182,11 +449,34 @@ where
     pub(crate) h2: Vec<F>,
 }
 
+/// Decodes `(limbs, len_s)`, with the default [`NUM_LIMBS`] limbs per `Fq`
+/// coordinate.  See [`PaddedVerifyingKeyLimbs::try_from_limbs`].
+impl<F> TryFrom<(&[F], usize)> for PaddedVerifyingKeyLimbs<F>
+where
+    F: EccPrimeField<Repr = [u8; 32]>,
+{
+    type Error = LimbError;
+
+    fn try_from((limbs, len_s): (&[F], usize)) -> Result<Self, Self::Error> {
+        Self::try_from_limbs(limbs, len_s, NUM_LIMBS)
+    }
+}
+
 impl<F> PaddedVerifyingKeyLimbs<F>
 where
     F: EccPrimeField<Repr = [u8; 32]>,
 {
-    /// Returns an iterator over the elements of `self`.
+    /// Returns an iterator over the elements of `self`, in the order given
+    /// by [`VK_LIMBS_ORDER`]: the limbs of `alpha`, `beta`, `gamma`,
+    /// `delta`, `s[0]`, ..., `s[len_s - 1]`, then all of `h1` followed by all
+    /// of `h2`, each point in the coordinate order of
+    /// [`g1affine_into_limbs`] and [`g2affine_into_limbs`].
+    ///
+    /// # Note
+    ///
+    /// This order is part of the keccak and universal batch verifier
+    /// instance layouts (and hence of the circuit ids), so it must not
+    /// change.
     pub fn iter(&self) -> impl Iterator<Item = &F> {
         self.alpha
             .iter()
@@ -198,26 +488,65 @@ where
             .chain(self.h2.iter())
     }
 
-    /// Returns a vector with the elements of `self`.
+    /// Returns a vector with the elements of `self`, in the order of
+    /// [`Self::iter`].
     pub fn flatten(&self) -> Vec<F> {
         self.iter().copied().collect()
     }
 
-    /// Builds a new [`PaddedVerifyingKeyLimbs`] from `limbs`
-    /// with `self.s.len() = len_s`.
-    pub fn from_limbs(limbs: &[F], len_s: usize) -> Self {
-        assert!(len_s > 0, "length of s can't be zero");
-        assert_eq!(
-            limbs.len(),
-            NUM_LIMBS * (22 + 2 * len_s),
-            "Inconsistent length"
-        );
+    /// Returns the number of (padded) Pedersen commitment keys in `self`.
+    pub fn num_commitments(&self) -> usize {
+        // Each key in `h1` takes as many limbs as the G2 point `beta`.
+        self.h1.len() / self.beta.len()
+    }
+
+    /// Builds a new [`PaddedVerifyingKeyLimbs`] from `limbs`, where each `Fq`
+    /// coordinate takes `num_limbs` limbs, with `self.s.len() = len_s`. The
+    /// number of Pedersen commitment keys is determined by the length of
+    /// `limbs`.
+    ///
+    /// # Panics
+    ///
+    /// If `limbs` is not a valid encoding.  See [`Self::try_from_limbs`].
+    pub fn from_limbs(limbs: &[F], len_s: usize, num_limbs: usize) -> Self {
+        Self::try_from_limbs(limbs, len_s, num_limbs)
+            .unwrap_or_else(|e| panic!("Inconsistent length: {e}"))
+    }
+
+    /// Fallible version of [`Self::from_limbs`], for limbs from untrusted
+    /// sources.  Fails if `len_s` is zero, or if the number of limbs is not
+    /// that of a VK with `len_s` points in `s` and at least one commitment
+    /// key.
+    pub fn try_from_limbs(
+        limbs: &[F],
+        len_s: usize,
+        num_limbs: usize,
+    ) -> Result<Self, LimbError> {
+        if len_s == 0 {
+            return Err(LimbError::ZeroLenS);
+        }
+        // alpha, beta, gamma, delta and s, then 8 coordinates per key.
+        let fixed_len = num_limbs * (14 + 2 * len_s);
+        let commitment_key_len = 8 * num_limbs;
+        let commitment_keys_len = limbs.len().saturating_sub(fixed_len);
+        if limbs.len() <= fixed_len
+            || commitment_keys_len % commitment_key_len != 0
+        {
+            let num_commitments =
+                (commitment_keys_len / commitment_key_len).max(1);
+            return Err(LimbError::InvalidLength {
+                len_s,
+                expected: fixed_len + num_commitments * commitment_key_len,
+                actual: limbs.len(),
+            });
+        }
+        let num_commitments = commitment_keys_len / commitment_key_len;
         let mut limbs_iter = limbs.iter();
 
         let take_fq = |limbs_iter: &mut Iter<F>, num_fq: usize| {
             limbs_iter
                 .by_ref()
-                .take(num_fq * NUM_LIMBS)
+                .take(num_fq * num_limbs)
                 .copied()
                 .collect_vec()
         };
@@ -229,9 +558,9 @@ where
         for _ in 0..len_s {
             s.push(take_fq(&mut limbs_iter, 2));
         }
-        let h1 = take_fq(&mut limbs_iter, 4);
-        let h2 = take_fq(&mut limbs_iter, 4);
-        Self {
+        let h1 = take_fq(&mut limbs_iter, 4 * num_commitments);
+        let h2 = take_fq(&mut limbs_iter, 4 * num_commitments);
+        Ok(Self {
             alpha,
             beta,
             gamma,
@@ -239,7 +568,7 @@ where
             s,
             h1,
             h2,
-        }
+        })
     }
 
     /// Returns a dummy [`PaddedVerifyingKeyLimbs`] compatible with `config`,
@@ -247,10 +576,16 @@ where
     pub fn dummy(config: &KeccakConfig) -> Self {
         let g1_generator = G1Affine::generator();
         let g2_generator = G2Affine::generator();
-        let g1_generator_limbs =
-            g1affine_into_limbs(&g1_generator, LIMB_BITS, NUM_LIMBS);
-        let g2_generator_limbs =
-            g2affine_into_limbs(&g2_generator, LIMB_BITS, NUM_LIMBS);
+        let g1_generator_limbs = g1affine_into_limbs(
+            &g1_generator,
+            config.limb_bits,
+            config.num_limbs,
+        );
+        let g2_generator_limbs = g2affine_into_limbs(
+            &g2_generator,
+            config.limb_bits,
+            config.num_limbs,
+        );
         Self {
             alpha: g1_generator_limbs.clone(),
             beta: g2_generator_limbs.clone(),
@@ -260,17 +595,23 @@ where
                 .into_iter()
                 .map(|_| g1_generator_limbs.clone())
                 .collect(),
-            h1: g2_generator_limbs.clone(),
-            h2: g2_generator_limbs,
+            h1: g2_generator_limbs.repeat(config.max_commitments as usize),
+            h2: g2_generator_limbs.repeat(config.max_commitments as usize),
         }
     }
 
-    /// Creates a new [`PaddedVerifyingKeyLimbs`] from `vk`.
+    /// Creates a new [`PaddedVerifyingKeyLimbs`] from `vk`, decomposing
+    /// each `Fq` coordinate into `num_limbs` limbs of `limb_bits` bits.
     ///
     /// # Note
     ///
-    /// The `vk` has to be already paddeed.
-    pub fn from_vk(vk: &VerificationKey) -> Self {
+    /// The `vk` has to be already paddeed, including its Pedersen
+    /// commitment keys.
+    pub fn from_vk(
+        vk: &VerificationKey,
+        limb_bits: usize,
+        num_limbs: usize,
+    ) -> Self {
         let VerificationKey {
             alpha,
             beta,
@@ -281,34 +622,94 @@ where
             h2,
         } = vk;
         assert_eq!(h1.len(), h2.len(), "inconsistent vk");
-        assert_eq!(h1.len(), 1, "vk must be already padded");
+        assert!(!h1.is_empty(), "vk must be already padded");
+        let g2_limbs = |points: &[G2Affine]| -> Vec<F> {
+            points
+                .iter()
+                .flat_map(|h| g2affine_into_limbs(h, limb_bits, num_limbs))
+                .collect()
+        };
         Self {
-            alpha: g1affine_into_limbs(alpha, LIMB_BITS, NUM_LIMBS),
-            beta: g2affine_into_limbs(beta, LIMB_BITS, NUM_LIMBS),
-            gamma: g2affine_into_limbs(gamma, LIMB_BITS, NUM_LIMBS),
-            delta: g2affine_into_limbs(delta, LIMB_BITS, NUM_LIMBS),
+            alpha: g1affine_into_limbs(alpha, limb_bits, num_limbs),
+            beta: g2affine_into_limbs(beta, limb_bits, num_limbs),
+            gamma: g2affine_into_limbs(gamma, limb_bits, num_limbs),
+            delta: g2affine_into_limbs(delta, limb_bits, num_limbs),
             s: s.iter()
-                .map(|s_i| g1affine_into_limbs(s_i, LIMB_BITS, NUM_LIMBS))
+                .map(|s_i| g1affine_into_limbs(s_i, limb_bits, num_limbs))
                 .collect(),
-            h1: g2affine_into_limbs(&h1[0], LIMB_BITS, NUM_LIMBS),
-            h2: g2affine_into_limbs(&h2[0], LIMB_BITS, NUM_LIMBS),
+            h1: g2_limbs(h1),
+            h2: g2_limbs(h2),
         }
     }
 
-    /// Converts `self` back into a [`VerificationKey`].
-    pub fn vk(&self) -> VerificationKey {
+    /// Pads `vk` to `max_num_public_inputs` public inputs and
+    /// `max_commitments` Pedersen commitment keys, and creates a new
+    /// [`PaddedVerifyingKeyLimbs`] from the result (see [`Self::from_vk`]).
+    pub(crate) fn from_vk_with_padding(
+        vk: &VerificationKey,
+        max_num_public_inputs: usize,
+        max_commitments: usize,
+        limb_bits: usize,
+        num_limbs: usize,
+    ) -> Self {
+        assert!(
+            vk.h1.len() <= max_commitments,
+            "Only up to {max_commitments} commitment points allowed"
+        );
+        let mut vk = vk.clone();
+        vk.pad(max_num_public_inputs);
+        vk.h1.resize(max_commitments, G2Affine::generator());
+        vk.h2.resize(max_commitments, G2Affine::generator());
+        Self::from_vk(&vk, limb_bits, num_limbs)
+    }
+
+    /// Returns the limbs of the unpadded `vk`, padded to
+    /// `max_num_public_inputs` public inputs and `max_commitments` Pedersen
+    /// commitment keys, and flattened in the order given by
+    /// [`VK_LIMBS_ORDER`].  This is exactly the `vk` part of the keccak
+    /// circuit instance for an entry of a [`KeccakConfig`] with
+    /// `num_app_public_inputs = max_num_public_inputs` and the same
+    /// `max_commitments`, `limb_bits` and `num_limbs`.
+    pub fn from_vk_unpadded(
+        vk: &VerificationKey,
+        max_num_public_inputs: usize,
+        max_commitments: usize,
+        limb_bits: usize,
+        num_limbs: usize,
+    ) -> Vec<F> {
+        Self::from_vk_with_padding(
+            vk,
+            max_num_public_inputs,
+            max_commitments,
+            limb_bits,
+            num_limbs,
+        )
+        .flatten()
+    }
+
+    /// Converts `self`, whose `Fq` coordinates are decomposed into
+    /// `num_limbs` limbs of `limb_bits` bits, back into a
+    /// [`VerificationKey`]. Note that the result retains all the (padded)
+    /// Pedersen commitment keys.
+    pub fn vk(&self, limb_bits: usize, num_limbs: usize) -> VerificationKey {
+        let g2_points = |limbs: &[F]| -> Vec<G2Affine> {
+            limbs
+                .chunks(4 * num_limbs)
+                .map(|h| limbs_into_g2affine(h, limb_bits, num_limbs))
+                .collect()
+        };
         VerificationKey {
-            alpha: limbs_into_g1affine(&self.alpha, LIMB_BITS, NUM_LIMBS),
-            beta: limbs_into_g2affine(&self.beta, LIMB_BITS, NUM_LIMBS),
-            gamma: limbs_into_g2affine(&self.gamma, LIMB_BITS, NUM_LIMBS),
-            delta: limbs_into_g2affine(&self.delta, LIMB_BITS, NUM_LIMBS),
+            alpha: limbs_into_g1affine(&self.alpha, limb_bits, num_limbs),
+            beta: limbs_into_g2affine(&self.beta, limb_bits, num_limbs),
+            gamma: limbs_into_g2affine(&self.gamma, limb_bits, num_limbs),
+            delta: limbs_into_g2affine(&self.delta, limb_bits, num_limbs),
             s: self
                 .s
                 .iter()
-                .map(|s_i| limbs_into_g1affine(s_i, LIMB_BITS, NUM_LIMBS))
+                .map(|s_i| limbs_into_g1affine(s_i, limb_bits, num_limbs))
                 .collect(),
-            h1: vec![limbs_into_g2affine(&self.h1, LIMB_BITS, NUM_LIMBS)],
-            h2: vec![limbs_into_g2affine(&self.h2, LIMB_BITS, NUM_LIMBS)],
+            h1: g2_points(&self.h1),
+            h2: g2_points(&self.h2),
         }
     }
 }
@@ -327,8 +728,9 @@ where
     /// Verifying key of the application circuit
     pub app_vk: PaddedVerifyingKeyLimbs<F>,
 
-    /// Has commitment flag
-    pub has_commitment: F,
+    /// Number of Pedersen commitments. For `max_commitments = 1` this is
+    /// the "has commitment" flag.
+    pub num_commitments: F,
 
     /// Application public inputs
     ///
@@ -339,10 +741,11 @@ where
     /// circuit satisfiability is independent of the field elements chosen to
     pub app_public_inputs: Vec<F>,
 
-    /// Commitment point hash
-    pub commitment_hash: F,
+    /// Commitment point hashes, padded to `max_commitments`
+    pub commitment_hashes: Vec<F>,
 
-    /// Commitment point limbs
+    /// Concatenated commitment point limbs, padded to `max_commitments`
+    /// points
     pub commitment_point_limbs: Vec<F>,
 }
 
@@ -350,14 +753,13 @@ impl<F> KeccakPaddedCircuitInput<F>
 where
     F: EccPrimeField<Repr = [u8; 32]>,
 {
-    /// Returns the length, application vk limbs, has_commitment
-    /// flag, commitment hash, commitment point limbs, and
-    /// public inputs.
+    /// Returns the length, application vk limbs, number of commitments,
+    /// commitment hashes, commitment point limbs, and public inputs.
     pub fn to_instance_values(&self) -> Vec<F> {
         let mut result = vec![self.len];
         result.extend_from_slice(&self.app_vk.flatten());
-        result.push(self.has_commitment);
-        result.push(self.commitment_hash);
+        result.push(self.num_commitments);
+        result.extend_from_slice(&self.commitment_hashes);
         result.extend_from_slice(&self.commitment_point_limbs);
         result.extend_from_slice(&self.app_public_inputs);
         result
@@ -365,19 +767,21 @@ where
 
     /// Generates a dummy [`KeccakPaddedCircuitInput`] for `config` with a given `input_type`.
     pub fn dummy(config: &KeccakConfig) -> Self {
+        let max_commitments = config.max_commitments as usize;
         let app_vk = PaddedVerifyingKeyLimbs::dummy(config);
-        let has_commitment = F::zero();
+        let num_commitments = F::zero();
         let app_public_inputs = (0..config.num_app_public_inputs)
             .map(|_| Default::default())
             .collect();
-        let commitment_hash = Default::default();
-        let commitment_point_limbs = vec![Default::default(); NUM_LIMBS * 2];
+        let commitment_hashes = vec![Default::default(); max_commitments];
+        let commitment_point_limbs =
+            vec![Default::default(); config.num_limbs * 2 * max_commitments];
         Self {
             len: F::from(config.num_app_public_inputs as u64),
             app_vk,
-            has_commitment,
+            num_commitments,
             app_public_inputs,
-            commitment_hash,
+            commitment_hashes,
             commitment_point_limbs,
         }
     }
@@ -390,40 +794,51 @@ where
 
     /// Checks if `self` is a valid public input for `config`.
     pub fn is_well_constructed(&self, config: &KeccakConfig) -> bool {
+        let max_commitments = config.max_commitments as usize;
         let length_condition =
             self.len.get_lower_32() <= config.num_app_public_inputs;
-        let has_commitment_condition =
-            self.commitment_point_limbs.len() == 2 * NUM_LIMBS;
+        let commitments_condition = self.num_commitments.get_lower_32()
+            <= config.max_commitments
+            && self.commitment_hashes.len() == max_commitments
+            && self.commitment_point_limbs.len()
+                == 2 * config.num_limbs * max_commitments
+            && self.app_vk.num_commitments() == max_commitments;
         (config.num_app_public_inputs == self.app_public_inputs.len() as u32)
             && length_condition
-            && has_commitment_condition
+            && commitments_condition
     }
 
-    /// Pads `var_len_input` with zeros to have length `max_num_public_inputs`.
+    /// Pads `var_len_input` with zeros to have length `max_num_public_inputs`,
+    /// and pads its commitments to `max_commitments`, decomposing all `Fq`
+    /// coordinates into `num_limbs` limbs of `limb_bits` bits.  Fails if any
+    /// commitment point is invalid.
     fn from_var_len_input(
         var_len_input: &KeccakVarLenInput<F>,
         max_num_public_inputs: usize,
-    ) -> Self {
+        max_commitments: usize,
+        limb_bits: usize,
+        num_limbs: usize,
+    ) -> Result<Self, CommitmentPointError> {
         let commitment_point_coordinates =
             &var_len_input.commitment_point_coordinates;
-        let has_commitment = !commitment_point_coordinates.is_empty();
+        let num_commitments = commitment_point_coordinates.len();
         assert!(
-            commitment_point_coordinates.len() < 2,
-            "Only up to one commitment point allowed"
+            num_commitments <= max_commitments,
+            "Only up to {max_commitments} commitment points allowed"
         );
         assert!(
-            var_len_input.app_public_inputs.len() + has_commitment as usize
+            var_len_input.app_public_inputs.len() + num_commitments
                 <= max_num_public_inputs,
             "Too many app inputs for config."
         );
         assert_eq!(
-            var_len_input.app_public_inputs.len() + has_commitment as usize + 1,
+            var_len_input.app_public_inputs.len() + num_commitments + 1,
             var_len_input.app_vk.s.len(),
             "vk incompatible with inputs"
         );
         assert_eq!(
             var_len_input.app_vk.h1.len(),
-            has_commitment as usize,
+            num_commitments,
             "vk incompatible with proof"
         );
         assert_eq!(
@@ -431,53 +846,54 @@ where
             var_len_input.app_vk.h2.len(),
             "inconsistent vk"
         );
-        let commitment_point = commitment_point_coordinates
-            .get(0)
-            .map(|commitment_point_coordinates| {
-                let m = G1Affine {
-                    x: commitment_point_coordinates[0],
-                    y: commitment_point_coordinates[1],
-                };
-                assert!(
-                    bool::from(m.is_on_curve()),
-                    "Coordinates do not represent a curve point"
-                );
-                m
+        let commitment_points = commitment_point_coordinates
+            .iter()
+            .map(|coordinates| {
+                try_point_from_coords(coordinates[0], coordinates[1])
             })
-            .unwrap_or(G1Affine::generator());
-        let commitment_point_limbs =
-            g1affine_into_limbs(&commitment_point, LIMB_BITS, NUM_LIMBS);
-        let commitment_hash = commitment_hash_from_commitment_point_limbs(
-            &commitment_point_limbs,
-            LIMB_BITS,
-            NUM_LIMBS,
-        );
+            .collect::<Result<Vec<_>, _>>()?;
+        let commitment_points_limbs = commitment_points
+            .into_iter()
+            .chain(iter::repeat(G1Affine::generator()))
+            .take(max_commitments)
+            .map(|m| g1affine_into_limbs(&m, limb_bits, num_limbs))
+            .collect_vec();
+        let commitment_hashes = commitment_points_limbs
+            .iter()
+            .map(|limbs| {
+                commitment_hash_from_commitment_point_limbs(
+                    limbs, limb_bits, num_limbs,
+                )
+            })
+            .collect_vec();
+        let commitment_point_limbs = commitment_points_limbs.concat();
 
-        let padding = (var_len_input.app_public_inputs.len()
-            + has_commitment as usize
+        let padding = (var_len_input.app_public_inputs.len() + num_commitments
             ..max_num_public_inputs)
             .into_iter()
             .map(|_| F::zero());
         let mut padded_app_public_inputs =
             var_len_input.app_public_inputs.clone();
-        if has_commitment {
-            padded_app_public_inputs.push(commitment_hash);
-        }
+        padded_app_public_inputs
+            .extend_from_slice(&commitment_hashes[..num_commitments]);
         padded_app_public_inputs.extend(padding);
 
-        let mut vk = var_len_input.app_vk.clone();
-        vk.pad(max_num_public_inputs);
-
-        let app_vk = PaddedVerifyingKeyLimbs::from_vk(&vk);
+        let app_vk = PaddedVerifyingKeyLimbs::from_vk_with_padding(
+            &var_len_input.app_vk,
+            max_num_public_inputs,
+            max_commitments,
+            limb_bits,
+            num_limbs,
+        );
 
-        KeccakPaddedCircuitInput {
+        Ok(KeccakPaddedCircuitInput {
             len: F::from(var_len_input.app_public_inputs.len() as u64),
-            has_commitment: F::from(has_commitment),
+            num_commitments: F::from(num_commitments as u64),
             app_vk,
             app_public_inputs: padded_app_public_inputs,
-            commitment_hash,
+            commitment_hashes,
             commitment_point_limbs,
-        }
+        })
     }
 }
 
@@ -499,32 +915,44 @@ where
     pub(crate) fn from_var_len_inputs(
         value: &[KeccakVarLenInput<F>],
         max_num_public_inputs: usize,
+        max_commitments: usize,
+        limb_bits: usize,
+        num_limbs: usize,
         num_proof_ids: Option<u64>,
-    ) -> Self {
+    ) -> Result<Self, CommitmentPointError> {
         let inputs: Vec<KeccakPaddedCircuitInput<F>> = value
             .iter()
             .map(|var_len_input| {
                 KeccakPaddedCircuitInput::from_var_len_input(
                     var_len_input,
                     max_num_public_inputs,
+                    max_commitments,
+                    limb_bits,
+                    num_limbs,
                 )
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
         let num_proof_ids = num_proof_ids.map(F::from);
 
-        KeccakPaddedCircuitInputs {
+        Ok(KeccakPaddedCircuitInputs {
             inputs,
             num_proof_ids,
-        }
+        })
     }
 
     pub(crate) fn from_keccak_circuit_inputs(
         value: &KeccakCircuitInputs<F>,
         max_num_public_inputs: usize,
-    ) -> Self {
+        max_commitments: usize,
+        limb_bits: usize,
+        num_limbs: usize,
+    ) -> Result<Self, CommitmentPointError> {
         KeccakPaddedCircuitInputs::from_var_len_inputs(
             &value.inputs,
             max_num_public_inputs,
+            max_commitments,
+            limb_bits,
+            num_limbs,
             value.num_proof_ids,
         )
     }
@@ -533,9 +961,12 @@ where
 impl<F: EccPrimeField<Repr = [u8; 32]>> KeccakPaddedCircuitInputs<F> {
     /// Creates some dummy [`KeccakPaddedCircuitInputs`] for `config` with `input_type`.
     pub fn dummy(config: &KeccakConfig) -> Self {
-        let num_proof_ids = config.output_submission_id.then_some(F::from(
-            (config.inner_batch_size * config.outer_batch_size) as u64,
-        ));
+        let num_proof_ids = config
+            .output_mode
+            .outputs_submission_id()
+            .then_some(F::from(
+                (config.inner_batch_size * config.outer_batch_size) as u64,
+            ));
         Self {
             inputs: (0..config.inner_batch_size * config.outer_batch_size)
                 .map(|_| KeccakPaddedCircuitInput::dummy(config))
@@ -553,7 +984,9 @@ impl<F: EccPrimeField<Repr = [u8; 32]>> KeccakPaddedCircuitInputs<F> {
             return false;
         }
 
-        if config.output_submission_id ^ self.num_proof_ids.is_some() {
+        if config.output_mode.outputs_submission_id()
+            ^ self.num_proof_ids.is_some()
+        {
             return false;
         }
 
@@ -608,7 +1041,8 @@ where
         }
     }
 
-    /// Returns an iterator over the elements of `self`.
+    /// Returns an iterator over the elements of `self`, in the same order
+    /// as [`PaddedVerifyingKeyLimbs::iter`].
     pub fn iter(&self) -> impl Iterator<Item = &AssignedValue<F>> {
         self.alpha
             .iter()
@@ -644,15 +1078,16 @@ pub(crate) struct AssignedKeccakInput<F: ScalarField> {
     /// limbs of a fully constrained (in the UBV circuit) Groth16 verification key.
     pub(crate) app_vk: AssignedVerifyingKeyLimbs<F>,
 
-    /// Has commitment flag.
+    /// Number of Pedersen commitments.
     ///
     /// # Note
     ///
-    /// This flag isn't constrained to be boolean in the keccak circuit.
-    /// However, it will be copy-constrained in the outer circuit to another
-    /// value which is known to be boolean (because it is constrained in the
-    /// UBV circuit).
-    pub(crate) has_commitment: AssignedValue<F>,
+    /// When `max_commitments = 1`, this is the "has commitment" flag, and it
+    /// isn't constrained to be boolean in the keccak circuit. However, it will
+    /// be copy-constrained in the outer circuit to another value which is
+    /// known to be boolean (because it is constrained in the UBV circuit).
+    /// When `max_commitments > 1`, it is range checked in the keccak circuit.
+    pub(crate) num_commitments: AssignedValue<F>,
 
     /// Application public inputs
     ///
@@ -663,10 +1098,10 @@ pub(crate) struct AssignedKeccakInput<F: ScalarField> {
     /// circuit satisfiability is independent of the field elements chosen to
     app_public_inputs: Vec<AssignedValue<F>>,
 
-    /// Commitment point hash
-    pub(crate) commitment_hash: AssignedValue<F>,
+    /// Commitment point hashes
+    pub(crate) commitment_hashes: Vec<AssignedValue<F>>,
 
-    /// Commitment point limbs
+    /// Concatenated commitment point limbs
     pub(crate) commitment_point_limbs: Vec<AssignedValue<F>>,
 }
 
@@ -681,12 +1116,17 @@ impl<F: ScalarField> AssignedKeccakInput<F> {
         self.app_public_inputs.clone()
     }
 
+    /// Returns the maximum number of commitments supported by `self`.
+    pub fn max_commitments(&self) -> usize {
+        self.commitment_hashes.len()
+    }
+
     /// Flattens `self`, returning a vector of [`AssignedValue`]s.
     pub fn to_instance_values(&self) -> Vec<AssignedValue<F>> {
         let mut result = vec![self.len];
         result.extend_from_slice(&self.app_vk.flatten());
-        result.push(self.has_commitment);
-        result.push(self.commitment_hash);
+        result.push(self.num_commitments);
+        result.extend_from_slice(&self.commitment_hashes);
         result.extend_from_slice(&self.commitment_point_limbs);
         result.extend_from_slice(&self.app_public_inputs);
         result
@@ -702,20 +1142,30 @@ impl<F: ScalarField> AssignedKeccakInput<F> {
         F: EccPrimeField<Repr = [u8; 32]>,
     {
         let max_len = input.app_public_inputs.len() as u64;
+        let max_commitments = input.commitment_hashes.len() as u64;
 
         let len = ctx.load_witness(input.len);
         let app_public_inputs = ctx.assign_witnesses(input.app_public_inputs);
-        let commitment_hash = ctx.load_witness(input.commitment_hash);
+        let commitment_hashes = ctx.assign_witnesses(input.commitment_hashes);
         let commitment_point_limbs =
             ctx.assign_witnesses(input.commitment_point_limbs);
         let app_vk = AssignedVerifyingKeyLimbs::from_padded_verifying_key(
             ctx,
             input.app_vk,
         );
-        let has_commitment = ctx.load_witness(input.has_commitment);
-        // Constrain `len + has_commitment < MAX_LEN`
+        let num_commitments = ctx.load_witness(input.num_commitments);
+        // Constrain `num_commitments <= max_commitments`. For a single
+        // commitment this is enforced by the outer circuit (see above).
+        if max_commitments > 1 {
+            range.check_less_than_safe(
+                ctx,
+                num_commitments,
+                max_commitments + 1,
+            );
+        }
+        // Constrain `len + num_commitments < MAX_LEN`
         let len_inputs_and_commitment =
-            range.gate.add(ctx, len, has_commitment);
+            range.gate.add(ctx, len, num_commitments);
         range.check_less_than_safe(ctx, len_inputs_and_commitment, max_len + 1);
         // Constrain `len > 0`
         let is_len_zero = range.gate.is_zero(ctx, len);
@@ -723,9 +1173,9 @@ impl<F: ScalarField> AssignedKeccakInput<F> {
         Self {
             len,
             app_vk,
-            has_commitment,
+            num_commitments,
             app_public_inputs,
-            commitment_hash,
+            commitment_hashes,
             commitment_point_limbs,
         }
     }
@@ -736,23 +1186,26 @@ impl<F: ScalarField> AssignedKeccakInput<F> {
 pub(crate) struct AssignedKeccakInputs<F: ScalarField> {
     pub inputs: Vec<AssignedKeccakInput<F>>,
     /// Number of proof ids for the submission id computation.
-    /// Note: this is a witness and not part of the instance
-    #[allow(dead_code)] // Kept for consistency with the other two
-    // keccak input structs. Also it is useful in case we want to make
-    // num_proof_ids a public input (instead of a witness) in the
-    // future
     pub num_proof_ids: Option<AssignedValue<F>>,
+    /// Whether `num_proof_ids` is part of the instance.
+    pub public_num_proof_ids: bool,
 }
 
 impl<F> AssignedKeccakInputs<F>
 where
     F: Field,
 {
-    /// Flattens `self`.
+    /// Flattens `self`. `num_proof_ids` is appended after the inputs
+    /// when `public_num_proof_ids` is set.
     pub fn to_instance_values(&self) -> Vec<AssignedValue<F>> {
+        let num_proof_ids = self.public_num_proof_ids.then(|| {
+            self.num_proof_ids
+                .expect("Public num proof ids requires num proof ids")
+        });
         self.inputs
             .iter()
             .flat_map(AssignedKeccakInput::to_instance_values)
+            .chain(num_proof_ids)
             .collect()
     }
 }
@@ -764,9 +1217,18 @@ pub struct KeccakGateConfig {
     flex_gate_config_params: FlexGateConfigParams,
     rows_per_round: u32,
     lookup_bits: usize,
+    /// Overridden number of unusable rows, if any.  See
+    /// [`KeccakConfig::unusable_rows`].
+    #[serde(default)]
+    unusable_rows: Option<usize>,
 }
 
 impl KeccakGateConfig {
+    /// Returns the number of rows per round of the Keccak permutation.
+    pub fn rows_per_round(&self) -> u32 {
+        self.rows_per_round
+    }
+
     /// Returns the number of flex gate columns in `self`. This number is
     /// necessary to determine which columns in the circuit will contain
     /// the keccak input words and the keccak output bytes, and thus have
@@ -777,6 +1239,239 @@ impl KeccakGateConfig {
             + (self.flex_gate_config_params.num_advice_per_phase[0] > 1)
                 as usize
     }
+
+    /// Computes the [`KeccakGateConfig`] that keygen would produce for
+    /// `config`, by assigning dummy inputs to a keygen builder.  Unlike
+    /// keygen, this neither synthesizes the circuit nor sets the keccak
+    /// environment variables.
+    pub fn estimate(config: &KeccakConfig) -> Self {
+        let (builder, cells) = Self::assign_dummy_cells(config);
+        KeccakCircuit::<Fr, G1Affine>::optimal_gate_config(
+            &builder,
+            &cells.keccak,
+            config.degree_bits,
+            config.unusable_rows,
+            Some(config.lookup_bits),
+            config.max_rows_per_round,
+        )
+    }
+
+    /// Checks, before keygen, that the keccak rows of `config` fit in its
+    /// usable rows with at least one row per round.  The flex gate cells are
+    /// spread over as many columns as needed, so only the keccak rows are
+    /// bounded by the degree.  Otherwise, keygen (and [`Self::estimate`])
+    /// would panic after building the circuit.
+    pub fn check_rows(config: &KeccakConfig) -> Result<(), InsufficientRows> {
+        let (_, cells) = Self::assign_dummy_cells(config);
+        let required_rows =
+            keccak_num_rows(1, cells.keccak.total_keccak_perms());
+        let usable_rows = (1usize << config.degree_bits).saturating_sub(
+            config.unusable_rows.unwrap_or(DEFAULT_UNUSABLE_ROWS),
+        );
+        match required_rows <= usable_rows {
+            true => Ok(()),
+            false => Err(InsufficientRows {
+                required_rows,
+                degree_bits: config.degree_bits,
+                usable_rows,
+            }),
+        }
+    }
+
+    /// Assigns dummy inputs for `config` to a keygen builder.
+    fn assign_dummy_cells(
+        config: &KeccakConfig,
+    ) -> (GateThreadBuilder<Fr>, AssignedKeccakCells<Fr>) {
+        let mut builder = GateThreadBuilder::keygen();
+        let cells = KeccakCircuit::<Fr, G1Affine>::assign_cells(
+            config,
+            &mut builder,
+            KeccakPaddedCircuitInputs::dummy(config),
+            &mut |_| {},
+            &CancellationToken::default(),
+        )
+        .expect("uncancellable token was cancelled");
+        (builder, cells)
+    }
+
+    /// Checks that `self` was generated (at keygen) for `config`.  The
+    /// number of flex gate columns is checked against `FLEX_GATE_NUM_COLS`
+    /// separately, and is required to be set, when building the prover.
+    pub fn assert_compatible(
+        &self,
+        config: &KeccakConfig,
+    ) -> Result<(), KeccakConfigMismatch> {
+        if self.flex_gate_config_params.k != config.degree_bits as usize {
+            return Err(KeccakConfigMismatch::DegreeBits {
+                gate_config: self.flex_gate_config_params.k as u32,
+                config: config.degree_bits,
+            });
+        }
+        if self.lookup_bits != config.lookup_bits {
+            return Err(KeccakConfigMismatch::LookupBits {
+                gate_config: self.lookup_bits,
+                config: config.lookup_bits,
+            });
+        }
+        let max_rows_per_round = config
+            .max_rows_per_round
+            .unwrap_or(MAX_KECCAK_ROWS_PER_ROUND);
+        if self.rows_per_round == 0 || self.rows_per_round > max_rows_per_round
+        {
+            return Err(KeccakConfigMismatch::RowsPerRound {
+                gate_config: self.rows_per_round,
+                max: max_rows_per_round,
+            });
+        }
+        if self.unusable_rows != config.unusable_rows {
+            return Err(KeccakConfigMismatch::UnusableRows {
+                gate_config: self.unusable_rows,
+                config: config.unusable_rows,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that `self` has `num_flex_cols` flex gate columns.
+    pub(crate) fn check_num_flex_cols(
+        &self,
+        num_flex_cols: usize,
+    ) -> Result<(), KeccakConfigMismatch> {
+        match self.num_flex_cols() == num_flex_cols {
+            true => Ok(()),
+            false => Err(KeccakConfigMismatch::FlexCols {
+                gate_config: self.num_flex_cols(),
+                env: num_flex_cols,
+            }),
+        }
+    }
+}
+
+/// Reason for which a [`KeccakGateConfig`] is incompatible with a
+/// [`KeccakConfig`].  See [`KeccakGateConfig::assert_compatible`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeccakConfigMismatch {
+    /// The gate config was generated for a different `degree_bits`.
+    DegreeBits { gate_config: u32, config: u32 },
+    /// The gate config was generated for a different `lookup_bits`.
+    LookupBits { gate_config: usize, config: usize },
+    /// The gate config has a different number of flex gate columns than
+    /// `FLEX_GATE_NUM_COLS`.
+    FlexCols { gate_config: usize, env: usize },
+    /// The rows per round of the gate config are zero or exceed the
+    /// configured maximum.
+    RowsPerRound { gate_config: u32, max: u32 },
+    /// The gate config was generated for a different `unusable_rows`.
+    UnusableRows {
+        gate_config: Option<usize>,
+        config: Option<usize>,
+    },
+}
+
+impl fmt::Display for KeccakConfigMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeccakConfigMismatch::DegreeBits {
+                gate_config,
+                config,
+            } => write!(
+                f,
+                "gate config was generated for degree {gate_config} \
+                 but config says {config}"
+            ),
+            KeccakConfigMismatch::LookupBits {
+                gate_config,
+                config,
+            } => write!(
+                f,
+                "gate config was generated for lookup bits {gate_config} \
+                 but config says {config}"
+            ),
+            KeccakConfigMismatch::FlexCols { gate_config, env } => write!(
+                f,
+                "gate config has {gate_config} flex gate columns but \
+                 FLEX_GATE_NUM_COLS is {env}"
+            ),
+            KeccakConfigMismatch::RowsPerRound { gate_config, max } => write!(
+                f,
+                "gate config has {gate_config} rows per round, expected \
+                 between 1 and {max}"
+            ),
+            KeccakConfigMismatch::UnusableRows {
+                gate_config,
+                config,
+            } => write!(
+                f,
+                "gate config was generated for unusable rows \
+                 {gate_config:?} but config says {config:?}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KeccakConfigMismatch {}
+
+/// A stage of [`KeccakCircuit`] witness generation, reported to the callback
+/// passed to [`KeccakCircuit::prover_with_progress`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeccakProgress {
+    /// Computing the leaves of the submission id Merkle tree.
+    ComputingLeaves,
+    /// Hashing the given level of the submission id Merkle tree (the leaves
+    /// are at level 0).
+    HashingRow(usize),
+    /// `done` of the `total` application inputs have been assigned.
+    AssigningCells { done: usize, total: usize },
+}
+
+/// Keccak Circuit Inconsistency Error.
+///
+/// This error can be returned as the result of [`check_well_constructed`](KeccakCircuit::check_well_constructed).
+#[derive(Clone, Debug)]
+pub enum KeccakCircuitInconsistency<F> {
+    /// Keccak Proof Id Mismatch Error.
+    ///
+    /// The keccak output of a keccak query differs from the
+    /// expected value. Returns the number of the query, the output proofId and the
+    /// expected proofId.
+    KeccakProofId(u32, Vec<u8>, Vec<u8>),
+
+    /// Keccak CircuitId Mismatch Error.
+    ///
+    /// The keccak output of a keccak query differs from the
+    /// expected value. Returns the number of the query, the output circuitId and the
+    /// expected circuitId.
+    KeccakCircuitId(u32, Vec<u8>, Vec<u8>),
+
+    /// Public Input Number Mismatch Error.
+    ///
+    /// The number of public inputs of the circuit doesn't correspond to that of
+    /// the configuration. Returns those two values in order.
+    PublicInputNumber(u32, u32),
+
+    /// Public Output Mismatch Error.
+    ///
+    /// The field elements given as the public output don't decompose into
+    /// the keccak output bytes of the last keccak query. Returns the actual value,
+    /// the value computed from the pair of field elements in the instance
+    /// and the expected value, in order.
+    PublicOutput(Vec<u8>, Vec<u8>, Vec<u8>),
+
+    /// Commitment Query Mismatch Error.
+    ///
+    /// The field elements given as the commitment hash don't coincide with the
+    /// output bytes of the commitment point queries. Returns the number of the
+    /// query, the commitment hash computed from the query output bytes,
+    /// the commitment hash computed from the limbs and the expected commitment hash.
+    CommitmentQuery(u32, F, F, F),
+
+    /// VK Digest Mismatch Error.
+    ///
+    /// The field elements given as the vk digest don't decompose into the
+    /// keccak output bytes of the last keccak query. Returns the actual value,
+    /// the value computed from the pair of field elements in the instance
+    /// and the expected value, in order.
+    VkDigest(Vec<u8>, Vec<u8>, Vec<u8>),
 }
 
 /// The Keccak circuit.  Note that all initalizaters should *ignore* environment variables,
@@ -796,39 +1491,95 @@ where
     keccak: KeccakChip<F>,
     /// Public inputs
     pub(crate) public_inputs: AssignedKeccakInputs<F>,
-    /// Public output
-    pub(crate) public_output: [AssignedValue<F>; 2],
+    /// Public output: the final digest(s) selected by the config's
+    /// [`OutputMode`], 2 field elements each
+    pub(crate) public_output: Vec<AssignedValue<F>>,
+    /// VK digest, present if `include_vk_digest` is set
+    pub(crate) vk_digest: Option<[AssignedValue<F>; 2]>,
     /// Gate config
     config: KeccakGateConfig,
+    /// Checked while assigning the keccak rows during synthesis
+    cancel: CancellationToken,
     _marker: PhantomData<C>,
 }
 
+/// The parts of a [`KeccakCircuit`] assigned before its gate config is
+/// computed.
+struct AssignedKeccakCells<F: Field> {
+    keccak: KeccakChip<F>,
+    public_inputs: AssignedKeccakInputs<F>,
+    public_output: Vec<AssignedValue<F>>,
+    vk_digest: Option<[AssignedValue<F>; 2]>,
+}
+
 impl<F, C> KeccakCircuit<F, C>
 where
     F: Field,
     C: CurveAffine<ScalarExt = F>,
 {
+    /// Returns the advice and lookup cell counts of the populated builder,
+    /// along with the computed gate config.  Must be called before the
+    /// circuit is synthesized.
+    pub fn cell_report(&self) -> CellReport {
+        CellReport::new(
+            &self.builder.borrow(),
+            &self.config.flex_gate_config_params,
+        )
+    }
+
     /// Computes the circuit Id as a [`multi_var_query`](KeccakChip::multi_var_query)
-    /// of the limbs of `assigned_input.app_vk`.
+    /// of the limbs of `assigned_input.app_vk`, where each `Fq` coordinate
+    /// is decomposed into `num_limbs` limbs of `limb_bits` bits.  If not
+    /// `allow_commitments`, `assigned_input` must have no commitments, and
+    /// the commitment keys are omitted from the query.
+    #[allow(clippy::too_many_arguments)]
     fn compute_circuit_id(
         ctx: &mut Context<F>,
         range: &RangeChip<F>,
         keccak: &mut KeccakChip<F>,
         assigned_input: &AssignedKeccakInput<F>,
+        domain_tag_version: DomainTagVersion,
+        limb_bits: usize,
+        num_limbs: usize,
+        allow_commitments: bool,
     ) -> Vec<AssignedValue<F>> {
+        let domain_tag_groth16: Vec<AssignedValue<F>> = compute_domain_tag(
+            domain_tag_version.circuit_id_domain_tag_string(false),
+        )
+        .into_iter()
+        .map(|byte| ctx.load_constant(F::from(byte as u64)))
+        .collect_vec();
+        if !allow_commitments {
+            return Self::compute_circuit_id_without_commitment(
+                ctx,
+                range,
+                keccak,
+                assigned_input,
+                domain_tag_groth16,
+                limb_bits,
+                num_limbs,
+            );
+        }
         // select domain tag
-        let domain_tag_groth16: Vec<AssignedValue<F>> =
-            compute_domain_tag(UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING)
-                .into_iter()
-                .map(|byte| ctx.load_constant(F::from(byte as u64)))
-                .collect_vec();
         let domain_tag_groth16_with_commitment: Vec<AssignedValue<F>> =
             compute_domain_tag(
-                UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING_WITH_COMMITMENT,
+                domain_tag_version.circuit_id_domain_tag_string(true),
             )
             .into_iter()
             .map(|byte| ctx.load_constant(F::from(byte as u64)))
             .collect_vec();
+        // For a single commitment, `num_commitments` is the "has
+        // commitment" flag. Otherwise, compute the flag as
+        // `num_commitments != 0`.
+        let max_commitments = assigned_input.max_commitments();
+        let has_commitment = match max_commitments {
+            1 => assigned_input.num_commitments,
+            _ => {
+                let no_commitment =
+                    range.gate.is_zero(ctx, assigned_input.num_commitments);
+                range.gate.not(ctx, no_commitment)
+            }
+        };
         let mut domain_tag = Vec::with_capacity(KECCAK_OUTPUT_BYTES);
         for (without_commitment, with_commitment) in domain_tag_groth16
             .into_iter()
@@ -838,56 +1589,32 @@ where
                 ctx,
                 with_commitment,
                 without_commitment,
-                assigned_input.has_commitment,
+                has_commitment,
             ));
         }
 
-        // Compute vk_s length as public_inputs.len() + has_commitment + 1
+        // Compute vk_s length as public_inputs.len() + num_commitments + 1
         let len = assigned_input.len();
-        let pi_len_plus_has_commitment =
-            range.gate.add(ctx, *len, assigned_input.has_commitment);
+        let pi_len_plus_num_commitments =
+            range.gate.add(ctx, *len, assigned_input.num_commitments);
         let one = ctx.load_constant(F::one());
-        let vk_s_len = range.gate.add(ctx, pi_len_plus_has_commitment, one);
+        let vk_s_len = range.gate.add(ctx, pi_len_plus_num_commitments, one);
 
-        // fixed input = domain_tag || alpha || beta || gamma || delta || vk_s length || vk_s[0] || vk_s[1]
         let mut fixed_input = domain_tag;
-        fixed_input.append(&mut g1_point_limbs_to_bytes(
-            ctx,
-            range,
-            &assigned_input.app_vk.alpha,
-        ));
-        fixed_input.append(&mut g2_point_limbs_to_bytes(
-            ctx,
-            range,
-            &assigned_input.app_vk.beta,
-        ));
-        fixed_input.append(&mut g2_point_limbs_to_bytes(
-            ctx,
-            range,
-            &assigned_input.app_vk.gamma,
-        ));
-        fixed_input.append(&mut g2_point_limbs_to_bytes(
-            ctx,
-            range,
-            &assigned_input.app_vk.delta,
-        ));
-        fixed_input.append(&mut byte_decomposition(ctx, range, &vk_s_len));
-        fixed_input.append(&mut g1_point_limbs_to_bytes(
-            ctx,
-            range,
-            &assigned_input.app_vk.s[0],
-        ));
-        // We require `len > 0`, so this element always exists
-        fixed_input.append(&mut g1_point_limbs_to_bytes(
+        Self::append_circuit_id_fixed_input(
             ctx,
             range,
-            &assigned_input.app_vk.s[1],
-        ));
+            &mut fixed_input,
+            assigned_input,
+            &vk_s_len,
+            limb_bits,
+            num_limbs,
+        );
 
         // Variable input vk.s[2..]
-        let num_limbs_per_g1 = ctx.load_constant(F::from(2 * NUM_LIMBS as u64));
+        let num_limbs_per_g1 = ctx.load_constant(F::from(2 * num_limbs as u64));
         let vk_remaining_len =
-            range.gate.sub(ctx, pi_len_plus_has_commitment, one);
+            range.gate.sub(ctx, pi_len_plus_num_commitments, one);
         let vk_s_len_limbs =
             range.gate.mul(ctx, vk_remaining_len, num_limbs_per_g1);
         let vk_s = assigned_input
@@ -898,45 +1625,166 @@ where
             .flatten()
             .cloned()
             .collect();
-        // Variable input vk.h1 || vk.h2
-        let mut vk_h = Vec::with_capacity(2 * 4 * NUM_LIMBS);
-        let vk_h_max_len = ctx.load_constant(F::from(2 * 4 * NUM_LIMBS as u64));
+        // Variable input vk.h1[0] || vk.h2[0] || vk.h1[1] || vk.h2[1] ...
+        let mut vk_h = Vec::with_capacity(2 * 4 * num_limbs * max_commitments);
+        let vk_h_max_len = ctx.load_constant(F::from(2 * 4 * num_limbs as u64));
         let vk_h_len =
             range
                 .gate
-                .mul(ctx, vk_h_max_len, assigned_input.has_commitment);
-        vk_h.extend_from_slice(&assigned_input.app_vk.h1);
-        vk_h.extend_from_slice(&assigned_input.app_vk.h2);
+                .mul(ctx, vk_h_max_len, assigned_input.num_commitments);
+        for (h1, h2) in assigned_input
+            .app_vk
+            .h1
+            .chunks(4 * num_limbs)
+            .zip_eq(assigned_input.app_vk.h2.chunks(4 * num_limbs))
+        {
+            vk_h.extend_from_slice(h1);
+            vk_h.extend_from_slice(h2);
+        }
         keccak.multi_var_query(
             ctx,
             range,
             fixed_input,
             vec![vk_s, vk_h],
             vec![vk_s_len_limbs, vk_h_len],
+            limb_bits,
+            num_limbs,
         )
     }
 
-    /// For `assigned_input` and `circuit_id`:
-    /// 1) computes the byte decomposition of `assigned_input.public_inputs`
-    /// 2) computes its proof Id as the [`keccak_var_len`](KeccakChip::keccak_var_len)
-    /// query of the concatenatenation of `circuit_id` and the byte decomposition
-    /// computed in 1).
-    ///
-    /// The resulting query added to the keccak chip will be processed later
-    /// by [`assign_keccak_cells`](KeccakChip::assign_keccak_cells).
-    fn compute_proof_id(
+    /// Computes the circuit Id of `assigned_input`, which has no
+    /// commitments, as in [`Self::compute_circuit_id`], using the (already
+    /// assigned) `domain_tag`.  Since `num_commitments` is zero, the
+    /// commitment keys contribute nothing to the query and are omitted.
+    fn compute_circuit_id_without_commitment(
         ctx: &mut Context<F>,
         range: &RangeChip<F>,
         keccak: &mut KeccakChip<F>,
-        circuit_id: &[AssignedValue<F>],
         assigned_input: &AssignedKeccakInput<F>,
-    ) {
-        // Step 1: Byte decomposition of the field elements
+        domain_tag: Vec<AssignedValue<F>>,
+        limb_bits: usize,
+        num_limbs: usize,
+    ) -> Vec<AssignedValue<F>> {
+        // Compute vk_s length as public_inputs.len() + 1
         let len = assigned_input.len();
-        let field_elements = assigned_input.public_inputs();
-        let mut byte_repr = circuit_id.to_owned();
-        byte_repr.append(&mut byte_decomposition_list(
-            ctx,
+        let one = ctx.load_constant(F::one());
+        let vk_s_len = range.gate.add(ctx, *len, one);
+
+        let mut fixed_input = domain_tag;
+        Self::append_circuit_id_fixed_input(
+            ctx,
+            range,
+            &mut fixed_input,
+            assigned_input,
+            &vk_s_len,
+            limb_bits,
+            num_limbs,
+        );
+
+        // Variable input vk.s[2..]
+        let num_limbs_per_g1 = ctx.load_constant(F::from(2 * num_limbs as u64));
+        let vk_remaining_len = range.gate.sub(ctx, *len, one);
+        let vk_s_len_limbs =
+            range.gate.mul(ctx, vk_remaining_len, num_limbs_per_g1);
+        let vk_s = assigned_input
+            .app_vk
+            .s
+            .iter()
+            .skip(2)
+            .flatten()
+            .cloned()
+            .collect();
+        keccak.multi_var_query(
+            ctx,
+            range,
+            fixed_input,
+            vec![vk_s],
+            vec![vk_s_len_limbs],
+            limb_bits,
+            num_limbs,
+        )
+    }
+
+    /// Appends `alpha || beta || gamma || delta || vk_s length || vk_s[0] ||
+    /// vk_s[1]` (as bytes) to the circuit Id `fixed_input`, which holds the
+    /// domain tag.
+    fn append_circuit_id_fixed_input(
+        ctx: &mut Context<F>,
+        range: &RangeChip<F>,
+        fixed_input: &mut Vec<AssignedValue<F>>,
+        assigned_input: &AssignedKeccakInput<F>,
+        vk_s_len: &AssignedValue<F>,
+        limb_bits: usize,
+        num_limbs: usize,
+    ) {
+        // fixed input = domain_tag || alpha || beta || gamma || delta || vk_s length || vk_s[0] || vk_s[1]
+        fixed_input.append(&mut g1_point_limbs_to_bytes(
+            ctx,
+            range,
+            &assigned_input.app_vk.alpha,
+            limb_bits,
+            num_limbs,
+        ));
+        fixed_input.append(&mut g2_point_limbs_to_bytes(
+            ctx,
+            range,
+            &assigned_input.app_vk.beta,
+            limb_bits,
+            num_limbs,
+        ));
+        fixed_input.append(&mut g2_point_limbs_to_bytes(
+            ctx,
+            range,
+            &assigned_input.app_vk.gamma,
+            limb_bits,
+            num_limbs,
+        ));
+        fixed_input.append(&mut g2_point_limbs_to_bytes(
+            ctx,
+            range,
+            &assigned_input.app_vk.delta,
+            limb_bits,
+            num_limbs,
+        ));
+        fixed_input.append(&mut byte_decomposition(ctx, range, vk_s_len));
+        fixed_input.append(&mut g1_point_limbs_to_bytes(
+            ctx,
+            range,
+            &assigned_input.app_vk.s[0],
+            limb_bits,
+            num_limbs,
+        ));
+        // We require `len > 0`, so this element always exists
+        fixed_input.append(&mut g1_point_limbs_to_bytes(
+            ctx,
+            range,
+            &assigned_input.app_vk.s[1],
+            limb_bits,
+            num_limbs,
+        ));
+    }
+
+    /// For `assigned_input` and `circuit_id`:
+    /// 1) computes the byte decomposition of `assigned_input.public_inputs`
+    /// 2) computes its proof Id as the [`keccak_var_len`](KeccakChip::keccak_var_len)
+    /// query of the concatenatenation of `circuit_id` and the byte decomposition
+    /// computed in 1).
+    ///
+    /// The resulting query added to the keccak chip will be processed later
+    /// by [`assign_keccak_cells`](KeccakChip::assign_keccak_cells).
+    fn compute_proof_id(
+        ctx: &mut Context<F>,
+        range: &RangeChip<F>,
+        keccak: &mut KeccakChip<F>,
+        circuit_id: &[AssignedValue<F>],
+        assigned_input: &AssignedKeccakInput<F>,
+    ) {
+        // Step 1: Byte decomposition of the field elements
+        let len = assigned_input.len();
+        let field_elements = assigned_input.public_inputs();
+        let mut byte_repr = circuit_id.to_owned();
+        byte_repr.append(&mut byte_decomposition_list(
+            ctx,
             range,
             &field_elements,
         ));
@@ -946,75 +1794,95 @@ where
         keccak.keccak_var_len(ctx, range, byte_repr, byte_len);
     }
 
-    /// For `assigned_input.commitment_point_limbs`, computes:
+    /// For each commitment point in `assigned_input.commitment_point_limbs`,
+    /// computes:
     /// 1) Its byte decomposition
     /// 2) Its word decomposition
     /// 3) Its keccak hash as a [`keccak_fixed_len`](KeccakChip::keccak_fixed_len) query.
     ///
     /// Then it constrains the resulting keccak hash (composed as a field element) to
-    /// be equal to `assigned_input.commitment_hash`.
+    /// be equal to the corresponding element of `assigned_input.commitment_hashes`.
+    /// Each `Fq` coordinate is decomposed into `num_limbs` limbs of
+    /// `limb_bits` bits.
     fn commitment_point_hash_query(
         ctx: &mut Context<F>,
         range: &RangeChip<F>,
         keccak: &mut KeccakChip<F>,
         assigned_input: &AssignedKeccakInput<F>,
+        limb_bits: usize,
+        num_limbs: usize,
     ) {
-        // Byte decomposition
-        let commitment_point_bytes = g1_point_limbs_to_bytes(
-            ctx,
-            range,
-            &assigned_input.commitment_point_limbs,
-        );
-        keccak.keccak_fixed_len(ctx, range, commitment_point_bytes);
-        let output_bytes = keccak
-            .fixed_len_queries()
-            .last()
-            .expect("Retrieving the last keccak query is not allowed to fail")
-            .output_bytes_assigned()
-            .try_into()
-            .expect("Conversion is not allowed to fail");
-        let commitment_hash =
-            compose_into_field_element(ctx, range, &output_bytes);
-        ctx.constrain_equal(&commitment_hash, &assigned_input.commitment_hash);
+        for (commitment_point_limbs, expected_commitment_hash) in assigned_input
+            .commitment_point_limbs
+            .chunks(2 * num_limbs)
+            .zip_eq(assigned_input.commitment_hashes.iter())
+        {
+            // Byte decomposition
+            let commitment_point_bytes = g1_point_limbs_to_bytes(
+                ctx,
+                range,
+                commitment_point_limbs,
+                limb_bits,
+                num_limbs,
+            );
+            let output_bytes = keccak.keccak_fixed_len_output_bytes(
+                ctx,
+                range,
+                commitment_point_bytes,
+            );
+            let commitment_hash =
+                compose_into_field_element(ctx, range, &output_bytes);
+            ctx.constrain_equal(&commitment_hash, expected_commitment_hash);
+        }
     }
 
-    /// Computes the Merkle leaf corresponding to `proof_id`.
+    /// Computes the Merkle leaf corresponding to `proof_id`.  If
+    /// `domain_separated`, the proof id is prefixed with
+    /// [`MERKLE_LEAF_DOMAIN_TAG`].
     fn compute_leaf(
         ctx: &mut Context<F>,
         range: &RangeChip<F>,
         keccak: &mut KeccakChip<F>,
         proof_id: &[AssignedValue<F>],
+        domain_separated: bool,
     ) -> Vec<AssignedValue<F>> {
         assert_eq!(
             proof_id.len(),
             KECCAK_OUTPUT_BYTES,
             "Invalid number of bytes in proof id"
         );
-        keccak.keccak_fixed_len(ctx, range, proof_id.to_vec());
+        let mut input_bytes = Vec::with_capacity(KECCAK_OUTPUT_BYTES + 1);
+        if domain_separated {
+            input_bytes.push(
+                ctx.load_constant(F::from(MERKLE_LEAF_DOMAIN_TAG as u64)),
+            );
+        }
+        input_bytes.extend_from_slice(proof_id);
         keccak
-            .fixed_len_queries()
-            .last()
-            .expect("Retrieving the last keccak query is not allowed to fail")
-            .output_bytes_assigned()
+            .keccak_fixed_len_output_bytes(ctx, range, input_bytes)
             .to_vec()
     }
 
-    /// Hashes `left_node` with `right_node`.
+    /// Hashes `left_node` with `right_node`.  If `domain_separated`, they
+    /// are prefixed with [`MERKLE_NODE_DOMAIN_TAG`].
     fn hash_pair(
         ctx: &mut Context<F>,
         range: &RangeChip<F>,
         keccak: &mut KeccakChip<F>,
         left_node: &[AssignedValue<F>],
         right_node: &[AssignedValue<F>],
+        domain_separated: bool,
     ) -> Vec<AssignedValue<F>> {
-        let mut input_bytes = left_node.to_vec();
+        let mut input_bytes = Vec::with_capacity(2 * KECCAK_OUTPUT_BYTES + 1);
+        if domain_separated {
+            input_bytes.push(
+                ctx.load_constant(F::from(MERKLE_NODE_DOMAIN_TAG as u64)),
+            );
+        }
+        input_bytes.extend_from_slice(left_node);
         input_bytes.extend_from_slice(right_node);
-        keccak.keccak_fixed_len(ctx, range, input_bytes);
         keccak
-            .fixed_len_queries()
-            .last()
-            .expect("Retrieving the last keccak query is not allowed to fail")
-            .output_bytes_assigned()
+            .keccak_fixed_len_output_bytes(ctx, range, input_bytes)
             .to_vec()
     }
 
@@ -1024,6 +1892,7 @@ where
         range: &RangeChip<F>,
         keccak: &mut KeccakChip<F>,
         row: Vec<Vec<AssignedValue<F>>>,
+        domain_separated: bool,
     ) -> Vec<Vec<AssignedValue<F>>> {
         let number_of_nodes = row.len();
         let mut next_row = Vec::with_capacity(number_of_nodes / 2);
@@ -1034,13 +1903,18 @@ where
                 keccak,
                 &row[i],
                 &row[i + 1],
+                domain_separated,
             ));
         }
         next_row
     }
 
-    /// Groups `proof_ids` in groups of 32 bytes (each representing a proof id).
+    /// Groups `proof_ids` in groups of [`KECCAK_OUTPUT_BYTES`] bytes (each
+    /// representing a proof id).
     /// Keeps the first `num_proof_ids` groups and replaces the rest with zeroes.
+    /// If the number of groups is not a power of two, zero proof ids are
+    /// appended up to the next power of two, so that the result can be used as
+    /// the (padded) leaf set of the submission id Merkle tree.
     fn pad_proof_ids(
         ctx: &mut Context<F>,
         range: &RangeChip<F>,
@@ -1054,10 +1928,9 @@ where
             .map(|chunk| chunk.into_iter().copied().collect_vec())
             .collect_vec();
         let total_num_proof_ids = proof_ids.len();
-        assert_eq!(
-            (total_num_proof_ids & (total_num_proof_ids - 1)),
-            0,
-            "The number of (padded) proof ids must be a power of two in submission id mode"
+        assert!(
+            total_num_proof_ids > 0,
+            "No proof ids in submission id mode"
         );
 
         let zero = ctx.load_constant(F::zero());
@@ -1074,6 +1947,10 @@ where
             }
         }
 
+        proof_ids.resize(
+            total_num_proof_ids.next_power_of_two(),
+            vec![zero; KECCAK_OUTPUT_BYTES],
+        );
         proof_ids
     }
 
@@ -1084,25 +1961,46 @@ where
         keccak: &mut KeccakChip<F>,
         proof_ids: &[AssignedValue<F>],
         num_proof_ids: AssignedValue<F>,
+        domain_separated: bool,
     ) -> Vec<Vec<AssignedValue<F>>> {
         let proof_ids =
             Self::pad_proof_ids(ctx, range, proof_ids, num_proof_ids);
         proof_ids
             .into_iter()
-            .map(|proof_id| Self::compute_leaf(ctx, range, keccak, &proof_id))
+            .map(|proof_id| {
+                Self::compute_leaf(
+                    ctx,
+                    range,
+                    keccak,
+                    &proof_id,
+                    domain_separated,
+                )
+            })
             .collect_vec()
     }
 
-    /// Computes the submission id from `proof_ids` as bytes.
+    /// Computes the submission id from `proof_ids` as bytes.  If
+    /// `domain_separated`, leaves and internal nodes are hashed with
+    /// distinct prefixes (see
+    /// [`utils::build_submission_tree_with_domain_separation`]).
     pub(crate) fn compute_submission_id_bytes(
         ctx: &mut Context<F>,
         range: &RangeChip<F>,
         keccak: &mut KeccakChip<F>,
         proof_ids: &[AssignedValue<F>],
         num_proof_ids: AssignedValue<F>,
+        domain_separated: bool,
+        progress: &mut dyn FnMut(KeccakProgress),
     ) -> [AssignedValue<F>; KECCAK_OUTPUT_BYTES] {
-        let mut current_row =
-            Self::compute_leaves(ctx, range, keccak, proof_ids, num_proof_ids);
+        progress(KeccakProgress::ComputingLeaves);
+        let mut current_row = Self::compute_leaves(
+            ctx,
+            range,
+            keccak,
+            proof_ids,
+            num_proof_ids,
+            domain_separated,
+        );
         let num_leaves = current_row.len();
         let depth = (num_leaves.ilog2() + 1) as usize;
         let next_power_of_two = compute_next_power_of_two_bit_decomposition(
@@ -1116,7 +2014,14 @@ where
 
         let mut subtree_roots = vec![current_row[0].clone()];
         while current_row.len() > 1 {
-            current_row = Self::hash_row(ctx, range, keccak, current_row);
+            progress(KeccakProgress::HashingRow(subtree_roots.len() - 1));
+            current_row = Self::hash_row(
+                ctx,
+                range,
+                keccak,
+                current_row,
+                domain_separated,
+            );
             subtree_roots.push(current_row[0].clone());
         }
 
@@ -1144,6 +2049,8 @@ where
         keccak: &mut KeccakChip<F>,
         proof_ids: &[AssignedValue<F>],
         num_proof_ids: AssignedValue<F>,
+        domain_separated: bool,
+        progress: &mut dyn FnMut(KeccakProgress),
     ) -> [AssignedValue<F>; 2] {
         let submission_id_bytes = Self::compute_submission_id_bytes(
             ctx,
@@ -1151,6 +2058,8 @@ where
             keccak,
             proof_ids,
             num_proof_ids,
+            domain_separated,
+            progress,
         );
         encode_digest_as_field_elements(ctx, range, &submission_id_bytes)
     }
@@ -1162,54 +2071,163 @@ where
         keccak: &mut KeccakChip<F>,
         proof_ids: &[AssignedValue<F>],
     ) -> [AssignedValue<F>; 2] {
-        keccak.keccak_fixed_len(ctx, range, proof_ids.to_vec());
-        let public_output_bytes = keccak
-            .fixed_len_queries()
-            .last()
-            .expect("Retrieving the last keccak query is not allowed to fail")
-            .output_bytes_assigned();
-        let public_output_bytes = public_output_bytes
-            .to_vec()
-            .try_into()
-            .expect("Conversion from vector to array is not allowed to fail");
-        encode_digest_as_field_elements(ctx, range, &public_output_bytes)
+        keccak.keccak_fixed_len_as_field_elements(
+            ctx,
+            range,
+            proof_ids.to_vec(),
+        )
+    }
+
+    /// Computes the vk digest as the keccak hash of all `circuit_ids`.
+    fn compute_vk_digest(
+        ctx: &mut Context<F>,
+        range: &RangeChip<F>,
+        keccak: &mut KeccakChip<F>,
+        circuit_ids: &[AssignedValue<F>],
+    ) -> [AssignedValue<F>; 2] {
+        // Same computation as the linear final digest, over different bytes.
+        Self::compute_linear_final_digest(ctx, range, keccak, circuit_ids)
     }
 
     /// Instantiates a new [`KeccakCircuit`] from `degree_bits`, `builder` and `inputs`.
     fn new(
         config: &KeccakConfig,
-        mut builder: GateThreadBuilder<F>,
+        builder: GateThreadBuilder<F>,
+        inputs: KeccakPaddedCircuitInputs<F>,
+    ) -> Self {
+        Self::new_with_progress(config, builder, inputs, &mut |_| {})
+    }
+
+    /// Same as [`Self::new`], reporting the progress of witness generation
+    /// to `progress`.
+    fn new_with_progress(
+        config: &KeccakConfig,
+        builder: GateThreadBuilder<F>,
         inputs: KeccakPaddedCircuitInputs<F>,
+        progress: &mut dyn FnMut(KeccakProgress),
     ) -> Self {
+        Self::new_cancellable(
+            config,
+            builder,
+            inputs,
+            progress,
+            CancellationToken::default(),
+        )
+        .expect("uncancellable token was cancelled")
+    }
+
+    /// Same as [`Self::new_with_progress`], but checks `cancel` after each
+    /// application input is assigned, returning `Err(Cancelled)` if it is
+    /// cancelled.  The circuit keeps `cancel`, and checks it again while
+    /// assigning the keccak rows during synthesis.
+    pub(crate) fn new_cancellable(
+        config: &KeccakConfig,
+        mut builder: GateThreadBuilder<F>,
+        inputs: KeccakPaddedCircuitInputs<F>,
+        progress: &mut dyn FnMut(KeccakProgress),
+        cancel: CancellationToken,
+    ) -> Result<Self, Cancelled> {
         let witness_gen_only = builder.witness_gen_only();
+        let AssignedKeccakCells {
+            mut keccak,
+            public_inputs,
+            public_output,
+            vk_digest,
+        } = Self::assign_cells(
+            config,
+            &mut builder,
+            inputs,
+            progress,
+            &cancel,
+        )?;
+        // Compute optimal parameters
+        let gate_config = if witness_gen_only {
+            serde_json::from_str(
+                &var("KECCAK_GATE_CONFIG").expect("KECCAK_GATE_CONFIG not set"),
+            )
+            .expect("Deserialization error")
+        } else {
+            Self::config(
+                &builder,
+                &mut keccak,
+                config.degree_bits,
+                config.unusable_rows,
+                Some(config.lookup_bits),
+                config.max_rows_per_round,
+            )
+        };
+        Ok(Self {
+            builder: RefCell::new(builder),
+            break_points: RefCell::new(Default::default()),
+            keccak,
+            public_inputs,
+            public_output,
+            vk_digest,
+            config: gate_config,
+            cancel,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Assigns `inputs` to `builder`, and makes the keccak queries for the
+    /// circuit ids, proof ids, commitment hashes and digests, checking
+    /// `cancel` after each application input is assigned.
+    fn assign_cells(
+        config: &KeccakConfig,
+        builder: &mut GateThreadBuilder<F>,
+        inputs: KeccakPaddedCircuitInputs<F>,
+        progress: &mut dyn FnMut(KeccakProgress),
+        cancel: &CancellationToken,
+    ) -> Result<AssignedKeccakCells<F>, Cancelled> {
         let ctx = builder.main(0);
-        let lookup_bits = config.lookup_bits;
-        let range = RangeChip::default(lookup_bits);
+        let range = RangeChip::default(config.lookup_bits);
         let mut keccak = KeccakChip::default();
         let mut public_inputs = Vec::new();
         // Assign and constrain `num_proof_ids`
+        let output_submission_id = config.output_mode.outputs_submission_id();
         assert!(
-            config.output_submission_id ^ inputs.num_proof_ids.is_none(),
+            output_submission_id ^ inputs.num_proof_ids.is_none(),
             "Config incompatible with inputs"
         );
+        assert!(
+            output_submission_id || !config.public_num_proof_ids,
+            "Public num proof ids requires output submission id"
+        );
+        assert!(
+            config.output_mode == OutputMode::Linear
+                || !config.include_vk_digest,
+            "VK digest requires linear final digest"
+        );
         let num_proof_ids =
             inputs.num_proof_ids.map(|npi| ctx.load_witness(npi));
-        if config.output_submission_id {
+        if output_submission_id {
             range.check_less_than_safe(
                 ctx,
                 num_proof_ids.expect("Num proof ids has been assigned before"),
                 (config.inner_batch_size * config.outer_batch_size + 1).into(),
             );
         }
-        for input in inputs.inputs {
+        let total = inputs.inputs.len();
+        for (i, input) in inputs.inputs.into_iter().enumerate() {
             let assigned_input = AssignedKeccakInput::from_keccak_padded_input(
                 ctx, &range, input,
             );
+            if !config.allow_commitments {
+                range.gate.assert_is_const(
+                    ctx,
+                    &assigned_input.num_commitments,
+                    &F::zero(),
+                );
+            }
             let circuit_id = Self::compute_circuit_id(
                 ctx,
                 &range,
                 &mut keccak,
                 &assigned_input,
+                config.domain_tag_version,
+                config.limb_bits,
+                config.num_limbs,
+                config.allow_commitments,
             );
             // Specification: Proof ID Computation
             Self::compute_proof_id(
@@ -1220,13 +2238,19 @@ where
                 &assigned_input,
             );
             // Specification: Curve-to-Field Hash
-            Self::commitment_point_hash_query(
-                ctx,
-                &range,
-                &mut keccak,
-                &assigned_input,
-            );
+            if config.allow_commitments {
+                Self::commitment_point_hash_query(
+                    ctx,
+                    &range,
+                    &mut keccak,
+                    &assigned_input,
+                    config.limb_bits,
+                    config.num_limbs,
+                );
+            }
             public_inputs.push(assigned_input);
+            progress(KeccakProgress::AssigningCells { done: i + 1, total });
+            cancel.check()?;
         }
 
         // Specification: Final Digest Computation.
@@ -1240,48 +2264,54 @@ where
             .step_by(2) // we skip the circuitId computations
             .flat_map(|query| query.output_bytes_assigned().to_vec())
             .collect::<Vec<_>>();
-        let public_output = match config.output_submission_id {
-            true => Self::compute_submission_id(
+        // The submission id queries come before the linear digest query, so
+        // that the latter is the last fixed-length query when computed.
+        let submission_id = output_submission_id.then(|| {
+            Self::compute_submission_id(
                 ctx,
                 &range,
                 &mut keccak,
                 &proof_ids,
                 num_proof_ids.expect("Num proof ids has been assigned before"),
-            ),
-            false => Self::compute_linear_final_digest(
-                ctx,
-                &range,
-                &mut keccak,
-                &proof_ids,
-            ),
-        };
-        // Compute optimal parameters
-        let config = if witness_gen_only {
-            serde_json::from_str(
-                &var("KECCAK_GATE_CONFIG").expect("KECCAK_GATE_CONFIG not set"),
+                config.domain_separated_tree,
+                progress,
             )
-            .expect("Deserialization error")
-        } else {
-            Self::config(
-                &builder,
-                &mut keccak,
-                config.degree_bits,
-                Some(DEFAULT_UNUSABLE_ROWS),
-                Some(lookup_bits),
-            )
-        };
-        Self {
-            builder: RefCell::new(builder),
-            break_points: RefCell::new(Default::default()),
+        });
+        let linear_digest =
+            config.output_mode.outputs_linear_digest().then(|| {
+                Self::compute_linear_final_digest(
+                    ctx,
+                    &range,
+                    &mut keccak,
+                    &proof_ids,
+                )
+            });
+        // In `OutputMode::Both`, the linear digest precedes the submission id.
+        let public_output = linear_digest
+            .into_iter()
+            .chain(submission_id)
+            .flatten()
+            .collect_vec();
+        let vk_digest = config.include_vk_digest.then(|| {
+            // The even var_len_queries contain the circuitIds.
+            let circuit_ids = keccak
+                .var_len_queries()
+                .iter()
+                .step_by(2)
+                .flat_map(|query| query.output_bytes_assigned().to_vec())
+                .collect::<Vec<_>>();
+            Self::compute_vk_digest(ctx, &range, &mut keccak, &circuit_ids)
+        });
+        Ok(AssignedKeccakCells {
             keccak,
             public_inputs: AssignedKeccakInputs {
                 inputs: public_inputs,
                 num_proof_ids,
+                public_num_proof_ids: config.public_num_proof_ids,
             },
             public_output,
-            config,
-            _marker: PhantomData,
-        }
+            vk_digest,
+        })
     }
 
     /// Returns the public output.
@@ -1289,43 +2319,46 @@ where
         self.public_output.as_slice()
     }
 
+    /// Returns the vk digest, if `include_vk_digest` is set.
+    pub fn vk_digest(&self) -> Option<&[AssignedValue<F>]> {
+        self.vk_digest
+            .as_ref()
+            .map(|vk_digest| vk_digest.as_slice())
+    }
+
+    /// Returns the keccak chip, holding the queries made by the circuit.
+    pub fn keccak_chip(&self) -> &KeccakChip<F> {
+        &self.keccak
+    }
+
     /// Returns the intermediate outputs.
     pub fn keccak_output_bytes(&self) -> Vec<&AssignedValue<F>> {
         self.keccak
-            .var_len_queries()
-            .iter()
+            .iter_queries()
             .flat_map(|query| query.output_bytes_assigned())
-            .chain(
-                self.keccak
-                    .fixed_len_queries()
-                    .iter()
-                    .flat_map(|query| query.output_bytes_assigned()),
-            )
             .collect()
     }
 
-    /// Calculates the optimal [`KeccakGateConfig`] for a given `degree_bits`.
+    /// Calculates the optimal [`KeccakGateConfig`] for a given `degree_bits`
+    /// (see [`Self::optimal_gate_config`]), sets the number of rows per
+    /// round of `keccak` accordingly, and sets the keccak environment
+    /// variables read at proving time.
     pub(crate) fn config(
         builder: &GateThreadBuilder<F>,
         keccak: &mut KeccakChip<F>,
         degree_bits: u32,
-        minimum_rows: Option<usize>,
+        unusable_rows: Option<usize>,
         lookup_bits: Option<usize>,
+        max_rows_per_round: Option<u32>,
     ) -> KeccakGateConfig {
-        let optimal_rows_per_round = rows_per_round(
-            (1 << degree_bits) - minimum_rows.unwrap_or(0),
-            keccak.total_keccak_perms(),
+        let params = Self::optimal_gate_config(
+            builder,
+            keccak,
+            degree_bits,
+            unusable_rows,
+            lookup_bits,
+            max_rows_per_round,
         );
-        let flex_gate_config_params =
-            builder.config(degree_bits as usize, minimum_rows);
-        let mut params = KeccakGateConfig {
-            flex_gate_config_params,
-            rows_per_round: 0,
-            lookup_bits: lookup_bits.unwrap_or(KECCAK_LOOKUP_BITS),
-        };
-
-        params.rows_per_round =
-            std::cmp::min(optimal_rows_per_round, MAX_KECCAK_ROWS_PER_ROUND);
         keccak.num_rows_per_round = params.rows_per_round as usize;
         set_var("KECCAK_LOOKUP_BITS", params.lookup_bits.to_string());
         set_var(
@@ -1338,6 +2371,52 @@ where
         params
     }
 
+    /// Calculates the optimal [`KeccakGateConfig`] for a given `degree_bits`,
+    /// from the cells assigned in `builder` and the queries in `keccak`.
+    /// If `unusable_rows` is `None`, [`DEFAULT_UNUSABLE_ROWS`] rows are
+    /// reserved for blinding factors.
+    fn optimal_gate_config(
+        builder: &GateThreadBuilder<F>,
+        keccak: &KeccakChip<F>,
+        degree_bits: u32,
+        unusable_rows: Option<usize>,
+        lookup_bits: Option<usize>,
+        max_rows_per_round: Option<u32>,
+    ) -> KeccakGateConfig {
+        let minimum_rows = unusable_rows.unwrap_or(DEFAULT_UNUSABLE_ROWS);
+        let usable_rows = (1 << degree_bits) - minimum_rows;
+        let optimal_rows_per_round =
+            rows_per_round(usable_rows, keccak.total_keccak_perms());
+        let max_rows_per_round =
+            max_rows_per_round.unwrap_or(MAX_KECCAK_ROWS_PER_ROUND);
+        assert!(
+            max_rows_per_round > 0,
+            "max_rows_per_round must be positive"
+        );
+        let flex_gate_config_params =
+            builder.config(degree_bits as usize, Some(minimum_rows));
+        let mut params = KeccakGateConfig {
+            flex_gate_config_params,
+            rows_per_round: 0,
+            lookup_bits: lookup_bits.unwrap_or(KECCAK_LOOKUP_BITS),
+            unusable_rows,
+        };
+
+        params.rows_per_round =
+            std::cmp::min(optimal_rows_per_round, max_rows_per_round);
+        let keccak_rows = keccak_num_rows(
+            params.rows_per_round as usize,
+            keccak.total_keccak_perms(),
+        );
+        assert!(
+            params.rows_per_round > 0 && keccak_rows <= usable_rows,
+            "Keccak rows ({keccak_rows} with {} rows per round) exceed the \
+             {usable_rows} usable rows for degree {degree_bits}",
+            params.rows_per_round
+        );
+        params
+    }
+
     /// Extracts the cells containing the public inputs in `self`.
     fn extract_public_inputs(
         &self,
@@ -1352,17 +2431,23 @@ where
             .collect()
     }
 
-    /// Extracts the two cells containing the public output in `self`.
+    /// Extracts the cells containing the public output (the final digest,
+    /// followed by the vk digest if any) in `self`.
     fn extract_public_output(
         &self,
         assignments: &KeygenAssignments<F>,
-    ) -> [circuit::Cell; 2] {
+    ) -> Vec<circuit::Cell> {
         self.public_output
-            .map(|po| assigned_cell_from_assigned_value(&po, assignments))
+            .iter()
+            .chain(self.vk_digest.iter().flatten())
+            .map(|po| assigned_cell_from_assigned_value(po, assignments))
+            .collect()
     }
 
     /// Synthesizes `self`, generating constraints. Returns the [`Cell`](circuit::Cell)s
     /// corresponding to the public inputs and outputs so we can later expose them.
+    /// Fails with [`Error::Synthesis`] if the circuit's [`CancellationToken`]
+    /// is cancelled.
     ///
     /// The keccak chip defines (wide) gates and rows, unrelated to the Context columns,
     /// and allocates cells in the context for the inputs as 64-bit words, and for output bytes.
@@ -1379,7 +2464,16 @@ where
         &self,
         config: &KeccakCircuitConfig<F>,
         layouter: &mut impl Layouter<F>,
-    ) -> ExposedInstances {
+    ) -> Result<ExposedInstances, Error> {
+        let witness_gen_only = self.builder.borrow().witness_gen_only();
+        let _span = tracing::info_span!(
+            "keccak_synthesize",
+            degree = self.config.flex_gate_config_params.k,
+            rows_per_round = self.config.rows_per_round,
+            num_queries = self.keccak.iter_queries().count(),
+            witness_gen_only,
+        )
+        .entered();
         config
             .range
             .load_lookup_table(layouter)
@@ -1389,7 +2483,6 @@ where
             .load_aux_tables(layouter)
             .expect("load keccak lookup tables");
         let mut first_pass = SKIP_FIRST_PASS;
-        let witness_gen_only = self.builder.borrow().witness_gen_only();
         let mut assigned_public_outputs = None;
         let mut assigned_public_inputs = Vec::new();
         layouter
@@ -1412,7 +2505,12 @@ where
                             break_points,
                         );
                         self.keccak
-                            .assign_keccak_cells(&mut region, &config.keccak);
+                            .assign_keccak_cells_cancellable(
+                                &mut region,
+                                &config.keccak,
+                                &self.cancel,
+                            )
+                            .map_err(|_| Error::Synthesis)?;
                     } else {
                         let start = std::time::Instant::now();
                         let builder = self.builder.borrow();
@@ -1435,7 +2533,12 @@ where
 
                         let (fixed_len_cells, var_len_cells) = self
                             .keccak
-                            .assign_keccak_cells(&mut region, &config.keccak);
+                            .assign_keccak_cells_cancellable(
+                                &mut region,
+                                &config.keccak,
+                                &self.cancel,
+                            )
+                            .map_err(|_| Error::Synthesis)?;
 
                         self.keccak.constrain_fixed_queries(
                             &mut region,
@@ -1451,67 +2554,60 @@ where
                         // Update break points
                         *self.break_points.borrow_mut() =
                             assignments.break_points;
-                        log::info!(
-                            "keccak keygen constraint gen {:?}",
-                            start.elapsed()
+                        tracing::info!(
+                            elapsed = ?start.elapsed(),
+                            "keccak keygen constraint gen"
                         );
                     }
                     Ok(())
                 },
             )
-            .unwrap();
-        (assigned_public_inputs, assigned_public_outputs)
+            .map(|()| (assigned_public_inputs, assigned_public_outputs))
     }
 }
 
-// NOTE: only implemented for F = bn256::Fr, since the implementation relies on
-// digest_as_field_elements, which is field dependent.
-impl<'a> SafeCircuit<'a, Fr, G1Affine> for KeccakCircuit<Fr, G1Affine> {
-    type CircuitConfig = KeccakConfig;
-    type GateConfig = KeccakGateConfig;
-    type CircuitInputs = KeccakCircuitInputs<Fr>;
-    type KeygenInputs = ();
-    type InstanceInputs = KeccakCircuitInputs<Fr>;
-
-    fn mock(
-        config: &Self::CircuitConfig,
-        inputs: &Self::CircuitInputs,
-    ) -> Self {
-        let circuit_inputs =
-            KeccakPaddedCircuitInputs::from_keccak_circuit_inputs(
-                inputs,
-                config.num_app_public_inputs as usize,
-            );
-        assert!(
-            circuit_inputs.is_well_constructed(config),
-            "Invalid keccak circuit inputs"
-        );
-        Self::new(config, GateThreadBuilder::mock(), circuit_inputs)
-    }
-
-    fn keygen(
-        config: &Self::CircuitConfig,
-        inputs: &Self::KeygenInputs,
+impl KeccakCircuit<Fr, G1Affine> {
+    /// Same as [`SafeCircuit::prover`], reporting the progress of witness
+    /// generation to `progress`.
+    pub fn prover_with_progress(
+        config: &KeccakConfig,
+        gate_config: &KeccakGateConfig,
+        break_points: MultiPhaseThreadBreakPoints,
+        inputs: &KeccakCircuitInputs<Fr>,
+        progress: &mut dyn FnMut(KeccakProgress),
     ) -> Self {
-        let _ = inputs;
-        Self::new(
+        Self::prover_cancellable(
             config,
-            GateThreadBuilder::keygen(),
-            KeccakPaddedCircuitInputs::dummy(config),
+            gate_config,
+            break_points,
+            inputs,
+            progress,
+            CancellationToken::default(),
         )
+        .expect("uncancellable token was cancelled")
     }
 
-    fn prover(
-        config: &Self::CircuitConfig,
-        gate_config: &Self::GateConfig,
+    /// Same as [`Self::prover_with_progress`], but returns `Err(Cancelled)`
+    /// if `cancel` is cancelled during witness generation.  If it is
+    /// cancelled later, while the proof is being created, synthesis fails
+    /// with [`Error::Synthesis`].
+    pub fn prover_cancellable(
+        config: &KeccakConfig,
+        gate_config: &KeccakGateConfig,
         break_points: MultiPhaseThreadBreakPoints,
-        inputs: &Self::CircuitInputs,
-    ) -> Self {
+        inputs: &KeccakCircuitInputs<Fr>,
+        progress: &mut dyn FnMut(KeccakProgress),
+        cancel: CancellationToken,
+    ) -> Result<Self, Cancelled> {
         let circuit_inputs =
             KeccakPaddedCircuitInputs::from_keccak_circuit_inputs(
                 inputs,
                 config.num_app_public_inputs as usize,
-            );
+                config.max_commitments as usize,
+                config.limb_bits,
+                config.num_limbs,
+            )
+            .unwrap_or_else(|e| panic!("Invalid commitment point: {e}"));
 
         {
             // Check well-formedness of the public inputs w.r.t. the configuration
@@ -1519,6 +2615,10 @@ impl<'a> SafeCircuit<'a, Fr, G1Affine> for KeccakCircuit<Fr, G1Affine> {
                 circuit_inputs.is_well_constructed(config),
                 "Invalid keccak circuit inputs"
             );
+            // Check gate_config was generated for config
+            gate_config
+                .assert_compatible(config)
+                .unwrap_or_else(|e| panic!("{e}"));
             // Check gate_config coincides with KECCAK_GATE_CONFIG
             let gate_config_env =
                 var("KECCAK_GATE_CONFIG").expect("KECCAK_GATE_CONFIG not set");
@@ -1537,21 +2637,15 @@ impl<'a> SafeCircuit<'a, Fr, G1Affine> for KeccakCircuit<Fr, G1Affine> {
                 lookup_bits_env, config.lookup_bits,
                 "lookup bits configuration mismatch"
             );
-            assert_eq!(
-                lookup_bits_env, gate_config.lookup_bits,
-                "lookup bits configuration mismatch"
-            );
             // Check gate_config.flex_gate_config_params has the same number of
             // columns as FLEX_GATE_NUM_COLS
             let num_flex_cols = var("FLEX_GATE_NUM_COLS")
                 .expect("FLEX_GATE_NUM_COLS not set")
                 .parse::<usize>()
                 .expect("Error parsing FLEX_GATE_NUM_COLS");
-            assert_eq!(
-                num_flex_cols,
-                gate_config.num_flex_cols(),
-                "Flex Gate num cols mismatch"
-            );
+            gate_config
+                .check_num_flex_cols(num_flex_cols)
+                .unwrap_or_else(|e| panic!("{e}"));
 
             // Check config.degree_bits coincides with KECCAK_DEGREE
             let keccak_degree_env = var("KECCAK_DEGREE")
@@ -1563,37 +2657,399 @@ impl<'a> SafeCircuit<'a, Fr, G1Affine> for KeccakCircuit<Fr, G1Affine> {
                 "Keccak degree mismatch"
             );
         }
-        let circuit =
-            Self::new(config, GateThreadBuilder::prover(), circuit_inputs);
+        let circuit = Self::new_cancellable(
+            config,
+            GateThreadBuilder::prover(),
+            circuit_inputs,
+            progress,
+            cancel,
+        )?;
         *circuit.break_points.borrow_mut() = break_points;
-        circuit
+        Ok(circuit)
+    }
+
+    /// Check that for each `input: KeccakInput` of `self.public_inputs`, and
+    /// each of its (padded) commitment points,
+    /// 1) The keccak output bytes of `self` match the commitment hash in `input`.
+    /// 2) The limbs in `input` (`num_limbs` limbs of `limb_bits` bits per
+    ///    coordinate) represent coordinates that hash to the commitment hash
+    ///
+    /// `query_outputs` holds the output bytes of each keccak query, in the
+    /// order of [`KeccakChip::iter_queries`].
+    fn are_commitment_point_queries_well_constructed(
+        &self,
+        query_outputs: &[Vec<u8>],
+        starting_index_commitment_queries: usize,
+        limb_bits: usize,
+        num_limbs: usize,
+    ) -> Result<(), KeccakCircuitInconsistency<Fr>> {
+        let commitment_queries =
+            self.public_inputs.inputs.iter().flat_map(|input| {
+                input
+                    .commitment_point_limbs
+                    .chunks(2 * num_limbs)
+                    .zip_eq(input.commitment_hashes.iter())
+            });
+        for (i, (commitment_point_limbs, expected_commitment_hash)) in
+            commitment_queries.enumerate()
+        {
+            let expected_commitment_hash = expected_commitment_hash.value();
+            let commitment_query_index =
+                i + 2 * starting_index_commitment_queries;
+            let query_commitment_hash_bytes: [u8; KECCAK_OUTPUT_BYTES] =
+                query_outputs[commitment_query_index]
+                    .as_slice()
+                    .try_into()
+                    .expect("Conversion to array is not allowed to fail");
+            let commitment_hash = be_bytes_to_field_element::<Fr, 32>(
+                &query_commitment_hash_bytes,
+            );
+            let limbs = commitment_point_limbs
+                .iter()
+                .map(|limb| *limb.value())
+                .collect_vec();
+            let computed_commitment_hash =
+                commitment_hash_from_commitment_point_limbs(
+                    &limbs[..],
+                    limb_bits,
+                    num_limbs,
+                );
+            ((&commitment_hash == expected_commitment_hash)
+                && (&computed_commitment_hash == expected_commitment_hash))
+                .then_some(())
+                .ok_or({
+                    KeccakCircuitInconsistency::CommitmentQuery(
+                        commitment_query_index as u32,
+                        commitment_hash,
+                        computed_commitment_hash,
+                        *expected_commitment_hash,
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the vk digest of `self` (if any) is the keccak hash of
+    /// the circuit ids of the first `num_inputs` inputs, and that it matches
+    /// the output bytes of the last keccak query.  See
+    /// [`Self::are_commitment_point_queries_well_constructed`] for
+    /// `query_outputs`.
+    fn is_vk_digest_well_constructed(
+        &self,
+        query_outputs: &[Vec<u8>],
+        num_inputs: usize,
+    ) -> Result<(), KeccakCircuitInconsistency<Fr>> {
+        let vk_digest = match &self.vk_digest {
+            Some(vk_digest) => vk_digest,
+            None => return Ok(()),
+        };
+        let circuit_id_bytes = query_outputs[..2 * num_inputs]
+            .iter()
+            .step_by(2)
+            .flatten()
+            .copied()
+            .collect_vec();
+        let expected_bytes = keccak256(circuit_id_bytes);
+        let last_output_bytes = query_outputs
+            .last()
+            .expect("vk digest query must exist")
+            .clone();
+        let vk_digest_bytes = vk_digest.map(|field_element| {
+            field_element
+                .value()
+                .to_bytes_le()
+                .into_iter()
+                .take(16)
+                .rev()
+                .collect_vec()
+        });
+        let mut output_bytes = vk_digest_bytes[1].clone();
+        output_bytes.extend(vk_digest_bytes[0].iter());
+        (last_output_bytes == output_bytes
+            && last_output_bytes == expected_bytes)
+            .then_some(())
+            .ok_or({
+                KeccakCircuitInconsistency::VkDigest(
+                    last_output_bytes,
+                    output_bytes,
+                    expected_bytes.into(),
+                )
+            })
+    }
+
+    /// Checks that `self` is well-formed w.r.t. `config`, returning the first
+    /// inconsistency found.
+    ///
+    /// # Note
+    ///
+    /// In principle, any circuit generated via [`new`](Self::new) should always be well-formed.
+    /// This function is intended for checking invariants, and for diagnosing
+    /// failed proofs.
+    pub fn check_well_constructed(
+        &self,
+        config: &KeccakConfig,
+    ) -> Result<(), KeccakCircuitInconsistency<Fr>> {
+        // The output bytes of each query, in the order of `iter_queries`:
+        // the circuit id and proof id of each input come first.
+        let query_outputs = self
+            .keccak
+            .iter_queries()
+            .map(|query| {
+                get_assigned_bytes_values(query.output_bytes_assigned())
+            })
+            .collect_vec();
+        let mut last_index = 0;
+        for (i, input) in self.public_inputs.inputs.iter().enumerate() {
+            last_index = i as u32;
+            let number_of_field_elements = input.num_field_elements();
+            let num_commitments = input.num_commitments();
+            let num_bytes =
+                KECCAK_OUTPUT_BYTES + NUM_BYTES_FQ * number_of_field_elements;
+            let mut vk =
+                input.app_vk.value().vk(config.limb_bits, config.num_limbs);
+            vk.s =
+                vk.s.into_iter()
+                    .take(number_of_field_elements + 1 + num_commitments)
+                    .collect();
+            vk.h1.truncate(num_commitments);
+            vk.h2.truncate(num_commitments);
+            let circuit_id =
+                universal::native::compute_circuit_id_with_domain_version(
+                    &vk,
+                    config.domain_tag_version,
+                );
+            let input_bytes = circuit_id
+                .iter()
+                .copied()
+                .chain(input.public_inputs().into_iter().flat_map(
+                    |field_element| {
+                        field_element
+                            .value()
+                            .to_bytes_le()
+                            .into_iter()
+                            .rev()
+                            .collect_vec()
+                    },
+                ))
+                .collect_vec();
+            let expected_bytes_proof_id = keccak256(&input_bytes[..num_bytes]);
+            let output_bytes_circuit_id = &query_outputs[2 * i];
+            let output_bytes_proof_id = &query_outputs[2 * i + 1];
+            (output_bytes_circuit_id[..] == circuit_id)
+                .then_some(())
+                .ok_or_else(|| {
+                    KeccakCircuitInconsistency::KeccakCircuitId(
+                        last_index,
+                        output_bytes_circuit_id.clone(),
+                        circuit_id.to_vec(),
+                    )
+                })?;
+            (output_bytes_proof_id[..] == expected_bytes_proof_id)
+                .then_some(())
+                .ok_or_else(|| {
+                    KeccakCircuitInconsistency::KeccakProofId(
+                        last_index,
+                        output_bytes_proof_id.clone(),
+                        expected_bytes_proof_id.to_vec(),
+                    )
+                })?;
+        }
+        (last_index + 1 == config.inner_batch_size * config.outer_batch_size)
+            .then_some(())
+            .ok_or({
+                KeccakCircuitInconsistency::PublicInputNumber(
+                    last_index + 1,
+                    config.inner_batch_size * config.outer_batch_size,
+                )
+            })?;
+        let num_inputs = last_index as usize + 1;
+        let last_input_bytes = query_outputs[..2 * num_inputs]
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .flatten()
+            .copied()
+            .collect_vec();
+        // Each digest in the public output must be the output of a keccak
+        // query.  The submission id queries are followed by the linear
+        // digest query, which is followed by the vk digest query, if any.
+        let num_queries = query_outputs.len();
+        let mut expected_outputs = Vec::new();
+        if config.output_mode.outputs_linear_digest() {
+            let query_index =
+                num_queries - 1 - config.include_vk_digest as usize;
+            expected_outputs.push((keccak256(&last_input_bytes), query_index));
+        }
+        if config.output_mode.outputs_submission_id() {
+            let proof_ids = last_input_bytes
+                .chunks(KECCAK_OUTPUT_BYTES)
+                .map(|chunk| {
+                    <[u8; KECCAK_OUTPUT_BYTES]>::try_from(chunk).expect(
+                        "Conversion from slice into array is not allowed to fail",
+                    )
+                })
+                .collect_vec();
+            let num_proof_ids = self
+                .public_inputs
+                .num_proof_ids
+                .expect(
+                    "num_proof_ids must exist when the circuit outputs submission id",
+                )
+                .value()
+                .get_lower_32() as u64;
+            // The tree has `(last_index + 1).next_power_of_two()` leaves,
+            // and the submission id is the root of the subtree over the
+            // first `num_proof_ids.next_power_of_two()` of them.
+            let depth_diff = (last_index + 1).next_power_of_two().ilog2()
+                - num_proof_ids.next_power_of_two().ilog2();
+            let query_index = num_queries
+                - (2usize.pow(depth_diff + 1) - 1
+                    + config.output_mode.outputs_linear_digest() as usize);
+            expected_outputs.push((
+                compute_submission_id_with_domain_separation(
+                    proof_ids,
+                    num_proof_ids,
+                    config.domain_separated_tree,
+                ),
+                query_index,
+            ));
+        }
+        // The keccak output bytes at each location must match the
+        // expected bytes and the corresponding public output.
+        for ((last_expected_bytes, query_index), public_output) in
+            expected_outputs
+                .into_iter()
+                .zip_eq(self.public_output.chunks(2))
+        {
+            let last_output_bytes = query_outputs[query_index].clone();
+            let public_output = public_output
+                .iter()
+                .map(|field_element| {
+                    field_element
+                        .value()
+                        .to_bytes_le()
+                        .into_iter()
+                        .take(16)
+                        .rev()
+                        .collect_vec()
+                })
+                .collect_vec();
+            let mut output_bytes = public_output[1].clone();
+            output_bytes.extend(public_output[0].iter());
+            (last_output_bytes == output_bytes
+                && last_output_bytes == last_expected_bytes)
+                .then_some(())
+                .ok_or({
+                    KeccakCircuitInconsistency::PublicOutput(
+                        last_output_bytes,
+                        output_bytes,
+                        last_expected_bytes.into(),
+                    )
+                })?;
+        }
+        self.is_vk_digest_well_constructed(&query_outputs, num_inputs)?;
+        self.are_commitment_point_queries_well_constructed(
+            &query_outputs,
+            num_inputs,
+            config.limb_bits,
+            config.num_limbs,
+        )?;
+        Ok(())
+    }
+}
+
+// NOTE: only implemented for F = bn256::Fr, since the implementation relies on
+// digest_as_field_elements, which is field dependent.
+impl<'a> SafeCircuit<'a, Fr, G1Affine> for KeccakCircuit<Fr, G1Affine> {
+    type CircuitConfig = KeccakConfig;
+    type GateConfig = KeccakGateConfig;
+    type CircuitInputs = KeccakCircuitInputs<Fr>;
+    type KeygenInputs = ();
+    type InstanceInputs = KeccakCircuitInputs<Fr>;
+
+    fn mock(
+        config: &Self::CircuitConfig,
+        inputs: &Self::CircuitInputs,
+    ) -> Self {
+        let circuit_inputs =
+            KeccakPaddedCircuitInputs::from_keccak_circuit_inputs(
+                inputs,
+                config.num_app_public_inputs as usize,
+                config.max_commitments as usize,
+                config.limb_bits,
+                config.num_limbs,
+            )
+            .unwrap_or_else(|e| panic!("Invalid commitment point: {e}"));
+        assert!(
+            circuit_inputs.is_well_constructed(config),
+            "Invalid keccak circuit inputs"
+        );
+        Self::new(config, GateThreadBuilder::mock(), circuit_inputs)
+    }
+
+    fn keygen(
+        config: &Self::CircuitConfig,
+        inputs: &Self::KeygenInputs,
+    ) -> Self {
+        let _ = inputs;
+        Self::new(
+            config,
+            GateThreadBuilder::keygen(),
+            KeccakPaddedCircuitInputs::dummy(config),
+        )
+    }
+
+    fn prover(
+        config: &Self::CircuitConfig,
+        gate_config: &Self::GateConfig,
+        break_points: MultiPhaseThreadBreakPoints,
+        inputs: &Self::CircuitInputs,
+    ) -> Self {
+        Self::prover_with_progress(
+            config,
+            gate_config,
+            break_points,
+            inputs,
+            &mut |_| {},
+        )
     }
 
     fn compute_instance(
         config: &Self::CircuitConfig,
         inputs: &Self::InstanceInputs,
     ) -> Vec<Fr> {
-        let (proof_ids, padded_inputs): (
+        let (circuit_ids, proof_ids, padded_inputs): (
+            Vec<[u8; KECCAK_OUTPUT_BYTES]>,
             Vec<[u8; KECCAK_OUTPUT_BYTES]>,
             Vec<KeccakPaddedCircuitInput<Fr>>,
         ) = {
             let input_slice = &inputs.inputs;
-            let proof_ids: Vec<[u8; KECCAK_OUTPUT_BYTES]> = input_slice
+            let circuit_ids: Vec<[u8; KECCAK_OUTPUT_BYTES]> = input_slice
                 .iter()
                 .map(|i| {
-                    let circuit_id =
-                        universal::native::compute_circuit_id(&i.app_vk);
-                    compute_proof_id(&circuit_id, i.app_public_inputs.iter())
+                    universal::native::compute_circuit_id_with_domain_version(
+                        &i.app_vk,
+                        config.domain_tag_version,
+                    )
+                })
+                .collect();
+            let proof_ids: Vec<[u8; KECCAK_OUTPUT_BYTES]> = input_slice
+                .iter()
+                .zip_eq(circuit_ids.iter())
+                .map(|(i, circuit_id)| {
+                    compute_proof_id(circuit_id, i.app_public_inputs.iter())
                 })
                 .collect();
 
             // [
-            //   len_0, vk_limbs_0, has_commitment_0, commitment_hash_0, commitment_limbs_0, padded_inputs_0
-            //   len_1, vk_limbs_1, has_commitment_1, commitment_hash_1, commitment_limbs_1, padded_inputs_1
+            //   len_0, vk_limbs_0, num_commitments_0, commitment_hashes_0, commitment_limbs_0, padded_inputs_0
+            //   len_1, vk_limbs_1, num_commitments_1, commitment_hashes_1, commitment_limbs_1, padded_inputs_1
             //   ...
-            //   final_digest_0, final_digest_1
+            //   (num_proof_ids), (linear_digest_0, linear_digest_1),
+            //   (submission_id_0, submission_id_1), (vk_digest_0, vk_digest_1)
             // ]
             (
+                circuit_ids,
                 proof_ids,
                 input_slice
                     .iter()
@@ -1601,29 +3057,106 @@ impl<'a> SafeCircuit<'a, Fr, G1Affine> for KeccakCircuit<Fr, G1Affine> {
                         KeccakPaddedCircuitInput::from_var_len_input(
                             i,
                             config.num_app_public_inputs as usize,
+                            config.max_commitments as usize,
+                            config.limb_bits,
+                            config.num_limbs,
                         )
+                        .unwrap_or_else(|e| {
+                            panic!("Invalid commitment point: {e}")
+                        })
                     })
                     .collect::<Vec<_>>(),
             )
         };
 
-        let final_digest = match config.output_submission_id {
-            true => {
+        let linear_digest = config
+            .output_mode
+            .outputs_linear_digest()
+            .then(|| compute_final_digest(&proof_ids));
+        let submission_id =
+            config.output_mode.outputs_submission_id().then(|| {
                 let num_proof_ids = inputs.num_proof_ids.expect(
                     "Num proof ids must be provided for the submission id computation",
                 );
-                compute_submission_id(proof_ids, num_proof_ids)
-            }
-            false => compute_final_digest(proof_ids),
-        };
+                compute_submission_id_with_domain_separation(
+                    proof_ids,
+                    num_proof_ids,
+                    config.domain_separated_tree,
+                )
+            });
+        let num_proof_ids = config.public_num_proof_ids.then(|| {
+            assert!(
+                config.output_mode.outputs_submission_id(),
+                "Public num proof ids requires output submission id"
+            );
+            Fr::from(inputs.num_proof_ids.expect("Num proof ids"))
+        });
+        let vk_digest = config.include_vk_digest.then(|| {
+            assert_eq!(
+                config.output_mode,
+                OutputMode::Linear,
+                "VK digest requires linear final digest"
+            );
+            digest_as_field_elements(&compute_vk_digest(circuit_ids))
+        });
 
         padded_inputs
             .iter()
             .flat_map(|i| i.to_instance_values())
-            .chain(digest_as_field_elements(&final_digest).into_iter())
+            .chain(num_proof_ids)
+            .chain(
+                linear_digest
+                    .into_iter()
+                    .chain(submission_id)
+                    .flat_map(|digest| digest_as_field_elements(&digest)),
+            )
+            .chain(vk_digest.into_iter().flatten())
             .collect()
     }
 
+    fn describe_instance(
+        config: &Self::CircuitConfig,
+    ) -> Vec<(String, Range<usize>)> {
+        let num_pub_ins = config.num_app_public_inputs as usize;
+        let max_commitments = config.max_commitments as usize;
+        let num_limbs = config.num_limbs;
+        let num_proofs =
+            (config.inner_batch_size * config.outer_batch_size) as usize;
+        let mut segments = Vec::new();
+        for i in 0..num_proofs {
+            let mut push = |name: &str, len: usize| {
+                push_instance_segment(
+                    &mut segments,
+                    format!("entry{i}.{name}"),
+                    len,
+                )
+            };
+            push("len", 1);
+            push(
+                "vk",
+                padded_vk_limbs_len(num_pub_ins, max_commitments, num_limbs),
+            );
+            push("num_commitments", 1);
+            push("commitment_hashes", max_commitments);
+            // x and y coordinates of each commitment point
+            push("commitment_points", 2 * num_limbs * max_commitments);
+            push("inputs", num_pub_ins);
+        }
+        if config.public_num_proof_ids {
+            push_instance_segment(&mut segments, "num_proof_ids", 1);
+        }
+        if config.output_mode.outputs_linear_digest() {
+            push_instance_segment(&mut segments, "linear_digest", 2);
+        }
+        if config.output_mode.outputs_submission_id() {
+            push_instance_segment(&mut segments, "submission_id", 2);
+        }
+        if config.include_vk_digest {
+            push_instance_segment(&mut segments, "vk_digest", 2);
+        }
+        segments
+    }
+
     fn gate_config(&self) -> &Self::GateConfig {
         &self.config
     }
@@ -1675,6 +3208,20 @@ impl<'a> SafeCircuit<'a, Fr, G1Affine> for KeccakCircuit<Fr, G1Affine> {
         // Read verifying key
         VerifyingKey::read::<_, Self>(reader, SerdeFormat::RawBytesUnchecked)
     }
+
+    fn write_proving_key<W: std::io::Write>(
+        pk: &ProvingKey<G1Affine>,
+        writer: &mut W,
+    ) -> Result<(), std::io::Error> {
+        pk.write(writer, SerdeFormat::RawBytesUnchecked)
+    }
+
+    fn write_verifying_key<W: std::io::Write>(
+        vk: &VerifyingKey<G1Affine>,
+        writer: &mut W,
+    ) -> Result<(), std::io::Error> {
+        vk.write(writer, SerdeFormat::RawBytesUnchecked)
+    }
 }
 
 /// Keccak Circuit Configuration
@@ -1710,8 +3257,15 @@ impl<F: Field> KeccakCircuitConfig<F> {
             degree_bits,
         );
         let keccak = KeccakBaseConfig::new(meta);
-        set_var("UNUSABLE_ROWS", meta.minimum_rows().to_string());
-        range.gate.max_rows = (1 << degree_bits) - meta.minimum_rows();
+        let unusable_rows = params.unusable_rows.unwrap_or(meta.minimum_rows());
+        assert!(
+            unusable_rows >= meta.minimum_rows(),
+            "unusable_rows ({unusable_rows}) is less than the {} rows required \
+             by the constraint system",
+            meta.minimum_rows()
+        );
+        set_var("UNUSABLE_ROWS", unusable_rows.to_string());
+        range.gate.max_rows = (1 << degree_bits) - unusable_rows;
         let instance = meta.instance_column();
         meta.enable_equality(instance);
         Self {
@@ -1750,7 +3304,7 @@ where
         // We later take the builder, so we need to save this value
         let witness_gen_only = self.builder.borrow().witness_gen_only();
         let (assigned_public_inputs, assigned_public_output) =
-            self.synthesize(&config, &mut layouter);
+            self.synthesize(&config, &mut layouter)?;
         if !witness_gen_only {
             // Expose public inputs
             let mut layouter = layouter.namespace(|| "expose");
@@ -1790,6 +3344,7 @@ where
             .to_instance_values()
             .into_iter()
             .chain(self.public_output.iter().cloned())
+            .chain(self.vk_digest.iter().flatten().cloned())
             .map(|assigned| *assigned.value())
             .collect()]
     }
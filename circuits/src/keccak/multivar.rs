@@ -1,7 +1,6 @@
 use crate::{
     keccak::{
         chip::KeccakChip, utils::multi_coordinates_to_bytes, NUM_BYTES_FQ,
-        NUM_LIMBS,
     },
     EccPrimeField,
 };
@@ -15,9 +14,14 @@ const MAX_BIT_SIZE: usize = 32;
 
 /// Keccak hasher that can absorb one fixed-length input and multiple
 /// variable-length inputs. The variable-length inputs are assumed to
-/// be limb decompositions of Fq points, each encoded as 3 non-native
-/// limbs containing 11, 11, and 10 bytes of data, respectively.
+/// be limb decompositions of Fq points, each encoded as `num_limbs`
+/// non-native limbs of `limb_bits` bits (e.g. 3 limbs containing 11, 11,
+/// and 10 bytes of data, respectively, for the default configuration).
 pub(crate) struct KeccakMultiVarHasher<F: EccPrimeField> {
+    /// Size in bits of the limbs of the variable length inputs
+    limb_bits: usize,
+    /// Number of limbs per Fq element of the variable length inputs
+    num_limbs: usize,
     /// Fixed length input, assumed to be bytes
     fixed_input: Vec<AssignedValue<F>>,
     /// Padded variable length inputs, assumed to be limb decompositions of Fq
@@ -32,8 +36,10 @@ pub(crate) struct KeccakMultiVarHasher<F: EccPrimeField> {
 }
 
 impl<F: EccPrimeField<Repr = [u8; 32]>> KeccakMultiVarHasher<F> {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(limb_bits: usize, num_limbs: usize) -> Self {
         Self {
+            limb_bits,
+            num_limbs,
             fixed_input: Vec::new(),
             var_inputs: Vec::new(),
             var_input_lengths: Vec::new(),
@@ -57,15 +63,15 @@ impl<F: EccPrimeField<Repr = [u8; 32]>> KeccakMultiVarHasher<F> {
     /// but only `input[0..len]` will be absorbed.
     ///
     /// Note: `len` is measured in number of F elements, so it will be
-    /// `NUM_LIMBS` times the number of Fq elements in the input.
+    /// `num_limbs` times the number of Fq elements in the input.
     pub(crate) fn absorb_var(
         &mut self,
         input: &[AssignedValue<F>],
         len: AssignedValue<F>,
     ) {
         // Expect input to be a limb decomposition of Fq points
-        assert_eq!(input.len() % NUM_LIMBS, 0);
-        assert_eq!(len.value().get_lower_32() % NUM_LIMBS as u32, 0);
+        assert_eq!(input.len() % self.num_limbs, 0);
+        assert_eq!(len.value().get_lower_32() % self.num_limbs as u32, 0);
 
         // `input.len` at keygen determines the maximum lengths supported by
         // the circuit.  Since the in-circuit constraint on length is
@@ -91,12 +97,8 @@ impl<F: EccPrimeField<Repr = [u8; 32]>> KeccakMultiVarHasher<F> {
 
         // KeccakChip computes digest
         if self.is_fixed() {
-            keccak_chip.keccak_fixed_len(ctx, range, preimage);
             return keccak_chip
-                .fixed_len_queries()
-                .last()
-                .expect("no queries")
-                .output_bytes_assigned()
+                .keccak_fixed_len_output_bytes(ctx, range, preimage)
                 .to_vec();
         } else {
             keccak_chip.keccak_var_len(ctx, range, preimage, len);
@@ -123,7 +125,7 @@ impl<F: EccPrimeField<Repr = [u8; 32]>> KeccakMultiVarHasher<F> {
             .var_inputs
             .iter()
             .map(|x| {
-                assert_eq!(x.len() % NUM_LIMBS, 0);
+                assert_eq!(x.len() % self.num_limbs, 0);
                 x.len()
             })
             .sum();
@@ -163,19 +165,25 @@ impl<F: EccPrimeField<Repr = [u8; 32]>> KeccakMultiVarHasher<F> {
 
         let mut preimage = Vec::with_capacity(
             self.fixed_input.len()
-                + var_preimage_len / NUM_LIMBS * NUM_BYTES_FQ,
+                + var_preimage_len / self.num_limbs * NUM_BYTES_FQ,
         );
         preimage.extend_from_slice(&self.fixed_input);
-        let var_bytes = multi_coordinates_to_bytes(ctx, range, &preimage_var);
+        let var_bytes = multi_coordinates_to_bytes(
+            ctx,
+            range,
+            &preimage_var,
+            self.limb_bits,
+            self.num_limbs,
+        );
         assert_eq!(
             var_bytes.len(),
-            var_preimage_len / NUM_LIMBS * NUM_BYTES_FQ
+            var_preimage_len / self.num_limbs * NUM_BYTES_FQ
         );
         preimage.extend_from_slice(&var_bytes);
 
         // Given the length in limbs, compute the length of the variable part of the preimage in bytes.
 
-        let limbs_per_fq = ctx.load_constant(F::from(NUM_LIMBS as u64));
+        let limbs_per_fq = ctx.load_constant(F::from(self.num_limbs as u64));
         let var_input_fq_length =
             range.gate.div_unsafe(ctx, offset_limbs, limbs_per_fq);
         // Overly conservative constraint to ensure division didn't overflow.
@@ -202,7 +210,8 @@ impl<F: EccPrimeField<Repr = [u8; 32]>> KeccakMultiVarHasher<F> {
 /// `max_nonzero_position` in `dest`.
 ///
 /// Note: This measures length and offset in F elements, so `len` is
-/// `NUM_LIMBS` times the number of Fq elements in the source.
+/// the number of limbs per Fq element times the number of Fq elements in the
+/// source.
 fn add_slice_at_offset<F: EccPrimeField>(
     ctx: &mut Context<F>,
     range: &RangeChip<F>,
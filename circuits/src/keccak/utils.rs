@@ -1,16 +1,13 @@
 //! Some `KeccakCircuit`-related utility functions.
 
 use super::{
-    KeccakCircuitInputs, KeccakVarLenInput, KECCAK_OUTPUT_BYTES, LIMB_BITS,
-    NUM_BYTES_FQ, NUM_LIMBS,
+    KeccakCircuitInputs, KeccakVarLenInput, KECCAK_OUTPUT_BYTES, NUM_BYTES_FQ,
+    NUM_LIMBS,
 };
 use crate::{
-    keccak::{
-        KeccakCircuit, KeccakConfig, KeccakGateConfig, PaddedVerifyingKeyLimbs,
-    },
-    utils::{
-        commitment_point::limbs_into_g1affine, keccak_hasher::KeccakHasher,
-    },
+    batch_verify::universal::types::parse_ubv_instance,
+    keccak::{KeccakCircuit, KeccakConfig, KeccakGateConfig},
+    utils::keccak_hasher::KeccakHasher,
     EccPrimeField, SafeCircuit,
 };
 use core::{borrow::Borrow, iter};
@@ -34,9 +31,9 @@ use halo2_base::{
     AssignedValue, Context, QuantumCell,
 };
 use itertools::Itertools;
-use log::info;
 use snark_verifier_sdk::{gen_pk, halo2::gen_snark, Snark};
 use tiny_keccak::{Hasher, Keccak};
+use tracing::info;
 use zkevm_keccak::util::{NUM_WORDS_TO_ABSORB, RATE_IN_BITS};
 
 /// Byte size in bits
@@ -265,44 +262,57 @@ where
         .collect()
 }
 
-/// Converts [`NUM_LIMBS`] coordinate limbs to bytes, interpreted as the
-/// big-endian byte representation of an `Fq` point. Each element of the
-/// output is constrained to be a byte. Currently this function assumes
-/// `LIMB_BITS = 88`, `NUM_LIMBS = 3`.
-#[allow(clippy::assertions_on_constants)]
+/// Converts `num_limbs` coordinate limbs of `limb_bits` bits to bytes,
+/// interpreted as the big-endian byte representation of an `Fq` point. Each
+/// element of the output is constrained to be a byte. `limb_bits` must be a
+/// multiple of 8, and the limbs must hold exactly [`NUM_BYTES_FQ`] bytes,
+/// with a non-empty most significant limb.
 fn coordinate_limbs_to_bytes<F>(
     ctx: &mut Context<F>,
     chip: &RangeChip<F>,
     coordinate_limbs: &[AssignedValue<F>],
+    limb_bits: usize,
+    num_limbs: usize,
 ) -> Vec<AssignedValue<F>>
 where
     F: EccPrimeField,
 {
-    assert!(
-        LIMB_BITS == 88 && NUM_LIMBS == 3,
-        "Unsupported limb decomposition"
-    );
-    assert_eq!(coordinate_limbs.len(), NUM_LIMBS, "Wrong number of limbs");
+    assert_eq!(coordinate_limbs.len(), num_limbs, "Wrong number of limbs");
+    assert_eq!(limb_bits % 8, 0, "limb bits must be a multiple of 8");
     // Note: All byte decompositions are big-endian, however the arrangement
     // of limbs is least-to-most significant.
-    // For `NUM_LIMBS = 3, LIMB_BITS = 88`:
+    // For `num_limbs = 3, limb_bits = 88`:
     // The big-endian bytes [b_0, ... , b_31] of a field element
     // are arranged in the limbs as
     // [0, ..., 0, b_21,  ... b_31]
     // [0, ..., 0, b_10,  ... b_20]
     // [0, ..., 0, 0, b_0 ... b_9]
-    // (assuming `num_limbs = 3`, `limb_bits = 88`)
     // In the first two limbs, we take the last 11 bytes from each limb.
     // In the third limb, we take only the last 10 bytes.
+    // In general, we take the last `limb_bits / 8` bytes of all limbs but
+    // the most significant one, which holds the remaining bytes.
+    let limb_bytes = limb_bits / 8;
+    let last_limb_bytes = num_limbs
+        .checked_sub(1)
+        .and_then(|n| NUM_BYTES_FQ.checked_sub(n * limb_bytes))
+        .filter(|bytes| 0 < *bytes && *bytes <= limb_bytes)
+        .unwrap_or_else(|| {
+            panic!(
+                "Unsupported limb decomposition: limb_bits = {limb_bits}, \
+                 num_limbs = {num_limbs}"
+            )
+        });
 
-    // num_zeroes takes the .rev() into account
-    let num_zeroes = [22, 21, 21];
+    // num_bytes takes the .rev() into account
+    let num_bytes = iter::once(last_limb_bytes)
+        .chain(iter::repeat(limb_bytes).take(num_limbs - 1));
     let limbwise_bytes = coordinate_limbs
         .iter()
         .rev()
-        .zip_eq(num_zeroes)
-        .flat_map(|(limb, num_zeroes)| {
+        .zip_eq(num_bytes)
+        .flat_map(|(limb, num_bytes)| {
             let limb_byte_decomposition = byte_decomposition(ctx, chip, limb);
+            let num_zeroes = limb_byte_decomposition.len() - num_bytes;
             limb_byte_decomposition.into_iter().skip(num_zeroes)
         })
         .collect_vec();
@@ -316,52 +326,66 @@ where
 }
 
 /// Converts `g1_point_limbs` to bytes, where `g1_point_limbs`
-/// is the limb representation of a G1 affine point. Each element of the output
+/// is the limb representation of a G1 affine point, with `num_limbs` limbs
+/// of `limb_bits` bits per coordinate. Each element of the output
 /// is constrained to be a byte.
 pub fn g1_point_limbs_to_bytes<F>(
     ctx: &mut Context<F>,
     chip: &RangeChip<F>,
     g1_point_limbs: &[AssignedValue<F>],
+    limb_bits: usize,
+    num_limbs: usize,
 ) -> Vec<AssignedValue<F>>
 where
     F: EccPrimeField,
 {
-    assert_eq!(g1_point_limbs.len(), NUM_LIMBS * 2, "Wrong number of limbs");
-    multi_coordinates_to_bytes(ctx, chip, g1_point_limbs)
+    assert_eq!(g1_point_limbs.len(), num_limbs * 2, "Wrong number of limbs");
+    multi_coordinates_to_bytes(ctx, chip, g1_point_limbs, limb_bits, num_limbs)
 }
 
 /// Converts `g2_point_limbs` to bytes, where `g2_point_limbs`
-/// is the limb representation of a G1 affine point. Each element of the output
+/// is the limb representation of a G2 affine point, with `num_limbs` limbs
+/// of `limb_bits` bits per coordinate. Each element of the output
 /// is constrained to be a byte.
 pub fn g2_point_limbs_to_bytes<F>(
     ctx: &mut Context<F>,
     chip: &RangeChip<F>,
     g2_point_limbs: &[AssignedValue<F>],
+    limb_bits: usize,
+    num_limbs: usize,
 ) -> Vec<AssignedValue<F>>
 where
     F: EccPrimeField,
 {
-    assert_eq!(g2_point_limbs.len(), NUM_LIMBS * 4, "Wrong number of limbs");
-    multi_coordinates_to_bytes(ctx, chip, g2_point_limbs)
+    assert_eq!(g2_point_limbs.len(), num_limbs * 4, "Wrong number of limbs");
+    multi_coordinates_to_bytes(ctx, chip, g2_point_limbs, limb_bits, num_limbs)
 }
 
-/// Converts `limbs` to bytes, where each chunk of [`NUM_LIMBS`] limbs is
-/// understood as an `FQ` element.
+/// Converts `limbs` to bytes, where each chunk of `num_limbs` limbs of
+/// `limb_bits` bits is understood as an `FQ` element.
 pub(crate) fn multi_coordinates_to_bytes<F>(
     ctx: &mut Context<F>,
     chip: &RangeChip<F>,
     limbs: &[AssignedValue<F>],
+    limb_bits: usize,
+    num_limbs: usize,
 ) -> Vec<AssignedValue<F>>
 where
     F: EccPrimeField,
 {
-    assert_eq!(limbs.len() % NUM_LIMBS, 0);
+    assert_eq!(limbs.len() % num_limbs, 0);
     limbs
         .iter()
-        .chunks(NUM_LIMBS)
+        .chunks(num_limbs)
         .into_iter()
         .flat_map(|chunk| {
-            coordinate_limbs_to_bytes(ctx, chip, &chunk.copied().collect_vec())
+            coordinate_limbs_to_bytes(
+                ctx,
+                chip,
+                &chunk.copied().collect_vec(),
+                limb_bits,
+                num_limbs,
+            )
         })
         .collect()
 }
@@ -397,25 +421,81 @@ pub fn compute_final_digest(
     output
 }
 
-/// Computes the Merkle leaf corresponding to `proof_id`.
+/// Computes the same digest as [`compute_final_digest`], with proof ids
+/// given one at a time, so that they need not all be held in memory.
+pub struct FinalDigestHasher(Keccak);
+
+impl FinalDigestHasher {
+    pub fn new() -> Self {
+        FinalDigestHasher(Keccak::v256())
+    }
+
+    /// Appends `proof_id` to the proof ids absorbed so far.
+    pub fn update(&mut self, proof_id: &[u8; KECCAK_OUTPUT_BYTES]) {
+        self.0.update(proof_id);
+    }
+
+    /// Returns the final digest of the absorbed proof ids.
+    pub fn finalize(self) -> [u8; KECCAK_OUTPUT_BYTES] {
+        let mut output = [0u8; KECCAK_OUTPUT_BYTES];
+        self.0.finalize(&mut output);
+        output
+    }
+}
+
+impl Default for FinalDigestHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Concatenate all `circuit_ids` and return their Keccak digest (the "vk
+/// digest").  Exposed by the `KeccakCircuit` when
+/// `KeccakConfig::include_vk_digest` is set.
+pub fn compute_vk_digest(
+    circuit_ids: impl IntoIterator<Item = impl Borrow<[u8; KECCAK_OUTPUT_BYTES]>>,
+) -> [u8; KECCAK_OUTPUT_BYTES] {
+    compute_final_digest(circuit_ids)
+}
+
+/// Byte prepended to the proof id when computing a Merkle leaf of a
+/// domain-separated submission tree.
+pub const MERKLE_LEAF_DOMAIN_TAG: u8 = 0x00;
+
+/// Byte prepended to the children when computing an internal node of a
+/// domain-separated submission tree.
+pub const MERKLE_NODE_DOMAIN_TAG: u8 = 0x01;
+
+/// Computes the Merkle leaf corresponding to `proof_id`.  If
+/// `domain_separated`, the proof id is prefixed with
+/// [`MERKLE_LEAF_DOMAIN_TAG`].
 fn compute_leaf(
     proof_id: impl Borrow<[u8; KECCAK_OUTPUT_BYTES]>,
+    domain_separated: bool,
 ) -> [u8; KECCAK_OUTPUT_BYTES] {
     let mut leaf = [0u8; KECCAK_OUTPUT_BYTES];
     let mut hasher = Keccak::v256();
+    if domain_separated {
+        hasher.update(&[MERKLE_LEAF_DOMAIN_TAG]);
+    }
     hasher.update(proof_id.borrow());
     hasher.finalize(&mut leaf);
     leaf
 }
 
-/// Computes the keccak hash of `left` and `right`.
+/// Computes the keccak hash of `left` and `right`.  If `domain_separated`,
+/// they are prefixed with [`MERKLE_NODE_DOMAIN_TAG`].
 fn hash_pair(
     left: &[u8; KECCAK_OUTPUT_BYTES],
     right: &[u8; KECCAK_OUTPUT_BYTES],
+    domain_separated: bool,
 ) -> [u8; KECCAK_OUTPUT_BYTES] {
     let mut output = [0u8; KECCAK_OUTPUT_BYTES];
     let mut hasher = Keccak::v256();
 
+    if domain_separated {
+        hasher.update(&[MERKLE_NODE_DOMAIN_TAG]);
+    }
     hasher.update(left);
     hasher.update(right);
 
@@ -425,49 +505,167 @@ fn hash_pair(
 
 /// Hashes the elements of `row` by pairs.
 fn hash_row(
-    row: Vec<[u8; KECCAK_OUTPUT_BYTES]>,
+    row: &[[u8; KECCAK_OUTPUT_BYTES]],
+    domain_separated: bool,
 ) -> Vec<[u8; KECCAK_OUTPUT_BYTES]> {
-    let mut next_row = Vec::new();
-    let row_len = row.len();
-    for i in (0..row_len).step_by(2) {
-        next_row.push(hash_pair(&row[i], &row[i + 1]));
+    row.chunks(2)
+        .map(|pair| hash_pair(&pair[0], &pair[1], domain_separated))
+        .collect()
+}
+
+/// The submission id Merkle tree, holding every level from the (padded)
+/// leaves up to the root.  See [`build_submission_tree`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SubmissionTree {
+    /// Levels of the tree, starting from the leaves.  The last level holds
+    /// only the root.
+    levels: Vec<Vec<[u8; KECCAK_OUTPUT_BYTES]>>,
+}
+
+impl SubmissionTree {
+    /// Returns the root of the tree, i.e. the submission id.
+    pub fn root(&self) -> [u8; KECCAK_OUTPUT_BYTES] {
+        self.levels
+            .last()
+            .expect("Submission tree has at least one level")[0]
+    }
+
+    /// Returns the number of leaves, including the padding leaves.  This is
+    /// always a power of two.
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Always `false`: the tree has at least one leaf.
+    pub fn is_empty(&self) -> bool {
+        self.levels[0].is_empty()
+    }
+
+    /// Returns the Merkle inclusion path of the leaf at `index` (which may
+    /// be a padding leaf), as the sibling nodes from the leaf level up.
+    pub fn path(&self, index: usize) -> Vec<[u8; KECCAK_OUTPUT_BYTES]> {
+        assert!(index < self.len(), "leaf index out of range");
+        let mut index = index;
+        let mut path = Vec::with_capacity(self.levels.len() - 1);
+        for level in &self.levels[..self.levels.len() - 1] {
+            path.push(level[index ^ 1]);
+            index /= 2;
+        }
+        path
+    }
+}
+
+/// Builds the submission id Merkle tree of the first `num_proof_ids` entries
+/// of `proof_ids`.  The tree is built over the leaves of these proof ids,
+/// padded with the zero leaf (the leaf of the zero proof id) to the next
+/// power of two.  `proof_ids` may have any length, as long as it contains at
+/// least `num_proof_ids` entries.  This matches the in-circuit computation,
+/// where the padded proof ids are also extended to a power of two.
+pub fn build_submission_tree(
+    proof_ids: &[[u8; KECCAK_OUTPUT_BYTES]],
+    num_proof_ids: u64,
+) -> SubmissionTree {
+    build_submission_tree_with_domain_separation(
+        proof_ids,
+        num_proof_ids,
+        false,
+    )
+}
+
+/// Like [`build_submission_tree`], but if `domain_separated`, leaves and
+/// internal nodes are hashed with distinct prefixes
+/// ([`MERKLE_LEAF_DOMAIN_TAG`] and [`MERKLE_NODE_DOMAIN_TAG`]), so that an
+/// internal node can never be presented as a leaf.  This matches the
+/// in-circuit computation when `KeccakConfig::domain_separated_tree` is set.
+pub fn build_submission_tree_with_domain_separation(
+    proof_ids: &[[u8; KECCAK_OUTPUT_BYTES]],
+    num_proof_ids: u64,
+    domain_separated: bool,
+) -> SubmissionTree {
+    let num_proof_ids = num_proof_ids as usize;
+    assert!(proof_ids.len() >= num_proof_ids, "not enough proof ids");
+    let leaves = proof_ids[..num_proof_ids]
+        .iter()
+        .copied()
+        .chain(iter::repeat([0u8; KECCAK_OUTPUT_BYTES]))
+        .take(num_proof_ids.next_power_of_two())
+        .map(|proof_id| compute_leaf(proof_id, domain_separated))
+        .collect_vec();
+    let mut levels = vec![leaves];
+    while levels.last().expect("Levels are never empty").len() > 1 {
+        let next_level = hash_row(
+            levels.last().expect("Levels are never empty"),
+            domain_separated,
+        );
+        levels.push(next_level);
     }
-    next_row
+
+    SubmissionTree { levels }
 }
 
-/// Computes the submission id corresponding to `proof_ids`.
+/// Computes the submission id corresponding to the first `num_proof_ids`
+/// entries of `proof_ids`, i.e. the root of their [`build_submission_tree`].
 pub fn compute_submission_id(
     proof_ids: impl IntoIterator<Item = impl Borrow<[u8; KECCAK_OUTPUT_BYTES]>>,
     num_proof_ids: u64,
 ) -> [u8; KECCAK_OUTPUT_BYTES] {
-    let num_proof_ids = num_proof_ids as usize;
-    let next_power_of_two = num_proof_ids.next_power_of_two();
+    compute_submission_id_with_domain_separation(
+        proof_ids,
+        num_proof_ids,
+        false,
+    )
+}
+
+/// Like [`compute_submission_id`], for the tree built by
+/// [`build_submission_tree_with_domain_separation`].
+pub fn compute_submission_id_with_domain_separation(
+    proof_ids: impl IntoIterator<Item = impl Borrow<[u8; KECCAK_OUTPUT_BYTES]>>,
+    num_proof_ids: u64,
+    domain_separated: bool,
+) -> [u8; KECCAK_OUTPUT_BYTES] {
     let proof_ids = proof_ids
         .into_iter()
         .map(|proof_id| *proof_id.borrow())
         .collect_vec();
-    let num_leaves = proof_ids.len();
-    let proof_ids = proof_ids
-        .into_iter()
-        .take(num_proof_ids)
-        .chain(
-            iter::repeat([0u8; KECCAK_OUTPUT_BYTES])
-                .take(next_power_of_two - num_proof_ids),
-        )
-        .collect_vec();
-    let mut current_row = proof_ids.into_iter().map(compute_leaf).collect_vec();
-    assert!(num_leaves >= next_power_of_two, "not enough leaves");
-    assert_eq!(
-        (num_leaves & (num_leaves - 1)),
-        0,
-        "The number of leaves must be a power of two in submission id mode"
-    );
+    build_submission_tree_with_domain_separation(
+        &proof_ids,
+        num_proof_ids,
+        domain_separated,
+    )
+    .root()
+}
 
-    while current_row.len() > 1 {
-        current_row = hash_row(current_row);
-    }
+/// Computes the Merkle inclusion path of `proof_ids[index]` in the
+/// submission id Merkle tree of `proof_ids` (see [`build_submission_tree`]).
+/// Returns the sibling nodes, from the leaf level up, and the root (equal to
+/// `compute_submission_id(proof_ids, proof_ids.len())`).
+pub fn compute_merkle_path(
+    proof_ids: &[[u8; KECCAK_OUTPUT_BYTES]],
+    index: usize,
+) -> (Vec<[u8; KECCAK_OUTPUT_BYTES]>, [u8; KECCAK_OUTPUT_BYTES]) {
+    assert!(index < proof_ids.len(), "proof id index out of range");
+    let tree = build_submission_tree(proof_ids, proof_ids.len() as u64);
+    (tree.path(index), tree.root())
+}
 
-    current_row[0]
+/// Computes the Merkle root from `proof_id`, its `index` in the submission
+/// and its Merkle inclusion `path` (see [`compute_merkle_path`]).
+pub fn compute_merkle_root_from_path(
+    proof_id: &[u8; KECCAK_OUTPUT_BYTES],
+    index: usize,
+    path: &[[u8; KECCAK_OUTPUT_BYTES]],
+) -> [u8; KECCAK_OUTPUT_BYTES] {
+    let mut index = index;
+    let mut node = compute_leaf(proof_id, false);
+    for sibling in path {
+        node = match index % 2 {
+            0 => hash_pair(&node, sibling, false),
+            _ => hash_pair(sibling, &node, false),
+        };
+        index /= 2;
+    }
+    assert_eq!(index, 0, "proof id index out of range");
+    node
 }
 
 /// Compute the representation of a 32-byte Keccak digest as a pair of field
@@ -686,103 +884,77 @@ pub fn compute_next_power_of_two_bit_decomposition<F: EccPrimeField>(
 }
 
 /// The number of public inputs each application proof
-/// contributes to the keccak circuit.
+/// contributes to the keccak circuit (and to the UBV circuit), for a single
+/// commitment and the default limb configuration.
 pub(crate) fn inputs_per_application_proof(num_pub_ins: usize) -> usize {
+    inputs_per_application_proof_with_commitments(num_pub_ins, 1, NUM_LIMBS)
+}
+
+/// The number of public inputs each application proof
+/// contributes to the keccak circuit, for up to `max_commitments`
+/// commitments and `num_limbs` limbs per `Fq` coordinate.
+pub(crate) fn inputs_per_application_proof_with_commitments(
+    num_pub_ins: usize,
+    max_commitments: usize,
+    num_limbs: usize,
+) -> usize {
     // Keccak inputs contain for each application proof:
-    // len_i, vk_limbs_i, has_commitment_i, commitment_hash_i, commitment_limbs_i, padded_inputs_i
+    // len_i, vk_limbs_i, num_commitments_i, commitment_hashes_i, commitment_limbs_i, padded_inputs_i
     num_pub_ins
-        + 3 // len + has_commitment + commitment_hash
-        + NUM_LIMBS
-            * (NUM_FQ_PER_G1AFFINE * 3 // alpha + s[0] + commitment_point
-                + NUM_FQ_PER_G2AFFINE * 5 // beta + gamma + delta + h1 + h2
+        + 2 // len + num_commitments
+        + max_commitments // commitment_hashes
+        + num_limbs
+            * (NUM_FQ_PER_G1AFFINE * 2 // alpha + s[0]
+                + NUM_FQ_PER_G1AFFINE * max_commitments // commitment_points
+                + NUM_FQ_PER_G2AFFINE * 3 // beta + gamma + delta
+                + NUM_FQ_PER_G2AFFINE * 2 * max_commitments // h1 + h2
                 + NUM_FQ_PER_G1AFFINE * num_pub_ins // s[1..]
                 )
 }
 
+/// The number of limbs in the (flattened) [`PaddedVerifyingKeyLimbs`] of a
+/// VK padded to `num_pub_ins` public inputs and `max_commitments` commitment
+/// keys, with `num_limbs` limbs per `Fq` coordinate.
+///
+/// [`PaddedVerifyingKeyLimbs`]: super::PaddedVerifyingKeyLimbs
+pub(crate) fn padded_vk_limbs_len(
+    num_pub_ins: usize,
+    max_commitments: usize,
+    num_limbs: usize,
+) -> usize {
+    num_limbs
+        * (NUM_FQ_PER_G1AFFINE * (num_pub_ins + 2) // alpha + s
+            + NUM_FQ_PER_G2AFFINE * (3 + 2 * max_commitments)) // beta + gamma + delta + h1 + h2
+}
+
 /// Given slice of `UniversalBatchVerifyCircuit` instances,
-/// compute the appropriate keccak inputs.
+/// compute the appropriate keccak inputs.  The UBV instances are assumed to
+/// use the default limb configuration ([`LIMB_BITS`](super::LIMB_BITS),
+/// [`NUM_LIMBS`]).
 pub fn keccak_inputs_from_ubv_instances<'a>(
     ubv_instances: impl ExactSizeIterator<Item = &'a [Fr]>,
     max_num_public_inputs: usize,
     inner_batch_size: usize,
 ) -> Vec<KeccakVarLenInput<Fr>> {
-    let inputs_per_proof = inputs_per_application_proof(max_num_public_inputs);
-
     let mut keccak_inputs =
-        Vec::with_capacity(ubv_instances.len() * inputs_per_proof);
+        Vec::with_capacity(ubv_instances.len() * inner_batch_size);
     for instance in ubv_instances {
-        assert_eq!(
-            instance.len(),
-            inputs_per_proof * inner_batch_size,
-            "UBV instance length inconsistent with configuration"
-        );
-        for mut app_inputs in &instance.iter().chunks(inputs_per_proof) {
-            let len = app_inputs
-                .next()
-                .expect("Missing input length")
-                .get_lower_32() as usize;
-            let app_vk_vec = app_inputs
-                .by_ref()
-                .take(NUM_LIMBS * (24 + 2 * max_num_public_inputs))
-                .copied()
-                .collect_vec();
-            let has_commitment =
-                *app_inputs.next().expect("Missing has commitment flag");
-            let _ = *app_inputs.next().expect("Missing commitment hash");
-            let commitment_point_limbs = app_inputs
-                .by_ref()
-                .take(NUM_LIMBS * 2)
-                .copied()
-                .collect_vec();
-            let app_public_inputs =
-                app_inputs.by_ref().take(len).copied().collect_vec();
-            assert_eq!(
-                commitment_point_limbs.len(),
-                NUM_LIMBS * 2,
-                "Missing commitment point limbs"
-            );
-            assert_eq!(app_public_inputs.len(), len, "Missing public inputs");
-
-            // If the lth public input coincides with the commitment
-            // hash, then this input has a commitment.
-            const ZERO: Fr = Fr::zero();
-            const ONE: Fr = Fr::one();
-            let commitment_point_coordinates = {
-                match has_commitment {
-                    ZERO => vec![],
-                    ONE => {
-                        let commitment_point = limbs_into_g1affine(
-                            &commitment_point_limbs,
-                            LIMB_BITS,
-                            NUM_LIMBS,
-                        );
-                        vec![[commitment_point.x, commitment_point.y]]
-                    }
-                    _ => panic!("has commitment can only be 0 or 1"),
-                }
-            };
-            let has_commitment_bool = match has_commitment {
-                ZERO => 0,
-                _ => 1,
-            };
-            let len_s = len + 1 + has_commitment_bool;
-            let mut app_vk = PaddedVerifyingKeyLimbs::from_limbs(
-                &app_vk_vec,
-                max_num_public_inputs + 1,
+        keccak_inputs.extend(
+            parse_ubv_instance(
+                instance,
+                max_num_public_inputs,
+                inner_batch_size,
             )
-            .vk();
-            app_vk.s.drain(len_s..);
-            if has_commitment_bool == 0 {
-                app_vk.h1 = Vec::new();
-                app_vk.h2 = Vec::new();
-            }
-
-            keccak_inputs.push(KeccakVarLenInput {
-                app_vk,
-                app_public_inputs,
-                commitment_point_coordinates,
-            })
-        }
+            .into_iter()
+            .map(|entry| KeccakVarLenInput {
+                app_vk: entry.vk,
+                app_public_inputs: entry.public_inputs.0,
+                commitment_point_coordinates: entry
+                    .commitment_point
+                    .map(|point| vec![[point.x, point.y]])
+                    .unwrap_or_default(),
+            }),
+        );
     }
 
     keccak_inputs
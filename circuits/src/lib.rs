@@ -11,10 +11,14 @@ use halo2_base::{
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
     fmt,
-    fs::OpenOptions,
-    io::{BufReader, Read},
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Cursor, Read},
+    ops::Range,
+};
+use utils::{
+    file::{load_json, mmap_file_for_read},
+    upa_config::UpaConfig,
 };
-use utils::upa_config::UpaConfig;
 
 // For simplicity, we use this trait even in situations
 // where `halo2_base::utils::ScalarField` would suffice.
@@ -33,6 +37,10 @@ const DEFAULT_NUM_LIMBS: usize = 3;
 
 const DEFAULT_LIMB_BITS: usize = 88;
 
+/// Number of bits of the BN254 base field modulus, which the limbs of a
+/// [`CircuitWithLimbsConfig`] must be able to hold.
+const FQ_NUM_BITS: usize = 254;
+
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 /// Configuration parameters for a axiom-halo2 circuit (where degree is
 /// specified, and numbers of columns are computed).
@@ -77,6 +85,49 @@ impl CircuitWithLimbsConfig {
         }
     }
 
+    /// Returns a [`CircuitWithLimbsConfigBuilder`] for a circuit of degree
+    /// `2^degree_bits`.
+    pub fn builder(degree_bits: u32) -> CircuitWithLimbsConfigBuilder {
+        CircuitWithLimbsConfigBuilder::new(degree_bits)
+    }
+
+    /// Panics unless `self` uses DEFAULT_LIMB_BITS and DEFAULT_NUM_LIMBS.
+    ///
+    /// # Note
+    ///
+    /// The Keccak circuit (see [`keccak::LIMB_BITS`] and
+    /// [`keccak::NUM_LIMBS`]) and the instance layouts derived from it assume
+    /// the default limb configuration.  This must be called wherever those
+    /// constants are combined with limbs computed from `self`, until the limb
+    /// configuration is fully parameterized.
+    pub fn assert_default_limbs(&self) {
+        assert!(
+            self.limb_bits == DEFAULT_LIMB_BITS
+                && self.num_limbs == DEFAULT_NUM_LIMBS,
+            "Unsupported limb configuration: limb_bits = {}, num_limbs = {}. \
+             The Keccak circuit and instance layouts currently assume \
+             limb_bits = {DEFAULT_LIMB_BITS} and num_limbs = \
+             {DEFAULT_NUM_LIMBS}",
+            self.limb_bits,
+            self.num_limbs,
+        );
+    }
+
+    /// Read a `CircuitWithLimbsConfig` (with no other fields) from the JSON
+    /// file `path`.
+    pub fn from_file(path: &str) -> Self {
+        load_json(path)
+    }
+
+    /// Write this config as JSON to the file `path`, overwriting any
+    /// existing file.
+    pub fn to_file(&self, path: &str) {
+        let f = File::create(path)
+            .unwrap_or_else(|e| panic!("failed to create file {path}: {e}"));
+        serde_json::to_writer_pretty(BufWriter::new(f), self)
+            .unwrap_or_else(|e| panic!("failed to write {path}: {e}"));
+    }
+
     fn read_from_str(s: &str) -> Self {
         serde_json::from_str(s).unwrap_or_else(|e| {
             panic!("invalid CircuitWithLimbsConfig json: {e}")
@@ -117,6 +168,123 @@ impl fmt::Display for CircuitWithLimbsConfig {
     }
 }
 
+/// Reason for which [`CircuitWithLimbsConfigBuilder::build`] rejected a
+/// configuration.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConfigError {
+    /// `lookup_bits` is not strictly smaller than `degree_bits`.
+    LookupBitsTooLarge {
+        lookup_bits: usize,
+        degree_bits: u32,
+    },
+    /// `num_limbs` is zero.
+    ZeroLimbs,
+    /// The limbs cannot hold an `Fq` element, i.e. `limb_bits * num_limbs`
+    /// is smaller than the size of the modulus.
+    LimbsTooSmall { limb_bits: usize, num_limbs: usize },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::LookupBitsTooLarge {
+                lookup_bits,
+                degree_bits,
+            } => write!(
+                f,
+                "lookup bits {lookup_bits} must be smaller than degree bits \
+                 {degree_bits}"
+            ),
+            ConfigError::ZeroLimbs => write!(f, "num limbs must be positive"),
+            ConfigError::LimbsTooSmall {
+                limb_bits,
+                num_limbs,
+            } => write!(
+                f,
+                "{num_limbs} limbs of {limb_bits} bits cannot hold \
+                 {FQ_NUM_BITS}-bit field elements"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Builder for a [`CircuitWithLimbsConfig`], checking the configuration on
+/// [`build`](Self::build).  Unless set, the lookup bits are `degree_bits -
+/// 1` and the limb configuration uses DEFAULT_LIMB_BITS and
+/// DEFAULT_NUM_LIMBS, as in [`CircuitWithLimbsConfig::from_degree_bits`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CircuitWithLimbsConfigBuilder {
+    degree_bits: u32,
+    lookup_bits: Option<usize>,
+    limb_bits: usize,
+    num_limbs: usize,
+}
+
+impl CircuitWithLimbsConfigBuilder {
+    /// Creates a builder for a circuit of degree `2^degree_bits`.
+    pub fn new(degree_bits: u32) -> Self {
+        Self {
+            degree_bits,
+            lookup_bits: None,
+            limb_bits: DEFAULT_LIMB_BITS,
+            num_limbs: DEFAULT_NUM_LIMBS,
+        }
+    }
+
+    pub fn degree_bits(mut self, degree_bits: u32) -> Self {
+        self.degree_bits = degree_bits;
+        self
+    }
+
+    pub fn lookup_bits(mut self, lookup_bits: usize) -> Self {
+        self.lookup_bits = Some(lookup_bits);
+        self
+    }
+
+    pub fn limb_bits(mut self, limb_bits: usize) -> Self {
+        self.limb_bits = limb_bits;
+        self
+    }
+
+    pub fn num_limbs(mut self, num_limbs: usize) -> Self {
+        self.num_limbs = num_limbs;
+        self
+    }
+
+    /// Returns the [`CircuitWithLimbsConfig`], or an error if:
+    /// - `lookup_bits >= degree_bits`,
+    /// - `num_limbs` is zero, or
+    /// - `limb_bits * num_limbs` is too small to hold an `Fq` element.
+    pub fn build(self) -> Result<CircuitWithLimbsConfig, ConfigError> {
+        let lookup_bits = self
+            .lookup_bits
+            .unwrap_or_else(|| self.degree_bits.saturating_sub(1) as usize);
+        if lookup_bits >= self.degree_bits as usize {
+            return Err(ConfigError::LookupBitsTooLarge {
+                lookup_bits,
+                degree_bits: self.degree_bits,
+            });
+        }
+        if self.num_limbs == 0 {
+            return Err(ConfigError::ZeroLimbs);
+        }
+        if self.limb_bits.saturating_mul(self.num_limbs) < FQ_NUM_BITS {
+            return Err(ConfigError::LimbsTooSmall {
+                limb_bits: self.limb_bits,
+                num_limbs: self.num_limbs,
+            });
+        }
+        Ok(CircuitWithLimbsConfig {
+            degree_bits: self.degree_bits,
+            lookup_bits,
+            limb_bits: self.limb_bits,
+            num_limbs: self.num_limbs,
+        })
+    }
+}
+
 /// Circuit interface. This trait provides methods to build circuits for both
 /// keygen and proving such that the caller does not need to handle
 /// the underlying environment variables.
@@ -176,6 +344,14 @@ where
         inputs: &Self::InstanceInputs,
     ) -> Vec<F>;
 
+    /// Returns the layout of the instance for `config`, as a list of named
+    /// segments (e.g. `("entry0.len", 0..1)`) which, in order, cover the
+    /// whole instance.  Intended for debugging instance mismatches (see
+    /// [`format_instance_layout`]).
+    fn describe_instance(
+        config: &Self::CircuitConfig,
+    ) -> Vec<(String, Range<usize>)>;
+
     /// Returns the gate configuration.  Intended to be called after the
     /// circuit is initialized using `keygen`, where `GateConfig` should be
     /// stored and used for `prover` operations.
@@ -213,4 +389,76 @@ where
     ) -> Result<VerifyingKey<C>, std::io::Error>
     where
         R: std::io::Read;
+
+    /// Writes a proving key in the format expected by
+    /// [`read_proving_key`](SafeCircuit::read_proving_key).
+    fn write_proving_key<W>(
+        pk: &ProvingKey<C>,
+        writer: &mut W,
+    ) -> Result<(), std::io::Error>
+    where
+        W: std::io::Write;
+
+    /// Writes a verification key in the format expected by
+    /// [`read_verifying_key`](SafeCircuit::read_verifying_key).
+    fn write_verifying_key<W>(
+        vk: &VerifyingKey<C>,
+        writer: &mut W,
+    ) -> Result<(), std::io::Error>
+    where
+        W: std::io::Write;
+}
+
+/// Appends a segment named `name` of `len` elements to `segments`, starting
+/// where the last segment ends.  Used to build the layouts returned by
+/// [`SafeCircuit::describe_instance`].
+pub(crate) fn push_instance_segment(
+    segments: &mut Vec<(String, Range<usize>)>,
+    name: impl Into<String>,
+    len: usize,
+) {
+    let start = segments.last().map(|(_, range)| range.end).unwrap_or(0);
+    segments.push((name.into(), start..start + len));
+}
+
+/// Formats the instance layout returned by
+/// [`SafeCircuit::describe_instance`] as a table, one segment per line.
+pub fn format_instance_layout(segments: &[(String, Range<usize>)]) -> String {
+    segments
+        .iter()
+        .map(|(name, range)| {
+            format!("{:>8}..{:<8} {name}\n", range.start, range.end)
+        })
+        .collect()
+}
+
+/// Reads a proving key for `Circuit` from the file at `path` (see
+/// [`SafeCircuit::read_proving_key`]), memory-mapping the file instead of
+/// reading it through a buffer.  The file contents are then backed by the OS
+/// page cache, which can evict them once deserialized, rather than by the
+/// heap.  Note that the deserialized key itself still resides on the heap.
+///
+/// # Platform caveats
+///
+/// - The file must not be modified or truncated while it is being read,
+///   otherwise the behaviour is undefined (usually a `SIGBUS` on Unix).
+/// - On Windows, the file cannot be deleted or replaced while mapped.
+/// - On network filesystems, page faults may make loading slower than a
+///   buffered read.
+pub fn read_proving_key_mmap<'a, F, C, Circuit>(
+    circuit_config: &Circuit::CircuitConfig,
+    gate_config: &Circuit::GateConfig,
+    path: &str,
+) -> Result<ProvingKey<C>, std::io::Error>
+where
+    F: EccPrimeField,
+    C: CurveAffine<ScalarExt = F>,
+    Circuit: SafeCircuit<'a, F, C>,
+{
+    let mmap = mmap_file_for_read(path);
+    Circuit::read_proving_key(
+        circuit_config,
+        gate_config,
+        &mut Cursor::new(&mmap[..]),
+    )
 }
@@ -1,13 +1,21 @@
 //! Common functionality for Fixed/Universal Outer Circuits.
 use crate::{
     keccak::{
-        inputs::KeccakCircuitInputs, utils::gen_keccak_snark, KeccakCircuit,
-        KeccakConfig,
+        inputs::KeccakCircuitInputs,
+        utils::{
+            gen_keccak_snark, inputs_per_application_proof_with_commitments,
+        },
+        KeccakCircuit, KeccakConfig,
+    },
+    push_instance_segment,
+    utils::{
+        cancellation::{CancellationToken, Cancelled},
+        srs::gen_deterministic_srs,
+        upa_config::{UpaConfig, UpaConfigError},
     },
-    utils::upa_config::UpaConfig,
     SafeCircuit,
 };
-use core::{iter, marker::PhantomData};
+use core::{fmt, iter, marker::PhantomData, ops::Range};
 use halo2_base::{
     gates::builder::{
         CircuitBuilderStage, FlexGateConfigParams, MultiPhaseThreadBreakPoints,
@@ -26,7 +34,6 @@ use halo2_base::{
         },
         SerdeFormat,
     },
-    utils::fs::gen_srs,
 };
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
@@ -34,31 +41,57 @@ use snark_verifier_sdk::{
     halo2::aggregation::{AggregationCircuit, Halo2KzgAccumulationScheme},
     CircuitExt, Snark,
 };
-use std::env::set_var;
+use std::{
+    env::set_var,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
 
 pub type OuterConfig = UpaConfig;
 pub type UniversalOuterConfig = UpaConfig;
 
+pub mod onchain_vk;
+pub mod recursive;
 pub mod universal;
 pub mod utils;
 
 /// The number of rows to be set aside for blinding
 /// factors when computing `FlexGateConfigParams`.
-pub(crate) const MINIMUM_ROWS: usize = 20;
+pub const MINIMUM_ROWS: usize = 20;
 
-/// Abstracts the `FixedOuterCircuit` and `UniversalOuterCircuit`. In the
-/// universal case, `bv` and `BatchVerify` should be understood to mean
-/// `ubv` and `UniversalBatchVerify`.
+/// The number of limbs used by the [AggregationCircuit] to represent each
+/// coordinate of the KZG accumulator points.
+pub const NUM_LIMBS: usize = 3;
+
+/// The number of field elements encoding the KZG accumulator: 2 `G1` points,
+/// each with 2 coordinates of [NUM_LIMBS] limbs.
+pub const ACCUMULATOR_LEN: usize = 4 * NUM_LIMBS;
+
+/// The number of field elements encoding the final digest: 2 elements each
+/// holding 128 bits (16 bytes).
+pub const FINAL_DIGEST_LEN: usize = 2;
+
+/// A sequence of [Snark]s to be aggregated.
+pub type SnarkIter<'a> = Box<dyn Iterator<Item = Snark> + 'a>;
+
+/// Abstracts the circuits which aggregate some [Snark]s into a single
+/// [AggregationCircuit], exposing the KZG accumulator followed by some
+/// circuit-specific public inputs (e.g. a final digest).
 pub trait OuterCircuit {
     /// The configuration of this `OuterCircuit`, generally a [UpaConfig]
     type Config;
 
-    /// The configuration of BV Circuits to be aggregated
-    type BatchVerifyConfig;
+    /// The data required to create a witness.  See
+    /// [`SafeCircuit::CircuitInputs`].
+    type CircuitInputs: Clone;
 
-    fn bv_config(config: &Self::Config) -> Self::BatchVerifyConfig;
+    /// The data required to compute the instance.  See
+    /// [`SafeCircuit::InstanceInputs`].
+    type InstanceInputs;
 
-    fn keccak_config(config: &Self::Config) -> KeccakConfig;
+    /// The data required for keygen.  See [`SafeCircuit::KeygenInputs`].
+    type KeygenInputs<'p>;
 
     fn gate_config(&self) -> &OuterGateConfig;
 
@@ -68,29 +101,50 @@ pub trait OuterCircuit {
         Self::degree_bits(config) - 1
     }
 
-    fn outer_batch_size(config: &Self::Config) -> usize;
+    /// Returns the [Snark]s to be aggregated, in order.
+    fn snarks(inputs: Self::CircuitInputs) -> SnarkIter<'static>;
 
-    /// Return a (potentially invalid) [Snark] for the corresponding
-    /// BV circuit based on the given configuration.
-    fn dummy_bv_snark(
-        bv_params: &ParamsKZG<Bn256>,
-        bv_config: &Self::BatchVerifyConfig,
-    ) -> Snark;
+    /// Returns the SRS used to generate keys for this circuit.
+    fn outer_params<'p>(
+        inputs: &Self::KeygenInputs<'p>,
+    ) -> &'p ParamsKZG<Bn256>;
 
-    /// Return [KeccakCircuitInputs] corresponding to `bv_instances`.
-    fn keccak_inputs_from_bv_instances<'a>(
-        bv_config: &Self::BatchVerifyConfig,
-        bv_instances: impl ExactSizeIterator<Item = &'a [Fr]>,
-        num_proof_ids: Option<u64>,
-    ) -> KeccakCircuitInputs<Fr>;
+    /// Returns (potentially invalid) circuit inputs of the correct shape
+    /// for keygen.
+    fn keygen_circuit_inputs<'p, P, V>(
+        config: &Self::Config,
+        inputs: &Self::KeygenInputs<'p>,
+    ) -> Self::CircuitInputs
+    where
+        P: Prover<'p, KZGCommitmentScheme<Bn256>>,
+        V: Verifier<
+            'p,
+            KZGCommitmentScheme<Bn256>,
+            Guard = GuardKZG<'p, Bn256>,
+            MSMAccumulator = DualMSM<'p, Bn256>,
+        >;
+
+    /// Returns the public inputs following the KZG accumulator.  See
+    /// [`SafeCircuit::compute_instance`].
+    fn compute_instance(
+        config: &Self::Config,
+        inputs: &Self::InstanceInputs,
+    ) -> Vec<Fr>;
+
+    /// Returns the number of public inputs, including the KZG accumulator.
+    fn instance_len(config: &Self::Config) -> usize;
+
+    /// Returns the layout of the public inputs, including the KZG
+    /// accumulator.  See [`SafeCircuit::describe_instance`].
+    fn describe_instance(config: &Self::Config) -> Vec<(String, Range<usize>)>;
 
     /// Implementors are expected to have some inner [AggregationCircuit]
     fn inner(&self) -> &AggregationCircuit;
 
     /// By default, the [AggregationCircuit] will only expose its KZG accumulator
-    /// as public inputs. This method adds the final digest to the Outer Circuit public
-    /// inputs and copy-constrains all BV circuit inputs to the corresponding
-    /// Keccak circuit inputs.
+    /// as public inputs. This method adds the circuit-specific public inputs
+    /// (e.g. the final digest) and any constraints between the instances of
+    /// the aggregated snarks.
     fn expose_final_digest_and_constrain(
         inner: &mut AggregationCircuit,
         config: &Self::Config,
@@ -106,7 +160,7 @@ pub trait OuterCircuit {
     fn new<AS>(
         stage: CircuitBuilderStage,
         config: &Self::Config,
-        inputs: OuterCircuitInputs<Self>,
+        inputs: Self::CircuitInputs,
         break_points: Option<MultiPhaseThreadBreakPoints>,
         gate_config: Option<OuterGateConfig>,
     ) -> Self
@@ -114,14 +168,96 @@ pub trait OuterCircuit {
         AS: for<'a> Halo2KzgAccumulationScheme<'a>,
         Self: core::marker::Sized,
     {
-        let snarks = {
-            let mut snarks = inputs.bv_snarks;
-            snarks.push(inputs.keccak_snark);
-            snarks
-        };
-        // Note: This assumes BV/Keccak use an SRS that has
+        Self::new_from_snarks::<AS>(
+            stage,
+            config,
+            Self::snarks(inputs),
+            break_points,
+            gate_config,
+        )
+    }
+
+    /// As [`OuterCircuit::new`], but returns `Err(Cancelled)` if `cancel`
+    /// is cancelled during witness generation.
+    fn new_cancellable<AS>(
+        stage: CircuitBuilderStage,
+        config: &Self::Config,
+        inputs: Self::CircuitInputs,
+        break_points: Option<MultiPhaseThreadBreakPoints>,
+        gate_config: Option<OuterGateConfig>,
+        cancel: &CancellationToken,
+    ) -> Result<Self, Cancelled>
+    where
+        AS: for<'a> Halo2KzgAccumulationScheme<'a>,
+        Self: core::marker::Sized,
+    {
+        Self::new_from_snarks_cancellable::<AS>(
+            stage,
+            config,
+            Self::snarks(inputs),
+            break_points,
+            gate_config,
+            cancel,
+        )
+    }
+
+    /// As [`OuterCircuit::new`], but takes the [Snark]s to be aggregated
+    /// directly.
+    ///
+    /// # Note
+    ///
+    /// [AggregationCircuit::new] collects `snarks` before aggregating them,
+    /// so all snarks are resident at once.
+    fn new_from_snarks<AS>(
+        stage: CircuitBuilderStage,
+        config: &Self::Config,
+        snarks: SnarkIter<'_>,
+        break_points: Option<MultiPhaseThreadBreakPoints>,
+        gate_config: Option<OuterGateConfig>,
+    ) -> Self
+    where
+        AS: for<'a> Halo2KzgAccumulationScheme<'a>,
+        Self: core::marker::Sized,
+    {
+        Self::new_from_snarks_cancellable::<AS>(
+            stage,
+            config,
+            snarks,
+            break_points,
+            gate_config,
+            &CancellationToken::default(),
+        )
+        .expect("uncancellable token was cancelled")
+    }
+
+    /// As [`OuterCircuit::new_from_snarks`], but checks `cancel` before
+    /// each snark is pulled from `snarks` (see
+    /// [`CancellationToken::collect`]).  If it is cancelled, the remaining
+    /// snarks are not pulled, no circuit is built and `Err(Cancelled)` is
+    /// returned.  Once all snarks are loaded, aggregation itself cannot be
+    /// cancelled.
+    fn new_from_snarks_cancellable<AS>(
+        stage: CircuitBuilderStage,
+        config: &Self::Config,
+        snarks: SnarkIter<'_>,
+        break_points: Option<MultiPhaseThreadBreakPoints>,
+        gate_config: Option<OuterGateConfig>,
+        cancel: &CancellationToken,
+    ) -> Result<Self, Cancelled>
+    where
+        AS: for<'a> Halo2KzgAccumulationScheme<'a>,
+        Self: core::marker::Sized,
+    {
+        let _span = tracing::info_span!(
+            "aggregation_build",
+            degree = Self::degree_bits(config),
+            ?stage,
+        )
+        .entered();
+        let snarks = cancel.collect(snarks)?;
+        // Note: This assumes all aggregated snarks use an SRS that has
         // G1 generator (1, 2).
-        let verifier_params = gen_srs(0);
+        let verifier_params = gen_deterministic_srs(0, 0);
         let mut inner = AggregationCircuit::new::<AS>(
             stage,
             break_points,
@@ -142,10 +278,46 @@ pub trait OuterCircuit {
                 lookup_bits,
             }
         });
-        Self::from_inner(inner, gate_config)
+        Ok(Self::from_inner(inner, gate_config))
     }
 }
 
+/// Abstracts the `FixedOuterCircuit` and `UniversalOuterCircuit`, which
+/// aggregate BV proofs together with a Keccak proof of their inputs. In the
+/// universal case, `bv` and `BatchVerify` should be understood to mean
+/// `ubv` and `UniversalBatchVerify`.
+pub trait BatchVerifyOuterCircuit: OuterCircuit {
+    /// The configuration of BV Circuits to be aggregated
+    type BatchVerifyConfig;
+
+    fn bv_config(config: &Self::Config) -> Self::BatchVerifyConfig;
+
+    fn keccak_config(config: &Self::Config) -> KeccakConfig;
+
+    /// Checks that `keccak_config`, the config of the `KeccakCircuit` whose
+    /// proof is aggregated, is [`Self::keccak_config`] for `config`.
+    fn check_keccak_config(
+        config: &Self::Config,
+        keccak_config: &KeccakConfig,
+    ) -> Result<(), UpaConfigError>;
+
+    fn outer_batch_size(config: &Self::Config) -> usize;
+
+    /// Return a (potentially invalid) [Snark] for the corresponding
+    /// BV circuit based on the given configuration.
+    fn dummy_bv_snark(
+        bv_params: &ParamsKZG<Bn256>,
+        bv_config: &Self::BatchVerifyConfig,
+    ) -> Snark;
+
+    /// Return [KeccakCircuitInputs] corresponding to `bv_instances`.
+    fn keccak_inputs_from_bv_instances<'a>(
+        bv_config: &Self::BatchVerifyConfig,
+        bv_instances: impl ExactSizeIterator<Item = &'a [Fr]>,
+        num_proof_ids: Option<u64>,
+    ) -> KeccakCircuitInputs<Fr>;
+}
+
 /// Outer circuit gate configuration
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct OuterGateConfig {
@@ -174,13 +346,83 @@ impl OuterGateConfig {
     }
 }
 
-/// Here "Outer Instance" is understood to mean only the final
-/// digest. See note on [SafeCircuit::compute_instance] in the
+/// The public inputs of an outer circuit proof: the KZG accumulator followed
+/// by the outputs (see [`outer_output_len`]).  See
+/// [`CircuitExt::instances`] for [OuterCircuitWrapper].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OuterInstance {
+    /// Limbs of the KZG accumulator points `(lhs, rhs)`, in the order
+    /// `lhs.x, lhs.y, rhs.x, rhs.y`
+    pub accumulator_limbs: [Fr; ACCUMULATOR_LEN],
+    /// Number of proof ids covered by the final digest, present if
+    /// [`UpaConfig::public_num_proof_ids`] is set
+    pub num_proof_ids: Option<Fr>,
+    /// Final digest, as 2 field elements holding the high and low 16 bytes
+    pub final_digest: [Fr; FINAL_DIGEST_LEN],
+    /// VK digest, in the same format as the final digest, present if
+    /// [`UpaConfig::include_vk_digest`] is set
+    pub vk_digest: Option<[Fr; FINAL_DIGEST_LEN]>,
+}
+
+impl OuterInstance {
+    /// Parses the flat list of public inputs of an outer circuit for
+    /// `config`, as returned by [`CircuitExt::instances`].  Fails if
+    /// `instance` has the wrong length.
+    pub fn from_flat(
+        config: &UpaConfig,
+        instance: &[Fr],
+    ) -> Result<Self, String> {
+        let expected_len = ACCUMULATOR_LEN + outer_output_len(config);
+        if instance.len() != expected_len {
+            return Err(format!(
+                "unexpected outer instance length: {} (expected {})",
+                instance.len(),
+                expected_len
+            ));
+        }
+        let (accumulator_limbs, outputs) = instance.split_at(ACCUMULATOR_LEN);
+        let (num_proof_ids, digests) =
+            outputs.split_at(config.public_num_proof_ids as usize);
+        let (final_digest, vk_digest) = digests.split_at(FINAL_DIGEST_LEN);
+        Ok(Self {
+            accumulator_limbs: accumulator_limbs.try_into().unwrap(),
+            num_proof_ids: num_proof_ids.first().copied(),
+            final_digest: final_digest.try_into().unwrap(),
+            vk_digest: config
+                .include_vk_digest
+                .then(|| vk_digest.try_into().unwrap()),
+        })
+    }
+
+    /// Returns the public inputs following the KZG accumulator, as computed
+    /// by [`SafeCircuit::compute_instance`].
+    pub fn outputs(&self) -> Vec<Fr> {
+        self.num_proof_ids
+            .iter()
+            .chain(self.final_digest.iter())
+            .chain(self.vk_digest.iter().flatten())
+            .copied()
+            .collect()
+    }
+
+    /// Returns the flat list of public inputs.  Inverse of
+    /// [`OuterInstance::from_flat`].
+    pub fn to_flat(&self) -> Vec<Fr> {
+        self.accumulator_limbs
+            .iter()
+            .copied()
+            .chain(self.outputs())
+            .collect()
+    }
+}
+
+/// Here "Outer Instance" is understood to mean only the outputs forwarded
+/// from the `KeccakCircuit` (see [`outer_output_len`]). See note on [SafeCircuit::compute_instance] in the
 /// [SafeCircuit] impl. Although this could be extracted from
 /// `keccak_instance` alone, in practice we wish to enforce consistency
 /// of the `bv_instances` and `keccak_instance`. See constructor.
 #[derive(Debug, Deserialize, Serialize)]
-pub struct OuterInstanceInputs<O: OuterCircuit> {
+pub struct OuterInstanceInputs<O: BatchVerifyOuterCircuit> {
     /// BatchVerifyCircuit instances
     bv_instances: Vec<Vec<Fr>>,
     /// `KeccakCircuit` instance
@@ -188,50 +430,25 @@ pub struct OuterInstanceInputs<O: OuterCircuit> {
     __: PhantomData<O>,
 }
 
-impl<O: OuterCircuit> OuterInstanceInputs<O> {
+impl<O: BatchVerifyOuterCircuit> OuterInstanceInputs<O> {
     /// Constructor that enforces consistency of
-    /// `keccak_instance` and `bv_instances`.
+    /// `keccak_instance` and `bv_instances`, and that `keccak_config` (the
+    /// config for which `keccak_instance` was computed) is the keccak config
+    /// of `config`.
     pub fn new(
         config: &O::Config,
+        keccak_config: &KeccakConfig,
         bv_instances: Vec<Vec<Fr>>,
         keccak_instance: Vec<Fr>,
     ) -> Self {
-        let bv_config = O::bv_config(config);
-        let keccak_config = O::keccak_config(config);
-
-        let create_keccak_instance = |i: Option<u64>| {
-            let expected_circuit_inputs = O::keccak_inputs_from_bv_instances(
-                &bv_config,
-                bv_instances.iter().map(|i| i.as_slice()),
-                i,
-            );
-            <KeccakCircuit<Fr, G1Affine> as SafeCircuit<_, _>>::compute_instance(
-                &keccak_config,
-                &expected_circuit_inputs,
-            )
-        };
-
-        // We can't compute the submissionId from the bv instances, but we can
-        // precompute all possible sids and check the one in `keccak_instance`
-        // is one of them
-        if keccak_config.output_submission_id {
-            let total_batch_size =
-                keccak_config.inner_batch_size * keccak_config.outer_batch_size;
-            let expected_instances = (1..=total_batch_size)
-                .into_iter()
-                .map(|i| create_keccak_instance(Some(i as u64)))
-                .collect_vec();
-            assert!(
-                expected_instances.contains(&keccak_instance),
-                "Unexpected keccak instance"
-            );
-        } else {
-            let expected_instance = create_keccak_instance(None);
-            assert_eq!(
-                expected_instance, keccak_instance,
-                "Unexpected keccak instance"
-            );
-        }
+        O::check_keccak_config(config, keccak_config)
+            .unwrap_or_else(|e| panic!("Unexpected keccak config: {e}"));
+        OuterCircuitInputs::<O>::check_consistency(
+            config,
+            &bv_instances,
+            &keccak_instance,
+        )
+        .unwrap_or_else(|e| panic!("Unexpected keccak instance: {e}"));
 
         Self {
             bv_instances,
@@ -241,6 +458,99 @@ impl<O: OuterCircuit> OuterInstanceInputs<O> {
     }
 }
 
+impl<O: BatchVerifyOuterCircuit<Config = UpaConfig>> OuterInstanceInputs<O> {
+    /// Returns the outputs of the `KeccakCircuit` forwarded by the outer
+    /// circuit for `config`, namely the last [`outer_output_len`] elements of
+    /// `keccak_instance`.
+    pub fn outputs(&self, config: &UpaConfig) -> &[Fr] {
+        let outputs_start =
+            self.keccak_instance.len() - outer_output_len(config);
+        &self.keccak_instance[outputs_start..]
+    }
+
+    /// Returns the final digest, which follows `num_proof_ids` (if public)
+    /// in [`Self::outputs`].  The constructor ensures this is consistent
+    /// with `bv_instances`.
+    pub fn final_digest(&self, config: &UpaConfig) -> [Fr; FINAL_DIGEST_LEN] {
+        let digest_start = config.public_num_proof_ids as usize;
+        self.outputs(config)[digest_start..digest_start + FINAL_DIGEST_LEN]
+            .try_into()
+            .unwrap()
+    }
+}
+
+/// Reason for which the BV instances and `KeccakCircuit` instance given to an
+/// outer circuit are inconsistent.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OuterInputError {
+    /// The number of BV instances is not the outer batch size.
+    WrongNumberOfBvInstances { expected: usize, actual: usize },
+    /// The `KeccakCircuit` instance has the wrong length.
+    WrongKeccakInstanceLength { expected: usize, actual: usize },
+    /// The `KeccakCircuit` instance entries for the application proofs of
+    /// the BV instance with this index do not match it.
+    BvInstanceMismatch(usize),
+    /// The `KeccakCircuit` instance matches all BV instances, but its
+    /// digests (or `num_proof_ids`) do not.
+    DigestMismatch,
+    /// The public `num_proof_ids` of the `KeccakCircuit` instance is not in
+    /// `1..=max`, where `max` is the total batch size.
+    InvalidNumProofIds { max: u64 },
+    /// The snark file at this path could not be read or deserialized.
+    UnreadableSnark { path: PathBuf, reason: String },
+    /// The snark at this path has no instance column.
+    MissingSnarkInstance(PathBuf),
+}
+
+impl fmt::Display for OuterInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OuterInputError::WrongNumberOfBvInstances { expected, actual } => {
+                write!(f, "expected {expected} BV instances, got {actual}")
+            }
+            OuterInputError::WrongKeccakInstanceLength { expected, actual } => {
+                write!(
+                    f,
+                    "keccak instance has length {actual} (expected {expected})"
+                )
+            }
+            OuterInputError::BvInstanceMismatch(i) => {
+                write!(f, "keccak instance does not match BV instance {i}")
+            }
+            OuterInputError::DigestMismatch => {
+                write!(f, "keccak instance has unexpected digest")
+            }
+            OuterInputError::InvalidNumProofIds { max } => {
+                write!(f, "keccak instance num_proof_ids not in 1..={max}")
+            }
+            OuterInputError::UnreadableSnark { path, reason } => {
+                write!(f, "failed to read snark {}: {reason}", path.display())
+            }
+            OuterInputError::MissingSnarkInstance(path) => {
+                write!(f, "snark {} has no instance column", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for OuterInputError {}
+
+/// Reads the JSON-serialized [Snark] at `path`, which must have an instance
+/// column.
+fn load_snark(path: &Path) -> Result<Snark, OuterInputError> {
+    let unreadable = |reason: String| OuterInputError::UnreadableSnark {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let file = File::open(path).map_err(|e| unreadable(e.to_string()))?;
+    let snark: Snark = serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| unreadable(e.to_string()))?;
+    if snark.instances.is_empty() {
+        return Err(OuterInputError::MissingSnarkInstance(path.to_path_buf()));
+    }
+    Ok(snark)
+}
+
 /// The data required to create an outer circuit witness. Consistency
 /// of these inputs is enforced by the constructor.
 ///
@@ -249,7 +559,7 @@ impl<O: OuterCircuit> OuterInstanceInputs<O> {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct OuterCircuitInputs<O>
 where
-    O: OuterCircuit,
+    O: BatchVerifyOuterCircuit,
 {
     /// Batch Verifier Snarks
     bv_snarks: Vec<Snark>,
@@ -262,7 +572,7 @@ where
 
 impl<O> Clone for OuterCircuitInputs<O>
 where
-    O: OuterCircuit,
+    O: BatchVerifyOuterCircuit,
 {
     #[inline]
     fn clone(&self) -> Self {
@@ -276,18 +586,22 @@ where
 
 impl<O> OuterCircuitInputs<O>
 where
-    O: OuterCircuit,
+    O: BatchVerifyOuterCircuit,
 {
     /// Constructor that enforces consistency of public inputs to
-    /// `keccak_snark` and `bv_snarks`.
+    /// `keccak_snark` and `bv_snarks`, and that `keccak_config` (the config
+    /// for which `keccak_snark` was generated) is the keccak config of
+    /// `outer_config`.
     pub fn new(
         outer_config: &O::Config,
+        keccak_config: &KeccakConfig,
         bv_snarks: Vec<Snark>,
         keccak_snark: Snark,
     ) -> Self {
         // Consistency check
         let _inputs = OuterInstanceInputs::<O>::new(
             outer_config,
+            keccak_config,
             bv_snarks.iter().map(|s| s.instances[0].clone()).collect(),
             keccak_snark.instances[0].clone(),
         );
@@ -298,6 +612,142 @@ where
         }
     }
 
+    /// Checks the consistency of the `bv_instances` and `keccak_instance`
+    /// of the snarks to be aggregated, as enforced by [`Self::new`], without
+    /// requiring the snarks themselves.  On failure, the error identifies
+    /// the first BV instance whose application proofs do not match the
+    /// `keccak_instance`, or else reports a digest mismatch.
+    pub fn check_consistency(
+        outer_config: &O::Config,
+        bv_instances: &[Vec<Fr>],
+        keccak_instance: &[Fr],
+    ) -> Result<(), OuterInputError> {
+        let bv_config = O::bv_config(outer_config);
+        let keccak_config = O::keccak_config(outer_config);
+        let outer_batch_size = O::outer_batch_size(outer_config);
+        if bv_instances.len() != outer_batch_size {
+            return Err(OuterInputError::WrongNumberOfBvInstances {
+                expected: outer_batch_size,
+                actual: bv_instances.len(),
+            });
+        }
+
+        let create_keccak_instance = |i: Option<u64>| {
+            let expected_circuit_inputs = O::keccak_inputs_from_bv_instances(
+                &bv_config,
+                bv_instances.iter().map(|i| i.as_slice()),
+                i,
+            );
+            <KeccakCircuit<Fr, G1Affine> as SafeCircuit<_, _>>::compute_instance(
+                &keccak_config,
+                &expected_circuit_inputs,
+            )
+        };
+
+        // If `num_proof_ids` is public, it immediately precedes the final
+        // digest (2 or 4 field elements, depending on the output mode) in
+        // `keccak_instance` and we can read it directly. Otherwise
+        // we can't compute the submissionId from the bv instances, but we can
+        // precompute all possible sids and check the one in `keccak_instance`
+        // is one of them.
+        let total_batch_size =
+            keccak_config.inner_batch_size * keccak_config.outer_batch_size;
+        let expected_instances = if keccak_config.public_num_proof_ids {
+            let num_proof_ids_idx = keccak_instance
+                .len()
+                .checked_sub(
+                    1 + keccak_config.output_mode.num_output_field_elements(),
+                )
+                .ok_or(OuterInputError::DigestMismatch)?;
+            let num_proof_ids = (1..=total_batch_size as u64)
+                .find(|n| Fr::from(*n) == keccak_instance[num_proof_ids_idx])
+                .ok_or(OuterInputError::InvalidNumProofIds {
+                    max: total_batch_size as u64,
+                })?;
+            vec![create_keccak_instance(Some(num_proof_ids))]
+        } else if keccak_config.output_mode.outputs_submission_id() {
+            (1..=total_batch_size)
+                .map(|i| create_keccak_instance(Some(i as u64)))
+                .collect_vec()
+        } else {
+            vec![create_keccak_instance(None)]
+        };
+
+        // All candidates have the same length and the same entries for the
+        // application proofs, which precede the digests.
+        let expected_instance = &expected_instances[0];
+        if keccak_instance.len() != expected_instance.len() {
+            return Err(OuterInputError::WrongKeccakInstanceLength {
+                expected: expected_instance.len(),
+                actual: keccak_instance.len(),
+            });
+        }
+        let entry_len = inputs_per_application_proof_with_commitments(
+            keccak_config.num_app_public_inputs as usize,
+            keccak_config.max_commitments as usize,
+            keccak_config.num_limbs,
+        );
+        let entries_per_bv_instance =
+            keccak_config.inner_batch_size as usize * entry_len;
+        let entries_len = total_batch_size as usize * entry_len;
+        if let Some(idx) = keccak_instance[..entries_len]
+            .iter()
+            .zip_eq(&expected_instance[..entries_len])
+            .position(|(actual, expected)| actual != expected)
+        {
+            return Err(OuterInputError::BvInstanceMismatch(
+                idx / entries_per_bv_instance,
+            ));
+        }
+        match expected_instances
+            .iter()
+            .any(|expected| expected.as_slice() == keccak_instance)
+        {
+            true => Ok(()),
+            false => Err(OuterInputError::DigestMismatch),
+        }
+    }
+
+    /// Constructor from the paths of JSON-serialized BV snarks and the
+    /// `KeccakCircuit` snark, which checks the consistency of their public
+    /// inputs as [`Self::new`] does, but returns an error (rather than
+    /// panicking) if the snarks are unreadable or inconsistent.  Each snark
+    /// is read once, and all of them are held in memory.
+    pub fn from_snark_paths(
+        outer_config: &O::Config,
+        paths: &[PathBuf],
+        keccak_path: &Path,
+    ) -> Result<Self, OuterInputError> {
+        let bv_snarks: Vec<Snark> = paths
+            .iter()
+            .map(|path| load_snark(path.as_path()))
+            .collect::<Result<_, _>>()?;
+        let keccak_snark = load_snark(keccak_path)?;
+        let bv_instances = bv_snarks
+            .iter()
+            .map(|snark| snark.instances[0].clone())
+            .collect_vec();
+        Self::check_consistency(
+            outer_config,
+            &bv_instances,
+            &keccak_snark.instances[0],
+        )?;
+        Ok(Self {
+            bv_snarks,
+            keccak_snark,
+            __: PhantomData,
+        })
+    }
+
+    /// Returns the BV snarks followed by the `KeccakCircuit` snark.
+    pub fn into_snarks(self) -> SnarkIter<'static> {
+        Box::new(
+            self.bv_snarks
+                .into_iter()
+                .chain(iter::once(self.keccak_snark)),
+        )
+    }
+
     /// Returns default data appropriate for Outer Circuit keygen
     /// with specified Outer Circuit config.
     pub fn keygen_default<'p, P, V>(
@@ -323,7 +773,8 @@ where
         let total_batch_size =
             keccak_config.inner_batch_size * keccak_config.outer_batch_size;
         let num_proof_ids = keccak_config
-            .output_submission_id
+            .output_mode
+            .outputs_submission_id()
             .then_some(total_batch_size as u64);
         let keccak_inputs = O::keccak_inputs_from_bv_instances(
             &bv_config,
@@ -337,7 +788,55 @@ where
             &keccak_inputs,
         );
 
-        Self::new(outer_config, bv_snarks, keccak_snark)
+        Self::new(outer_config, &keccak_config, bv_snarks, keccak_snark)
+    }
+}
+
+/// A serializable description of the [`OuterCircuitInputs`] of a proving
+/// job: the outer circuit config and the paths of the JSON-serialized BV
+/// snarks and `KeccakCircuit` snark.  Unlike serialized
+/// [`OuterCircuitInputs`], which may hold the snarks themselves, this stays
+/// small, so can be persisted by a job scheduler.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct OuterCircuitInputsRef {
+    pub config: UpaConfig,
+    pub bv_snark_paths: Vec<PathBuf>,
+    pub keccak_snark_path: PathBuf,
+}
+
+impl OuterCircuitInputsRef {
+    pub fn new(
+        config: &UpaConfig,
+        bv_snark_paths: Vec<PathBuf>,
+        keccak_snark_path: PathBuf,
+    ) -> Self {
+        Self {
+            config: *config,
+            bv_snark_paths,
+            keccak_snark_path,
+        }
+    }
+
+    /// Loads the [`OuterCircuitInputs`] described by `self` (see
+    /// [`OuterCircuitInputs::from_snark_paths`]).  Fails if the number of BV
+    /// snarks is not the outer batch size of the config, or if the snarks
+    /// are unreadable or their instances are inconsistent.
+    pub fn resolve<O>(&self) -> Result<OuterCircuitInputs<O>, OuterInputError>
+    where
+        O: BatchVerifyOuterCircuit<Config = UpaConfig>,
+    {
+        let outer_batch_size = O::outer_batch_size(&self.config);
+        if self.bv_snark_paths.len() != outer_batch_size {
+            return Err(OuterInputError::WrongNumberOfBvInstances {
+                expected: outer_batch_size,
+                actual: self.bv_snark_paths.len(),
+            });
+        }
+        OuterCircuitInputs::from_snark_paths(
+            &self.config,
+            &self.bv_snark_paths,
+            &self.keccak_snark_path,
+        )
     }
 }
 
@@ -429,6 +928,29 @@ where
             __: PhantomData,
         }
     }
+
+    /// Same as [`SafeCircuit::prover`], but returns `Err(Cancelled)` if
+    /// `cancel` is cancelled during witness generation.
+    pub fn prover_cancellable(
+        config: &O::Config,
+        gate_config: &OuterGateConfig,
+        break_points: MultiPhaseThreadBreakPoints,
+        inputs: &O::CircuitInputs,
+        cancel: &CancellationToken,
+    ) -> Result<Self, Cancelled> {
+        flex_gate_params_env_check(&gate_config.flex_gate_config_params);
+        lookup_bits_env_check(O::lookup_bits(config));
+
+        O::new_cancellable::<AS>(
+            CircuitBuilderStage::Prover,
+            config,
+            inputs.clone(),
+            Some(break_points),
+            Some(gate_config.clone()),
+            cancel,
+        )
+        .map(Self::new)
+    }
 }
 
 impl<'p, AS, O, P, V> Circuit<Fr> for OuterCircuitWrapper<'p, AS, O, P, V>
@@ -482,15 +1004,25 @@ where
         <AggregationCircuit as CircuitExt<_>>::num_instance(self.inner.inner())
     }
 
+    /// The KZG accumulator occupies the first `4 * NUM_LIMBS` public inputs,
+    /// as for [AggregationCircuit].  This allows `OuterCircuit` snarks to be
+    /// aggregated in turn.
+    fn accumulator_indices() -> Option<Vec<(usize, usize)>> {
+        AggregationCircuit::accumulator_indices()
+    }
+
     /// Return public inputs to Outer Circuit.
     ///
-    /// This consists of the KZG accumulator formed from the BV/Keccak Snarks,
-    /// followed by the Keccak digest of all application proof IDs.
+    /// This consists of the KZG accumulator formed from the aggregated Snarks,
+    /// followed by the public inputs added by
+    /// [`OuterCircuit::expose_final_digest_and_constrain`]. In the case of
+    /// BV/Keccak Snarks, this is the Keccak digest of all application proof
+    /// IDs.
     ///
     /// The KZG accumulator consists of 2 `G1` points, encoded as `4 *
     /// NUM_LIMBS` `Fr` elements. The Keccak digest is encoded as 2 `Fr`
     /// elements (each holding 128 bits or 16 bytes). The length of the entire
-    /// instance is therefore `4 * NUM_LIMBS + 2 = 14`.
+    /// instance is therefore `4 * NUM_LIMBS + 2 = 14` in this case.
     fn instances(&self) -> Vec<Vec<Fr>> {
         <AggregationCircuit as CircuitExt<_>>::instances(self.inner.inner())
     }
@@ -513,11 +1045,11 @@ where
 
     type GateConfig = OuterGateConfig;
 
-    type CircuitInputs = OuterCircuitInputs<O>;
+    type CircuitInputs = O::CircuitInputs;
 
-    type KeygenInputs = OuterKeygenInputs<'p>;
+    type KeygenInputs = O::KeygenInputs<'p>;
 
-    type InstanceInputs = OuterInstanceInputs<O>;
+    type InstanceInputs = O::InstanceInputs;
 
     fn mock(
         config: &Self::CircuitConfig,
@@ -536,16 +1068,11 @@ where
         config: &Self::CircuitConfig,
         inputs: &Self::KeygenInputs,
     ) -> Self {
-        let keygen_default_inputs =
-            OuterCircuitInputs::<O>::keygen_default::<P, V>(
-                config,
-                inputs.bv_params(),
-                inputs.keccak_params(),
-            );
+        let keygen_inputs = O::keygen_circuit_inputs::<P, V>(config, inputs);
         Self::new(O::new::<AS>(
             CircuitBuilderStage::Keygen,
             config,
-            keygen_default_inputs,
+            keygen_inputs,
             None,
             None,
         ))
@@ -574,18 +1101,20 @@ where
     /// circuit is verified (with a deferred pairing).  Since these are
     /// non-trivial to compute (effectively requiring a full prove step), we
     /// consider the "instance" here to refer to just the public inputs related
-    /// to core outer circuit logic - namely the final digest.
+    /// to core outer circuit logic - e.g. the final digest.
     fn compute_instance(
-        _: &Self::CircuitConfig,
+        config: &Self::CircuitConfig,
         inputs: &Self::InstanceInputs,
     ) -> Vec<Fr> {
-        // Note, the construction of `inputs` requires that that all inputs are
-        // consistent, including the final digest.  Hence we can just read that
-        // out.
-        let keccak_instance = &inputs.keccak_instance;
-        let keccak_instance_size = keccak_instance.len();
+        O::compute_instance(config, inputs)
+    }
 
-        keccak_instance[keccak_instance_size - 2..].to_vec()
+    /// Unlike [`SafeCircuit::compute_instance`], this describes the full
+    /// instance, starting with the KZG accumulator.
+    fn describe_instance(
+        config: &Self::CircuitConfig,
+    ) -> Vec<(String, Range<usize>)> {
+        O::describe_instance(config)
     }
 
     fn gate_config(&self) -> &Self::GateConfig {
@@ -624,6 +1153,88 @@ where
             SerdeFormat::RawBytesUnchecked,
         )
     }
+
+    fn write_proving_key<W>(
+        pk: &ProvingKey<G1Affine>,
+        writer: &mut W,
+    ) -> Result<(), std::io::Error>
+    where
+        W: std::io::Write,
+    {
+        pk.write(writer, SerdeFormat::RawBytesUnchecked)
+    }
+
+    fn write_verifying_key<W>(
+        vk: &VerifyingKey<G1Affine>,
+        writer: &mut W,
+    ) -> Result<(), std::io::Error>
+    where
+        W: std::io::Write,
+    {
+        vk.write(writer, SerdeFormat::RawBytesUnchecked)
+    }
+}
+
+/// Returns the number of public inputs of the outer circuit for `config`
+/// following the KZG accumulator: `num_proof_ids` (if
+/// [`UpaConfig::public_num_proof_ids`] is set), the final digest (2 field
+/// elements) and the vk digest (2 field elements, if
+/// [`UpaConfig::include_vk_digest`] is set).  These are forwarded from the end of the `KeccakCircuit`
+/// instance, and are all that [`SafeCircuit::compute_instance`] returns.
+pub fn outer_output_len(config: &UpaConfig) -> usize {
+    config.public_num_proof_ids as usize
+        + FINAL_DIGEST_LEN
+        + FINAL_DIGEST_LEN * config.include_vk_digest as usize
+}
+
+/// Returns the number of public inputs of the outer circuit for `config`,
+/// without constructing the circuit.  This is the full instance, namely the
+/// KZG accumulator (2 G1 points, each as `2 * num_limbs` field elements)
+/// followed by the outputs (see [`outer_output_len`]).
+pub fn outer_instance_len(config: &UpaConfig) -> usize {
+    4 * config.outer_config.num_limbs + outer_output_len(config)
+}
+
+/// Appends the segments of the KZG accumulator (see
+/// [`OuterInstance::accumulator_limbs`]), with `num_limbs` limbs per
+/// coordinate, to `segments`.
+pub(crate) fn push_accumulator_segments(
+    segments: &mut Vec<(String, Range<usize>)>,
+    num_limbs: usize,
+) {
+    for name in [
+        "accumulator.lhs.x",
+        "accumulator.lhs.y",
+        "accumulator.rhs.x",
+        "accumulator.rhs.y",
+    ] {
+        push_instance_segment(segments, name, num_limbs);
+    }
+}
+
+/// Appends the segments of the outputs (see [`outer_output_len`]) of an
+/// outer circuit for `config` to `segments`, with names prefixed by
+/// `prefix`.
+pub(crate) fn push_output_segments(
+    segments: &mut Vec<(String, Range<usize>)>,
+    config: &UpaConfig,
+    prefix: &str,
+) {
+    if config.public_num_proof_ids {
+        push_instance_segment(segments, format!("{prefix}num_proof_ids"), 1);
+    }
+    push_instance_segment(
+        segments,
+        format!("{prefix}final_digest"),
+        FINAL_DIGEST_LEN,
+    );
+    if config.include_vk_digest {
+        push_instance_segment(
+            segments,
+            format!("{prefix}vk_digest"),
+            FINAL_DIGEST_LEN,
+        );
+    }
 }
 
 /// Asserts that the environment variable `FLEX_GATE_CONFIG_PARAMS` is
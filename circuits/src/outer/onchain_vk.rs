@@ -0,0 +1,255 @@
+//! The outer circuit verifying key data embedded in the EVM verifier, and
+//! its extraction from the verifier Yul code.
+
+use crate::outer::utils::compile_outer_protocol;
+use core::fmt;
+use halo2_base::halo2_proofs::{
+    arithmetic::CurveAffine,
+    halo2curves::{
+        bn256::{Bn256, Fq, Fq2, Fr, G1Affine, G2Affine},
+        group::ff::PrimeField,
+    },
+    plonk::VerifyingKey,
+    poly::kzg::commitment::ParamsKZG,
+};
+use snark_verifier::verifier::plonk::PlonkProtocol;
+
+/// Number of hex digits in each 32-byte constant of the verifier.
+const WORD_HEX_LEN: usize = 64;
+
+/// Number of constants holding the G2 points `g2` and `s_g2`.
+const G2_CONSTANTS_LEN: usize = 8;
+
+/// The verifying key data which the generated EVM verifier embeds as
+/// constants: the initial transcript state (a digest of the VK), the
+/// commitments to the preprocessed (fixed and permutation) polynomials and
+/// the G2 points `g2` and `s_g2` of the SRS used in the final pairing.
+///
+/// # Layout in the verifier
+///
+/// The Yul code generated by [`gen_outer_evm_verifier`] stores the initial
+/// transcript state as `mstore(0x0, <decimal>)`, and every other constant
+/// as a 32-byte hex word `mstore(<address>, 0x<64 hex digits>)`, in the
+/// order: the G1 generator of the SRS, `x, y` of each preprocessed
+/// commitment, then `x.c1, x.c0, y.c1, y.c0` of `g2` and of `s_g2` (the
+/// order expected by the pairing precompile).  See [`Self::from_yul`].
+///
+/// [`gen_outer_evm_verifier`]: crate::outer::utils::gen_outer_evm_verifier
+#[derive(Clone, Debug, PartialEq)]
+pub struct OnchainVerifyingKey {
+    pub transcript_initial_state: Fr,
+    pub preprocessed: Vec<G1Affine>,
+    pub g2: G2Affine,
+    pub s_g2: G2Affine,
+}
+
+/// Reason for which verifier Yul code does not embed a valid
+/// [`OnchainVerifyingKey`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OnchainVerifyingKeyError {
+    /// No `mstore(0x0, <decimal>)` holding the initial transcript state.
+    MissingTranscriptState,
+    /// The number of 32-byte hex constants does not match the layout.
+    InvalidNumConstants(usize),
+    /// The first point is not the G1 generator.
+    MissingG1Generator,
+    /// The constant at this index is not a canonical field element.  The
+    /// initial transcript state has index 0, followed by the hex constants.
+    InvalidFieldElement(usize),
+    /// The preprocessed commitment at this index is not on the curve.
+    InvalidG1Point(usize),
+    /// `g2` or `s_g2` is not on the curve.
+    InvalidG2Point,
+    /// The number of preprocessed commitments differs from that of the
+    /// verifying key (`expected`).
+    UnexpectedNumPreprocessed { expected: usize, actual: usize },
+}
+
+impl fmt::Display for OnchainVerifyingKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OnchainVerifyingKeyError::MissingTranscriptState => {
+                write!(f, "no initial transcript state in verifier")
+            }
+            OnchainVerifyingKeyError::InvalidNumConstants(num_constants) => {
+                write!(f, "unexpected number of constants: {num_constants}")
+            }
+            OnchainVerifyingKeyError::MissingG1Generator => {
+                write!(f, "first verifier constant is not the G1 generator")
+            }
+            OnchainVerifyingKeyError::InvalidFieldElement(i) => {
+                write!(f, "constant {i} is not a valid field element")
+            }
+            OnchainVerifyingKeyError::InvalidG1Point(i) => {
+                write!(f, "preprocessed commitment {i} is not on the curve")
+            }
+            OnchainVerifyingKeyError::InvalidG2Point => {
+                write!(f, "G2 point is not on the curve")
+            }
+            OnchainVerifyingKeyError::UnexpectedNumPreprocessed {
+                expected,
+                actual,
+            } => write!(
+                f,
+                "expected {expected} preprocessed commitments, got {actual}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OnchainVerifyingKeyError {}
+
+impl OnchainVerifyingKey {
+    /// Returns the data embedded in the EVM verifier generated (by
+    /// [`gen_outer_evm_verifier`]) for `outer_vk` with `num_instance`
+    /// public inputs.
+    ///
+    /// [`gen_outer_evm_verifier`]: crate::outer::utils::gen_outer_evm_verifier
+    pub fn new(
+        params: &ParamsKZG<Bn256>,
+        outer_vk: &VerifyingKey<G1Affine>,
+        num_instance: usize,
+    ) -> Self {
+        let protocol =
+            compile_outer_protocol(params, outer_vk, vec![num_instance]);
+        Self {
+            transcript_initial_state: protocol
+                .transcript_initial_state
+                .expect("protocol has no initial transcript state"),
+            preprocessed: protocol.preprocessed,
+            g2: params.g2(),
+            s_g2: params.s_g2(),
+        }
+    }
+
+    /// Returns the [`PlonkProtocol`] of `outer_vk` (with `num_instance`
+    /// public inputs), with the VK-dependent constants replaced by those of
+    /// `self`.  Only the structure of `outer_vk` (its constraint system) is
+    /// used.
+    pub fn protocol(
+        &self,
+        params: &ParamsKZG<Bn256>,
+        outer_vk: &VerifyingKey<G1Affine>,
+        num_instance: usize,
+    ) -> Result<PlonkProtocol<G1Affine>, OnchainVerifyingKeyError> {
+        let mut protocol =
+            compile_outer_protocol(params, outer_vk, vec![num_instance]);
+        if protocol.preprocessed.len() != self.preprocessed.len() {
+            return Err(OnchainVerifyingKeyError::UnexpectedNumPreprocessed {
+                expected: protocol.preprocessed.len(),
+                actual: self.preprocessed.len(),
+            });
+        }
+        protocol.preprocessed = self.preprocessed.clone();
+        protocol.transcript_initial_state = Some(self.transcript_initial_state);
+        Ok(protocol)
+    }
+
+    /// Extracts the verifying key data from the Yul code of a verifier
+    /// generated by [`gen_outer_evm_verifier`], checking that all points are
+    /// on the curve.  See [`OnchainVerifyingKey`] for the layout.
+    ///
+    /// [`gen_outer_evm_verifier`]: crate::outer::utils::gen_outer_evm_verifier
+    pub fn from_yul(yul: &str) -> Result<Self, OnchainVerifyingKeyError> {
+        let mut transcript_initial_state = None;
+        let mut words = Vec::new();
+        for (address, value) in yul.lines().filter_map(parse_mstore) {
+            if let Some(word) = value.strip_prefix("0x") {
+                if word.len() == WORD_HEX_LEN {
+                    words.push(word);
+                }
+            } else if address == "0x0"
+                && value.bytes().all(|b| b.is_ascii_digit())
+                && transcript_initial_state.is_none()
+            {
+                transcript_initial_state = Some(value);
+            }
+        }
+        let transcript_initial_state = transcript_initial_state
+            .ok_or(OnchainVerifyingKeyError::MissingTranscriptState)?;
+        let transcript_initial_state =
+            Fr::from_str_vartime(transcript_initial_state)
+                .ok_or(OnchainVerifyingKeyError::InvalidFieldElement(0))?;
+
+        // 2 words for the generator and for each preprocessed commitment,
+        // 8 words for the G2 points.
+        let num_words = words.len();
+        if num_words < 2 + G2_CONSTANTS_LEN || num_words % 2 != 0 {
+            return Err(OnchainVerifyingKeyError::InvalidNumConstants(
+                num_words,
+            ));
+        }
+        // Index 0 is the initial transcript state.
+        let fq = |i: usize| {
+            word_to_fq(words[i])
+                .ok_or(OnchainVerifyingKeyError::InvalidFieldElement(i + 1))
+        };
+        let g1_point = |start: usize| -> Result<_, OnchainVerifyingKeyError> {
+            Ok(G1Affine {
+                x: fq(start)?,
+                y: fq(start + 1)?,
+            })
+        };
+
+        if g1_point(0)?
+            != (G1Affine {
+                x: Fq::from(1),
+                y: Fq::from(2),
+            })
+        {
+            return Err(OnchainVerifyingKeyError::MissingG1Generator);
+        }
+        let num_preprocessed = (num_words - 2 - G2_CONSTANTS_LEN) / 2;
+        let preprocessed = (0..num_preprocessed)
+            .map(|i| {
+                let point = g1_point(2 + 2 * i)?;
+                match bool::from(point.is_on_curve()) {
+                    true => Ok(point),
+                    false => Err(OnchainVerifyingKeyError::InvalidG1Point(i)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let g2_point = |start: usize| {
+            let point = G2Affine {
+                x: Fq2 {
+                    c0: fq(start + 1)?,
+                    c1: fq(start)?,
+                },
+                y: Fq2 {
+                    c0: fq(start + 3)?,
+                    c1: fq(start + 2)?,
+                },
+            };
+            match bool::from(point.is_on_curve()) {
+                true => Ok(point),
+                false => Err(OnchainVerifyingKeyError::InvalidG2Point),
+            }
+        };
+        let g2_start = num_words - G2_CONSTANTS_LEN;
+
+        Ok(Self {
+            transcript_initial_state,
+            preprocessed,
+            g2: g2_point(g2_start)?,
+            s_g2: g2_point(g2_start + 4)?,
+        })
+    }
+}
+
+/// Splits a line of Yul code of the form `mstore(<address>, <value>)` into
+/// its address and value.
+fn parse_mstore(line: &str) -> Option<(&str, &str)> {
+    line.trim()
+        .strip_prefix("mstore(")?
+        .strip_suffix(')')?
+        .split_once(", ")
+}
+
+/// Parses a big-endian hex word, returning `None` if it is not a canonical
+/// field element.
+fn word_to_fq(word: &str) -> Option<Fq> {
+    let mut repr = <Fq as PrimeField>::Repr::default();
+    hex::decode_to_slice(word, repr.as_mut()).ok()?;
+    repr.as_mut().reverse();
+    Fq::from_repr(repr).into()
+}
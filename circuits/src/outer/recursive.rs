@@ -0,0 +1,348 @@
+//! Recursive aggregation of outer circuit proofs.
+use crate::{
+    outer::{
+        outer_instance_len, outer_output_len, push_accumulator_segments,
+        push_output_segments, OuterCircuit, OuterGateConfig, OuterInstance,
+        SnarkIter,
+    },
+    utils::{
+        commitment_point::g1affine_into_limbs, hashing::SECURE_MDS,
+        upa_config::UpaConfig,
+    },
+    CircuitWithLimbsConfig,
+};
+use core::{iter, ops::Range};
+use halo2_base::halo2_proofs::{
+    halo2curves::bn256::{Bn256, Fr, G1Affine},
+    poly::{
+        commitment::{Prover, Verifier},
+        kzg::{
+            commitment::{KZGCommitmentScheme, ParamsKZG},
+            msm::DualMSM,
+            strategy::GuardKZG,
+        },
+    },
+};
+use serde::{Deserialize, Serialize};
+use snark_verifier::{
+    loader::native::NativeLoader, util::transcript::TranscriptWrite,
+    verifier::plonk::PlonkProtocol,
+};
+use snark_verifier_sdk::{
+    halo2::{aggregation::AggregationCircuit, PoseidonTranscript},
+    Snark,
+};
+
+/// Configuration of a [`RecursiveOuterCircuit`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecursiveOuterConfig {
+    /// Configuration of the UPA instance whose outer proofs are aggregated
+    pub child_config: UpaConfig,
+    /// Number of outer proofs aggregated by each recursive proof
+    pub num_children: u32,
+    /// Configuration of the recursive circuit itself
+    pub outer_config: CircuitWithLimbsConfig,
+}
+
+impl RecursiveOuterConfig {
+    pub fn new(
+        child_config: UpaConfig,
+        num_children: u32,
+        outer_config: CircuitWithLimbsConfig,
+    ) -> Self {
+        assert!(num_children > 0, "num_children must be positive");
+        Self {
+            child_config,
+            num_children,
+            outer_config,
+        }
+    }
+
+    /// Number of public inputs of each child outer proof
+    pub fn child_instance_len(&self) -> usize {
+        outer_instance_len(&self.child_config)
+    }
+}
+
+/// Returns the number of public inputs of the recursive circuit for
+/// `config`.  This is the KZG accumulator (2 G1 points, each as `2 *
+/// num_limbs` field elements) followed by the outputs of each child (see
+/// [`outer_output_len`]).
+pub fn recursive_outer_instance_len(config: &RecursiveOuterConfig) -> usize {
+    4 * config.outer_config.num_limbs
+        + outer_output_len(&config.child_config) * config.num_children as usize
+}
+
+/// The instances of the child outer proofs, from which the recursive
+/// circuit's (non-accumulator) public inputs are computed.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecursiveOuterInstanceInputs {
+    child_instances: Vec<Vec<Fr>>,
+}
+
+impl RecursiveOuterInstanceInputs {
+    /// Constructor that checks the number and size of `child_instances`.
+    pub fn new(
+        config: &RecursiveOuterConfig,
+        child_instances: Vec<Vec<Fr>>,
+    ) -> Self {
+        assert_eq!(
+            child_instances.len(),
+            config.num_children as usize,
+            "Unexpected number of child instances"
+        );
+        let child_instance_len = config.child_instance_len();
+        for instance in child_instances.iter() {
+            assert_eq!(
+                instance.len(),
+                child_instance_len,
+                "Unexpected child instance size"
+            );
+        }
+        Self { child_instances }
+    }
+}
+
+/// The data required to create a [`RecursiveOuterCircuit`] witness, namely
+/// the child outer circuit [Snark]s.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecursiveOuterCircuitInputs {
+    child_snarks: Vec<Snark>,
+}
+
+impl RecursiveOuterCircuitInputs {
+    /// Constructor that checks the number and instance sizes of
+    /// `child_snarks`.
+    ///
+    /// Note: the child proofs must have been generated with a Poseidon
+    /// transcript (see
+    /// [`prove_outer_snark`](crate::outer::utils::prove_outer_snark)), and
+    /// their protocol must specify the accumulator indices.
+    pub fn new(
+        config: &RecursiveOuterConfig,
+        child_snarks: Vec<Snark>,
+    ) -> Self {
+        // Consistency check
+        let _inputs = RecursiveOuterInstanceInputs::new(
+            config,
+            child_snarks
+                .iter()
+                .map(|s| s.instances[0].clone())
+                .collect(),
+        );
+        for snark in child_snarks.iter() {
+            assert!(
+                snark.protocol.accumulator_indices.len() == 1,
+                "Child protocol must specify the KZG accumulator indices"
+            );
+        }
+        Self { child_snarks }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RecursiveOuterKeygenInputs<'p> {
+    child_protocol: &'p PlonkProtocol<G1Affine>,
+    pub outer_params: &'p ParamsKZG<Bn256>,
+}
+
+impl<'p> RecursiveOuterKeygenInputs<'p> {
+    /// Constructor.  `child_protocol` is the protocol of the outer circuit
+    /// whose proofs are aggregated.
+    pub fn new(
+        child_protocol: &'p PlonkProtocol<G1Affine>,
+        outer_params: &'p ParamsKZG<Bn256>,
+    ) -> Self {
+        RecursiveOuterKeygenInputs {
+            child_protocol,
+            outer_params,
+        }
+    }
+
+    /// Return reference to `child_protocol`
+    pub fn child_protocol(&self) -> &'p PlonkProtocol<G1Affine> {
+        self.child_protocol
+    }
+}
+
+/// Returns a "dummy" outer circuit [Snark] with the given `protocol`.  The
+/// proof it contains is not valid, but all group elements (including the
+/// KZG accumulator in the instance) are valid points.  This is intended to
+/// be a helper in `RecursiveOuterCircuit` key generation.
+fn dummy_outer_snark(
+    config: &RecursiveOuterConfig,
+    protocol: &PlonkProtocol<G1Affine>,
+) -> Snark {
+    let child_outer_config = &config.child_config.outer_config;
+    let accumulator_point_limbs = g1affine_into_limbs::<Fr>(
+        &G1Affine::generator(),
+        child_outer_config.limb_bits,
+        child_outer_config.num_limbs,
+    );
+    let instance: Vec<Fr> = iter::repeat(accumulator_point_limbs)
+        .take(2)
+        .flatten()
+        .chain(
+            iter::repeat(Fr::from(0))
+                .take(outer_output_len(&config.child_config)),
+        )
+        .collect();
+    assert_eq!(instance.len(), config.child_instance_len());
+
+    let proof = {
+        let mut transcript = PoseidonTranscript::<NativeLoader, Vec<u8>>::new::<
+            SECURE_MDS,
+        >(vec![]);
+        let num_points = protocol.num_witness.iter().sum::<usize>()
+            + protocol.quotient.num_chunk();
+        for _ in 0..num_points {
+            transcript.write_ec_point(G1Affine::generator()).unwrap();
+        }
+        for _ in 0..protocol.evaluations.len() {
+            transcript.write_scalar(Fr::from(1)).unwrap();
+        }
+        // The number of commitments in the opening proof depends on the
+        // multi-open scheme, but never exceeds the number of queries.  Any
+        // unread points are ignored.
+        for _ in 0..protocol.queries.len() {
+            transcript.write_ec_point(G1Affine::generator()).unwrap();
+        }
+        transcript.finalize()
+    };
+    Snark::new(protocol.clone(), vec![instance], proof)
+}
+
+/// `RecursiveOuterCircuit` aggregates some number of outer circuit proofs
+/// (e.g. `UniversalOuterCircuit` proofs) into a single proof.  Its public
+/// inputs are the KZG accumulator followed by the outputs (final digest, and
+/// `num_proof_ids` if public) of the children, in order.
+pub struct RecursiveOuterCircuit {
+    inner: AggregationCircuit,
+    gate_config: OuterGateConfig,
+}
+
+impl OuterCircuit for RecursiveOuterCircuit {
+    type Config = RecursiveOuterConfig;
+
+    type CircuitInputs = RecursiveOuterCircuitInputs;
+
+    type InstanceInputs = RecursiveOuterInstanceInputs;
+
+    type KeygenInputs<'p> = RecursiveOuterKeygenInputs<'p>;
+
+    fn gate_config(&self) -> &OuterGateConfig {
+        &self.gate_config
+    }
+
+    fn degree_bits(config: &Self::Config) -> usize {
+        config.outer_config.degree_bits as usize
+    }
+
+    fn lookup_bits(config: &Self::Config) -> usize {
+        config.outer_config.lookup_bits
+    }
+
+    fn snarks(inputs: Self::CircuitInputs) -> SnarkIter<'static> {
+        Box::new(inputs.child_snarks.into_iter())
+    }
+
+    fn outer_params<'p>(
+        inputs: &Self::KeygenInputs<'p>,
+    ) -> &'p ParamsKZG<Bn256> {
+        inputs.outer_params
+    }
+
+    fn keygen_circuit_inputs<'p, P, V>(
+        config: &Self::Config,
+        inputs: &Self::KeygenInputs<'p>,
+    ) -> Self::CircuitInputs
+    where
+        P: Prover<'p, KZGCommitmentScheme<Bn256>>,
+        V: Verifier<
+            'p,
+            KZGCommitmentScheme<Bn256>,
+            Guard = GuardKZG<'p, Bn256>,
+            MSMAccumulator = DualMSM<'p, Bn256>,
+        >,
+    {
+        let child_snarks =
+            iter::repeat(dummy_outer_snark(config, inputs.child_protocol()))
+                .take(config.num_children as usize)
+                .collect();
+        RecursiveOuterCircuitInputs::new(config, child_snarks)
+    }
+
+    fn compute_instance(
+        config: &Self::Config,
+        inputs: &Self::InstanceInputs,
+    ) -> Vec<Fr> {
+        // The constructor of `inputs` checks the instance sizes, so we can
+        // read the outputs directly.
+        inputs
+            .child_instances
+            .iter()
+            .flat_map(|instance| {
+                OuterInstance::from_flat(&config.child_config, instance)
+                    .unwrap_or_else(|e| panic!("invalid child instance: {e}"))
+                    .outputs()
+            })
+            .collect()
+    }
+
+    fn instance_len(config: &Self::Config) -> usize {
+        recursive_outer_instance_len(config)
+    }
+
+    fn describe_instance(config: &Self::Config) -> Vec<(String, Range<usize>)> {
+        let mut segments = Vec::new();
+        push_accumulator_segments(&mut segments, config.outer_config.num_limbs);
+        for i in 0..config.num_children {
+            push_output_segments(
+                &mut segments,
+                &config.child_config,
+                &format!("child{i}."),
+            );
+        }
+        segments
+    }
+
+    fn inner(&self) -> &AggregationCircuit {
+        &self.inner
+    }
+
+    /// Exposes the outputs (the public inputs following the accumulator, see
+    /// [`outer_output_len`]) of each child outer proof.  The child KZG accumulators are folded into the
+    /// accumulator of `inner` by the [AggregationCircuit] itself.
+    fn expose_final_digest_and_constrain(
+        inner: &mut AggregationCircuit,
+        config: &Self::Config,
+    ) {
+        assert_eq!(
+            inner.previous_instances.len(),
+            config.num_children as usize,
+            "Unexpected number of child instances"
+        );
+        let child_instance_len = config.child_instance_len();
+        let child_output_len = outer_output_len(&config.child_config);
+        let child_outputs = inner
+            .previous_instances
+            .iter()
+            .flat_map(|child_instance| {
+                assert_eq!(
+                    child_instance.len(),
+                    child_instance_len,
+                    "Unexpected child instance size"
+                );
+                child_instance[child_instance_len - child_output_len..].to_vec()
+            })
+            .collect::<Vec<_>>();
+        inner.inner.assigned_instances.extend(child_outputs);
+    }
+
+    fn from_inner(
+        inner: AggregationCircuit,
+        gate_config: OuterGateConfig,
+    ) -> Self {
+        Self { inner, gate_config }
+    }
+}
@@ -7,13 +7,28 @@ use crate::{
         utils::{
             inputs_per_application_proof, keccak_inputs_from_ubv_instances,
         },
+        KeccakConfig,
     },
-    outer::{OuterCircuit, OuterGateConfig},
-    utils::upa_config::UpaConfig,
+    outer::{
+        outer_instance_len, outer_output_len, push_accumulator_segments,
+        push_output_segments, BatchVerifyOuterCircuit, OuterCircuit,
+        OuterCircuitInputs, OuterGateConfig, OuterInstanceInputs,
+        OuterKeygenInputs, SnarkIter,
+    },
+    utils::upa_config::{UpaConfig, UpaConfigError},
 };
+use core::ops::Range;
 use halo2_base::halo2_proofs::{
     halo2curves::bn256::{Bn256, Fr},
-    poly::kzg::{commitment::ParamsKZG, multiopen::ProverSHPLONK},
+    poly::{
+        commitment::{Prover, Verifier},
+        kzg::{
+            commitment::{KZGCommitmentScheme, ParamsKZG},
+            msm::DualMSM,
+            multiopen::ProverSHPLONK,
+            strategy::GuardKZG,
+        },
+    },
 };
 use itertools::Itertools;
 use snark_verifier_sdk::{halo2::aggregation::AggregationCircuit, Snark};
@@ -29,15 +44,11 @@ pub struct UniversalOuterCircuit {
 impl OuterCircuit for UniversalOuterCircuit {
     type Config = UpaConfig;
 
-    type BatchVerifyConfig = UniversalBatchVerifierConfig;
+    type CircuitInputs = OuterCircuitInputs<Self>;
 
-    fn bv_config(config: &Self::Config) -> Self::BatchVerifyConfig {
-        config.into()
-    }
+    type InstanceInputs = OuterInstanceInputs<Self>;
 
-    fn keccak_config(config: &Self::Config) -> crate::keccak::KeccakConfig {
-        config.into()
-    }
+    type KeygenInputs<'p> = OuterKeygenInputs<'p>;
 
     fn gate_config(&self) -> &OuterGateConfig {
         &self.gate_config
@@ -47,30 +58,55 @@ impl OuterCircuit for UniversalOuterCircuit {
         config.outer_config.degree_bits as usize
     }
 
-    fn outer_batch_size(config: &Self::Config) -> usize {
-        config.outer_batch_size as usize
+    fn snarks(inputs: Self::CircuitInputs) -> SnarkIter<'static> {
+        inputs.into_snarks()
     }
 
-    fn dummy_bv_snark(
-        bv_params: &ParamsKZG<Bn256>,
-        bv_config: &Self::BatchVerifyConfig,
-    ) -> Snark {
-        dummy_ubv_snark::<ProverSHPLONK<Bn256>>(bv_params, bv_config)
+    fn outer_params<'p>(
+        inputs: &Self::KeygenInputs<'p>,
+    ) -> &'p ParamsKZG<Bn256> {
+        inputs.outer_params
     }
 
-    fn keccak_inputs_from_bv_instances<'a>(
-        bv_config: &Self::BatchVerifyConfig,
-        bv_instances: impl ExactSizeIterator<Item = &'a [Fr]>,
-        num_proof_ids: Option<u64>,
-    ) -> KeccakCircuitInputs<Fr> {
-        KeccakCircuitInputs {
-            inputs: keccak_inputs_from_ubv_instances(
-                bv_instances,
-                bv_config.max_num_public_inputs as usize,
-                bv_config.inner_batch_size as usize,
-            ),
-            num_proof_ids,
-        }
+    fn keygen_circuit_inputs<'p, P, V>(
+        config: &Self::Config,
+        inputs: &Self::KeygenInputs<'p>,
+    ) -> Self::CircuitInputs
+    where
+        P: Prover<'p, KZGCommitmentScheme<Bn256>>,
+        V: Verifier<
+            'p,
+            KZGCommitmentScheme<Bn256>,
+            Guard = GuardKZG<'p, Bn256>,
+            MSMAccumulator = DualMSM<'p, Bn256>,
+        >,
+    {
+        OuterCircuitInputs::keygen_default::<P, V>(
+            config,
+            inputs.bv_params(),
+            inputs.keccak_params(),
+        )
+    }
+
+    fn compute_instance(
+        config: &Self::Config,
+        inputs: &Self::InstanceInputs,
+    ) -> Vec<Fr> {
+        // Note, the construction of `inputs` requires that that all inputs are
+        // consistent, including the final digest and `num_proof_ids`.  Hence
+        // we can just read them out.
+        inputs.outputs(config).to_vec()
+    }
+
+    fn instance_len(config: &Self::Config) -> usize {
+        outer_instance_len(config)
+    }
+
+    fn describe_instance(config: &Self::Config) -> Vec<(String, Range<usize>)> {
+        let mut segments = Vec::new();
+        push_accumulator_segments(&mut segments, config.outer_config.num_limbs);
+        push_output_segments(&mut segments, config, "");
+        segments
     }
 
     fn inner(&self) -> &AggregationCircuit {
@@ -84,6 +120,17 @@ impl OuterCircuit for UniversalOuterCircuit {
         let inner_batch_size = config.inner_batch_size as usize;
         let outer_batch_size = config.outer_batch_size as usize;
         let num_pub_ins = config.max_num_app_public_inputs as usize;
+        // The Keccak instance layout assumes the default limb configuration
+        // for the UBV instances and the outer accumulator.
+        config.bv_config.assert_default_limbs();
+        config.outer_config.assert_default_limbs();
+        // The Keccak circuit hashes the commitment entries of the UBV
+        // instances, which both circuits must treat the same way.
+        assert_eq!(
+            Self::bv_config(config).allow_commitments,
+            Self::keccak_config(config).allow_commitments,
+            "UBV and Keccak configs disagree on allow_commitments"
+        );
 
         let mut builder = inner.inner.circuit.0.builder.borrow_mut();
         let ctx = builder.main(0);
@@ -95,7 +142,8 @@ impl OuterCircuit for UniversalOuterCircuit {
             inner_batch_size * inputs_per_application_proof(num_pub_ins);
         assert_eq!(
             keccak_instances.len(),
-            outer_batch_size * num_keccak_inputs_per_ubv_snark + 2,
+            outer_batch_size * num_keccak_inputs_per_ubv_snark
+                + outer_output_len(config),
             "Unexpected Keccak input size"
         );
 
@@ -110,7 +158,8 @@ impl OuterCircuit for UniversalOuterCircuit {
             }
         }
 
-        // Expose keccak output digest
+        // Expose the keccak outputs: `num_proof_ids` (if public) and the
+        // final digest
         inner.inner.assigned_instances.extend_from_slice(
             &keccak_instances
                 [(outer_batch_size * num_keccak_inputs_per_ubv_snark)..],
@@ -124,3 +173,48 @@ impl OuterCircuit for UniversalOuterCircuit {
         Self { inner, gate_config }
     }
 }
+
+impl BatchVerifyOuterCircuit for UniversalOuterCircuit {
+    type BatchVerifyConfig = UniversalBatchVerifierConfig;
+
+    fn bv_config(config: &Self::Config) -> Self::BatchVerifyConfig {
+        config.into()
+    }
+
+    fn keccak_config(config: &Self::Config) -> KeccakConfig {
+        config.keccak_config()
+    }
+
+    fn check_keccak_config(
+        config: &Self::Config,
+        keccak_config: &KeccakConfig,
+    ) -> Result<(), UpaConfigError> {
+        config.check_keccak_config(keccak_config)
+    }
+
+    fn outer_batch_size(config: &Self::Config) -> usize {
+        config.outer_batch_size as usize
+    }
+
+    fn dummy_bv_snark(
+        bv_params: &ParamsKZG<Bn256>,
+        bv_config: &Self::BatchVerifyConfig,
+    ) -> Snark {
+        dummy_ubv_snark::<ProverSHPLONK<Bn256>>(bv_params, bv_config)
+    }
+
+    fn keccak_inputs_from_bv_instances<'a>(
+        bv_config: &Self::BatchVerifyConfig,
+        bv_instances: impl ExactSizeIterator<Item = &'a [Fr]>,
+        num_proof_ids: Option<u64>,
+    ) -> KeccakCircuitInputs<Fr> {
+        KeccakCircuitInputs {
+            inputs: keccak_inputs_from_ubv_instances(
+                bv_instances,
+                bv_config.max_num_public_inputs as usize,
+                bv_config.inner_batch_size as usize,
+            ),
+            num_proof_ids,
+        }
+    }
+}
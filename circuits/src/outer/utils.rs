@@ -2,39 +2,63 @@ extern crate alloc;
 
 use crate::{
     outer::{
-        OuterCircuit, OuterCircuitInputs, OuterCircuitWrapper, OuterGateConfig,
-        OuterKeygenInputs,
+        onchain_vk::OnchainVerifyingKey, OuterCircuit, OuterCircuitWrapper,
+        OuterGateConfig,
     },
+    utils::{commitment_point::limbs_into_g1affine, hashing::SECURE_MDS},
     SafeCircuit,
 };
 use alloc::rc::Rc;
+use ethers_core::{
+    abi::{decode, encode, ParamType, Token},
+    types::U256,
+};
 use halo2_base::{
     gates::builder::MultiPhaseThreadBreakPoints,
     halo2_proofs::{
-        halo2curves::bn256::{Bn256, Fq, Fr, G1Affine},
+        halo2curves::{
+            bn256::{Bn256, Fq, Fr, G1Affine, G1},
+            group::{
+                ff::{Field, PrimeField},
+                Curve, Group,
+            },
+            pairing::Engine,
+        },
         plonk::{
-            keygen_pk, keygen_vk, Error as Halo2ProofsError, ProvingKey,
-            VerifyingKey,
+            keygen_pk, keygen_vk, verify_proof, Error as Halo2ProofsError,
+            ProvingKey, VerifyingKey,
         },
         poly::{
             commitment::{ParamsProver, Prover, Verifier},
             kzg::{
                 commitment::{KZGCommitmentScheme, ParamsKZG},
                 msm::DualMSM,
-                strategy::GuardKZG,
+                strategy::{AccumulatorStrategy, GuardKZG},
             },
+            VerificationStrategy,
         },
+        transcript::TranscriptReadBuffer,
     },
 };
+use rand_core::OsRng;
 use snark_verifier::{
-    loader::evm::EvmLoader,
-    system::halo2::{compile, transcript::evm::EvmTranscript, Config},
-    verifier::SnarkVerifier,
+    loader::{evm::EvmLoader, native::NativeLoader},
+    pcs::kzg::KzgDecidingKey,
+    system::halo2::{
+        compile,
+        transcript::{evm::EvmTranscript, halo2::ChallengeScalar},
+        Config,
+    },
+    verifier::{plonk::PlonkProtocol, SnarkVerifier},
+    Error as SnarkVerifierError,
 };
 use snark_verifier_sdk::{
     evm::{gen_evm_proof, EvmKzgAccumulationScheme},
-    halo2::aggregation::{AggregationCircuit, Halo2KzgAccumulationScheme},
-    CircuitExt, PlonkVerifier,
+    halo2::{
+        aggregation::{AggregationCircuit, Halo2KzgAccumulationScheme},
+        gen_snark, PoseidonTranscript,
+    },
+    CircuitExt, PlonkVerifier, Snark,
 };
 
 /// Computes a proving key for `OuterCircuit` of type
@@ -51,7 +75,7 @@ use snark_verifier_sdk::{
 /// consistent with each other.
 pub fn gen_outer_pk<'params, AS, O, P, V>(
     outer_config: &O::Config,
-    inputs: &'params OuterKeygenInputs,
+    inputs: &'params O::KeygenInputs<'params>,
 ) -> Result<
     (
         ProvingKey<G1Affine>,
@@ -75,11 +99,16 @@ where
 {
     let circuit =
         OuterCircuitWrapper::<AS, O, P, V>::keygen(outer_config, inputs);
-    let vk = keygen_vk(inputs.outer_params, &circuit)?;
-    let pk = keygen_pk(inputs.outer_params, vk, &circuit)?;
+    let outer_params = O::outer_params(inputs);
+    let vk = keygen_vk(outer_params, &circuit)?;
+    let pk = keygen_pk(outer_params, vk, &circuit)?;
 
     let instance_size: usize = circuit.num_instance()[0];
-    assert!(instance_size == 14usize, "unexpected instance size");
+    assert_eq!(
+        instance_size,
+        O::instance_len(outer_config),
+        "unexpected instance size"
+    );
 
     Ok((
         pk,
@@ -103,7 +132,7 @@ where
 /// consistent with each other.
 pub fn gen_outer_vk<'params, AS, O, P, V>(
     outer_config: &O::Config,
-    inputs: &'params OuterKeygenInputs,
+    inputs: &'params O::KeygenInputs<'params>,
 ) -> Result<
     (
         VerifyingKey<G1Affine>,
@@ -127,10 +156,14 @@ where
 {
     let circuit =
         OuterCircuitWrapper::<AS, O, P, V>::keygen(outer_config, inputs);
-    let vk = keygen_vk(inputs.outer_params, &circuit)?;
+    let vk = keygen_vk(O::outer_params(inputs), &circuit)?;
 
     let instance_size: usize = circuit.num_instance()[0];
-    assert!(instance_size == 14usize, "unexpected instance size");
+    assert_eq!(
+        instance_size,
+        O::instance_len(outer_config),
+        "unexpected instance size"
+    );
 
     Ok((
         vk,
@@ -140,6 +173,59 @@ where
     ))
 }
 
+/// Computes a proving key for `OuterCircuit` from `vk`, the verifying key
+/// returned by [`gen_outer_vk`] for the same `outer_config` and `inputs`.
+/// This allows the VK generation step of [`gen_outer_pk`] to be skipped, for
+/// example when resuming an interrupted keygen.
+///
+/// Returns proving key, `FlexGateConfigParams`, and break points, which the
+/// caller may check against those returned by [`gen_outer_vk`].
+///
+/// Use of `SHPLONK` or `GWC19` is specified
+/// by the types `AS`, `P`, `V`.
+pub fn gen_outer_pk_from_vk<'params, AS, O, P, V>(
+    outer_config: &O::Config,
+    inputs: &'params O::KeygenInputs<'params>,
+    vk: VerifyingKey<G1Affine>,
+) -> Result<
+    (
+        ProvingKey<G1Affine>,
+        OuterGateConfig,
+        MultiPhaseThreadBreakPoints,
+        usize,
+    ),
+    Halo2ProofsError,
+>
+where
+    AS: for<'a> Halo2KzgAccumulationScheme<'a> + 'params,
+    O: OuterCircuit + 'params,
+    P: Prover<'params, KZGCommitmentScheme<Bn256>> + 'params,
+    V: Verifier<
+            'params,
+            KZGCommitmentScheme<Bn256>,
+            Guard = GuardKZG<'params, Bn256>,
+            MSMAccumulator = DualMSM<'params, Bn256>,
+        > + 'params,
+{
+    let circuit =
+        OuterCircuitWrapper::<AS, O, P, V>::keygen(outer_config, inputs);
+    let pk = keygen_pk(O::outer_params(inputs), vk, &circuit)?;
+
+    let instance_size: usize = circuit.num_instance()[0];
+    assert_eq!(
+        instance_size,
+        O::instance_len(outer_config),
+        "unexpected instance size"
+    );
+
+    Ok((
+        pk,
+        circuit.gate_config().clone(),
+        circuit.break_points(),
+        instance_size,
+    ))
+}
+
 /// Returns Yul code for EVM verifier of `OuterCircuit` proofs as
 /// a `String`.
 ///
@@ -157,17 +243,8 @@ pub fn gen_outer_evm_verifier<AS>(
 where
     AS: EvmKzgAccumulationScheme,
 {
-    // Compile verifying key into a `PlonkProtocol`.
     let protocol =
-        compile(
-            params,
-            outer_vk,
-            Config::kzg()
-                .with_num_instance(num_instance.clone())
-                .with_accumulator_indices(
-                    AggregationCircuit::accumulator_indices(),
-                ),
-        );
+        compile_outer_protocol(params, outer_vk, num_instance.clone());
 
     // Deciding key for pairing check
     let dk = (params.get_g()[0], params.g2(), params.s_g2()).into();
@@ -189,6 +266,91 @@ where
     loader.yul_code()
 }
 
+/// Compiles `outer_vk` into the [`PlonkProtocol`] of an `OuterCircuit`,
+/// whose KZG accumulator is at the start of its instance.
+pub(crate) fn compile_outer_protocol(
+    params: &ParamsKZG<Bn256>,
+    outer_vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+) -> PlonkProtocol<G1Affine> {
+    compile(
+        params,
+        outer_vk,
+        Config::kzg()
+            .with_num_instance(num_instance)
+            .with_accumulator_indices(AggregationCircuit::accumulator_indices()),
+    )
+}
+
+/// Verifies an `OuterCircuit` EVM proof (as produced by [`prove_outer`])
+/// natively, performing exactly the computation of the verifier generated
+/// by [`gen_outer_evm_verifier`]: the proof is read with an
+/// [`EvmTranscript`], the KZG accumulator encoded in `instance` is combined
+/// with the accumulator of the proof's own opening, and the result is
+/// decided with a single pairing check.  Hence the proof is accepted if and
+/// only if it would be accepted on-chain.
+///
+/// `Shplonk` or `GWC` variant is specified by the type `AS`.
+pub fn verify_outer_onchain_equivalent<AS>(
+    params: &ParamsKZG<Bn256>,
+    outer_vk: &VerifyingKey<G1Affine>,
+    proof: &[u8],
+    instance: &[Fr],
+) -> Result<(), SnarkVerifierError>
+where
+    AS: for<'a> Halo2KzgAccumulationScheme<'a>,
+{
+    let protocol =
+        compile_outer_protocol(params, outer_vk, vec![instance.len()]);
+    let dk = (params.get_g()[0], params.g2(), params.s_g2()).into();
+    verify_outer_protocol::<AS>(&dk, &protocol, proof, instance)
+}
+
+/// As [`verify_outer_onchain_equivalent`], but against the verifying key
+/// data deployed on-chain, given as an [`OnchainVerifyingKey`].  Only the
+/// structure of `outer_vk` is used: the preprocessed commitments, initial
+/// transcript state and the G2 points of the final pairing are taken from
+/// `onchain_vk`.
+pub fn verify_outer_with_onchain_vk<AS>(
+    params: &ParamsKZG<Bn256>,
+    outer_vk: &VerifyingKey<G1Affine>,
+    onchain_vk: &OnchainVerifyingKey,
+    proof: &[u8],
+    instance: &[Fr],
+) -> Result<(), SnarkVerifierError>
+where
+    AS: for<'a> Halo2KzgAccumulationScheme<'a>,
+{
+    let protocol = onchain_vk
+        .protocol(params, outer_vk, instance.len())
+        .map_err(|e| SnarkVerifierError::AssertionFailure(e.to_string()))?;
+    let dk = (params.get_g()[0], onchain_vk.g2, onchain_vk.s_g2).into();
+    verify_outer_protocol::<AS>(&dk, &protocol, proof, instance)
+}
+
+/// Reads `proof` with an [`EvmTranscript`] and verifies it against
+/// `protocol`, including the final pairing check.
+fn verify_outer_protocol<AS>(
+    dk: &KzgDecidingKey<Bn256>,
+    protocol: &PlonkProtocol<G1Affine>,
+    proof: &[u8],
+    instance: &[Fr],
+) -> Result<(), SnarkVerifierError>
+where
+    AS: for<'a> Halo2KzgAccumulationScheme<'a>,
+{
+    let mut transcript =
+        EvmTranscript::<G1Affine, NativeLoader, _, _>::new(proof);
+    let instances = vec![instance.to_vec()];
+    let proof = PlonkVerifier::<AS>::read_proof(
+        dk,
+        protocol,
+        &instances,
+        &mut transcript,
+    )?;
+    PlonkVerifier::<AS>::verify(dk, protocol, &instances, &proof)
+}
+
 /// Returns an `UniversalOuterCircuit` EVM proof and accompanying
 /// instances. The proof's validity is checked internally
 /// by `gen_evm_proof`. Use of `SHPLONK` or `GWC19` is specified
@@ -201,7 +363,7 @@ pub fn prove_outer<'params, AS, O, P, V>(
     outer_gate_config: &OuterGateConfig,
     pk: &'params ProvingKey<G1Affine>,
     break_points: MultiPhaseThreadBreakPoints,
-    outer_inputs: OuterCircuitInputs<O>,
+    outer_inputs: O::CircuitInputs,
     outer_srs: &'params ParamsKZG<Bn256>,
 ) -> (Vec<u8>, Vec<Fr>)
 where
@@ -228,3 +390,307 @@ where
         instances[0].clone(),
     )
 }
+
+/// Returns an `OuterCircuit` [Snark], whose proof uses a Poseidon transcript
+/// (rather than the Keccak-based EVM transcript used by [`prove_outer`]).
+/// Such proofs cannot be verified on-chain, but can be aggregated by a
+/// [`RecursiveOuterCircuit`](crate::outer::recursive::RecursiveOuterCircuit).
+/// The proof's validity is checked internally by `gen_snark`.  Use of
+/// `SHPLONK` or `GWC19` is specified by the types `AS`, `P`, `V`.
+///
+/// Note: `AS`, `P`, `V` are not constrained to be
+/// consistent with each other.
+pub fn prove_outer_snark<'params, AS, O, P, V>(
+    outer_config: &O::Config,
+    outer_gate_config: &OuterGateConfig,
+    pk: &'params ProvingKey<G1Affine>,
+    break_points: MultiPhaseThreadBreakPoints,
+    outer_inputs: O::CircuitInputs,
+    outer_srs: &'params ParamsKZG<Bn256>,
+) -> Snark
+where
+    AS: for<'a> Halo2KzgAccumulationScheme<'a> + 'params,
+    O: OuterCircuit + 'params,
+    P: Prover<'params, KZGCommitmentScheme<Bn256>> + 'params,
+    V: Verifier<
+            'params,
+            KZGCommitmentScheme<Bn256>,
+            Guard = GuardKZG<'params, Bn256>,
+            MSMAccumulator = DualMSM<'params, Bn256>,
+        > + 'params,
+{
+    let circuit = OuterCircuitWrapper::<AS, O, P, V>::prover(
+        outer_config,
+        outer_gate_config,
+        break_points,
+        &outer_inputs,
+    );
+    gen_snark::<_, P, V>(outer_srs, pk, circuit, None::<&str>)
+}
+
+/// Verifies an `OuterCircuit` EVM proof (as produced by [`prove_outer`])
+/// natively, following the same path as the EVM verifier: the proof is read
+/// with an [`EvmTranscript`] and its opening is checked with an
+/// [`AccumulatorStrategy`], then the deferred pairing check is performed on
+/// the KZG accumulator encoded (as `limb_bits`-bit limbs) in the first
+/// `4 * num_limbs` elements of `instance`.
+///
+/// Returns `true` if and only if both checks pass.  Use of `SHPLONK` or
+/// `GWC19` is specified by the type `V`.
+pub fn verify_outer<'params, V>(
+    outer_srs: &'params ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proof: &[u8],
+    instance: &[Fr],
+    limb_bits: usize,
+    num_limbs: usize,
+) -> bool
+where
+    V: Verifier<
+        'params,
+        KZGCommitmentScheme<Bn256>,
+        Guard = GuardKZG<'params, Bn256>,
+        MSMAccumulator = DualMSM<'params, Bn256>,
+    >,
+{
+    let mut transcript = TranscriptReadBuffer::<_, G1Affine, _>::init(proof);
+    let proof_accepted = verify_proof::<_, V, _, EvmTranscript<_, _, _, _>, _>(
+        outer_srs.verifier_params(),
+        vk,
+        AccumulatorStrategy::new(outer_srs.verifier_params()),
+        &[&[instance]],
+        &mut transcript,
+    )
+    .map(VerificationStrategy::<_, V>::finalize)
+    .unwrap_or(false);
+
+    proof_accepted
+        && accumulator_accepted(outer_srs, instance, limb_bits, num_limbs)
+}
+
+/// Verifies an `OuterCircuit` [Snark] (as produced by
+/// [`prove_outer_snark`]) natively.  As for [`verify_outer`], this checks
+/// both the proof itself and the KZG accumulator in its instance.
+pub fn verify_outer_snark<'params, V>(
+    outer_srs: &'params ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    snark: &Snark,
+    limb_bits: usize,
+    num_limbs: usize,
+) -> bool
+where
+    V: Verifier<
+        'params,
+        KZGCommitmentScheme<Bn256>,
+        Guard = GuardKZG<'params, Bn256>,
+        MSMAccumulator = DualMSM<'params, Bn256>,
+    >,
+{
+    let instance = &snark.instances[0];
+    let mut transcript = PoseidonTranscript::<NativeLoader, _>::new::<SECURE_MDS>(
+        &snark.proof[..],
+    );
+    let proof_accepted = verify_proof::<_, V, ChallengeScalar<_>, _, _>(
+        outer_srs.verifier_params(),
+        vk,
+        AccumulatorStrategy::new(outer_srs.verifier_params()),
+        &[&[instance.as_slice()]],
+        &mut transcript,
+    )
+    .map(VerificationStrategy::<_, V>::finalize)
+    .unwrap_or(false);
+
+    proof_accepted
+        && accumulator_accepted(outer_srs, instance, limb_bits, num_limbs)
+}
+
+/// Verifies a batch of `OuterCircuit` EVM proofs (as produced by
+/// [`prove_outer`]), each given with its instance.  The KZG openings of all
+/// proofs are accumulated into a single [`DualMSM`] (the
+/// [`AccumulatorStrategy`] scales the running accumulator by a random
+/// coefficient before adding each proof), and the KZG accumulators in the
+/// instances are combined by a random linear combination, so the pairing
+/// checks are performed once for the whole batch rather than per proof.
+///
+/// If the batch is rejected, it is bisected to find the proofs which fail
+/// on their own, and their indices are returned in increasing order.  A
+/// proof is accepted here if and only if it is accepted by
+/// [`verify_outer`].  Use of `SHPLONK` or `GWC19` is specified by the type
+/// `V`.
+pub fn batch_verify_outer<'params, V>(
+    outer_srs: &'params ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proofs: &[(Vec<u8>, Vec<Fr>)],
+    limb_bits: usize,
+    num_limbs: usize,
+) -> Result<(), Vec<usize>>
+where
+    V: Verifier<
+        'params,
+        KZGCommitmentScheme<Bn256>,
+        Guard = GuardKZG<'params, Bn256>,
+        MSMAccumulator = DualMSM<'params, Bn256>,
+    >,
+{
+    let batch_accepted = |indices: &[usize]| {
+        let proofs_accepted = indices
+            .iter()
+            .try_fold(
+                AccumulatorStrategy::new(outer_srs.verifier_params()),
+                |strategy, &i| {
+                    let (proof, instance) = &proofs[i];
+                    let mut transcript =
+                        TranscriptReadBuffer::<_, G1Affine, _>::init(
+                            proof.as_slice(),
+                        );
+                    verify_proof::<_, V, _, EvmTranscript<_, _, _, _>, _>(
+                        outer_srs.verifier_params(),
+                        vk,
+                        strategy,
+                        &[&[instance.as_slice()]],
+                        &mut transcript,
+                    )
+                },
+            )
+            .map(VerificationStrategy::<_, V>::finalize)
+            .unwrap_or(false);
+        proofs_accepted
+            && accumulators_accepted(
+                outer_srs,
+                indices.iter().map(|&i| proofs[i].1.as_slice()),
+                limb_bits,
+                num_limbs,
+            )
+    };
+
+    let mut failed = Vec::new();
+    let mut pending = vec![(0..proofs.len()).collect::<Vec<_>>()];
+    while let Some(indices) = pending.pop() {
+        if indices.is_empty() || batch_accepted(&indices) {
+            continue;
+        }
+        if indices.len() == 1 {
+            failed.push(indices[0]);
+        } else {
+            let (left, right) = indices.split_at(indices.len() / 2);
+            pending.push(right.to_vec());
+            pending.push(left.to_vec());
+        }
+    }
+
+    match failed.is_empty() {
+        true => Ok(()),
+        false => Err(failed),
+    }
+}
+
+/// Performs the deferred pairing checks `e(lhs_i, g2) == e(rhs_i, s_g2)` on
+/// the KZG accumulators `(lhs_i, rhs_i)` at the start of each of the
+/// `instances`, as a single pairing check on a random linear combination of
+/// the accumulators.
+fn accumulators_accepted<'i>(
+    outer_srs: &ParamsKZG<Bn256>,
+    instances: impl Iterator<Item = &'i [Fr]>,
+    limb_bits: usize,
+    num_limbs: usize,
+) -> bool {
+    let point_len = 2 * num_limbs;
+    let mut lhs = G1::identity();
+    let mut rhs = G1::identity();
+    for instance in instances {
+        if instance.len() < 2 * point_len {
+            return false;
+        }
+        let coeff = Fr::random(OsRng);
+        lhs +=
+            limbs_into_g1affine(&instance[..point_len], limb_bits, num_limbs)
+                * coeff;
+        rhs += limbs_into_g1affine(
+            &instance[point_len..2 * point_len],
+            limb_bits,
+            num_limbs,
+        ) * coeff;
+    }
+    Bn256::pairing(&lhs.to_affine(), &outer_srs.g2())
+        == Bn256::pairing(&rhs.to_affine(), &outer_srs.s_g2())
+}
+
+/// Performs the deferred pairing check `e(lhs, g2) == e(rhs, s_g2)` on the
+/// KZG accumulator `(lhs, rhs)` at the start of an outer `instance`.
+fn accumulator_accepted(
+    outer_srs: &ParamsKZG<Bn256>,
+    instance: &[Fr],
+    limb_bits: usize,
+    num_limbs: usize,
+) -> bool {
+    let point_len = 2 * num_limbs;
+    if instance.len() < 2 * point_len {
+        return false;
+    }
+    let lhs = limbs_into_g1affine(&instance[..point_len], limb_bits, num_limbs);
+    let rhs = limbs_into_g1affine(
+        &instance[point_len..2 * point_len],
+        limb_bits,
+        num_limbs,
+    );
+    Bn256::pairing(&lhs, &outer_srs.g2())
+        == Bn256::pairing(&rhs, &outer_srs.s_g2())
+}
+
+/// Encodes an outer `proof` and its `instances` as calldata for a call to
+/// the contract function `f(bytes proof, uint256[] instances)` with 4-byte
+/// function `selector`.  This matches Solidity's
+/// `abi.encodeWithSelector(selector, proof, instances)`, and is intended
+/// for contracts which embed the UPA verifier (whose own calldata layout is
+/// given by `encode_calldata`).
+pub fn abi_encode_calldata(
+    selector: [u8; 4],
+    proof: &[u8],
+    instances: &[Fr],
+) -> Vec<u8> {
+    let instances = instances
+        .iter()
+        .map(|f| Token::Uint(U256::from_little_endian(&f.to_repr())))
+        .collect();
+    let args = encode(&[Token::Bytes(proof.to_vec()), Token::Array(instances)]);
+    [selector.as_slice(), &args].concat()
+}
+
+/// Inverse of [`abi_encode_calldata`].  Checks that `calldata` starts with
+/// `selector` and returns the decoded proof and instances.
+pub fn abi_decode_calldata(
+    selector: [u8; 4],
+    calldata: &[u8],
+) -> Result<(Vec<u8>, Vec<Fr>), String> {
+    if calldata.len() < 4 || calldata[..4] != selector {
+        return Err("calldata does not start with the selector".to_string());
+    }
+    let tokens = decode(
+        &[
+            ParamType::Bytes,
+            ParamType::Array(Box::new(ParamType::Uint(256))),
+        ],
+        &calldata[4..],
+    )
+    .map_err(|e| format!("invalid ABI encoding: {e}"))?;
+    let (proof, instances) = match tokens.as_slice() {
+        [Token::Bytes(proof), Token::Array(instances)] => (proof, instances),
+        _ => return Err("unexpected ABI tokens".to_string()),
+    };
+    let instances = instances
+        .iter()
+        .enumerate()
+        .map(|(i, token)| {
+            let word = token
+                .clone()
+                .into_uint()
+                .ok_or_else(|| format!("instance {i} is not a uint256"))?;
+            let mut repr = [0u8; 32];
+            word.to_little_endian(&mut repr);
+            Option::from(Fr::from_repr(repr)).ok_or_else(|| {
+                format!("instance {i} is not a valid field element")
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((proof.clone(), instances))
+}
@@ -0,0 +1,113 @@
+use crate::{
+    utils::{check_flex_gate_rows, InsufficientRows},
+    CircuitWithLimbsConfig, ConfigError,
+};
+use halo2_base::gates::{
+    builder::FlexGateConfigParams, flex_gate::GateStrategy,
+};
+
+/// CMD: `cargo test --package upa-circuits --lib -- tests::circuit_config::circuit_with_limbs_config_builder --exact --nocapture`
+#[test]
+fn circuit_with_limbs_config_builder() {
+    assert_eq!(
+        CircuitWithLimbsConfig::builder(20).build(),
+        Ok(CircuitWithLimbsConfig::from_degree_bits(20))
+    );
+    assert_eq!(
+        CircuitWithLimbsConfig::builder(20)
+            .lookup_bits(17)
+            .limb_bits(64)
+            .num_limbs(4)
+            .build(),
+        Ok(CircuitWithLimbsConfig {
+            degree_bits: 20,
+            lookup_bits: 17,
+            limb_bits: 64,
+            num_limbs: 4,
+        })
+    );
+}
+
+/// CMD: `cargo test --package upa-circuits --lib -- tests::circuit_config::circuit_with_limbs_config_builder_rejects --exact --nocapture`
+#[test]
+fn circuit_with_limbs_config_builder_rejects() {
+    assert_eq!(
+        CircuitWithLimbsConfig::builder(20).lookup_bits(20).build(),
+        Err(ConfigError::LookupBitsTooLarge {
+            lookup_bits: 20,
+            degree_bits: 20
+        })
+    );
+    // The default lookup bits of a degree 0 circuit are also too large.
+    assert_eq!(
+        CircuitWithLimbsConfig::builder(0).build(),
+        Err(ConfigError::LookupBitsTooLarge {
+            lookup_bits: 0,
+            degree_bits: 0
+        })
+    );
+    assert_eq!(
+        CircuitWithLimbsConfig::builder(20).num_limbs(0).build(),
+        Err(ConfigError::ZeroLimbs)
+    );
+    assert_eq!(
+        CircuitWithLimbsConfig::builder(20)
+            .limb_bits(84)
+            .num_limbs(3)
+            .build(),
+        Err(ConfigError::LimbsTooSmall {
+            limb_bits: 84,
+            num_limbs: 3
+        })
+    );
+    assert_eq!(
+        CircuitWithLimbsConfig::builder(20)
+            .limb_bits(85)
+            .num_limbs(3)
+            .build()
+            .map(|config| config.limb_bits),
+        Ok(85)
+    );
+}
+
+/// Checks that [`check_flex_gate_rows`] accepts gate params whose lookup
+/// table and instance fit in the usable rows, and rejects a deliberately
+/// undersized degree.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::circuit_config::flex_gate_rows_checked --exact --nocapture`
+#[test]
+fn flex_gate_rows_checked() {
+    let params = |k: usize| FlexGateConfigParams {
+        strategy: GateStrategy::Vertical,
+        k,
+        num_advice_per_phase: vec![4],
+        num_lookup_advice_per_phase: vec![1],
+        num_fixed: 1,
+    };
+    assert_eq!(check_flex_gate_rows(&params(10), 9, 100, 20), Ok(()));
+
+    // The lookup table no longer fits once the minimum rows are reserved.
+    let err = check_flex_gate_rows(&params(10), 10, 100, 20).unwrap_err();
+    assert_eq!(
+        err,
+        InsufficientRows {
+            required_rows: 1 << 10,
+            degree_bits: 10,
+            usable_rows: (1 << 10) - 20,
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "config requires ~1024 rows but degree 10 provides only 1004"
+    );
+
+    // Nor does an instance longer than the usable rows.
+    assert_eq!(
+        check_flex_gate_rows(&params(6), 5, 100, 20),
+        Err(InsufficientRows {
+            required_rows: 100,
+            degree_bits: 6,
+            usable_rows: 44,
+        })
+    );
+}
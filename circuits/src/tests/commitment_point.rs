@@ -5,13 +5,15 @@ use crate::{
     },
     utils::commitment_point::{
         be_bytes_to_field_element, commitment_hash_bytes_from_g1_point,
-        commitment_hash_from_commitment_point_limbs, g1affine_into_limbs,
-        limbs_into_g1affine,
+        commitment_hash_from_commitment_point_limbs,
+        commitment_hash_from_point, g1affine_into_limbs, limbs_into_g1affine,
+        point_from_hash_preimage, try_point_from_coords, CommitmentPointError,
     },
 };
+use ethers_core::utils::keccak256;
 use halo2_base::halo2_proofs::{
     arithmetic::Field,
-    halo2curves::bn256::{Fr, G1Affine},
+    halo2curves::bn256::{Fq, Fr, G1Affine},
 };
 use rand_core::OsRng;
 
@@ -34,6 +36,12 @@ pub const OUTPUT_BYTES: [u8; 32] = [
 pub const FIELD_ELEMENT_HEX: &str =
     "0x1f9c1ecc840b2e7a4e7cfcb9b3a32bf60cf4216d33cef6868d317a67dd92fa11";
 
+/// `uint256(keccak256(abi.encodePacked(uint256(1), uint256(2)))) % r`, the
+/// commitment hash of `G1Affine::generator()`.  The digest
+/// `0xe90b7bce...d2a7c2e0` exceeds `r`, so this also pins the reduction.
+pub const GENERATOR_COMMITMENT_HASH_HEX: &str =
+    "0x277a420332215ead37ba61fee84f0d23276a6799e5da57c1354dc37d12a7c2dc";
+
 /// Interprets `COMMITMENT_POINT` as coordinates of a `G1` point.
 pub fn parse_commitment_point() -> G1Affine {
     g1_from_json(&[
@@ -94,3 +102,64 @@ fn test_conversion_to_and_from_limbs() {
         limbs_into_g1affine(&limbs, LIMB_BITS, NUM_LIMBS);
     assert_eq!(g1_point, reconstructed_g1_point, "g1 point mismatch");
 }
+
+/// Tests that [`try_point_from_coords`] accepts a valid commitment point and
+/// reports off-curve coordinates.
+#[test]
+fn test_try_point_from_coords() {
+    let commitment_point = parse_commitment_point();
+    assert_eq!(
+        try_point_from_coords(commitment_point.x, commitment_point.y),
+        Ok(commitment_point)
+    );
+
+    // (1, 1) does not satisfy y^2 = x^3 + 3
+    let (x, y) = (Fq::one(), Fq::one());
+    assert_eq!(
+        try_point_from_coords(x, y),
+        Err(CommitmentPointError::NotOnCurve { x, y })
+    );
+}
+
+/// Checks [`commitment_hash_from_point`] and [`point_from_hash_preimage`]
+/// against known vectors, including the generator.
+#[test]
+fn test_commitment_hash_from_point() {
+    let generator = G1Affine::generator();
+    assert_eq!(
+        commitment_hash_from_point(&generator),
+        field_element_from_str::<Fr>(GENERATOR_COMMITMENT_HASH_HEX)
+    );
+    assert_eq!(
+        commitment_hash_from_point(&parse_commitment_point()),
+        field_element_from_str::<Fr>(FIELD_ELEMENT_HEX)
+    );
+
+    // The preimage of the generator is (1, 2) as big-endian words.
+    let mut preimage = [0u8; 64];
+    preimage[31] = 1;
+    preimage[63] = 2;
+    assert_eq!(point_from_hash_preimage(&preimage), Ok(generator));
+    assert_eq!(
+        be_bytes_to_field_element::<Fr, 32>(&keccak256(preimage)),
+        commitment_hash_from_point(&generator)
+    );
+
+    // Little-endian coordinates are rejected
+    let mut le_preimage = [0u8; 64];
+    le_preimage[0] = 1;
+    le_preimage[32] = 2;
+    assert!(point_from_hash_preimage(&le_preimage).is_err());
+    // as are non-canonical coordinates (x = p + 1).
+    let mut non_canonical = preimage;
+    non_canonical[..32].copy_from_slice(
+        &hex::decode(
+            "30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd48",
+        )
+        .unwrap(),
+    );
+    assert!(matches!(
+        point_from_hash_preimage(&non_canonical),
+        Err(CommitmentPointError::NonCanonicalCoordinate(_))
+    ));
+}
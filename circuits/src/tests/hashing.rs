@@ -1,25 +1,32 @@
 use crate::{
     batch_verify::universal::types::{
-        UPA_V1_0_0_CHALLENGE_DOMAIN_TAG_STRING,
+        DomainTagVersion, UPA_V1_0_0_CHALLENGE_DOMAIN_TAG_STRING,
         UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING,
         UPA_V1_0_0_CIRCUITID_DOMAIN_TAG_STRING_WITH_COMMITMENT,
     },
     utils::hashing::{
         brute_force_poseidon, compute_domain_tag, var_len_poseidon,
         FieldElementRepresentation, InCircuitHash, InCircuitPartialHash,
-        PoseidonHasher, POSEIDON_R,
+        PoseidonHasher, WrongFieldHasher, POSEIDON_R, SECURE_MDS,
     },
-    EccPrimeField,
+    CircuitWithLimbsConfig, EccPrimeField,
 };
 use halo2_base::{
     gates::builder::GateThreadBuilder,
-    halo2_proofs::halo2curves::bn256::Fr,
+    halo2_proofs::halo2curves::{
+        bn256::{Fr, G1Affine, G2Affine},
+        group::ff::Field,
+    },
     safe_types::{RangeChip, RangeInstructions},
     AssignedValue, Context,
 };
 use itertools::Itertools;
 use rand::Rng;
 use rand_core::{OsRng, RngCore};
+use snark_verifier::{
+    loader::native::NativeLoader, util::transcript::Transcript,
+};
+use snark_verifier_sdk::halo2::{PoseidonTranscript, POSEIDON_SPEC};
 
 /// Lookup bits
 const LOOKUP_BITS: usize = 16;
@@ -45,6 +52,38 @@ fn domain_tags() {
     print_domain_tag(UPA_V1_0_0_CHALLENGE_DOMAIN_TAG_STRING);
 }
 
+/// Circuit id domain tags, as documented in `UpaInternalLib.sol`.
+const CIRCUIT_ID_DOMAIN_TAG: &str =
+    "4fb2fda778fd224ee633116280b47f502b0d937ce78d390aa16f73d9007c65f2";
+const CIRCUIT_ID_DOMAIN_TAG_WITH_COMMITMENT: &str =
+    "be0523909703924017e523b64b54adc1091d895bc2cea0e312c4b2e63c813202";
+
+/// Checks that each [`DomainTagVersion`] produces its documented circuit id
+/// domain tags.
+#[test]
+fn domain_tag_versions() {
+    fn domain_tag(version: DomainTagVersion, has_commitment: bool) -> String {
+        hex::encode(compute_domain_tag(
+            version.circuit_id_domain_tag_string(has_commitment),
+        ))
+    }
+
+    assert_eq!(DomainTagVersion::default(), DomainTagVersion::V1_0_0);
+    assert_eq!(
+        domain_tag(DomainTagVersion::V1_0_0, false),
+        CIRCUIT_ID_DOMAIN_TAG
+    );
+    assert_eq!(
+        domain_tag(DomainTagVersion::V1_0_0, true),
+        CIRCUIT_ID_DOMAIN_TAG_WITH_COMMITMENT
+    );
+
+    let version = DomainTagVersion::V1_0_0;
+    assert_eq!(version.to_string().parse(), Ok(version));
+    assert_eq!("v1.0.0".parse(), Ok(version));
+    assert!("0.9.0".parse::<DomainTagVersion>().is_err());
+}
+
 /// Dummy Hashable.
 ///
 /// This struct is intended to test the different cases of
@@ -166,8 +205,12 @@ where
         result &= check_poseidon_implementation::<F, DummyHashable<F>, _, _, _, _>(
             &poseidon,
             |ctx, domain_tag, chip, num_parts, assigned| {
-                let mut hasher =
-                    PoseidonHasher::new(ctx, &chip.gate, Some(domain_tag));
+                let mut hasher = PoseidonHasher::new(
+                    ctx,
+                    &chip.gate,
+                    Some(domain_tag),
+                    SECURE_MDS,
+                );
                 let num_parts_assigned =
                     ctx.load_witness(F::from(num_parts as u64));
                 hasher.absorb(&num_parts_assigned);
@@ -215,3 +258,39 @@ fn check_brute_force_poseidon_on_dummy() {
         "Brute force Poseidon error"
     );
 }
+
+/// Checks that the transcript of the inner proofs, set up with
+/// [`SECURE_MDS`], agrees with the transcript spec used by the outer circuit
+/// to verify them.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::hashing::secure_mds_matches_outer_spec --exact --nocapture`
+#[test]
+fn secure_mds_matches_outer_spec() {
+    let mut inner =
+        PoseidonTranscript::<NativeLoader, Vec<u8>>::new::<SECURE_MDS>(vec![]);
+    let mut outer = PoseidonTranscript::<NativeLoader, Vec<u8>>::from_spec(
+        vec![],
+        POSEIDON_SPEC.clone(),
+    );
+    for _ in 0..3 {
+        let scalar = Fr::random(OsRng);
+        inner.common_scalar(&scalar).unwrap();
+        outer.common_scalar(&scalar).unwrap();
+        assert_eq!(
+            inner.squeeze_challenge(),
+            outer.squeeze_challenge(),
+            "inner and outer transcripts disagree"
+        );
+    }
+}
+
+/// Checks that the hashers reject an MDS setting which their Poseidon chips
+/// do not implement, rather than silently using another spec.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::hashing::unsupported_secure_mds --exact --nocapture`
+#[test]
+#[should_panic(expected = "unsupported secure_mds 1")]
+fn unsupported_secure_mds() {
+    let circuit_config = CircuitWithLimbsConfig::from_degree_bits(1);
+    WrongFieldHasher::<G1Affine, G2Affine>::new(&circuit_config, None, 1);
+}
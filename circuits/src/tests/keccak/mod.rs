@@ -1,25 +1,41 @@
 //! Keccak circuit tests
 
 use crate::{
-    batch_verify::universal::native::compute_circuit_id,
+    batch_verify::universal::{
+        native::compute_circuit_id_with_domain_version, types::DomainTagVersion,
+    },
     keccak::{
-        self, inputs::KeccakCircuitInputs, utils::compute_submission_id,
-        AssignedKeccakInput, AssignedVerifyingKeyLimbs, KeccakConfig,
-        KeccakPaddedCircuitInput, PaddedVerifyingKeyLimbs, KECCAK_LOOKUP_BITS,
+        self,
+        inputs::KeccakCircuitInputs,
+        keccak_instance_len,
+        utils::{
+            compute_vk_digest, digest_as_field_elements,
+            inputs_per_application_proof_with_commitments,
+        },
+        AssignedKeccakInput, AssignedVerifyingKeyLimbs, InsufficientRows,
+        KeccakConfig, KeccakConfigMismatch, KeccakGateConfig,
+        KeccakPaddedCircuitInput, KeccakPaddedCircuitInputs, KeccakProgress,
+        LimbError, OutputMode, PaddedVerifyingKeyLimbs,
+        DEFAULT_MAX_COMMITMENTS, KECCAK_LOOKUP_BITS, KECCAK_OUTPUT_BYTES,
         LIMB_BITS, NUM_LIMBS,
     },
-    tests::utils::check_instance,
-    utils::commitment_point::{
-        be_bytes_to_field_element, commitment_hash_from_commitment_point_limbs,
+    tests::utils::{
+        assert_mock_failure, check_instance, check_instance_layout,
+        check_key_round_trip, FailureKind,
     },
+    utils::cancellation::{CancellationToken, Cancelled},
     EccPrimeField, SafeCircuit,
 };
 use ark_std::{end_timer, start_timer};
-use ethers_core::utils::keccak256;
 use halo2_base::{
+    gates::{
+        builder::{FlexGateConfigParams, GateThreadBuilder},
+        flex_gate::GateStrategy,
+    },
     halo2_proofs::{
+        arithmetic::Field,
         dev::MockProver,
-        halo2curves::bn256::{Bn256, Fr, G1Affine},
+        halo2curves::bn256::{Bn256, Fr, G1Affine, G2Affine},
         plonk::{create_proof, keygen_pk, keygen_vk, verify_proof},
         poly::kzg::{
             commitment::KZGCommitmentScheme,
@@ -33,7 +49,6 @@ use halo2_base::{
     },
     utils::fs::gen_srs,
 };
-use itertools::Itertools;
 use rand_core::OsRng;
 use snark_verifier_sdk::CircuitExt;
 use std::env::var;
@@ -54,255 +69,30 @@ const OUTER_BATCH_SIZE: u32 = 2;
 /// Keccak circuit type
 type KeccakCircuit = keccak::KeccakCircuit<Fr, G1Affine>;
 
-//// Keccak Circuit Inconsistency Error.
-///
-/// This error can be returned as the result of [`is_well_constructed`](KeccakCircuit::is_well_constructed).
-#[derive(Clone, Debug)]
-pub enum KeccakCircuitInconsistency<F> {
-    /// Keccak Proof Id Mismatch Error.
-    ///
-    /// The keccak output of a keccak query differs from the
-    /// expected value. Returns the number of the query, the output proofId and the
-    /// expected proofId.
-    KeccakProofId(u32, Vec<u8>, Vec<u8>),
-
-    /// Keccak CircuitId Mismatch Error.
-    ///
-    /// The keccak output of a keccak query differs from the
-    /// expected value. Returns the number of the query, the output circuitId and the
-    /// expected circuitId.
-    KeccakCircuitId(u32, Vec<u8>, Vec<u8>),
-
-    /// Public Input Number Mismatch Error.
-    ///
-    /// The number of public inputs of the circuit doesn't correspond to that of
-    /// the configuration. Returns those two values in order.
-    PublicInputNumber(u32, u32),
-
-    /// Public Output Mismatch Error.
-    ///
-    /// The field elements given as the public output don't decompose into
-    /// the keccak output bytes of the last keccak query. Returns the actual value,
-    /// the value computed from the pair of field elements in the instance
-    /// and the expected value, in order.
-    PublicOutput(Vec<u8>, Vec<u8>, Vec<u8>),
-
-    /// Commitment Query Mismatch Error.
-    ///
-    /// The field elements given as the commitment hash don't coincide with the
-    /// output bytes of the commitment point queries. Returns the number of the
-    /// query, the commitment hash computed from the query output bytes,
-    /// the commitment hash computed from the limbs and the expected commitment hash.
-    CommitmentQuery(u32, F, F, F),
-}
-
-/// Keccak circuit test function
-impl KeccakCircuit {
-    /// Check that for each `input: KeccakInput` of `self.public_inputs`,
-    /// 1) The keccak output bytes of `self` match the `commitment_hash` in `input`.
-    /// 2) The limbs in `input` represent coordinates that hash to `input.commitment_hash`
-    pub fn are_commitment_point_queries_well_constructed(
-        &self,
-        starting_index_commitment_queries: usize,
-    ) -> Result<(), KeccakCircuitInconsistency<Fr>> {
-        for (i, input) in self.public_inputs.inputs.iter().enumerate() {
-            let expected_commitment_hash = input.commitment_hash.value();
-            let commitment_query_index =
-                i + 2 * starting_index_commitment_queries;
-            let query_commitment_hash_bytes: [u8; 32] = self
-                .keccak_output_bytes()[32 * commitment_query_index
-                ..32 * (commitment_query_index + 1)]
-                .iter()
-                .map(|assigned| {
-                    assigned
-                        .value()
-                        .get_lower_32()
-                        .try_into()
-                        .expect("Not a byte")
-                })
-                .collect_vec()
-                .try_into()
-                .expect("Conversion to array is not allowed to fail");
-            let commitment_hash = be_bytes_to_field_element::<Fr, 32>(
-                &query_commitment_hash_bytes,
-            );
-            let limbs = input
-                .commitment_point_limbs
-                .iter()
-                .map(|limb| *limb.value())
-                .collect_vec();
-            let computed_commitment_hash =
-                commitment_hash_from_commitment_point_limbs(
-                    &limbs[..],
-                    LIMB_BITS,
-                    NUM_LIMBS,
-                );
-            ((&commitment_hash == expected_commitment_hash)
-                && (&computed_commitment_hash == expected_commitment_hash))
-                .then_some(())
-                .ok_or({
-                    KeccakCircuitInconsistency::CommitmentQuery(
-                        commitment_query_index as u32,
-                        commitment_hash,
-                        computed_commitment_hash,
-                        *expected_commitment_hash,
-                    )
-                })?;
-        }
-        Ok(())
-    }
-
-    /// Checks that `self` is well-formed w.r.t. `config`.
-    ///
-    /// # Note
-    ///
-    /// In principle, any circuit generated via [`new`](Self::new) should always be well-formed.
-    /// This function is primarly for testing purposes and checking invariants.
-    pub fn is_well_constructed(
-        &self,
-        config: &KeccakConfig,
-    ) -> Result<(), KeccakCircuitInconsistency<Fr>> {
-        let mut last_index = 0;
-        for (i, input) in self.public_inputs.inputs.iter().enumerate() {
-            last_index = i as u32;
-            let number_of_field_elements = input.num_field_elements();
-            let has_commitment = input.has_commitment();
-            let num_bytes = 32 * (number_of_field_elements + 1);
-            let mut vk = input.app_vk.value().vk();
-            vk.s = vk
-                .s
-                .into_iter()
-                .take(number_of_field_elements + 1 + has_commitment as usize)
-                .collect();
-            if !has_commitment {
-                vk.h1 = vec![];
-                vk.h2 = vec![];
-            }
-            let circuit_id = compute_circuit_id(&vk);
-            let input_bytes = circuit_id
-                .iter()
-                .copied()
-                .chain(input.public_inputs().into_iter().flat_map(
-                    |field_element| {
-                        field_element
-                            .value()
-                            .to_bytes_le()
-                            .into_iter()
-                            .rev()
-                            .collect_vec()
-                    },
-                ))
-                .collect_vec();
-            let expected_bytes_proof_id = keccak256(&input_bytes[..num_bytes]);
-            let output_bytes_circuit_id = self.keccak_output_bytes()
-                [32 * 2 * i..32 * (2 * i + 1)]
-                .iter()
-                .map(|v| v.value().to_bytes_le()[0])
-                .collect_vec();
-            let output_bytes_proof_id = self.keccak_output_bytes()
-                [32 * (2 * i + 1)..32 * (2 * i + 2)]
-                .iter()
-                .map(|v| v.value().to_bytes_le()[0])
-                .collect_vec();
-            (output_bytes_circuit_id == circuit_id)
-                .then_some(())
-                .ok_or_else(|| {
-                    KeccakCircuitInconsistency::KeccakCircuitId(
-                        last_index,
-                        output_bytes_circuit_id,
-                        circuit_id.to_vec(),
-                    )
-                })?;
-            (output_bytes_proof_id == expected_bytes_proof_id)
-                .then_some(())
-                .ok_or_else(|| {
-                    KeccakCircuitInconsistency::KeccakProofId(
-                        last_index,
-                        output_bytes_proof_id,
-                        expected_bytes_proof_id.to_vec(),
-                    )
-                })?;
-        }
-        (last_index + 1 == config.inner_batch_size * config.outer_batch_size)
-            .then_some(())
-            .ok_or({
-                KeccakCircuitInconsistency::PublicInputNumber(
-                    last_index + 1,
-                    config.inner_batch_size * config.outer_batch_size,
-                )
-            })?;
-        let last_input_bytes = self.keccak_output_bytes()
-            [0..32 * 2 * (last_index as usize + 1)]
-            .iter()
-            .chunks(32)
-            .into_iter()
-            .skip(1)
-            .step_by(2)
-            .flat_map(|chunk| {
-                chunk.into_iter().map(|v| v.value().to_bytes_le()[0])
-            })
-            .collect_vec();
-        // The last bytes will be either the submission Id or the keccak
-        // of the proof Ids.
-        let num_keccak_output_bytes = self.keccak_output_bytes().len();
-        let (last_expected_bytes, location_last_output_bytes) = match config
-            .output_submission_id
-        {
-            true => {
-                let proof_ids = last_input_bytes.into_iter().chunks(32).into_iter().map(|chunk| {
-                    <[u8; 32]>::try_from(chunk.collect_vec()).expect(
-                        "Conversion from vector into array is not allowed to fail",
-                    )
-                }).collect_vec();
-                let num_proof_ids = self
-                    .public_inputs
-                    .num_proof_ids
-                    .expect(
-                        "num_proof_ids must exist when the circuit outputs submission id",
-                    )
-                    .value()
-                    .get_lower_32() as u64;
-                let depth_diff = (last_index + 1).ilog2()
-                    - num_proof_ids.next_power_of_two().ilog2();
-                let location = num_keccak_output_bytes
-                    - 32 * (2usize.pow(depth_diff + 1) - 1);
-                (compute_submission_id(proof_ids, num_proof_ids), location)
-            }
-            false => {
-                (keccak256(last_input_bytes), num_keccak_output_bytes - 32)
-            }
-        };
-        // The last 32 keccak output bytes must match `last_expected_bytes`.
-        let last_output_bytes = self.keccak_output_bytes()
-            [location_last_output_bytes..location_last_output_bytes + 32]
-            .iter()
-            .map(|v| v.value().to_bytes_le()[0])
-            .collect_vec();
-        let public_output = self.public_output.map(|field_element| {
-            field_element
-                .value()
-                .to_bytes_le()
-                .into_iter()
-                .take(16)
-                .rev()
-                .collect_vec()
-        });
-        let mut output_bytes = public_output[1].clone();
-        output_bytes.extend(public_output[0].iter());
-        (last_output_bytes == output_bytes
-            && last_output_bytes == last_expected_bytes)
-            .then_some(())
-            .ok_or({
-                KeccakCircuitInconsistency::PublicOutput(
-                    last_output_bytes,
-                    output_bytes,
-                    last_expected_bytes.into(),
-                )
-            })?;
-        self.are_commitment_point_queries_well_constructed(
-            last_index as usize + 1,
-        )?;
-        Ok(())
+/// Returns the [`KeccakConfig`] for the test batch sizes, with degree
+/// `degree_bits`, `output_mode` and all optional features disabled.  Tests
+/// override other fields with struct update syntax.
+fn test_keccak_config(
+    degree_bits: u32,
+    output_mode: OutputMode,
+) -> KeccakConfig {
+    KeccakConfig {
+        degree_bits,
+        num_app_public_inputs: NUM_APP_PUBLIC_INPUTS,
+        inner_batch_size: INNER_BATCH_SIZE,
+        outer_batch_size: OUTER_BATCH_SIZE,
+        lookup_bits: KECCAK_LOOKUP_BITS,
+        output_mode,
+        max_commitments: DEFAULT_MAX_COMMITMENTS,
+        public_num_proof_ids: false,
+        domain_tag_version: DomainTagVersion::default(),
+        max_rows_per_round: None,
+        include_vk_digest: false,
+        unusable_rows: None,
+        limb_bits: LIMB_BITS,
+        num_limbs: NUM_LIMBS,
+        domain_separated_tree: false,
+        allow_commitments: true,
     }
 }
 
@@ -311,26 +101,28 @@ impl KeccakCircuit {
 /// # Note
 ///
 /// The test fails for KECCAK_DEGREE values below 17.
-fn test_keccak_mock(output_submission_id: bool) {
+fn test_keccak_mock(
+    output_mode: OutputMode,
+    public_num_proof_ids: bool,
+    include_vk_digest: bool,
+) {
     let _ = env_logger::builder().is_test(true).try_init();
     let k: u32 = var("KECCAK_DEGREE")
         .unwrap_or_else(|_| "18".to_string())
         .parse()
         .expect("Parsing error");
     let config = KeccakConfig {
-        degree_bits: k,
-        num_app_public_inputs: NUM_APP_PUBLIC_INPUTS,
-        inner_batch_size: INNER_BATCH_SIZE,
-        outer_batch_size: OUTER_BATCH_SIZE,
-        lookup_bits: KECCAK_LOOKUP_BITS,
-        output_submission_id,
+        public_num_proof_ids,
+        include_vk_digest,
+        ..test_keccak_config(k, output_mode)
     };
     let mut rng = OsRng;
     let inputs = KeccakCircuitInputs::<Fr>::sample(&config, &mut rng);
     let circuit = KeccakCircuit::mock(&config, &inputs);
     let instances: Vec<Fr> = circuit.instances()[0].clone();
+    assert_eq!(instances.len(), keccak_instance_len(&config));
     circuit
-        .is_well_constructed(&config)
+        .check_well_constructed(&config)
         .unwrap_or_else(|err| panic!("Circuit not well constructed: {err:?}"));
     assert!(check_instance(&circuit, &config, &inputs));
     MockProver::<Fr>::run(k, &circuit, vec![instances])
@@ -343,7 +135,82 @@ fn test_keccak_mock(output_submission_id: bool) {
 /// KECCAK_DEGREE=18 RUST_LOG=info cargo test --release -- --nocapture test_keccak_mock_output_sid
 #[test]
 fn test_keccak_mock_output_sid() {
-    test_keccak_mock(true);
+    test_keccak_mock(OutputMode::SubmissionId, false, false);
+}
+
+/// # Command line
+///
+/// KECCAK_DEGREE=18 RUST_LOG=info cargo test --release -- --nocapture test_keccak_mock_output_both
+#[test]
+fn test_keccak_mock_output_both() {
+    test_keccak_mock(OutputMode::Both, false, false);
+}
+
+/// Checks that in [`OutputMode::Both`] the final digest consists of the
+/// linear digest followed by the submission id, each matching the instance
+/// computed in the corresponding single-output mode.
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_keccak_output_both_instance --exact --nocapture
+#[test]
+fn test_keccak_output_both_instance() {
+    let mut rng = OsRng;
+    let mut config = test_keccak_config(18, OutputMode::Both);
+    let inputs = KeccakCircuitInputs::<Fr>::sample(&config, &mut rng);
+    let instance = KeccakCircuit::compute_instance(&config, &inputs);
+
+    config.output_mode = OutputMode::SubmissionId;
+    let sid_instance = KeccakCircuit::compute_instance(&config, &inputs);
+    config.output_mode = OutputMode::Linear;
+    let linear_instance = KeccakCircuit::compute_instance(
+        &config,
+        &KeccakCircuitInputs {
+            inputs: inputs.inputs.clone(),
+            num_proof_ids: None,
+        },
+    );
+
+    assert_eq!(instance.len(), sid_instance.len() + 2);
+    let (linear_digest, sid) = instance[instance.len() - 4..].split_at(2);
+    assert_eq!(sid, &sid_instance[sid_instance.len() - 2..]);
+    assert_eq!(linear_digest, &linear_instance[linear_instance.len() - 2..]);
+    assert_eq!(instance[..instance.len() - 2], linear_instance[..]);
+}
+
+/// Checks that configs with the legacy boolean `output_submission_id` field
+/// deserialize to the corresponding [`OutputMode`].
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_keccak_config_legacy_output_submission_id --exact --nocapture
+#[test]
+fn test_keccak_config_legacy_output_submission_id() {
+    for output_submission_id in [false, true] {
+        let config: KeccakConfig = serde_json::from_value(serde_json::json!({
+            "degree_bits": 18,
+            "num_app_public_inputs": NUM_APP_PUBLIC_INPUTS,
+            "inner_batch_size": INNER_BATCH_SIZE,
+            "outer_batch_size": OUTER_BATCH_SIZE,
+            "lookup_bits": KECCAK_LOOKUP_BITS,
+            "output_submission_id": output_submission_id,
+        }))
+        .expect("legacy config");
+        assert_eq!(config.output_mode, output_submission_id.into());
+        let round_trip: KeccakConfig = serde_json::from_str(
+            &serde_json::to_string(&config).expect("serialize"),
+        )
+        .expect("deserialize");
+        assert_eq!(round_trip, config);
+    }
+}
+
+/// # Command line
+///
+/// KECCAK_DEGREE=18 RUST_LOG=info cargo test --release -- --nocapture test_keccak_mock_public_num_proof_ids
+#[test]
+fn test_keccak_mock_public_num_proof_ids() {
+    test_keccak_mock(OutputMode::SubmissionId, true, false);
 }
 
 /// # Command line
@@ -351,7 +218,620 @@ fn test_keccak_mock_output_sid() {
 /// KECCAK_DEGREE=18 RUST_LOG=info cargo test --release -- --nocapture test_keccak_mock_no_sid
 #[test]
 fn test_keccak_mock_no_sid() {
-    test_keccak_mock(false);
+    test_keccak_mock(OutputMode::Linear, false, false);
+}
+
+/// # Command line
+///
+/// KECCAK_DEGREE=18 RUST_LOG=info cargo test --release -- --nocapture test_keccak_mock_vk_digest
+#[test]
+fn test_keccak_mock_vk_digest() {
+    test_keccak_mock(OutputMode::Linear, false, true);
+}
+
+/// Checks [`KeccakCircuit::check_well_constructed`] for a total batch size
+/// which is not a power of two, so that the submission id Merkle tree is
+/// padded, for each possible `num_proof_ids`.
+///
+/// # Command line
+///
+/// cargo test --release --package upa-circuits --lib -- tests::keccak::test_keccak_well_constructed_padded_tree --exact --nocapture
+#[test]
+fn test_keccak_well_constructed_padded_tree() {
+    let mut rng = OsRng;
+    let config = KeccakConfig {
+        inner_batch_size: 3,
+        outer_batch_size: 1,
+        ..test_keccak_config(18, OutputMode::SubmissionId)
+    };
+    let inputs = KeccakCircuitInputs::<Fr>::sample(&config, &mut rng);
+    for num_proof_ids in 1..=3 {
+        let inputs = KeccakCircuitInputs {
+            num_proof_ids: Some(num_proof_ids),
+            ..inputs.clone()
+        };
+        KeccakCircuit::mock(&config, &inputs)
+            .check_well_constructed(&config)
+            .unwrap_or_else(|err| {
+                panic!(
+                    "{num_proof_ids} proof ids: not well constructed: {err:?}"
+                )
+            });
+    }
+}
+
+/// Checks that the mock prover rejects a keccak circuit whose final digest
+/// in the instance does not match the computed one, and that only the
+/// copy constraints between the output cells and the instance fail.
+///
+/// # Command line
+///
+/// cargo test --release --package upa-circuits --lib -- tests::keccak::test_keccak_mock_wrong_instance --exact --nocapture
+#[test]
+fn test_keccak_mock_wrong_instance() {
+    let mut rng = OsRng;
+    let k = 18;
+    let config = test_keccak_config(k, OutputMode::SubmissionId);
+    let inputs = KeccakCircuitInputs::<Fr>::sample(&config, &mut rng);
+    let circuit = KeccakCircuit::mock(&config, &inputs);
+    let mut instances: Vec<Fr> = circuit.instances()[0].clone();
+    *instances.last_mut().expect("empty instance") += Fr::one();
+
+    assert_mock_failure(
+        &circuit,
+        k,
+        vec![instances],
+        &[FailureKind::Permutation],
+    );
+}
+
+/// Checks that, when `include_vk_digest` is set, the last 2 instance values
+/// are the keccak hash of the circuit ids of all entries, and that the final
+/// digest is unchanged.
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_keccak_vk_digest_instance --exact --nocapture
+#[test]
+fn test_keccak_vk_digest_instance() {
+    let mut rng = OsRng;
+    let mut config = test_keccak_config(18, OutputMode::Linear);
+    let inputs = KeccakCircuitInputs::<Fr>::sample(&config, &mut rng);
+    let instance = KeccakCircuit::compute_instance(&config, &inputs);
+
+    config.include_vk_digest = true;
+    let instance_with_vk_digest =
+        KeccakCircuit::compute_instance(&config, &inputs);
+    assert_eq!(instance_with_vk_digest.len(), instance.len() + 2);
+    assert_eq!(instance_with_vk_digest[..instance.len()], instance[..]);
+
+    let circuit_ids = inputs.inputs.iter().map(|input| {
+        compute_circuit_id_with_domain_version(
+            &input.app_vk,
+            config.domain_tag_version,
+        )
+    });
+    let expected_vk_digest =
+        digest_as_field_elements(&compute_vk_digest(circuit_ids));
+    assert_eq!(
+        instance_with_vk_digest[instance.len()..],
+        expected_vk_digest
+    );
+}
+
+/// Instantiates a [`KeccakCircuit`] which does not allow commitments, with
+/// random inputs without commitments, and does a mock run.  The circuit ids
+/// are exposed through the vk digest, so this checks that they are computed
+/// correctly without the commitment keys.  Also checks that fewer keccak
+/// permutations are needed than with commitments allowed.
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_keccak_mock_no_commitments --exact --nocapture
+#[test]
+fn test_keccak_mock_no_commitments() {
+    let mut rng = OsRng;
+    let k = 18;
+    let config = KeccakConfig {
+        include_vk_digest: true,
+        allow_commitments: false,
+        ..test_keccak_config(k, OutputMode::Linear)
+    };
+    let inputs = KeccakCircuitInputs::<Fr>::sample(&config, &mut rng);
+    let circuit = KeccakCircuit::mock(&config, &inputs);
+    let instances: Vec<Fr> = circuit.instances()[0].clone();
+
+    let circuit_ids = inputs.inputs.iter().map(|input| {
+        compute_circuit_id_with_domain_version(
+            &input.app_vk,
+            config.domain_tag_version,
+        )
+    });
+    let expected_vk_digest =
+        digest_as_field_elements(&compute_vk_digest(circuit_ids));
+    assert_eq!(instances[instances.len() - 2..], expected_vk_digest);
+    assert!(check_instance(&circuit, &config, &inputs));
+    MockProver::<Fr>::run(k, &circuit, vec![instances])
+        .expect("Mock prover run failure")
+        .assert_satisfied();
+
+    let circuit_with_commitments = KeccakCircuit::mock(
+        &KeccakConfig {
+            allow_commitments: true,
+            ..config
+        },
+        &inputs,
+    );
+    assert!(
+        circuit.keccak_chip().total_keccak_perms()
+            < circuit_with_commitments.keccak_chip().total_keccak_perms()
+    );
+}
+
+/// Checks that [`KECCAK_OUTPUT_BYTES`] is the length of a keccak digest, as
+/// assumed by the proof id and submission id encodings.
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_keccak_output_bytes --exact --nocapture
+#[test]
+fn test_keccak_output_bytes() {
+    assert_eq!(KECCAK_OUTPUT_BYTES, 32);
+    assert_eq!(ethers_core::utils::keccak256([]).len(), KECCAK_OUTPUT_BYTES);
+}
+
+/// Checks that [`keccak_instance_len`] matches the length of the instance
+/// computed by [`KeccakCircuit::compute_instance`], and that
+/// [`KeccakCircuit::describe_instance`] covers it, with each `len` segment
+/// holding the number of inputs of its entry.
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_keccak_instance_len --exact --nocapture
+#[test]
+fn test_keccak_instance_len() {
+    let mut rng = OsRng;
+    for (output_mode, public_num_proof_ids, include_vk_digest) in [
+        (OutputMode::Linear, false, false),
+        (OutputMode::Linear, false, true),
+        (OutputMode::SubmissionId, false, false),
+        (OutputMode::SubmissionId, true, false),
+        (OutputMode::Both, false, false),
+        (OutputMode::Both, true, false),
+    ] {
+        let config = KeccakConfig {
+            public_num_proof_ids,
+            include_vk_digest,
+            ..test_keccak_config(18, output_mode)
+        };
+        let inputs = KeccakCircuitInputs::<Fr>::sample(&config, &mut rng);
+        let instance = KeccakCircuit::compute_instance(&config, &inputs);
+        assert_eq!(instance.len(), keccak_instance_len(&config));
+
+        let layout = KeccakCircuit::describe_instance(&config);
+        check_instance_layout(&layout, instance.len());
+        for (i, input) in inputs.inputs.iter().enumerate() {
+            let (_, range) = layout
+                .iter()
+                .find(|(name, _)| name == &format!("entry{i}.len"))
+                .expect("missing len segment");
+            assert_eq!(
+                instance[range.clone()],
+                [Fr::from(input.app_public_inputs.len() as u64)]
+            );
+        }
+        let (last_name, _) = layout.last().unwrap();
+        let expected_last_name = match include_vk_digest {
+            true => "vk_digest",
+            false if output_mode.outputs_submission_id() => "submission_id",
+            false => "linear_digest",
+        };
+        assert_eq!(last_name, expected_last_name);
+    }
+}
+
+/// Checks that [`PaddedVerifyingKeyLimbs`] can be decoded from limbs of the
+/// correct length, and that too short or too long limb slices are rejected.
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_padded_vk_limbs_try_from --exact --nocapture
+#[test]
+fn test_padded_vk_limbs_try_from() {
+    let len_s = NUM_APP_PUBLIC_INPUTS as usize + 1;
+    let num_vk_limbs = NUM_LIMBS * (14 + 2 * len_s + 8);
+    let limbs = (0..num_vk_limbs as u64).map(Fr::from).collect::<Vec<_>>();
+
+    let vk_limbs =
+        PaddedVerifyingKeyLimbs::try_from((limbs.as_slice(), len_s)).unwrap();
+    assert_eq!(vk_limbs.flatten(), limbs);
+    assert_eq!(vk_limbs.num_commitments(), 1);
+    assert_eq!(
+        vk_limbs,
+        PaddedVerifyingKeyLimbs::from_limbs(&limbs, len_s, NUM_LIMBS)
+    );
+
+    for actual in [0, num_vk_limbs - 1, num_vk_limbs + 1] {
+        let mut invalid_limbs = limbs.clone();
+        invalid_limbs.resize(actual, Fr::zero());
+        let err = PaddedVerifyingKeyLimbs::try_from((
+            invalid_limbs.as_slice(),
+            len_s,
+        ))
+        .unwrap_err();
+        assert_eq!(
+            err,
+            LimbError::InvalidLength {
+                len_s,
+                expected: num_vk_limbs,
+                actual,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "expected {num_vk_limbs} limbs for len_s = {len_s}, got {actual}"
+            )
+        );
+    }
+
+    assert_eq!(
+        PaddedVerifyingKeyLimbs::try_from((limbs.as_slice(), 0)),
+        Err(LimbError::ZeroLenS)
+    );
+}
+
+/// Checks [`PaddedVerifyingKeyLimbs::iter`] and
+/// [`PaddedVerifyingKeyLimbs::flatten`] against a hardcoded vector, to
+/// guard the limb order on which the keccak and UBV instances depend.
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_padded_vk_limbs_order --exact --nocapture
+#[test]
+fn test_padded_vk_limbs_order() {
+    let fr = |values: &[u64]| {
+        values.iter().copied().map(Fr::from).collect::<Vec<_>>()
+    };
+    // One limb per coordinate, two commitment keys.
+    let vk_limbs = PaddedVerifyingKeyLimbs {
+        alpha: fr(&[1, 2]),
+        beta: fr(&[3, 4, 5, 6]),
+        gamma: fr(&[7, 8, 9, 10]),
+        delta: fr(&[11, 12, 13, 14]),
+        s: vec![fr(&[15, 16]), fr(&[17, 18])],
+        h1: fr(&[19, 20, 21, 22, 23, 24, 25, 26]),
+        h2: fr(&[27, 28, 29, 30, 31, 32, 33, 34]),
+    };
+    #[rustfmt::skip]
+    let expected = fr(&[
+        1, 2, // alpha
+        3, 4, 5, 6, // beta
+        7, 8, 9, 10, // gamma
+        11, 12, 13, 14, // delta
+        15, 16, 17, 18, // s
+        19, 20, 21, 22, 23, 24, 25, 26, // h1
+        27, 28, 29, 30, 31, 32, 33, 34, // h2
+    ]);
+    assert_eq!(vk_limbs.flatten(), expected);
+    assert!(vk_limbs.iter().eq(expected.iter()));
+    assert_eq!(vk_limbs.num_commitments(), 2);
+    assert_eq!(
+        PaddedVerifyingKeyLimbs::from_limbs(&expected, 2, 1),
+        vk_limbs
+    );
+}
+
+/// Checks that [`PaddedVerifyingKeyLimbs::from_vk_unpadded`] matches the vk
+/// limbs of each entry in the instance computed by
+/// [`KeccakCircuit::compute_instance`].
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_keccak_vk_limbs_from_vk_unpadded --exact --nocapture
+#[test]
+fn test_keccak_vk_limbs_from_vk_unpadded() {
+    let mut rng = OsRng;
+    let config = test_keccak_config(18, OutputMode::Linear);
+    let inputs = KeccakCircuitInputs::<Fr>::sample(&config, &mut rng);
+    let instance = KeccakCircuit::compute_instance(&config, &inputs);
+    let entry_len = inputs_per_application_proof_with_commitments(
+        config.num_app_public_inputs as usize,
+        config.max_commitments as usize,
+        config.num_limbs,
+    );
+    for (entry, input) in inputs.inputs.iter().enumerate() {
+        let vk_limbs = PaddedVerifyingKeyLimbs::<Fr>::from_vk_unpadded(
+            &input.app_vk,
+            config.num_app_public_inputs as usize,
+            config.max_commitments as usize,
+            config.limb_bits,
+            config.num_limbs,
+        );
+        // Each entry starts with `len`, followed by the vk limbs.
+        let start = entry * entry_len + 1;
+        assert_eq!(instance[start..start + vk_limbs.len()], vk_limbs[..]);
+    }
+}
+
+/// Checks that [`KeccakGateConfig::estimate`] matches the gate config
+/// computed at keygen, for a few batch sizes.
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_keccak_gate_config_estimate --exact --nocapture
+#[test]
+fn test_keccak_gate_config_estimate() {
+    for (inner_batch_size, outer_batch_size) in [(1, 1), (2, 1), (2, 2)] {
+        let config = KeccakConfig {
+            inner_batch_size,
+            outer_batch_size,
+            ..test_keccak_config(18, OutputMode::SubmissionId)
+        };
+        let estimate = KeccakGateConfig::estimate(&config);
+        let circuit = KeccakCircuit::keygen(&config, &());
+        // `FlexGateConfigParams` is not `PartialEq`, so compare serializations.
+        assert_eq!(
+            serde_json::to_string(&estimate).unwrap(),
+            serde_json::to_string(circuit.gate_config()).unwrap(),
+            "estimate mismatch for batch size {inner_batch_size}x{outer_batch_size}"
+        );
+    }
+}
+
+/// Checks that [`KeccakGateConfig::check_rows`] accepts a config which fits
+/// in its degree, and rejects the same config with a deliberately
+/// undersized degree.
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_keccak_gate_config_check_rows --exact --nocapture
+#[test]
+fn test_keccak_gate_config_check_rows() {
+    let config = KeccakConfig {
+        inner_batch_size: 2,
+        outer_batch_size: 2,
+        ..test_keccak_config(18, OutputMode::SubmissionId)
+    };
+    assert_eq!(KeccakGateConfig::check_rows(&config), Ok(()));
+
+    let undersized_config = KeccakConfig {
+        degree_bits: 10,
+        lookup_bits: 9,
+        ..config
+    };
+    let err = KeccakGateConfig::check_rows(&undersized_config).unwrap_err();
+    assert_eq!(err.degree_bits, 10);
+    assert_eq!(err.usable_rows, (1 << 10) - keccak::DEFAULT_UNUSABLE_ROWS);
+    assert!(err.required_rows > err.usable_rows);
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "config requires ~{} rows but degree 10 provides only {}",
+            err.required_rows, err.usable_rows
+        )
+    );
+    // The rows required only depend on the number of keccak permutations.
+    assert_eq!(
+        KeccakGateConfig::check_rows(&KeccakConfig {
+            degree_bits: 11,
+            lookup_bits: 9,
+            unusable_rows: Some(1 << 10),
+            ..config
+        }),
+        Err(InsufficientRows {
+            degree_bits: 11,
+            usable_rows: 1 << 10,
+            ..err
+        })
+    );
+}
+
+/// Checks that [`KeccakGateConfig::assert_compatible`] reports each
+/// mismatch between a gate config and a [`KeccakConfig`].
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_keccak_gate_config_mismatch --exact --nocapture
+#[test]
+fn test_keccak_gate_config_mismatch() {
+    let gate_config: KeccakGateConfig =
+        serde_json::from_value(serde_json::json!({
+            "flex_gate_config_params": FlexGateConfigParams {
+                strategy: GateStrategy::Vertical,
+                k: 18,
+                num_advice_per_phase: vec![2],
+                num_lookup_advice_per_phase: vec![1],
+                num_fixed: 1,
+            },
+            "rows_per_round": 10,
+            "lookup_bits": KECCAK_LOOKUP_BITS,
+        }))
+        .expect("valid gate config");
+    let config = test_keccak_config(18, OutputMode::Linear);
+
+    let mismatch = gate_config
+        .assert_compatible(&KeccakConfig {
+            degree_bits: 19,
+            ..config
+        })
+        .unwrap_err();
+    assert_eq!(
+        mismatch,
+        KeccakConfigMismatch::DegreeBits {
+            gate_config: 18,
+            config: 19
+        }
+    );
+    assert_eq!(
+        mismatch.to_string(),
+        "gate config was generated for degree 18 but config says 19"
+    );
+
+    assert_eq!(
+        gate_config.assert_compatible(&KeccakConfig {
+            lookup_bits: KECCAK_LOOKUP_BITS + 1,
+            ..config
+        }),
+        Err(KeccakConfigMismatch::LookupBits {
+            gate_config: KECCAK_LOOKUP_BITS,
+            config: KECCAK_LOOKUP_BITS + 1
+        })
+    );
+
+    assert_eq!(
+        gate_config.assert_compatible(&KeccakConfig {
+            max_rows_per_round: Some(9),
+            ..config
+        }),
+        Err(KeccakConfigMismatch::RowsPerRound {
+            gate_config: 10,
+            max: 9
+        })
+    );
+
+    assert_eq!(
+        gate_config.assert_compatible(&KeccakConfig {
+            unusable_rows: Some(200),
+            ..config
+        }),
+        Err(KeccakConfigMismatch::UnusableRows {
+            gate_config: None,
+            config: Some(200)
+        })
+    );
+
+    // 2 advice + 1 lookup advice + 1 copy column
+    assert_eq!(gate_config.check_num_flex_cols(4), Ok(()));
+    assert_eq!(
+        gate_config.check_num_flex_cols(5),
+        Err(KeccakConfigMismatch::FlexCols {
+            gate_config: 4,
+            env: 5
+        })
+    );
+}
+
+/// Checks that the `rows_per_round` computed at keygen honors
+/// [`KeccakConfig::max_rows_per_round`].
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_keccak_max_rows_per_round --exact --nocapture
+#[test]
+fn test_keccak_max_rows_per_round() {
+    let mut config = test_keccak_config(18, OutputMode::SubmissionId);
+    let default_rows_per_round =
+        KeccakCircuit::<Fr, G1Affine>::keygen(&config, &())
+            .gate_config()
+            .rows_per_round();
+    assert!(default_rows_per_round <= keccak::MAX_KECCAK_ROWS_PER_ROUND);
+    assert!(default_rows_per_round > 1, "degree too small for this test");
+
+    let max_rows_per_round = default_rows_per_round - 1;
+    config.max_rows_per_round = Some(max_rows_per_round);
+    let rows_per_round = KeccakCircuit::<Fr, G1Affine>::keygen(&config, &())
+        .gate_config()
+        .rows_per_round();
+    assert_eq!(rows_per_round, max_rows_per_round);
+}
+
+/// Checks that the `rows_per_round` computed at keygen honors
+/// [`KeccakConfig::unusable_rows`].  The cap on rows per round is lifted so
+/// that `rows_per_round` is determined by the number of usable rows alone.
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_keccak_unusable_rows --exact --nocapture
+#[test]
+fn test_keccak_unusable_rows() {
+    let degree_bits = 18;
+    let mut config = KeccakConfig {
+        max_rows_per_round: Some(u32::MAX),
+        ..test_keccak_config(degree_bits, OutputMode::SubmissionId)
+    };
+    let rows_per_round = |config: &KeccakConfig| {
+        KeccakCircuit::keygen(config, &())
+            .gate_config()
+            .rows_per_round() as usize
+    };
+    let usable_rows = |unusable_rows: usize| (1 << degree_bits) - unusable_rows;
+
+    // Explicitly setting the default is the same as not setting it.
+    let default_rows_per_round = rows_per_round(&config);
+    config.unusable_rows = Some(keccak::DEFAULT_UNUSABLE_ROWS);
+    assert_eq!(rows_per_round(&config), default_rows_per_round);
+
+    // Halving the usable rows (roughly) halves the rows per round. Writing
+    // `R` for the number of keccak rounds, `rows_per_round = floor(usable /
+    // R)`, so the bounds below follow from those on `R` implied by
+    // `default_rows_per_round`.
+    let unusable_rows = usable_rows(keccak::DEFAULT_UNUSABLE_ROWS) / 2;
+    config.unusable_rows = Some(unusable_rows);
+    let rows_per_round = rows_per_round(&config);
+    let default_usable = usable_rows(keccak::DEFAULT_UNUSABLE_ROWS);
+    let usable = usable_rows(unusable_rows);
+    assert!(rows_per_round > 0 && rows_per_round < default_rows_per_round);
+    assert!(
+        rows_per_round * default_usable < usable * (default_rows_per_round + 1)
+    );
+    assert!(
+        (rows_per_round + 1) * default_usable > usable * default_rows_per_round
+    );
+}
+
+/// Mock prover check of a [`KeccakCircuit`] supporting up to 2 commitments
+/// per application proof, where the inputs use 0, 1 and 2 commitments.
+///
+/// # Command line
+///
+/// KECCAK_DEGREE=18 RUST_LOG=info cargo test --release -- --nocapture test_keccak_mock_multiple_commitments
+#[test]
+fn test_keccak_mock_multiple_commitments() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let k: u32 = var("KECCAK_DEGREE")
+        .unwrap_or_else(|_| "18".to_string())
+        .parse()
+        .expect("Parsing error");
+    let max_commitments = 2;
+    let config = KeccakConfig {
+        max_commitments,
+        ..test_keccak_config(k, OutputMode::Linear)
+    };
+    let mut rng = OsRng;
+    let mut inputs = KeccakCircuitInputs::<Fr>::sample(&config, &mut rng);
+    for (i, input) in inputs.inputs.iter_mut().enumerate() {
+        let num_commitments = i % (max_commitments as usize + 1);
+        let max_len = NUM_APP_PUBLIC_INPUTS as usize - num_commitments;
+        input.app_public_inputs.truncate(max_len);
+        input.app_vk.s.truncate(input.app_public_inputs.len() + 1);
+        for _ in 0..num_commitments {
+            let m = G1Affine::from(G1Affine::generator() * Fr::random(rng));
+            input.commitment_point_coordinates.push([m.x, m.y]);
+            input
+                .app_vk
+                .s
+                .push(G1Affine::from(G1Affine::generator() * Fr::random(rng)));
+            input
+                .app_vk
+                .h1
+                .push(G2Affine::from(G2Affine::generator() * Fr::random(rng)));
+            input
+                .app_vk
+                .h2
+                .push(G2Affine::from(G2Affine::generator() * Fr::random(rng)));
+        }
+    }
+    let circuit = KeccakCircuit::mock(&config, &inputs);
+    let instances: Vec<Fr> = circuit.instances()[0].clone();
+    assert_eq!(instances.len(), keccak_instance_len(&config));
+    circuit
+        .check_well_constructed(&config)
+        .unwrap_or_else(|err| panic!("Circuit not well constructed: {err:?}"));
+    assert!(check_instance(&circuit, &config, &inputs));
+    MockProver::<Fr>::run(k, &circuit, vec![instances])
+        .expect("Mock prover run failure")
+        .assert_satisfied();
 }
 
 /// Instantiates a [`KeccakCircuitBuilder`] with random inputs and generates/verifies a proof.
@@ -365,14 +845,7 @@ fn test_keccak_prover(output_submission_id: bool) {
         .unwrap_or_else(|_| "18".to_string())
         .parse()
         .expect("Parsing error");
-    let config = KeccakConfig {
-        degree_bits: k,
-        num_app_public_inputs: NUM_APP_PUBLIC_INPUTS,
-        inner_batch_size: INNER_BATCH_SIZE,
-        outer_batch_size: OUTER_BATCH_SIZE,
-        lookup_bits: KECCAK_LOOKUP_BITS,
-        output_submission_id,
-    };
+    let config = test_keccak_config(k, output_submission_id.into());
     let mut rng = OsRng;
     let inputs = KeccakCircuitInputs::sample(&config, &mut rng);
     // Keygen
@@ -394,7 +867,7 @@ fn test_keccak_prover(output_submission_id: bool) {
     let circuit =
         KeccakCircuit::prover(&config, &gate_config, break_points, &inputs);
     circuit
-        .is_well_constructed(&config)
+        .check_well_constructed(&config)
         .unwrap_or_else(|err| panic!("Circuit not well constructed: {err:?}"));
     assert!(check_instance(&circuit, &config, &inputs));
     let instances: &[Fr] = &circuit.instances()[0];
@@ -452,6 +925,65 @@ fn test_keccak_prover_no_sid() {
     test_keccak_prover(false);
 }
 
+/// Generates keys for a [`KeccakCircuit`] and checks they survive a
+/// write/read round-trip.
+///
+/// # Command line
+///
+/// KECCAK_DEGREE=18 RUST_LOG=info cargo test --release -- --ignored --nocapture test_keccak_key_round_trip
+#[test]
+#[ignore = "takes too long"]
+fn test_keccak_key_round_trip() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let k: u32 = var("KECCAK_DEGREE")
+        .unwrap_or_else(|_| "18".to_string())
+        .parse()
+        .expect("Parsing error");
+    let config = test_keccak_config(k, OutputMode::SubmissionId);
+    let params = gen_srs(k);
+    let circuit = KeccakCircuit::keygen(&config, &());
+    let vk = keygen_vk(&params, &circuit).expect("unable to gen. vk");
+    let pk = keygen_pk(&params, vk, &circuit).expect("unable to gen. pk");
+    check_key_round_trip::<KeccakCircuit>(&config, circuit.gate_config(), &pk);
+}
+
+/// Checks that witness generation stops with `Err(Cancelled)` once the
+/// cancellation token is set, here after the first input is assigned.
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::keccak::test_keccak_cancellation --exact --nocapture
+#[test]
+fn test_keccak_cancellation() {
+    let config = test_keccak_config(18, OutputMode::Linear);
+    let inputs = KeccakCircuitInputs::<Fr>::sample(&config, &mut OsRng);
+    let padded_inputs = KeccakPaddedCircuitInputs::from_keccak_circuit_inputs(
+        &inputs,
+        config.num_app_public_inputs as usize,
+        config.max_commitments as usize,
+        config.limb_bits,
+        config.num_limbs,
+    )
+    .expect("valid inputs");
+
+    let cancel = CancellationToken::new();
+    let mut num_assigned = 0;
+    let result = KeccakCircuit::new_cancellable(
+        &config,
+        GateThreadBuilder::mock(),
+        padded_inputs,
+        &mut |progress| {
+            if let KeccakProgress::AssigningCells { done, .. } = progress {
+                num_assigned = done;
+                cancel.cancel();
+            }
+        },
+        cancel.clone(),
+    );
+    assert!(matches!(result, Err(Cancelled)));
+    assert_eq!(num_assigned, 1, "inputs assigned after cancellation");
+}
+
 /// Unit test checking that [`KeccakPaddedCircuitInputs::to_instance_values`]
 /// works correctly in both the fixed and variable length cases.
 ///
@@ -477,6 +1009,7 @@ fn test_keccak_padded_circuit_input_to_instance_values() {
     let dummy_app_vk = PaddedVerifyingKeyLimbs::from_limbs(
         &dummy_app_vk_limbs,
         NUM_APP_PUBLIC_INPUTS as usize + 1,
+        NUM_LIMBS,
     );
 
     // Test variable length case
@@ -484,20 +1017,20 @@ fn test_keccak_padded_circuit_input_to_instance_values() {
     let variable_padded_circuit_input = KeccakPaddedCircuitInput {
         len: Fr::from(variable_len),
         app_vk: dummy_app_vk,
-        has_commitment: Fr::zero(),
+        num_commitments: Fr::zero(),
         app_public_inputs: dummy_app_public_inputs[..variable_len as usize]
             .to_vec(),
         commitment_point_limbs: dummy_commitment_limbs,
-        commitment_hash: dummy_commitment_hash,
+        commitment_hashes: vec![dummy_commitment_hash],
     };
 
     let mut expected_variable_instance_values =
         vec![variable_padded_circuit_input.len];
     expected_variable_instance_values.extend(dummy_app_vk_limbs);
     expected_variable_instance_values
-        .push(variable_padded_circuit_input.has_commitment);
+        .push(variable_padded_circuit_input.num_commitments);
     expected_variable_instance_values
-        .push(variable_padded_circuit_input.commitment_hash);
+        .extend(&variable_padded_circuit_input.commitment_hashes);
     expected_variable_instance_values
         .extend(&variable_padded_circuit_input.commitment_point_limbs);
     expected_variable_instance_values
@@ -514,9 +1047,9 @@ impl<F: EccPrimeField> AssignedKeccakInput<F> {
         self.len().value().get_lower_32() as usize
     }
 
-    /// Returns 1 if the input uses the optional commitment, and 0 otherwise.
-    pub fn has_commitment(&self) -> bool {
-        self.has_commitment.value().get_lower_32() != 0
+    /// Returns the number of commitments used by the input.
+    pub fn num_commitments(&self) -> usize {
+        self.num_commitments.value().get_lower_32() as usize
     }
 }
 
@@ -2,9 +2,10 @@ use crate::{
     keccak::{
         chip::{assigned_cell_from_assigned_value, KeccakChip},
         multivar::KeccakMultiVarHasher,
-        KeccakCircuit, KeccakCircuitConfig, DEFAULT_UNUSABLE_ROWS,
+        KeccakCircuit, KeccakCircuitConfig, LIMB_BITS, NUM_LIMBS,
     },
     tests::remove_env_variables,
+    utils::keccak_hasher::native_multi_var_query,
     EccPrimeField,
 };
 use core::{cell::RefCell, default::Default};
@@ -17,12 +18,18 @@ use halo2_base::{
     halo2_proofs::{
         circuit::{Cell, Layouter, SimpleFloorPlanner},
         dev::MockProver,
-        halo2curves::bn256::{Fr, G1Affine},
+        halo2curves::{
+            bn256::{Fq, Fr, G1Affine},
+            group::ff::Field,
+        },
         plonk::{Circuit, ConstraintSystem, Error},
     },
+    utils::{decompose_biguint, fe_to_biguint},
     AssignedValue,
 };
 use itertools::Itertools;
+use rand::Rng;
+use rand_core::OsRng;
 use std::env::var;
 
 // Define a KeccakHasher circuit for testing
@@ -39,7 +46,7 @@ impl KeccakTestCircuit {
         mut builder: GateThreadBuilder<Fr>,
         degree_bits: usize,
         fixed_input: Vec<u8>,
-        var_inputs: Vec<Vec<u8>>,
+        var_inputs: Vec<Vec<Fr>>,
         var_input_lengths: Vec<usize>,
         expected_digest: [u8; 32],
     ) -> Self {
@@ -50,11 +57,7 @@ impl KeccakTestCircuit {
             .collect();
         let var_inputs: Vec<Vec<AssignedValue<Fr>>> = var_inputs
             .iter()
-            .map(|x| {
-                x.iter()
-                    .map(|y| ctx.load_witness(Fr::from(*y as u64)))
-                    .collect()
-            })
+            .map(|x| x.iter().map(|y| ctx.load_witness(*y)).collect())
             .collect();
         let var_input_lengths: Vec<AssignedValue<Fr>> = var_input_lengths
             .iter()
@@ -65,7 +68,7 @@ impl KeccakTestCircuit {
             .map(|x| ctx.load_witness(Fr::from(*x as u64)))
             .collect();
 
-        let mut hasher = KeccakMultiVarHasher::new();
+        let mut hasher = KeccakMultiVarHasher::new(LIMB_BITS, NUM_LIMBS);
         hasher.absorb_fixed(&fixed_input);
         for (input, len) in var_inputs.iter().zip_eq(&var_input_lengths) {
             hasher.absorb_var(input, *len);
@@ -88,8 +91,9 @@ impl KeccakTestCircuit {
             &builder,
             &mut keccak_chip,
             degree_bits as u32,
-            Some(DEFAULT_UNUSABLE_ROWS),
+            None,
             Some(degree_bits - 1),
+            None,
         );
         Self {
             builder: RefCell::new(builder),
@@ -219,6 +223,28 @@ fn test_keccak_mock(
     var_inputs: Vec<Vec<u8>>,
     var_input_lengths: Vec<usize>,
     expected_digest: [u8; 32],
+) {
+    let var_inputs = var_inputs
+        .into_iter()
+        .map(|x| x.into_iter().map(|y| Fr::from(y as u64)).collect())
+        .collect();
+    test_keccak_mock_limbs(
+        degree_bits,
+        fixed_input,
+        var_inputs,
+        var_input_lengths,
+        expected_digest,
+    );
+}
+
+/// As [`test_keccak_mock`], where the var length inputs are arbitrary limb
+/// values.
+fn test_keccak_mock_limbs(
+    degree_bits: usize,
+    fixed_input: Vec<u8>,
+    var_inputs: Vec<Vec<Fr>>,
+    var_input_lengths: Vec<usize>,
+    expected_digest: [u8; 32],
 ) {
     let circuit = KeccakTestCircuit::new(
         GateThreadBuilder::new(false),
@@ -364,3 +390,57 @@ fn test_fixed_var_var_var() {
         expected_digest,
     );
 }
+
+/// Checks that [`native_multi_var_query`] agrees with the in-circuit
+/// multi-var hasher, for random fixed inputs and random Fq elements in the
+/// var length inputs.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::keccak::multivar::test_native_multi_var_query --exact --show-output`
+#[test]
+fn test_native_multi_var_query() {
+    let degree_bits = 16;
+    let mut rng = OsRng;
+
+    let fixed_input: Vec<u8> =
+        (0..8 * rng.gen_range(1..4)).map(|_| rng.gen()).collect();
+    let mut var_input_bytes = Vec::new();
+    let mut var_inputs = Vec::new();
+    let mut var_input_lengths = Vec::new();
+    for _ in 0..rng.gen_range(1..=3) {
+        let padded_len = rng.gen_range(1..=3);
+        let len = rng.gen_range(1..=padded_len);
+        let elements = (0..padded_len).map(|_| Fq::random(rng)).collect_vec();
+        var_input_bytes.push(
+            elements
+                .iter()
+                .flat_map(|fq| fq.to_bytes().into_iter().rev())
+                .collect_vec(),
+        );
+        var_inputs.push(
+            elements
+                .iter()
+                .flat_map(|fq| {
+                    decompose_biguint::<Fr>(
+                        &fe_to_biguint(fq),
+                        NUM_LIMBS,
+                        LIMB_BITS,
+                    )
+                })
+                .collect_vec(),
+        );
+        var_input_lengths.push((len, len * NUM_LIMBS));
+    }
+
+    let expected_digest = native_multi_var_query(
+        &fixed_input,
+        &var_input_bytes.iter().map(Vec::as_slice).collect_vec(),
+        &var_input_lengths.iter().map(|(len, _)| *len).collect_vec(),
+    );
+    test_keccak_mock_limbs(
+        degree_bits,
+        fixed_input,
+        var_inputs,
+        var_input_lengths.into_iter().map(|(_, len)| len).collect(),
+        expected_digest,
+    );
+}
@@ -1,39 +1,53 @@
+use super::test_keccak_config;
 use crate::{
     batch_verify::{
         common::native::json::field_element_from_str,
         universal::native::compute_circuit_id,
     },
     keccak::{
-        chip::KeccakChip,
-        inputs::KeccakCircuitInputs,
+        chip::{KeccakChip, QueryRef},
+        inputs::{KeccakCircuitInputs, KeccakVarLenInput},
         utils::{
-            assert_byte_decomposition_is_in_field, byte_decomposition,
+            assert_byte_decomposition_is_in_field, build_submission_tree,
+            build_submission_tree_with_domain_separation, byte_decomposition,
             byte_decomposition_powers, compose_into_field_element,
-            compute_final_digest, compute_proof_id, compute_submission_id,
+            compute_final_digest, compute_merkle_path,
+            compute_merkle_root_from_path, compute_proof_id,
+            compute_submission_id,
+            compute_submission_id_with_domain_separation,
             digest_as_field_elements, encode_digest_as_field_elements,
             field_max_element_into_parts, g1_point_limbs_to_bytes,
+            FinalDigestHasher,
         },
-        KeccakCircuit, KeccakConfig, KeccakPaddedCircuitInputs,
-        KECCAK_LOOKUP_BITS, LIMB_BITS, NUM_LIMBS,
+        KeccakCircuit, KeccakConfig, KeccakPaddedCircuitInputs, OutputMode,
+        DEFAULT_MAX_COMMITMENTS, LIMB_BITS, NUM_LIMBS,
     },
     tests::{
         commitment_point::{
             parse_commitment_point, FIELD_ELEMENT_HEX, OUTPUT_BYTES,
         },
         load_proof_and_inputs, load_vk,
+        utils::check_instance,
+        VK_FILE,
     },
     utils::commitment_point::{self, g1affine_into_limbs},
     EccPrimeField, SafeCircuit,
 };
+use core::iter;
+use ethers_core::utils::keccak256;
 use halo2_base::{
     gates::builder::GateThreadBuilder,
-    halo2_proofs::halo2curves::bn256::{Fq, Fr, G1Affine},
+    halo2_proofs::{
+        dev::MockProver,
+        halo2curves::bn256::{Fq, Fr, G1Affine},
+    },
     safe_types::RangeChip,
 };
 use hex::ToHex;
 use itertools::Itertools;
 use rand::Rng;
 use rand_core::OsRng;
+use snark_verifier_sdk::CircuitExt;
 
 /// Max vector length for tests to prevent overflow.
 const MAX_VEC_LEN: u64 = 200;
@@ -177,6 +191,54 @@ fn composition_test() {
     assert_eq!(digest_result, compose_result, "Composition mismatch");
 }
 
+/// Checks that [`KeccakChip::keccak_fixed_len_as_field_elements`] matches the
+/// manual composition of the output bytes of
+/// [`KeccakChip::keccak_fixed_len`], and the native digest.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::keccak::utils::test_keccak_fixed_len_as_field_elements --exact --nocapture`
+#[test]
+fn test_keccak_fixed_len_as_field_elements() {
+    let mut builder = GateThreadBuilder::<Fr>::mock();
+    let ctx = builder.main(0);
+    let range = RangeChip::default(8);
+    let mut rng = OsRng;
+    let input_bytes: Vec<u8> = (0..64).map(|_| rng.gen()).collect();
+    let assigned_input_bytes = ctx.assign_witnesses(
+        input_bytes.iter().map(|byte| Fr::from(*byte as u64)),
+    );
+
+    let mut keccak_chip = KeccakChip::default();
+    let digest = keccak_chip
+        .keccak_fixed_len_as_field_elements(
+            ctx,
+            &range,
+            assigned_input_bytes.clone(),
+        )
+        .map(|assigned| *assigned.value());
+
+    let mut manual_keccak_chip = KeccakChip::default();
+    manual_keccak_chip.keccak_fixed_len(ctx, &range, assigned_input_bytes);
+    let output_bytes = manual_keccak_chip
+        .fixed_len_queries()
+        .last()
+        .unwrap()
+        .output_bytes_assigned()
+        .to_vec()
+        .try_into()
+        .unwrap();
+    let manual_digest =
+        encode_digest_as_field_elements(ctx, &range, &output_bytes)
+            .map(|assigned| *assigned.value());
+
+    assert_eq!(digest, manual_digest, "Composition mismatch");
+    assert_eq!(
+        digest,
+        digest_as_field_elements(&keccak256(&input_bytes)),
+        "Native digest mismatch"
+    );
+    assert_eq!(keccak_chip.fixed_len_queries().len(), 1);
+}
+
 /// Computes and outputs the proof_id of the proofs in the test data.  Primary
 /// purpose is to produce text vectors for implementations in other languages.
 #[test]
@@ -236,18 +298,38 @@ pub fn test_compute_final_digest_test_vector() {
     );
 }
 
+/// Checks that [`FinalDigestHasher`] computes the same digest as
+/// [`compute_final_digest`] (and as Keccak of the concatenated proof ids).
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::keccak::utils::test_final_digest_hasher --exact --nocapture`
+#[test]
+fn test_final_digest_hasher() {
+    const NUM_PROOF_IDS: usize = 1000;
+    let mut rng = OsRng;
+    let proof_ids: Vec<[u8; 32]> =
+        (0..NUM_PROOF_IDS).map(|_| rng.gen()).collect();
+
+    let mut hasher = FinalDigestHasher::new();
+    for proof_id in &proof_ids {
+        hasher.update(proof_id);
+    }
+    let streamed = hasher.finalize();
+
+    assert_eq!(streamed, compute_final_digest(&proof_ids));
+    assert_eq!(streamed, keccak256(proof_ids.concat()));
+    assert_eq!(FinalDigestHasher::new().finalize(), keccak256(b""));
+}
+
 #[test]
 fn keccak_var_len_input_serialization() {
     const DEFAULT_DEGREE_BITS: u32 = 20;
     let mut rng = OsRng;
     let num_app_public_inputs = 20u32;
     let config = KeccakConfig {
-        degree_bits: DEFAULT_DEGREE_BITS,
         inner_batch_size: 1,
         outer_batch_size: 1,
         num_app_public_inputs,
-        lookup_bits: KECCAK_LOOKUP_BITS,
-        output_submission_id: false, // Irrelevant for this test
+        ..test_keccak_config(DEFAULT_DEGREE_BITS, OutputMode::Linear)
     };
     let mut inputs = KeccakCircuitInputs::<Fr>::sample(&config, &mut rng);
     inputs.inputs[0]
@@ -260,6 +342,65 @@ fn keccak_var_len_input_serialization() {
     assert_eq!(inputs, inputs_deserialized)
 }
 
+/// Enumerates the queries made by a [`KeccakCircuit`] for a small batch,
+/// checking their order and that each output is the keccak digest of its
+/// input.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::keccak::utils::iter_keccak_queries --exact --nocapture`
+#[test]
+fn iter_keccak_queries() {
+    let mut rng = OsRng;
+    let config = KeccakConfig {
+        inner_batch_size: 2,
+        outer_batch_size: 1,
+        num_app_public_inputs: 4,
+        ..test_keccak_config(20, OutputMode::Linear)
+    };
+    let keccak_inputs = KeccakCircuitInputs::sample(&config, &mut rng);
+    let circuit = KeccakCircuit::<Fr, G1Affine>::mock(&config, &keccak_inputs);
+    let chip = circuit.keccak_chip();
+
+    let queries = chip.iter_queries().collect_vec();
+    let num_var_len_queries = chip.var_len_queries().len();
+    // A circuitId and a proofId query per application proof.
+    assert_eq!(num_var_len_queries, 2 * keccak_inputs.inputs.len());
+    assert_eq!(
+        queries.len(),
+        num_var_len_queries + chip.fixed_len_queries().len()
+    );
+    for (i, query) in queries.iter().enumerate() {
+        assert_eq!(
+            matches!(query, QueryRef::Var(_)),
+            i < num_var_len_queries,
+            "Query {i} out of order"
+        );
+        assert_eq!(
+            query.output_bytes(),
+            keccak256(query.input_bytes()),
+            "Query {i} output mismatch"
+        );
+        assert_eq!(
+            query
+                .output_bytes_assigned()
+                .iter()
+                .map(|byte| byte.value().get_lower_32() as u8)
+                .collect_vec(),
+            query.output_bytes(),
+            "Query {i} assigned output mismatch"
+        );
+    }
+    assert_eq!(
+        queries[0].output_bytes(),
+        compute_circuit_id(&keccak_inputs.inputs[0].app_vk),
+        "First query is not the first circuitId"
+    );
+    assert_eq!(
+        circuit.keccak_output_bytes().len(),
+        32 * queries.len(),
+        "Output bytes length mismatch"
+    );
+}
+
 /// Checks the [`KeccakCircuit`] computes circuitIds and proofIds correctly by
 /// comparing them to the output of [`compute_circuit_id`] and [`compute_proof_id`],
 /// respectively.
@@ -270,18 +411,20 @@ fn circuit_id_and_proof_id_test() {
     let num_app_public_inputs = rng.gen_range(1..MAX_VEC_LEN) as u32;
     // We use a circuit with only one application circuit as input
     let config = KeccakConfig {
-        degree_bits: DEFAULT_DEGREE_BITS,
         inner_batch_size: 1,
         outer_batch_size: 1,
         num_app_public_inputs,
-        lookup_bits: KECCAK_LOOKUP_BITS,
-        output_submission_id: false, // Irrelevant for this test
+        ..test_keccak_config(DEFAULT_DEGREE_BITS, OutputMode::Linear)
     };
     let keccak_inputs = KeccakCircuitInputs::sample(&config, &mut rng);
     let circuit_inputs = KeccakPaddedCircuitInputs::from_keccak_circuit_inputs(
         &keccak_inputs,
         num_app_public_inputs as usize,
-    );
+        DEFAULT_MAX_COMMITMENTS as usize,
+        LIMB_BITS,
+        NUM_LIMBS,
+    )
+    .expect("invalid commitment point");
 
     let number_of_field_elements =
         circuit_inputs.inputs[0].num_field_elements();
@@ -313,6 +456,100 @@ fn circuit_id_and_proof_id_test() {
     assert_eq!(proof_id, circuit_output_proof_id, "Proof id mismatch");
 }
 
+/// Checks that configs without explicit limb parameters default to the
+/// (88, 3) decomposition, and that the [`KeccakCircuit`] then computes the
+/// known circuitId of `vk.json` (the test vector in `upa/test/upaLibTests.ts`).
+///
+/// CMD: `cargo test --package circuits -- tests::keccak::utils::default_limbs_circuit_id_regression --exact --nocapture`
+#[test]
+fn default_limbs_circuit_id_regression() {
+    let config = vk_file_keccak_config();
+
+    // A config file predating the limb parameters deserializes to the
+    // default limbs.
+    let mut config_json = serde_json::to_value(&config).unwrap();
+    let config_fields = config_json.as_object_mut().unwrap();
+    config_fields.remove("limb_bits").unwrap();
+    config_fields.remove("num_limbs").unwrap();
+    let config: KeccakConfig = serde_json::from_value(config_json).unwrap();
+    assert_eq!(config.limb_bits, 88);
+    assert_eq!(config.num_limbs, 3);
+
+    let (circuit, _) = vk_file_keccak_circuit(&config);
+    assert_eq!(
+        vk_file_circuit_id(&circuit),
+        VK_FILE_CIRCUIT_ID,
+        "Circuit id mismatch"
+    );
+}
+
+/// Checks that the [`KeccakCircuit`] is satisfied with a non-default limb
+/// decomposition (64, 4) of the vk points, and still computes the known
+/// circuitId of `vk.json`, which does not depend on the limbs.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::keccak::utils::non_default_limbs_circuit_id --exact --nocapture`
+#[test]
+fn non_default_limbs_circuit_id() {
+    let config = KeccakConfig {
+        limb_bits: 64,
+        num_limbs: 4,
+        ..vk_file_keccak_config()
+    };
+    let (circuit, keccak_inputs) = vk_file_keccak_circuit(&config);
+    assert_eq!(
+        vk_file_circuit_id(&circuit),
+        VK_FILE_CIRCUIT_ID,
+        "Circuit id mismatch"
+    );
+    assert!(check_instance(&circuit, &config, &keccak_inputs));
+    MockProver::<Fr>::run(config.degree_bits, &circuit, circuit.instances())
+        .expect("Mock prover run failure")
+        .assert_satisfied();
+}
+
+/// CircuitId of `vk.json` (the test vector in `upa/test/upaLibTests.ts`)
+const VK_FILE_CIRCUIT_ID: &str =
+    "6144828f857cd0e0bc6b6a40f426b186b897ba5ec2cd10becf94e404e91a26e2";
+
+/// Returns a [`KeccakConfig`] for a single proof for `vk.json`.
+fn vk_file_keccak_config() -> KeccakConfig {
+    let num_app_public_inputs = load_vk(VK_FILE).s.len() - 1;
+    KeccakConfig {
+        inner_batch_size: 1,
+        outer_batch_size: 1,
+        num_app_public_inputs: num_app_public_inputs as u32,
+        ..test_keccak_config(20, OutputMode::Linear)
+    }
+}
+
+/// Returns a mock [`KeccakCircuit`] for `config`, and its inputs, for a
+/// single proof for `vk.json` with zero public inputs.
+fn vk_file_keccak_circuit(
+    config: &KeccakConfig,
+) -> (KeccakCircuit<Fr, G1Affine>, KeccakCircuitInputs<Fr>) {
+    let app_vk = load_vk(VK_FILE);
+    let num_app_public_inputs = app_vk.s.len() - 1;
+    let keccak_inputs = KeccakCircuitInputs {
+        inputs: vec![KeccakVarLenInput {
+            app_vk,
+            app_public_inputs: vec![Fr::from(0); num_app_public_inputs],
+            commitment_point_coordinates: vec![],
+        }],
+        num_proof_ids: None,
+    };
+    let circuit = KeccakCircuit::<Fr, G1Affine>::mock(config, &keccak_inputs);
+    (circuit, keccak_inputs)
+}
+
+/// Returns the circuitId computed by `circuit`, as a hex string.
+fn vk_file_circuit_id(circuit: &KeccakCircuit<Fr, G1Affine>) -> String {
+    let circuit_id = circuit.keccak_output_bytes()[0..32]
+        .iter()
+        .map(|assigned_value| assigned_value.value().to_bytes()[0])
+        .collect::<Vec<_>>();
+    hex::encode(circuit_id)
+}
+
 /// Tests [`field_max_element_into_parts`] returns the right decomposition.
 #[test]
 fn test_field_modulus_parts() {
@@ -423,8 +660,13 @@ fn test_commitment_point_limbs_to_bytes() {
         );
     let assigned_commitment_point_limbs =
         ctx.assign_witnesses(commitment_point_limbs);
-    let assigned_bytes =
-        g1_point_limbs_to_bytes(ctx, &range, &assigned_commitment_point_limbs);
+    let assigned_bytes = g1_point_limbs_to_bytes(
+        ctx,
+        &range,
+        &assigned_commitment_point_limbs,
+        LIMB_BITS,
+        NUM_LIMBS,
+    );
     let assigned_commitment_bytes = assigned_bytes
         .iter()
         .map(|byte| byte.value().get_lower_32() as u8)
@@ -452,24 +694,23 @@ fn test_compose_into_field_element() {
     );
 }
 
-/// Checks the submission id computed in circuit coincides with the native
-/// computation
-#[test]
-fn test_submission_id() {
-    const NUMBER_OF_PROOFS: u64 = 8;
+/// Checks the submission id of the first `num_proof_ids` of `proof_ids`
+/// computed in circuit coincides with the native computation, with or
+/// without domain separation of the tree.
+fn check_submission_id(
+    proof_ids: &[[u8; 32]],
+    num_proof_ids: u64,
+    domain_separated: bool,
+) {
     let mut builder = GateThreadBuilder::<Fr>::mock();
     let ctx = builder.main(0);
     let range = RangeChip::default(8);
     let mut keccak_chip = KeccakChip::default();
-    let mut rng = OsRng;
-    let proof_ids: Vec<[u8; 32]> =
-        (0..NUMBER_OF_PROOFS).map(|_| rng.gen()).collect_vec();
     let proof_ids_fr = proof_ids
         .iter()
         .flat_map(|bytes| bytes.map(|byte| Fr::from(byte as u64)).to_vec())
         .collect_vec();
     let assigned_proof_ids = ctx.assign_witnesses(proof_ids_fr);
-    let num_proof_ids = rng.gen_range(1..=NUMBER_OF_PROOFS);
     let assigned_num_proof_ids = ctx.load_constant(Fr::from(num_proof_ids));
     let circuit_sid =
         KeccakCircuit::<_, G1Affine>::compute_submission_id_bytes(
@@ -478,11 +719,159 @@ fn test_submission_id() {
             &mut keccak_chip,
             &assigned_proof_ids,
             assigned_num_proof_ids,
+            domain_separated,
+            &mut |_| {},
         )
         .map(|assigned_byte| assigned_byte.value().get_lower_32() as u8);
-    let native_sid = compute_submission_id(proof_ids, num_proof_ids);
+    let native_sid = compute_submission_id_with_domain_separation(
+        proof_ids,
+        num_proof_ids,
+        domain_separated,
+    );
     assert_eq!(
         circuit_sid, native_sid,
         "Native and circuit submission id mismatch"
     );
 }
+
+/// Checks the submission id computed in circuit coincides with the native
+/// computation
+#[test]
+fn test_submission_id() {
+    const NUMBER_OF_PROOFS: u64 = 8;
+    let mut rng = OsRng;
+    let proof_ids: Vec<[u8; 32]> =
+        (0..NUMBER_OF_PROOFS).map(|_| rng.gen()).collect_vec();
+    let num_proof_ids = rng.gen_range(1..=NUMBER_OF_PROOFS);
+    check_submission_id(&proof_ids, num_proof_ids, false);
+}
+
+/// Checks that the domain-separated submission id computed in circuit
+/// coincides with the native computation, and differs from the submission id
+/// of the tree without domain separation.
+#[test]
+fn test_domain_separated_submission_id() {
+    const NUMBER_OF_PROOFS: u64 = 4;
+    let mut rng = OsRng;
+    let proof_ids: Vec<[u8; 32]> =
+        (0..NUMBER_OF_PROOFS).map(|_| rng.gen()).collect_vec();
+    for num_proof_ids in 1..=NUMBER_OF_PROOFS {
+        check_submission_id(&proof_ids, num_proof_ids, true);
+        let tree = build_submission_tree_with_domain_separation(
+            &proof_ids,
+            num_proof_ids,
+            true,
+        );
+        assert_eq!(
+            tree.root(),
+            compute_submission_id_with_domain_separation(
+                &proof_ids,
+                num_proof_ids,
+                true
+            )
+        );
+        assert_ne!(
+            tree.root(),
+            compute_submission_id(&proof_ids, num_proof_ids),
+            "Domain separation did not change the submission id"
+        );
+    }
+    assert_eq!(
+        compute_submission_id_with_domain_separation(
+            &proof_ids,
+            NUMBER_OF_PROOFS,
+            false
+        ),
+        compute_submission_id(&proof_ids, NUMBER_OF_PROOFS),
+    );
+}
+
+/// Checks that the Merkle path of each proof id recomputes the submission id.
+#[test]
+fn test_merkle_path() {
+    let mut rng = OsRng;
+    for number_of_proofs in [1u64, 3, 4, 5, 8] {
+        let proof_ids: Vec<[u8; 32]> =
+            (0..number_of_proofs).map(|_| rng.gen()).collect_vec();
+        let submission_id = compute_submission_id(&proof_ids, number_of_proofs);
+        for (index, proof_id) in proof_ids.iter().enumerate() {
+            let (path, root) = compute_merkle_path(&proof_ids, index);
+            assert_eq!(root, submission_id, "Merkle root mismatch");
+            assert_eq!(
+                path.len(),
+                number_of_proofs.next_power_of_two().ilog2() as usize,
+                "Merkle path length mismatch"
+            );
+            assert_eq!(
+                compute_merkle_root_from_path(proof_id, index, &path),
+                submission_id,
+                "Merkle path does not recompute the root"
+            );
+        }
+    }
+}
+
+/// Checks that the path of every leaf of the submission tree, including the
+/// padding leaves, verifies against its root, and that the root is the
+/// submission id.
+#[test]
+fn test_submission_tree() {
+    let mut rng = OsRng;
+    for (number_of_proofs, num_proof_ids) in [(1u64, 1), (4, 3), (5, 5), (8, 5)]
+    {
+        let proof_ids: Vec<[u8; 32]> =
+            (0..number_of_proofs).map(|_| rng.gen()).collect_vec();
+        let tree = build_submission_tree(&proof_ids, num_proof_ids);
+        assert_eq!(
+            tree.root(),
+            compute_submission_id(&proof_ids, num_proof_ids),
+            "Submission tree root mismatch"
+        );
+        assert_eq!(tree.len(), num_proof_ids.next_power_of_two() as usize);
+        let leaves = proof_ids[..num_proof_ids as usize]
+            .iter()
+            .copied()
+            .chain(iter::repeat([0u8; 32]))
+            .take(tree.len());
+        for (index, proof_id) in leaves.enumerate() {
+            let path = tree.path(index);
+            assert_eq!(
+                path.len(),
+                tree.len().ilog2() as usize,
+                "Merkle path length mismatch"
+            );
+            assert_eq!(
+                compute_merkle_root_from_path(&proof_id, index, &path),
+                tree.root(),
+                "Merkle path does not recompute the root"
+            );
+        }
+    }
+}
+
+/// Checks the submission id computation when the total number of proof ids
+/// is not a power of two.  The tree is built over the proof ids padded with
+/// zero proof ids to the next power of two, so the result must coincide with
+/// that of the explicitly padded proof ids.
+#[test]
+fn test_submission_id_non_power_of_two() {
+    let mut rng = OsRng;
+    for number_of_proofs in [3u64, 5, 6] {
+        let proof_ids: Vec<[u8; 32]> =
+            (0..number_of_proofs).map(|_| rng.gen()).collect_vec();
+        let padded_proof_ids = proof_ids
+            .iter()
+            .copied()
+            .chain(iter::repeat([0u8; 32]))
+            .take(number_of_proofs.next_power_of_two() as usize)
+            .collect_vec();
+        for num_proof_ids in 1..=number_of_proofs {
+            check_submission_id(&proof_ids, num_proof_ids, false);
+            assert_eq!(
+                compute_submission_id(&proof_ids, num_proof_ids),
+                compute_submission_id(&padded_proof_ids, num_proof_ids),
+                "Padding changed the submission id"
+            );
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use super::test_keccak_config;
 use crate::{
     keccak::{
         chip::get_assigned_bytes_values,
@@ -7,7 +8,7 @@ use crate::{
             input_to_keccak_padded_words, remove_padded_bytes,
         },
         AssignedKeccakInput, KeccakConfig, KeccakPaddedCircuitInput,
-        PaddedVerifyingKeyLimbs, KECCAK_LOOKUP_BITS,
+        OutputMode, PaddedVerifyingKeyLimbs,
     },
     utils::bitmask::{first_i_bits_bitmask, ith_bit_bitmask},
 };
@@ -74,20 +75,18 @@ fn check_words() {
         (0..filler_len).map(|_| Fr::zero()).collect();
     field_elements.extend(filler_field_elements);
     let config = KeccakConfig {
-        degree_bits: 1,
         num_app_public_inputs: (len + filler_len) as u32,
         inner_batch_size: 1,
         outer_batch_size: 1,
-        lookup_bits: KECCAK_LOOKUP_BITS,
-        output_submission_id: false, // Irrelevant for this test
+        ..test_keccak_config(1, OutputMode::Linear)
     };
     // This input has `app_public_inputs` of length `len` + `filler_len`.
     let keccak_input = KeccakPaddedCircuitInput {
         len: len_fr,
         app_vk: PaddedVerifyingKeyLimbs::dummy(&config),
-        has_commitment: Fr::zero(),
+        num_commitments: Fr::zero(),
         app_public_inputs: field_elements,
-        commitment_hash: Default::default(),
+        commitment_hashes: vec![Default::default()],
         commitment_point_limbs: Default::default(),
     };
     // Assign keccak input
@@ -3,6 +3,7 @@ use crate::{
         native::json::{load_proof_and_inputs, load_vk},
         types::{Proof, PublicInputs, VerificationKey},
     },
+    tests::utils::{assert_mock_failure, FailureKind},
     CircuitWithLimbsConfig, EccPrimeField,
 };
 use ark_std::{end_timer, start_timer};
@@ -38,6 +39,7 @@ use std::{
     path::Path,
 };
 
+mod circuit_config;
 mod commitment_point;
 mod hashing;
 mod keccak;
@@ -267,9 +269,9 @@ pub fn run_circuit_mock_test<
     out
 }
 
-/// Run `MockProver` on a circuit from a config located at `path`.  Operation
-/// is exactly the same as run_circuit_test, except that `MockProver` is used.
-/// This often gives more informative error messages.
+/// Run `MockProver` on a circuit from a config located at `path`, asserting
+/// that it fails with exactly the `expected` kinds of failure (see
+/// [`assert_mock_failure`]).
 #[allow(dead_code)] // Used in fixed BV circuit, no longer supported
 pub fn run_circuit_mock_test_failure<
     C: DeserializeOwned + Debug,
@@ -278,6 +280,7 @@ pub fn run_circuit_mock_test_failure<
 >(
     path: impl AsRef<Path>,
     build_circuit: BC,
+    expected: &[FailureKind],
 ) {
     let params_file = File::open(&path).unwrap_or_else(|e| {
         let path = path.as_ref().to_str().unwrap();
@@ -303,19 +306,7 @@ pub fn run_circuit_mock_test_failure<
         let circuit = RangeWithInstanceCircuitBuilder::mock(builder, instance);
         let instance_vals = circuit.instance();
 
-        let prover = MockProver::run(k, &circuit, vec![instance_vals]).unwrap();
-        let res = prover.verify();
-        match res {
-            Ok(_) => {
-                panic!("Expected circuit failure");
-            }
-            Err(_failures) => {
-                // TODO: Determine how to allow the caller to specify a
-                // specific failiure. (_failures is a vector of error codes,
-                // so need to consider exactly what the caller should
-                // provide).
-            }
-        }
+        assert_mock_failure(&circuit, k, vec![instance_vals], expected);
     }
 }
 
@@ -11,7 +11,10 @@ use crate::{
             types::VerificationKey,
         },
         universal::{
-            chip::{AssignedPreparedProof, UniversalBatchVerifierChip},
+            chip::{
+                AssignedBatchEntries, AssignedPreparedProof,
+                UniversalBatchVerifierChip,
+            },
             native::{
                 self, compute_circuit_id, compute_pi_term_for_entry,
                 update_batch,
@@ -251,3 +254,42 @@ fn compute_pi_pairs() {
         assert_eq!(pair.1, g2, "g2 mismatch at {idx:?}");
     }
 }
+
+/// Checks that assigning the batch entries in parallel yields the same values,
+/// in the same order, as the serial assignment.
+#[test]
+fn assign_batch_entries_parallel() {
+    let mut rng = OsRng;
+    let config = sample_test_config(DEFAULT_DEGREE, &mut rng);
+    let inputs = UniversalBatchVerifierInputs::sample_mixed(&config, &mut rng);
+    let entries = BatchEntries::from_ubv_inputs_and_config(&inputs, &config);
+
+    let mut builder = GateThreadBuilder::<Fr>::mock();
+    let range = RangeChip::<Fr>::default(config.lookup_bits);
+    let fp_chip = FpChip::new(&range, config.limb_bits, config.num_limbs);
+    let bv_chip = BatchVerifierChip::new(&fp_chip);
+    let chip = UniversalBatchVerifierChip::new(&bv_chip);
+
+    let serial = chip.assign_batch_entries(builder.main(0), &entries);
+    let parallel = chip.assign_batch_entries_parallel(&mut builder, &entries);
+
+    let values = |assigned: &AssignedBatchEntries<Fr>,
+                  builder: &mut GateThreadBuilder<Fr>| {
+        assigned
+            .0
+            .iter()
+            .map(|entry| *chip.compute_vk_hash(builder.main(0), entry).value())
+            .chain(
+                assigned
+                    .public_inputs()
+                    .iter()
+                    .flat_map(|pi| pi.0.iter().map(|v| *v.value())),
+            )
+            .collect_vec()
+    };
+    assert_eq!(
+        values(&serial, &mut builder),
+        values(&parallel, &mut builder),
+        "parallel assignment mismatch"
+    );
+}
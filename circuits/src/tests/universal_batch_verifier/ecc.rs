@@ -5,10 +5,17 @@ use crate::{
             BN254_CURVE_PARAMETER, Q_MINUS_ONE_OVER_THREE,
             Q_MINUS_ONE_OVER_TWO, XI, XI_Q_2, XI_Q_3,
         },
+        msm::{CpuMsmBackend, MsmBackend},
         MINIMUM_ROWS,
     },
-    tests::{LIMB_BITS, NUM_LIMBS},
-    utils::reduced::FromReduced,
+    tests::{
+        utils::{assert_mock_failure, FailureKind},
+        LIMB_BITS, NUM_LIMBS,
+    },
+    utils::{
+        benchmarks::msm::{sample_points_and_scalars, scale_points},
+        reduced::FromReduced,
+    },
     EccPrimeField,
 };
 use halo2_base::{
@@ -21,7 +28,7 @@ use halo2_base::{
     halo2_proofs::{
         circuit::{Layouter, SimpleFloorPlanner},
         dev::MockProver,
-        halo2curves::bn256::{Fq, Fq2, Fr, G2Affine},
+        halo2curves::bn256::{Fq, Fq2, Fr, G1Affine, G2Affine},
         plonk::{Circuit, ConstraintSystem, Error},
     },
     safe_types::RangeChip,
@@ -231,7 +238,7 @@ where
 
 /// Samples a G2Affine point which is on the curve but not in the right
 /// subgroup
-fn sample_g2_affine_not_in_subgroup<R>(rng: &mut R) -> G2Affine
+pub(super) fn sample_g2_affine_not_in_subgroup<R>(rng: &mut R) -> G2Affine
 where
     R: RngCore + ?Sized,
 {
@@ -280,6 +287,8 @@ fn test_g2_membership_for_subgroup_check(subgroup_check: SubgroupCheck) {
         .assert_satisfied();
     let circuit_not_on_curve =
         G2EllipticCurveCircuit::new(DEGREE_BITS, not_on_curve, subgroup_check);
+    // The failure kinds of the curve equation check depend on the sign of
+    // `y^2 - x^3 - b`, so only check that the circuit fails.
     MockProver::run(DEGREE_BITS as u32, &circuit_not_on_curve, Vec::new())
         .expect("Mock prover run failure")
         .verify()
@@ -291,24 +300,27 @@ fn test_g2_membership_for_subgroup_check(subgroup_check: SubgroupCheck) {
         not_in_subgroup,
         subgroup_check,
     );
-    let is_satisfied = MockProver::run(
-        DEGREE_BITS as u32,
-        &circuit_not_in_subgroup,
-        Vec::new(),
-    )
-    .expect("Mock prover run failure")
-    .verify();
     match subgroup_check {
         SubgroupCheck::None => {
-            is_satisfied.expect(
+            MockProver::run(
+                DEGREE_BITS as u32,
+                &circuit_not_in_subgroup,
+                Vec::new(),
+            )
+            .expect("Mock prover run failure")
+            .verify()
+            .expect(
                 "Verification shouldn't fail when the subgroup isn't checked",
             );
         }
-        _ => {
-            is_satisfied.expect_err(
-                "Verification should fail when the subgroup isn't checked",
-            );
-        }
+        // The subgroup checks end in an equality constraint between two
+        // points, so only copy constraints should fail.
+        _ => assert_mock_failure(
+            &circuit_not_in_subgroup,
+            DEGREE_BITS as u32,
+            Vec::new(),
+            &[FailureKind::Permutation],
+        ),
     }
 }
 
@@ -320,3 +332,51 @@ fn test_g2_membership() {
     test_g2_membership_for_subgroup_check(SubgroupCheck::Naive);
     test_g2_membership_for_subgroup_check(SubgroupCheck::None);
 }
+
+/// Tests that the G1 subgroup membership check is satisfied for a valid G1
+/// point.  (Since the G1 cofactor is 1, every point on the curve is in the
+/// subgroup.)
+#[test]
+fn test_g1_subgroup_membership() {
+    const DEGREE_BITS: usize = 15;
+    let point = G1Affine::random(OsRng);
+
+    let mut builder = GateThreadBuilder::mock();
+    let ctx = builder.main(0);
+    let range = RangeChip::<Fr>::default(DEGREE_BITS - 1);
+    let fp_chip = FpChip::<Fr>::new(&range, LIMB_BITS, NUM_LIMBS);
+    let batch_verifier_chip = BatchVerifierChip::<Fr>::new(&fp_chip);
+    let assigned_point = batch_verifier_chip.assign_g1_reduced(ctx, point);
+    batch_verifier_chip.assert_g1_subgroup_membership(
+        ctx,
+        &FromReduced::from_reduced(&assigned_point),
+    );
+    std::env::set_var("LOOKUP_BITS", (DEGREE_BITS - 1).to_string());
+    builder.config(DEGREE_BITS, Some(MINIMUM_ROWS));
+    let circuit = RangeCircuitBuilder::mock(builder);
+    MockProver::run(DEGREE_BITS as u32, &circuit, Vec::new())
+        .expect("Mock prover run failure")
+        .assert_satisfied();
+}
+
+/// Checks that `msm_backend` computes the scalar multiplications of
+/// `BatchVerifierChip::scale_pairs` correctly.  Any new [`MsmBackend`]
+/// should be added here.
+fn check_msm_backend(msm_backend: &dyn MsmBackend<Fr>) {
+    let (points, scalars) = sample_points_and_scalars(4, &mut OsRng);
+    let expected: Vec<G1Affine> = points
+        .iter()
+        .zip(scalars.iter())
+        .map(|(point, scalar)| G1Affine::from(*point * scalar))
+        .collect();
+    assert_eq!(scale_points(msm_backend, &points, &scalars), expected);
+}
+
+/// Checks the default [`CpuMsmBackend`] against native scalar
+/// multiplication.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_batch_verifier::ecc::test_msm_backends --exact --nocapture`
+#[test]
+fn test_msm_backends() {
+    check_msm_backend(&CpuMsmBackend);
+}
@@ -3,26 +3,33 @@
 use crate::{
     batch_verify::{
         common::{
+            chip::{WINDOW_BITS, WINDOW_BITS_G2_SUBGROUP_CHECK},
             native::unsafe_proof_generation::UnsafeVerificationKey,
             types::PublicInputs,
         },
         universal::{
+            native::verify_batch,
             types::{
-                UniversalBatchVerifierConfig, UniversalBatchVerifierInput,
-                UniversalBatchVerifierInputs,
+                BatchEntries, BatchEntry, UniversalBatchVerifierConfig,
+                UniversalBatchVerifierInput, UniversalBatchVerifierInputs,
             },
-            UniversalBatchVerifyCircuit,
+            ubv_instance_len, UniversalBatchVerifyCircuit,
         },
     },
-    tests::utils::check_instance,
+    read_proving_key_mmap,
+    tests::utils::{
+        assert_mock_failure, check_instance, check_key_round_trip, FailureKind,
+    },
+    utils::file::{load_json, open_file_for_read},
     SafeCircuit,
 };
-use ark_std::{end_timer, start_timer};
+use ark_std::{end_timer, start_timer, test_rng};
 use halo2_base::{
+    gates::builder::FlexGateConfigParams,
     halo2_proofs::{
         dev::MockProver,
         halo2curves::bn256::{Bn256, Fr, G1Affine},
-        plonk::{create_proof, keygen_pk, keygen_vk, verify_proof},
+        plonk::{create_proof, keygen_pk, keygen_vk, verify_proof, ProvingKey},
         poly::kzg::{
             commitment::KZGCommitmentScheme,
             multiopen::{ProverSHPLONK, VerifierSHPLONK},
@@ -68,10 +75,29 @@ where
     UniversalBatchVerifierConfig {
         degree_bits,
         lookup_bits: (degree_bits - 1) as usize,
+        ..test_config(inner_batch_size, max_num_public_inputs)
+    }
+}
+
+/// Returns a test configuration with `inner_batch_size` and
+/// `max_num_public_inputs`, and default values for the other parameters.
+/// Only the former matter for native checks; circuit tests override
+/// `degree_bits` and `lookup_bits` as needed.
+fn test_config(
+    inner_batch_size: u32,
+    max_num_public_inputs: u32,
+) -> UniversalBatchVerifierConfig {
+    UniversalBatchVerifierConfig {
+        degree_bits: 16,
+        lookup_bits: 15,
         limb_bits: LIMB_BITS,
         num_limbs: NUM_LIMBS,
         inner_batch_size,
         max_num_public_inputs,
+        window_bits: WINDOW_BITS,
+        g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK,
+        strict_g1_subgroup: false,
+        allow_commitments: true,
     }
 }
 
@@ -172,6 +198,7 @@ where
     let circuit =
         UniversalBatchVerifyCircuit::<Fr, G1Affine>::mock(&config, &inputs);
     let instances = circuit.instances();
+    assert_eq!(instances[0].len(), ubv_instance_len(&config));
     MockProver::<Fr>::run(k, &circuit, instances)
         .expect("Mock prover run failure")
         .assert_satisfied();
@@ -179,6 +206,202 @@ where
     assert!(check_instance(&circuit, &config, &inputs));
 }
 
+/// Instantiates a [`UniversalBatchVerifyCircuit`] with a batch in which one
+/// public input has been altered, so that the batched pairing check fails,
+/// and checks that the mock prover only reports the failure of the final
+/// equality constraint of the pairing check.
+///
+/// # Command line
+///
+/// cargo test --release --package upa-circuits --lib -- tests::universal_batch_verifier::universal_batch_verifier_test_mock_invalid_proof
+/// --exact --nocapture
+#[test]
+fn universal_batch_verifier_test_mock_invalid_proof() {
+    let k: u32 = std::env::var("UBV_DEGREE")
+        .unwrap_or_else(|_| "18".to_string())
+        .parse()
+        .expect("Parsing error");
+    let mut rng = OsRng;
+    let config = sample_test_config(k, &mut rng);
+    let mut inputs =
+        UniversalBatchVerifierInputs::sample_mixed(&config, &mut rng);
+    inputs.0[0].inputs.0[0] += Fr::one();
+    assert!(verify_batch(&inputs).is_err());
+
+    let circuit =
+        UniversalBatchVerifyCircuit::<Fr, G1Affine>::mock(&config, &inputs);
+    assert_mock_failure(
+        &circuit,
+        k,
+        circuit.instances(),
+        &[FailureKind::Permutation],
+    );
+}
+
+/// Instantiates a [`UniversalBatchVerifyCircuit`] which does not allow
+/// commitments with randomly sampled inputs without commitments and does a
+/// mock run.  Checks that the instance is the same as with commitments
+/// allowed, and that the dummy entries used at keygen hold the same
+/// (constant) commitment hash as the real entries.
+///
+/// # Command line
+///
+/// cargo test --release --package upa-circuits --lib -- tests::universal_batch_verifier::universal_batch_verifier_test_mock_no_commitments
+/// --exact --nocapture
+#[test]
+fn universal_batch_verifier_test_mock_no_commitments() {
+    let k: u32 = std::env::var("UBV_DEGREE")
+        .unwrap_or_else(|_| "18".to_string())
+        .parse()
+        .expect("Parsing error");
+    let mut rng = OsRng;
+    let config = UniversalBatchVerifierConfig {
+        allow_commitments: false,
+        ..sample_test_config(k, &mut rng)
+    };
+    let inputs = UniversalBatchVerifierInputs::sample(&config, false, &mut rng);
+
+    let dummy_entry = BatchEntry::<Fr>::dummy(&config);
+    for entry in BatchEntries::from_ubv_inputs_and_config(&inputs, &config).0 {
+        assert_eq!(entry.commitment_hash(), dummy_entry.commitment_hash());
+    }
+
+    let circuit =
+        UniversalBatchVerifyCircuit::<Fr, G1Affine>::mock(&config, &inputs);
+    let instances = circuit.instances();
+    let config_with_commitments = UniversalBatchVerifierConfig {
+        allow_commitments: true,
+        ..config
+    };
+    assert_eq!(
+        instances[0],
+        UniversalBatchVerifyCircuit::<Fr, G1Affine>::compute_instance(
+            &config_with_commitments,
+            &inputs
+        )
+    );
+    MockProver::<Fr>::run(k, &circuit, instances)
+        .expect("Mock prover run failure")
+        .assert_satisfied();
+}
+
+/// Checks that [`ubv_instance_len`] matches the length of the instance
+/// computed by [`UniversalBatchVerifyCircuit::compute_instance`].
+#[test]
+fn universal_batch_verifier_instance_len() {
+    let mut rng = OsRng;
+    for _ in 0..4 {
+        let config = sample_test_config(18, &mut rng);
+        let inputs =
+            UniversalBatchVerifierInputs::sample_mixed(&config, &mut rng);
+        let instance =
+            UniversalBatchVerifyCircuit::<Fr, G1Affine>::compute_instance(
+                &config, &inputs,
+            );
+        assert_eq!(instance.len(), ubv_instance_len(&config));
+    }
+}
+
+/// Checks that [`UniversalBatchVerifierConfig::parse_instance`] recovers
+/// the verification keys, commitment points and public inputs from the
+/// instance computed by [`UniversalBatchVerifyCircuit::compute_instance`].
+///
+/// # Command line
+///
+/// cargo test --package upa-circuits --lib -- tests::universal_batch_verifier::universal_batch_verifier_parse_instance
+/// --exact --nocapture
+#[test]
+fn universal_batch_verifier_parse_instance() {
+    let mut rng = OsRng;
+    for _ in 0..4 {
+        let config = sample_test_config(18, &mut rng);
+        let inputs =
+            UniversalBatchVerifierInputs::sample_mixed(&config, &mut rng);
+        let instance =
+            UniversalBatchVerifyCircuit::<Fr, G1Affine>::compute_instance(
+                &config, &inputs,
+            );
+        let parsed = config.parse_instance(&instance);
+        assert_eq!(parsed.len(), inputs.0.len());
+        for (entry, input) in parsed.iter().zip(inputs.0.iter()) {
+            assert_eq!(entry.vk, input.vk);
+            assert_eq!(entry.len, input.inputs.0.len());
+            assert_eq!(entry.has_commitment, input.has_commitment());
+            assert_eq!(entry.commitment_point, input.proof.m.first().copied());
+            assert_eq!(entry.public_inputs, input.inputs);
+        }
+    }
+}
+
+/// Selects a subset of the entries of a larger batch with
+/// [`UniversalBatchVerifierInputs::select_with_padding`], checks that the
+/// padded batch verifies (natively and in a mock run) and that its instance
+/// holds the selected entries followed by padding entries.
+///
+/// # Command line
+///
+/// cargo test --release --package upa-circuits --lib -- tests::universal_batch_verifier::universal_batch_verifier_select_with_padding
+/// --exact --nocapture
+#[test]
+fn universal_batch_verifier_select_with_padding() {
+    let k: u32 = std::env::var("UBV_DEGREE")
+        .unwrap_or_else(|_| "18".to_string())
+        .parse()
+        .expect("Parsing error");
+    let mut rng = OsRng;
+    let config = UniversalBatchVerifierConfig {
+        inner_batch_size: MAX_BATCH_SIZE,
+        ..sample_test_config(k, &mut rng)
+    };
+    let batch = UniversalBatchVerifierInputs::sample_mixed(
+        &UniversalBatchVerifierConfig {
+            inner_batch_size: 4,
+            ..config
+        },
+        &mut rng,
+    );
+
+    let (inputs, positions) = batch.select_with_padding(&[3], &config).unwrap();
+    assert_eq!(positions, vec![Some(3), None]);
+    verify_batch(&inputs).expect("padded batch should verify");
+
+    let circuit =
+        UniversalBatchVerifyCircuit::<Fr, G1Affine>::mock(&config, &inputs);
+    let instances = circuit.instances();
+    MockProver::<Fr>::run(k, &circuit, instances.clone())
+        .expect("Mock prover run failure")
+        .assert_satisfied();
+
+    let padding = UniversalBatchVerifierInput::padding(&config);
+    let parsed = config.parse_instance(&instances[0]);
+    for (entry, position) in parsed.iter().zip(positions) {
+        let expected = match position {
+            Some(idx) => &batch.0[idx],
+            None => &padding,
+        };
+        assert_eq!(entry.vk, expected.vk);
+        assert_eq!(entry.has_commitment, expected.has_commitment());
+        assert_eq!(entry.public_inputs, expected.inputs);
+    }
+
+    for (entries, err) in [
+        (
+            vec![0, 4],
+            "entry 4 selected, but there are only 4 entries".to_string(),
+        ),
+        (vec![2, 2], "entry 2 selected more than once".to_string()),
+        (
+            vec![0, 1, 2],
+            "3 entries selected, exceeds inner batch size 2".to_string(),
+        ),
+    ] {
+        assert_eq!(
+            batch.select_with_padding(&entries, &config).unwrap_err(),
+            err
+        );
+    }
+}
+
 /// Instantiates a [`UniversalBatchVerifyCircuit`] with randomly sampled inputs
 /// and generates/verifies a proof.
 ///
@@ -254,3 +477,203 @@ fn universal_batch_verifier_test_prover() {
     .expect("verification failure");
     end_timer!(timer);
 }
+
+/// Generates keys for a [`UniversalBatchVerifyCircuit`] and checks they
+/// survive a write/read round-trip.
+///
+/// # Command line
+///
+/// cargo test --release --package upa-circuits --lib -- tests::universal_batch_verifier::universal_batch_verifier_key_round_trip
+/// --exact --nocapture --ignored
+#[ignore = "takes too long"]
+#[test]
+fn universal_batch_verifier_key_round_trip() {
+    let k: u32 = std::env::var("UBV_DEGREE")
+        .unwrap_or_else(|_| "18".to_string())
+        .parse()
+        .expect("Parsing error");
+    let config = sample_test_config(k, &mut OsRng);
+    let params = gen_srs(k);
+    let circuit =
+        UniversalBatchVerifyCircuit::<Fr, G1Affine>::keygen(&config, &());
+    let vk = keygen_vk(&params, &circuit).expect("unable to gen. vk");
+    let pk = keygen_pk(&params, vk, &circuit).expect("unable to gen. pk");
+    check_key_round_trip::<UniversalBatchVerifyCircuit<Fr, G1Affine>>(
+        &config,
+        circuit.gate_config(),
+        &pk,
+    );
+}
+
+/// Generates a proving key for a [`UniversalBatchVerifyCircuit`], writes it to
+/// a file and checks that proofs generated with the key read through a buffer
+/// and through [`read_proving_key_mmap`] coincide.
+///
+/// # Command line
+///
+/// cargo test --release --package upa-circuits --lib -- tests::universal_batch_verifier::universal_batch_verifier_mmap_pk
+/// --exact --nocapture --ignored
+#[ignore = "takes too long"]
+#[test]
+fn universal_batch_verifier_mmap_pk() {
+    let k: u32 = std::env::var("UBV_DEGREE")
+        .unwrap_or_else(|_| "18".to_string())
+        .parse()
+        .expect("Parsing error");
+    let mut rng = OsRng;
+    let config = sample_test_config(k, &mut rng);
+    let inputs = UniversalBatchVerifierInputs::sample_mixed(&config, &mut rng);
+    let params = gen_srs(k);
+    let (gate_config, break_points, pk_file) = {
+        let circuit =
+            UniversalBatchVerifyCircuit::<Fr, G1Affine>::keygen(&config, &());
+        let vk = keygen_vk(&params, &circuit).expect("unable to gen. vk");
+        let pk = keygen_pk(&params, vk, &circuit).expect("unable to gen. pk");
+        let pk_file = std::env::temp_dir()
+            .join(format!("ubv_mmap_pk_test_{}.pk", std::process::id()));
+        let mut writer = std::fs::File::create(&pk_file)
+            .unwrap_or_else(|e| panic!("failed to create pk file: {e}"));
+        UniversalBatchVerifyCircuit::<Fr, G1Affine>::write_proving_key(
+            &pk,
+            &mut writer,
+        )
+        .expect("failed to write pk");
+        (
+            circuit.gate_config().clone(),
+            circuit.break_points(),
+            pk_file.to_str().expect("non utf-8 path").to_string(),
+        )
+    };
+
+    let prove = |pk: &ProvingKey<G1Affine>| {
+        let circuit = UniversalBatchVerifyCircuit::<Fr, G1Affine>::prover(
+            &config,
+            &gate_config,
+            break_points.clone(),
+            &inputs,
+        );
+        let instances: &[Fr] = &circuit.instances()[0];
+        let mut transcript =
+            Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+        create_proof::<
+            KZGCommitmentScheme<Bn256>,
+            ProverSHPLONK<Bn256>,
+            _,
+            _,
+            _,
+            _,
+        >(
+            &params,
+            pk,
+            &[circuit],
+            &[&[instances]],
+            test_rng(),
+            &mut transcript,
+        )
+        .expect("proof gen. failure");
+        transcript.finalize()
+    };
+
+    let pk = UniversalBatchVerifyCircuit::<Fr, G1Affine>::read_proving_key(
+        &config,
+        &gate_config,
+        &mut open_file_for_read(&pk_file),
+    )
+    .expect("failed to read pk");
+    let proof = prove(&pk);
+    drop(pk);
+
+    let pk_mmap = read_proving_key_mmap::<_, _, UniversalBatchVerifyCircuit>(
+        &config,
+        &gate_config,
+        &pk_file,
+    )
+    .expect("failed to read pk with mmap");
+    let proof_mmap = prove(&pk_mmap);
+
+    std::fs::remove_file(&pk_file).expect("failed to remove pk file");
+    assert_eq!(proof, proof_mmap, "proofs differ");
+}
+
+/// Checks that a proof generated with the gate config written to (and read
+/// back from) a file, and set with
+/// [`UniversalBatchVerifyCircuit::set_gate_config_env`], coincides with one
+/// generated with keygen's in-memory gate config.
+///
+/// # Command line
+///
+/// cargo test --release --package upa-circuits --lib -- tests::universal_batch_verifier::universal_batch_verifier_gate_config_file
+/// --exact --nocapture --ignored
+#[ignore = "takes too long"]
+#[test]
+fn universal_batch_verifier_gate_config_file() {
+    let k: u32 = std::env::var("UBV_DEGREE")
+        .unwrap_or_else(|_| "18".to_string())
+        .parse()
+        .expect("Parsing error");
+    let mut rng = OsRng;
+    let config = sample_test_config(k, &mut rng);
+    let inputs = UniversalBatchVerifierInputs::sample_mixed(&config, &mut rng);
+    let params = gen_srs(k);
+    let (pk, gate_config, break_points) = {
+        let circuit =
+            UniversalBatchVerifyCircuit::<Fr, G1Affine>::keygen(&config, &());
+        let vk = keygen_vk(&params, &circuit).expect("unable to gen. vk");
+        let pk = keygen_pk(&params, vk, &circuit).expect("unable to gen. pk");
+        (pk, circuit.gate_config().clone(), circuit.break_points())
+    };
+
+    let gate_config_file = std::env::temp_dir()
+        .join(format!("ubv_gate_config_test_{}.json", std::process::id()));
+    let gate_config_file = gate_config_file
+        .to_str()
+        .expect("non utf-8 path")
+        .to_string();
+    std::fs::write(
+        &gate_config_file,
+        serde_json::to_string(&gate_config).expect("failed to serialize"),
+    )
+    .expect("failed to write gate config");
+    let loaded_gate_config: FlexGateConfigParams = load_json(&gate_config_file);
+    std::fs::remove_file(&gate_config_file)
+        .expect("failed to remove gate config file");
+
+    let prove = |gate_config: &FlexGateConfigParams| {
+        UniversalBatchVerifyCircuit::<Fr, G1Affine>::set_gate_config_env(
+            &config,
+            gate_config,
+        );
+        let circuit = UniversalBatchVerifyCircuit::<Fr, G1Affine>::prover(
+            &config,
+            gate_config,
+            break_points.clone(),
+            &inputs,
+        );
+        let instances: &[Fr] = &circuit.instances()[0];
+        let mut transcript =
+            Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+        create_proof::<
+            KZGCommitmentScheme<Bn256>,
+            ProverSHPLONK<Bn256>,
+            _,
+            _,
+            _,
+            _,
+        >(
+            &params,
+            &pk,
+            &[circuit],
+            &[&[instances]],
+            test_rng(),
+            &mut transcript,
+        )
+        .expect("proof gen. failure");
+        transcript.finalize()
+    };
+
+    assert_eq!(
+        prove(&gate_config),
+        prove(&loaded_gate_config),
+        "proofs differ"
+    );
+}
@@ -2,27 +2,51 @@ use crate::{
     batch_verify::{
         common::{
             native::{
-                json::{load_proof_and_inputs_batch, load_vk},
-                unsafe_proof_generation::sample_proofs_inputs_vk,
+                json::{
+                    load_proof_and_inputs, load_proof_and_inputs_batch,
+                    load_snarkjs_proof_and_inputs, load_vk,
+                },
+                unsafe_proof_generation::{
+                    create_proofs_and_inputs, sample_proofs_inputs_vk,
+                    UnsafeVerificationKey,
+                },
             },
-            types::PublicInputs,
+            types::{Proof, PublicInputs, VerificationKey, VkError},
         },
         universal::{
             native::{
+                compute_circuit_id,
                 compute_pi_term_for_entry_without_commitment,
-                verify_universal_groth16_batch,
+                json::JsonUniversalBatchVerifierInput, verify_batch,
+                verify_universal_groth16_batch, BatchVerifyError,
             },
             types::{
-                UniversalBatchVerifierConfig, UniversalBatchVerifierInput,
-                UniversalBatchVerifierInputs,
+                BatchEntries, UniversalBatchVerifierConfig,
+                UniversalBatchVerifierInput, UniversalBatchVerifierInputs,
             },
+            UniversalBatchVerifyCircuit,
         },
     },
-    tests::{encode_g1, PROOF_BATCH_1_8_FILE, VK_FILE},
+    tests::{
+        encode_g1,
+        universal_batch_verifier::{
+            ecc::sample_g2_affine_not_in_subgroup, test_config,
+        },
+        utils::check_instance_layout,
+        PROOF1_FILE, PROOF_BATCH_1_8_FILE, VK_FILE,
+    },
+    SafeCircuit,
+};
+use halo2_base::halo2_proofs::halo2curves::{
+    bn256::{Fq, Fr, G1Affine, G1},
+    CurveAffine,
 };
-use halo2_base::halo2_proofs::halo2curves::bn256::{Fr, G1};
 use rand::Rng;
-use rand_core::OsRng;
+use rand_chacha::ChaCha20Rng;
+use rand_core::{OsRng, SeedableRng};
+
+const SNARKJS_PROOF_FILE: &str = "src/tests/data/snarkjs/proof.json";
+const SNARKJS_PUBLIC_FILE: &str = "src/tests/data/snarkjs/public.json";
 
 /// Simple test of the universal verifier for multiple proofs with the same VK.
 #[test]
@@ -67,16 +91,12 @@ fn test_universal_verifier_general_case() {
     const MAX_NUM_PUBLIC_INPUTS: u32 = 100;
     const MAX_BATCH_SIZE: u32 = 32;
     let rng = &mut OsRng;
-    let fake_config = UniversalBatchVerifierConfig {
-        // The first four values don't matter for native checks;
-        // only the batch size and the (max) number of public inputs do.
-        degree_bits: 16,
-        lookup_bits: 15,
-        limb_bits: 88,
-        num_limbs: 3,
-        inner_batch_size: rng.gen_range(1..=MAX_BATCH_SIZE),
-        max_num_public_inputs: rng.gen_range(2..=MAX_NUM_PUBLIC_INPUTS),
-    };
+    // Only the batch size and the (max) number of public inputs matter for
+    // native checks.
+    let fake_config = test_config(
+        rng.gen_range(1..=MAX_BATCH_SIZE),
+        rng.gen_range(2..=MAX_NUM_PUBLIC_INPUTS),
+    );
     let full_batch =
         UniversalBatchVerifierInputs::sample_mixed(&fake_config, rng);
     let max_num_public_inputs = full_batch.max_len();
@@ -85,6 +105,243 @@ fn test_universal_verifier_general_case() {
     assert!(is_satisfied, "Verification failed");
 }
 
+/// Checks that [`verify_batch`] accepts valid entries and identifies the
+/// first failing entry and check otherwise.
+#[test]
+fn test_verify_batch() {
+    let rng = &mut OsRng;
+    let (batch, vk) = sample_proofs_inputs_vk(4, true, 3, rng);
+    let inputs = UniversalBatchVerifierInputs(
+        batch
+            .into_iter()
+            .map(|(p, i)| UniversalBatchVerifierInput::new(vk.clone(), p, i))
+            .collect(),
+    );
+    assert_eq!(verify_batch(&inputs), Ok(()));
+
+    // Invalid public input
+    let mut invalid = inputs.clone();
+    invalid.0[1].inputs.0[0] += Fr::one();
+    assert_eq!(verify_batch(&invalid), Err(BatchVerifyError::Pairing(1)));
+
+    // Invalid Pedersen commitment proof of knowledge
+    let mut invalid = inputs.clone();
+    let pok = &mut invalid.0[2].proof.pok[0];
+    *pok = (*pok + G1Affine::generator()).into();
+    assert_eq!(
+        verify_batch(&invalid),
+        Err(BatchVerifyError::PedersenPoK(2))
+    );
+
+    // Point not on curve. Takes precedence over the failures above.
+    invalid.0[0].proof.a = G1Affine {
+        x: Fq::one(),
+        y: Fq::one(),
+    };
+    assert_eq!(
+        verify_batch(&invalid),
+        Err(BatchVerifyError::PointNotOnCurve(0))
+    );
+
+    // Wrong number of public inputs for the vk: an error, not a panic.
+    let mut malformed = inputs;
+    malformed.0[3].inputs.0.push(Fr::one());
+    assert_eq!(
+        verify_batch(&malformed),
+        Err(BatchVerifyError::Malformed(3))
+    );
+}
+
+/// Checks that [`UniversalBatchVerifierInputs::check_consistent`] reports the
+/// first inconsistent entry.
+#[test]
+fn test_check_consistent() {
+    let rng = &mut OsRng;
+    let config = test_config(3, 5);
+    let mut inputs = UniversalBatchVerifierInputs::sample_mixed(&config, rng);
+    assert_eq!(inputs.check_consistent(&config), Ok(()));
+
+    inputs.0[1].inputs.0.push(Fr::one());
+    let err = inputs.check_consistent(&config).unwrap_err();
+    assert!(err.starts_with("entry 1: vk.s length"), "{err}");
+
+    inputs.0.pop();
+    let err = inputs.check_consistent(&config).unwrap_err();
+    assert!(err.starts_with("batch has 2 entries"), "{err}");
+}
+
+/// Checks that [`UniversalBatchVerifierInputs::sample_with_commitment_fraction`]
+/// produces `inner_batch_size` valid entries, with the requested number of
+/// commitments and input lengths within the configured bound.
+#[test]
+fn test_sample_with_commitment_fraction() {
+    let rng = &mut OsRng;
+    let config = test_config(4, 4);
+    for (fraction, expected_num_commitments) in
+        [(0.0, 0), (0.25, 1), (0.5, 2), (1.0, 4)]
+    {
+        let inputs =
+            UniversalBatchVerifierInputs::sample_with_commitment_fraction(
+                &config, fraction, rng,
+            );
+        assert_eq!(inputs.0.len(), config.inner_batch_size as usize);
+        assert_eq!(verify_batch(&inputs), Ok(()));
+        assert_eq!(inputs.check_consistent(&config), Ok(()));
+        let num_commitments =
+            inputs.0.iter().filter(|i| i.vk.has_commitment()).count();
+        assert_eq!(num_commitments, expected_num_commitments);
+        for input in inputs.0.iter() {
+            let num_inputs = input.inputs.0.len();
+            let max_num_inputs = config.max_num_public_inputs as usize
+                - input.vk.has_commitment() as usize;
+            assert!((1..=max_num_inputs).contains(&num_inputs));
+        }
+    }
+}
+
+/// Checks that [`UniversalBatchVerifyCircuit::describe_instance`] covers the
+/// instance computed by [`UniversalBatchVerifyCircuit::compute_instance`],
+/// and that its `len`, `has_commitment` and `inputs` segments hold the
+/// corresponding values of each entry.
+#[test]
+fn test_ubv_describe_instance() {
+    let rng = &mut OsRng;
+    let config = test_config(3, 4);
+    let inputs = UniversalBatchVerifierInputs::sample_mixed(&config, rng);
+    let instance =
+        UniversalBatchVerifyCircuit::<Fr, G1Affine>::compute_instance(
+            &config, &inputs,
+        );
+    let layout =
+        UniversalBatchVerifyCircuit::<Fr, G1Affine>::describe_instance(&config);
+    check_instance_layout(&layout, instance.len());
+
+    let segment = |name: String| {
+        let (_, range) = layout
+            .iter()
+            .find(|(segment_name, _)| segment_name == &name)
+            .unwrap_or_else(|| panic!("missing segment {name}"));
+        &instance[range.clone()]
+    };
+    for (i, input) in inputs.0.iter().enumerate() {
+        let num_inputs = input.inputs.0.len();
+        assert_eq!(
+            segment(format!("entry{i}.len")),
+            [Fr::from(num_inputs as u64)]
+        );
+        assert_eq!(
+            segment(format!("entry{i}.has_commitment")),
+            [Fr::from(input.vk.has_commitment())]
+        );
+        assert_eq!(
+            &segment(format!("entry{i}.inputs"))[..num_inputs],
+            &input.inputs.0[..]
+        );
+    }
+}
+
+/// Checks that the UBV instance cannot be computed with a limb configuration
+/// the Keccak circuit does not support.
+#[test]
+#[should_panic(expected = "Unsupported limb configuration")]
+fn test_ubv_instance_non_default_limbs() {
+    let rng = &mut OsRng;
+    let config = UniversalBatchVerifierConfig {
+        limb_bits: 64,
+        num_limbs: 4,
+        ..test_config(1, 2)
+    };
+    let inputs = UniversalBatchVerifierInputs::sample(&config, false, rng);
+    UniversalBatchVerifyCircuit::<Fr, G1Affine>::compute_instance(
+        &config, &inputs,
+    );
+}
+
+/// Checks that [`BatchEntries::check_commitment_hashes`] reports the first
+/// entry whose commitment hash does not match its commitment point.
+#[test]
+fn test_check_commitment_hashes() {
+    let rng = &mut OsRng;
+    let config = test_config(3, 5);
+    let inputs = UniversalBatchVerifierInputs::sample(&config, true, rng);
+    let entries = BatchEntries::from_ubv_inputs_and_config(&inputs, &config);
+    assert_eq!(entries.check_commitment_hashes(), Ok(()));
+
+    // Entries can only be given an arbitrary commitment hash by
+    // deserialization.  Give entry 2 the commitment hash of entry 0.
+    let mut entries_json = serde_json::to_value(&entries).unwrap();
+    entries_json[2]["commitment_hash"] =
+        entries_json[0]["commitment_hash"].clone();
+    let entries: BatchEntries<Fr> =
+        serde_json::from_value(entries_json).unwrap();
+    assert_eq!(entries.check_commitment_hashes(), Err(2));
+}
+
+/// Checks that [`BatchEntries::warn_on_adjacent_duplicates`] reports entries
+/// identical to the preceding one, but not non-adjacent duplicates.
+#[test]
+fn test_adjacent_duplicates() {
+    let rng = &mut OsRng;
+    let config = test_config(5, 5);
+    let mut inputs = UniversalBatchVerifierInputs::sample(&config, true, rng);
+    let entries = BatchEntries::from_ubv_inputs_and_config(&inputs, &config);
+    assert!(entries.warn_on_adjacent_duplicates().is_empty());
+
+    // Entries 1, 2 and 4 duplicate entry 0, but only 1 and 2 are adjacent
+    // to an identical entry.
+    for idx in [1, 2, 4] {
+        inputs.0[idx] = inputs.0[0].clone();
+    }
+    let entries = BatchEntries::from_ubv_inputs_and_config(&inputs, &config);
+    assert_eq!(entries.warn_on_adjacent_duplicates(), vec![1, 2]);
+}
+
+/// Checks that a proof in the snarkjs format is parsed into valid curve
+/// points, matching the same proof in the native format.
+#[test]
+fn test_load_snarkjs_proof_and_inputs() {
+    let (proof, inputs) =
+        load_snarkjs_proof_and_inputs(SNARKJS_PROOF_FILE, SNARKJS_PUBLIC_FILE);
+    assert!(bool::from(proof.a.is_on_curve()));
+    assert!(bool::from(proof.b.is_on_curve()));
+    assert!(bool::from(proof.c.is_on_curve()));
+    assert!(proof.m.is_empty() && proof.pok.is_empty());
+
+    let (expected_proof, expected_inputs) = load_proof_and_inputs(PROOF1_FILE);
+    assert_eq!(proof, expected_proof);
+    assert_eq!(inputs, expected_inputs);
+
+    let vk = load_vk(VK_FILE);
+    let batch =
+        UniversalBatchVerifierInputs(vec![UniversalBatchVerifierInput::new(
+            vk, proof, inputs,
+        )]);
+    assert_eq!(verify_batch(&batch), Ok(()));
+}
+
+/// Checks the default values of the optional config fields.
+#[test]
+fn test_config_defaults() {
+    let config: UniversalBatchVerifierConfig = serde_json::from_str(
+        r#"{
+            "degree_bits": 16,
+            "lookup_bits": 15,
+            "limb_bits": 88,
+            "num_limbs": 3,
+            "inner_batch_size": 3,
+            "max_num_public_inputs": 5
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(config.window_bits, WINDOW_BITS);
+    assert_eq!(
+        config.g2_subgroup_window_bits,
+        WINDOW_BITS_G2_SUBGROUP_CHECK
+    );
+    assert!(!config.strict_g1_subgroup);
+    assert!(config.allow_commitments);
+}
+
 /// Test the native computation of the PI term in the universal verifier.
 #[test]
 fn test_compute_pi_term() {
@@ -116,3 +373,160 @@ fn test_compute_pi_term() {
         ),
     );
 }
+
+/// Generates a JSON proof batch from a fake VK, using an RNG seeded with
+/// `seed` for both the VK trapdoor and the proofs.
+fn seeded_proof_batch_json(seed: u64, with_commitment: bool) -> String {
+    const NUM_PROOFS: usize = 3;
+    const NUM_PUBLIC_INPUTS: usize = 4;
+    let rng = &mut ChaCha20Rng::seed_from_u64(seed);
+    let unsafe_vk =
+        UnsafeVerificationKey::sample(NUM_PUBLIC_INPUTS, with_commitment, rng);
+    let batch: Vec<JsonUniversalBatchVerifierInput> = (0..NUM_PROOFS)
+        .map(|_| {
+            let (proof, inputs) = unsafe_vk.create_proof_and_inputs(rng);
+            JsonUniversalBatchVerifierInput {
+                vk: unsafe_vk.vk().into(),
+                proof: (&proof).into(),
+                inputs: (&inputs).into(),
+            }
+        })
+        .collect();
+    serde_json::to_string_pretty(&batch).unwrap()
+}
+
+/// Checks that proof generation with a seeded RNG is reproducible.
+#[test]
+fn test_seeded_proof_generation() {
+    for with_commitment in [false, true] {
+        assert_eq!(
+            seeded_proof_batch_json(1, with_commitment),
+            seeded_proof_batch_json(1, with_commitment),
+            "Same seed gave different proofs"
+        );
+        assert_ne!(
+            seeded_proof_batch_json(1, with_commitment),
+            seeded_proof_batch_json(2, with_commitment),
+            "Different seeds gave the same proofs"
+        );
+    }
+}
+
+/// Checks that parallel proof generation gives the same (ordered) output as
+/// serial generation for a given seed.
+///
+/// CMD: cargo test --release -- --nocapture test_parallel_proof_generation
+#[test]
+fn test_parallel_proof_generation() {
+    const NUM_PROOFS: usize = 9;
+    let rng = &mut ChaCha20Rng::seed_from_u64(1);
+    let uvks = vec![
+        UnsafeVerificationKey::sample(2, false, rng),
+        UnsafeVerificationKey::sample(3, true, rng),
+    ];
+    let serial = create_proofs_and_inputs(&uvks, NUM_PROOFS, Some(1), false);
+    let parallel = create_proofs_and_inputs(&uvks, NUM_PROOFS, Some(1), true);
+    assert_eq!(serial, parallel, "Parallel and serial outputs differ");
+    assert_ne!(
+        serial,
+        create_proofs_and_inputs(&uvks, NUM_PROOFS, Some(2), true),
+        "Different seeds gave the same proofs"
+    );
+
+    // Proofs cycle through the vks, and are valid
+    let batch = parallel
+        .into_iter()
+        .enumerate()
+        .map(|(i, (proof, inputs))| {
+            let vk = uvks[i % uvks.len()].vk().clone();
+            UniversalBatchVerifierInput::new(vk, proof, inputs)
+        });
+    assert!(
+        verify_universal_groth16_batch(batch, 4),
+        "Verification failed"
+    );
+}
+
+/// Checks that `VerificationKey::validate` accepts a valid vk and reports
+/// each kind of malformed vk.
+#[test]
+fn test_vk_validate() {
+    let vk = load_vk(VK_FILE);
+    assert_eq!(vk.validate(), Ok(()));
+
+    let mut bad_vk = vk.clone();
+    bad_vk.s[1] = G1Affine {
+        x: Fq::one(),
+        y: Fq::one(),
+    };
+    assert_eq!(
+        bad_vk.validate(),
+        Err(VkError::NotOnCurve("s[1]".to_string()))
+    );
+
+    let mut bad_vk = vk.clone();
+    bad_vk.delta = sample_g2_affine_not_in_subgroup(&mut OsRng);
+    assert_eq!(
+        bad_vk.validate(),
+        Err(VkError::NotInSubgroup("delta".to_string()))
+    );
+
+    let mut bad_vk = vk.clone();
+    bad_vk.s.clear();
+    assert_eq!(bad_vk.validate(), Err(VkError::EmptyS));
+
+    let mut bad_vk = vk.clone();
+    bad_vk.h1.push(bad_vk.beta);
+    bad_vk.h2.clear();
+    assert_eq!(
+        bad_vk.validate(),
+        Err(VkError::InvalidCommitmentKey {
+            h1_len: 1,
+            h2_len: 0
+        })
+    );
+}
+
+/// Checks that [`compute_circuit_id`] rejects a vk with more than one
+/// commitment.
+#[test]
+#[should_panic]
+fn test_compute_circuit_id_ill_formed_vk() {
+    let mut vk = load_vk(VK_FILE);
+    vk.h1 = vec![vk.beta; 2];
+    vk.h2 = vec![vk.beta; 2];
+    compute_circuit_id(&vk);
+}
+
+/// Checks the lengths of padded vks and proofs, with and without commitment,
+/// and that padding twice is the same as padding once.
+#[test]
+fn test_pad_vk_and_proof() {
+    const NUM_PUBLIC_INPUTS: usize = 3;
+    const MAX_NUM_PUBLIC_INPUTS: usize = 8;
+    for has_commitment in [false, true] {
+        let mut vk: VerificationKey = VerificationKey::default_with_length(
+            NUM_PUBLIC_INPUTS,
+            has_commitment,
+        );
+        vk.pad(MAX_NUM_PUBLIC_INPUTS);
+        assert_eq!(vk.s.len(), MAX_NUM_PUBLIC_INPUTS + 1);
+        assert_eq!(vk.h1.len(), 1);
+        assert_eq!(vk.h2.len(), 1);
+        let padded_vk = vk.clone();
+        vk.pad(MAX_NUM_PUBLIC_INPUTS);
+        assert_eq!(vk, padded_vk, "vk padding is not idempotent");
+
+        let mut proof = Proof::default_with_commitment(has_commitment);
+        let unpadded_proof = proof.clone();
+        proof.pad_commitments();
+        assert_eq!(proof.m.len(), 1);
+        assert_eq!(proof.pok.len(), 1);
+        if has_commitment {
+            assert_eq!(proof, unpadded_proof);
+        }
+        let padded_proof = proof.clone();
+        proof.pad_commitments();
+        assert_eq!(proof, padded_proof, "proof padding is not idempotent");
+    }
+}
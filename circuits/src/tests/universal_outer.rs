@@ -1,42 +1,73 @@
 //! Universal Outer circuit tests
 use crate::{
-    batch_verify::universal::{
-        native::compute_circuit_id,
-        types::{
-            UniversalBatchVerifierConfig, UniversalBatchVerifierInput,
-            UniversalBatchVerifierInputs,
+    batch_verify::{
+        common::chip::{WINDOW_BITS, WINDOW_BITS_G2_SUBGROUP_CHECK},
+        universal::{
+            native::compute_circuit_id,
+            types::{
+                UniversalBatchVerifierConfig, UniversalBatchVerifierInput,
+                UniversalBatchVerifierInputs,
+            },
+            utils::gen_ubv_snark,
+            UniversalBatchVerifyCircuit,
         },
-        utils::gen_ubv_snark,
     },
     keccak::{
         inputs::KeccakCircuitInputs,
         utils::{
             compute_final_digest, compute_proof_id, digest_as_field_elements,
-            gen_keccak_snark, keccak_inputs_from_ubv_instances,
+            gen_keccak_snark, inputs_per_application_proof_with_commitments,
+            keccak_inputs_from_ubv_instances,
         },
-        KeccakConfig, KECCAK_LOOKUP_BITS,
+        KeccakCircuit, KeccakConfig, OutputMode, KECCAK_LOOKUP_BITS,
     },
     outer::{
+        onchain_vk::{OnchainVerifyingKey, OnchainVerifyingKeyError},
+        outer_instance_len,
+        recursive::{
+            recursive_outer_instance_len, RecursiveOuterCircuit,
+            RecursiveOuterConfig, RecursiveOuterInstanceInputs,
+        },
         universal::UniversalOuterCircuit,
-        utils::{gen_outer_evm_verifier, gen_outer_pk, prove_outer},
-        OuterCircuitInputs, OuterCircuitWrapper, OuterGateConfig,
-        OuterKeygenInputs, UniversalOuterConfig,
+        utils::{
+            abi_decode_calldata, abi_encode_calldata, batch_verify_outer,
+            gen_outer_evm_verifier, gen_outer_pk, gen_outer_pk_from_vk,
+            gen_outer_vk, prove_outer, verify_outer_onchain_equivalent,
+            verify_outer_with_onchain_vk,
+        },
+        OuterCircuit, OuterCircuitInputs, OuterCircuitInputsRef,
+        OuterCircuitWrapper, OuterGateConfig, OuterInputError, OuterInstance,
+        OuterInstanceInputs, OuterKeygenInputs, UniversalOuterConfig,
+        ACCUMULATOR_LEN, FINAL_DIGEST_LEN,
+    },
+    tests::utils::{check_instance_layout, check_key_round_trip},
+    utils::{
+        benchmarks::save_json,
+        cancellation::{CancellationToken, Cancelled},
+        srs::gen_deterministic_srs,
     },
     CircuitConfig, CircuitWithLimbsConfig, SafeCircuit,
 };
 use ark_std::{end_timer, start_timer};
+use core::iter;
+use ethers_core::{types::Address, utils::keccak256};
 use halo2_base::{
-    gates::builder::MultiPhaseThreadBreakPoints,
+    gates::builder::{CircuitBuilderStage, MultiPhaseThreadBreakPoints},
     halo2_proofs::{
         dev::MockProver,
-        halo2curves::bn256::{Bn256, Fr, G1Affine},
+        halo2curves::{
+            bn256::{Bn256, Fr, G1Affine, G2Affine},
+            group::{ff::PrimeField, prime::PrimeCurveAffine, Curve},
+        },
         plonk::{create_proof, verify_proof, ProvingKey},
         poly::{
             commitment::{Prover, Verifier},
             kzg::{
                 commitment::KZGCommitmentScheme,
                 msm::DualMSM,
-                multiopen::{ProverSHPLONK, VerifierSHPLONK},
+                multiopen::{
+                    ProverGWC, ProverSHPLONK, VerifierGWC, VerifierSHPLONK,
+                },
                 strategy::{GuardKZG, SingleStrategy},
             },
         },
@@ -45,16 +76,19 @@ use halo2_base::{
             TranscriptWriterBuffer,
         },
     },
-    utils::fs::gen_srs,
 };
 use log::info;
 use rand::rngs::StdRng;
 use rand_core::{OsRng, SeedableRng};
-use snark_verifier::loader::evm::compile_yul;
+use snark_verifier::loader::evm::{compile_yul, ExecutorBuilder};
 use snark_verifier_sdk::{
-    evm::{evm_verify, EvmKzgAccumulationScheme},
+    evm::{encode_calldata, evm_verify, EvmKzgAccumulationScheme},
     halo2::aggregation::Halo2KzgAccumulationScheme,
-    CircuitExt, Snark, SHPLONK,
+    CircuitExt, Snark, GWC, SHPLONK,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
 };
 
 /// Small inner/outer batch sizes, but high enough degrees to be EVM compatible.
@@ -71,6 +105,11 @@ const EVM_OUTER_CONFIG: UniversalOuterConfig = UniversalOuterConfig {
         limb_bits: 88,
     },
     output_submission_id: false,
+    allow_commitments: true,
+    public_num_proof_ids: false,
+    include_vk_digest: false,
+    window_bits: WINDOW_BITS,
+    g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK,
 };
 
 const TINY_INNER_BATCH_SIZE: u32 = 1;
@@ -117,6 +156,11 @@ const DEFAULT_OUTER_CONFIG: UniversalOuterConfig = UniversalOuterConfig {
         limb_bits: 88,
     },
     output_submission_id: false,
+    allow_commitments: true,
+    public_num_proof_ids: false,
+    include_vk_digest: false,
+    window_bits: WINDOW_BITS,
+    g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK,
 };
 
 fn compute_proof_id_from_ubv_input(
@@ -191,6 +235,7 @@ where
     (
         OuterCircuitInputs::<UniversalOuterCircuit>::new(
             outer_config,
+            &keccak_config,
             ubv_snarks,
             keccak_snark,
         ),
@@ -243,6 +288,7 @@ fn outer_circuit_mock<'params, AS, P, V>(
         &outer_inputs,
     );
     let instances = circuit.instances();
+    assert_eq!(instances[0].len(), outer_instance_len(outer_config));
     MockProver::<Fr>::run(
         outer_config.outer_config.degree_bits,
         &circuit,
@@ -264,9 +310,10 @@ fn outer_circuit_mock_shplonk() {
     let ubv_config: UniversalBatchVerifierConfig = (&outer_config).into();
     let keccak_config = TINY_KECCAK_CONFIG;
 
-    let outer_params = gen_srs(outer_config.outer_config.degree_bits);
-    let bv_params = gen_srs(ubv_config.degree_bits);
-    let keccak_params = gen_srs(keccak_config.degree_bits);
+    let outer_params =
+        gen_deterministic_srs(outer_config.outer_config.degree_bits, 0);
+    let bv_params = gen_deterministic_srs(ubv_config.degree_bits, 0);
+    let keccak_params = gen_deterministic_srs(keccak_config.degree_bits, 0);
     let outer_keygen_inputs =
         OuterKeygenInputs::new(&bv_params, &keccak_params, &outer_params);
 
@@ -276,6 +323,737 @@ fn outer_circuit_mock_shplonk() {
     );
 }
 
+/// Samples UBV inputs for each inner batch and returns the corresponding
+/// BV instances and `KeccakCircuit` instance, without generating any proofs.
+fn sample_outer_instances(
+    outer_config: &UniversalOuterConfig,
+) -> (Vec<Vec<Fr>>, Vec<Fr>) {
+    let ubv_config: UniversalBatchVerifierConfig = outer_config.into();
+    let keccak_config: KeccakConfig = outer_config.into();
+
+    let bv_instances: Vec<Vec<Fr>> = (0..outer_config.outer_batch_size)
+        .map(|_| {
+            let ubv_inputs = UniversalBatchVerifierInputs::sample_mixed(
+                &ubv_config,
+                &mut OsRng,
+            );
+            UniversalBatchVerifyCircuit::<Fr, G1Affine>::compute_instance(
+                &ubv_config,
+                &ubv_inputs,
+            )
+        })
+        .collect();
+    let keccak_instance = {
+        let inputs = keccak_inputs_from_ubv_instances(
+            bv_instances.iter().map(|i| i.as_slice()),
+            outer_config.max_num_app_public_inputs as usize,
+            outer_config.inner_batch_size as usize,
+        );
+        KeccakCircuit::<Fr, G1Affine>::compute_instance(
+            &keccak_config,
+            &KeccakCircuitInputs::from_inputs_and_config(
+                inputs,
+                &keccak_config,
+            ),
+        )
+    };
+    (bv_instances, keccak_instance)
+}
+
+/// Checks that the instance of a `KeccakCircuit` in [`OutputMode::Both`]
+/// cannot be aggregated by the outer circuit, which forwards a single final
+/// digest, for either value of `output_submission_id`.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_outer::outer_inputs_reject_both_output_mode --exact --nocapture`
+#[test]
+fn outer_inputs_reject_both_output_mode() {
+    for output_submission_id in [false, true] {
+        let outer_config = UniversalOuterConfig {
+            output_submission_id,
+            ..DEFAULT_OUTER_CONFIG
+        };
+        let (bv_instances, keccak_instance) =
+            sample_outer_instances(&outer_config);
+        let both_keccak_config = KeccakConfig {
+            output_mode: OutputMode::Both,
+            ..outer_config.keccak_config()
+        };
+        assert!(outer_config
+            .check_keccak_config(&both_keccak_config)
+            .is_err());
+
+        let both_keccak_instance = {
+            let inputs = keccak_inputs_from_ubv_instances(
+                bv_instances.iter().map(|i| i.as_slice()),
+                outer_config.max_num_app_public_inputs as usize,
+                outer_config.inner_batch_size as usize,
+            );
+            KeccakCircuit::<Fr, G1Affine>::compute_instance(
+                &both_keccak_config,
+                &KeccakCircuitInputs::from_inputs_and_config(
+                    inputs,
+                    &both_keccak_config,
+                ),
+            )
+        };
+        assert_eq!(
+            both_keccak_instance.len(),
+            keccak_instance.len() + FINAL_DIGEST_LEN
+        );
+        assert_eq!(
+            OuterCircuitInputs::<UniversalOuterCircuit>::check_consistency(
+                &outer_config,
+                &bv_instances,
+                &both_keccak_instance,
+            ),
+            Err(OuterInputError::WrongKeccakInstanceLength {
+                expected: keccak_instance.len(),
+                actual: both_keccak_instance.len(),
+            })
+        );
+    }
+}
+
+/// Checks that [`OuterInstanceInputs::new`] rejects a keccak config which
+/// diverges from the one derived from the outer config, even if the keccak
+/// instance itself is consistent with the BV instances.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_outer::outer_inputs_reject_divergent_keccak_config --exact --nocapture`
+#[test]
+#[should_panic(expected = "Unexpected keccak config")]
+fn outer_inputs_reject_divergent_keccak_config() {
+    let outer_config = DEFAULT_OUTER_CONFIG;
+    let (bv_instances, keccak_instance) = sample_outer_instances(&outer_config);
+    let keccak_config = KeccakConfig {
+        public_num_proof_ids: !outer_config.public_num_proof_ids,
+        ..outer_config.keccak_config()
+    };
+    OuterInstanceInputs::<UniversalOuterCircuit>::new(
+        &outer_config,
+        &keccak_config,
+        bv_instances,
+        keccak_instance,
+    );
+}
+
+/// Samples UBV inputs for each inner batch and returns the corresponding
+/// outer circuit instance inputs, without generating any proofs.
+fn sample_outer_instance_inputs(
+    outer_config: &UniversalOuterConfig,
+) -> OuterInstanceInputs<UniversalOuterCircuit> {
+    let (bv_instances, keccak_instance) = sample_outer_instances(outer_config);
+    OuterInstanceInputs::<UniversalOuterCircuit>::new(
+        outer_config,
+        &outer_config.keccak_config(),
+        bv_instances,
+        keccak_instance,
+    )
+}
+
+/// Checks that [`OuterCircuitInputs::check_consistency`] accepts sampled
+/// instances, and identifies the BV instance or digest which does not match
+/// when the `KeccakCircuit` instance is modified.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_outer::outer_inputs_check_consistency --exact --nocapture`
+#[test]
+fn outer_inputs_check_consistency() {
+    let outer_config = DEFAULT_OUTER_CONFIG;
+    let keccak_config: KeccakConfig = (&outer_config).into();
+    let (bv_instances, keccak_instance) = sample_outer_instances(&outer_config);
+    let check = |bv_instances: &[Vec<Fr>], keccak_instance: &[Fr]| {
+        OuterCircuitInputs::<UniversalOuterCircuit>::check_consistency(
+            &outer_config,
+            bv_instances,
+            keccak_instance,
+        )
+    };
+    assert_eq!(check(&bv_instances, &keccak_instance), Ok(()));
+
+    assert_eq!(
+        check(&bv_instances[1..], &keccak_instance),
+        Err(OuterInputError::WrongNumberOfBvInstances {
+            expected: bv_instances.len(),
+            actual: bv_instances.len() - 1,
+        })
+    );
+    assert_eq!(
+        check(&bv_instances, &keccak_instance[1..]),
+        Err(OuterInputError::WrongKeccakInstanceLength {
+            expected: keccak_instance.len(),
+            actual: keccak_instance.len() - 1,
+        })
+    );
+
+    // Modify the entry of the first application proof of the last BV
+    // instance.
+    let last_bv_instance = bv_instances.len() - 1;
+    let entry_len = inputs_per_application_proof_with_commitments(
+        keccak_config.num_app_public_inputs as usize,
+        keccak_config.max_commitments as usize,
+        keccak_config.num_limbs,
+    );
+    let mut bad_keccak_instance = keccak_instance.clone();
+    bad_keccak_instance[last_bv_instance
+        * outer_config.inner_batch_size as usize
+        * entry_len] += Fr::from(1);
+    assert_eq!(
+        check(&bv_instances, &bad_keccak_instance),
+        Err(OuterInputError::BvInstanceMismatch(last_bv_instance))
+    );
+
+    let mut bad_keccak_instance = keccak_instance;
+    *bad_keccak_instance.last_mut().unwrap() += Fr::from(1);
+    assert_eq!(
+        check(&bv_instances, &bad_keccak_instance),
+        Err(OuterInputError::DigestMismatch)
+    );
+}
+
+/// Checks that [`OuterCircuitInputs::check_consistency`] rejects a public
+/// `num_proof_ids` outside `1..=total_batch_size`, including values whose
+/// low bits are in range.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_outer::outer_inputs_check_num_proof_ids --exact --nocapture`
+#[test]
+fn outer_inputs_check_num_proof_ids() {
+    let outer_config = UniversalOuterConfig {
+        output_submission_id: true,
+        public_num_proof_ids: true,
+        ..DEFAULT_OUTER_CONFIG
+    };
+    let (bv_instances, keccak_instance) = sample_outer_instances(&outer_config);
+    let check = |keccak_instance: &[Fr]| {
+        OuterCircuitInputs::<UniversalOuterCircuit>::check_consistency(
+            &outer_config,
+            &bv_instances,
+            keccak_instance,
+        )
+    };
+    assert_eq!(check(&keccak_instance), Ok(()));
+
+    let total_batch_size =
+        (outer_config.inner_batch_size * outer_config.outer_batch_size) as u64;
+    let num_proof_ids_idx = keccak_instance.len() - 1 - FINAL_DIGEST_LEN;
+    for num_proof_ids in [
+        Fr::from(0),
+        Fr::from(total_batch_size + 1),
+        Fr::from(1 << 32) + Fr::from(total_batch_size),
+    ] {
+        let mut bad_keccak_instance = keccak_instance.clone();
+        bad_keccak_instance[num_proof_ids_idx] = num_proof_ids;
+        assert_eq!(
+            check(&bad_keccak_instance),
+            Err(OuterInputError::InvalidNumProofIds {
+                max: total_batch_size
+            })
+        );
+    }
+}
+
+/// Returns the paths of the `outer_batch_size` BV snark files in `dir`
+fn bv_snark_paths(dir: &Path, outer_batch_size: usize) -> Vec<PathBuf> {
+    (0..outer_batch_size)
+        .map(|i| dir.join(format!("bv_snark_{i}.json")))
+        .collect()
+}
+
+/// Returns the path of the keccak snark file in `dir`
+fn keccak_snark_path(dir: &Path) -> PathBuf {
+    dir.join("keccak_snark.json")
+}
+
+/// Checks that [`OuterCircuitInputsRef::resolve`], after a JSON round trip
+/// of the job description, yields the same snarks as the eager
+/// [`OuterCircuitInputs`] they were written from, and that it rejects the
+/// wrong number of BV snarks and snarks with inconsistent instances.
+///
+/// CMD: `cargo test --release --package upa-circuits --lib -- tests::universal_outer::outer_inputs_ref_resolve --exact --nocapture --include-ignored`
+#[ignore = "takes too long"]
+#[test]
+fn outer_inputs_ref_resolve() {
+    let outer_config = DEFAULT_OUTER_CONFIG;
+    let outer_batch_size = outer_config.outer_batch_size as usize;
+    let bv_params =
+        gen_deterministic_srs(outer_config.bv_config.degree_bits, 0);
+    let keccak_params =
+        gen_deterministic_srs(outer_config.keccak_config.degree_bits, 0);
+    let eager = OuterCircuitInputs::<UniversalOuterCircuit>::keygen_default::<
+        ProverSHPLONK<Bn256>,
+        VerifierSHPLONK<Bn256>,
+    >(&outer_config, &bv_params, &keccak_params);
+
+    let dir = std::env::temp_dir()
+        .join(format!("upa-outer-inputs-ref-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut snarks = eager.clone().into_snarks();
+    for path in bv_snark_paths(&dir, outer_batch_size) {
+        save_json(path.to_str().unwrap(), &snarks.next().unwrap());
+    }
+    save_json(
+        keccak_snark_path(&dir).to_str().unwrap(),
+        &snarks.next().unwrap(),
+    );
+
+    let inputs_ref = OuterCircuitInputsRef::new(
+        &outer_config,
+        bv_snark_paths(&dir, outer_batch_size),
+        keccak_snark_path(&dir),
+    );
+    let inputs_ref: OuterCircuitInputsRef =
+        serde_json::from_str(&serde_json::to_string(&inputs_ref).unwrap())
+            .unwrap();
+    let resolved = inputs_ref
+        .resolve::<UniversalOuterCircuit>()
+        .unwrap_or_else(|e| panic!("failed to resolve: {e}"));
+    let to_json = |snark: Snark| serde_json::to_value(snark).unwrap();
+    assert_eq!(
+        resolved.into_snarks().map(to_json).collect::<Vec<_>>(),
+        eager.into_snarks().map(to_json).collect::<Vec<_>>()
+    );
+
+    let wrong_keccak_snark = OuterCircuitInputsRef {
+        keccak_snark_path: inputs_ref.bv_snark_paths[0].clone(),
+        ..inputs_ref.clone()
+    };
+    assert!(matches!(
+        wrong_keccak_snark.resolve::<UniversalOuterCircuit>().err(),
+        Some(OuterInputError::WrongKeccakInstanceLength { .. })
+    ));
+
+    let missing_bv_snark = OuterCircuitInputsRef {
+        bv_snark_paths: inputs_ref.bv_snark_paths[1..].to_vec(),
+        ..inputs_ref
+    };
+    assert_eq!(
+        missing_bv_snark.resolve::<UniversalOuterCircuit>().err(),
+        Some(OuterInputError::WrongNumberOfBvInstances {
+            expected: outer_batch_size,
+            actual: outer_batch_size - 1,
+        })
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Checks that [`OuterCircuitInputsRef::resolve`] returns an error for
+/// missing and malformed snark files.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_outer::outer_inputs_ref_unreadable_snark --exact --nocapture`
+#[test]
+fn outer_inputs_ref_unreadable_snark() {
+    let outer_config = DEFAULT_OUTER_CONFIG;
+    let outer_batch_size = outer_config.outer_batch_size as usize;
+    let dir = std::env::temp_dir()
+        .join(format!("upa-outer-unreadable-snark-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let bv_snark_paths = bv_snark_paths(&dir, outer_batch_size);
+
+    let missing = OuterCircuitInputsRef::new(
+        &outer_config,
+        bv_snark_paths.clone(),
+        keccak_snark_path(&dir),
+    );
+    assert!(matches!(
+        missing.resolve::<UniversalOuterCircuit>().err(),
+        Some(OuterInputError::UnreadableSnark { path, .. })
+            if path == bv_snark_paths[0]
+    ));
+
+    fs::write(&bv_snark_paths[0], "{}").unwrap();
+    assert!(matches!(
+        missing.resolve::<UniversalOuterCircuit>().err(),
+        Some(OuterInputError::UnreadableSnark { path, .. })
+            if path == bv_snark_paths[0]
+    ));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Checks that [`outer_instance_len`] is consistent with the instance
+/// computed by [`OuterCircuitWrapper::compute_instance`], which omits the
+/// `4 * num_limbs` field elements of the KZG accumulator, and with the
+/// layout given by [`OuterCircuit::describe_instance`].
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_outer::outer_circuit_instance_len --exact --nocapture`
+#[test]
+fn outer_circuit_instance_len() {
+    let outer_config = DEFAULT_OUTER_CONFIG;
+    let instance_inputs = sample_outer_instance_inputs(&outer_config);
+    let final_digest = OuterCircuitWrapper::<
+        SHPLONK,
+        UniversalOuterCircuit,
+        ProverSHPLONK<Bn256>,
+        VerifierSHPLONK<Bn256>,
+    >::compute_instance(&outer_config, &instance_inputs);
+
+    assert_eq!(
+        outer_instance_len(&outer_config),
+        4 * outer_config.outer_config.num_limbs + final_digest.len()
+    );
+
+    let layout = UniversalOuterCircuit::describe_instance(&outer_config);
+    check_instance_layout(&layout, outer_instance_len(&outer_config));
+    assert_eq!(
+        layout.last().unwrap(),
+        &(
+            "final_digest".to_string(),
+            ACCUMULATOR_LEN..ACCUMULATOR_LEN + FINAL_DIGEST_LEN
+        )
+    );
+}
+
+/// Checks that [`OuterInstance`] round-trips through its flat
+/// representation, and rejects flat instances of the wrong length.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_outer::outer_instance_round_trip --exact --nocapture`
+#[test]
+fn outer_instance_round_trip() {
+    let flat: Vec<Fr> = (0..(ACCUMULATOR_LEN + FINAL_DIGEST_LEN) as u64)
+        .map(Fr::from)
+        .collect();
+    let config = DEFAULT_OUTER_CONFIG;
+    let outer_instance = OuterInstance::from_flat(&config, &flat).unwrap();
+    assert_eq!(
+        &outer_instance.accumulator_limbs[..],
+        &flat[..ACCUMULATOR_LEN]
+    );
+    assert_eq!(outer_instance.num_proof_ids, None);
+    assert_eq!(&outer_instance.final_digest[..], &flat[ACCUMULATOR_LEN..]);
+    assert_eq!(outer_instance.to_flat(), flat);
+    assert_eq!(
+        OuterInstance::from_flat(&config, &outer_instance.to_flat()).unwrap(),
+        outer_instance
+    );
+
+    assert!(OuterInstance::from_flat(&config, &flat[1..]).is_err());
+    assert!(
+        OuterInstance::from_flat(&config, &[flat.clone(), flat].concat())
+            .is_err()
+    );
+    assert_eq!(
+        ACCUMULATOR_LEN + FINAL_DIGEST_LEN,
+        outer_instance_len(&config)
+    );
+}
+
+/// Checks that, when `public_num_proof_ids` is set, the outer circuit
+/// forwards `num_proof_ids` from the `KeccakCircuit` instance, immediately
+/// before the final digest, and that [`OuterInstance`],
+/// [`outer_instance_len`] and [`OuterCircuit::describe_instance`] account
+/// for it.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_outer::outer_instance_public_num_proof_ids --exact --nocapture`
+#[test]
+fn outer_instance_public_num_proof_ids() {
+    let outer_config = UniversalOuterConfig {
+        output_submission_id: true,
+        public_num_proof_ids: true,
+        ..DEFAULT_OUTER_CONFIG
+    };
+    assert_eq!(outer_config.validate(), Ok(()));
+    let instance_inputs = sample_outer_instance_inputs(&outer_config);
+    let outputs = OuterCircuitWrapper::<
+        SHPLONK,
+        UniversalOuterCircuit,
+        ProverSHPLONK<Bn256>,
+        VerifierSHPLONK<Bn256>,
+    >::compute_instance(&outer_config, &instance_inputs);
+    let total_batch_size =
+        outer_config.inner_batch_size * outer_config.outer_batch_size;
+    assert_eq!(outputs.len(), 1 + FINAL_DIGEST_LEN);
+    assert_eq!(outputs[0], Fr::from(total_batch_size as u64));
+    assert_eq!(
+        outer_instance_len(&outer_config),
+        ACCUMULATOR_LEN + outputs.len()
+    );
+
+    let flat = [vec![Fr::from(1); ACCUMULATOR_LEN], outputs.clone()].concat();
+    let outer_instance =
+        OuterInstance::from_flat(&outer_config, &flat).unwrap();
+    assert_eq!(outer_instance.num_proof_ids, Some(outputs[0]));
+    assert_eq!(&outer_instance.final_digest[..], &outputs[1..]);
+    assert_eq!(outer_instance.vk_digest, None);
+    assert_eq!(
+        instance_inputs.final_digest(&outer_config),
+        outer_instance.final_digest
+    );
+    assert_eq!(outer_instance.outputs(), outputs);
+    assert_eq!(outer_instance.to_flat(), flat);
+    assert!(OuterInstance::from_flat(&DEFAULT_OUTER_CONFIG, &flat).is_err());
+
+    let layout = UniversalOuterCircuit::describe_instance(&outer_config);
+    check_instance_layout(&layout, outer_instance_len(&outer_config));
+    assert_eq!(
+        layout[layout.len() - 2..],
+        [
+            (
+                "num_proof_ids".to_string(),
+                ACCUMULATOR_LEN..ACCUMULATOR_LEN + 1
+            ),
+            (
+                "final_digest".to_string(),
+                ACCUMULATOR_LEN + 1..ACCUMULATOR_LEN + 1 + FINAL_DIGEST_LEN
+            ),
+        ]
+    );
+}
+
+/// Checks that, with `include_vk_digest`, the outer instance forwards the
+/// vk digest computed by the `KeccakCircuit` after the final digest.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_outer::outer_instance_include_vk_digest --exact --nocapture`
+#[test]
+fn outer_instance_include_vk_digest() {
+    let outer_config = UniversalOuterConfig {
+        output_submission_id: false,
+        include_vk_digest: true,
+        ..DEFAULT_OUTER_CONFIG
+    };
+    assert_eq!(outer_config.validate(), Ok(()));
+    let instance_inputs = sample_outer_instance_inputs(&outer_config);
+    let outputs = OuterCircuitWrapper::<
+        SHPLONK,
+        UniversalOuterCircuit,
+        ProverSHPLONK<Bn256>,
+        VerifierSHPLONK<Bn256>,
+    >::compute_instance(&outer_config, &instance_inputs);
+    assert_eq!(outputs.len(), 2 * FINAL_DIGEST_LEN);
+    assert_eq!(
+        outer_instance_len(&outer_config),
+        ACCUMULATOR_LEN + outputs.len()
+    );
+
+    let flat = [vec![Fr::from(1); ACCUMULATOR_LEN], outputs.clone()].concat();
+    let outer_instance =
+        OuterInstance::from_flat(&outer_config, &flat).unwrap();
+    assert_eq!(outer_instance.num_proof_ids, None);
+    assert_eq!(
+        &outer_instance.final_digest[..],
+        &outputs[..FINAL_DIGEST_LEN]
+    );
+    assert_eq!(
+        outer_instance.vk_digest.map(|d| d.to_vec()),
+        Some(outputs[FINAL_DIGEST_LEN..].to_vec())
+    );
+    assert_eq!(
+        instance_inputs.final_digest(&outer_config),
+        outer_instance.final_digest
+    );
+    assert_eq!(outer_instance.to_flat(), flat);
+
+    let layout = UniversalOuterCircuit::describe_instance(&outer_config);
+    check_instance_layout(&layout, outer_instance_len(&outer_config));
+    assert_eq!(
+        layout[layout.len() - 2..],
+        [
+            (
+                "final_digest".to_string(),
+                ACCUMULATOR_LEN..ACCUMULATOR_LEN + FINAL_DIGEST_LEN
+            ),
+            (
+                "vk_digest".to_string(),
+                ACCUMULATOR_LEN + FINAL_DIGEST_LEN
+                    ..ACCUMULATOR_LEN + 2 * FINAL_DIGEST_LEN
+            ),
+        ]
+    );
+}
+
+/// Verifier Yul code generated for an outer circuit, deployed in the UPA
+/// contract tests.
+const OUTER_VERIFIER_YUL_FILE: &str = "../upa/test/data/outer_2_2.verifier.yul";
+
+/// Checks that [`OnchainVerifyingKey::from_yul`] extracts the verifying key
+/// constants of a generated verifier, and rejects malformed verifiers.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_outer::onchain_vk_from_yul --exact --nocapture`
+#[test]
+fn onchain_vk_from_yul() {
+    let yul = fs::read_to_string(OUTER_VERIFIER_YUL_FILE)
+        .expect("failed to read verifier yul");
+    let onchain_vk = OnchainVerifyingKey::from_yul(&yul).unwrap();
+    assert_eq!(
+        onchain_vk.transcript_initial_state,
+        Fr::from_str_vartime(
+            "11906053369211682963391748746389926647989723026658246039630686681052930727235"
+        )
+        .unwrap()
+    );
+    assert_eq!(onchain_vk.preprocessed.len(), 13);
+    assert_eq!(onchain_vk.g2, G2Affine::generator());
+
+    // Line of the `i`-th 32-byte hex constant, and the same line with the
+    // last digit of the constant modified.
+    let constant_line = |i: usize| {
+        yul.lines()
+            .filter(|line| {
+                let line = line.trim();
+                line.starts_with("mstore(")
+                    && line
+                        .split_once(", 0x")
+                        .map_or(false, |(_, word)| word.len() == 64 + 1)
+            })
+            .nth(i)
+            .unwrap()
+    };
+    let modify_constant = |i: usize| {
+        let line = constant_line(i);
+        let (prefix, last_digit) = line
+            .trim_end()
+            .strip_suffix(')')
+            .unwrap()
+            .split_at(line.trim_end().len() - 2);
+        let digit = if last_digit == "0" { "1" } else { "0" };
+        yul.replacen(line, &format!("{prefix}{digit})"), 1)
+    };
+
+    let without_state =
+        yul.replacen("mstore(0x0, 1190605336", "mstore(0x0, x", 1);
+    assert_eq!(
+        OnchainVerifyingKey::from_yul(&without_state),
+        Err(OnchainVerifyingKeyError::MissingTranscriptState)
+    );
+    let without_last = yul.replacen(constant_line(35), "", 1);
+    assert_eq!(
+        OnchainVerifyingKey::from_yul(&without_last),
+        Err(OnchainVerifyingKeyError::InvalidNumConstants(35))
+    );
+    assert_eq!(
+        OnchainVerifyingKey::from_yul(&modify_constant(0)),
+        Err(OnchainVerifyingKeyError::MissingG1Generator)
+    );
+    // The y coordinate of the second preprocessed commitment
+    assert_eq!(
+        OnchainVerifyingKey::from_yul(&modify_constant(5)),
+        Err(OnchainVerifyingKeyError::InvalidG1Point(1))
+    );
+}
+
+/// Checks that [`abi_encode_calldata`] output decodes to the original proof
+/// and the instance computed for sampled inputs, and that the selector is
+/// checked.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_outer::outer_abi_calldata_round_trip --exact --nocapture`
+#[test]
+fn outer_abi_calldata_round_trip() {
+    let outer_config = DEFAULT_OUTER_CONFIG;
+    let instance_inputs = sample_outer_instance_inputs(&outer_config);
+    let final_digest = OuterCircuitWrapper::<
+        SHPLONK,
+        UniversalOuterCircuit,
+        ProverSHPLONK<Bn256>,
+        VerifierSHPLONK<Bn256>,
+    >::compute_instance(&outer_config, &instance_inputs);
+    let instance = OuterInstance {
+        accumulator_limbs: [Fr::from(1); ACCUMULATOR_LEN],
+        num_proof_ids: None,
+        final_digest: final_digest.try_into().unwrap(),
+        vk_digest: None,
+    }
+    .to_flat();
+    // Odd length, to exercise the padding of `bytes`
+    let proof: Vec<u8> = (0..=100).collect();
+    let selector = [0xde, 0xad, 0xbe, 0xef];
+
+    let calldata = abi_encode_calldata(selector, &proof, &instance);
+    assert_eq!(&calldata[..4], &selector);
+    let (decoded_proof, decoded_instance) =
+        abi_decode_calldata(selector, &calldata).unwrap();
+    assert_eq!(decoded_proof, proof);
+    assert_eq!(decoded_instance, instance);
+
+    assert!(abi_decode_calldata([0, 0, 0, 0], &calldata).is_err());
+}
+
+/// Checks that outer circuit construction returns `Err(Cancelled)`, without
+/// pulling any snarks, if the cancellation token is already set.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_outer::outer_circuit_cancelled --exact --nocapture`
+#[test]
+fn outer_circuit_cancelled() {
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let snarks = Box::new(iter::from_fn(|| -> Option<Snark> {
+        panic!("snark pulled after cancellation")
+    }));
+    let result = UniversalOuterCircuit::new_from_snarks_cancellable::<SHPLONK>(
+        CircuitBuilderStage::Mock,
+        &DEFAULT_OUTER_CONFIG,
+        snarks,
+        None,
+        None,
+        &cancel,
+    );
+    assert!(matches!(result, Err(Cancelled)));
+
+    // Cancelled while loading the snarks: no circuit is built from the
+    // snarks loaded so far.
+    let cancel = CancellationToken::new();
+    let snarks = Box::new(iter::from_fn(|| -> Option<Snark> {
+        cancel.cancel();
+        None
+    }));
+    let result = UniversalOuterCircuit::new_from_snarks_cancellable::<SHPLONK>(
+        CircuitBuilderStage::Mock,
+        &DEFAULT_OUTER_CONFIG,
+        snarks,
+        None,
+        None,
+        &cancel,
+    );
+    assert!(matches!(result, Err(Cancelled)));
+}
+
+/// Checks that the `RecursiveOuterCircuit` instance is the list of child
+/// final digests, and that [`recursive_outer_instance_len`] and
+/// [`OuterCircuit::describe_instance`] account for these and the KZG
+/// accumulator.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::universal_outer::recursive_outer_circuit_instance --exact --nocapture`
+#[test]
+fn recursive_outer_circuit_instance() {
+    let config = RecursiveOuterConfig::new(
+        DEFAULT_OUTER_CONFIG,
+        3,
+        CircuitWithLimbsConfig::from_degree_bits(22),
+    );
+    let child_instance_len = outer_instance_len(&config.child_config);
+    let child_instances: Vec<Vec<Fr>> = (0..config.num_children)
+        .map(|i| {
+            (0..child_instance_len)
+                .map(|j| Fr::from((i as u64) << 32 | j as u64))
+                .collect()
+        })
+        .collect();
+    let expected_digests: Vec<Fr> = child_instances
+        .iter()
+        .flat_map(|instance| instance[child_instance_len - 2..].to_vec())
+        .collect();
+
+    let instance_inputs =
+        RecursiveOuterInstanceInputs::new(&config, child_instances);
+    let digests = OuterCircuitWrapper::<
+        SHPLONK,
+        RecursiveOuterCircuit,
+        ProverSHPLONK<Bn256>,
+        VerifierSHPLONK<Bn256>,
+    >::compute_instance(&config, &instance_inputs);
+
+    assert_eq!(digests, expected_digests);
+    assert_eq!(
+        recursive_outer_instance_len(&config),
+        4 * config.outer_config.num_limbs + digests.len()
+    );
+
+    let layout = RecursiveOuterCircuit::describe_instance(&config);
+    check_instance_layout(&layout, recursive_outer_instance_len(&config));
+    assert_eq!(
+        layout.len(),
+        4 + config.num_children as usize,
+        "expected 4 accumulator coordinates and one digest per child"
+    );
+}
+
 /// Computes and natively verifies an outer circuit proof. The
 /// proving key is generated from a config using default data,
 /// whereas the proof is generated from sample application proofs.
@@ -357,9 +1135,10 @@ fn outer_circuit_prover_shplonk() {
     let ubv_config: UniversalBatchVerifierConfig = (&outer_config).into();
     let keccak_config = DEFAULT_KECCAK_CONFIG;
 
-    let outer_params = gen_srs(outer_config.outer_config.degree_bits);
-    let bv_params = gen_srs(ubv_config.degree_bits);
-    let keccak_params = gen_srs(keccak_config.degree_bits);
+    let outer_params =
+        gen_deterministic_srs(outer_config.outer_config.degree_bits, 0);
+    let bv_params = gen_deterministic_srs(ubv_config.degree_bits, 0);
+    let keccak_params = gen_deterministic_srs(keccak_config.degree_bits, 0);
     let outer_keygen_inputs =
         OuterKeygenInputs::new(&bv_params, &keccak_params, &outer_params);
 
@@ -369,6 +1148,128 @@ fn outer_circuit_prover_shplonk() {
     );
 }
 
+/// Generates keys for the outer circuit and checks they survive a
+/// write/read round-trip.
+///
+/// CMD: `cargo test --release --package upa-circuits --lib -- tests::universal_outer::outer_circuit_key_round_trip_shplonk --exact --nocapture --include-ignored`
+#[ignore = "takes too long"]
+#[test]
+fn outer_circuit_key_round_trip_shplonk() {
+    let outer_config = DEFAULT_OUTER_CONFIG;
+    let ubv_config: UniversalBatchVerifierConfig = (&outer_config).into();
+    let keccak_config = DEFAULT_KECCAK_CONFIG;
+
+    let outer_params =
+        gen_deterministic_srs(outer_config.outer_config.degree_bits, 0);
+    let bv_params = gen_deterministic_srs(ubv_config.degree_bits, 0);
+    let keccak_params = gen_deterministic_srs(keccak_config.degree_bits, 0);
+    let outer_keygen_inputs =
+        OuterKeygenInputs::new(&bv_params, &keccak_params, &outer_params);
+
+    let (pk, outer_gate_config, _, _) =
+        gen_outer_pk::<
+            SHPLONK,
+            UniversalOuterCircuit,
+            ProverSHPLONK<Bn256>,
+            VerifierSHPLONK<Bn256>,
+        >(&outer_config, &outer_keygen_inputs)
+        .unwrap();
+
+    check_key_round_trip::<
+        OuterCircuitWrapper<
+            SHPLONK,
+            UniversalOuterCircuit,
+            ProverSHPLONK<Bn256>,
+            VerifierSHPLONK<Bn256>,
+        >,
+    >(&outer_config, &outer_gate_config, &pk);
+}
+
+/// Checks that generating the outer PK in two stages, with the VK (and gate
+/// config) written out and read back in between as when resuming keygen from
+/// a checkpoint, gives the same PK as a one-shot keygen.
+///
+/// CMD: `cargo test --release --package upa-circuits --lib -- tests::universal_outer::outer_circuit_resumed_keygen_shplonk --exact --nocapture --include-ignored`
+#[ignore = "takes too long"]
+#[test]
+fn outer_circuit_resumed_keygen_shplonk() {
+    type Circuit = OuterCircuitWrapper<
+        'static,
+        SHPLONK,
+        UniversalOuterCircuit,
+        ProverSHPLONK<'static, Bn256>,
+        VerifierSHPLONK<'static, Bn256>,
+    >;
+
+    let outer_config = DEFAULT_OUTER_CONFIG;
+    let ubv_config: UniversalBatchVerifierConfig = (&outer_config).into();
+    let keccak_config = DEFAULT_KECCAK_CONFIG;
+
+    let outer_params =
+        gen_deterministic_srs(outer_config.outer_config.degree_bits, 0);
+    let bv_params = gen_deterministic_srs(ubv_config.degree_bits, 0);
+    let keccak_params = gen_deterministic_srs(keccak_config.degree_bits, 0);
+    let outer_keygen_inputs =
+        OuterKeygenInputs::new(&bv_params, &keccak_params, &outer_params);
+    let pk_hash = |pk: &ProvingKey<G1Affine>| {
+        let mut buf = Vec::new();
+        Circuit::write_proving_key(pk, &mut buf).expect("failed to write pk");
+        keccak256(buf)
+    };
+
+    let (pk, gate_config, break_points, num_instance) =
+        gen_outer_pk::<
+            SHPLONK,
+            UniversalOuterCircuit,
+            ProverSHPLONK<Bn256>,
+            VerifierSHPLONK<Bn256>,
+        >(&outer_config, &outer_keygen_inputs)
+        .unwrap();
+    let one_shot_pk_hash = pk_hash(&pk);
+    drop(pk);
+
+    // Stage 1: VK
+    let (vk, vk_gate_config, vk_break_points, vk_num_instance) =
+        gen_outer_vk::<
+            SHPLONK,
+            UniversalOuterCircuit,
+            ProverSHPLONK<Bn256>,
+            VerifierSHPLONK<Bn256>,
+        >(&outer_config, &outer_keygen_inputs)
+        .unwrap();
+    let vk_bytes = {
+        let mut buf = Vec::new();
+        Circuit::write_verifying_key(&vk, &mut buf)
+            .expect("failed to write vk");
+        buf
+    };
+    drop(vk);
+
+    // Stage 2: PK from the checkpointed VK
+    let vk = Circuit::read_verifying_key(&vk_gate_config, &mut &vk_bytes[..])
+        .expect("failed to read vk");
+    let (pk, pk_gate_config, pk_break_points, pk_num_instance) =
+        gen_outer_pk_from_vk::<
+            SHPLONK,
+            UniversalOuterCircuit,
+            ProverSHPLONK<Bn256>,
+            VerifierSHPLONK<Bn256>,
+        >(&outer_config, &outer_keygen_inputs, vk)
+        .unwrap();
+
+    assert_eq!(pk_hash(&pk), one_shot_pk_hash, "pk mismatch");
+    for other in [&vk_gate_config, &pk_gate_config] {
+        assert_eq!(
+            serde_json::to_value(other).unwrap(),
+            serde_json::to_value(&gate_config).unwrap()
+        );
+    }
+    assert_eq!(vk_break_points, break_points);
+    assert_eq!(pk_break_points, break_points);
+    assert_eq!(vk_num_instance, num_instance);
+    assert_eq!(pk_num_instance, num_instance);
+}
+
 /// Computes an outer circuit proof and checks in EVM. The
 /// proving key is generated from a config using default data,
 /// whereas the proof is generated from actual sample application
@@ -415,13 +1316,99 @@ fn outer_circuit_evm_check<'params, AS, P, V>(
     end_timer!(timer);
     check_expected_final_digest(&instances, expected_final_digest);
 
-    // Verify
+    // Verify natively, as the EVM verifier would
+    verify_outer_onchain_equivalent::<AS>(
+        keygen_inputs.outer_params,
+        outer_pk.get_vk(),
+        &proof,
+        &instances,
+    )
+    .expect("on-chain equivalent verification failed");
+    let mut bad_instances = instances.clone();
+    *bad_instances.last_mut().unwrap() += Fr::from(1);
+    assert!(
+        verify_outer_onchain_equivalent::<AS>(
+            keygen_inputs.outer_params,
+            outer_pk.get_vk(),
+            &proof,
+            &bad_instances,
+        )
+        .is_err(),
+        "proof accepted with modified final digest"
+    );
+
+    // Verify against the VK data extracted from the verifier
     let num_instance = vec![instances.len()];
     let verifier_yul = gen_outer_evm_verifier::<AS>(
         keygen_inputs.outer_params,
         outer_pk.get_vk(),
         num_instance,
     );
+    let onchain_vk = OnchainVerifyingKey::from_yul(&verifier_yul).unwrap();
+    assert_eq!(
+        onchain_vk,
+        OnchainVerifyingKey::new(
+            keygen_inputs.outer_params,
+            outer_pk.get_vk(),
+            instances.len(),
+        )
+    );
+    verify_outer_with_onchain_vk::<AS>(
+        keygen_inputs.outer_params,
+        outer_pk.get_vk(),
+        &onchain_vk,
+        &proof,
+        &instances,
+    )
+    .expect("verification against on-chain VK failed");
+    let mut bad_onchain_vk = onchain_vk;
+    bad_onchain_vk.preprocessed[0] =
+        (bad_onchain_vk.preprocessed[0] + G1Affine::generator()).to_affine();
+    assert!(
+        verify_outer_with_onchain_vk::<AS>(
+            keygen_inputs.outer_params,
+            outer_pk.get_vk(),
+            &bad_onchain_vk,
+            &proof,
+            &instances,
+        )
+        .is_err(),
+        "proof accepted with modified on-chain VK"
+    );
+
+    // Batch verify a mix of valid and invalid proofs
+    let mut bad_proof = proof.clone();
+    let mid = bad_proof.len() / 2;
+    bad_proof[mid] ^= 1;
+    let batch = vec![
+        (proof.clone(), instances.clone()),
+        (proof.clone(), bad_instances),
+        (proof.clone(), instances.clone()),
+        (bad_proof, instances.clone()),
+    ];
+    let limbs_config = &outer_config.outer_config;
+    assert_eq!(
+        batch_verify_outer::<V>(
+            keygen_inputs.outer_params,
+            outer_pk.get_vk(),
+            &batch[..1],
+            limbs_config.limb_bits,
+            limbs_config.num_limbs,
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        batch_verify_outer::<V>(
+            keygen_inputs.outer_params,
+            outer_pk.get_vk(),
+            &batch,
+            limbs_config.limb_bits,
+            limbs_config.num_limbs,
+        ),
+        Err(vec![1, 3])
+    );
+
+    // Verify
     let verifier_byte_code = compile_yul(&verifier_yul);
     println!(
         "Verifier contract length: {} bytes",
@@ -438,9 +1425,10 @@ fn outer_circuit_evm_check_shplonk() {
     let ubv_config = TINY_UBV_CONFIG;
     let keccak_config = TINY_KECCAK_CONFIG;
 
-    let outer_params = gen_srs(outer_config.outer_config.degree_bits);
-    let ubv_params = gen_srs(ubv_config.degree_bits);
-    let keccak_params = gen_srs(keccak_config.degree_bits);
+    let outer_params =
+        gen_deterministic_srs(outer_config.outer_config.degree_bits, 0);
+    let ubv_params = gen_deterministic_srs(ubv_config.degree_bits, 0);
+    let keccak_params = gen_deterministic_srs(keccak_config.degree_bits, 0);
     let outer_keygen_inputs =
         OuterKeygenInputs::new(&ubv_params, &keccak_params, &outer_params);
 
@@ -465,3 +1453,145 @@ fn outer_circuit_evm_check_shplonk() {
         break_points,
     );
 }
+
+/// Deploys `deployment_code` and calls it with the calldata for `instances`
+/// and `proof`.  Returns `true` if the call succeeded.
+fn evm_verify_succeeds(
+    deployment_code: Vec<u8>,
+    instances: Vec<Fr>,
+    proof: Vec<u8>,
+) -> bool {
+    let calldata = encode_calldata(&[instances], &proof);
+    let mut evm = ExecutorBuilder::default()
+        .with_gas_limit(u64::MAX.into())
+        .build();
+    let caller = Address::from_low_u64_be(0xfe);
+    let verifier = evm
+        .deploy(caller, deployment_code.into(), 0.into())
+        .address
+        .expect("deploy failed");
+    let result = evm.call_raw(caller, verifier, calldata.into(), 0.into());
+    !result.reverted
+}
+
+/// Computes SHPLONK and GWC outer proofs from the same proving key, and
+/// checks that each is accepted by the EVM verifier for its own scheme and
+/// rejected by the verifier for the other scheme.
+///
+/// Note: the circuit always aggregates SHPLONK BV/Keccak snarks, so `AS` is
+/// `SHPLONK` in both cases.  Only the outer proof's scheme differs.
+fn outer_circuit_scheme_mismatch_check<'params>(
+    outer_config: &UniversalOuterConfig,
+    keygen_inputs: &'params OuterKeygenInputs,
+    outer_pk: &'params ProvingKey<G1Affine>,
+    outer_gate_config: &OuterGateConfig,
+    break_points: MultiPhaseThreadBreakPoints,
+) {
+    let (outer_inputs, _) = outer_input_setup::<
+        ProverSHPLONK<Bn256>,
+        VerifierSHPLONK<Bn256>,
+    >(outer_config, keygen_inputs);
+
+    // Note: In practice this `set_environment` call is performed by `read_proving_key`.
+    outer_gate_config.set_environment();
+    let (shplonk_proof, shplonk_instances) = prove_outer::<
+        SHPLONK,
+        UniversalOuterCircuit,
+        ProverSHPLONK<Bn256>,
+        VerifierSHPLONK<Bn256>,
+    >(
+        outer_config,
+        outer_gate_config,
+        outer_pk,
+        break_points.clone(),
+        outer_inputs.clone(),
+        keygen_inputs.outer_params,
+    );
+    let (gwc_proof, gwc_instances) = prove_outer::<
+        SHPLONK,
+        UniversalOuterCircuit,
+        ProverGWC<Bn256>,
+        VerifierGWC<Bn256>,
+    >(
+        outer_config,
+        outer_gate_config,
+        outer_pk,
+        break_points,
+        outer_inputs,
+        keygen_inputs.outer_params,
+    );
+
+    let num_instance = vec![shplonk_instances.len()];
+    let shplonk_verifier = compile_yul(&gen_outer_evm_verifier::<SHPLONK>(
+        keygen_inputs.outer_params,
+        outer_pk.get_vk(),
+        num_instance.clone(),
+    ));
+    let gwc_verifier = compile_yul(&gen_outer_evm_verifier::<GWC>(
+        keygen_inputs.outer_params,
+        outer_pk.get_vk(),
+        num_instance,
+    ));
+
+    assert!(evm_verify_succeeds(
+        shplonk_verifier.clone(),
+        shplonk_instances.clone(),
+        shplonk_proof.clone()
+    ));
+    assert!(evm_verify_succeeds(
+        gwc_verifier.clone(),
+        gwc_instances.clone(),
+        gwc_proof.clone()
+    ));
+    assert!(
+        verify_outer_onchain_equivalent::<SHPLONK>(
+            keygen_inputs.outer_params,
+            outer_pk.get_vk(),
+            &gwc_proof,
+            &gwc_instances,
+        )
+        .is_err(),
+        "GWC proof accepted by native SHPLONK verifier"
+    );
+    assert!(
+        !evm_verify_succeeds(shplonk_verifier, gwc_instances, gwc_proof),
+        "GWC proof accepted by SHPLONK verifier"
+    );
+    assert!(
+        !evm_verify_succeeds(gwc_verifier, shplonk_instances, shplonk_proof),
+        "SHPLONK proof accepted by GWC verifier"
+    );
+}
+
+/// CMD: `cargo test --release --package upa-circuits --lib -- tests::universal_outer::outer_circuit_scheme_mismatch --exact --nocapture --include-ignored`
+#[ignore = "takes too long"]
+#[test]
+fn outer_circuit_scheme_mismatch() {
+    let outer_config = EVM_OUTER_CONFIG;
+    let ubv_config = TINY_UBV_CONFIG;
+    let keccak_config = TINY_KECCAK_CONFIG;
+
+    let outer_params =
+        gen_deterministic_srs(outer_config.outer_config.degree_bits, 0);
+    let ubv_params = gen_deterministic_srs(ubv_config.degree_bits, 0);
+    let keccak_params = gen_deterministic_srs(keccak_config.degree_bits, 0);
+    let outer_keygen_inputs =
+        OuterKeygenInputs::new(&ubv_params, &keccak_params, &outer_params);
+
+    let (pk, outer_gate_config, break_points, _) =
+        gen_outer_pk::<
+            SHPLONK,
+            UniversalOuterCircuit,
+            ProverSHPLONK<Bn256>,
+            VerifierSHPLONK<Bn256>,
+        >(&outer_config, &outer_keygen_inputs)
+        .unwrap();
+
+    outer_circuit_scheme_mismatch_check(
+        &outer_config,
+        &outer_keygen_inputs,
+        &pk,
+        &outer_gate_config,
+        break_points,
+    );
+}
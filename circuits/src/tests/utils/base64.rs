@@ -0,0 +1,107 @@
+use crate::{
+    batch_verify::common::{
+        native::unsafe_proof_generation::sample_proofs_inputs_vk,
+        types::{Proof, PublicInputs, VerificationKey},
+    },
+    utils::base64::Base64DecodeError,
+};
+use base64::prelude::{Engine, BASE64_STANDARD_NO_PAD};
+use halo2_base::halo2_proofs::halo2curves::{bn256::G2Affine, CurveAffine};
+use rand_core::{OsRng, RngCore};
+
+const NUM_SAMPLES: usize = 8;
+
+fn check_on_curve(proof: &Proof, vk: &VerificationKey) {
+    let g1_points = [proof.a, proof.c]
+        .into_iter()
+        .chain(proof.m.iter().copied())
+        .chain(proof.pok.iter().copied())
+        .chain([vk.alpha])
+        .chain(vk.s.iter().copied());
+    for point in g1_points {
+        assert!(bool::from(point.is_on_curve()), "G1 point not on curve");
+    }
+    let g2_points = [proof.b, vk.beta, vk.gamma, vk.delta]
+        .into_iter()
+        .chain(vk.h1.iter().copied())
+        .chain(vk.h2.iter().copied());
+    for point in g2_points {
+        assert!(bool::from(point.is_on_curve()), "G2 point not on curve");
+    }
+}
+
+/// CMD: `cargo test --package upa-circuits --lib -- tests::utils::base64::random_proofs_round_trip --exact --nocapture`
+#[test]
+fn random_proofs_round_trip() {
+    let mut rng = OsRng;
+    for _ in 0..NUM_SAMPLES {
+        let num_pub_inputs = 1 + (rng.next_u32() % 8) as usize;
+        let has_commitment = rng.next_u32() % 2 == 1;
+        let (proofs_and_inputs, vk) = sample_proofs_inputs_vk(
+            num_pub_inputs,
+            has_commitment,
+            2,
+            &mut rng,
+        );
+
+        let decoded_vk = VerificationKey::from_base64(&vk.to_base64())
+            .expect("failed to decode vk");
+        assert_eq!(vk, decoded_vk);
+
+        for (proof, inputs) in proofs_and_inputs {
+            let decoded_proof = Proof::from_base64(&proof.to_base64())
+                .expect("failed to decode proof");
+            assert_eq!(proof, decoded_proof);
+            check_on_curve(&decoded_proof, &decoded_vk);
+
+            let decoded_inputs = PublicInputs::from_base64(&inputs.to_base64())
+                .expect("failed to decode inputs");
+            assert_eq!(inputs, decoded_inputs);
+        }
+    }
+}
+
+/// CMD: `cargo test --package upa-circuits --lib -- tests::utils::base64::invalid_blobs_rejected --exact --nocapture`
+#[test]
+fn invalid_blobs_rejected() {
+    let proof = Proof::default_with_commitment(false);
+    let mut bytes = BASE64_STANDARD_NO_PAD
+        .decode(proof.to_base64())
+        .expect("invalid base64");
+
+    // Truncated
+    let truncated = BASE64_STANDARD_NO_PAD.encode(&bytes[..bytes.len() - 32]);
+    assert!(matches!(
+        Proof::from_base64(&truncated),
+        Err(Base64DecodeError::InvalidLength(_))
+    ));
+
+    // Not base64
+    assert!(matches!(
+        Proof::from_base64("not base64!"),
+        Err(Base64DecodeError::InvalidBase64(_))
+    ));
+
+    // Non-canonical field element (all 0xff)
+    bytes[..32].fill(0xff);
+    assert!(matches!(
+        Proof::from_base64(&BASE64_STANDARD_NO_PAD.encode(&bytes)),
+        Err(Base64DecodeError::InvalidFieldElement(0))
+    ));
+
+    // Off-curve point: (1, 1) is not on the curve.
+    bytes[..32].fill(0);
+    bytes[31] = 1;
+    bytes[32..64].fill(0);
+    bytes[63] = 1;
+    assert!(matches!(
+        Proof::from_base64(&BASE64_STANDARD_NO_PAD.encode(&bytes)),
+        Err(Base64DecodeError::NotOnCurve(0))
+    ));
+
+    // G2 generator survives a round trip
+    let vk = VerificationKey::default_with_length(2, true);
+    let decoded = VerificationKey::from_base64(&vk.to_base64())
+        .expect("failed to decode vk");
+    assert_eq!(decoded.beta, G2Affine::generator());
+}
@@ -0,0 +1,30 @@
+use crate::utils::cancellation::{CancellationToken, Cancelled};
+use core::{cell::Cell, iter};
+
+/// Checks that [`CancellationToken::collect`] returns all items if not
+/// cancelled, and returns `Err(Cancelled)` without pulling further items if
+/// cancelled after the k-th item.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::utils::cancellation::collect_cancelled_after_kth_item --exact --nocapture`
+#[test]
+fn collect_cancelled_after_kth_item() {
+    const NUM_ITEMS: usize = 8;
+    assert_eq!(
+        CancellationToken::new().collect(0..NUM_ITEMS),
+        Ok((0..NUM_ITEMS).collect())
+    );
+
+    for k in 1..=NUM_ITEMS {
+        let cancel = CancellationToken::new();
+        let pulled = Cell::new(0);
+        let items = iter::from_fn(|| {
+            pulled.set(pulled.get() + 1);
+            if pulled.get() == k {
+                cancel.cancel();
+            }
+            Some(pulled.get())
+        });
+        assert_eq!(cancel.collect(items), Err(Cancelled));
+        assert_eq!(pulled.get(), k, "item pulled after cancellation");
+    }
+}
@@ -1,4 +1,10 @@
-use crate::utils::field_elements_hex;
+use crate::{
+    batch_verify::common::{
+        native::json::JsonPublicInputs, types::PublicInputs,
+    },
+    keccak::utils::compute_proof_id,
+    utils::field_elements_hex,
+};
 use halo2_base::halo2_proofs::halo2curves::{bn256::Fr, group::ff::Field};
 use rand_core::OsRng;
 use serde::{Deserialize, Serialize};
@@ -23,3 +29,52 @@ fn test_ser_deser() {
     println!("field elements json: {reserialized}");
     assert_eq!(serialized, reserialized);
 }
+
+/// Checks that public inputs given as decimal strings and as EVM `uint256[]`
+/// hex strings decode to the same elements (and hence proof id), and that the
+/// hex form round-trips.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::utils::field_elements_hex::test_decimal_and_hex_inputs_proof_id --exact --nocapture`
+#[test]
+fn test_decimal_and_hex_inputs_proof_id() {
+    let decimal: JsonPublicInputs = serde_json::from_str(
+        r#"["0", "1", "255", "21888242871839275222246405745257275088548364400416034343698204186575808495616"]"#,
+    )
+    .unwrap();
+    let hex = [
+        "0x0000000000000000000000000000000000000000000000000000000000000000",
+        "0x0000000000000000000000000000000000000000000000000000000000000001",
+        "0xff",
+        "0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000000",
+    ];
+    let from_decimal = PublicInputs::from(&decimal);
+    let from_hex = PublicInputs::<Fr>::from_hex_vec(&hex).unwrap();
+    assert_eq!(from_decimal, from_hex);
+
+    let circuit_id = [0x5a; 32];
+    assert_eq!(
+        compute_proof_id(&circuit_id, &from_decimal.0),
+        compute_proof_id(&circuit_id, &from_hex.0)
+    );
+
+    // `to_hex_vec` gives the full 32-byte words.
+    let hex_vec = from_hex.to_hex_vec();
+    assert_eq!(hex_vec[0], hex[0]);
+    assert_eq!(hex_vec[3], hex[3]);
+    assert_eq!(
+        PublicInputs::from_hex_vec(hex_vec.as_slice()).unwrap(),
+        from_hex
+    );
+
+    // Non-prefixed, over-long and non-canonical strings are rejected.
+    assert!(PublicInputs::<Fr>::from_hex_vec(&["ff"]).is_err());
+    assert!(PublicInputs::<Fr>::from_hex_vec(&[format!(
+        "0x{}",
+        "0".repeat(65)
+    )])
+    .is_err());
+    assert!(PublicInputs::<Fr>::from_hex_vec(&[
+        "0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001"
+    ])
+    .is_err());
+}
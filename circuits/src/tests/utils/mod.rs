@@ -1,8 +1,20 @@
 use crate::{EccPrimeField, SafeCircuit};
-use halo2_base::halo2_proofs::halo2curves::CurveAffine;
+use halo2_base::halo2_proofs::{
+    dev::{MockProver, VerifyFailure},
+    halo2curves::{
+        bn256::{Fr, G1Affine},
+        CurveAffine,
+    },
+    plonk::{Circuit, ProvingKey},
+};
 use snark_verifier_sdk::CircuitExt;
+use std::{collections::BTreeSet, ops::Range};
 
+mod base64;
+mod cancellation;
 mod field_elements_hex;
+mod srs;
+mod upa_config;
 
 pub fn check_instance<'a, F, C, Circuit>(
     circuit: &Circuit,
@@ -18,3 +30,120 @@ where
     let expect_instance = Circuit::compute_instance(config, inputs);
     instances[0] == expect_instance
 }
+
+/// Asserts that the instance layout `segments` (see
+/// [`SafeCircuit::describe_instance`]) consists of non-empty, contiguous
+/// segments covering `0..instance_len`.
+pub fn check_instance_layout(
+    segments: &[(String, Range<usize>)],
+    instance_len: usize,
+) {
+    let mut end = 0;
+    for (name, range) in segments {
+        assert_eq!(range.start, end, "segment {name} is not contiguous");
+        assert!(range.end > range.start, "segment {name} is empty");
+        end = range.end;
+    }
+    assert_eq!(end, instance_len, "layout does not cover the instance");
+}
+
+/// Kind of a [`VerifyFailure`] reported by the `MockProver`, as checked by
+/// [`assert_mock_failure`].
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum FailureKind {
+    /// A lookup (e.g. a range check) is not satisfied
+    Lookup,
+    /// A copy constraint is not satisfied.  This includes the equality
+    /// constraints of `halo2-base` (`constrain_equal`, `assert_equal`) and
+    /// the constraints between advice and instance cells.
+    Permutation,
+    /// A constraint of the gate with the given name is not satisfied
+    ConstraintNotSatisfied(String),
+    /// Any other failure, e.g. an unassigned cell
+    Other,
+}
+
+impl From<&VerifyFailure> for FailureKind {
+    fn from(failure: &VerifyFailure) -> Self {
+        match failure {
+            VerifyFailure::Lookup { .. } => FailureKind::Lookup,
+            VerifyFailure::Permutation { .. } => FailureKind::Permutation,
+            VerifyFailure::ConstraintNotSatisfied { constraint, .. } => {
+                // The gate name is only exposed through the `Display` impl,
+                // as "Constraint <i> in gate <j> ('<name>')".
+                let constraint = constraint.to_string();
+                let gate = constraint
+                    .rsplit_once("('")
+                    .and_then(|(_, gate)| gate.strip_suffix("')"))
+                    .unwrap_or(&constraint);
+                FailureKind::ConstraintNotSatisfied(gate.to_string())
+            }
+            _ => FailureKind::Other,
+        }
+    }
+}
+
+/// Runs the `MockProver` on `circuit` and asserts that it is not satisfied,
+/// with failures of exactly the kinds in `expected`.  Each kind may be
+/// reported any number of times (e.g. once per offending row).
+pub fn assert_mock_failure<C: Circuit<Fr>>(
+    circuit: &C,
+    k: u32,
+    instances: Vec<Vec<Fr>>,
+    expected: &[FailureKind],
+) {
+    let failures = MockProver::run(k, circuit, instances)
+        .expect("Mock prover run failure")
+        .verify()
+        .expect_err("Expected circuit failure");
+    let kinds: BTreeSet<FailureKind> =
+        failures.iter().map(FailureKind::from).collect();
+    let expected: BTreeSet<FailureKind> = expected.iter().cloned().collect();
+    assert_eq!(
+        kinds,
+        expected,
+        "unexpected failure kinds ({} failures, first: {:?})",
+        failures.len(),
+        failures.first()
+    );
+}
+
+/// Writes `pk` (and its verifying key) using the `SafeCircuit` writers, reads
+/// them back with the corresponding readers and checks that the verifying key
+/// bytes are unchanged.
+pub fn check_key_round_trip<'a, Circuit>(
+    config: &Circuit::CircuitConfig,
+    gate_config: &Circuit::GateConfig,
+    pk: &ProvingKey<G1Affine>,
+) where
+    Circuit: SafeCircuit<'a, Fr, G1Affine>,
+{
+    let vk_bytes = {
+        let mut buf = Vec::new();
+        Circuit::write_verifying_key(pk.get_vk(), &mut buf)
+            .expect("failed to write vk");
+        buf
+    };
+    let vk = Circuit::read_verifying_key(gate_config, &mut vk_bytes.as_slice())
+        .expect("failed to read vk");
+    let mut read_vk_bytes = Vec::new();
+    Circuit::write_verifying_key(&vk, &mut read_vk_bytes)
+        .expect("failed to write vk");
+    assert_eq!(vk_bytes, read_vk_bytes, "vk round-trip mismatch");
+
+    let pk_bytes = {
+        let mut buf = Vec::new();
+        Circuit::write_proving_key(pk, &mut buf).expect("failed to write pk");
+        buf
+    };
+    let pk = Circuit::read_proving_key(
+        config,
+        gate_config,
+        &mut pk_bytes.as_slice(),
+    )
+    .expect("failed to read pk");
+    let mut pk_vk_bytes = Vec::new();
+    Circuit::write_verifying_key(pk.get_vk(), &mut pk_vk_bytes)
+        .expect("failed to write vk");
+    assert_eq!(vk_bytes, pk_vk_bytes, "pk round-trip mismatch");
+}
@@ -0,0 +1,73 @@
+use crate::utils::srs::{gen_deterministic_srs, srs_from_ptau, PtauError};
+use halo2_base::halo2_proofs::{
+    halo2curves::bn256::{Bn256, Fr, G1Affine},
+    poly::{
+        commitment::{Params, ParamsProver},
+        kzg::commitment::ParamsKZG,
+    },
+};
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use std::fs::File;
+
+/// `.ptau` file of maximum power 2, generated with tau = 7.  See the tests
+/// README.
+const PTAU_FIXTURE: &str = "src/tests/data/tau_7_power_2.ptau";
+
+fn srs_bytes(params: &ParamsKZG<Bn256>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    params.write(&mut bytes).unwrap();
+    bytes
+}
+
+/// CMD: `cargo test --package upa-circuits --lib -- tests::utils::srs::deterministic_srs --exact --nocapture`
+#[test]
+fn deterministic_srs() {
+    const K: u32 = 4;
+
+    let srs = gen_deterministic_srs(K, 1);
+    assert_eq!(srs.k(), K);
+    assert_eq!(srs.get_g()[0], G1Affine::generator());
+
+    // Same seed gives identical bytes, different seed does not.
+    assert_eq!(srs_bytes(&srs), srs_bytes(&gen_deterministic_srs(K, 1)));
+    assert_ne!(srs_bytes(&srs), srs_bytes(&gen_deterministic_srs(K, 2)));
+
+    // Seed 0 matches the default ChaCha20 seed.
+    let default_srs = ParamsKZG::<Bn256>::setup(
+        K,
+        ChaCha20Rng::from_seed(Default::default()),
+    );
+    assert_eq!(
+        srs_bytes(&gen_deterministic_srs(K, 0)),
+        srs_bytes(&default_srs)
+    );
+}
+
+/// CMD: `cargo test --package upa-circuits --lib -- tests::utils::srs::import_ptau --exact --nocapture`
+#[test]
+fn import_ptau() {
+    let mut ptau = File::open(PTAU_FIXTURE).unwrap();
+    let srs = srs_from_ptau(&mut ptau, 2).expect("failed to import ptau");
+    assert_eq!(srs.k(), 2);
+    assert_eq!(srs.get_g()[0], G1Affine::generator());
+
+    // The imported SRS (including the Lagrange basis) matches one generated
+    // directly from the same tau.
+    let expected = ParamsKZG::<Bn256>::unsafe_setup_with_s(2, Fr::from(7));
+    assert_eq!(srs_bytes(&srs), srs_bytes(&expected));
+
+    // Smaller degrees are truncated.
+    let mut ptau = File::open(PTAU_FIXTURE).unwrap();
+    let srs = srs_from_ptau(&mut ptau, 1).expect("failed to import ptau");
+    assert_eq!(srs.get_g().len(), 2);
+
+    // Degrees above the maximum power fail.
+    let mut ptau = File::open(PTAU_FIXTURE).unwrap();
+    assert!(matches!(
+        srs_from_ptau(&mut ptau, 3),
+        Err(PtauError::DegreeTooLarge {
+            degree_bits: 3,
+            max_power: 2
+        })
+    ));
+}
@@ -0,0 +1,336 @@
+use crate::{
+    batch_verify::{
+        common::chip::{WINDOW_BITS, WINDOW_BITS_G2_SUBGROUP_CHECK},
+        universal::types::UniversalBatchVerifierConfig,
+    },
+    keccak::{KeccakConfig, OutputMode},
+    outer::{universal::UniversalOuterCircuit, BatchVerifyOuterCircuit},
+    utils::upa_config::{UpaConfig, UpaConfigError, UpaConfigFieldDiff},
+    CircuitConfig, CircuitWithLimbsConfig,
+};
+
+fn upa_config() -> UpaConfig {
+    UpaConfig {
+        max_num_app_public_inputs: 8,
+        inner_batch_size: 4,
+        outer_batch_size: 2,
+        bv_config: CircuitWithLimbsConfig::from_degree_bits(21),
+        keccak_config: CircuitConfig {
+            degree_bits: 20,
+            lookup_bits: 19,
+        },
+        outer_config: CircuitWithLimbsConfig::from_degree_bits(22),
+        output_submission_id: true,
+        allow_commitments: true,
+        public_num_proof_ids: false,
+        include_vk_digest: false,
+        window_bits: WINDOW_BITS,
+        g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK,
+    }
+}
+
+/// CMD: `cargo test --package upa-circuits --lib -- tests::utils::upa_config::upa_config_validate --exact --nocapture`
+#[test]
+fn upa_config_validate() {
+    assert_eq!(upa_config().validate(), Ok(()));
+
+    let mut config = upa_config();
+    config.outer_batch_size = 0;
+    assert_eq!(
+        config.validate(),
+        Err(UpaConfigError::ZeroBatchSize("outer_batch_size"))
+    );
+
+    let mut config = upa_config();
+    config.window_bits = 0;
+    assert_eq!(
+        config.validate(),
+        Err(UpaConfigError::ZeroWindowBits("window_bits"))
+    );
+
+    // A zero G2 window deserialized from a config file is rejected here,
+    // rather than panicking when the UBV circuit is built.
+    let mut json = serde_json::to_value(upa_config()).unwrap();
+    json["g2_subgroup_window_bits"] = 0.into();
+    let config: UpaConfig = serde_json::from_value(json).unwrap();
+    assert_eq!(
+        config.validate(),
+        Err(UpaConfigError::ZeroWindowBits("g2_subgroup_window_bits"))
+    );
+
+    let mut config = upa_config();
+    config.bv_config.degree_bits = 29;
+    assert_eq!(
+        config.validate(),
+        Err(UpaConfigError::DegreeBitsOutOfRange {
+            field: "bv_config",
+            degree_bits: 29
+        })
+    );
+
+    let mut config = upa_config();
+    config.keccak_config.lookup_bits = 20;
+    assert_eq!(
+        config.validate(),
+        Err(UpaConfigError::LookupBitsTooLarge {
+            field: "keccak_config",
+            lookup_bits: 20,
+            degree_bits: 20
+        })
+    );
+
+    let mut config = upa_config();
+    config.outer_config.num_limbs = 4;
+    assert!(matches!(
+        config.validate(),
+        Err(UpaConfigError::Inconsistent {
+            field: "outer_config.num_limbs",
+            ..
+        })
+    ));
+
+    let mut config = upa_config();
+    config.public_num_proof_ids = true;
+    assert_eq!(config.validate(), Ok(()));
+    assert!(config.keccak_config().public_num_proof_ids);
+    config.output_submission_id = false;
+    assert_eq!(
+        config.validate(),
+        Err(UpaConfigError::Incompatible {
+            field: "public_num_proof_ids",
+            requires: "output_submission_id",
+        })
+    );
+
+    let mut config = upa_config();
+    config.include_vk_digest = true;
+    assert_eq!(
+        config.validate(),
+        Err(UpaConfigError::Incompatible {
+            field: "include_vk_digest",
+            requires: "output_submission_id to be unset",
+        })
+    );
+    config.output_submission_id = false;
+    assert_eq!(config.validate(), Ok(()));
+    assert!(config.keccak_config().include_vk_digest);
+}
+
+/// Checks that the [`UniversalBatchVerifierConfig`] derived from a
+/// [`UpaConfig`] matches the one built field by field from `bv_config`, and
+/// takes its `window_bits` and `g2_subgroup_window_bits` from the
+/// [`UpaConfig`].
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::utils::upa_config::ubv_config_from_upa_config --exact --nocapture`
+#[test]
+fn ubv_config_from_upa_config() {
+    let config = upa_config();
+    let ubv_config = UniversalBatchVerifierConfig::from(&config);
+    assert_eq!(
+        ubv_config,
+        UniversalBatchVerifierConfig::from_circuit_config(
+            &config.bv_config,
+            config.inner_batch_size,
+            config.max_num_app_public_inputs,
+        )
+    );
+    assert_eq!(ubv_config.circuit_config(), config.bv_config);
+
+    let config = UpaConfig {
+        window_bits: WINDOW_BITS + 1,
+        ..config
+    };
+    assert_eq!(
+        UniversalBatchVerifierConfig::from(&config).window_bits,
+        WINDOW_BITS + 1
+    );
+
+    let config = UpaConfig {
+        g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK + 1,
+        ..config
+    };
+    assert_eq!(
+        UniversalBatchVerifierConfig::from(&config).g2_subgroup_window_bits,
+        WINDOW_BITS_G2_SUBGROUP_CHECK + 1
+    );
+}
+
+/// Checks that [`UpaConfig::keccak_config`] is the keccak config of the
+/// outer circuit, and that manually constructed keccak configs which
+/// diverge from it are rejected by [`UpaConfig::check_keccak_config`].
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::utils::upa_config::keccak_config_from_upa_config --exact --nocapture`
+#[test]
+fn keccak_config_from_upa_config() {
+    let config = upa_config();
+    let keccak_config = config.keccak_config();
+    assert_eq!(keccak_config, KeccakConfig::from(&config));
+    assert_eq!(keccak_config, UniversalOuterCircuit::keccak_config(&config));
+    assert_eq!(keccak_config.output_mode, OutputMode::SubmissionId);
+    assert_eq!(config.check_keccak_config(&keccak_config), Ok(()));
+
+    let linear_keccak_config = KeccakConfig {
+        output_mode: OutputMode::Linear,
+        ..keccak_config
+    };
+    let err = config
+        .check_keccak_config(&linear_keccak_config)
+        .unwrap_err();
+    assert_eq!(err, UpaConfigError::KeccakConfigMismatch("output_mode"));
+    assert_eq!(
+        err.to_string(),
+        "keccak output_mode: differs from the keccak config derived from \
+         the UPA config"
+    );
+    assert_eq!(
+        UpaConfig {
+            output_submission_id: false,
+            ..config
+        }
+        .check_keccak_config(&linear_keccak_config),
+        Ok(())
+    );
+
+    assert_eq!(
+        config.check_keccak_config(&KeccakConfig {
+            public_num_proof_ids: true,
+            ..keccak_config
+        }),
+        Err(UpaConfigError::KeccakConfigMismatch("config"))
+    );
+
+    let err = config
+        .check_keccak_config(&KeccakConfig {
+            output_mode: OutputMode::Both,
+            ..keccak_config
+        })
+        .unwrap_err();
+    assert_eq!(
+        err,
+        UpaConfigError::Incompatible {
+            field: "output_mode",
+            requires: "a single final digest (linear or submission id)",
+        }
+    );
+}
+
+/// Checks that `allow_commitments` determines the flag of both the UBV and
+/// Keccak configs, and is set when absent from a config file.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::utils::upa_config::allow_commitments_from_upa_config --exact --nocapture`
+#[test]
+fn allow_commitments_from_upa_config() {
+    let config = UpaConfig {
+        allow_commitments: false,
+        ..upa_config()
+    };
+    assert_eq!(config.validate(), Ok(()));
+    assert!(!UniversalBatchVerifierConfig::from(&config).allow_commitments);
+    assert!(!config.keccak_config().allow_commitments);
+    assert_eq!(
+        upa_config().diff(&config),
+        vec![UpaConfigFieldDiff {
+            field: "allow_commitments",
+            this: "true".to_string(),
+            other: "false".to_string(),
+        }]
+    );
+
+    let mut json = serde_json::to_value(upa_config()).unwrap();
+    json.as_object_mut().unwrap().remove("allow_commitments");
+    let parsed: UpaConfig = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed, upa_config());
+}
+
+/// Checks [`UpaConfig::key_compatible_with`] and [`UpaConfig::diff`] for
+/// identical configs, configs whose files only differ cosmetically, and
+/// structurally different configs.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::utils::upa_config::upa_config_key_compatible_with --exact --nocapture`
+#[test]
+fn upa_config_key_compatible_with() {
+    let config = upa_config();
+    assert!(config.key_compatible_with(&config));
+    assert_eq!(config.diff(&config), vec![]);
+
+    // Same config, with reordered fields and different formatting.
+    let reformatted: UpaConfig = serde_json::from_str(
+        r#"{"output_submission_id":true,
+            "keccak_config":{"lookup_bits":19,"degree_bits":20},
+            "outer_config":{"num_limbs":3,"limb_bits":88,
+                            "lookup_bits":21,"degree_bits":22},
+            "bv_config":{"degree_bits":21,"lookup_bits":20,
+                         "limb_bits":88,"num_limbs":3},
+            "outer_batch_size":2,"inner_batch_size":4,
+            "max_num_app_public_inputs":8}"#,
+    )
+    .unwrap();
+    assert!(config.key_compatible_with(&reformatted));
+    assert_eq!(config.diff(&reformatted), vec![]);
+
+    let mut other = upa_config();
+    other.keccak_config.lookup_bits = 18;
+    other.output_submission_id = false;
+    assert!(!config.key_compatible_with(&other));
+    assert!(!other.key_compatible_with(&config));
+    let diff = config.diff(&other);
+    assert_eq!(
+        diff,
+        vec![
+            UpaConfigFieldDiff {
+                field: "keccak_config.lookup_bits",
+                this: "19".to_string(),
+                other: "18".to_string(),
+            },
+            UpaConfigFieldDiff {
+                field: "output_submission_id",
+                this: "true".to_string(),
+                other: "false".to_string(),
+            },
+        ]
+    );
+    assert_eq!(diff[0].to_string(), "keccak_config.lookup_bits: 19 -> 18");
+
+    let mut other = upa_config();
+    other.bv_config.degree_bits = 20;
+    other.bv_config.lookup_bits = 19;
+    assert!(!config.key_compatible_with(&other));
+    assert_eq!(config.diff(&other).len(), 2);
+}
+
+/// Checks that the [`UpaConfig`] display includes the derived totals and
+/// sub-circuit configs.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::utils::upa_config::upa_config_display --exact --nocapture`
+#[test]
+fn upa_config_display() {
+    let display = upa_config().to_string();
+    assert!(display.contains("Total proofs (inner * outer): 8\n"));
+    assert!(display.contains("UBV config:\n"));
+    assert!(display.contains("Keccak config:\n"));
+    assert!(display.contains("Outer config:\n"));
+}
+
+/// Checks that a `CircuitWithLimbsConfig` written with `to_file` is read
+/// back unchanged by `from_file`.
+///
+/// CMD: `cargo test --package upa-circuits --lib -- tests::utils::upa_config::circuit_with_limbs_config_file_round_trip --exact --nocapture`
+#[test]
+fn circuit_with_limbs_config_file_round_trip() {
+    let config = CircuitWithLimbsConfig {
+        degree_bits: 20,
+        lookup_bits: 18,
+        limb_bits: 90,
+        num_limbs: 3,
+    };
+    let path = std::env::temp_dir().join(format!(
+        "circuit_with_limbs_config_test_{}.json",
+        std::process::id()
+    ));
+    let path = path.to_str().expect("non utf-8 path");
+
+    config.to_file(path);
+    let read_config = CircuitWithLimbsConfig::from_file(path);
+    std::fs::remove_file(path).expect("failed to remove config file");
+    assert_eq!(read_config, config);
+}
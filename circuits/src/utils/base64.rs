@@ -1,4 +1,16 @@
+//! Base64 serde helpers for byte vectors, and a compact base64 encoding of
+//! proofs, verification keys and public inputs for transport in JSON fields.
+
+use crate::batch_verify::common::types::{
+    Proof, PublicInputs, VerificationKey,
+};
 use base64::prelude::{Engine, BASE64_STANDARD_NO_PAD};
+use core::fmt;
+use halo2_base::halo2_proofs::halo2curves::{
+    bn256::{Fq2, Fr, G1Affine, G2Affine},
+    group::ff::PrimeField,
+    CurveAffine,
+};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 pub fn serialize<S: Serializer>(v: &Vec<u8>, s: S) -> Result<S::Ok, S::Error> {
@@ -14,3 +26,271 @@ pub fn deserialize<'de, D: Deserializer<'de>>(
         .decode(base64.as_bytes())
         .map_err(serde::de::Error::custom)
 }
+
+/// Size in bytes of each encoded base field / scalar field element.
+const WORD_BYTES: usize = 32;
+
+/// Size in bytes of an encoded G1 point.
+const G1_BYTES: usize = 2 * WORD_BYTES;
+
+/// Size in bytes of an encoded G2 point.
+const G2_BYTES: usize = 4 * WORD_BYTES;
+
+/// Error returned when decoding a base64 blob.
+#[derive(Debug)]
+pub enum Base64DecodeError {
+    /// The string is not valid (unpadded, standard alphabet) base64.
+    InvalidBase64(base64::DecodeError),
+    /// The decoded bytes have an unexpected length.
+    InvalidLength(usize),
+    /// A 32-byte word is not a canonical field element encoding.
+    InvalidFieldElement(usize),
+    /// The point at the given byte offset is not on the curve.
+    NotOnCurve(usize),
+}
+
+impl fmt::Display for Base64DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Base64DecodeError::InvalidBase64(e) => write!(f, "{e}"),
+            Base64DecodeError::InvalidLength(len) => {
+                write!(f, "unexpected length {len} bytes")
+            }
+            Base64DecodeError::InvalidFieldElement(offset) => {
+                write!(f, "invalid field element at byte {offset}")
+            }
+            Base64DecodeError::NotOnCurve(offset) => {
+                write!(f, "point at byte {offset} is not on the curve")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Base64DecodeError {}
+
+/// Appends the big-endian encoding of `f` to `bytes`.
+fn write_field_element<F: PrimeField<Repr = [u8; 32]>>(
+    bytes: &mut Vec<u8>,
+    f: &F,
+) {
+    let mut repr = f.to_repr();
+    repr.reverse();
+    bytes.extend_from_slice(&repr);
+}
+
+fn write_g1(bytes: &mut Vec<u8>, point: &G1Affine) {
+    write_field_element(bytes, &point.x);
+    write_field_element(bytes, &point.y);
+}
+
+/// G2 coordinates are written in the same order as the JSON format, namely
+/// `x.c0, x.c1, y.c0, y.c1`.
+fn write_g2(bytes: &mut Vec<u8>, point: &G2Affine) {
+    write_field_element(bytes, &point.x.c0);
+    write_field_element(bytes, &point.x.c1);
+    write_field_element(bytes, &point.y.c0);
+    write_field_element(bytes, &point.y.c1);
+}
+
+/// Sequential reader over a decoded blob, which tracks the offset for error
+/// reporting.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.offset
+    }
+
+    fn read_field_element<F: PrimeField<Repr = [u8; 32]>>(
+        &mut self,
+    ) -> Result<F, Base64DecodeError> {
+        let offset = self.offset;
+        let word = self
+            .bytes
+            .get(offset..offset + WORD_BYTES)
+            .ok_or(Base64DecodeError::InvalidLength(self.bytes.len()))?;
+        let mut repr = [0u8; WORD_BYTES];
+        repr.copy_from_slice(word);
+        repr.reverse();
+        self.offset += WORD_BYTES;
+        Option::from(F::from_repr(repr))
+            .ok_or(Base64DecodeError::InvalidFieldElement(offset))
+    }
+
+    fn read_g1(&mut self) -> Result<G1Affine, Base64DecodeError> {
+        let offset = self.offset;
+        let x = self.read_field_element()?;
+        let y = self.read_field_element()?;
+        Option::from(G1Affine::from_xy(x, y))
+            .ok_or(Base64DecodeError::NotOnCurve(offset))
+    }
+
+    fn read_g2(&mut self) -> Result<G2Affine, Base64DecodeError> {
+        let offset = self.offset;
+        let x = Fq2 {
+            c0: self.read_field_element()?,
+            c1: self.read_field_element()?,
+        };
+        let y = Fq2 {
+            c0: self.read_field_element()?,
+            c1: self.read_field_element()?,
+        };
+        Option::from(G2Affine::from_xy(x, y))
+            .ok_or(Base64DecodeError::NotOnCurve(offset))
+    }
+
+    /// Reads a 32-byte big-endian word holding a length.
+    fn read_len(&mut self) -> Result<usize, Base64DecodeError> {
+        let len: Fr = self.read_field_element()?;
+        let repr = len.to_repr();
+        if repr[8..].iter().any(|b| *b != 0) {
+            return Err(Base64DecodeError::InvalidLength(self.bytes.len()));
+        }
+        Ok(u64::from_le_bytes(repr[..8].try_into().unwrap()) as usize)
+    }
+}
+
+fn decode(s: &str) -> Result<Vec<u8>, Base64DecodeError> {
+    BASE64_STANDARD_NO_PAD
+        .decode(s.trim().as_bytes())
+        .map_err(Base64DecodeError::InvalidBase64)
+}
+
+impl Proof {
+    /// Encodes `self` as `a || b || c || m || pok`, with each coordinate as a
+    /// 32-byte big-endian word, in unpadded base64.  The number of commitment
+    /// points is implied by the length.
+    pub fn to_base64(&self) -> String {
+        assert_eq!(self.m.len(), self.pok.len(), "Inconsistent m, pok");
+        let mut bytes = Vec::with_capacity(
+            2 * G1_BYTES + G2_BYTES + 2 * self.m.len() * G1_BYTES,
+        );
+        write_g1(&mut bytes, &self.a);
+        write_g2(&mut bytes, &self.b);
+        write_g1(&mut bytes, &self.c);
+        self.m.iter().for_each(|m| write_g1(&mut bytes, m));
+        self.pok.iter().for_each(|pok| write_g1(&mut bytes, pok));
+        BASE64_STANDARD_NO_PAD.encode(bytes)
+    }
+
+    /// Decodes a proof encoded with [`Proof::to_base64`], checking that all
+    /// points are on the curve.
+    pub fn from_base64(s: &str) -> Result<Self, Base64DecodeError> {
+        let bytes = decode(s)?;
+        let fixed_len = 2 * G1_BYTES + G2_BYTES;
+        if bytes.len() < fixed_len
+            || (bytes.len() - fixed_len) % (2 * G1_BYTES) != 0
+        {
+            return Err(Base64DecodeError::InvalidLength(bytes.len()));
+        }
+        let num_commitments = (bytes.len() - fixed_len) / (2 * G1_BYTES);
+        let mut reader = Reader::new(&bytes);
+        Ok(Proof {
+            a: reader.read_g1()?,
+            b: reader.read_g2()?,
+            c: reader.read_g1()?,
+            m: (0..num_commitments)
+                .map(|_| reader.read_g1())
+                .collect::<Result<_, _>>()?,
+            pok: (0..num_commitments)
+                .map(|_| reader.read_g1())
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl VerificationKey {
+    /// Encodes `self` as `alpha || beta || gamma || delta || len(s) || s ||
+    /// h1 || h2`, with each coordinate (and `len(s)`) as a 32-byte big-endian
+    /// word, in unpadded base64.  The number of commitment keys is implied by
+    /// the length.
+    pub fn to_base64(&self) -> String {
+        assert_eq!(self.h1.len(), self.h2.len(), "Inconsistent h1, h2");
+        let mut bytes = Vec::with_capacity(
+            G1_BYTES
+                + 3 * G2_BYTES
+                + WORD_BYTES
+                + self.s.len() * G1_BYTES
+                + 2 * self.h1.len() * G2_BYTES,
+        );
+        write_g1(&mut bytes, &self.alpha);
+        write_g2(&mut bytes, &self.beta);
+        write_g2(&mut bytes, &self.gamma);
+        write_g2(&mut bytes, &self.delta);
+        write_field_element(&mut bytes, &Fr::from(self.s.len() as u64));
+        self.s.iter().for_each(|s| write_g1(&mut bytes, s));
+        self.h1.iter().for_each(|h1| write_g2(&mut bytes, h1));
+        self.h2.iter().for_each(|h2| write_g2(&mut bytes, h2));
+        BASE64_STANDARD_NO_PAD.encode(bytes)
+    }
+
+    /// Decodes a verification key encoded with
+    /// [`VerificationKey::to_base64`], checking that all points are on the
+    /// curve.
+    pub fn from_base64(s: &str) -> Result<Self, Base64DecodeError> {
+        let bytes = decode(s)?;
+        let mut reader = Reader::new(&bytes);
+        let alpha = reader.read_g1()?;
+        let beta = reader.read_g2()?;
+        let gamma = reader.read_g2()?;
+        let delta = reader.read_g2()?;
+        let num_s = reader.read_len()?;
+        if reader.remaining() / G1_BYTES < num_s {
+            return Err(Base64DecodeError::InvalidLength(bytes.len()));
+        }
+        let s = (0..num_s)
+            .map(|_| reader.read_g1())
+            .collect::<Result<_, _>>()?;
+        if reader.remaining() % (2 * G2_BYTES) != 0 {
+            return Err(Base64DecodeError::InvalidLength(bytes.len()));
+        }
+        let num_commitments = reader.remaining() / (2 * G2_BYTES);
+        let h1 = (0..num_commitments)
+            .map(|_| reader.read_g2())
+            .collect::<Result<_, _>>()?;
+        let h2 = (0..num_commitments)
+            .map(|_| reader.read_g2())
+            .collect::<Result<_, _>>()?;
+        Ok(VerificationKey {
+            alpha,
+            beta,
+            gamma,
+            delta,
+            s,
+            h1,
+            h2,
+        })
+    }
+}
+
+impl PublicInputs {
+    /// Encodes `self` as a sequence of 32-byte big-endian words, in unpadded
+    /// base64.
+    pub fn to_base64(&self) -> String {
+        let mut bytes = Vec::with_capacity(self.0.len() * WORD_BYTES);
+        self.0
+            .iter()
+            .for_each(|f| write_field_element(&mut bytes, f));
+        BASE64_STANDARD_NO_PAD.encode(bytes)
+    }
+
+    /// Decodes public inputs encoded with [`PublicInputs::to_base64`].
+    pub fn from_base64(s: &str) -> Result<Self, Base64DecodeError> {
+        let bytes = decode(s)?;
+        if bytes.len() % WORD_BYTES != 0 {
+            return Err(Base64DecodeError::InvalidLength(bytes.len()));
+        }
+        let mut reader = Reader::new(&bytes);
+        let inputs = (0..bytes.len() / WORD_BYTES)
+            .map(|_| reader.read_field_element())
+            .collect::<Result<_, _>>()?;
+        Ok(PublicInputs(inputs))
+    }
+}
@@ -0,0 +1,61 @@
+//! Helpers for benchmarking the assignment of UBV batch entries, serially on
+//! a single [`Context`](halo2_base::Context) versus in parallel across one
+//! `Context` per entry.
+
+use crate::{
+    batch_verify::{
+        common::chip::BatchVerifierChip,
+        universal::{
+            chip::UniversalBatchVerifierChip,
+            types::{BatchEntries, UniversalBatchVerifierConfig},
+        },
+    },
+    CircuitWithLimbsConfig,
+};
+use halo2_base::{
+    gates::{builder::GateThreadBuilder, RangeChip},
+    halo2_proofs::halo2curves::bn256::Fr,
+};
+use halo2_ecc::bn254::FpChip;
+
+/// Inner batch size used by the batch entries assignment benchmark
+pub const ASSIGN_BATCH_ENTRIES_INNER_BATCH_SIZE: u32 = 16;
+
+/// Max number of public inputs used by the batch entries assignment benchmark
+pub const ASSIGN_BATCH_ENTRIES_NUM_PUBLIC_INPUTS: u32 = 16;
+
+/// Degree bits used by the batch entries assignment benchmark. Only affects
+/// the lookup bits, since the circuit is never laid out.
+const ASSIGN_BATCH_ENTRIES_DEGREE_BITS: u32 = 20;
+
+/// Returns the [`UniversalBatchVerifierConfig`] for the batch entries
+/// assignment benchmark.
+pub fn assign_batch_entries_config() -> UniversalBatchVerifierConfig {
+    UniversalBatchVerifierConfig::from_circuit_config(
+        &CircuitWithLimbsConfig::from_degree_bits(
+            ASSIGN_BATCH_ENTRIES_DEGREE_BITS,
+        ),
+        ASSIGN_BATCH_ENTRIES_INNER_BATCH_SIZE,
+        ASSIGN_BATCH_ENTRIES_NUM_PUBLIC_INPUTS,
+    )
+}
+
+/// Assigns `entries` in a new mock [`GateThreadBuilder`], in parallel if
+/// `parallel` is `true` and serially otherwise. Returns the builder.
+pub fn assign_batch_entries(
+    config: &UniversalBatchVerifierConfig,
+    entries: &BatchEntries<Fr>,
+    parallel: bool,
+) -> GateThreadBuilder<Fr> {
+    let mut builder = GateThreadBuilder::<Fr>::mock();
+    let range = RangeChip::<Fr>::default(config.lookup_bits);
+    let fp_chip = FpChip::new(&range, config.limb_bits, config.num_limbs);
+    let bv_chip = BatchVerifierChip::new(&fp_chip);
+    let chip = UniversalBatchVerifierChip::new(&bv_chip);
+    if parallel {
+        chip.assign_batch_entries_parallel(&mut builder, entries);
+    } else {
+        chip.assign_batch_entries(builder.main(0), entries);
+    }
+    builder
+}
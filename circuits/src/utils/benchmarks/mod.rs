@@ -15,6 +15,11 @@ use std::{
     io::{BufWriter, Write},
 };
 
+pub mod batch_entries;
+pub mod msm;
+pub mod no_commitment;
+pub mod window_bits;
+
 pub const PROOF_BATCH_FILE: &str = "src/tests/data/proof_batch_4_pi.json";
 pub const VK_FILE: &str = "src/tests/data/vk.json";
 pub const CONTRACT_BYTE_LIMIT: usize = 24576;
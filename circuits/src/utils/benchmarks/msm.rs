@@ -0,0 +1,76 @@
+//! Helpers for comparing the [`MsmBackend`]s used by
+//! [`BatchVerifierChip::scale_pairs`].
+
+use crate::{
+    batch_verify::common::{
+        chip::BatchVerifierChip,
+        ecc::{get_assigned_value_g1point, G1Point, G2Point},
+        msm::MsmBackend,
+    },
+    utils::reduced::FromReduced,
+    CircuitWithLimbsConfig,
+};
+use halo2_base::{
+    gates::{builder::GateThreadBuilder, RangeChip},
+    halo2_proofs::halo2curves::{
+        bn256::{Fr, G1Affine, G2Affine},
+        group::{ff::Field, prime::PrimeCurveAffine, Curve},
+    },
+};
+use halo2_ecc::bn254::FpChip;
+use rand::RngCore;
+
+/// Number of scalar multiplications in the MSM benchmark.
+pub const MSM_BATCH_SIZE: usize = 32;
+
+/// Degree bits used by the MSM benchmark.  Only affects the lookup bits,
+/// since the circuit is never laid out.
+const MSM_DEGREE_BITS: u32 = 20;
+
+/// Samples `num` random G1 points and scalars.
+pub fn sample_points_and_scalars<R: RngCore>(
+    num: usize,
+    rng: &mut R,
+) -> (Vec<G1Affine>, Vec<Fr>) {
+    (0..num)
+        .map(|_| {
+            let point =
+                (G1Affine::generator() * Fr::random(&mut *rng)).to_affine();
+            (point, Fr::random(&mut *rng))
+        })
+        .unzip()
+}
+
+/// Assigns `points` and `scalars` and computes `scalars[i] * points[i]` with
+/// [`BatchVerifierChip::scale_pairs`], using `msm_backend`.  Returns the
+/// values of the resulting assigned points.
+pub fn scale_points(
+    msm_backend: &dyn MsmBackend<Fr>,
+    points: &[G1Affine],
+    scalars: &[Fr],
+) -> Vec<G1Affine> {
+    let config = CircuitWithLimbsConfig::from_degree_bits(MSM_DEGREE_BITS);
+    let mut builder = GateThreadBuilder::<Fr>::mock();
+    let range = RangeChip::<Fr>::default(config.lookup_bits);
+    let fp_chip = FpChip::new(&range, config.limb_bits, config.num_limbs);
+    let chip = BatchVerifierChip::new(&fp_chip).with_msm_backend(msm_backend);
+    let ctx = builder.main(0);
+
+    let g2 = G2Point::from_reduced(
+        &chip.assign_g2_reduced(ctx, G2Affine::generator()),
+    );
+    let pairs: Vec<_> = points
+        .iter()
+        .map(|point| {
+            let g1 =
+                G1Point::from_reduced(&chip.assign_g1_reduced(ctx, *point));
+            (g1, g2.clone())
+        })
+        .collect();
+    let scalars = ctx.assign_witnesses(scalars.iter().copied());
+
+    chip.scale_pairs(ctx, &scalars, &pairs)
+        .iter()
+        .map(|(g1, _)| get_assigned_value_g1point(&fp_chip, g1))
+        .collect()
+}
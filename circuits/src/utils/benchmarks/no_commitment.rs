@@ -0,0 +1,75 @@
+//! Helpers for measuring the cost saved by the universal batch verifier and
+//! keccak circuits when commitments are not allowed.
+
+use crate::{
+    batch_verify::universal::types::{
+        DomainTagVersion, UniversalBatchVerifierConfig,
+    },
+    keccak::{
+        inputs::KeccakCircuitInputs, KeccakCircuit, KeccakConfig, OutputMode,
+        DEFAULT_MAX_COMMITMENTS, LIMB_BITS, NUM_LIMBS,
+    },
+    CircuitWithLimbsConfig, SafeCircuit,
+};
+use halo2_base::halo2_proofs::halo2curves::bn256::{Fr, G1Affine};
+
+/// Inner batch size used by the no-commitment benchmark
+pub const NO_COMMITMENT_INNER_BATCH_SIZE: u32 = 8;
+
+/// Max number of public inputs used by the no-commitment benchmark
+pub const NO_COMMITMENT_NUM_PUBLIC_INPUTS: u32 = 16;
+
+/// Degree bits used by the no-commitment benchmark. Only affects the lookup
+/// bits, since the circuits are never laid out.
+const NO_COMMITMENT_DEGREE_BITS: u32 = 20;
+
+/// Returns the [`UniversalBatchVerifierConfig`] for the no-commitment
+/// benchmark.
+pub fn no_commitment_ubv_config(
+    allow_commitments: bool,
+) -> UniversalBatchVerifierConfig {
+    UniversalBatchVerifierConfig {
+        allow_commitments,
+        ..UniversalBatchVerifierConfig::from_circuit_config(
+            &CircuitWithLimbsConfig::from_degree_bits(
+                NO_COMMITMENT_DEGREE_BITS,
+            ),
+            NO_COMMITMENT_INNER_BATCH_SIZE,
+            NO_COMMITMENT_NUM_PUBLIC_INPUTS,
+        )
+    }
+}
+
+/// Returns the [`KeccakConfig`] for the no-commitment benchmark, for a
+/// single batch.
+pub fn no_commitment_keccak_config(allow_commitments: bool) -> KeccakConfig {
+    KeccakConfig {
+        degree_bits: NO_COMMITMENT_DEGREE_BITS,
+        num_app_public_inputs: NO_COMMITMENT_NUM_PUBLIC_INPUTS,
+        inner_batch_size: NO_COMMITMENT_INNER_BATCH_SIZE,
+        outer_batch_size: 1,
+        lookup_bits: NO_COMMITMENT_DEGREE_BITS as usize - 1,
+        output_mode: OutputMode::Linear,
+        max_commitments: DEFAULT_MAX_COMMITMENTS,
+        public_num_proof_ids: false,
+        domain_tag_version: DomainTagVersion::default(),
+        max_rows_per_round: None,
+        include_vk_digest: false,
+        unusable_rows: None,
+        limb_bits: LIMB_BITS,
+        num_limbs: NUM_LIMBS,
+        domain_separated_tree: false,
+        allow_commitments,
+    }
+}
+
+/// Returns the number of keccak permutations (which determine the number of
+/// keccak rows) used by the keccak circuit for `config` and `inputs`.
+pub fn keccak_num_perms(
+    config: &KeccakConfig,
+    inputs: &KeccakCircuitInputs<Fr>,
+) -> usize {
+    KeccakCircuit::<Fr, G1Affine>::mock(config, inputs)
+        .keccak_chip()
+        .total_keccak_perms()
+}
@@ -0,0 +1,85 @@
+//! Helpers for comparing the cost of the universal batch verifier circuit
+//! for different scalar multiplication window sizes.
+
+use crate::{
+    batch_verify::{
+        common::chip::WINDOW_BITS,
+        universal::{
+            types::{BatchEntries, UniversalBatchVerifierConfig},
+            universal_batch_verify_circuit,
+        },
+    },
+    utils::advice_cell_count,
+    CircuitWithLimbsConfig,
+};
+use halo2_base::{
+    gates::{builder::GateThreadBuilder, RangeChip},
+    halo2_proofs::halo2curves::bn256::Fr,
+};
+use halo2_ecc::bn254::FpChip;
+
+/// Window sizes compared by the window bits benchmark
+pub const WINDOW_BITS_RANGE: [usize; 3] = [3, 4, 5];
+
+/// Window sizes of the G2 subgroup check compared by the G2 subgroup window
+/// bits benchmark
+pub const G2_SUBGROUP_WINDOW_BITS_RANGE: [usize; 3] = [2, 3, 4];
+
+/// Inner batch size used by the window bits benchmark
+pub const WINDOW_BITS_INNER_BATCH_SIZE: u32 = 8;
+
+/// Max number of public inputs used by the window bits benchmark
+pub const WINDOW_BITS_NUM_PUBLIC_INPUTS: u32 = 16;
+
+/// Degree bits used by the window bits benchmark. Only affects the lookup
+/// bits, since the circuit is never laid out.
+const WINDOW_BITS_DEGREE_BITS: u32 = 20;
+
+/// Returns the [`UniversalBatchVerifierConfig`] for the window bits
+/// benchmark, using windows of `window_bits` bits.
+pub fn window_bits_config(window_bits: usize) -> UniversalBatchVerifierConfig {
+    UniversalBatchVerifierConfig {
+        window_bits,
+        ..UniversalBatchVerifierConfig::from_circuit_config(
+            &CircuitWithLimbsConfig::from_degree_bits(WINDOW_BITS_DEGREE_BITS),
+            WINDOW_BITS_INNER_BATCH_SIZE,
+            WINDOW_BITS_NUM_PUBLIC_INPUTS,
+        )
+    }
+}
+
+/// Returns the [`UniversalBatchVerifierConfig`] for the G2 subgroup window
+/// bits benchmark, using windows of `g2_subgroup_window_bits` bits for the
+/// G2 subgroup check (and the default window size elsewhere).
+pub fn g2_subgroup_window_bits_config(
+    g2_subgroup_window_bits: usize,
+) -> UniversalBatchVerifierConfig {
+    UniversalBatchVerifierConfig {
+        g2_subgroup_window_bits,
+        ..window_bits_config(WINDOW_BITS)
+    }
+}
+
+/// Runs witness generation of the universal batch verifier circuit for
+/// `entries` in a new mock [`GateThreadBuilder`]. Returns the builder.
+pub fn ubv_witness_gen(
+    config: &UniversalBatchVerifierConfig,
+    entries: &BatchEntries<Fr>,
+) -> GateThreadBuilder<Fr> {
+    let mut builder = GateThreadBuilder::<Fr>::mock();
+    let range = RangeChip::<Fr>::default(config.lookup_bits);
+    let fp_chip = FpChip::new(&range, config.limb_bits, config.num_limbs);
+    universal_batch_verify_circuit(&mut builder, &fp_chip, entries, config);
+    builder
+}
+
+/// Returns the total number of advice cells used by the universal batch
+/// verifier circuit for `entries`.
+pub fn ubv_advice_cell_count(
+    config: &UniversalBatchVerifierConfig,
+    entries: &BatchEntries<Fr>,
+) -> usize {
+    advice_cell_count(&ubv_witness_gen(config, entries))
+        .into_iter()
+        .sum()
+}
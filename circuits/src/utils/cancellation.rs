@@ -0,0 +1,67 @@
+//! Cooperative cancellation of long-running circuit construction.
+
+use core::fmt;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Error returned when circuit construction is aborted because its
+/// [`CancellationToken`] was cancelled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// A "should-cancel" flag shared between the caller and a long-running
+/// operation, which checks it periodically.  Clones refer to the same flag.
+/// The default token is not cancelled.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests cancellation of any operation holding this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns `Err(Cancelled)` if cancellation has been requested.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        match self.is_cancelled() {
+            true => Err(Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    /// Collects `items`, checking for cancellation before pulling each one.
+    /// If cancellation is requested, the remaining items are not pulled and
+    /// `Err(Cancelled)` is returned, so a partial set is never returned.
+    pub fn collect<T>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+    ) -> Result<Vec<T>, Cancelled> {
+        let mut items = items.into_iter();
+        let mut collected = Vec::new();
+        loop {
+            self.check()?;
+            match items.next() {
+                Some(item) => collected.push(item),
+                None => return Ok(collected),
+            }
+        }
+    }
+}
@@ -3,13 +3,17 @@
 use crate::{
     batch_verify::common::ecc::{G1InputPoint, G2InputPoint},
     utils::hashing::FieldElementRepresentation,
-    EccPrimeField,
+    EccPrimeField, DEFAULT_LIMB_BITS, DEFAULT_NUM_LIMBS,
 };
+use core::fmt;
 use ethers_core::utils::keccak256;
 use halo2_base::{
     halo2_proofs::{
         arithmetic::CurveAffine,
-        halo2curves::bn256::{Fq, Fq2, G1Affine, G2Affine},
+        halo2curves::{
+            bn256::{Fq, Fq2, Fr, G1Affine, G2Affine, G1},
+            group::cofactor::CofactorGroup,
+        },
     },
     utils::{decompose_biguint, fe_to_biguint, CurveAffineExt},
     AssignedValue,
@@ -36,6 +40,53 @@ where
     F::from_bytes_wide(&new_input_bytes)
 }
 
+/// Reason for which coordinates do not represent a valid commitment point.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CommitmentPointError {
+    /// The coordinates `(x, y)` do not satisfy the curve equation.
+    NotOnCurve { x: Fq, y: Fq },
+    /// The point is on the curve, but not in the prime-order subgroup.
+    NotInSubgroup { x: Fq, y: Fq },
+    /// The 32 bytes of a coordinate are not a canonical Fq element.
+    NonCanonicalCoordinate([u8; 32]),
+}
+
+impl fmt::Display for CommitmentPointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommitmentPointError::NotOnCurve { x, y } => {
+                write!(f, "({x:?}, {y:?}) is not on the curve")
+            }
+            CommitmentPointError::NotInSubgroup { x, y } => {
+                write!(f, "({x:?}, {y:?}) is not in the G1 subgroup")
+            }
+            CommitmentPointError::NonCanonicalCoordinate(bytes) => write!(
+                f,
+                "0x{} is not a canonical coordinate",
+                hex::encode(bytes)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CommitmentPointError {}
+
+/// Constructs the commitment point with coordinates `(x, y)`, returning an
+/// error if it is not a valid G1 point.
+pub fn try_point_from_coords(
+    x: Fq,
+    y: Fq,
+) -> Result<G1Affine, CommitmentPointError> {
+    let m = G1Affine { x, y };
+    if !bool::from(m.is_on_curve()) {
+        return Err(CommitmentPointError::NotOnCurve { x, y });
+    }
+    if !bool::from(G1::from(m).is_torsion_free()) {
+        return Err(CommitmentPointError::NotInSubgroup { x, y });
+    }
+    Ok(m)
+}
+
 /// Computes the commitment hash bytes from `m`.
 pub fn commitment_hash_bytes_from_g1_point(m: &G1Affine) -> [u8; 32] {
     let (m_x, m_y) = m.into_coordinates();
@@ -248,3 +299,60 @@ pub fn commitment_hash_from_commitment_point_limbs<F: EccPrimeField>(
     let commitment_hash_bytes = keccak256(input_bytes);
     be_bytes_to_field_element(&commitment_hash_bytes)
 }
+
+/// Computes the commitment hash of `point`, as computed by the on-chain
+/// verifier:
+///
+/// ```text
+/// uint256(keccak256(abi.encodePacked(x, y))) % r
+/// ```
+///
+/// That is, the keccak preimage is the 64 bytes of `x` then `y`, each as a
+/// 32-byte *big-endian* integer (see [`point_from_hash_preimage`]), and the
+/// 32-byte digest is read as a *big-endian* integer, reduced modulo the
+/// order `r` of [`Fr`].  Note that halo2curves' `to_bytes`/`from_bytes` are
+/// little-endian.
+pub fn commitment_hash_from_point(point: &G1Affine) -> Fr {
+    let limbs: Vec<Fr> =
+        g1affine_into_limbs(point, DEFAULT_LIMB_BITS, DEFAULT_NUM_LIMBS);
+    commitment_hash_from_commitment_point_limbs(
+        &limbs,
+        DEFAULT_LIMB_BITS,
+        DEFAULT_NUM_LIMBS,
+    )
+}
+
+/// Returns the commitment point whose keccak preimage (see
+/// [`commitment_hash_from_point`]) is `preimage`, i.e. the point with
+/// coordinates `x = preimage[..32]` and `y = preimage[32..]`, each read as a
+/// 32-byte big-endian integer.  Fails if a coordinate is not a canonical Fq
+/// element, or the point is not a valid G1 point.
+pub fn point_from_hash_preimage(
+    preimage: &[u8; 2 * NUM_BYTES_FQ],
+) -> Result<G1Affine, CommitmentPointError> {
+    let coordinate = |be_bytes: &[u8]| {
+        let be_bytes: [u8; NUM_BYTES_FQ] =
+            be_bytes.try_into().expect("coordinate length");
+        let mut le_bytes = be_bytes;
+        le_bytes.reverse();
+        Option::<Fq>::from(Fq::from_bytes(&le_bytes))
+            .ok_or(CommitmentPointError::NonCanonicalCoordinate(be_bytes))
+    };
+    let x = coordinate(&preimage[..NUM_BYTES_FQ])?;
+    let y = coordinate(&preimage[NUM_BYTES_FQ..])?;
+    let point = try_point_from_coords(x, y)?;
+    debug_assert_eq!(
+        commitment_point_limbs_to_bytes(
+            &g1affine_into_limbs::<Fr>(
+                &point,
+                DEFAULT_LIMB_BITS,
+                DEFAULT_NUM_LIMBS
+            ),
+            DEFAULT_LIMB_BITS,
+            DEFAULT_NUM_LIMBS
+        ),
+        preimage,
+        "preimage round-trip"
+    );
+    Ok(point)
+}
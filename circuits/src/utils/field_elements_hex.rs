@@ -1,4 +1,5 @@
 ///! JSON (de)serialization of sequences of field elements as hex string.
+///! Use as `#[serde(with = "field_elements_hex")]` on `Vec<F>` fields.
 // TODO: this utils module should not depend on other modules.  Move these
 // serialization functions into this module.
 use crate::batch_verify::common::native::json::field_element_from_str;
@@ -25,6 +26,58 @@ pub fn serialize<S: Serializer, F: EccPrimeField>(
     seq.end()
 }
 
+/// Returns the `0x`-prefixed, 32-byte big-endian hex representation of each
+/// element of `v`, as EVM tooling encodes a `uint256[]`.
+pub fn to_hex_vec<F>(v: &[F]) -> Vec<String>
+where
+    F: EccPrimeField<Repr = [u8; 32]>,
+{
+    v.iter()
+        .map(|element| {
+            let mut be_bytes = element.to_repr();
+            be_bytes.reverse();
+            format!("0x{}", hex::encode(be_bytes))
+        })
+        .collect()
+}
+
+/// Inverse of [`to_hex_vec`].  Unlike [`deserialize`], which also accepts
+/// decimal strings, each string must be `0x`-prefixed big-endian hex (of at
+/// most 32 bytes) representing a canonical field element.
+pub fn from_hex_vec<F, S>(hex_strings: &[S]) -> Result<Vec<F>, String>
+where
+    F: EccPrimeField<Repr = [u8; 32]>,
+    S: AsRef<str>,
+{
+    hex_strings
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            field_element_from_hex(s.as_ref())
+                .map_err(|e| format!("element {i}: {e}"))
+        })
+        .collect()
+}
+
+fn field_element_from_hex<F>(s: &str) -> Result<F, String>
+where
+    F: EccPrimeField<Repr = [u8; 32]>,
+{
+    let digits = s
+        .strip_prefix("0x")
+        .ok_or_else(|| format!("missing 0x prefix: {s}"))?;
+    if digits.is_empty() || digits.len() > 64 {
+        return Err(format!("expected 1 to 64 hex digits: {s}"));
+    }
+    let padded = format!("{digits:0>64}");
+    let mut repr = [0u8; 32];
+    hex::decode_to_slice(padded, &mut repr)
+        .map_err(|e| format!("invalid hex {s}: {e}"))?;
+    repr.reverse();
+    Option::from(F::from_repr(repr))
+        .ok_or_else(|| format!("not a canonical field element: {s}"))
+}
+
 struct FieldElementVecDeserializer<F> {
     __: PhantomData<F>,
 }
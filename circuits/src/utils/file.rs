@@ -1,3 +1,4 @@
+use memmap2::Mmap;
 use serde::de::DeserializeOwned;
 use std::{
     fs::{File, OpenOptions},
@@ -12,6 +13,23 @@ pub fn open_file_for_read(path: &str) -> BufReader<File> {
     BufReader::new(f)
 }
 
+/// Memory-maps the file at `path` for reading.
+///
+/// # Note
+///
+/// The mapping is only valid as long as the file is not modified or
+/// truncated by another process, which would result in undefined behaviour
+/// (typically a `SIGBUS` on Unix).
+pub fn mmap_file_for_read(path: &str) -> Mmap {
+    let f = OpenOptions::new()
+        .read(true)
+        .open(path)
+        .unwrap_or_else(|e| panic!("failed to open for read {path}: {e}"));
+    // Safety: see the note above.
+    unsafe { Mmap::map(&f) }
+        .unwrap_or_else(|e| panic!("failed to mmap {path}: {e}"))
+}
+
 pub fn load_json<T: DeserializeOwned>(filename: &str) -> T {
     let val: T = serde_json::from_reader(
         File::open(filename).unwrap_or_else(|e| panic!("{filename}: {e:?}")),
@@ -42,6 +42,26 @@ pub(crate) const POSEIDON_R_F: usize = 8;
 /// Poseidon Partial rounds
 pub(crate) const POSEIDON_R_P: usize = 57;
 
+/// Selects the MDS matrix of the Poseidon spec, for the transcripts of the
+/// inner (UBV and Keccak) and outer proofs and for the [`PoseidonHasher`] and
+/// [`WrongFieldHasher`] sponges.  0 for most specs.  See documentation for
+/// `snark_verifier::util::hash::Poseidon::new`.
+///
+/// The aggregation circuit verifies the inner proofs with the transcript spec
+/// [`POSEIDON_SPEC`](snark_verifier_sdk::halo2::POSEIDON_SPEC), so this must
+/// match the setting of that spec.
+pub const SECURE_MDS: usize = 0;
+
+/// Checks that the Poseidon chips of the hashers support `secure_mds`.  They
+/// always generate the first secure MDS matrix, i.e. `secure_mds = 0`.
+fn assert_supported_secure_mds(secure_mds: usize) {
+    assert_eq!(
+        secure_mds, 0,
+        "unsupported secure_mds {secure_mds}: Poseidon hashers only \
+         support 0"
+    );
+}
+
 pub fn digest_to_field_element<F: EccPrimeField>(digest: &[u8; 32]) -> F {
     let mut bytes_64 = [0u8; 64];
     bytes_64[..32].copy_from_slice(digest);
@@ -72,21 +92,26 @@ pub struct PoseidonHasher<'a, F: EccPrimeField> {
 }
 
 impl<'a, F: EccPrimeField> PoseidonHasher<'a, F> {
+    /// Returns a hasher using the Poseidon spec selected by `secure_mds`
+    /// (see [`SECURE_MDS`]), which first absorbs `domain_tag` if any.
     pub fn new(
         ctx: &mut Context<F>,
         gate_chip: &'a GateChip<F>,
         domain_tag: Option<&'a str>,
+        secure_mds: usize,
     ) -> Self {
         let domain_tag = domain_tag
             .map(|domain_tag_str| assigned_domain_tag(ctx, domain_tag_str));
-        Self::new_with_domain_tag(ctx, gate_chip, domain_tag)
+        Self::new_with_domain_tag(ctx, gate_chip, domain_tag, secure_mds)
     }
 
     pub fn new_with_domain_tag(
         ctx: &mut Context<F>,
         gate_chip: &'a GateChip<F>,
         domain_tag: Option<AssignedValue<F>>,
+        secure_mds: usize,
     ) -> Self {
+        assert_supported_secure_mds(secure_mds);
         let mut hasher = PoseidonHasher {
             gate_chip,
             poseidon: PoseidonChip::<F, POSEIDON_T, POSEIDON_R>::new(
@@ -105,7 +130,9 @@ impl<'a, F: EccPrimeField> PoseidonHasher<'a, F> {
     pub fn new_with_state(
         init_state: [AssignedValue<F>; POSEIDON_T],
         gate_chip: &'a GateChip<F>,
+        secure_mds: usize,
     ) -> Self {
+        assert_supported_secure_mds(secure_mds);
         PoseidonHasher {
             gate_chip,
             poseidon:
@@ -151,7 +178,7 @@ where
     let mut result = Vec::with_capacity(max_parts);
     let bitmask = ith_bit_bitmask(ctx, chip, len, max_parts as u64);
     for i in 0..max_parts {
-        let mut hasher = PoseidonHasher::new(ctx, chip, domain_tag);
+        let mut hasher = PoseidonHasher::new(ctx, chip, domain_tag, SECURE_MDS);
         hasher.absorb(&len);
         hasher.partial_absorb(assigned, i);
         result.push(hasher.squeeze(ctx));
@@ -283,7 +310,8 @@ where
         })
         .collect_vec();
     // Instantiate the hasher and absorb all elements.
-    let mut hasher = PoseidonHasher::new_with_domain_tag(ctx, chip, domain_tag);
+    let mut hasher =
+        PoseidonHasher::new_with_domain_tag(ctx, chip, domain_tag, SECURE_MDS);
     hasher.absorb(&num_parts);
     hasher.absorb(assigned);
     // Retrieve all intermediate states (after the absorption of each chunk).
@@ -312,7 +340,8 @@ where
     let state = select_with_bitmask(ctx, chip, &bitmask, intermediate_states);
     let state = state.try_into().expect("Conversion not allowed to fail");
     // Return the final value
-    let mut second_hasher = PoseidonHasher::new_with_state(state, chip);
+    let mut second_hasher =
+        PoseidonHasher::new_with_state(state, chip, SECURE_MDS);
     let extra_terms = extra_terms.representation();
     match remainder_len + extra_terms.len() {
         // If we absorbed an exact multiple of `POSEIDON_R`, we need an extra
@@ -544,10 +573,15 @@ where
     C1::ScalarExt: EccPrimeField,
     C1::Base: EccPrimeField,
 {
+    /// Returns a hasher using the Poseidon spec selected by `secure_mds`
+    /// (see [`SECURE_MDS`]), which first absorbs the domain tag of
+    /// `domain_tag_str` if any.
     pub fn new(
         circuit_config: &'a CircuitWithLimbsConfig,
         domain_tag_str: Option<&str>,
+        secure_mds: usize,
     ) -> Self {
+        assert_supported_secure_mds(secure_mds);
         let mut hasher = WrongFieldHasher {
             circuit_config,
             hasher: poseidon_native::Poseidon::new(POSEIDON_R_F, POSEIDON_R_P),
@@ -1,4 +1,4 @@
-use crate::EccPrimeField;
+use crate::{keccak::NUM_BYTES_FQ, EccPrimeField};
 use halo2_base::utils::CurveAffineExt;
 use halo2_ecc::fields::FieldExtConstructor;
 use tiny_keccak::{Hasher, Keccak};
@@ -69,3 +69,37 @@ impl Default for KeccakHasher {
         Self::new()
     }
 }
+
+/// Native equivalent of [`KeccakChip::multi_var_query`].  Returns the keccak
+/// digest of `fixed` followed by the first `var_lengths[i]` Fq elements of
+/// each `var_inputs[i]`, where the variable length inputs are given as the
+/// concatenated 32-byte big-endian encodings of their (padded) Fq elements.
+/// This is the preimage formed by `KeccakMultiVarHasher` from the limb
+/// decompositions of the same elements.
+///
+/// Note that `var_lengths` is measured in Fq elements, whereas the in-circuit
+/// lengths are measured in limbs (`num_limbs` per Fq element).  As in the
+/// circuit, the fixed input length must be a multiple of 8 bytes.
+///
+/// [`KeccakChip::multi_var_query`]: crate::keccak::chip::KeccakChip::multi_var_query
+pub fn native_multi_var_query(
+    fixed: &[u8],
+    var_inputs: &[&[u8]],
+    var_lengths: &[usize],
+) -> [u8; 32] {
+    assert_eq!(
+        fixed.len() % 8,
+        0,
+        "Fixed input length must be a multiple of 8 bytes"
+    );
+    assert_eq!(var_inputs.len(), var_lengths.len());
+    let mut hasher = KeccakHasher::new();
+    hasher.absorb_bytes(fixed);
+    for (input, len) in var_inputs.iter().zip(var_lengths) {
+        assert_eq!(input.len() % NUM_BYTES_FQ, 0);
+        let num_bytes = len * NUM_BYTES_FQ;
+        assert!(num_bytes <= input.len(), "length exceeds padded input");
+        hasher.absorb_bytes(&input[..num_bytes]);
+    }
+    hasher.finalize()
+}
@@ -1,9 +1,14 @@
-use crate::EccPrimeField;
-use halo2_base::gates::builder::GateThreadBuilder;
+use core::fmt;
+use halo2_base::{
+    gates::builder::{FlexGateConfigParams, GateThreadBuilder},
+    utils::ScalarField,
+};
+use serde::{Deserialize, Serialize};
 
 pub mod base64;
 pub mod benchmarks;
 pub mod bitmask;
+pub mod cancellation;
 pub mod commitment_point;
 pub mod field_element_hex;
 pub mod field_elements_hex;
@@ -11,6 +16,7 @@ pub mod file;
 pub mod hashing;
 pub mod keccak_hasher;
 pub mod reduced;
+pub mod srs;
 pub mod upa_config;
 pub mod vk_hex;
 
@@ -23,7 +29,7 @@ pub fn to_ref_vec<A, B>(pairs: &[(A, B)]) -> Vec<(&A, &B)> {
 
 /// Compute the current cell count for each phase.  Can be called during the
 /// vertical gate definition phase using a GateThreadBuilder.
-pub fn advice_cell_count<F: EccPrimeField>(
+pub fn advice_cell_count<F: ScalarField>(
     builder: &GateThreadBuilder<F>,
 ) -> Vec<usize> {
     builder
@@ -32,3 +38,104 @@ pub fn advice_cell_count<F: EccPrimeField>(
         .map(|threads| threads.iter().map(|ctx| ctx.advice.len()).sum())
         .collect()
 }
+
+/// Compute the total number of cells to be copied to lookup advice columns,
+/// over all phases.
+pub fn lookup_advice_cell_count<F: ScalarField>(
+    builder: &GateThreadBuilder<F>,
+) -> usize {
+    builder
+        .threads
+        .iter()
+        .flatten()
+        .map(|ctx| ctx.cells_to_lookup.len())
+        .sum()
+}
+
+/// Machine-readable summary of the cells used by a circuit, intended for
+/// capacity planning.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CellReport {
+    /// Log-2 of the number of rows
+    pub degree_bits: usize,
+    /// Number of advice cells in each phase
+    pub advice_cells_per_phase: Vec<usize>,
+    /// Total number of cells copied to lookup advice columns
+    pub lookup_advice_cells: usize,
+    /// The gate configuration computed from the cell counts
+    pub flex_gate_config_params: FlexGateConfigParams,
+}
+
+impl CellReport {
+    /// Computes the report from a populated `builder` and the
+    /// `flex_gate_config_params` derived from it.
+    pub fn new<F: ScalarField>(
+        builder: &GateThreadBuilder<F>,
+        flex_gate_config_params: &FlexGateConfigParams,
+    ) -> Self {
+        Self {
+            degree_bits: flex_gate_config_params.k,
+            advice_cells_per_phase: advice_cell_count(builder),
+            lookup_advice_cells: lookup_advice_cell_count(builder),
+            flex_gate_config_params: flex_gate_config_params.clone(),
+        }
+    }
+}
+
+/// Error returned when a circuit config requires more rows than its degree
+/// provides.  See [`check_flex_gate_rows`] and
+/// [`KeccakGateConfig::check_rows`](crate::keccak::KeccakGateConfig::check_rows).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct InsufficientRows {
+    pub required_rows: usize,
+    pub degree_bits: u32,
+    pub usable_rows: usize,
+}
+
+impl fmt::Display for InsufficientRows {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "config requires ~{} rows but degree {} provides only {}",
+            self.required_rows, self.degree_bits, self.usable_rows
+        )
+    }
+}
+
+impl std::error::Error for InsufficientRows {}
+
+/// Checks, before keygen, that a flex gate circuit with `params` fits in its
+/// `2^params.k - minimum_rows` usable rows.  The advice and fixed cells are
+/// spread over as many columns as `params` requires, so only the lookup
+/// table (`2^lookup_bits` rows) and the `num_instance` public inputs, which
+/// occupy a single column each, are bounded by the degree.  Otherwise, the
+/// keygen would panic after loading the SRS.
+pub fn check_flex_gate_rows(
+    params: &FlexGateConfigParams,
+    lookup_bits: usize,
+    num_instance: usize,
+    minimum_rows: usize,
+) -> Result<(), InsufficientRows> {
+    check_degree_rows(params.k as u32, lookup_bits, num_instance, minimum_rows)
+}
+
+/// As [`check_flex_gate_rows`], for a circuit of degree `2^degree_bits`
+/// whose [`FlexGateConfigParams`] are not known yet (e.g. because computing
+/// them requires an SRS).
+pub fn check_degree_rows(
+    degree_bits: u32,
+    lookup_bits: usize,
+    num_instance: usize,
+    minimum_rows: usize,
+) -> Result<(), InsufficientRows> {
+    let required_rows = num_instance.max(1 << lookup_bits);
+    let usable_rows = (1usize << degree_bits).saturating_sub(minimum_rows);
+    match required_rows <= usable_rows {
+        true => Ok(()),
+        false => Err(InsufficientRows {
+            required_rows,
+            degree_bits,
+            usable_rows,
+        }),
+    }
+}
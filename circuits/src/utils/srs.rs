@@ -0,0 +1,245 @@
+//! Deterministic SRS generation, and import of SRS from
+//! perpetual-powers-of-tau `.ptau` files.
+use core::fmt;
+use halo2_base::halo2_proofs::{
+    arithmetic::g_to_lagrange,
+    halo2curves::{
+        bn256::{Bn256, Fq, G1Affine, G2Affine, G1},
+        group::ff::{Field, PrimeField},
+        serde::SerdeObject,
+    },
+    poly::{commitment::ParamsProver, kzg::commitment::ParamsKZG},
+    SerdeFormat,
+};
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Generates an (insecure) SRS of degree `2^k` from a ChaCha20 RNG seeded
+/// with `seed`, so that the same `(k, seed)` always gives a bit-identical
+/// SRS, independent of the machine or any cached parameter files.  The seed
+/// is written little-endian into the first 8 bytes of the (otherwise zero)
+/// 32-byte ChaCha20 seed, so `seed = 0` corresponds to
+/// `ChaCha20Rng::from_seed(Default::default())`.
+///
+/// The G1 generator of the resulting SRS is `(1, 2)`, as required for the
+/// BV and Keccak circuits (see `OuterKeygenInputs::new`).
+///
+/// NOTE: For testing only. The toxic waste is derivable from `seed`.
+pub fn gen_deterministic_srs(k: u32, seed: u64) -> ParamsKZG<Bn256> {
+    let mut rng_seed = <ChaCha20Rng as SeedableRng>::Seed::default();
+    rng_seed[..8].copy_from_slice(&seed.to_le_bytes());
+    let params = ParamsKZG::<Bn256>::setup(k, ChaCha20Rng::from_seed(rng_seed));
+    assert_eq!(
+        params.get_g()[0],
+        G1Affine::generator(),
+        "SRS has unexpected G1 generator"
+    );
+    params
+}
+
+/// Magic bytes at the start of a `.ptau` file.
+const PTAU_MAGIC: &[u8; 4] = b"ptau";
+
+/// `.ptau` section containing the header.
+const PTAU_HEADER_SECTION: u32 = 1;
+
+/// `.ptau` section containing `[tau^i]_1`.
+const PTAU_TAU_G1_SECTION: u32 = 2;
+
+/// `.ptau` section containing `[tau^i]_2`.
+const PTAU_TAU_G2_SECTION: u32 = 3;
+
+/// Size in bytes of an (uncompressed, Montgomery form) G1 point in a
+/// `.ptau` file.
+const PTAU_G1_BYTES: usize = 64;
+
+/// Size in bytes of an (uncompressed, Montgomery form) G2 point in a
+/// `.ptau` file.
+const PTAU_G2_BYTES: usize = 128;
+
+/// Reason for which a `.ptau` file could not be imported.
+#[derive(Debug)]
+pub enum PtauError {
+    Io(io::Error),
+    /// The file does not start with the `ptau` magic bytes.
+    InvalidMagic,
+    /// The header describes a field other than the BN254 base field.
+    UnsupportedField,
+    /// The given section is not present.
+    MissingSection(u32),
+    /// The requested degree exceeds the maximum power of the file.
+    DegreeTooLarge {
+        degree_bits: u32,
+        max_power: u32,
+    },
+    /// A point in the given section could not be decoded.
+    InvalidPoint(u32),
+    /// The first G1 power is not the generator `(1, 2)`.
+    UnexpectedGenerator,
+}
+
+impl fmt::Display for PtauError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PtauError::Io(e) => write!(f, "{e}"),
+            PtauError::InvalidMagic => write!(f, "not a .ptau file"),
+            PtauError::UnsupportedField => {
+                write!(f, "unsupported field (expected BN254)")
+            }
+            PtauError::MissingSection(section) => {
+                write!(f, "missing section {section}")
+            }
+            PtauError::DegreeTooLarge {
+                degree_bits,
+                max_power,
+            } => write!(
+                f,
+                "requested degree {degree_bits} exceeds the maximum power \
+                 {max_power} of the .ptau file"
+            ),
+            PtauError::InvalidPoint(section) => {
+                write!(f, "invalid point in section {section}")
+            }
+            PtauError::UnexpectedGenerator => {
+                write!(f, "first G1 power is not the generator (1, 2)")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PtauError {}
+
+impl From<io::Error> for PtauError {
+    fn from(e: io::Error) -> Self {
+        PtauError::Io(e)
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Returns the little-endian bytes of the BN254 base field modulus.
+fn fq_modulus_le_bytes() -> [u8; 32] {
+    // The modulus is odd, so adding 1 to the low byte of `q - 1` does not
+    // carry.
+    let mut bytes = (-Fq::one()).to_repr();
+    bytes[0] += 1;
+    bytes
+}
+
+/// Reads the points of a `.ptau` section.  Points are stored uncompressed in
+/// little-endian Montgomery form, which coincides with the `RawBytes` format
+/// of `halo2curves` (which also checks that the points are on the curve).
+fn read_ptau_points<R: Read, C: SerdeObject>(
+    reader: &mut R,
+    num_points: usize,
+    point_bytes: usize,
+    section: u32,
+) -> Result<Vec<C>, PtauError> {
+    let mut bytes = vec![0u8; point_bytes];
+    (0..num_points)
+        .map(|_| {
+            reader.read_exact(&mut bytes)?;
+            C::from_raw_bytes(&bytes).ok_or(PtauError::InvalidPoint(section))
+        })
+        .collect()
+}
+
+/// Imports an SRS of degree `2^degree_bits` from a `.ptau` file (as
+/// produced by snarkjs and the perpetual powers of tau ceremony).  Only the
+/// header and the `[tau^i]_1`, `[tau^i]_2` sections are read.  The resulting
+/// SRS is checked to have the G1 generator `(1, 2)`.
+pub fn srs_from_ptau<R: Read + Seek>(
+    reader: &mut R,
+    degree_bits: u32,
+) -> Result<ParamsKZG<Bn256>, PtauError> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != PTAU_MAGIC {
+        return Err(PtauError::InvalidMagic);
+    }
+    let _version = read_u32(reader)?;
+    let num_sections = read_u32(reader)?;
+
+    // Record the (offset, size) of each section
+    let mut sections = Vec::with_capacity(num_sections as usize);
+    for _ in 0..num_sections {
+        let section_type = read_u32(reader)?;
+        let section_size = read_u64(reader)?;
+        let offset = reader.stream_position()?;
+        sections.push((section_type, offset));
+        reader.seek(SeekFrom::Current(section_size as i64))?;
+    }
+    let seek_section = |reader: &mut R, section: u32| {
+        let (_, offset) = sections
+            .iter()
+            .find(|(section_type, _)| *section_type == section)
+            .ok_or(PtauError::MissingSection(section))?;
+        reader.seek(SeekFrom::Start(*offset))?;
+        Ok::<_, PtauError>(())
+    };
+
+    // Header: n8, q, power, ceremony power
+    seek_section(reader, PTAU_HEADER_SECTION)?;
+    let n8 = read_u32(reader)?;
+    if n8 != 32 {
+        return Err(PtauError::UnsupportedField);
+    }
+    let mut q = [0u8; 32];
+    reader.read_exact(&mut q)?;
+    if q != fq_modulus_le_bytes() {
+        return Err(PtauError::UnsupportedField);
+    }
+    let max_power = read_u32(reader)?;
+    if degree_bits > max_power {
+        return Err(PtauError::DegreeTooLarge {
+            degree_bits,
+            max_power,
+        });
+    }
+
+    let n = 1usize << degree_bits;
+    seek_section(reader, PTAU_TAU_G1_SECTION)?;
+    let g: Vec<G1Affine> =
+        read_ptau_points(reader, n, PTAU_G1_BYTES, PTAU_TAU_G1_SECTION)?;
+    if g[0] != G1Affine::generator() {
+        return Err(PtauError::UnexpectedGenerator);
+    }
+    seek_section(reader, PTAU_TAU_G2_SECTION)?;
+    let g2s: Vec<G2Affine> =
+        read_ptau_points(reader, 2, PTAU_G2_BYTES, PTAU_TAU_G2_SECTION)?;
+    let g_lagrange: Vec<G1Affine> = g_to_lagrange(
+        g.iter().map(|point| G1::from(*point)).collect(),
+        degree_bits,
+    );
+
+    // Assemble the params in the `RawBytes` serialization format of
+    // `ParamsKZG`: k, g, g_lagrange, g2, s_g2.
+    let mut bytes =
+        Vec::with_capacity(4 + 2 * n * PTAU_G1_BYTES + 2 * PTAU_G2_BYTES);
+    bytes.extend_from_slice(&degree_bits.to_le_bytes());
+    g.iter()
+        .chain(g_lagrange.iter())
+        .for_each(|point| point.write_raw(&mut bytes).unwrap());
+    g2s.iter()
+        .for_each(|point| point.write_raw(&mut bytes).unwrap());
+    let params = ParamsKZG::<Bn256>::read_custom(
+        &mut bytes.as_slice(),
+        SerdeFormat::RawBytes,
+    )?;
+    assert_eq!(
+        params.get_g()[0],
+        G1Affine::generator(),
+        "SRS has unexpected G1 generator"
+    );
+    Ok(params)
+}
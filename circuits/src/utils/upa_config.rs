@@ -1,5 +1,13 @@
 use super::file::load_json;
-use crate::{CircuitConfig, CircuitWithLimbsConfig};
+use crate::{
+    batch_verify::{
+        common::chip::{WINDOW_BITS, WINDOW_BITS_G2_SUBGROUP_CHECK},
+        universal::types::UniversalBatchVerifierConfig,
+    },
+    keccak::{keccak_instance_len, KeccakConfig, OutputMode},
+    outer::{outer_instance_len, NUM_LIMBS},
+    CircuitConfig, CircuitWithLimbsConfig,
+};
 use core::fmt;
 use serde::{Deserialize, Serialize};
 
@@ -36,52 +44,449 @@ pub struct UpaConfig {
     /// the proofIds it computes as the final digest. If `false`, it outputs the
     /// keccak hash of all proofIds.
     pub output_submission_id: bool,
+
+    /// Support application proofs with a Pedersen commitment.  Determines
+    /// `allow_commitments` in both the UBV and Keccak configs, which must
+    /// agree.  Set by default.
+    #[serde(default = "default_allow_commitments")]
+    pub allow_commitments: bool,
+
+    /// Expose `num_proof_ids` as a public input of the Keccak and outer
+    /// circuits, immediately before the final digest.  Requires
+    /// `output_submission_id`.  Unset by default.
+    #[serde(default)]
+    pub public_num_proof_ids: bool,
+
+    /// Expose the keccak hash of the circuit ids of all entries (the vk
+    /// digest) as a public input of the Keccak and outer circuits,
+    /// immediately after the final digest.  Requires `output_submission_id`
+    /// to be unset.  Unset by default.
+    #[serde(default)]
+    pub include_vk_digest: bool,
+
+    /// Window size for the G1 scalar multiplications of the batched pairing
+    /// check in the BV circuit (see
+    /// [`UniversalBatchVerifierConfig::window_bits`]).  Affects the number
+    /// of advice cells, hence the keys.  Defaults to [`WINDOW_BITS`].
+    #[serde(default = "default_window_bits")]
+    pub window_bits: usize,
+
+    /// Window size for the G2 scalar multiplication of the vk subgroup
+    /// check in the BV circuit (see
+    /// [`UniversalBatchVerifierConfig::g2_subgroup_window_bits`]).  Affects
+    /// the number of advice cells, hence the keys.  Defaults to
+    /// [`WINDOW_BITS_G2_SUBGROUP_CHECK`].
+    #[serde(default = "default_g2_subgroup_window_bits")]
+    pub g2_subgroup_window_bits: usize,
 }
 
-impl UpaConfig {
-    // Checks that the `BatchVerifyConfig`, KeccakConfig, and `OuterConfig`
-    // in an `UpaConfig` are compatible with each other.
-    pub fn check(&self) -> Result<(), &'static str> {
-        // Number of lookup bits should be strictly smaller than the degree
-        // for each circuit.
-        if self.bv_config.lookup_bits
-            > (self.bv_config.degree_bits - 1) as usize
-        {
-            return Err("BV lookup bits greater or equal to degree bits.");
+fn default_allow_commitments() -> bool {
+    true
+}
+
+fn default_window_bits() -> usize {
+    WINDOW_BITS
+}
+
+fn default_g2_subgroup_window_bits() -> usize {
+    WINDOW_BITS_G2_SUBGROUP_CHECK
+}
+
+/// Maximum supported `degree_bits` (the 2-adicity of the BN254 scalar field).
+pub const MAX_DEGREE_BITS: u32 = 28;
+
+/// Reason for which [`UpaConfig::validate`] rejected a configuration.  Each
+/// variant holds the name of the offending field.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UpaConfigError {
+    /// A batch size is zero.
+    ZeroBatchSize(&'static str),
+    /// A window size is zero.
+    ZeroWindowBits(&'static str),
+    /// `degree_bits` is not in `1..=MAX_DEGREE_BITS`.
+    DegreeBitsOutOfRange {
+        field: &'static str,
+        degree_bits: u32,
+    },
+    /// `lookup_bits` is not strictly smaller than `degree_bits`.
+    LookupBitsTooLarge {
+        field: &'static str,
+        lookup_bits: usize,
+        degree_bits: u32,
+    },
+    /// A field of the UPA config does not have the value required by the
+    /// circuits.
+    Inconsistent {
+        field: &'static str,
+        expected: u64,
+        actual: u64,
+    },
+    /// A [`KeccakConfig`] differs from [`UpaConfig::keccak_config`] in this
+    /// field.
+    KeccakConfigMismatch(&'static str),
+    /// The flag `field` is set, but the config does not satisfy
+    /// `requires`.
+    Incompatible {
+        field: &'static str,
+        requires: &'static str,
+    },
+}
+
+impl fmt::Display for UpaConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpaConfigError::ZeroBatchSize(field) => {
+                write!(f, "{field}: batch size must be positive")
+            }
+            UpaConfigError::ZeroWindowBits(field) => {
+                write!(f, "{field}: window size must be positive")
+            }
+            UpaConfigError::DegreeBitsOutOfRange { field, degree_bits } => {
+                write!(
+                    f,
+                    "{field}: degree bits {degree_bits} not in \
+                     1..={MAX_DEGREE_BITS}"
+                )
+            }
+            UpaConfigError::LookupBitsTooLarge {
+                field,
+                lookup_bits,
+                degree_bits,
+            } => write!(
+                f,
+                "{field}: lookup bits {lookup_bits} must be smaller than \
+                 degree bits {degree_bits}"
+            ),
+            UpaConfigError::Inconsistent {
+                field,
+                expected,
+                actual,
+            } => write!(f, "{field}: expected {expected}, got {actual}"),
+            UpaConfigError::KeccakConfigMismatch(field) => write!(
+                f,
+                "keccak {field}: differs from the keccak config derived from \
+                 the UPA config"
+            ),
+            UpaConfigError::Incompatible { field, requires } => {
+                write!(f, "{field}: requires {requires}")
+            }
         }
+    }
+}
+
+impl std::error::Error for UpaConfigError {}
 
-        if self.keccak_config.lookup_bits
-            > (self.keccak_config.degree_bits - 1) as usize
-        {
-            return Err("Keccak lookup bits greater or equal to degree bits.");
+/// A field which differs between two [`UpaConfig`]s.  See
+/// [`UpaConfig::diff`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UpaConfigFieldDiff {
+    /// Name of the field, with the sub-circuit config if any (e.g.
+    /// `bv_config.degree_bits`).
+    pub field: &'static str,
+    pub this: String,
+    pub other: String,
+}
+
+impl fmt::Display for UpaConfigFieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.this, self.other)
+    }
+}
+
+/// Checks `degree_bits` and `lookup_bits` of the sub-circuit config
+/// `field`.
+fn validate_circuit_degree(
+    field: &'static str,
+    degree_bits: u32,
+    lookup_bits: usize,
+) -> Result<(), UpaConfigError> {
+    if degree_bits == 0 || degree_bits > MAX_DEGREE_BITS {
+        return Err(UpaConfigError::DegreeBitsOutOfRange {
+            field,
+            degree_bits,
+        });
+    }
+    if lookup_bits >= degree_bits as usize {
+        return Err(UpaConfigError::LookupBitsTooLarge {
+            field,
+            lookup_bits,
+            degree_bits,
+        });
+    }
+    Ok(())
+}
+
+/// Returns the [`UpaConfigFieldDiff`] for `field` if `this != other`.
+fn field_diff<T: PartialEq + ToString>(
+    field: &'static str,
+    this: T,
+    other: T,
+) -> Option<UpaConfigFieldDiff> {
+    (this != other).then(|| UpaConfigFieldDiff {
+        field,
+        this: this.to_string(),
+        other: other.to_string(),
+    })
+}
+
+/// Returns an [`UpaConfigError::Inconsistent`] error for `field` if
+/// `expected != actual`.
+fn check_consistent(
+    field: &'static str,
+    expected: u64,
+    actual: u64,
+) -> Result<(), UpaConfigError> {
+    if expected != actual {
+        return Err(UpaConfigError::Inconsistent {
+            field,
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+impl UpaConfig {
+    /// Checks that the `BatchVerifyConfig`, `KeccakConfig`, and
+    /// `OuterConfig` in an `UpaConfig` are well-formed and compatible with
+    /// each other, namely:
+    /// - batch sizes and window sizes are positive,
+    /// - degree bits are in `1..=MAX_DEGREE_BITS` for each circuit,
+    /// - lookup bits are strictly smaller than degree bits for each circuit,
+    /// - `public_num_proof_ids` is only set with `output_submission_id`, and
+    ///   `include_vk_digest` only without it,
+    /// - the outer circuit uses the number of limbs of its accumulator.
+    pub fn validate(&self) -> Result<(), UpaConfigError> {
+        if self.inner_batch_size == 0 {
+            return Err(UpaConfigError::ZeroBatchSize("inner_batch_size"));
+        }
+        if self.outer_batch_size == 0 {
+            return Err(UpaConfigError::ZeroBatchSize("outer_batch_size"));
+        }
+        if self.window_bits == 0 {
+            return Err(UpaConfigError::ZeroWindowBits("window_bits"));
+        }
+        if self.g2_subgroup_window_bits == 0 {
+            return Err(UpaConfigError::ZeroWindowBits(
+                "g2_subgroup_window_bits",
+            ));
         }
 
-        if self.outer_config.lookup_bits
-            > (self.outer_config.degree_bits - 1) as usize
-        {
-            return Err(
-                "Outer circuit lookup bits greater or equal to degree bits.",
-            );
+        validate_circuit_degree(
+            "bv_config",
+            self.bv_config.degree_bits,
+            self.bv_config.lookup_bits,
+        )?;
+        validate_circuit_degree(
+            "keccak_config",
+            self.keccak_config.degree_bits,
+            self.keccak_config.lookup_bits,
+        )?;
+        validate_circuit_degree(
+            "outer_config",
+            self.outer_config.degree_bits,
+            self.outer_config.lookup_bits,
+        )?;
+
+        if self.public_num_proof_ids && !self.output_submission_id {
+            return Err(UpaConfigError::Incompatible {
+                field: "public_num_proof_ids",
+                requires: "output_submission_id",
+            });
         }
+        if self.include_vk_digest && self.output_submission_id {
+            return Err(UpaConfigError::Incompatible {
+                field: "include_vk_digest",
+                requires: "output_submission_id to be unset",
+            });
+        }
+
+        check_consistent(
+            "outer_config.num_limbs",
+            NUM_LIMBS as u64,
+            self.outer_config.num_limbs as u64,
+        )?;
 
         Ok(())
     }
 
+    /// Returns the [`KeccakConfig`] of the keccak circuit aggregated by the
+    /// outer circuit for `self`.  This is the single source of truth for
+    /// that config (in particular its `output_mode`, which is determined by
+    /// `output_submission_id`): any other keccak config must be checked
+    /// with [`Self::check_keccak_config`].
+    ///
+    /// Not to be confused with the `keccak_config` field, which only holds
+    /// the degree and lookup bits of the keccak circuit.
+    pub fn keccak_config(&self) -> KeccakConfig {
+        KeccakConfig::from(self)
+    }
+
+    /// Checks that `keccak_config` is the config returned by
+    /// [`Self::keccak_config`], reporting the `output_mode` first since the
+    /// outer circuit handles the final digest differently for each mode.
+    /// [`OutputMode::Both`] is rejected outright, since the outer circuit
+    /// forwards a single final digest.
+    pub fn check_keccak_config(
+        &self,
+        keccak_config: &KeccakConfig,
+    ) -> Result<(), UpaConfigError> {
+        if keccak_config.output_mode == OutputMode::Both {
+            return Err(UpaConfigError::Incompatible {
+                field: "output_mode",
+                requires: "a single final digest (linear or submission id)",
+            });
+        }
+        let expected = self.keccak_config();
+        if keccak_config.output_mode != expected.output_mode {
+            return Err(UpaConfigError::KeccakConfigMismatch("output_mode"));
+        }
+        if *keccak_config != expected {
+            return Err(UpaConfigError::KeccakConfigMismatch("config"));
+        }
+        Ok(())
+    }
+
+    /// Returns the fields whose values differ between `self` and `other`,
+    /// in declaration order.
+    pub fn diff(&self, other: &UpaConfig) -> Vec<UpaConfigFieldDiff> {
+        [
+            field_diff(
+                "max_num_app_public_inputs",
+                self.max_num_app_public_inputs,
+                other.max_num_app_public_inputs,
+            ),
+            field_diff(
+                "inner_batch_size",
+                self.inner_batch_size,
+                other.inner_batch_size,
+            ),
+            field_diff(
+                "outer_batch_size",
+                self.outer_batch_size,
+                other.outer_batch_size,
+            ),
+            field_diff(
+                "bv_config.degree_bits",
+                self.bv_config.degree_bits,
+                other.bv_config.degree_bits,
+            ),
+            field_diff(
+                "bv_config.lookup_bits",
+                self.bv_config.lookup_bits,
+                other.bv_config.lookup_bits,
+            ),
+            field_diff(
+                "bv_config.limb_bits",
+                self.bv_config.limb_bits,
+                other.bv_config.limb_bits,
+            ),
+            field_diff(
+                "bv_config.num_limbs",
+                self.bv_config.num_limbs,
+                other.bv_config.num_limbs,
+            ),
+            field_diff(
+                "keccak_config.degree_bits",
+                self.keccak_config.degree_bits,
+                other.keccak_config.degree_bits,
+            ),
+            field_diff(
+                "keccak_config.lookup_bits",
+                self.keccak_config.lookup_bits,
+                other.keccak_config.lookup_bits,
+            ),
+            field_diff(
+                "outer_config.degree_bits",
+                self.outer_config.degree_bits,
+                other.outer_config.degree_bits,
+            ),
+            field_diff(
+                "outer_config.lookup_bits",
+                self.outer_config.lookup_bits,
+                other.outer_config.lookup_bits,
+            ),
+            field_diff(
+                "outer_config.limb_bits",
+                self.outer_config.limb_bits,
+                other.outer_config.limb_bits,
+            ),
+            field_diff(
+                "outer_config.num_limbs",
+                self.outer_config.num_limbs,
+                other.outer_config.num_limbs,
+            ),
+            field_diff(
+                "output_submission_id",
+                self.output_submission_id,
+                other.output_submission_id,
+            ),
+            field_diff(
+                "allow_commitments",
+                self.allow_commitments,
+                other.allow_commitments,
+            ),
+            field_diff(
+                "public_num_proof_ids",
+                self.public_num_proof_ids,
+                other.public_num_proof_ids,
+            ),
+            field_diff(
+                "include_vk_digest",
+                self.include_vk_digest,
+                other.include_vk_digest,
+            ),
+            field_diff("window_bits", self.window_bits, other.window_bits),
+            field_diff(
+                "g2_subgroup_window_bits",
+                self.g2_subgroup_window_bits,
+                other.g2_subgroup_window_bits,
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Returns `true` if the keys generated for `self` can be used with
+    /// `other`, i.e. if all fields affecting the structure of the circuits
+    /// (degrees, batch sizes, limb configs, lookup bits and output mode)
+    /// match.
+    ///
+    /// # Note
+    ///
+    /// Every field of [`UpaConfig`] currently affects the structure of at
+    /// least one circuit, so this holds exactly when `self == other`.
+    /// Cosmetic differences between config files (formatting, order of the
+    /// fields) do not survive deserialization and are ignored.
+    pub fn key_compatible_with(&self, other: &UpaConfig) -> bool {
+        let compatible = self.diff(other).is_empty();
+        debug_assert_eq!(
+            compatible,
+            self == other,
+            "UpaConfig::diff is missing a field"
+        );
+        compatible
+    }
+
     // Constructor method to load UpaConfig from a JSON file.
     pub fn from_file(config_file: &str) -> Self {
         let config: UpaConfig = load_json(config_file);
 
-        // Panic if check fails
-        config.check().unwrap_or_else(|e| {
-            panic!("Compatibility check for config {config_file} failed: {e:?}")
+        // Panic if validation fails
+        config.validate().unwrap_or_else(|e| {
+            panic!("Invalid UPA config {config_file}: {e}")
         });
 
         config
     }
 }
 
+/// Shows the UPA parameters followed by the sub-circuit configs derived
+/// from them.
 impl fmt::Display for UpaConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keccak_config = self.keccak_config();
         writeln!(
             f,
             "Max num public inputs: {}",
@@ -89,8 +494,24 @@ impl fmt::Display for UpaConfig {
         )?;
         writeln!(f, "Inner batch size: {}", self.inner_batch_size)?;
         writeln!(f, "Outer batch size: {}", self.outer_batch_size)?;
-        writeln!(f, "BV config: {}", self.bv_config)?;
-        writeln!(f, "Keccak config: {}", self.keccak_config)?;
-        writeln!(f, "Outer config: {}", self.outer_config)
+        writeln!(
+            f,
+            "Total proofs (inner * outer): {}",
+            self.inner_batch_size * self.outer_batch_size
+        )?;
+        writeln!(f, "Output submission id: {}", self.output_submission_id)?;
+        writeln!(f, "Allow commitments: {}", self.allow_commitments)?;
+        writeln!(f, "Public num proof ids: {}", self.public_num_proof_ids)?;
+        writeln!(f, "Include vk digest: {}", self.include_vk_digest)?;
+        writeln!(f, "\nUBV config:")?;
+        writeln!(f, "{}", UniversalBatchVerifierConfig::from(self))?;
+        writeln!(f, "\nKeccak config:")?;
+        writeln!(f, "{keccak_config}")?;
+        writeln!(f, "Lookup bits: {}", keccak_config.lookup_bits)?;
+        writeln!(f, "Instance len: {}", keccak_instance_len(&keccak_config))?;
+        writeln!(f, "\nOuter config:")?;
+        write!(f, "{}", self.outer_config)?;
+        writeln!(f, "Num BV proofs: {}", self.outer_batch_size)?;
+        writeln!(f, "Instance len: {}", outer_instance_len(self))
     }
 }
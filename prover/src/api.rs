@@ -0,0 +1,577 @@
+//! Library API for the core prover operations: `keygen`, `prove` and
+//! `verify` of the UBV, Keccak and Outer circuits.
+//!
+//! These functions take typed, in-memory inputs (configs, SRS, keys, circuit
+//! inputs) and return their results, so that servers can load keys once and
+//! prove many batches.  The CLI commands are thin wrappers which load their
+//! inputs from files, call these functions and write the results to files.
+//!
+//! Failures caused by the inputs (inconsistent inputs, invalid proofs) are
+//! returned as [`ProverError`]s, as are Outer key generation failures and
+//! failed circuit self-checks.  Violated circuit invariants during witness
+//! generation are reported by panicking, as for the CLI.
+//!
+//! As for [`SafeCircuit::prover`], proving keys must have been generated (by
+//! the `*_keygen` functions) or read (by [`SafeCircuit::read_proving_key`])
+//! in the current process, so that the circuit environment is set.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use circuits::batch_verify::universal::types::{
+//!     UniversalBatchVerifierConfig, UniversalBatchVerifierInputs,
+//! };
+//! use prover::{
+//!     api,
+//!     error::ProverError,
+//!     file_utils::{load_srs, load_upa_config},
+//! };
+//! use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+//!
+//! let config =
+//!     UniversalBatchVerifierConfig::from(&load_upa_config("upa_config.json")?);
+//! let srs = load_srs("ubv.srs")?;
+//! let keys = api::ubv_keygen(&config, &srs);
+//!
+//! let rng = &mut ChaCha20Rng::seed_from_u64(0);
+//! let inputs = UniversalBatchVerifierInputs::sample_mixed(&config, rng);
+//! let (proof, instance) = api::ubv_prove(
+//!     &srs,
+//!     &keys.pk,
+//!     &config,
+//!     &keys.gate_config,
+//!     keys.break_points.clone(),
+//!     &inputs,
+//! )?;
+//! api::ubv_verify(&srs, keys.pk.get_vk(), &proof, &instance)?;
+//! # Ok::<(), ProverError>(())
+//! ```
+//!
+//! With the `async` feature, [`spawn_blocking`] runs any of these functions
+//! on the tokio blocking thread pool, for example:
+//!
+//! ```ignore
+//! let (proof, instance) = api::spawn_blocking(move || {
+//!     api::ubv_prove(&srs, &pk, &config, &gate_config, break_points, &inputs)
+//! })
+//! .await?;
+//! ```
+
+use crate::{
+    error::ProverError,
+    universal_batch_verifier::check_num_public_inputs,
+    universal_outer::{Scheme, UniversalOuterCircuitInputs},
+};
+use circuits::{
+    batch_verify::universal::{
+        types::{UniversalBatchVerifierConfig, UniversalBatchVerifierInputs},
+        UniversalBatchVerifyCircuit,
+    },
+    keccak::{
+        inputs::KeccakCircuitInputs, KeccakCircuit, KeccakConfig,
+        KeccakGateConfig, KeccakProgress,
+    },
+    outer::{
+        universal,
+        utils::{
+            gen_outer_pk, prove_outer, prove_outer_snark, verify_outer,
+            verify_outer_snark,
+        },
+        OuterCircuit, OuterGateConfig, OuterKeygenInputs,
+    },
+    utils::{hashing::SECURE_MDS, upa_config::UpaConfig},
+    SafeCircuit,
+};
+use halo2_base::{
+    gates::builder::{FlexGateConfigParams, MultiPhaseThreadBreakPoints},
+    halo2_proofs::{
+        halo2curves::bn256::{Bn256, Fr, G1Affine},
+        plonk::{
+            create_proof, keygen_pk, keygen_vk, verify_proof, Circuit,
+            ProvingKey, VerifyingKey,
+        },
+        poly::{
+            commitment::ParamsProver,
+            kzg::{
+                commitment::{KZGCommitmentScheme, ParamsKZG},
+                multiopen::{
+                    ProverGWC, ProverSHPLONK, VerifierGWC, VerifierSHPLONK,
+                },
+                strategy::SingleStrategy,
+            },
+        },
+    },
+};
+use log::info;
+use rand_chacha::rand_core::OsRng;
+use snark_verifier::{
+    system::halo2::{compile, transcript::halo2::ChallengeScalar, Config},
+    verifier::plonk::PlonkProtocol,
+};
+use snark_verifier_sdk::{
+    halo2::PoseidonTranscript, CircuitExt, NativeLoader, Snark, SHPLONK,
+};
+use std::time::Instant;
+
+/// Keys and auxiliary data generated by [`ubv_keygen`] and
+/// [`keccak_keygen`].  `gate_config` and `break_points` are required to
+/// prove, and `protocol` to aggregate the proofs in the Outer circuit.
+pub struct CircuitKeys<G> {
+    pub pk: ProvingKey<G1Affine>,
+    pub gate_config: G,
+    pub break_points: MultiPhaseThreadBreakPoints,
+    pub protocol: PlonkProtocol<G1Affine>,
+}
+
+/// Keys and auxiliary data generated by [`outer_keygen`].  `num_instance` is
+/// required to generate the EVM verifier.
+pub struct OuterKeys {
+    pub pk: ProvingKey<G1Affine>,
+    pub gate_config: OuterGateConfig,
+    pub break_points: MultiPhaseThreadBreakPoints,
+    pub num_instance: usize,
+}
+
+/// Generates the keys of the UBV circuit for `config`.
+pub fn ubv_keygen(
+    config: &UniversalBatchVerifierConfig,
+    srs: &ParamsKZG<Bn256>,
+) -> CircuitKeys<FlexGateConfigParams> {
+    let circuit =
+        UniversalBatchVerifyCircuit::<_, G1Affine>::keygen(config, &());
+    keygen_circuit(srs, &circuit, "UBV")
+}
+
+/// Checks that `inputs` can be proven by the UBV circuit for `config`:
+/// each entry fits in `config.max_num_public_inputs` and the inputs are
+/// consistent with `config`.
+pub fn ubv_check_inputs(
+    config: &UniversalBatchVerifierConfig,
+    inputs: &UniversalBatchVerifierInputs<Fr>,
+) -> Result<(), ProverError> {
+    check_num_public_inputs(inputs, config)?;
+    inputs
+        .check_consistent(config)
+        .map_err(|e| ProverError::InvalidInput(e.to_string()))
+}
+
+/// Computes a UBV proof of `inputs`, after checking them with
+/// [`ubv_check_inputs`].  Returns the proof and its instance.
+pub fn ubv_prove(
+    srs: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    config: &UniversalBatchVerifierConfig,
+    gate_config: &FlexGateConfigParams,
+    break_points: MultiPhaseThreadBreakPoints,
+    inputs: &UniversalBatchVerifierInputs<Fr>,
+) -> Result<(Vec<u8>, Vec<Fr>), ProverError> {
+    ubv_check_inputs(config, inputs)?;
+    Ok(ubv_prove_unchecked(
+        srs,
+        pk,
+        config,
+        gate_config,
+        break_points,
+        inputs,
+    ))
+}
+
+/// As [`ubv_prove`], for `inputs` already checked with [`ubv_check_inputs`].
+pub(crate) fn ubv_prove_unchecked(
+    srs: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    config: &UniversalBatchVerifierConfig,
+    gate_config: &FlexGateConfigParams,
+    break_points: MultiPhaseThreadBreakPoints,
+    inputs: &UniversalBatchVerifierInputs<Fr>,
+) -> (Vec<u8>, Vec<Fr>) {
+    // Set the gate config explicitly, rather than relying on it having been
+    // set by keygen or while reading the PK.
+    UniversalBatchVerifyCircuit::<_, G1Affine>::set_gate_config_env(
+        config,
+        gate_config,
+    );
+
+    info!("Computing BV proof...");
+    let now = Instant::now();
+    let circuit = UniversalBatchVerifyCircuit::<_, G1Affine>::prover(
+        config,
+        gate_config,
+        break_points,
+        inputs,
+    );
+    let instance = circuit.instances().remove(0);
+    let proof = create_shplonk_proof(srs, pk, circuit, &instance);
+    info!("Finished computing BV proof in {:?}", now.elapsed());
+    (proof, instance)
+}
+
+/// Verifies a UBV `proof` against `vk` and `instance`.
+pub fn ubv_verify(
+    srs: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proof: &[u8],
+    instance: &[Fr],
+) -> Result<(), ProverError> {
+    verify_shplonk_proof(srs, vk, proof, instance, "UBV")
+}
+
+/// Generates the keys of the Keccak circuit for `config`.
+pub fn keccak_keygen(
+    config: &KeccakConfig,
+    srs: &ParamsKZG<Bn256>,
+) -> CircuitKeys<KeccakGateConfig> {
+    let circuit = KeccakCircuit::<_, G1Affine>::keygen(config, &());
+    keygen_circuit(srs, &circuit, "Keccak")
+}
+
+/// Computes a Keccak proof of `inputs`, reporting the progress of witness
+/// generation to `progress`.  If `self_check` is set, the circuit is first
+/// checked to be well constructed.  Returns the proof and its instance.
+#[allow(clippy::too_many_arguments)]
+pub fn keccak_prove(
+    srs: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    config: &KeccakConfig,
+    gate_config: &KeccakGateConfig,
+    break_points: MultiPhaseThreadBreakPoints,
+    inputs: &KeccakCircuitInputs<Fr>,
+    self_check: bool,
+    progress: &mut dyn FnMut(KeccakProgress),
+) -> Result<(Vec<u8>, Vec<Fr>), ProverError> {
+    gate_config
+        .assert_compatible(config)
+        .map_err(|e| ProverError::InvalidInput(e.to_string()))?;
+    if config.output_mode.outputs_submission_id()
+        != inputs.num_proof_ids.is_some()
+    {
+        return Err(ProverError::InvalidInput(
+            "the number of proof ids must be given if and only if the \
+             config outputs the submission id"
+                .to_string(),
+        ));
+    }
+
+    info!("Computing Keccak proof...");
+    let now = Instant::now();
+    let circuit = KeccakCircuit::<_, G1Affine>::prover_with_progress(
+        config,
+        gate_config,
+        break_points,
+        inputs,
+        progress,
+    );
+    if self_check {
+        info!("Checking the keccak circuit is well constructed...");
+        circuit.check_well_constructed(config).map_err(|e| {
+            ProverError::Circuit(format!("Keccak circuit inconsistency: {e:?}"))
+        })?;
+    }
+    let instance = circuit.instances().remove(0);
+    let proof = create_shplonk_proof(srs, pk, circuit, &instance);
+    info!("Finished computing Keccak proof in {:?}", now.elapsed());
+    Ok((proof, instance))
+}
+
+/// Verifies a Keccak `proof` against `vk` and `instance`.
+pub fn keccak_verify(
+    srs: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proof: &[u8],
+    instance: &[Fr],
+) -> Result<(), ProverError> {
+    verify_shplonk_proof(srs, vk, proof, instance, "Keccak")
+}
+
+/// Generates the keys of the Outer circuit for `config`.  `keygen_inputs`
+/// holds the SRS of the UBV, Keccak and Outer circuits.
+pub fn outer_keygen(
+    config: &UpaConfig,
+    keygen_inputs: &OuterKeygenInputs,
+) -> Result<OuterKeys, ProverError> {
+    config
+        .validate()
+        .map_err(|e| ProverError::InvalidInput(e.to_string()))?;
+    info!("Generating Outer PK ...");
+    let now = Instant::now();
+    let (pk, gate_config, break_points, num_instance) =
+        gen_outer_pk::<
+            SHPLONK,
+            universal::UniversalOuterCircuit,
+            ProverSHPLONK<Bn256>,
+            VerifierSHPLONK<Bn256>,
+        >(config, keygen_inputs)
+        .map_err(|e| {
+            ProverError::Circuit(format!("failed to generate outer PK: {e}"))
+        })?;
+    info!("Finished Outer PK gen in {:?}", now.elapsed());
+    Ok(OuterKeys {
+        pk,
+        gate_config,
+        break_points,
+        num_instance,
+    })
+}
+
+/// Checks that an Outer proof for `config` can be computed with `pk` and
+/// `gate_config`: `config` is valid, and `pk` and `gate_config` were
+/// generated for its outer circuit degree and lookup bits.  The inputs
+/// themselves are checked for consistency when constructed (see
+/// [`UniversalOuterCircuitInputs::new`]).
+pub fn outer_check_inputs(
+    pk: &ProvingKey<G1Affine>,
+    config: &UpaConfig,
+    gate_config: &OuterGateConfig,
+) -> Result<(), ProverError> {
+    config
+        .validate()
+        .map_err(|e| ProverError::InvalidInput(e.to_string()))?;
+    let degree_bits =
+        universal::UniversalOuterCircuit::degree_bits(config) as u32;
+    let pk_degree_bits = pk.get_vk().get_domain().k();
+    if pk_degree_bits != degree_bits {
+        return Err(ProverError::InvalidInput(format!(
+            "outer PK has degree {pk_degree_bits}, config requires \
+             {degree_bits}"
+        )));
+    }
+    let gate_degree_bits = gate_config.flex_gate_config_params().k;
+    if gate_degree_bits != degree_bits as usize {
+        return Err(ProverError::InvalidInput(format!(
+            "outer gate config has degree {gate_degree_bits}, config \
+             requires {degree_bits}"
+        )));
+    }
+    let lookup_bits = universal::UniversalOuterCircuit::lookup_bits(config);
+    if gate_config.lookup_bits() != lookup_bits {
+        return Err(ProverError::InvalidInput(format!(
+            "outer gate config has lookup bits {}, config requires \
+             {lookup_bits}",
+            gate_config.lookup_bits()
+        )));
+    }
+    Ok(())
+}
+
+/// Computes an Outer proof, using the multi-open `scheme`, with an EVM
+/// transcript (to be verified on-chain), after checking the keys with
+/// [`outer_check_inputs`].  Returns the proof and its instance.
+pub fn outer_prove(
+    srs: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    config: &UpaConfig,
+    gate_config: &OuterGateConfig,
+    break_points: MultiPhaseThreadBreakPoints,
+    inputs: UniversalOuterCircuitInputs,
+    scheme: Scheme,
+) -> Result<(Vec<u8>, Vec<Fr>), ProverError> {
+    outer_check_inputs(pk, config, gate_config)?;
+    info!("Computing Outer proof...");
+    let now = Instant::now();
+    // The circuit aggregates SHPLONK BV/Keccak proofs, so `AS` is always
+    // `SHPLONK`.  Only the outer proof itself uses the chosen scheme.
+    let proof_and_instance = match scheme {
+        Scheme::Shplonk => {
+            prove_outer::<
+                SHPLONK,
+                universal::UniversalOuterCircuit,
+                ProverSHPLONK<Bn256>,
+                VerifierSHPLONK<Bn256>,
+            >(config, gate_config, pk, break_points, inputs, srs)
+        }
+        Scheme::Gwc => {
+            prove_outer::<
+                SHPLONK,
+                universal::UniversalOuterCircuit,
+                ProverGWC<Bn256>,
+                VerifierGWC<Bn256>,
+            >(config, gate_config, pk, break_points, inputs, srs)
+        }
+    };
+    info!("Finished computing Outer proof in {:?}", now.elapsed());
+    Ok(proof_and_instance)
+}
+
+/// Computes an Outer SHPLONK proof with a Poseidon transcript, to be
+/// aggregated by the recursive outer circuit, after checking the keys with
+/// [`outer_check_inputs`].
+pub fn outer_prove_recursive(
+    srs: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    config: &UpaConfig,
+    gate_config: &OuterGateConfig,
+    break_points: MultiPhaseThreadBreakPoints,
+    inputs: UniversalOuterCircuitInputs,
+) -> Result<Snark, ProverError> {
+    outer_check_inputs(pk, config, gate_config)?;
+    info!("Computing Outer snark...");
+    let now = Instant::now();
+    let snark = prove_outer_snark::<
+        SHPLONK,
+        universal::UniversalOuterCircuit,
+        ProverSHPLONK<Bn256>,
+        VerifierSHPLONK<Bn256>,
+    >(config, gate_config, pk, break_points, inputs, srs);
+    info!("Finished computing Outer snark in {:?}", now.elapsed());
+    Ok(snark)
+}
+
+/// Verifies an Outer `proof` (computed by [`outer_prove`] with `scheme`)
+/// against `vk` and `instance`, including the deferred pairing check on the
+/// KZG accumulator (as performed by the EVM verifier).
+pub fn outer_verify(
+    srs: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    config: &UpaConfig,
+    scheme: Scheme,
+    proof: &[u8],
+    instance: &[Fr],
+) -> Result<(), ProverError> {
+    info!("Verifying Outer proof...");
+    let limb_bits = config.outer_config.limb_bits;
+    let num_limbs = config.outer_config.num_limbs;
+    let verified = match scheme {
+        Scheme::Shplonk => verify_outer::<VerifierSHPLONK<Bn256>>(
+            srs, vk, proof, instance, limb_bits, num_limbs,
+        ),
+        Scheme::Gwc => verify_outer::<VerifierGWC<Bn256>>(
+            srs, vk, proof, instance, limb_bits, num_limbs,
+        ),
+    };
+    match verified {
+        true => Ok(()),
+        false => Err(ProverError::InvalidInput(
+            "outer proof verification failed".to_string(),
+        )),
+    }
+}
+
+/// Verifies an Outer `snark` computed by [`outer_prove_recursive`], including
+/// the deferred pairing check on the KZG accumulator.
+pub fn outer_verify_recursive(
+    srs: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    config: &UpaConfig,
+    snark: &Snark,
+) -> Result<(), ProverError> {
+    info!("Verifying Outer snark...");
+    match verify_outer_snark::<VerifierSHPLONK<Bn256>>(
+        srs,
+        vk,
+        snark,
+        config.outer_config.limb_bits,
+        config.outer_config.num_limbs,
+    ) {
+        true => Ok(()),
+        false => Err(ProverError::InvalidInput(
+            "outer proof verification failed".to_string(),
+        )),
+    }
+}
+
+/// Runs `f` (typically a closure calling the functions of this module) on
+/// the tokio blocking thread pool, so that proving does not block the async
+/// runtime.  Panics in `f` are propagated to the caller.
+#[cfg(feature = "async")]
+pub async fn spawn_blocking<R, F>(f: F) -> R
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) => match e.try_into_panic() {
+            Ok(panic) => std::panic::resume_unwind(panic),
+            Err(e) => panic!("blocking task failed: {e}"),
+        },
+    }
+}
+
+/// Generates the keys of the keygen `circuit` (named `name` in logs).
+pub(crate) fn keygen_circuit<'a, C>(
+    srs: &ParamsKZG<Bn256>,
+    circuit: &C,
+    name: &str,
+) -> CircuitKeys<C::GateConfig>
+where
+    C: SafeCircuit<'a, Fr, G1Affine> + CircuitExt<Fr>,
+    C::GateConfig: Clone,
+{
+    info!("Generating {name} VK ...");
+    let now = Instant::now();
+    let vk = keygen_vk(srs, circuit)
+        .unwrap_or_else(|e| panic!("VK generation failed: {e}"));
+    info!("Finished generating {name} VK in {:?}", now.elapsed());
+
+    info!("compiling VK to Protocol ...");
+    let protocol = compile(
+        srs,
+        &vk,
+        Config::kzg()
+            .with_num_instance(circuit.num_instance())
+            .with_accumulator_indices(C::accumulator_indices()),
+    );
+
+    info!("Generating {name} PK ...");
+    let now = Instant::now();
+    let pk = keygen_pk(srs, vk, circuit)
+        .unwrap_or_else(|e| panic!("PK generation failed: {e}"));
+    info!("Finished generating {name} PK in {:?}", now.elapsed());
+
+    CircuitKeys {
+        pk,
+        gate_config: circuit.gate_config().clone(),
+        break_points: circuit.break_points(),
+        protocol,
+    }
+}
+
+/// Creates a SHPLONK proof, with a Poseidon transcript, of `circuit` with
+/// the single instance column `instance`.
+fn create_shplonk_proof<C: Circuit<Fr>>(
+    srs: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: C,
+    instance: &[Fr],
+) -> Vec<u8> {
+    let mut transcript =
+        PoseidonTranscript::<NativeLoader, _>::new::<SECURE_MDS>(vec![]);
+    create_proof::<
+        KZGCommitmentScheme<Bn256>,
+        ProverSHPLONK<'_, Bn256>,
+        ChallengeScalar<G1Affine>,
+        _,
+        _,
+        _,
+    >(srs, pk, &[circuit], &[&[instance]], OsRng, &mut transcript)
+    .unwrap();
+    transcript.finalize()
+}
+
+/// Verifies a SHPLONK `proof`, with a Poseidon transcript, of the circuit
+/// (named `name` in logs) with verifying key `vk` and the single instance
+/// column `instance`.
+fn verify_shplonk_proof(
+    srs: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proof: &[u8],
+    instance: &[Fr],
+    name: &str,
+) -> Result<(), ProverError> {
+    info!("Verifying {name} proof...");
+    let now = Instant::now();
+    let mut transcript =
+        PoseidonTranscript::<NativeLoader, _>::new::<SECURE_MDS>(proof);
+    verify_proof::<_, VerifierSHPLONK<'_, Bn256>, ChallengeScalar<_>, _, _>(
+        srs.verifier_params(),
+        vk,
+        SingleStrategy::new(srs),
+        &[&[instance]],
+        &mut transcript,
+    )
+    .map_err(|e| {
+        ProverError::InvalidInput(format!("proof verification failed: {e}"))
+    })?;
+    info!("Finished verifying {name} proof in {:?}", now.elapsed());
+    Ok(())
+}
@@ -0,0 +1,470 @@
+//! The `bench` command: runs keygen, prove and verify for each circuit of the
+//! UPA pipeline, and reports the duration and peak memory of each stage.
+
+use crate::{
+    default_values::{
+        KECCAK_GATE_CONFIG, KECCAK_PK, KECCAK_PROOF, KECCAK_PROTOCOL,
+        KECCAK_SRS, KECCAK_VK, OUTER_GATE_CONFIG, OUTER_INSTANCE_SIZE,
+        OUTER_PK, OUTER_PROOF, OUTER_PROTOCOL, OUTER_SRS, OUTER_VERIFIER_YUL,
+        OUTER_VK, UBV_GATE_CONFIG, UBV_PK, UBV_PROOF_BASE, UBV_PROTOCOL,
+        UBV_SRS, UBV_VK, UPA_CONFIG,
+    },
+    error::ProverError,
+    file_utils::{instance_file, load_upa_config, save_pretty_json_file},
+    keccak, universal_batch_verifier, universal_outer,
+};
+use circuits::{
+    batch_verify::{
+        common::native::unsafe_proof_generation::{
+            create_proofs_and_inputs, UnsafeVerificationKey,
+        },
+        universal::native::json::JsonUniversalBatchVerifierInput,
+    },
+    utils::upa_config::UpaConfig,
+};
+use clap::Parser;
+use log::{info, warn};
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    slice,
+    time::Instant,
+};
+
+/// Default directory for the keys, proofs and report written by `bench`
+const BENCH_DIR: &str = "bench";
+
+/// Name of the default report file in the output directory
+const BENCH_REPORT: &str = "report.json";
+
+#[derive(Debug, Parser)]
+pub struct BenchParams {
+    /// UPA configuration file
+    #[arg(short = 'c', long, value_name = "config-file", default_value = UPA_CONFIG)]
+    config: String,
+
+    /// UBV SRS file
+    #[arg(long, value_name = "ubv-srs-file", default_value = UBV_SRS)]
+    ubv_srs: String,
+
+    /// Keccak SRS file
+    #[arg(long, value_name = "keccak-srs-file", default_value = KECCAK_SRS)]
+    keccak_srs: String,
+
+    /// Outer SRS file
+    #[arg(long, value_name = "outer-srs-file", default_value = OUTER_SRS)]
+    outer_srs: String,
+
+    /// Number of application proofs to generate (defaults to
+    /// inner_batch_size * outer_batch_size).  Any remaining slots of the
+    /// batches are filled by repeating these proofs.
+    #[arg(long, value_name = "num-proofs")]
+    num_proofs: Option<usize>,
+
+    /// Directory for the keys (in `keys/`), proofs (in `proofs/`) and
+    /// report.  The proofs and report of a previous run are removed.
+    #[arg(long, value_name = "output-dir", default_value = BENCH_DIR)]
+    output_dir: String,
+
+    /// Reuse the keys in <output-dir>/keys from a previous run instead of
+    /// running keygen.
+    #[arg(long)]
+    skip_keygen: bool,
+
+    /// Output JSON report file (defaults to <output-dir>/report.json).  The
+    /// report is not printed, since stdout also carries the timing traces of
+    /// the circuits.
+    #[arg(long, value_name = "report-file")]
+    report: Option<String>,
+
+    /// Seed for the RNG used to generate the application proofs
+    #[arg(long, value_name = "seed", default_value_t = 0)]
+    seed: u64,
+
+    /// Number of threads used by each stage (defaults to all available
+    /// cores)
+    #[arg(long, value_name = "num-threads")]
+    threads: Option<usize>,
+}
+
+/// Duration and peak memory of a single stage.
+#[derive(Debug, Serialize)]
+struct StageReport {
+    stage: String,
+    duration_secs: f64,
+    /// Peak resident set size during the stage, where the platform allows
+    /// it to be measured.
+    peak_rss_kib: Option<u64>,
+}
+
+/// The JSON report written by `bench`.
+#[derive(Debug, Serialize)]
+struct BenchReport {
+    config: UpaConfig,
+    num_proofs: usize,
+    skip_keygen: bool,
+    stages: Vec<StageReport>,
+}
+
+/// Locations of the files used by `bench`.
+struct BenchFiles {
+    keys_dir: PathBuf,
+    proofs_dir: PathBuf,
+    default_report: PathBuf,
+}
+
+impl BenchFiles {
+    /// Creates the key and proof directories under `output_dir`, removing
+    /// the proofs and default report of any previous run.
+    fn new(output_dir: &str) -> Result<Self, ProverError> {
+        let keys_dir = Path::new(output_dir).join("keys");
+        let proofs_dir = Path::new(output_dir).join("proofs");
+        let default_report = Path::new(output_dir).join(BENCH_REPORT);
+        if proofs_dir.exists() {
+            fs::remove_dir_all(&proofs_dir).map_err(|e| {
+                ProverError::io(&proofs_dir.to_string_lossy(), e)
+            })?;
+        }
+        if default_report.exists() {
+            fs::remove_file(&default_report).map_err(|e| {
+                ProverError::io(&default_report.to_string_lossy(), e)
+            })?;
+        }
+        for dir in [&keys_dir, &proofs_dir] {
+            fs::create_dir_all(dir)
+                .map_err(|e| ProverError::io(&dir.to_string_lossy(), e))?;
+        }
+        Ok(Self {
+            keys_dir,
+            proofs_dir,
+            default_report,
+        })
+    }
+
+    fn key(&self, name: &str) -> String {
+        self.keys_dir.join(name).to_string_lossy().into_owned()
+    }
+
+    fn proof(&self, name: &str) -> String {
+        self.proofs_dir.join(name).to_string_lossy().into_owned()
+    }
+
+    fn app_batch(&self, batch_number: u32) -> String {
+        self.proof(&format!("app_batch{batch_number}.json"))
+    }
+
+    fn ubv_proof(&self, batch_number: u32) -> String {
+        self.proof(&format!("{UBV_PROOF_BASE}{batch_number}"))
+    }
+}
+
+/// Returns the peak resident set size of this process in KiB, as reported
+/// by `/proc/self/status` (Linux only).
+fn peak_rss_kib() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| {
+            value.trim().trim_end_matches("kB").trim().parse().ok()
+        })
+}
+
+/// Resets the peak resident set size of this process, so that it can be
+/// measured per stage (Linux only).
+fn reset_peak_rss() {
+    if let Err(e) = fs::write("/proc/self/clear_refs", "5") {
+        warn!("failed to reset peak memory usage: {e}");
+    }
+}
+
+/// Runs `f` as the stage `stage`, recording its duration and peak memory
+/// usage in `stages`.
+fn run_stage(
+    stages: &mut Vec<StageReport>,
+    stage: &str,
+    f: impl FnOnce() -> Result<(), ProverError>,
+) -> Result<(), ProverError> {
+    info!("bench: running {stage}");
+    reset_peak_rss();
+    let now = Instant::now();
+    f()?;
+    let duration = now.elapsed();
+    info!("bench: {stage} took {duration:?}");
+    stages.push(StageReport {
+        stage: stage.to_string(),
+        duration_secs: duration.as_secs_f64(),
+        peak_rss_kib: peak_rss_kib(),
+    });
+    Ok(())
+}
+
+/// Generates `num_proofs` application proofs for a single fake VK and writes
+/// them, in batches of `inner_batch_size`, to `outer_batch_size` batch
+/// files.
+fn generate_app_batches(
+    files: &BenchFiles,
+    config: &UpaConfig,
+    num_proofs: usize,
+    seed: u64,
+) -> Result<(), ProverError> {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let uvk = UnsafeVerificationKey::sample(
+        config.max_num_app_public_inputs as usize,
+        false,
+        &mut rng,
+    );
+    let proofs_and_inputs = create_proofs_and_inputs(
+        slice::from_ref(&uvk),
+        num_proofs,
+        Some(seed),
+        true,
+    );
+    let entries: Vec<JsonUniversalBatchVerifierInput> = proofs_and_inputs
+        .iter()
+        .cycle()
+        .take((config.inner_batch_size * config.outer_batch_size) as usize)
+        .map(|(proof, inputs)| JsonUniversalBatchVerifierInput {
+            vk: uvk.vk().into(),
+            proof: proof.into(),
+            inputs: inputs.into(),
+        })
+        .collect();
+    for (i, batch) in
+        entries.chunks(config.inner_batch_size as usize).enumerate()
+    {
+        save_pretty_json_file(&files.app_batch(i as u32), &batch)?;
+    }
+    Ok(())
+}
+
+/// Entry point to the `bench` command.  Runs each stage with the existing
+/// keygen, prove and verify commands and writes a JSON report.
+pub fn bench(params: BenchParams) -> Result<(), ProverError> {
+    let config = load_upa_config(&params.config)?;
+    let total_proofs =
+        (config.inner_batch_size * config.outer_batch_size) as usize;
+    let num_proofs = params.num_proofs.unwrap_or(total_proofs);
+    if num_proofs == 0 || num_proofs > total_proofs {
+        return Err(ProverError::InvalidInput(format!(
+            "num-proofs must be in 1..={total_proofs}"
+        )));
+    }
+    let files = BenchFiles::new(&params.output_dir)?;
+    let mut stages = Vec::new();
+
+    run_stage(&mut stages, "app_proofs", || {
+        generate_app_batches(&files, &config, num_proofs, params.seed)
+    })?;
+
+    // UBV
+    if !params.skip_keygen {
+        run_stage(&mut stages, "ubv_keygen", || {
+            universal_batch_verifier::keygen(
+                universal_batch_verifier::KeygenParams {
+                    config: params.config.clone(),
+                    srs: params.ubv_srs.clone(),
+                    output_dir: None,
+                    proving_key: Some(files.key(UBV_PK)),
+                    verification_key: Some(files.key(UBV_VK)),
+                    protocol: Some(files.key(UBV_PROTOCOL)),
+                    gate_config: Some(files.key(UBV_GATE_CONFIG)),
+                    report_cells: None,
+                    dry_run: false,
+                    threads: params.threads,
+                },
+            )
+        })?;
+    }
+    run_stage(&mut stages, "ubv_prove", || {
+        for i in 0..config.outer_batch_size {
+            universal_batch_verifier::prove(
+                universal_batch_verifier::ProveParams {
+                    config: params.config.clone(),
+                    srs: params.ubv_srs.clone(),
+                    proving_key: files.key(UBV_PK),
+                    gate_config: files.key(UBV_GATE_CONFIG),
+                    app_vk_proof_batch: files.app_batch(i),
+                    input_format: universal_batch_verifier::InputFormat::Native,
+                    proof: files.ubv_proof(i),
+                    instance: None,
+                    dry_run: false,
+                    verify_after_prove: false,
+                    threads: params.threads,
+                    reject_duplicate_entries: false,
+                    entries: None,
+                    jsonl: false,
+                },
+            )?;
+        }
+        Ok(())
+    })?;
+    run_stage(&mut stages, "ubv_verify", || {
+        for i in 0..config.outer_batch_size {
+            universal_batch_verifier::verify(
+                universal_batch_verifier::VerifyParams {
+                    srs: params.ubv_srs.clone(),
+                    verification_key: files.key(UBV_VK),
+                    gate_config: files.key(UBV_GATE_CONFIG),
+                    proof: files.ubv_proof(i),
+                    instance: None,
+                    dry_run: false,
+                    allowed_circuit_ids: None,
+                    config: params.config.clone(),
+                    domain_version: Default::default(),
+                },
+            )?;
+        }
+        Ok(())
+    })?;
+
+    let ubv_proofs: Vec<String> = (0..config.outer_batch_size)
+        .map(|i| files.ubv_proof(i))
+        .collect();
+    let ubv_instances: Vec<String> = ubv_proofs
+        .iter()
+        .map(|proof| instance_file(None, proof))
+        .collect();
+
+    // Keccak
+    if !params.skip_keygen {
+        run_stage(&mut stages, "keccak_keygen", || {
+            keccak::keygen(keccak::KeygenParams {
+                config: params.config.clone(),
+                srs: params.keccak_srs.clone(),
+                output_dir: None,
+                proving_key: Some(files.key(KECCAK_PK)),
+                verification_key: Some(files.key(KECCAK_VK)),
+                protocol: Some(files.key(KECCAK_PROTOCOL)),
+                gate_config: Some(files.key(KECCAK_GATE_CONFIG)),
+                report_cells: None,
+                dry_run: false,
+                threads: params.threads,
+            })
+        })?;
+    }
+    run_stage(&mut stages, "keccak_prove", || {
+        keccak::prove(keccak::ProveParams {
+            config: params.config.clone(),
+            srs: params.keccak_srs.clone(),
+            proving_key: files.key(KECCAK_PK),
+            gate_config: files.key(KECCAK_GATE_CONFIG),
+            ubv_instances: ubv_instances.clone(),
+            num_proof_ids: config
+                .output_submission_id
+                .then_some(num_proofs as u64),
+            proof: files.proof(KECCAK_PROOF),
+            instance: None,
+            self_check: false,
+            verify_after_prove: false,
+            concurrent_load: false,
+            dry_run: false,
+            threads: params.threads,
+        })
+    })?;
+    run_stage(&mut stages, "keccak_verify", || {
+        keccak::verify(keccak::VerifyParams {
+            srs: params.keccak_srs.clone(),
+            verification_key: files.key(KECCAK_VK),
+            gate_config: files.key(KECCAK_GATE_CONFIG),
+            proof: files.proof(KECCAK_PROOF),
+            instance: None,
+            dry_run: false,
+        })
+    })?;
+
+    // Outer
+    if !params.skip_keygen {
+        run_stage(&mut stages, "outer_keygen", || {
+            universal_outer::keygen(universal_outer::KeygenParams {
+                config: params.config.clone(),
+                outer_srs: params.outer_srs.clone(),
+                bv_srs: params.ubv_srs.clone(),
+                keccak_srs: params.keccak_srs.clone(),
+                output_dir: None,
+                proving_key: Some(files.key(OUTER_PK)),
+                verification_key: Some(files.key(OUTER_VK)),
+                protocol: Some(files.key(OUTER_PROTOCOL)),
+                gate_config: Some(files.key(OUTER_GATE_CONFIG)),
+                num_instance: Some(files.key(OUTER_INSTANCE_SIZE)),
+                manifest: None,
+                scheme: universal_outer::Scheme::Shplonk,
+                vk_only: false,
+                checkpoint: None,
+                resume: false,
+                dry_run: false,
+                threads: params.threads,
+            })?;
+            universal_outer::generate_evm_verifier(
+                universal_outer::GenerateVerifierParams {
+                    outer_srs: params.outer_srs.clone(),
+                    gate_config: files.key(OUTER_GATE_CONFIG),
+                    verification_key: files.key(OUTER_VK),
+                    num_instance: files.key(OUTER_INSTANCE_SIZE),
+                    yul: files.key(OUTER_VERIFIER_YUL),
+                    scheme: universal_outer::Scheme::Shplonk,
+                },
+            )
+        })?;
+    }
+    run_stage(&mut stages, "outer_prove", || {
+        universal_outer::prove(universal_outer::ProveParams {
+            config: params.config.clone(),
+            bv_protocol: files.key(UBV_PROTOCOL),
+            srs: params.outer_srs.clone(),
+            gate_config: files.key(OUTER_GATE_CONFIG),
+            proving_key: files.key(OUTER_PK),
+            ubv_proofs: ubv_proofs.clone(),
+            ubv_instances: Some(ubv_instances.clone()),
+            keccak_proof: files.proof(KECCAK_PROOF),
+            keccak_instance: None,
+            keccak_protocol: files.key(KECCAK_PROTOCOL),
+            proof: files.proof(OUTER_PROOF),
+            instance: None,
+            calldata: None,
+            abi_encode: false,
+            selector: None,
+            mmap_pk: false,
+            concurrent_load: false,
+            manifest: None,
+            recursive: false,
+            scheme: universal_outer::Scheme::Shplonk,
+            verify_after_prove: false,
+            dry_run: false,
+            threads: params.threads,
+        })
+    })?;
+    run_stage(&mut stages, "outer_verify", || {
+        universal_outer::verify(universal_outer::VerifyParams {
+            verifier_yul: files.key(OUTER_VERIFIER_YUL),
+            proof: files.proof(OUTER_PROOF),
+            instance: None,
+            config: params.config.clone(),
+            calldata: None,
+            proof_ids: None,
+            manifest: None,
+            verification_key: files.key(OUTER_VK),
+            gate_config: files.key(OUTER_GATE_CONFIG),
+            outer_srs: params.outer_srs.clone(),
+            onchain_equivalent: false,
+            vk_onchain: None,
+            scheme: universal_outer::Scheme::Shplonk,
+            dry_run: false,
+        })
+    })?;
+
+    let report = BenchReport {
+        config,
+        num_proofs,
+        skip_keygen: params.skip_keygen,
+        stages,
+    };
+    let report_file = params
+        .report
+        .unwrap_or_else(|| files.default_report.to_string_lossy().into_owned());
+    save_pretty_json_file(&report_file, &report)?;
+    info!("bench: report written to {report_file}");
+    Ok(())
+}
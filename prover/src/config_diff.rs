@@ -0,0 +1,81 @@
+use crate::{error::ProverError, file_utils::load_upa_config};
+use circuits::utils::upa_config::UpaConfig;
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+pub struct ConfigDiffParams {
+    /// UPA configuration file the existing keys were generated for
+    config_a: String,
+
+    /// New UPA configuration file
+    config_b: String,
+}
+
+/// Entry point to the `config-diff` subcommand.  Prints the fields which
+/// differ between the two UPA configs, and whether keys can be reused.
+pub fn config_diff(params: ConfigDiffParams) -> Result<(), ProverError> {
+    let config_a = load_upa_config(&params.config_a)?;
+    let config_b = load_upa_config(&params.config_b)?;
+    print!("{}", format_config_diff(&config_a, &config_b));
+    Ok(())
+}
+
+/// Returns one line per field differing between `config_a` and
+/// `config_b`, followed by whether keys are reusable.
+fn format_config_diff(config_a: &UpaConfig, config_b: &UpaConfig) -> String {
+    let mut output = String::new();
+    for field_diff in config_a.diff(config_b) {
+        output.push_str(&format!("{field_diff}\n"));
+    }
+    let reusable = match config_a.key_compatible_with(config_b) {
+        true => "yes",
+        false => "no",
+    };
+    output.push_str(&format!("Keys reusable: {reusable}\n"));
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_config_diff;
+    use circuits::utils::upa_config::UpaConfig;
+
+    /// Checks the `config-diff` output for identical and different configs.
+    ///
+    /// CMD: `cargo test --package prover -- config_diff::tests::config_diff_output --exact --nocapture`
+    #[test]
+    fn config_diff_output() {
+        let config: UpaConfig = serde_json::from_str(
+            r#"{
+                "max_num_app_public_inputs": 8,
+                "inner_batch_size": 4,
+                "outer_batch_size": 2,
+                "bv_config": {
+                    "degree_bits": 21, "lookup_bits": 20,
+                    "limb_bits": 88, "num_limbs": 3
+                },
+                "keccak_config": { "degree_bits": 20, "lookup_bits": 19 },
+                "outer_config": {
+                    "degree_bits": 22, "lookup_bits": 21,
+                    "limb_bits": 88, "num_limbs": 3
+                },
+                "output_submission_id": true
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            format_config_diff(&config, &config),
+            "Keys reusable: yes\n"
+        );
+
+        let mut other = config;
+        other.outer_batch_size = 3;
+        other.output_submission_id = false;
+        assert_eq!(
+            format_config_diff(&config, &other),
+            "outer_batch_size: 2 -> 3\n\
+             output_submission_id: true -> false\n\
+             Keys reusable: no\n"
+        );
+    }
+}
@@ -14,6 +14,7 @@ pub const BV_SRS: &str = "bv.srs";
 // case since we'd want to change it back anyway when we drop the "universal"
 // qualifiers.
 pub const UBV_SRS: &str = "bv.srs";
+pub const UBV_CIRCUIT_NAME: &str = "ubv";
 pub const UBV_PK: &str = "ubv.pk";
 pub const UBV_VK: &str = "ubv.vk";
 pub const UBV_GATE_CONFIG: &str = "ubv.specs";
@@ -21,6 +22,7 @@ pub const UBV_PROTOCOL: &str = "ubv.protocol";
 pub const UBV_PROOF_BASE: &str = "ubv.proof";
 
 pub const KECCAK_SRS: &str = "keccak.srs";
+pub const KECCAK_CIRCUIT_NAME: &str = "keccak";
 pub const KECCAK_PK: &str = "keccak.pk";
 pub const KECCAK_VK: &str = "keccak.vk";
 pub const KECCAK_GATE_CONFIG: &str = "keccak.specs";
@@ -28,6 +30,7 @@ pub const KECCAK_PROTOCOL: &str = "keccak.protocol";
 pub const KECCAK_PROOF: &str = "keccak.proof";
 
 pub const OUTER_SRS: &str = "outer.srs";
+pub const OUTER_CIRCUIT_NAME: &str = "outer";
 pub const OUTER_PK: &str = "outer.pk";
 pub const OUTER_VK: &str = "outer.vk";
 pub const OUTER_GATE_CONFIG: &str = "outer.specs";
@@ -36,3 +39,11 @@ pub const OUTER_INSTANCE_SIZE: &str = "outer.instance_size";
 pub const OUTER_VERIFIER_YUL: &str = "outer.verifier.yul";
 pub const OUTER_VERIFIER_BIN: &str = "outer.verifier.bin";
 pub const OUTER_PROOF: &str = "outer.proof";
+
+pub const RECURSIVE_CONFIG: &str = "recursive_config.json";
+pub const RECURSIVE_SRS: &str = "recursive.srs";
+pub const RECURSIVE_CIRCUIT_NAME: &str = "recursive";
+pub const RECURSIVE_PK: &str = "recursive.pk";
+pub const RECURSIVE_VK: &str = "recursive.vk";
+pub const RECURSIVE_GATE_CONFIG: &str = "recursive.specs";
+pub const RECURSIVE_INSTANCE_SIZE: &str = "recursive.instance_size";
@@ -0,0 +1,95 @@
+use circuits::utils::upa_config::UpaConfigError;
+use core::fmt;
+use std::io;
+
+/// User-facing failure of a prover command, such as a missing or malformed
+/// input file.  Violated circuit invariants are still reported by panicking.
+#[derive(Debug)]
+pub enum ProverError {
+    /// Reading or writing the file `path` failed.
+    Io { path: String, source: io::Error },
+    /// The file `path` does not hold valid JSON of the expected type.
+    Json {
+        path: String,
+        source: serde_json::Error,
+    },
+    /// The file `path` could not be decoded as a `desc` (e.g. a proving
+    /// key).
+    Decode {
+        path: String,
+        desc: &'static str,
+        reason: String,
+    },
+    /// Refusing to overwrite the existing file `path`.
+    FileExists(String),
+    /// The UPA config in the file `path` is invalid.
+    InvalidConfig {
+        path: String,
+        source: UpaConfigError,
+    },
+    /// The command line arguments or inputs are inconsistent.
+    InvalidInput(String),
+    /// Key generation failed, or a circuit failed its self-check.
+    Circuit(String),
+}
+
+impl ProverError {
+    pub fn io(path: &str, source: io::Error) -> Self {
+        ProverError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn json(path: &str, source: serde_json::Error) -> Self {
+        ProverError::Json {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn decode(
+        path: &str,
+        desc: &'static str,
+        reason: impl fmt::Display,
+    ) -> Self {
+        ProverError::Decode {
+            path: path.to_string(),
+            desc,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ProverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProverError::Io { path, source } => write!(f, "{path}: {source}"),
+            ProverError::Json { path, source } => {
+                write!(f, "{path}: invalid JSON: {source}")
+            }
+            ProverError::Decode { path, desc, reason } => {
+                write!(f, "{path}: error reading {desc}: {reason}")
+            }
+            ProverError::FileExists(path) => {
+                write!(f, "refusing to overwrite file {path}")
+            }
+            ProverError::InvalidConfig { path, source } => {
+                write!(f, "invalid UPA config {path}: {source}")
+            }
+            ProverError::InvalidInput(msg) => write!(f, "{msg}"),
+            ProverError::Circuit(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for ProverError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProverError::Io { source, .. } => Some(source),
+            ProverError::Json { source, .. } => Some(source),
+            ProverError::InvalidConfig { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -1,29 +1,50 @@
+use crate::error::ProverError;
 use circuits::{
     batch_verify::universal::{
         native::json::JsonUniversalBatchVerifierInput,
         types::UniversalBatchVerifierInput,
     },
-    utils::{field_elements_hex, file::load_json},
-    EccPrimeField,
+    read_proving_key_mmap,
+    utils::{field_elements_hex, upa_config::UpaConfig},
+    EccPrimeField, SafeCircuit,
 };
+use ethers_core::utils::keccak256;
 use halo2_base::{
     gates::builder::MultiPhaseThreadBreakPoints,
     halo2_proofs::{
-        halo2curves::bn256::{Bn256, G1Affine},
+        halo2curves::bn256::{Bn256, Fr, G1Affine},
         plonk::{ProvingKey, VerifyingKey},
         poly::{commitment::Params, kzg::commitment::ParamsKZG},
-        SerdeFormat,
     },
 };
 use log::info;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use snark_verifier::verifier::plonk::PlonkProtocol;
 use std::{
-    fs::{File, OpenOptions},
-    io::{BufReader, BufWriter, Write},
-    path::Path,
+    ffi::OsStr,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Instant,
 };
 
+/// Set by the `--verbose` flag.  When set, loaded configs are printed.
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+/// Set by the `--compact-json` flag.  When set, sidecar files are written as
+/// compact JSON (see [`write_json_pretty`]).
+static COMPACT_JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_compact_json(compact_json: bool) {
+    COMPACT_JSON.store(compact_json, Ordering::Relaxed);
+}
+
 #[derive(Debug, Serialize)]
 struct InstanceSerializeHelper<'a, F: EccPrimeField<Repr = [u8; 32]>> {
     #[serde(with = "field_elements_hex")]
@@ -36,40 +57,163 @@ struct InstanceDeserializeHelper<F: EccPrimeField<Repr = [u8; 32]>> {
     pub(crate) instance: Vec<F>,
 }
 
-/// Fail with a warning if the given path already exists.
-pub fn panic_if_file_exists(path: &str) {
+/// Fail if the given path already exists.
+pub fn fail_if_file_exists(path: &str) -> Result<(), ProverError> {
     if Path::new(path).exists() {
-        panic!("Refusing to overwrite file {path}");
+        return Err(ProverError::FileExists(path.to_string()));
     }
+    Ok(())
 }
 
-pub fn open_file_for_read(path: &str) -> BufReader<File> {
+pub fn open_file_for_read(path: &str) -> Result<BufReader<File>, ProverError> {
     let f = OpenOptions::new()
         .read(true)
         .open(path)
-        .unwrap_or_else(|e| panic!("failed to open for read {path}: {e}"));
-    BufReader::new(f)
+        .map_err(|e| ProverError::io(path, e))?;
+    Ok(BufReader::new(f))
 }
 
-/// Create a new file. Panic if the file already exists.
-pub fn create_file_no_overwrite(path: &str) -> File {
-    return OpenOptions::new()
+/// Create a new file. Fail if the file already exists.
+pub fn create_file_no_overwrite(path: &str) -> Result<File, ProverError> {
+    OpenOptions::new()
         .write(true)
         .create_new(true)
         .open(path)
-        .unwrap_or_else(|e| panic!("failed to create file {path}: {e}"));
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => {
+                ProverError::FileExists(path.to_string())
+            }
+            _ => ProverError::io(path, e),
+        })
 }
 
-/// Create a new file. Panic if the file already exists.
-pub fn create_file_buffer_no_overwrite(path: &str) -> BufWriter<File> {
-    BufWriter::new(create_file_no_overwrite(path))
+/// Create a new file. Fail if the file already exists.
+pub fn create_file_buffer_no_overwrite(
+    path: &str,
+) -> Result<BufWriter<File>, ProverError> {
+    Ok(BufWriter::new(create_file_no_overwrite(path)?))
 }
 
-/// Return the break-points file, given the proving key file
+/// Return the break-points file, given the proving key file.  For a
+/// proving key written to a keygen output directory (see [`KeygenOutputs`]),
+/// this is the canonical break-points file in the same directory.
 pub fn break_points_file(pk_file: &str) -> String {
+    let pk_path = Path::new(pk_file);
+    if pk_path.file_name() == Some(OsStr::new(OUTPUT_DIR_PK)) {
+        return pk_path
+            .with_file_name(OUTPUT_DIR_BREAK_POINTS)
+            .to_string_lossy()
+            .into_owned();
+    }
     format!("{pk_file}.bps")
 }
 
+/// Canonical proving key file name in a keygen output directory
+pub const OUTPUT_DIR_PK: &str = "pk.bin";
+/// Canonical verification key file name in a keygen output directory
+pub const OUTPUT_DIR_VK: &str = "vk.bin";
+/// Canonical gate config file name in a keygen output directory
+pub const OUTPUT_DIR_GATE_CONFIG: &str = "gate_config.json";
+/// Canonical break points file name in a keygen output directory
+pub const OUTPUT_DIR_BREAK_POINTS: &str = "break_points.json";
+/// Canonical manifest file name in a keygen output directory
+pub const OUTPUT_DIR_MANIFEST: &str = "manifest.json";
+/// Canonical protocol file name in a keygen output directory
+pub const OUTPUT_DIR_PROTOCOL: &str = "protocol.bin";
+/// Canonical num instance file name in a keygen output directory
+pub const OUTPUT_DIR_NUM_INSTANCE: &str = "num_instance.json";
+/// Canonical circuit config file name in a keygen output directory
+pub const OUTPUT_DIR_CONFIG: &str = "config.json";
+
+/// Returns a short (8 hex character) hash of the JSON serialization of
+/// `config`.
+pub fn config_short_hash<T: Serialize>(config: &T) -> String {
+    let config_json = serde_json::to_vec(config)
+        .unwrap_or_else(|e| panic!("failed to serialize config: {e}"));
+    hex::encode(&keccak256(config_json)[..4])
+}
+
+/// Returns the name of the keygen output directory for `circuit` (e.g.
+/// `ubv`) with configuration `config`.
+pub fn keygen_dir_name<T: Serialize>(circuit: &str, config: &T) -> String {
+    format!("{circuit}_{}", config_short_hash(config))
+}
+
+/// Manifest written to a keygen output directory, identifying the circuit
+/// and configuration the keys were generated for.
+#[derive(Debug, Serialize)]
+pub struct KeygenDirManifest<'a, T: Serialize> {
+    circuit: &'a str,
+    config_hash: String,
+    config: &'a T,
+}
+
+impl<'a, T: Serialize> KeygenDirManifest<'a, T> {
+    pub fn new(circuit: &'a str, config: &'a T) -> Self {
+        Self {
+            circuit,
+            config_hash: config_short_hash(config),
+            config,
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), ProverError> {
+        info!("writing keygen manifest: {path}");
+        write_json_pretty(path, self)
+    }
+}
+
+/// Locations of the files written by a `keygen` command.  Given an
+/// `--output-dir`, files default to their canonical names in the
+/// subdirectory `<circuit>_<config hash>` of it.  Explicitly given paths
+/// always take precedence.
+pub struct KeygenOutputs {
+    dir: Option<PathBuf>,
+}
+
+impl KeygenOutputs {
+    /// Creates the keygen output directory for `circuit` and `config` under
+    /// `output_dir`, if given.
+    pub fn new<T: Serialize>(
+        output_dir: Option<&str>,
+        circuit: &str,
+        config: &T,
+    ) -> Result<Self, ProverError> {
+        let dir = output_dir
+            .map(|output_dir| {
+                let dir = Path::new(output_dir)
+                    .join(keygen_dir_name(circuit, config));
+                info!("keygen output directory: {}", dir.display());
+                fs::create_dir_all(&dir)
+                    .map_err(|e| ProverError::io(&dir.to_string_lossy(), e))?;
+                Ok(dir)
+            })
+            .transpose()?;
+        Ok(Self { dir })
+    }
+
+    /// Returns `explicit` if given, otherwise the file `canonical` in the
+    /// output directory, or `default` if there is no output directory.
+    pub fn path(
+        &self,
+        explicit: &Option<String>,
+        canonical: &str,
+        default: &str,
+    ) -> String {
+        explicit
+            .clone()
+            .or_else(|| self.in_dir(canonical))
+            .unwrap_or_else(|| default.to_string())
+    }
+
+    /// Returns the file `canonical` in the output directory, if any.
+    pub fn in_dir(&self, canonical: &str) -> Option<String> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(canonical).to_string_lossy().into_owned())
+    }
+}
+
 /// Return the instance file accompanying a proof file
 pub fn instance_file(
     instance_file: Option<String>,
@@ -92,131 +236,455 @@ pub fn calldata_file(
     }
 }
 
-/// Convenience wrapper that prints an error on failure.
-pub fn save_pretty_json_file<T: Serialize>(path: &str, v: &T, desc: &str) {
-    let buf = create_file_buffer_no_overwrite(path);
-    serde_json::to_writer_pretty(buf, v)
-        .unwrap_or_else(|e| panic!("failed writing {desc}: {e}"))
+/// Writes `v` to the new file `path` as pretty-printed JSON.
+pub fn save_pretty_json_file<T: Serialize>(
+    path: &str,
+    v: &T,
+) -> Result<(), ProverError> {
+    let buf = create_file_buffer_no_overwrite(path)?;
+    serde_json::to_writer_pretty(buf, v).map_err(|e| ProverError::json(path, e))
 }
 
-/// Convenience wrapper that prints an error on failure.
-pub fn save_json_file<T: Serialize>(path: &str, v: &T, desc: &str) {
-    let buf = create_file_buffer_no_overwrite(path);
-    serde_json::to_writer(buf, v)
-        .unwrap_or_else(|e| panic!("failed writing {desc}: {e}"))
+/// Writes `v` to the new file `path` as JSON.
+pub fn save_json_file<T: Serialize>(
+    path: &str,
+    v: &T,
+) -> Result<(), ProverError> {
+    let buf = create_file_buffer_no_overwrite(path)?;
+    serde_json::to_writer(buf, v).map_err(|e| ProverError::json(path, e))
 }
 
-pub fn save_gate_config<T: Serialize>(path: &str, v: &T) {
+/// Writes `v` to the new file `path` as pretty-printed JSON, or as compact
+/// JSON if `--compact-json` was given.  Used for the human-inspectable
+/// sidecar files of the keys (gate configs, break points, manifests).
+pub fn write_json_pretty<T: Serialize>(
+    path: &str,
+    v: &T,
+) -> Result<(), ProverError> {
+    write_json(path, v, !COMPACT_JSON.load(Ordering::Relaxed))
+}
+
+fn write_json<T: Serialize>(
+    path: &str,
+    v: &T,
+    pretty: bool,
+) -> Result<(), ProverError> {
+    match pretty {
+        true => save_pretty_json_file(path, v),
+        false => save_json_file(path, v),
+    }
+}
+
+/// Writes `data` to the new file `path`.
+fn save_bytes(path: &str, data: &[u8]) -> Result<(), ProverError> {
+    let mut f = create_file_no_overwrite(path)?;
+    f.write_all(data).map_err(|e| ProverError::io(path, e))
+}
+
+pub fn save_gate_config<T: Serialize>(
+    path: &str,
+    v: &T,
+) -> Result<(), ProverError> {
     info!("writing gate config: {path}");
-    save_json_file(path, v, "gate config")
+    write_json_pretty(path, v)
 }
 
-pub fn save_vk(path: &str, vk: &VerifyingKey<G1Affine>) {
+/// Writes `vk` in the format expected by the `read_verifying_key` method of
+/// the circuit `C`.
+pub fn save_vk<'a, C: SafeCircuit<'a, Fr, G1Affine>>(
+    path: &str,
+    vk: &VerifyingKey<G1Affine>,
+) -> Result<(), ProverError> {
     info!("writing VK: {path}");
-    let mut buf = create_file_buffer_no_overwrite(path);
-    vk.write(&mut buf, SerdeFormat::RawBytesUnchecked)
-        .unwrap_or_else(|e| panic!("failed writing verification key: {e}"))
+    let mut buf = create_file_buffer_no_overwrite(path)?;
+    C::write_verifying_key(vk, &mut buf).map_err(|e| ProverError::io(path, e))
 }
 
-pub fn save_break_points(path: &str, bp: &MultiPhaseThreadBreakPoints) {
+pub fn save_break_points(
+    path: &str,
+    bp: &MultiPhaseThreadBreakPoints,
+) -> Result<(), ProverError> {
     info!("writing breakpoints: {path}");
-    save_json_file(path, bp, "breakpoints");
+    write_json_pretty(path, bp)
 }
 
-pub fn save_protocol(path: &str, protocol: &PlonkProtocol<G1Affine>) {
+pub fn save_protocol(
+    path: &str,
+    protocol: &PlonkProtocol<G1Affine>,
+) -> Result<(), ProverError> {
     info!("writing protocol: {path}");
-    let buf = create_file_buffer_no_overwrite(path);
-    bincode::serialize_into(buf, protocol)
-        .unwrap_or_else(|e| panic!("error writing protocol: {e}"))
+    let buf = create_file_buffer_no_overwrite(path)?;
+    bincode::serialize_into(buf, protocol).map_err(|e| {
+        ProverError::io(path, io::Error::new(io::ErrorKind::Other, e))
+    })
 }
 
-pub fn save_pk(path: &str, pk: &ProvingKey<G1Affine>) {
+/// Writes `pk` in the format expected by the `read_proving_key` method of
+/// the circuit `C`.
+pub fn save_pk<'a, C: SafeCircuit<'a, Fr, G1Affine>>(
+    path: &str,
+    pk: &ProvingKey<G1Affine>,
+) -> Result<(), ProverError> {
     info!("writing PK: {path}");
-    let mut buf = create_file_buffer_no_overwrite(path);
-    pk.write(&mut buf, SerdeFormat::RawBytesUnchecked)
-        .expect("failed writing proving key");
+    let mut buf = create_file_buffer_no_overwrite(path)?;
+    C::write_proving_key(pk, &mut buf).map_err(|e| ProverError::io(path, e))
 }
 
-pub fn save_proof(path: &str, proof: &[u8]) {
+pub fn save_proof(path: &str, proof: &[u8]) -> Result<(), ProverError> {
     info!("writing proof: {path}");
-    let mut f = create_file_no_overwrite(path);
-    f.write_all(proof)
-        .unwrap_or_else(|e| panic!("failed writing proof: {e}"));
+    save_bytes(path, proof)
 }
 
 /// Saves Yul code to file.
 ///
 /// (This is NOT converting to EVM bytecode.)
-pub fn save_yul(path: &str, yul_code: &str) {
+pub fn save_yul(path: &str, yul_code: &str) -> Result<(), ProverError> {
     info!("writing yul code: {path}");
-    let mut f = create_file_no_overwrite(path);
-    f.write_all(yul_code.as_bytes())
-        .unwrap_or_else(|e| panic!("failed writing yul code: {e}"));
+    save_bytes(path, yul_code.as_bytes())
+}
+
+pub fn save_csv(path: &str, csv: &str) -> Result<(), ProverError> {
+    info!("writing csv: {path}");
+    save_bytes(path, csv.as_bytes())
 }
 
 pub fn save_instance<F: EccPrimeField<Repr = [u8; 32]>>(
     path: &str,
     instance: &[F],
-) {
+) -> Result<(), ProverError> {
     info!("writing instance: {path}");
-    save_json_file(path, &InstanceSerializeHelper { instance }, "instance");
+    save_json_file(path, &InstanceSerializeHelper { instance })
 }
 
-pub fn save_calldata(path: &str, calldata: &[u8]) {
+pub fn save_calldata(path: &str, calldata: &[u8]) -> Result<(), ProverError> {
     info!("writing calldata: {path}");
-    let mut f = create_file_no_overwrite(path);
-    f.write_all(calldata)
-        .unwrap_or_else(|e| panic!("failed writing calldata: {e}"));
+    save_bytes(path, calldata)
+}
+
+/// Reads a JSON-encoded `T` from the file `path`.
+pub fn load_json_file<T: DeserializeOwned>(
+    path: &str,
+) -> Result<T, ProverError> {
+    serde_json::from_reader(open_file_for_read(path)?)
+        .map_err(|e| ProverError::json(path, e))
+}
+
+/// Reads the [`UpaConfig`] in `path` and checks that it is valid.
+pub fn load_upa_config(path: &str) -> Result<UpaConfig, ProverError> {
+    info!("loading UPA config {path} ...");
+    let config: UpaConfig = load_json_file(path)?;
+    config
+        .validate()
+        .map_err(|source| ProverError::InvalidConfig {
+            path: path.to_string(),
+            source,
+        })?;
+    if VERBOSE.load(Ordering::Relaxed) {
+        println!("UPA config {path}:\n{config}");
+    }
+    Ok(config)
 }
 
-pub fn load_gate_config<T: DeserializeOwned>(path: &str) -> T {
+pub fn load_gate_config<T: DeserializeOwned>(
+    path: &str,
+) -> Result<T, ProverError> {
     info!("loading gate config {path} ...");
-    load_json(path)
+    load_json_file(path)
+}
+
+/// Returns the hash of the VK in `vk_file`, or of the VK embedded in the PK
+/// in `pk_file`, for the circuit `C`.  Exactly one of the two files must be
+/// given.  The hash is the transcript representation of the VK, as recorded
+/// in the `vk_hash` of the outer keygen manifest.  halo2 computes it when
+/// the VK is built or read, as a digest of everything the verifier depends
+/// on (domain, constraint system, fixed and permutation commitments), so it
+/// needs no re-serialization of the VK and identifies it independently of
+/// the file format.
+pub fn load_vk_hash<'a, C: SafeCircuit<'a, Fr, G1Affine>>(
+    circuit_config: &C::CircuitConfig,
+    gate_config: &C::GateConfig,
+    vk_file: Option<&str>,
+    pk_file: Option<&str>,
+) -> Result<Fr, ProverError> {
+    match (vk_file, pk_file) {
+        (Some(vk_file), None) => {
+            info!("loading VK: {vk_file}");
+            let mut buf = open_file_for_read(vk_file)?;
+            let vk =
+                C::read_verifying_key(gate_config, &mut buf).map_err(|e| {
+                    ProverError::decode(vk_file, "verification key", e)
+                })?;
+            Ok(vk.transcript_repr())
+        }
+        (None, Some(pk_file)) => {
+            info!("loading PK: {pk_file}");
+            let mut buf = open_file_for_read(pk_file)?;
+            let pk = C::read_proving_key(circuit_config, gate_config, &mut buf)
+                .map_err(|e| ProverError::decode(pk_file, "proving key", e))?;
+            Ok(pk.get_vk().transcript_repr())
+        }
+        _ => Err(ProverError::InvalidInput(
+            "exactly one of the VK and PK files must be given".to_string(),
+        )),
+    }
 }
 
-pub fn load_srs(path: &str) -> ParamsKZG<Bn256> {
+/// Reads the PK of the circuit `C` from `proving_key` (memory-mapped if
+/// `mmap_pk`) and calls `load_inputs`.  If `concurrent`, `load_inputs` runs on
+/// a separate thread while the PK is read.
+///
+/// # Note
+///
+/// Reading the PK sets the gate config environment variables, which are read
+/// when constructing the circuit.  `load_inputs` must therefore not construct
+/// a circuit.  Both loads have completed when this function returns, so any
+/// circuit constructed afterwards sees the environment of the PK.
+pub(crate) fn load_pk_and_inputs<'a, C, T>(
+    config: &C::CircuitConfig,
+    gate_config: &C::GateConfig,
+    proving_key: &str,
+    mmap_pk: bool,
+    concurrent: bool,
+    load_inputs: impl FnOnce() -> Result<T, ProverError> + Send,
+) -> Result<(ProvingKey<G1Affine>, T), ProverError>
+where
+    C: SafeCircuit<'a, Fr, G1Affine>,
+    T: Send,
+{
+    let read_pk = || -> Result<ProvingKey<G1Affine>, ProverError> {
+        info!("reading PK {proving_key} ...");
+        let now = Instant::now();
+        let pk = if mmap_pk {
+            read_proving_key_mmap::<_, _, C>(config, gate_config, proving_key)
+        } else {
+            let mut buf = open_file_for_read(proving_key)?;
+            C::read_proving_key(config, gate_config, &mut buf)
+        }
+        .map_err(|e| ProverError::decode(proving_key, "proving key", e))?;
+        info!("Finished reading PK in {:?}", now.elapsed());
+        Ok(pk)
+    };
+
+    if !concurrent {
+        let pk = read_pk()?;
+        return Ok((pk, load_inputs()?));
+    }
+    thread::scope(|s| {
+        let inputs = s.spawn(load_inputs);
+        let pk = read_pk();
+        let inputs = inputs.join().expect("input loading thread panicked");
+        Ok((pk?, inputs?))
+    })
+}
+
+pub fn load_srs(path: &str) -> Result<ParamsKZG<Bn256>, ProverError> {
     info!("loading SRS {path}");
-    let mut buf = open_file_for_read(path);
+    let mut buf = open_file_for_read(path)?;
     ParamsKZG::<Bn256>::read(&mut buf)
-        .unwrap_or_else(|e| panic!("failed to read srs: {e}"))
+        .map_err(|e| ProverError::decode(path, "srs", e))
 }
 
-pub fn load_break_points(path: &str) -> MultiPhaseThreadBreakPoints {
+pub fn load_break_points(
+    path: &str,
+) -> Result<MultiPhaseThreadBreakPoints, ProverError> {
     info!("loading break points: {path}");
-    load_json(path)
+    load_json_file(path)
 }
 
-pub fn load_proof(path: &str) -> Vec<u8> {
+pub fn load_proof(path: &str) -> Result<Vec<u8>, ProverError> {
     info!("loading proof: {path}");
-    std::fs::read(path).unwrap_or_else(|e| panic!("error reading proof: {e}"))
+    fs::read(path).map_err(|e| ProverError::io(path, e))
 }
 
-pub fn load_instance<F: EccPrimeField<Repr = [u8; 32]>>(path: &str) -> Vec<F> {
+pub fn load_instance<F: EccPrimeField<Repr = [u8; 32]>>(
+    path: &str,
+) -> Result<Vec<F>, ProverError> {
     info!("loading instance: {path}");
-    let helper: InstanceDeserializeHelper<F> = load_json(path);
-    helper.instance
+    let helper: InstanceDeserializeHelper<F> = load_json_file(path)?;
+    Ok(helper.instance)
+}
+
+/// Load a JSON array of 32-byte proof ids, each given as a hex string
+/// (optionally `0x`-prefixed), as output by `compute-proof-id`.
+pub fn load_proof_ids(path: &str) -> Result<Vec<[u8; 32]>, ProverError> {
+    info!("loading proof ids: {path}");
+    load_hex_ids(path, "proof ids", "proof id")
+}
+
+/// Load a JSON array of 32-byte circuit ids, each given as a hex string
+/// (optionally `0x`-prefixed), as output by `compute-circuit-id`.
+pub fn load_circuit_ids(path: &str) -> Result<Vec<[u8; 32]>, ProverError> {
+    info!("loading circuit ids: {path}");
+    load_hex_ids(path, "circuit ids", "circuit id")
+}
+
+/// Load a JSON array of 32-byte ids given as (optionally `0x`-prefixed) hex
+/// strings.  `desc` and `name` describe the array and its elements in
+/// errors.
+fn load_hex_ids(
+    path: &str,
+    desc: &'static str,
+    name: &str,
+) -> Result<Vec<[u8; 32]>, ProverError> {
+    let ids: Vec<String> = load_json_file(path)?;
+    ids.iter()
+        .enumerate()
+        .map(|(i, id)| {
+            let id = id.strip_prefix("0x").unwrap_or(id);
+            hex::decode(id)
+                .map_err(|e| {
+                    ProverError::decode(
+                        path,
+                        desc,
+                        format!("invalid hex in {name} {i}: {e}"),
+                    )
+                })?
+                .try_into()
+                .map_err(|v: Vec<u8>| {
+                    ProverError::decode(
+                        path,
+                        desc,
+                        format!(
+                            "{name} {i} has {} bytes, expected 32",
+                            v.len()
+                        ),
+                    )
+                })
+        })
+        .collect()
 }
 
 pub fn load_app_vk_proof_inputs<F: EccPrimeField<Repr = [u8; 32]>>(
     path: &str,
-) -> UniversalBatchVerifierInput {
+) -> Result<UniversalBatchVerifierInput, ProverError> {
     info!("loading app vk, proof, inputs: {path}");
-    let json: JsonUniversalBatchVerifierInput = load_json(path);
-    (&json).into()
+    let json: JsonUniversalBatchVerifierInput = load_json_file(path)?;
+    Ok((&json).into())
 }
 
-pub fn load_protocol(path: &str) -> PlonkProtocol<G1Affine> {
-    let file = open_file_for_read(path);
+pub fn load_protocol(
+    path: &str,
+) -> Result<PlonkProtocol<G1Affine>, ProverError> {
+    let file = open_file_for_read(path)?;
     bincode::deserialize_from(file)
-        .unwrap_or_else(|e| panic!("error reading plonk protocol: {e}"))
+        .map_err(|e| ProverError::decode(path, "plonk protocol", e))
+}
+
+pub fn load_yul(path: &str) -> Result<String, ProverError> {
+    fs::read_to_string(path).map_err(|e| ProverError::io(path, e))
 }
 
-pub fn load_yul(path: &str) -> String {
-    std::fs::read_to_string(path)
-        .unwrap_or_else(|e| panic!("error reading yul code: {e}"))
+/// Load calldata from `path`, or from stdin if `path` is `-`.  The calldata
+/// may be given as raw bytes, or as a `0x`-prefixed hex string (possibly
+/// containing whitespace and newlines), as copied from a block explorer.
+pub fn load_calldata(path: &str) -> Result<Vec<u8>, ProverError> {
+    let data = if path == "-" {
+        info!("loading calldata from stdin");
+        let mut data = Vec::new();
+        io::stdin()
+            .read_to_end(&mut data)
+            .map_err(|e| ProverError::io(path, e))?;
+        data
+    } else {
+        info!("loading calldata: {path}");
+        fs::read(path).map_err(|e| ProverError::io(path, e))?
+    };
+    decode_calldata(data).map_err(|e| ProverError::decode(path, "calldata", e))
 }
 
-pub fn load_calldata(path: &str) -> Vec<u8> {
-    std::fs::read(path)
-        .unwrap_or_else(|e| panic!("error reading calldata: {e}"))
+/// Decodes `data` if it is a `0x`-prefixed hex string, otherwise returns it
+/// unchanged.  Raw calldata never starts with (whitespace followed by)
+/// `0x`, since the first 32-byte word is a big-endian scalar field element,
+/// so the detection is unambiguous.
+fn decode_calldata(data: Vec<u8>) -> Result<Vec<u8>, hex::FromHexError> {
+    let start = data
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(data.len());
+    match data[start..].strip_prefix(b"0x") {
+        Some(hex_data) => {
+            let hex_data: Vec<u8> = hex_data
+                .iter()
+                .filter(|b| !b.is_ascii_whitespace())
+                .copied()
+                .collect();
+            hex::decode(hex_data)
+        }
+        None => Ok(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{keygen_dir_name, load_json_file, write_json};
+    use crate::test_utils::test_upa_config;
+    use circuits::utils::upa_config::UpaConfig;
+    use halo2_base::gates::builder::MultiPhaseThreadBreakPoints;
+    use std::fs;
+
+    /// Checks that keygen output directories are distinct for distinct
+    /// configs, and stable for equal ones.
+    ///
+    /// CMD: `cargo test --package prover -- file_utils::tests::keygen_dir_name_depends_on_config --exact --nocapture`
+    #[test]
+    fn keygen_dir_name_depends_on_config() {
+        let config = test_upa_config();
+        let other_config = UpaConfig {
+            inner_batch_size: 8,
+            ..config
+        };
+        assert_eq!(
+            keygen_dir_name("ubv", &config),
+            keygen_dir_name("ubv", &test_upa_config())
+        );
+        assert_ne!(
+            keygen_dir_name("ubv", &config),
+            keygen_dir_name("ubv", &other_config)
+        );
+        assert_ne!(
+            keygen_dir_name("ubv", &config),
+            keygen_dir_name("keccak", &config)
+        );
+    }
+
+    /// Checks that sidecar files written as pretty and as compact JSON
+    /// deserialize to equal values.
+    ///
+    /// CMD: `cargo test --package prover -- file_utils::tests::pretty_and_compact_json_agree --exact --nocapture`
+    #[test]
+    fn pretty_and_compact_json_agree() {
+        let dir = std::env::temp_dir()
+            .join(format!("upa-sidecar-json-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let config = test_upa_config();
+        let break_points: MultiPhaseThreadBreakPoints =
+            vec![vec![1 << 20, 2 << 20, 3 << 20], vec![], vec![17]];
+
+        write_json(&path("config_pretty.json"), &config, true).unwrap();
+        write_json(&path("config_compact.json"), &config, false).unwrap();
+        write_json(&path("bps_pretty.json"), &break_points, true).unwrap();
+        write_json(&path("bps_compact.json"), &break_points, false).unwrap();
+
+        let pretty = fs::read_to_string(path("config_pretty.json")).unwrap();
+        let compact = fs::read_to_string(path("config_compact.json")).unwrap();
+        assert!(pretty.contains('\n'));
+        assert!(!compact.contains('\n'));
+        assert_eq!(
+            load_json_file::<UpaConfig>(&path("config_pretty.json")).unwrap(),
+            load_json_file::<UpaConfig>(&path("config_compact.json")).unwrap()
+        );
+        assert_eq!(
+            load_json_file::<MultiPhaseThreadBreakPoints>(&path(
+                "bps_pretty.json"
+            ))
+            .unwrap(),
+            load_json_file::<MultiPhaseThreadBreakPoints>(&path(
+                "bps_compact.json"
+            ))
+            .unwrap()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -1,12 +1,12 @@
 use crate::{
     default_values::{
-        KECCAK_GATE_CONFIG, KECCAK_PK, KECCAK_PROTOCOL, KECCAK_SRS, KECCAK_VK,
-        OUTER_GATE_CONFIG, OUTER_INSTANCE_SIZE, OUTER_PK, OUTER_PROTOCOL,
-        OUTER_SRS, OUTER_VERIFIER_BIN, OUTER_VERIFIER_YUL, OUTER_VK,
-        UBV_GATE_CONFIG, UBV_PK, UBV_PROTOCOL, UBV_SRS, UBV_VK, UPA_CONFIG,
+        KECCAK_SRS, OUTER_SRS, OUTER_VERIFIER_BIN, OUTER_VERIFIER_YUL, UBV_SRS,
+        UPA_CONFIG,
     },
+    error::ProverError,
+    file_utils::load_upa_config,
     keccak, universal_batch_verifier,
-    universal_outer::{self, generate_evm_verifier},
+    universal_outer::{self, generate_evm_verifier, KeygenFiles},
 };
 use clap::Parser;
 use log::info;
@@ -29,57 +29,79 @@ pub struct KeygenParams {
     #[arg(long, value_name = "outer-srs-file", default_value = OUTER_SRS)]
     outer_srs: String,
 
-    /// Output UBV proving key file
-    #[arg(long, value_name = "ubv-proving-key-file", default_value = UBV_PK)]
-    ubv_proving_key: String,
-
-    /// Output Keccak proving key file
-    #[arg(long, value_name = "keccak-proving-key-file", default_value = KECCAK_PK)]
-    keccak_proving_key: String,
-
-    /// Output Outer proving key file
-    #[arg(long, value_name = "outer-proving-key-file", default_value = OUTER_PK)]
-    outer_proving_key: String,
-
-    /// Output verification key file
-    #[arg(long, value_name = "ubv-verification-key-file", default_value = UBV_VK)]
-    ubv_verification_key: String,
-
-    /// Output verification key file
-    #[arg(long, value_name = "keccak-verification-key-file", default_value = KECCAK_VK)]
-    keccak_verification_key: String,
-
-    /// Output verification key file
-    #[arg(long, value_name = "outer-verification-key-file", default_value = OUTER_VK)]
-    outer_verification_key: String,
-
-    /// Output UBV protocol file
-    #[arg(long, value_name = "ubv-protocol-file", default_value = UBV_PROTOCOL)]
-    ubv_protocol: String,
-
-    /// Output Keccak protocol file
-    #[arg(long, value_name = "keccak-protocol-file", default_value = KECCAK_PROTOCOL)]
-    keccak_protocol: String,
-
-    /// Output Outer protocol file
-    #[arg(long, value_name = "outer-protocol-file", default_value = OUTER_PROTOCOL)]
-    outer_protocol: String,
-
-    /// Output circuit specs (UBVGateConfig) file
-    #[arg(long, value_name = "ubv-specs-file", default_value = UBV_GATE_CONFIG)]
-    ubv_gate_config: String,
-
-    /// Output circuit specs (KeccakGateConfig) file
-    #[arg(long, value_name = "keccak-specs-file", default_value = KECCAK_GATE_CONFIG)]
-    keccak_gate_config: String,
-
-    /// Output circuit specs (OuterGateConfig) file
-    #[arg(long, value_name = "outer-specs-file", default_value = OUTER_GATE_CONFIG)]
-    outer_gate_config: String,
-
-    /// Output num instance file
-    #[arg(long, value_name = "num_instance", default_value = OUTER_INSTANCE_SIZE)]
-    num_instance: String,
+    /// Output directory.  If given, the output files of each circuit default to
+    /// canonical names in a subdirectory named after the circuit and a hash of
+    /// the UPA config.
+    #[arg(long, value_name = "output-dir")]
+    output_dir: Option<String>,
+
+    /// Output UBV proving key file [default: ubv.pk, or pk.bin in --output-dir]
+    #[arg(long, value_name = "ubv-proving-key-file")]
+    ubv_proving_key: Option<String>,
+
+    /// Output Keccak proving key file [default: keccak.pk, or pk.bin in
+    /// --output-dir]
+    #[arg(long, value_name = "keccak-proving-key-file")]
+    keccak_proving_key: Option<String>,
+
+    /// Output Outer proving key file [default: outer.pk, or pk.bin in
+    /// --output-dir]
+    #[arg(long, value_name = "outer-proving-key-file")]
+    outer_proving_key: Option<String>,
+
+    /// Output verification key file [default: ubv.vk, or vk.bin in
+    /// --output-dir]
+    #[arg(long, value_name = "ubv-verification-key-file")]
+    ubv_verification_key: Option<String>,
+
+    /// Output verification key file [default: keccak.vk, or vk.bin in
+    /// --output-dir]
+    #[arg(long, value_name = "keccak-verification-key-file")]
+    keccak_verification_key: Option<String>,
+
+    /// Output verification key file [default: outer.vk, or vk.bin in
+    /// --output-dir]
+    #[arg(long, value_name = "outer-verification-key-file")]
+    outer_verification_key: Option<String>,
+
+    /// Output UBV protocol file [default: ubv.protocol, or protocol.bin in
+    /// --output-dir]
+    #[arg(long, value_name = "ubv-protocol-file")]
+    ubv_protocol: Option<String>,
+
+    /// Output Keccak protocol file [default: keccak.protocol, or protocol.bin
+    /// in --output-dir]
+    #[arg(long, value_name = "keccak-protocol-file")]
+    keccak_protocol: Option<String>,
+
+    /// Output Outer protocol file [default: outer.protocol, or protocol.bin in
+    /// --output-dir]
+    #[arg(long, value_name = "outer-protocol-file")]
+    outer_protocol: Option<String>,
+
+    /// Output circuit specs (UBVGateConfig) file [default: ubv.specs, or
+    /// gate_config.json in --output-dir]
+    #[arg(long, value_name = "ubv-specs-file")]
+    ubv_gate_config: Option<String>,
+
+    /// Output circuit specs (KeccakGateConfig) file [default: keccak.specs, or
+    /// gate_config.json in --output-dir]
+    #[arg(long, value_name = "keccak-specs-file")]
+    keccak_gate_config: Option<String>,
+
+    /// Output circuit specs (OuterGateConfig) file [default: outer.specs, or
+    /// gate_config.json in --output-dir]
+    #[arg(long, value_name = "outer-specs-file")]
+    outer_gate_config: Option<String>,
+
+    /// Output num instance file [default: outer.instance_size, or
+    /// num_instance.json in --output-dir]
+    #[arg(long, value_name = "num_instance")]
+    num_instance: Option<String>,
+
+    /// Optional output manifest file for the Outer keys
+    #[arg(long, value_name = "outer-manifest-file")]
+    outer_manifest: Option<String>,
 
     /// Output yul verifier
     #[arg(long, value_name = "yul-file", default_value = OUTER_VERIFIER_YUL)]
@@ -96,6 +118,11 @@ pub struct KeygenParams {
     /// Compute and write the circuit configs and exit.
     #[arg(short = 'n', long)]
     dry_run: bool,
+
+    /// Number of threads used for key generation (defaults to all available
+    /// cores)
+    #[arg(long, value_name = "num-threads")]
+    threads: Option<usize>,
 }
 
 impl From<&KeygenParams> for universal_batch_verifier::KeygenParams {
@@ -103,12 +130,15 @@ impl From<&KeygenParams> for universal_batch_verifier::KeygenParams {
         let value = value.clone();
         Self {
             config: value.config,
+            output_dir: value.output_dir,
             proving_key: value.ubv_proving_key,
             srs: value.ubv_srs,
             verification_key: value.ubv_verification_key,
             protocol: value.ubv_protocol,
             gate_config: value.ubv_gate_config,
+            report_cells: None,
             dry_run: value.dry_run,
+            threads: value.threads,
         }
     }
 }
@@ -118,12 +148,15 @@ impl From<&KeygenParams> for keccak::KeygenParams {
         let value = value.clone();
         Self {
             config: value.config,
+            output_dir: value.output_dir,
             proving_key: value.keccak_proving_key,
             srs: value.keccak_srs,
             verification_key: value.keccak_verification_key,
             protocol: value.keccak_protocol,
             gate_config: value.keccak_gate_config,
+            report_cells: None,
             dry_run: value.dry_run,
+            threads: value.threads,
         }
     }
 }
@@ -136,41 +169,54 @@ impl From<&KeygenParams> for universal_outer::KeygenParams {
             outer_srs: value.outer_srs,
             bv_srs: value.ubv_srs,
             keccak_srs: value.keccak_srs,
+            output_dir: value.output_dir,
             proving_key: value.outer_proving_key,
             verification_key: value.outer_verification_key,
             protocol: value.outer_protocol,
             gate_config: value.outer_gate_config,
             num_instance: value.num_instance,
+            manifest: value.outer_manifest,
+            scheme: universal_outer::Scheme::Shplonk,
             vk_only: value.vk_only,
+            checkpoint: None,
+            resume: false,
             dry_run: value.dry_run,
+            threads: value.threads,
         }
     }
 }
 
-impl From<&KeygenParams> for universal_outer::GenerateVerifierParams {
-    fn from(value: &KeygenParams) -> Self {
+impl TryFrom<&KeygenParams> for universal_outer::GenerateVerifierParams {
+    type Error = ProverError;
+
+    fn try_from(value: &KeygenParams) -> Result<Self, ProverError> {
+        // Locate the outer circuit files written by `universal_outer::keygen`
+        let files =
+            KeygenFiles::new(&value.into(), &load_upa_config(&value.config)?)?;
         let value = value.clone();
-        Self {
+        Ok(Self {
             outer_srs: value.outer_srs,
-            gate_config: value.outer_gate_config,
-            verification_key: value.outer_verification_key,
-            num_instance: value.num_instance,
+            gate_config: files.gate_config,
+            verification_key: files.verification_key,
+            num_instance: files.num_instance,
             yul: value.yul,
-        }
+            scheme: universal_outer::Scheme::Shplonk,
+        })
     }
 }
 
-pub fn keygen(params: KeygenParams) {
+pub fn keygen(params: KeygenParams) -> Result<(), ProverError> {
     info!("Generating UBV circuit proving and verifying keys");
-    universal_batch_verifier::keygen((&params).into());
+    universal_batch_verifier::keygen((&params).into())?;
     info!("Generating Keccak circuit proving and verifying keys");
-    keccak::keygen((&params).into());
+    keccak::keygen((&params).into())?;
     info!("Generating Outer circuit proving and verifying keys");
-    universal_outer::keygen((&params).into());
+    universal_outer::keygen((&params).into())?;
     if !params.dry_run {
         // only generate the evm verifier when
         // it isn't a dry run
         info!("Generating evm verifier");
-        generate_evm_verifier((&params).into());
+        generate_evm_verifier((&params).try_into()?)?;
     }
+    Ok(())
 }
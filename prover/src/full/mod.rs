@@ -1,3 +1,4 @@
+use crate::error::ProverError;
 use clap::{Parser, Subcommand};
 
 pub use self::{
@@ -28,7 +29,7 @@ enum FullCommand {
     Verify(VerifyParams),
 }
 
-pub fn full(params: FullParams) {
+pub fn full(params: FullParams) -> Result<(), ProverError> {
     match params.command {
         FullCommand::Keygen(params) => keygen(params),
         FullCommand::Prove(params) => prove(params),
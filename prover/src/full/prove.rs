@@ -5,9 +5,10 @@ use crate::{
         UBV_GATE_CONFIG, UBV_PK, UBV_PROOF_BASE, UBV_PROTOCOL, UBV_SRS,
         UPA_CONFIG,
     },
+    error::ProverError,
+    file_utils::load_upa_config,
     keccak, universal_batch_verifier, universal_outer,
 };
-use circuits::utils::upa_config::UpaConfig;
 use clap::Parser;
 use log::info;
 
@@ -103,9 +104,31 @@ pub struct ProveParams {
     #[arg(long, value_name = "calldata")]
     calldata: Option<String>,
 
+    /// Memory-map the outer proving key file instead of reading it into a
+    /// buffer.
+    #[arg(long)]
+    mmap_outer_pk: bool,
+
+    /// Load the inputs of the Keccak and outer provers on a separate thread
+    /// while their proving keys are read
+    #[arg(long)]
+    concurrent_load: bool,
+
+    /// Outer keygen manifest file to check the outer keys against
+    #[arg(long, value_name = "outer-manifest-file")]
+    outer_manifest: Option<String>,
+
+    /// Verify each proof immediately after it is generated
+    #[arg(long)]
+    verify_after_prove: bool,
+
     /// Do nothing
     #[arg(short = 'n', long)]
     dry_run: bool,
+
+    /// Number of threads used for proving (defaults to all available cores)
+    #[arg(long, value_name = "num-threads")]
+    threads: Option<usize>,
 }
 
 fn nth_instance_file(params: &ProveParams, n: u32) -> String {
@@ -120,22 +143,26 @@ fn nth_proof_file(params: &ProveParams, n: u32) -> String {
     params.ubv_proof.clone() + &n.to_string()
 }
 
-fn ubv_instances_from_params(params: &ProveParams) -> Vec<String> {
-    let upa_config = UpaConfig::from_file(&params.config);
+fn ubv_instances_from_params(
+    params: &ProveParams,
+) -> Result<Vec<String>, ProverError> {
+    let upa_config = load_upa_config(&params.config)?;
     let outer_batch_size = upa_config.outer_batch_size;
-    (0..outer_batch_size)
+    Ok((0..outer_batch_size)
         .into_iter()
         .map(|i| nth_instance_file(params, i))
-        .collect()
+        .collect())
 }
 
-fn ubv_proofs_from_params(params: &ProveParams) -> Vec<String> {
-    let upa_config = UpaConfig::from_file(&params.config);
+fn ubv_proofs_from_params(
+    params: &ProveParams,
+) -> Result<Vec<String>, ProverError> {
+    let upa_config = load_upa_config(&params.config)?;
     let outer_batch_size = upa_config.outer_batch_size;
-    (0..outer_batch_size)
+    Ok((0..outer_batch_size)
         .into_iter()
         .map(|i| nth_proof_file(params, i))
-        .collect()
+        .collect())
 }
 
 impl universal_batch_verifier::ProveParams {
@@ -153,18 +180,26 @@ impl universal_batch_verifier::ProveParams {
             proving_key: params.ubv_proving_key.clone(),
             gate_config: params.ubv_gate_config.clone(),
             app_vk_proof_batch,
+            input_format: universal_batch_verifier::InputFormat::Native,
             proof,
             instance: Some(instance),
             dry_run: params.dry_run,
+            verify_after_prove: params.verify_after_prove,
+            threads: params.threads,
+            reject_duplicate_entries: false,
+            entries: None,
+            jsonl: false,
         }
     }
 }
 
-impl From<&ProveParams> for keccak::ProveParams {
-    fn from(value: &ProveParams) -> Self {
-        let ubv_instances = ubv_instances_from_params(value);
+impl TryFrom<&ProveParams> for keccak::ProveParams {
+    type Error = ProverError;
+
+    fn try_from(value: &ProveParams) -> Result<Self, ProverError> {
+        let ubv_instances = ubv_instances_from_params(value)?;
         let value = value.clone();
-        Self {
+        Ok(Self {
             config: value.config,
             srs: value.keccak_srs,
             proving_key: value.keccak_proving_key,
@@ -174,16 +209,22 @@ impl From<&ProveParams> for keccak::ProveParams {
             instance: value.keccak_instance,
             dry_run: value.dry_run,
             num_proof_ids: value.num_proof_ids,
-        }
+            self_check: false,
+            verify_after_prove: value.verify_after_prove,
+            concurrent_load: value.concurrent_load,
+            threads: value.threads,
+        })
     }
 }
 
-impl From<&ProveParams> for universal_outer::ProveParams {
-    fn from(value: &ProveParams) -> Self {
-        let ubv_instances = ubv_instances_from_params(value);
-        let ubv_proofs = ubv_proofs_from_params(value);
+impl TryFrom<&ProveParams> for universal_outer::ProveParams {
+    type Error = ProverError;
+
+    fn try_from(value: &ProveParams) -> Result<Self, ProverError> {
+        let ubv_instances = ubv_instances_from_params(value)?;
+        let ubv_proofs = ubv_proofs_from_params(value)?;
         let value = value.clone();
-        Self {
+        Ok(Self {
             config: value.config,
             bv_protocol: value.ubv_protocol,
             srs: value.outer_srs,
@@ -197,21 +238,30 @@ impl From<&ProveParams> for universal_outer::ProveParams {
             proof: value.proof,
             instance: value.instance,
             calldata: value.calldata,
+            abi_encode: false,
+            selector: None,
+            mmap_pk: value.mmap_outer_pk,
+            concurrent_load: value.concurrent_load,
+            manifest: value.outer_manifest,
+            recursive: false,
+            scheme: universal_outer::Scheme::Shplonk,
+            verify_after_prove: value.verify_after_prove,
             dry_run: value.dry_run,
-        }
+            threads: value.threads,
+        })
     }
 }
 
-pub fn prove(params: ProveParams) {
-    let upa_config = UpaConfig::from_file(&params.config);
+pub fn prove(params: ProveParams) -> Result<(), ProverError> {
+    let upa_config = load_upa_config(&params.config)?;
     let outer_batch_size = upa_config.outer_batch_size;
     for i in 0..outer_batch_size {
         info!("Generating UBV proof for batch number {i}");
         let ubv_prove_params = universal_batch_verifier::ProveParams::from_full_prove_params_and_batch_number(&params, i);
-        universal_batch_verifier::prove(ubv_prove_params);
+        universal_batch_verifier::prove(ubv_prove_params)?;
     }
     info!("Generating keccak proof");
-    keccak::prove((&params).into());
+    keccak::prove((&params).try_into()?)?;
     info!("Generating outer proof");
-    universal_outer::prove((&params).into());
+    universal_outer::prove((&params).try_into()?)
 }
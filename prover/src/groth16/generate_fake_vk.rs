@@ -1,9 +1,9 @@
-use crate::file_utils::save_pretty_json_file;
+use super::rng_from_seed;
+use crate::{error::ProverError, file_utils::save_pretty_json_file};
 use circuits::batch_verify::common::native::unsafe_proof_generation::{
     JsonUnsafeVerificationKey, UnsafeVerificationKey,
 };
 use clap::Parser;
-use rand_chacha::rand_core::OsRng;
 
 #[derive(Parser, Debug)]
 pub struct GenerateFakeVkParams {
@@ -18,19 +18,23 @@ pub struct GenerateFakeVkParams {
     #[arg(short = 'c', long, value_name = "with-commitment")]
     /// Include a commitment
     with_commitment: bool,
+
+    /// Seed for the RNG.  If given, the same seed always gives the same
+    /// output.  Otherwise, the system RNG is used.
+    #[arg(long, value_name = "seed")]
+    seed: Option<u64>,
 }
 
-pub fn generate_fake_vk(params: GenerateFakeVkParams) {
-    let mut rng = OsRng;
+pub fn generate_fake_vk(
+    params: GenerateFakeVkParams,
+) -> Result<(), ProverError> {
+    let mut rng = rng_from_seed(params.seed);
+    let rng = rng.as_mut();
     let unsafe_vk = UnsafeVerificationKey::sample(
         params.num_public_inputs,
         params.with_commitment,
-        &mut rng,
+        rng,
     );
     let unsafe_vk_json: JsonUnsafeVerificationKey = (&unsafe_vk).into();
-    save_pretty_json_file(
-        &params.app_vk_file,
-        &unsafe_vk_json,
-        "unsafe verifying key",
-    );
+    save_pretty_json_file(&params.app_vk_file, &unsafe_vk_json)?
 }
@@ -1,15 +1,18 @@
-use crate::file_utils::save_pretty_json_file;
-use circuits::{
-    batch_verify::{
-        common::native::unsafe_proof_generation::{
-            JsonUnsafeVerificationKey, UnsafeVerificationKey,
-        },
-        universal::native::json::JsonUniversalBatchVerifierInput,
+use crate::{
+    error::ProverError,
+    file_utils::{load_json_file, save_pretty_json_file},
+};
+use circuits::batch_verify::{
+    common::native::unsafe_proof_generation::{
+        create_proofs_and_inputs, JsonUnsafeVerificationKey,
+        UnsafeVerificationKey,
+    },
+    universal::native::json::{
+        JsonShardIndex, JsonUniversalBatchVerifierInput,
     },
-    utils::file::load_json,
 };
 use clap::Parser;
-use rand_chacha::rand_core::OsRng;
+use std::path::Path;
 
 #[derive(Parser, Debug)]
 pub struct GenerateProofsParams {
@@ -22,34 +25,162 @@ pub struct GenerateProofsParams {
     #[clap(required = true)]
     app_vk_file: Vec<String>,
 
-    /// Output file into which the proof batch will be written
+    /// Output file into which the proof batch will be written.  With
+    /// `--proofs-per-file`, this is the index file listing the shards.
     #[arg(short = 'b', long, value_name = "batch-file")]
     batch_file: String,
-}
 
-pub fn generate_proofs(params: GenerateProofsParams) {
-    let rng = &mut OsRng;
+    /// Seed for the RNG.  If given, the same seed always gives the same
+    /// output.  Otherwise, the system RNG is used.
+    #[arg(long, value_name = "seed")]
+    seed: Option<u64>,
 
-    let unsafe_vks = params.app_vk_file.iter().map(|vk_file| {
-        let vk_json: JsonUnsafeVerificationKey = load_json(vk_file);
-        (&vk_json).into()
-    });
+    /// Write the batch into files `proofs_0.json`, `proofs_1.json`, ... (in
+    /// the directory of the batch file), each holding up to this many
+    /// proofs.  The batch file then holds the index of these shards.
+    #[arg(long, value_name = "proofs-per-file")]
+    proofs_per_file: Option<usize>,
+}
 
-    let batch: Vec<JsonUniversalBatchVerifierInput> = unsafe_vks
-        .cycle()
-        .take(params.num_proofs)
-        .map(|uvk: UnsafeVerificationKey| {
-            let (proof, inputs) = uvk.create_proof_and_inputs(rng);
-            JsonUniversalBatchVerifierInput {
-                vk: uvk.vk().into(),
-                proof: (&proof).into(),
-                inputs: (&inputs).into(),
-            }
+pub fn generate_proofs(
+    params: GenerateProofsParams,
+) -> Result<(), ProverError> {
+    let unsafe_vks: Vec<UnsafeVerificationKey> = params
+        .app_vk_file
+        .iter()
+        .map(|vk_file| {
+            let vk_json: JsonUnsafeVerificationKey = load_json_file(vk_file)?;
+            Ok((&vk_json).into())
         })
-        .collect();
+        .collect::<Result<_, ProverError>>()?;
+
+    let batch =
+        generate_proof_batch(&unsafe_vks, params.num_proofs, params.seed);
 
     // TODO: can't work out how to make `serde_json::to_writer_pretty` accept an
     // iterator, so we have to realise everything in memory before writing it.
 
-    save_pretty_json_file(&params.batch_file, &batch, "batch file");
+    match params.proofs_per_file {
+        Some(proofs_per_file) => save_sharded_proof_batch(
+            &params.batch_file,
+            &batch,
+            proofs_per_file,
+        ),
+        None => save_pretty_json_file(&params.batch_file, &batch),
+    }
+}
+
+/// Generates `num_proofs` proofs, cycling through `unsafe_vks`.
+fn generate_proof_batch(
+    unsafe_vks: &[UnsafeVerificationKey],
+    num_proofs: usize,
+    seed: Option<u64>,
+) -> Vec<JsonUniversalBatchVerifierInput> {
+    // Proofs are generated in parallel, each with its own RNG derived from
+    // the seed and the proof index, so the output is still reproducible.
+    let proofs_and_inputs =
+        create_proofs_and_inputs(unsafe_vks, num_proofs, seed, true);
+    unsafe_vks
+        .iter()
+        .cycle()
+        .zip(proofs_and_inputs.iter())
+        .map(|(uvk, (proof, inputs))| JsonUniversalBatchVerifierInput {
+            vk: uvk.vk().into(),
+            proof: proof.into(),
+            inputs: inputs.into(),
+        })
+        .collect()
+}
+
+/// Writes `batch` into shards of up to `proofs_per_file` entries, in the
+/// directory of `index_file`, and the [`JsonShardIndex`] into `index_file`.
+fn save_sharded_proof_batch(
+    index_file: &str,
+    batch: &[JsonUniversalBatchVerifierInput],
+    proofs_per_file: usize,
+) -> Result<(), ProverError> {
+    if proofs_per_file == 0 {
+        return Err(ProverError::InvalidInput(
+            "--proofs-per-file must be positive".to_string(),
+        ));
+    }
+    let dir = Path::new(index_file).parent().unwrap_or(Path::new(""));
+    let shards = batch
+        .chunks(proofs_per_file)
+        .enumerate()
+        .map(|(i, shard)| {
+            let shard_name = format!("proofs_{i}.json");
+            let shard_path = dir.join(&shard_name);
+            save_pretty_json_file(&shard_path.to_string_lossy(), &shard)?;
+            Ok(shard_name)
+        })
+        .collect::<Result<Vec<_>, ProverError>>()?;
+    save_pretty_json_file(
+        index_file,
+        &JsonShardIndex {
+            shards,
+            num_entries: batch.len(),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_proof_batch, save_sharded_proof_batch};
+    use crate::file_utils::save_pretty_json_file;
+    use circuits::{
+        batch_verify::{
+            common::native::unsafe_proof_generation::UnsafeVerificationKey,
+            universal::native::json::{
+                load_sharded, JsonUniversalBatchVerifierInputs,
+            },
+        },
+        utils::file::load_json,
+    };
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+    use std::fs;
+
+    /// Checks that a sharded proof batch, reassembled by [`load_sharded`],
+    /// is equal to the single-file batch generated with the same seed.
+    ///
+    /// CMD: `cargo test --package prover -- groth16::generate_proofs::tests::sharded_proof_batch --exact --nocapture`
+    #[test]
+    fn sharded_proof_batch() {
+        const NUM_PROOFS: usize = 5;
+        let rng = &mut ChaCha20Rng::seed_from_u64(1);
+        let uvks = vec![
+            UnsafeVerificationKey::sample(2, false, rng),
+            UnsafeVerificationKey::sample(3, true, rng),
+        ];
+        let dir = std::env::temp_dir()
+            .join(format!("upa-sharded-proofs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let batch_file = dir.join("batch.json");
+        let batch_file = batch_file.to_str().unwrap();
+        let index_file = dir.join("index.json");
+        let index_file = index_file.to_str().unwrap();
+
+        save_pretty_json_file(
+            batch_file,
+            &generate_proof_batch(&uvks, NUM_PROOFS, Some(1)),
+        )
+        .unwrap();
+        save_sharded_proof_batch(
+            index_file,
+            &generate_proof_batch(&uvks, NUM_PROOFS, Some(1)),
+            2,
+        )
+        .unwrap();
+
+        let batch: JsonUniversalBatchVerifierInputs = load_json(batch_file);
+        let sharded = load_sharded(index_file);
+        assert!(dir.join("proofs_2.json").exists());
+        assert!(!dir.join("proofs_3.json").exists());
+        assert_eq!(
+            serde_json::to_string(&sharded).unwrap(),
+            serde_json::to_string(&batch).unwrap()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -1,6 +1,11 @@
+use crate::error::ProverError;
 use clap::{Parser, Subcommand};
 use generate_fake_vk::{generate_fake_vk, GenerateFakeVkParams};
 use generate_proofs::{generate_proofs, GenerateProofsParams};
+use rand_chacha::{
+    rand_core::{OsRng, RngCore, SeedableRng},
+    ChaCha20Rng,
+};
 
 mod generate_fake_vk;
 mod generate_proofs;
@@ -22,9 +27,18 @@ enum Groth16Command {
     GenerateProofs(GenerateProofsParams),
 }
 
-pub fn groth16(params: Groth16Params) {
+pub fn groth16(params: Groth16Params) -> Result<(), ProverError> {
     match params.command {
         Groth16Command::GenerateFakeVk(params) => generate_fake_vk(params),
         Groth16Command::GenerateProofs(params) => generate_proofs(params),
     }
 }
+
+/// Returns a `ChaCha20Rng` seeded with `seed` if given (for reproducible
+/// output), or the system RNG otherwise.
+pub(crate) fn rng_from_seed(seed: Option<u64>) -> Box<dyn RngCore> {
+    match seed {
+        Some(seed) => Box::new(ChaCha20Rng::seed_from_u64(seed)),
+        None => Box::new(OsRng),
+    }
+}
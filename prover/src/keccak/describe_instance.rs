@@ -0,0 +1,24 @@
+use crate::{
+    default_values::UPA_CONFIG, error::ProverError, file_utils::load_upa_config,
+};
+use circuits::{format_instance_layout, keccak::KeccakCircuit, SafeCircuit};
+use clap::Parser;
+use halo2_base::halo2_proofs::halo2curves::bn256::{Fr, G1Affine};
+
+#[derive(Debug, Parser)]
+pub struct DescribeInstanceParams {
+    /// UPA configuration file
+    #[arg(short = 'c', long, value_name = "config-file", default_value = UPA_CONFIG)]
+    pub(crate) config: String,
+}
+
+pub fn describe_instance(
+    params: DescribeInstanceParams,
+) -> Result<(), ProverError> {
+    let upa_config = load_upa_config(&params.config)?;
+    let keccak_config = upa_config.keccak_config();
+    let layout =
+        KeccakCircuit::<Fr, G1Affine>::describe_instance(&keccak_config);
+    print!("{}", format_instance_layout(&layout));
+    Ok(())
+}
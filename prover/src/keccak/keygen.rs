@@ -1,26 +1,26 @@
 use crate::{
+    api::keygen_circuit,
     default_values::{
-        KECCAK_GATE_CONFIG, KECCAK_PK, KECCAK_PROTOCOL, KECCAK_SRS, KECCAK_VK,
-        UPA_CONFIG,
+        KECCAK_CIRCUIT_NAME, KECCAK_GATE_CONFIG, KECCAK_PK, KECCAK_PROTOCOL,
+        KECCAK_SRS, KECCAK_VK, UPA_CONFIG,
     },
+    error::ProverError,
     file_utils::{
-        break_points_file, load_srs, panic_if_file_exists, save_break_points,
-        save_gate_config, save_pk, save_protocol, save_vk,
+        break_points_file, fail_if_file_exists, load_srs, load_upa_config,
+        save_break_points, save_gate_config, save_json_file, save_pk,
+        save_protocol, save_vk, KeygenDirManifest, KeygenOutputs,
+        OUTPUT_DIR_GATE_CONFIG, OUTPUT_DIR_MANIFEST, OUTPUT_DIR_PK,
+        OUTPUT_DIR_PROTOCOL, OUTPUT_DIR_VK,
     },
+    thread_pool::with_thread_pool,
 };
 use circuits::{
-    keccak::{KeccakCircuit, KeccakConfig},
+    keccak::{KeccakCircuit, KeccakGateConfig},
     SafeCircuit,
 };
 use clap::Parser;
-use halo2_base::halo2_proofs::{
-    halo2curves::bn256::G1Affine,
-    plonk::{keygen_pk, keygen_vk},
-};
+use halo2_base::halo2_proofs::halo2curves::bn256::G1Affine;
 use log::info;
-use snark_verifier::system::halo2::{compile, Config};
-use snark_verifier_sdk::CircuitExt;
-use std::time::Instant;
 
 #[derive(Parser, Debug)]
 pub struct KeygenParams {
@@ -32,84 +32,125 @@ pub struct KeygenParams {
     /// SRS file
     pub(crate) srs: String,
 
-    #[arg(short = 'p', long, value_name = "proving-key-file", default_value = KECCAK_PK)]
-    /// Output proving key file
-    pub(crate) proving_key: String,
-
-    #[arg(short = 'v', long, value_name = "verification-key-file", default_value = KECCAK_VK)]
-    /// Output verification key file
-    pub(crate) verification_key: String,
-
-    #[arg(short = 'r', long, value_name = "protocol-file", default_value = KECCAK_PROTOCOL)]
-    /// Output protocol file
-    pub(crate) protocol: String,
-
-    #[arg(long, value_name = "specs-file", default_value = KECCAK_GATE_CONFIG)]
-    /// Output circuit specs (KeccakGateConfig) file
-    pub(crate) gate_config: String,
+    #[arg(long, value_name = "output-dir")]
+    /// Output directory.  If given, output files default to canonical names
+    /// in a subdirectory named after the circuit and a hash of the UPA
+    /// config, together with a manifest.json.
+    pub(crate) output_dir: Option<String>,
+
+    #[arg(short = 'p', long, value_name = "proving-key-file")]
+    /// Output proving key file [default: keccak.pk, or pk.bin in
+    /// --output-dir]
+    pub(crate) proving_key: Option<String>,
+
+    #[arg(short = 'v', long, value_name = "verification-key-file")]
+    /// Output verification key file [default: keccak.vk, or vk.bin in
+    /// --output-dir]
+    pub(crate) verification_key: Option<String>,
+
+    #[arg(short = 'r', long, value_name = "protocol-file")]
+    /// Output protocol file [default: keccak.protocol, or protocol.bin in
+    /// --output-dir]
+    pub(crate) protocol: Option<String>,
+
+    #[arg(long, value_name = "specs-file")]
+    /// Output circuit specs (KeccakGateConfig) file [default: keccak.specs,
+    /// or gate_config.json in --output-dir]
+    pub(crate) gate_config: Option<String>,
+
+    #[arg(long, value_name = "cell-report-file")]
+    /// Output cell report file.  If given, the advice and lookup cell
+    /// counts and the computed gate config are written here (also on
+    /// --dry-run).
+    pub(crate) report_cells: Option<String>,
 
     #[arg(short = 'n', long)]
     /// Compute and write the circuit configs and exit.
     pub(crate) dry_run: bool,
+
+    #[arg(long, value_name = "num-threads")]
+    /// Number of threads used for key generation (defaults to all available
+    /// cores)
+    pub(crate) threads: Option<usize>,
+}
+
+pub fn keygen(params: KeygenParams) -> Result<(), ProverError> {
+    with_thread_pool(params.threads, || run_keygen(params))
 }
 
-pub fn keygen(params: KeygenParams) {
-    let keccak_config: KeccakConfig =
-        KeccakConfig::from_upa_config_file(&params.config);
+fn run_keygen(params: KeygenParams) -> Result<(), ProverError> {
+    let upa_config = load_upa_config(&params.config)?;
+    let keccak_config = upa_config.keccak_config();
+    upa_config
+        .check_keccak_config(&keccak_config)
+        .map_err(|e| ProverError::InvalidInput(e.to_string()))?;
+
+    let outputs = KeygenOutputs::new(
+        params.output_dir.as_deref(),
+        KECCAK_CIRCUIT_NAME,
+        &upa_config,
+    )?;
+    let proving_key =
+        outputs.path(&params.proving_key, OUTPUT_DIR_PK, KECCAK_PK);
+    let verification_key =
+        outputs.path(&params.verification_key, OUTPUT_DIR_VK, KECCAK_VK);
+    let protocol_file =
+        outputs.path(&params.protocol, OUTPUT_DIR_PROTOCOL, KECCAK_PROTOCOL);
+    let gate_config_file = outputs.path(
+        &params.gate_config,
+        OUTPUT_DIR_GATE_CONFIG,
+        KECCAK_GATE_CONFIG,
+    );
+    let manifest = outputs.in_dir(OUTPUT_DIR_MANIFEST);
 
     // Fail if any of the output paths exist
-    let break_points_file = break_points_file(&params.proving_key);
+    let break_points_file = break_points_file(&proving_key);
     if !params.dry_run {
-        panic_if_file_exists(&params.verification_key);
-        panic_if_file_exists(&break_points_file);
-        panic_if_file_exists(&params.protocol);
-        panic_if_file_exists(&params.proving_key);
-        panic_if_file_exists(&params.gate_config);
+        fail_if_file_exists(&verification_key)?;
+        fail_if_file_exists(&break_points_file)?;
+        fail_if_file_exists(&protocol_file)?;
+        fail_if_file_exists(&proving_key)?;
+        fail_if_file_exists(&gate_config_file)?;
+        if let Some(manifest) = &manifest {
+            fail_if_file_exists(manifest)?;
+        }
     }
+    if let Some(report_cells) = &params.report_cells {
+        fail_if_file_exists(report_cells)?;
+    }
+
+    // Fail early, rather than panicking during keygen, if the config does
+    // not fit in the degree.
+    KeccakGateConfig::check_rows(&keccak_config)
+        .map_err(|e| ProverError::InvalidInput(e.to_string()))?;
 
     let circuit = KeccakCircuit::<_, G1Affine>::keygen(&keccak_config, &());
     let gate_config = circuit.gate_config();
 
-    save_gate_config(&params.gate_config, &gate_config);
+    if let Some(report_cells) = &params.report_cells {
+        save_json_file(report_cells, &circuit.cell_report())?;
+    }
+
+    save_gate_config(&gate_config_file, &gate_config)?;
 
     // Early-out if --dry-run was specified
     if params.dry_run {
         info!("dry-run.  not attempting to create proving key");
-        return;
+        return Ok(());
     }
 
-    let srs = load_srs(&params.srs);
+    let srs = load_srs(&params.srs)?;
 
-    info!("Generating Keccak VK ...");
-    let now = Instant::now();
-    let vk = keygen_vk(&srs, &circuit)
-        .unwrap_or_else(|e| panic!("VK generation failed: {e}"));
-    info!("Finished generating keccak VK in {:?}", now.elapsed());
+    let keys = keygen_circuit(&srs, &circuit, "Keccak");
+    save_vk::<KeccakCircuit<_, G1Affine>>(&verification_key, keys.pk.get_vk())?;
+    save_break_points(&break_points_file, &keys.break_points)?;
+    save_protocol(&protocol_file, &keys.protocol)?;
+    save_pk::<KeccakCircuit<_, G1Affine>>(&proving_key, &keys.pk)?;
 
-    save_vk(&params.verification_key, &vk);
-
-    {
-        let break_points = circuit.break_points();
-        save_break_points(&break_points_file, &break_points);
+    if let Some(manifest) = &manifest {
+        KeygenDirManifest::new(KECCAK_CIRCUIT_NAME, &upa_config)
+            .save(manifest)?;
     }
 
-    info!("compiling VK to Protocol ...");
-    let protocol = compile(
-        &srs,
-        &vk,
-        Config::kzg()
-            .with_num_instance(circuit.num_instance())
-            .with_accumulator_indices(
-                KeccakCircuit::<_, G1Affine>::accumulator_indices(),
-            ),
-    );
-
-    save_protocol(&params.protocol, &protocol);
-
-    info!("Generating Keccak PK ...");
-    let now = Instant::now();
-    let pk = keygen_pk(&srs, vk, &circuit)
-        .unwrap_or_else(|e| panic!("PK generation failed: {e}"));
-    info!("Finished generating keccak PK in {:?}", now.elapsed());
-    save_pk(&params.proving_key, &pk);
+    Ok(())
 }
@@ -1,14 +1,19 @@
+use crate::error::ProverError;
 use clap::{Parser, Subcommand};
 
 pub use self::{
+    describe_instance::{describe_instance, DescribeInstanceParams},
     keygen::{keygen, KeygenParams},
     prove::{prove, ProveParams},
     verify::{verify, VerifyParams},
+    vk_hash::{vk_hash, VkHashParams},
 };
 
+mod describe_instance;
 mod keygen;
 mod prove;
 mod verify;
+mod vk_hash;
 
 #[derive(Parser, Debug)]
 pub struct KeccakParams {
@@ -27,12 +32,23 @@ enum KeccakCommand {
 
     #[command()]
     Verify(VerifyParams),
+
+    /// Print the layout of the instance (the named segments of the public
+    /// inputs)
+    #[command()]
+    DescribeInstance(DescribeInstanceParams),
+
+    /// Print the hash of a VK, or of the VK embedded in a PK
+    #[command()]
+    VkHash(VkHashParams),
 }
 
-pub fn keccak(params: KeccakParams) {
+pub fn keccak(params: KeccakParams) -> Result<(), ProverError> {
     match params.command {
         KeccakCommand::Keygen(params) => keygen(params),
         KeccakCommand::Prove(params) => prove(params),
         KeccakCommand::Verify(params) => verify(params),
+        KeccakCommand::DescribeInstance(params) => describe_instance(params),
+        KeccakCommand::VkHash(params) => vk_hash(params),
     }
 }
@@ -1,32 +1,27 @@
 use crate::{
+    api::{keccak_prove, keccak_verify},
     default_values::{
         KECCAK_GATE_CONFIG, KECCAK_PK, KECCAK_PROOF, KECCAK_SRS, UPA_CONFIG,
     },
+    error::ProverError,
     file_utils::{
-        break_points_file, instance_file, load_break_points, load_gate_config,
-        load_instance, load_srs, open_file_for_read, panic_if_file_exists,
-        save_instance, save_proof,
+        break_points_file, fail_if_file_exists, instance_file,
+        load_break_points, load_gate_config, load_instance, load_pk_and_inputs,
+        load_srs, load_upa_config, save_instance, save_proof,
     },
-    universal_batch_verifier::SECURE_MDS,
+    thread_pool::with_thread_pool,
 };
 use circuits::{
     keccak::{
         inputs::KeccakCircuitInputs, utils::keccak_inputs_from_ubv_instances,
-        KeccakCircuit, KeccakConfig, KeccakGateConfig,
+        KeccakCircuit, KeccakGateConfig, KeccakProgress,
     },
     SafeCircuit,
 };
 use clap::Parser;
-use halo2_base::halo2_proofs::{
-    halo2curves::bn256::{Bn256, Fr, G1Affine},
-    plonk::create_proof,
-    poly::kzg::{commitment::KZGCommitmentScheme, multiopen::ProverSHPLONK},
-};
+use halo2_base::halo2_proofs::halo2curves::bn256::{Fr, G1Affine};
+use indicatif::{ProgressBar, ProgressStyle};
 use log::info;
-use rand_chacha::rand_core::OsRng;
-use snark_verifier::system::halo2::transcript::halo2::ChallengeScalar;
-use snark_verifier_sdk::{halo2::PoseidonTranscript, CircuitExt, NativeLoader};
-use std::time::Instant;
 
 #[derive(Parser, Debug)]
 pub struct ProveParams {
@@ -65,124 +60,240 @@ pub struct ProveParams {
     #[arg(long, value_name = "instance-file")]
     pub(crate) instance: Option<String>,
 
+    /// Check that the circuit is well constructed before proving, reporting
+    /// the first inconsistency found
+    #[arg(long)]
+    pub(crate) self_check: bool,
+
+    /// Verify the proof against the proving key's VK before writing it,
+    /// failing if verification fails
+    #[arg(long)]
+    pub(crate) verify_after_prove: bool,
+
+    /// Load the BV instances on a separate thread while the proving key is
+    /// read
+    #[arg(long)]
+    pub(crate) concurrent_load: bool,
+
     /// Do nothing
     #[arg(short = 'n', long)]
     pub(crate) dry_run: bool,
+
+    /// Number of threads used for proving (defaults to all available cores)
+    #[arg(long, value_name = "num-threads")]
+    pub(crate) threads: Option<usize>,
 }
 
-pub fn prove(params: ProveParams) {
+pub fn prove(params: ProveParams) -> Result<(), ProverError> {
+    // The keccak chip's row data is computed in parallel during synthesis,
+    // so `create_proof` must run inside the pool too.
+    with_thread_pool(params.threads, || run_prove(params))
+}
+
+fn run_prove(params: ProveParams) -> Result<(), ProverError> {
     let instance_file = instance_file(params.instance, &params.proof);
 
     if !params.dry_run {
-        panic_if_file_exists(&params.proof);
-        panic_if_file_exists(&instance_file);
+        fail_if_file_exists(&params.proof)?;
+        fail_if_file_exists(&instance_file)?;
     }
 
-    let keccak_config: KeccakConfig =
-        KeccakConfig::from_upa_config_file(&params.config);
+    let upa_config = load_upa_config(&params.config)?;
+    let keccak_config = upa_config.keccak_config();
+    upa_config
+        .check_keccak_config(&keccak_config)
+        .map_err(|e| ProverError::InvalidInput(e.to_string()))?;
 
-    assert_eq!(
-        keccak_config.output_submission_id,
-        params.num_proof_ids.is_some(),
-        "Config incompatible with inputs"
-    );
+    if keccak_config.output_mode.outputs_submission_id()
+        != params.num_proof_ids.is_some()
+    {
+        return Err(ProverError::InvalidInput(
+            "--num-proof-ids must be given if and only if the config \
+             outputs the submission id"
+                .to_string(),
+        ));
+    }
 
-    let keccak_inputs = {
-        // Outer Vec indexes BV proof, inner vec is inputs to given BV proof
-        let ubv_instances: Vec<Vec<Fr>> = params
-            .ubv_instances
-            .iter()
-            .map(|input_file| load_instance(input_file.as_str()))
-            .collect();
-        let ubv_instances = ubv_instances.iter().map(|inputs| &inputs[..]);
-        keccak_inputs_from_ubv_instances(
-            ubv_instances,
-            keccak_config.num_app_public_inputs as usize,
-            keccak_config.inner_batch_size as usize,
-        )
-    };
+    // Keccak inputs.  Loading them does not construct any circuit, so may
+    // run concurrently with reading the PK.
+    let load_keccak_inputs =
+        || -> Result<KeccakCircuitInputs<Fr>, ProverError> {
+            // Outer Vec indexes BV proof, inner vec is inputs to given BV
+            // proof
+            let ubv_instances: Vec<Vec<Fr>> = params
+                .ubv_instances
+                .iter()
+                .map(|input_file| load_instance(input_file.as_str()))
+                .collect::<Result<_, _>>()?;
+            let ubv_instances = ubv_instances.iter().map(|inputs| &inputs[..]);
+            Ok(KeccakCircuitInputs {
+                inputs: keccak_inputs_from_ubv_instances(
+                    ubv_instances,
+                    keccak_config.num_app_public_inputs as usize,
+                    keccak_config.inner_batch_size as usize,
+                ),
+                num_proof_ids: params.num_proof_ids,
+            })
+        };
 
     if params.dry_run {
         info!("dry-run.  computing instance and exiting");
         let instance = KeccakCircuit::<_, G1Affine>::compute_instance(
             &keccak_config,
-            &KeccakCircuitInputs {
-                inputs: keccak_inputs,
-                num_proof_ids: params.num_proof_ids,
-            },
+            &load_keccak_inputs()?,
         );
-        save_instance(&instance_file, &instance);
-        return;
+        return save_instance(&instance_file, &instance);
     }
 
-    let gate_config: KeccakGateConfig = load_gate_config(&params.gate_config);
-    info!("reading PK ...");
-    let now = Instant::now();
-    let pk = {
-        let mut buf = open_file_for_read(&params.proving_key);
-        KeccakCircuit::<_, G1Affine>::read_proving_key(
+    let gate_config: KeccakGateConfig = load_gate_config(&params.gate_config)?;
+    gate_config.assert_compatible(&keccak_config).map_err(|e| {
+        ProverError::InvalidInput(format!("{}: {e}", params.gate_config))
+    })?;
+    let (pk, keccak_inputs) =
+        load_pk_and_inputs::<KeccakCircuit<_, G1Affine>, _>(
             &keccak_config,
             &gate_config,
-            &mut buf,
-        )
-        .unwrap_or_else(|e| panic!("error reading pk: {e}"))
-    };
-    info!("Finished reading Keccak PK in {:?}", now.elapsed());
+            &params.proving_key,
+            false,
+            params.concurrent_load,
+            load_keccak_inputs,
+        )?;
 
     let break_points = {
         let break_points_file = break_points_file(&params.proving_key);
-        load_break_points(&break_points_file)
+        load_break_points(&break_points_file)?
     };
-    let srs = load_srs(&params.srs);
+    let srs = load_srs(&params.srs)?;
 
-    info!("Computing Keccak proof...");
-    let now = Instant::now();
-    let (keccak_proof, keccak_instance): (Vec<u8>, Vec<Fr>) = {
-        let circuit = KeccakCircuit::<_, G1Affine>::prover(
+    let (keccak_proof, keccak_instance) = {
+        let progress_bar = keccak_progress_bar();
+        let proof_and_instance = keccak_prove(
+            &srs,
+            &pk,
             &keccak_config,
             &gate_config,
             break_points,
-            &KeccakCircuitInputs {
-                inputs: keccak_inputs,
-                num_proof_ids: params.num_proof_ids,
-            },
-        );
+            &keccak_inputs,
+            params.self_check,
+            &mut |progress| update_progress_bar(&progress_bar, progress),
+        )?;
+        progress_bar.finish_and_clear();
+        proof_and_instance
+    };
 
-        // TODO: better interface for instance.  Avoid copy when returning.
+    if params.verify_after_prove {
+        keccak_verify(&srs, pk.get_vk(), &keccak_proof, &keccak_instance)?;
+    }
 
-        let instances = circuit.instances();
+    // Write the proof
 
-        // TODO: Better Rng than OsRng?
+    save_proof(&params.proof, &keccak_proof)?;
+    save_instance(&instance_file, &keccak_instance)
+}
 
-        let mut transcript =
-            PoseidonTranscript::<NativeLoader, _>::new::<SECURE_MDS>(vec![]);
+/// Progress bar for Keccak circuit witness generation.
+fn keccak_progress_bar() -> ProgressBar {
+    let progress_bar = ProgressBar::new(0);
+    progress_bar.set_style(
+        ProgressStyle::with_template("{msg} [{bar:40}] {pos}/{len}")
+            .expect("invalid progress bar template"),
+    );
+    progress_bar
+}
 
-        create_proof::<
-            KZGCommitmentScheme<Bn256>,
-            ProverSHPLONK<'_, Bn256>,
-            ChallengeScalar<G1Affine>,
-            _,
-            _,
-            _,
-        >(
-            &srs,
-            &pk,
-            &[circuit],
-            &[&[&instances[0]]],
-            OsRng,
-            &mut transcript,
-        )
-        .unwrap();
-        let proof = transcript.finalize();
-
-        (proof, instances[0].clone())
+fn update_progress_bar(progress_bar: &ProgressBar, progress: KeccakProgress) {
+    match progress {
+        KeccakProgress::AssigningCells { done, total } => {
+            progress_bar.set_message("assigning inputs");
+            progress_bar.set_length(total as u64);
+            progress_bar.set_position(done as u64);
+        }
+        KeccakProgress::ComputingLeaves => {
+            progress_bar.set_message("computing submission id leaves")
+        }
+        KeccakProgress::HashingRow(level) => progress_bar
+            .set_message(format!("hashing submission id tree level {level}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_prove, ProveParams};
+    use crate::{
+        default_values::{
+            KECCAK_GATE_CONFIG, KECCAK_PK, KECCAK_SRS, KECCAK_VK, UPA_CONFIG,
+        },
+        file_utils::{instance_file, load_instance, save_instance},
+        keccak::{verify, VerifyParams},
+        test_utils::{test_upa_config, KeccakTestKeys},
     };
-    info!("Finished computing Keccak proof in {:?}", now.elapsed());
+    use circuits::{
+        batch_verify::universal::{
+            types::{
+                UniversalBatchVerifierConfig, UniversalBatchVerifierInputs,
+            },
+            UniversalBatchVerifyCircuit,
+        },
+        SafeCircuit,
+    };
+    use halo2_base::halo2_proofs::halo2curves::bn256::{Fr, G1Affine};
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
 
-    // TODO: Verify the proof?
+    /// Checks that proving with `--concurrent-load` yields the same instance
+    /// as proving without it, and that both proofs verify.
+    ///
+    /// CMD: `cargo test --package prover -- keccak::prove::tests::concurrent_load_matches_sequential --exact --nocapture`
+    #[test]
+    fn concurrent_load_matches_sequential() {
+        let test_keys = KeccakTestKeys::new("keccak-concurrent-load");
+        let path = |file: &str| test_keys.path(file);
 
-    // Write the proof
+        let ubv_config = UniversalBatchVerifierConfig::from(&test_upa_config());
+        let ubv_inputs = UniversalBatchVerifierInputs::sample(
+            &ubv_config,
+            true,
+            &mut ChaCha20Rng::seed_from_u64(0),
+        );
+        let ubv_instance =
+            UniversalBatchVerifyCircuit::<Fr, G1Affine>::compute_instance(
+                &ubv_config,
+                &ubv_inputs,
+            );
+        let ubv_instance_file = path("ubv.instance");
+        save_instance(&ubv_instance_file, &ubv_instance).unwrap();
 
-    save_proof(&params.proof, &keccak_proof);
-    save_instance(&instance_file, &keccak_instance);
+        let instances: Vec<Vec<Fr>> = [false, true]
+            .into_iter()
+            .map(|concurrent_load| {
+                let proof = path(&format!("keccak-{concurrent_load}.proof"));
+                run_prove(ProveParams {
+                    config: path(UPA_CONFIG),
+                    srs: path(KECCAK_SRS),
+                    proving_key: path(KECCAK_PK),
+                    gate_config: path(KECCAK_GATE_CONFIG),
+                    ubv_instances: vec![ubv_instance_file.clone()],
+                    num_proof_ids: None,
+                    proof: proof.clone(),
+                    instance: None,
+                    self_check: false,
+                    verify_after_prove: false,
+                    concurrent_load,
+                    dry_run: false,
+                    threads: None,
+                })
+                .unwrap();
+                verify(VerifyParams {
+                    srs: path(KECCAK_SRS),
+                    verification_key: path(KECCAK_VK),
+                    gate_config: path(KECCAK_GATE_CONFIG),
+                    proof: proof.clone(),
+                    instance: None,
+                    dry_run: false,
+                })
+                .unwrap();
+                load_instance(&instance_file(None, &proof)).unwrap()
+            })
+            .collect();
+        assert_eq!(instances[0], instances[1]);
+    }
 }
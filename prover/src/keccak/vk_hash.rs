@@ -0,0 +1,67 @@
+use crate::{
+    default_values::KECCAK_GATE_CONFIG,
+    error::ProverError,
+    file_utils::{load_gate_config, load_upa_config},
+    vk_hash::VkHashArgs,
+};
+use circuits::keccak::{KeccakCircuit, KeccakGateConfig};
+use clap::Parser;
+use halo2_base::halo2_proofs::halo2curves::bn256::G1Affine;
+
+#[derive(Debug, Parser)]
+pub struct VkHashParams {
+    #[command(flatten)]
+    pub(crate) args: VkHashArgs,
+
+    /// Circuit specs file (KeccakGateConfig)
+    #[arg(short = 'g', long, value_name = "gate-config-file", default_value = KECCAK_GATE_CONFIG)]
+    pub(crate) gate_config: String,
+}
+
+pub fn vk_hash(params: VkHashParams) -> Result<(), ProverError> {
+    let keccak_config = load_upa_config(&params.args.config)?.keccak_config();
+    let gate_config: KeccakGateConfig = load_gate_config(&params.gate_config)?;
+    params.args.print_vk_hash::<KeccakCircuit<_, G1Affine>>(
+        &keccak_config,
+        &gate_config,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        default_values::{KECCAK_PK, KECCAK_VK},
+        file_utils::load_vk_hash,
+        test_utils::{test_upa_config, KeccakTestKeys},
+    };
+    use circuits::keccak::KeccakCircuit;
+    use halo2_base::halo2_proofs::halo2curves::bn256::G1Affine;
+
+    /// Checks that the hash of the VK embedded in a Keccak PK matches the
+    /// hash of the VK written alongside it by keygen.
+    ///
+    /// CMD: `cargo test --package prover -- keccak::vk_hash::tests::pk_and_vk_hashes_agree --exact --nocapture`
+    #[test]
+    fn pk_and_vk_hashes_agree() {
+        let keccak_config = test_upa_config().keccak_config();
+        let test_keys = KeccakTestKeys::new("vk-hash");
+        let keys = &test_keys.keys;
+
+        let vk_hash = load_vk_hash::<KeccakCircuit<_, G1Affine>>(
+            &keccak_config,
+            &keys.gate_config,
+            Some(&test_keys.path(KECCAK_VK)),
+            None,
+        )
+        .unwrap();
+        let pk_hash = load_vk_hash::<KeccakCircuit<_, G1Affine>>(
+            &keccak_config,
+            &keys.gate_config,
+            None,
+            Some(&test_keys.path(KECCAK_PK)),
+        )
+        .unwrap();
+        assert_eq!(vk_hash, pk_hash);
+        assert_eq!(vk_hash, keys.pk.get_vk().transcript_repr());
+    }
+}
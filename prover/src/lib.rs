@@ -1,8 +1,16 @@
+pub mod api;
+pub mod bench;
+pub mod config_diff;
 pub mod default_values;
+pub mod error;
 pub mod file_utils;
 pub mod full;
 pub mod groth16;
 pub mod keccak;
 pub mod srs;
+#[cfg(test)]
+mod test_utils;
+pub mod thread_pool;
 pub mod universal_batch_verifier;
 pub mod universal_outer;
+pub mod vk_hash;
@@ -1,7 +1,12 @@
 #![feature(async_closure)]
 #![allow(deprecated)]
 
-use crate::{
+use clap::{Parser, Subcommand};
+use log::debug;
+use prover::{
+    bench::{bench, BenchParams},
+    config_diff::{config_diff, ConfigDiffParams},
+    file_utils,
     full::{full, FullParams},
     groth16::{groth16, Groth16Params},
     keccak::{keccak, KeccakParams},
@@ -11,17 +16,7 @@ use crate::{
     },
     universal_outer::{universal_outer, UniversalOuterParams},
 };
-use clap::{Parser, Subcommand};
-use log::debug;
-
-mod default_values;
-mod file_utils;
-mod full;
-mod groth16;
-mod keccak;
-mod srs;
-mod universal_batch_verifier;
-mod universal_outer;
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
 
 #[derive(Parser, Debug)]
 #[command(arg_required_else_help(true))]
@@ -30,6 +25,11 @@ struct Cli {
     #[arg(short, long)]
     verbose: bool,
 
+    /// Write the JSON sidecar files of generated keys (gate configs, break
+    /// points, manifests) in compact form, rather than pretty-printed
+    #[arg(long)]
+    compact_json: bool,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -59,15 +59,42 @@ enum Command {
     #[command()]
     /// End-to-end aggregated proofs generation and verification
     Full(FullParams),
+
+    #[command()]
+    /// Time keygen, prove and verify of each circuit and print a JSON report
+    Bench(BenchParams),
+
+    #[command()]
+    /// Show the differences between two UPA configs and whether keys
+    /// generated for one can be reused with the other
+    ConfigDiff(ConfigDiffParams),
+}
+
+/// Installs a `tracing` subscriber filtered by `RUST_LOG`, which also
+/// receives the `log` records of the prover and its dependencies.  Closed
+/// spans (e.g. `assign_proofs`, `verify`, `keccak_synthesize`,
+/// `aggregation_build`) are reported with their durations.  Set
+/// `UPA_LOG_FORMAT=json` to emit one JSON object per line.
+fn init_tracing() {
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr);
+    match std::env::var("UPA_LOG_FORMAT").as_deref() {
+        Ok("json") => subscriber.json().init(),
+        _ => subscriber.init(),
+    }
 }
 
 fn main() {
-    env_logger::init();
+    init_tracing();
     let cli = Cli::parse();
 
     debug!("{cli:?}");
+    file_utils::set_verbose(cli.verbose);
+    file_utils::set_compact_json(cli.compact_json);
 
-    match cli.command {
+    let result = match cli.command {
         Command::Srs(params) => srs(params),
         Command::UniversalBatchVerifier(params) => {
             universal_batch_verifier(params)
@@ -76,5 +103,12 @@ fn main() {
         Command::UniversalOuter(params) => universal_outer(params),
         Command::Groth16(params) => groth16(params),
         Command::Full(params) => full(params),
+        Command::Bench(params) => bench(params),
+        Command::ConfigDiff(params) => config_diff(params),
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {e}");
+        std::process::exit(1);
     }
 }
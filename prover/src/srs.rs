@@ -1,11 +1,20 @@
-use crate::file_utils::{create_file_no_overwrite, panic_if_file_exists};
+use crate::{
+    error::ProverError,
+    file_utils::{
+        create_file_no_overwrite, fail_if_file_exists, load_srs,
+        open_file_for_read,
+    },
+};
+use circuits::utils::srs::{gen_deterministic_srs, srs_from_ptau};
 use clap::{Parser, Subcommand};
 use halo2_base::halo2_proofs::{
-    halo2curves::bn256::Bn256,
-    poly::{commitment::Params, kzg::commitment::ParamsKZG},
+    halo2curves::{
+        bn256::{Bn256, G1Affine},
+        pairing::Engine,
+    },
+    poly::commitment::Params,
 };
-use log::debug;
-use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use log::{debug, info};
 use std::io::BufWriter;
 
 #[derive(Parser, Debug)]
@@ -20,6 +29,14 @@ enum SrsCommand {
     #[command()]
     /// Locally generate an SRS and write to a file.
     Generate(GenerateParams),
+
+    #[command()]
+    /// Check that an SRS file is well-formed and has the expected degree.
+    Verify(VerifyParams),
+
+    #[command()]
+    /// Convert a perpetual-powers-of-tau `.ptau` file to an SRS file.
+    ImportPtau(ImportPtauParams),
 }
 
 #[derive(Parser, Debug)]
@@ -29,27 +46,121 @@ struct GenerateParams {
 
     #[arg(short, long)]
     srs_file: String,
+
+    /// Seed for the RNG used to generate the SRS.  The same degree and seed
+    /// always give a bit-identical SRS.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+#[derive(Parser, Debug)]
+struct VerifyParams {
+    #[arg(short, long)]
+    /// Expected degree (log-2 of the number of G1 powers)
+    degree_bits: u32,
+
+    #[arg(short, long)]
+    srs_file: String,
+}
+
+#[derive(Parser, Debug)]
+struct ImportPtauParams {
+    /// Input `.ptau` file
+    #[arg(long = "in", value_name = "ptau-file")]
+    input: String,
+
+    /// Output SRS file
+    #[arg(long = "out", value_name = "srs-file")]
+    output: String,
+
+    /// Degree (log-2 of the number of G1 powers) of the output SRS.  Must
+    /// not exceed the maximum power of the `.ptau` file.
+    #[arg(short, long)]
+    degree: u32,
 }
 
-pub fn srs(params: SrsParams) {
+pub fn srs(params: SrsParams) -> Result<(), ProverError> {
     debug!("srs: {params:?}");
 
     match params.command {
         SrsCommand::Generate(params) => generate(params),
+        SrsCommand::Verify(params) => verify(params),
+        SrsCommand::ImportPtau(params) => import_ptau(params),
     }
 }
 
-fn generate(params: GenerateParams) {
+fn generate(params: GenerateParams) -> Result<(), ProverError> {
     debug!("generate: {params:?}");
 
-    panic_if_file_exists(&params.srs_file);
+    fail_if_file_exists(&params.srs_file)?;
+
+    let srs = gen_deterministic_srs(params.degree_bits, params.seed);
+
+    let f = create_file_no_overwrite(&params.srs_file)?;
+    srs.write(&mut BufWriter::new(f))
+        .map_err(|e| ProverError::io(&params.srs_file, e))
+}
+
+fn import_ptau(params: ImportPtauParams) -> Result<(), ProverError> {
+    debug!("import_ptau: {params:?}");
+
+    fail_if_file_exists(&params.output)?;
 
-    let srs = ParamsKZG::<Bn256>::setup(
-        params.degree_bits,
-        ChaCha20Rng::from_seed(Default::default()),
-    );
+    info!("importing {} (degree {})", params.input, params.degree);
+    let srs =
+        srs_from_ptau(&mut open_file_for_read(&params.input)?, params.degree)
+            .map_err(|e| ProverError::decode(&params.input, "ptau", e))?;
 
-    let f = create_file_no_overwrite(&params.srs_file);
+    let f = create_file_no_overwrite(&params.output)?;
     srs.write(&mut BufWriter::new(f))
-        .expect("failed to write srs");
+        .map_err(|e| ProverError::io(&params.output, e))
+}
+
+fn verify(params: VerifyParams) -> Result<(), ProverError> {
+    debug!("verify: {params:?}");
+
+    let srs = load_srs(&params.srs_file)?;
+    let g = srs.get_g();
+
+    // (description, passed)
+    let checks = [
+        (
+            format!(
+                "degree: expected {}, found {}",
+                params.degree_bits,
+                srs.k()
+            ),
+            srs.k() == params.degree_bits,
+        ),
+        (
+            format!("number of G1 powers: {}", g.len()),
+            g.len() == 1 << srs.k(),
+        ),
+        (
+            "G1 generator is (1, 2)".to_string(),
+            g.first() == Some(&G1Affine::generator()),
+        ),
+        (
+            "pairing check e([tau]_1, [1]_2) == e([1]_1, [tau]_2)".to_string(),
+            g.len() > 1
+                && Bn256::pairing(&g[1], &srs.g2())
+                    == Bn256::pairing(&g[0], &srs.s_g2()),
+        ),
+    ];
+
+    let mut success = true;
+    for (description, passed) in checks.iter() {
+        let status = if *passed { "OK" } else { "FAILED" };
+        println!("{status:>6}  {description}");
+        success &= passed;
+    }
+
+    if !success {
+        return Err(ProverError::InvalidInput(format!(
+            "SRS {} is invalid",
+            params.srs_file
+        )));
+    }
+    println!("SRS {} is valid", params.srs_file);
+    Ok(())
 }
@@ -0,0 +1,132 @@
+//! Helpers shared by the unit tests.
+
+use crate::{
+    api::{keccak_keygen, CircuitKeys},
+    default_values::{
+        KECCAK_GATE_CONFIG, KECCAK_PK, KECCAK_SRS, KECCAK_VK, UPA_CONFIG,
+    },
+    file_utils::{
+        break_points_file, save_break_points, save_gate_config, save_pk,
+        save_vk, write_json_pretty,
+    },
+};
+use circuits::{
+    batch_verify::{
+        common::chip::{WINDOW_BITS, WINDOW_BITS_G2_SUBGROUP_CHECK},
+        universal::types::UniversalBatchVerifierConfig,
+    },
+    keccak::{KeccakCircuit, KeccakGateConfig},
+    utils::upa_config::UpaConfig,
+    CircuitConfig, CircuitWithLimbsConfig,
+};
+use halo2_base::halo2_proofs::{
+    halo2curves::bn256::{Bn256, G1Affine},
+    poly::{commitment::Params, kzg::commitment::ParamsKZG},
+};
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::PathBuf,
+};
+
+/// Returns a small [`UpaConfig`], whose Keccak circuit is cheap enough to
+/// generate keys for in tests.
+pub(crate) fn test_upa_config() -> UpaConfig {
+    UpaConfig {
+        max_num_app_public_inputs: 4,
+        inner_batch_size: 1,
+        outer_batch_size: 1,
+        bv_config: CircuitWithLimbsConfig::from_degree_bits(19),
+        keccak_config: CircuitConfig {
+            degree_bits: 18,
+            lookup_bits: 17,
+        },
+        outer_config: CircuitWithLimbsConfig::from_degree_bits(20),
+        output_submission_id: false,
+        allow_commitments: true,
+        public_num_proof_ids: false,
+        include_vk_digest: false,
+        window_bits: WINDOW_BITS,
+        g2_subgroup_window_bits: WINDOW_BITS_G2_SUBGROUP_CHECK,
+    }
+}
+
+/// Returns the [`UniversalBatchVerifierConfig`] of [`test_upa_config`],
+/// with `inner_batch_size` and `max_num_public_inputs`.
+pub(crate) fn test_ubv_config(
+    inner_batch_size: u32,
+    max_num_public_inputs: u32,
+) -> UniversalBatchVerifierConfig {
+    UniversalBatchVerifierConfig {
+        inner_batch_size,
+        max_num_public_inputs,
+        ..UniversalBatchVerifierConfig::from(&test_upa_config())
+    }
+}
+
+/// Keccak keys for [`test_upa_config`], together with the files read by the
+/// `keccak` commands (UPA config, SRS, PK, VK, gate config and break
+/// points, under their default names) in a fresh temporary directory, which
+/// is removed on drop.
+pub(crate) struct KeccakTestKeys {
+    pub(crate) srs: ParamsKZG<Bn256>,
+    pub(crate) keys: CircuitKeys<KeccakGateConfig>,
+    dir: PathBuf,
+}
+
+impl KeccakTestKeys {
+    /// Generates the keys in a temporary directory named after `name`.
+    pub(crate) fn new(name: &str) -> Self {
+        let upa_config = test_upa_config();
+        let keccak_config = upa_config.keccak_config();
+        let srs = ParamsKZG::<Bn256>::setup(
+            keccak_config.degree_bits,
+            ChaCha20Rng::seed_from_u64(0),
+        );
+        let keys = keccak_keygen(&keccak_config, &srs);
+
+        let dir = std::env::temp_dir()
+            .join(format!("upa-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let test_keys = Self { srs, keys, dir };
+        let path = |file: &str| test_keys.path(file);
+        write_json_pretty(&path(UPA_CONFIG), &upa_config).unwrap();
+        test_keys
+            .srs
+            .write(&mut BufWriter::new(File::create(path(KECCAK_SRS)).unwrap()))
+            .unwrap();
+        save_pk::<KeccakCircuit<_, G1Affine>>(
+            &path(KECCAK_PK),
+            &test_keys.keys.pk,
+        )
+        .unwrap();
+        save_vk::<KeccakCircuit<_, G1Affine>>(
+            &path(KECCAK_VK),
+            test_keys.keys.pk.get_vk(),
+        )
+        .unwrap();
+        save_gate_config(
+            &path(KECCAK_GATE_CONFIG),
+            &test_keys.keys.gate_config,
+        )
+        .unwrap();
+        save_break_points(
+            &break_points_file(&path(KECCAK_PK)),
+            &test_keys.keys.break_points,
+        )
+        .unwrap();
+        test_keys
+    }
+
+    /// Returns the path of `file` in the temporary directory.
+    pub(crate) fn path(&self, file: &str) -> String {
+        self.dir.join(file).to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for KeccakTestKeys {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,26 @@
+use log::info;
+use rayon::ThreadPoolBuilder;
+
+/// Runs `f` inside a dedicated rayon thread pool of `threads` threads.  Any
+/// rayon parallelism triggered from within `f` (witness generation, the
+/// keccak chip's row data computation, `create_proof`, key generation, etc)
+/// runs in this pool.  If `threads` is `None`, `f` is run directly, using
+/// rayon's default global pool.
+pub(crate) fn with_thread_pool<R, F>(threads: Option<usize>, f: F) -> R
+where
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    match threads {
+        Some(num_threads) => {
+            assert!(num_threads > 0, "number of threads must be positive");
+            info!("using rayon thread pool with {num_threads} threads");
+            let pool = ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .build()
+                .unwrap_or_else(|e| panic!("failed to build thread pool: {e}"));
+            pool.install(f)
+        }
+        None => f(),
+    }
+}
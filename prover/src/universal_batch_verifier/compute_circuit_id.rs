@@ -1,15 +1,64 @@
-use circuits::batch_verify::{common::native::json::load_vk, universal};
+use super::{bytes_to_hex, check_vk, Encoding, OutputFormat};
+use crate::error::ProverError;
+use circuits::batch_verify::universal::{
+    native::compute_circuit_id_with_domain_version, types::DomainTagVersion,
+};
 use clap::Parser;
+use serde::Serialize;
 
 #[derive(Parser, Debug)]
 pub struct ComputeCircuitIDParams {
     #[arg(long, value_name = "app-vk-file")]
     /// Groth16 verification key of the application.
     app_vk: String,
+
+    #[arg(long, value_enum, default_value = "json")]
+    /// Encoding of the app vk file.
+    encoding: Encoding,
+
+    #[arg(long, value_enum, default_value = "hex")]
+    /// Output format.
+    format: OutputFormat,
+
+    /// Protocol version of the circuit id domain tags (currently only 1.0.0)
+    #[arg(long, value_name = "version", default_value = "1.0.0")]
+    domain_version: DomainTagVersion,
+}
+
+/// JSON output of the `compute-circuit-id` subcommand.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CircuitIdOutput {
+    circuit_id: String,
+    has_commitment: bool,
+    num_public_inputs: usize,
 }
 
-pub fn compute_circuit_id(params: ComputeCircuitIDParams) {
-    let vk = load_vk(&params.app_vk);
-    let circuit_id = universal::native::compute_circuit_id(&vk);
-    println!("0x{}", hex::encode(circuit_id));
+pub fn compute_circuit_id(
+    params: ComputeCircuitIDParams,
+) -> Result<(), ProverError> {
+    let vk = params.encoding.load_vk(&params.app_vk)?;
+    check_vk(&params.app_vk, "", &vk)?;
+    let circuit_id =
+        compute_circuit_id_with_domain_version(&vk, params.domain_version);
+    match params.format {
+        OutputFormat::Hex => println!("{}", bytes_to_hex(&circuit_id)),
+        OutputFormat::Json => {
+            let has_commitment = vk.has_commitment();
+            // `vk.s` has one extra element, plus one for the commitment
+            // hash, if any.
+            let num_public_inputs = vk.s.len() - 1 - has_commitment as usize;
+            let output = CircuitIdOutput {
+                circuit_id: bytes_to_hex(&circuit_id),
+                has_commitment,
+                num_public_inputs,
+            };
+            println!(
+                "{}",
+                serde_json::to_string(&output)
+                    .unwrap_or_else(|e| panic!("failed to serialize: {e}"))
+            );
+        }
+    }
+    Ok(())
 }
@@ -1,24 +1,456 @@
-use crate::file_utils::load_app_vk_proof_inputs;
-use circuits::{batch_verify::universal::native::compute_circuit_id, keccak};
+use super::{
+    bytes_to_hex, replace_inputs_hex, replace_inputs_hex_batch, Encoding,
+    InputFormat, OutputFormat,
+};
+use crate::error::ProverError;
+use circuits::{
+    batch_verify::{
+        common::types::VerificationKey,
+        universal::{
+            native::compute_circuit_id_with_domain_version,
+            types::{DomainTagVersion, UniversalBatchVerifierInput},
+        },
+    },
+    keccak,
+    utils::field_elements_hex,
+};
 use clap::Parser;
 use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
-use hex::ToHex;
+use log::info;
+use serde::Serialize;
 
 #[derive(Parser, Debug)]
 pub struct ComputeProofIDParams {
-    #[arg(short = 'i', long, value_name = "app-vk-proof-and-inputs")]
+    #[arg(
+        short = 'i',
+        long,
+        value_name = "app-vk-proof-and-inputs",
+        required_unless_present = "batch_file",
+        conflicts_with = "batch_file"
+    )]
     /// JSON file containing an app vk, proof and public inputs.
-    app_vk_proof_and_inputs: String,
+    app_vk_proof_and_inputs: Option<String>,
+
+    #[arg(short = 'b', long, value_name = "app-vk-proof-batch-file")]
+    /// JSON file containing an array of app vk, proof and public inputs.
+    /// Outputs a JSON array with the output of each entry in `--format`, in
+    /// order.
+    batch_file: Option<String>,
+
+    #[arg(long, requires = "batch_file", conflicts_with = "inputs_hex")]
+    /// The batch file is JSONL (one app vk, proof and inputs object per
+    /// line) and is read one entry at a time, so that large batches need not
+    /// fit in memory.  Requires the native input format.
+    jsonl: bool,
+
+    #[arg(long, value_enum, default_value = "native")]
+    /// Format of the input file(s).
+    input_format: InputFormat,
+
+    #[arg(long, value_enum, default_value = "json")]
+    /// Encoding of the vk, proof and inputs in the input file(s).
+    encoding: Encoding,
+
+    #[arg(long, value_enum, default_value = "hex")]
+    /// Output format.
+    format: OutputFormat,
+
+    /// Protocol version of the circuit id domain tags (currently only 1.0.0)
+    #[arg(long, value_name = "version", default_value = "1.0.0")]
+    domain_version: DomainTagVersion,
+
+    /// JSON file holding the public inputs as `0x`-prefixed big-endian hex
+    /// strings (as an EVM `uint256[]`), used in place of the inputs in the
+    /// input file.  With `--batch-file`, holds one such array per entry.
+    #[arg(long, value_name = "inputs-hex-file")]
+    inputs_hex: Option<String>,
+}
+
+/// JSON output of the `compute-proof-id` subcommand.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProofIdOutput<'a> {
+    proof_id: String,
+    circuit_id: String,
+    #[serde(with = "field_elements_hex")]
+    public_inputs: &'a [Fr],
 }
 
-pub fn compute_proof_id(params: ComputeProofIDParams) {
+pub fn compute_proof_id(
+    params: ComputeProofIDParams,
+) -> Result<(), ProverError> {
+    if let Some(batch_file) = params.batch_file {
+        return compute_proof_id_batch(
+            &batch_file,
+            params.input_format,
+            params.encoding,
+            params.domain_version,
+            params.inputs_hex.as_deref(),
+            params.jsonl,
+            params.format,
+        );
+    }
+
     // Load
-    let app_vk_proof_and_inputs =
-        load_app_vk_proof_inputs::<Fr>(&params.app_vk_proof_and_inputs);
-    let circuit_id = compute_circuit_id(&app_vk_proof_and_inputs.vk);
+    let mut app_vk_proof_and_inputs =
+        params.encoding.load_app_vk_proof_inputs(
+            params.input_format,
+            &params
+                .app_vk_proof_and_inputs
+                .expect("app-vk-proof-and-inputs file must be given"),
+        )?;
+    if let Some(inputs_hex) = &params.inputs_hex {
+        replace_inputs_hex(inputs_hex, &mut app_vk_proof_and_inputs)?;
+    }
+    let circuit_id = compute_circuit_id_with_domain_version(
+        &app_vk_proof_and_inputs.vk,
+        params.domain_version,
+    );
     let proof_id = keccak::utils::compute_proof_id(
         &circuit_id,
         &app_vk_proof_and_inputs.inputs.0,
     );
-    println!("0x{}", proof_id.encode_hex::<String>());
+    match params.format {
+        OutputFormat::Hex => println!("{}", bytes_to_hex(&proof_id)),
+        OutputFormat::Json => {
+            let output = ProofIdOutput {
+                proof_id: bytes_to_hex(&proof_id),
+                circuit_id: bytes_to_hex(&circuit_id),
+                public_inputs: &app_vk_proof_and_inputs.inputs.0,
+            };
+            println!(
+                "{}",
+                serde_json::to_string(&output)
+                    .unwrap_or_else(|e| panic!("failed to serialize: {e}"))
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Computes the proof ids of all entries in `batch_file` and prints them as a
+/// JSON array: of hex strings for [`OutputFormat::Hex`], or of
+/// [`ProofIdOutput`] objects for [`OutputFormat::Json`].
+fn compute_proof_id_batch(
+    batch_file: &str,
+    input_format: InputFormat,
+    encoding: Encoding,
+    domain_version: DomainTagVersion,
+    inputs_hex: Option<&str>,
+    jsonl: bool,
+    format: OutputFormat,
+) -> Result<(), ProverError> {
+    let outputs = if jsonl {
+        batch_proof_ids(
+            encoding
+                .iter_app_vk_proof_inputs_jsonl(input_format, batch_file)?,
+            domain_version,
+            format,
+        )?
+    } else {
+        let mut ubv_inputs = encoding
+            .load_app_vk_proof_inputs_batch(input_format, batch_file)?;
+        if let Some(inputs_hex) = inputs_hex {
+            replace_inputs_hex_batch(inputs_hex, &mut ubv_inputs.0)?;
+        }
+        batch_proof_ids(
+            ubv_inputs.0.into_iter().map(Ok),
+            domain_version,
+            format,
+        )?
+    };
+    println!(
+        "{}",
+        serde_json::to_string(&outputs)
+            .unwrap_or_else(|e| panic!("failed to serialize: {e}"))
+    );
+    Ok(())
+}
+
+/// Returns the output in `format` of each entry of `inputs`, in order,
+/// consuming the entries one at a time: its proof id as a hex string, or a
+/// [`ProofIdOutput`] object. The circuit id of each distinct vk is computed
+/// only once.
+fn batch_proof_ids(
+    inputs: impl Iterator<
+        Item = Result<UniversalBatchVerifierInput<Fr>, ProverError>,
+    >,
+    domain_version: DomainTagVersion,
+    format: OutputFormat,
+) -> Result<Vec<serde_json::Value>, ProverError> {
+    // Batches typically contain few distinct vks, so a linear scan suffices.
+    let mut circuit_ids: Vec<(VerificationKey, [u8; 32])> = Vec::new();
+    let mut outputs = Vec::new();
+    for input in inputs {
+        let input = input?;
+        let circuit_id =
+            match circuit_ids.iter().find(|(vk, _)| vk == &input.vk) {
+                Some((_, circuit_id)) => *circuit_id,
+                None => {
+                    let circuit_id = compute_circuit_id_with_domain_version(
+                        &input.vk,
+                        domain_version,
+                    );
+                    circuit_ids.push((input.vk, circuit_id));
+                    circuit_id
+                }
+            };
+        let proof_id =
+            keccak::utils::compute_proof_id(&circuit_id, &input.inputs.0);
+        outputs.push(match format {
+            OutputFormat::Hex => {
+                serde_json::Value::String(bytes_to_hex(&proof_id))
+            }
+            OutputFormat::Json => serde_json::to_value(ProofIdOutput {
+                proof_id: bytes_to_hex(&proof_id),
+                circuit_id: bytes_to_hex(&circuit_id),
+                public_inputs: &input.inputs.0,
+            })
+            .unwrap_or_else(|e| panic!("failed to serialize: {e}")),
+        });
+    }
+    info!(
+        "computed {} proof ids ({} distinct vks)",
+        outputs.len(),
+        circuit_ids.len()
+    );
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{batch_proof_ids, compute_proof_id, ComputeProofIDParams};
+    use crate::{
+        error::ProverError,
+        test_utils::test_ubv_config,
+        universal_batch_verifier::{Encoding, InputFormat, OutputFormat},
+    };
+    use circuits::batch_verify::universal::{
+        native::json::JsonUniversalBatchVerifierInput,
+        types::{DomainTagVersion, UniversalBatchVerifierInputs},
+    };
+    use clap::Parser;
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+    use serde::Serialize;
+    use std::fs;
+
+    /// Checks that a batch written as a JSON array and as JSONL yields the
+    /// same proof ids, and that the JSON output format holds the same proof
+    /// ids.
+    ///
+    /// CMD: `cargo test --package prover -- universal_batch_verifier::compute_proof_id::tests::jsonl_matches_json_array --exact --nocapture`
+    #[test]
+    fn jsonl_matches_json_array() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(1);
+        let config = test_ubv_config(3, 4);
+        let domain_version = DomainTagVersion::default();
+        let ubv_inputs =
+            UniversalBatchVerifierInputs::sample_mixed(&config, rng);
+        let json: Vec<JsonUniversalBatchVerifierInput> = ubv_inputs
+            .0
+            .iter()
+            .map(JsonUniversalBatchVerifierInput::from)
+            .collect();
+
+        let dir = std::env::temp_dir()
+            .join(format!("upa-proof-ids-jsonl-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let jsonl: String = json
+            .iter()
+            .map(|entry| serde_json::to_string(entry).unwrap() + "\n")
+            .collect();
+        fs::write(path("batch.json"), serde_json::to_string(&json).unwrap())
+            .unwrap();
+        fs::write(path("batch.jsonl"), jsonl).unwrap();
+
+        let array_inputs = Encoding::Json
+            .load_app_vk_proof_inputs_batch(
+                InputFormat::Native,
+                &path("batch.json"),
+            )
+            .unwrap();
+        let array_ids = batch_proof_ids(
+            array_inputs.0.clone().into_iter().map(Ok),
+            domain_version,
+            OutputFormat::Hex,
+        )
+        .unwrap();
+        let jsonl_ids = batch_proof_ids(
+            Encoding::Json
+                .iter_app_vk_proof_inputs_jsonl(
+                    InputFormat::Native,
+                    &path("batch.jsonl"),
+                )
+                .unwrap(),
+            domain_version,
+            OutputFormat::Hex,
+        )
+        .unwrap();
+
+        assert_eq!(array_ids.len(), config.inner_batch_size as usize);
+        assert_eq!(array_ids, jsonl_ids);
+
+        let json_outputs = batch_proof_ids(
+            array_inputs.0.into_iter().map(Ok),
+            domain_version,
+            OutputFormat::Json,
+        )
+        .unwrap();
+        let json_ids: Vec<_> = json_outputs
+            .iter()
+            .map(|output| output["proofId"].clone())
+            .collect();
+        assert_eq!(json_ids, array_ids);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Checks that `--inputs-hex` files, for a single entry and for a batch,
+    /// are rejected unless they hold one input per public input of the vk.
+    ///
+    /// CMD: `cargo test --package prover -- universal_batch_verifier::compute_proof_id::tests::inputs_hex_count_checked --exact --nocapture`
+    #[test]
+    fn inputs_hex_count_checked() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(1);
+        let config = test_ubv_config(2, 4);
+        let ubv_inputs =
+            UniversalBatchVerifierInputs::sample_mixed(&config, rng);
+        let json: Vec<JsonUniversalBatchVerifierInput> = ubv_inputs
+            .0
+            .iter()
+            .map(JsonUniversalBatchVerifierInput::from)
+            .collect();
+        let hex_inputs: Vec<Vec<String>> = ubv_inputs
+            .0
+            .iter()
+            .map(|input| input.inputs.to_hex_vec())
+            .collect();
+        let mut extra_hex_inputs = hex_inputs.clone();
+        extra_hex_inputs[1].push(extra_hex_inputs[1][0].clone());
+
+        let dir = std::env::temp_dir()
+            .join(format!("upa-inputs-hex-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fn write_json<T: Serialize>(path: String, value: &T) {
+            fs::write(path, serde_json::to_string(value).unwrap()).unwrap()
+        }
+        write_json(path("entry.json"), &json[1]);
+        write_json(path("batch.json"), &json);
+        write_json(path("entry_inputs.json"), &hex_inputs[1]);
+        write_json(path("entry_extra_inputs.json"), &extra_hex_inputs[1]);
+        write_json(path("batch_inputs.json"), &hex_inputs);
+        write_json(path("batch_extra_inputs.json"), &extra_hex_inputs);
+
+        let run = |file_flag: &str, file: &str, inputs_hex: &str| {
+            compute_proof_id(ComputeProofIDParams::parse_from([
+                "compute-proof-id",
+                file_flag,
+                &path(file),
+                "--inputs-hex",
+                &path(inputs_hex),
+            ]))
+        };
+        run("-i", "entry.json", "entry_inputs.json").unwrap();
+        run("-b", "batch.json", "batch_inputs.json").unwrap();
+        for (file_flag, file, inputs_hex) in [
+            ("-i", "entry.json", "entry_extra_inputs.json"),
+            ("-b", "batch.json", "batch_extra_inputs.json"),
+        ] {
+            match run(file_flag, file, inputs_hex) {
+                Err(ProverError::InvalidInput(e)) => assert!(
+                    e.contains("hex inputs, but the vk has"),
+                    "unexpected error: {e}"
+                ),
+                result => panic!("expected InvalidInput, got {result:?}"),
+            }
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Checks that a vk with more than one commitment is rejected, as by
+    /// `compute-circuit-id`, for a single entry, a batch and a JSONL batch.
+    ///
+    /// CMD: `cargo test --package prover -- universal_batch_verifier::compute_proof_id::tests::invalid_vk_rejected --exact --nocapture`
+    #[test]
+    fn invalid_vk_rejected() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(1);
+        let config = test_ubv_config(2, 4);
+        let ubv_inputs =
+            UniversalBatchVerifierInputs::sample(&config, true, rng);
+        let mut json: Vec<JsonUniversalBatchVerifierInput> = ubv_inputs
+            .0
+            .iter()
+            .map(JsonUniversalBatchVerifierInput::from)
+            .collect();
+        let vk = &mut json[1].vk;
+        vk.h1.push(vk.h1[0].clone());
+        vk.h2.push(vk.h2[0].clone());
+
+        let dir = std::env::temp_dir()
+            .join(format!("upa-proof-id-invalid-vk-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("entry.json"), serde_json::to_string(&json[1]).unwrap())
+            .unwrap();
+        fs::write(path("batch.json"), serde_json::to_string(&json).unwrap())
+            .unwrap();
+        let jsonl: String = json
+            .iter()
+            .map(|entry| serde_json::to_string(entry).unwrap() + "\n")
+            .collect();
+        fs::write(path("batch.jsonl"), jsonl).unwrap();
+
+        for (file_flag, file, extra_args, entry) in [
+            ("-i", "entry.json", vec![], ""),
+            ("-b", "batch.json", vec![], "input 1: "),
+            ("-b", "batch.jsonl", vec!["--jsonl"], "input 1: "),
+        ] {
+            let file = path(file);
+            let args = ["compute-proof-id", file_flag, &file]
+                .into_iter()
+                .chain(extra_args);
+            match compute_proof_id(ComputeProofIDParams::parse_from(args)) {
+                Err(ProverError::InvalidInput(e)) => assert_eq!(
+                    e,
+                    format!(
+                        "{file}: {entry}vk has 2 h1 and 2 h2 points \
+                         (expected 0 or 1 of each)"
+                    )
+                ),
+                result => panic!("expected InvalidInput, got {result:?}"),
+            }
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Checks that base64 encoding with a non-native input format is
+    /// rejected with an error, before the input file is opened.
+    ///
+    /// CMD: `cargo test --package prover -- universal_batch_verifier::compute_proof_id::tests::base64_requires_native_format --exact --nocapture`
+    #[test]
+    fn base64_requires_native_format() {
+        for file_flag in ["-i", "-b"] {
+            let result = compute_proof_id(ComputeProofIDParams::parse_from([
+                "compute-proof-id",
+                file_flag,
+                "missing-input.json",
+                "--encoding",
+                "base64",
+                "--input-format",
+                "snarkjs",
+            ]));
+            match result {
+                Err(ProverError::InvalidInput(e)) => assert_eq!(
+                    e,
+                    "--encoding base64 requires the native input format, \
+                     got Snarkjs"
+                ),
+                result => panic!("expected InvalidInput, got {result:?}"),
+            }
+        }
+    }
 }
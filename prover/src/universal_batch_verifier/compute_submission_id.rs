@@ -1,11 +1,18 @@
+use super::{
+    bytes_to_hex, replace_inputs_hex_batch, Encoding, InputFormat, OutputFormat,
+};
+use crate::{
+    error::ProverError,
+    file_utils::{fail_if_file_exists, save_csv},
+};
 use circuits::{
-    batch_verify::universal::native::{
-        compute_circuit_id, json::load_app_vk_proof_and_inputs_batch,
+    batch_verify::universal::{
+        native::compute_circuit_id_with_domain_version, types::DomainTagVersion,
     },
     keccak::utils,
 };
 use clap::Parser;
-use core::iter;
+use serde::Serialize;
 
 #[derive(Parser, Debug)]
 pub struct ComputeSubmissionIdParams {
@@ -13,21 +20,157 @@ pub struct ComputeSubmissionIdParams {
     /// to be verified by the BatchVerifier circuit.
     #[arg(short = 'b', long, value_name = "app-vk-proof-batch-file")]
     pub(crate) app_vk_proof_batch: String,
+
+    /// Encoding of the vks, proofs and inputs in the batch file.
+    #[arg(long, value_enum, default_value = "json")]
+    pub(crate) encoding: Encoding,
+
+    /// The batch file is JSONL (one app vk, proof and inputs object per
+    /// line) and is read one entry at a time, so that large batches need not
+    /// fit in memory.
+    #[arg(long, conflicts_with = "inputs_hex")]
+    pub(crate) jsonl: bool,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value = "hex")]
+    pub(crate) format: OutputFormat,
+
+    /// Protocol version of the circuit id domain tags (currently only 1.0.0)
+    #[arg(long, value_name = "version", default_value = "1.0.0")]
+    pub(crate) domain_version: DomainTagVersion,
+
+    /// JSON file holding, for each entry of the batch, an array of public
+    /// inputs as `0x`-prefixed big-endian hex strings (as an EVM
+    /// `uint256[]`), used in place of the inputs in the batch file.
+    #[arg(long, value_name = "inputs-hex-file")]
+    pub(crate) inputs_hex: Option<String>,
+
+    /// Also write the circuit and proof id of each entry to this CSV file,
+    /// followed by a summary row holding the submission id.
+    #[arg(long, value_name = "csv-file")]
+    pub(crate) csv: Option<String>,
 }
 
-pub fn compute_submission_id(params: ComputeSubmissionIdParams) {
-    let ubv_inputs =
-        load_app_vk_proof_and_inputs_batch(&params.app_vk_proof_batch);
-    let num_proof_ids = ubv_inputs.0.len();
-    let submission_size = num_proof_ids.next_power_of_two();
-    let mut proof_ids = Vec::with_capacity(submission_size);
-    for input in ubv_inputs.0 {
-        let circuit_id = compute_circuit_id(&input.vk);
+/// JSON output of the `compute-submission-id` subcommand.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SubmissionIdOutput {
+    submission_id: String,
+    num_proof_ids: usize,
+    /// The (unpadded) proof ids making up the submission
+    proof_ids: Vec<String>,
+}
+
+pub fn compute_submission_id(
+    params: ComputeSubmissionIdParams,
+) -> Result<(), ProverError> {
+    if let Some(csv) = &params.csv {
+        fail_if_file_exists(csv)?;
+    }
+    let ubv_inputs: Box<dyn Iterator<Item = Result<_, ProverError>> + '_> =
+        if params.jsonl {
+            params.encoding.iter_app_vk_proof_inputs_jsonl(
+                InputFormat::Native,
+                &params.app_vk_proof_batch,
+            )?
+        } else {
+            let mut ubv_inputs =
+                params.encoding.load_app_vk_proof_inputs_batch(
+                    InputFormat::Native,
+                    &params.app_vk_proof_batch,
+                )?;
+            if let Some(inputs_hex) = &params.inputs_hex {
+                replace_inputs_hex_batch(inputs_hex, &mut ubv_inputs.0)?;
+            }
+            Box::new(ubv_inputs.0.into_iter().map(Ok))
+        };
+    let mut circuit_ids = Vec::new();
+    let mut proof_ids = Vec::new();
+    for input in ubv_inputs {
+        let input = input?;
+        let circuit_id = compute_circuit_id_with_domain_version(
+            &input.vk,
+            params.domain_version,
+        );
         proof_ids.push(utils::compute_proof_id(&circuit_id, &input.inputs.0));
+        circuit_ids.push(circuit_id);
     }
-    proof_ids
-        .extend(iter::repeat([0u8; 32]).take(submission_size - num_proof_ids));
+    let num_proof_ids = proof_ids.len();
+    let output_proof_ids: Vec<String> =
+        proof_ids.iter().map(|pid| bytes_to_hex(pid)).collect();
     let submission_id =
-        utils::compute_submission_id(proof_ids, num_proof_ids as u64);
-    println!("0x{}", hex::encode(submission_id));
+        utils::compute_submission_id(&proof_ids, num_proof_ids as u64);
+    if let Some(csv) = &params.csv {
+        save_csv(
+            csv,
+            &submission_id_csv(&circuit_ids, &proof_ids, &submission_id),
+        )?;
+    }
+    match params.format {
+        OutputFormat::Hex => println!("{}", bytes_to_hex(&submission_id)),
+        OutputFormat::Json => {
+            let output = SubmissionIdOutput {
+                submission_id: bytes_to_hex(&submission_id),
+                num_proof_ids,
+                proof_ids: output_proof_ids,
+            };
+            println!(
+                "{}",
+                serde_json::to_string(&output)
+                    .unwrap_or_else(|e| panic!("failed to serialize: {e}"))
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the CSV written by `--csv`: a header, one `index,circuit_id,
+/// proof_id` row per entry and a final `submission_id,,<submission id>` row.
+fn submission_id_csv(
+    circuit_ids: &[[u8; 32]],
+    proof_ids: &[[u8; 32]],
+    submission_id: &[u8; 32],
+) -> String {
+    assert_eq!(circuit_ids.len(), proof_ids.len(), "inconsistent lengths");
+    let mut csv = String::from("index,circuit_id,proof_id\n");
+    for (i, (circuit_id, proof_id)) in
+        circuit_ids.iter().zip(proof_ids).enumerate()
+    {
+        csv.push_str(&format!(
+            "{i},{},{}\n",
+            bytes_to_hex(circuit_id),
+            bytes_to_hex(proof_id)
+        ));
+    }
+    csv.push_str(&format!("submission_id,,{}\n", bytes_to_hex(submission_id)));
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::submission_id_csv;
+
+    /// Checks that the CSV holds a header, a row per proof and a summary row.
+    ///
+    /// CMD: `cargo test --package prover -- universal_batch_verifier::compute_submission_id::tests::csv_rows --exact --nocapture`
+    #[test]
+    fn csv_rows() {
+        const BATCH_SIZE: usize = 3;
+        let circuit_ids = [[1u8; 32]; BATCH_SIZE];
+        let proof_ids: Vec<[u8; 32]> =
+            (0..BATCH_SIZE as u8).map(|i| [i; 32]).collect();
+        let csv = submission_id_csv(&circuit_ids, &proof_ids, &[0xff; 32]);
+        let rows: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(rows.len(), BATCH_SIZE + 2);
+        assert_eq!(rows[0], "index,circuit_id,proof_id");
+        assert_eq!(
+            rows[3],
+            format!("2,0x{},0x{}", "01".repeat(32), "02".repeat(32))
+        );
+        assert_eq!(
+            rows[BATCH_SIZE + 1],
+            format!("submission_id,,0x{}", "ff".repeat(32))
+        );
+    }
 }
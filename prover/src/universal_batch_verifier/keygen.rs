@@ -1,27 +1,32 @@
 use crate::{
+    api::keygen_circuit,
     default_values::{
-        UBV_GATE_CONFIG, UBV_PK, UBV_PROTOCOL, UBV_SRS, UBV_VK, UPA_CONFIG,
+        UBV_CIRCUIT_NAME, UBV_GATE_CONFIG, UBV_PK, UBV_PROTOCOL, UBV_SRS,
+        UBV_VK, UPA_CONFIG,
     },
+    error::ProverError,
     file_utils::{
-        break_points_file, load_srs, panic_if_file_exists, save_break_points,
-        save_gate_config, save_pk, save_protocol, save_vk,
+        break_points_file, fail_if_file_exists, load_srs, load_upa_config,
+        save_break_points, save_gate_config, save_json_file, save_pk,
+        save_protocol, save_vk, KeygenDirManifest, KeygenOutputs,
+        OUTPUT_DIR_GATE_CONFIG, OUTPUT_DIR_MANIFEST, OUTPUT_DIR_PK,
+        OUTPUT_DIR_PROTOCOL, OUTPUT_DIR_VK,
     },
+    thread_pool::with_thread_pool,
 };
 use circuits::{
-    batch_verify::universal::{
-        types::UniversalBatchVerifierConfig, UniversalBatchVerifyCircuit,
+    batch_verify::{
+        common::MINIMUM_ROWS,
+        universal::{
+            types::UniversalBatchVerifierConfig, UniversalBatchVerifyCircuit,
+        },
     },
+    utils::check_flex_gate_rows,
     SafeCircuit,
 };
 use clap::Parser;
-use halo2_base::halo2_proofs::{
-    halo2curves::bn256::G1Affine,
-    plonk::{keygen_pk, keygen_vk},
-};
-use log::info;
-use snark_verifier::system::halo2::{compile, Config};
+use halo2_base::halo2_proofs::halo2curves::bn256::G1Affine;
 use snark_verifier_sdk::CircuitExt;
-use std::time::Instant;
 
 #[derive(Debug, Parser)]
 pub struct KeygenParams {
@@ -33,84 +38,138 @@ pub struct KeygenParams {
     #[arg(short = 's', long, value_name = "srs-file", default_value = UBV_SRS)]
     pub(crate) srs: String,
 
-    /// Output proving key file
-    #[arg(short = 'p', long, value_name = "proving-key-file", default_value = UBV_PK)]
-    pub(crate) proving_key: String,
-
-    /// Output verification key file
-    #[arg(short = 'v', long, value_name = "verification-key-file", default_value = UBV_VK)]
-    pub(crate) verification_key: String,
-
-    /// Output protocol file
-    #[arg(short = 'r', long, value_name = "protocol-file", default_value = UBV_PROTOCOL)]
-    pub(crate) protocol: String,
-
-    /// Output circuit specs file
-    #[arg(short = 'g', long, value_name = "gate-config-file", default_value = UBV_GATE_CONFIG)]
-    pub(crate) gate_config: String,
+    /// Output directory.  If given, output files default to canonical names
+    /// in a subdirectory named after the circuit and a hash of the UPA
+    /// config, together with a manifest.json.
+    #[arg(long, value_name = "output-dir")]
+    pub(crate) output_dir: Option<String>,
+
+    /// Output proving key file [default: ubv.pk, or pk.bin in --output-dir]
+    #[arg(short = 'p', long, value_name = "proving-key-file")]
+    pub(crate) proving_key: Option<String>,
+
+    /// Output verification key file [default: ubv.vk, or vk.bin in
+    /// --output-dir]
+    #[arg(short = 'v', long, value_name = "verification-key-file")]
+    pub(crate) verification_key: Option<String>,
+
+    /// Output protocol file [default: ubv.protocol, or protocol.bin in
+    /// --output-dir]
+    #[arg(short = 'r', long, value_name = "protocol-file")]
+    pub(crate) protocol: Option<String>,
+
+    /// Output circuit specs file (FlexGateConfigParams), to be passed to
+    /// `prove --gate-config` [default: ubv.specs, or gate_config.json in
+    /// --output-dir]
+    #[arg(
+        short = 'g',
+        long,
+        alias = "gate-config-out",
+        value_name = "gate-config-file"
+    )]
+    pub(crate) gate_config: Option<String>,
+
+    /// Output cell report file.  If given, the advice and lookup cell
+    /// counts and the computed gate config are written here (also on
+    /// --dry-run).
+    #[arg(long, value_name = "cell-report-file")]
+    pub(crate) report_cells: Option<String>,
 
     /// show circuit stats and exit.  do not write files.
     #[arg(short = 'n', long)]
     pub(crate) dry_run: bool,
+
+    /// Number of threads used for key generation (defaults to all available
+    /// cores)
+    #[arg(long, value_name = "num-threads")]
+    pub(crate) threads: Option<usize>,
 }
 
 /// Entry point to the `keygen` subcommand.  Runs the keygen process for the
 /// UniversalBatchVerifyCircuit.
-pub fn keygen(params: KeygenParams) {
-    let ubv_config: UniversalBatchVerifierConfig =
-        UniversalBatchVerifierConfig::from_upa_config_file(&params.config);
+pub fn keygen(params: KeygenParams) -> Result<(), ProverError> {
+    with_thread_pool(params.threads, || run_keygen(params))
+}
+
+fn run_keygen(params: KeygenParams) -> Result<(), ProverError> {
+    let upa_config = load_upa_config(&params.config)?;
+    let ubv_config = UniversalBatchVerifierConfig::from(&upa_config);
+
+    let outputs = KeygenOutputs::new(
+        params.output_dir.as_deref(),
+        UBV_CIRCUIT_NAME,
+        &upa_config,
+    )?;
+    let proving_key = outputs.path(&params.proving_key, OUTPUT_DIR_PK, UBV_PK);
+    let verification_key =
+        outputs.path(&params.verification_key, OUTPUT_DIR_VK, UBV_VK);
+    let protocol_file =
+        outputs.path(&params.protocol, OUTPUT_DIR_PROTOCOL, UBV_PROTOCOL);
+    let gate_config_file = outputs.path(
+        &params.gate_config,
+        OUTPUT_DIR_GATE_CONFIG,
+        UBV_GATE_CONFIG,
+    );
+    let manifest = outputs.in_dir(OUTPUT_DIR_MANIFEST);
 
     // Fail if any of the output paths exist
-    let break_points_file = break_points_file(&params.proving_key);
+    let break_points_file = break_points_file(&proving_key);
     if !params.dry_run {
-        panic_if_file_exists(&params.verification_key);
-        panic_if_file_exists(&break_points_file);
-        panic_if_file_exists(&params.protocol);
-        panic_if_file_exists(&params.proving_key);
-        panic_if_file_exists(&params.gate_config);
+        fail_if_file_exists(&verification_key)?;
+        fail_if_file_exists(&break_points_file)?;
+        fail_if_file_exists(&protocol_file)?;
+        fail_if_file_exists(&proving_key)?;
+        fail_if_file_exists(&gate_config_file)?;
+        if let Some(manifest) = &manifest {
+            fail_if_file_exists(manifest)?;
+        }
+    }
+    if let Some(report_cells) = &params.report_cells {
+        fail_if_file_exists(report_cells)?;
     }
 
     let circuit =
         UniversalBatchVerifyCircuit::<_, G1Affine>::keygen(&ubv_config, &());
     let gate_config = circuit.gate_config();
 
+    // Fail early, rather than panicking during keygen, if the lookup table
+    // or instance do not fit in the degree.
+    check_flex_gate_rows(
+        gate_config,
+        ubv_config.lookup_bits,
+        circuit.num_instance()[0],
+        MINIMUM_ROWS,
+    )
+    .map_err(|e| ProverError::InvalidInput(e.to_string()))?;
+
+    if let Some(report_cells) = &params.report_cells {
+        save_json_file(report_cells, &circuit.cell_report())?;
+    }
+
     // Write circuit specs (FlexGateConfigParams)
-    save_gate_config(&params.gate_config, &gate_config);
+    save_gate_config(&gate_config_file, &gate_config)?;
 
     // Early-out if --dry-run was specified
     if params.dry_run {
-        return;
+        return Ok(());
     }
 
-    let srs = load_srs(&params.srs);
-    info!("Generating UBV VK ...");
-    let now = Instant::now();
-    let vk = keygen_vk(&srs, &circuit)
-        .unwrap_or_else(|e| panic!("VK generation failed: {e}"));
-    info!("Finished generating UBV VK in {:?}", now.elapsed());
-
-    save_vk(&params.verification_key, &vk);
-
-    let break_points = circuit.break_points();
-    save_break_points(&break_points_file, &break_points);
-
-    info!("compiling VK to Protocol ...");
-    let protocol = compile(
-        &srs,
-        &vk,
-        Config::kzg()
-            .with_num_instance(circuit.num_instance())
-            .with_accumulator_indices(
-                UniversalBatchVerifyCircuit::<_, G1Affine>::accumulator_indices(
-                ),
-            ),
-    );
-    save_protocol(&params.protocol, &protocol);
-
-    info!("generating UBV PK ...");
-    let now = Instant::now();
-    let pk = keygen_pk(&srs, vk, &circuit)
-        .unwrap_or_else(|e| panic!("PK generation failed: {e}"));
-    info!("Finished generating UBV PK in {:?}", now.elapsed());
-    save_pk(&params.proving_key, &pk);
+    let srs = load_srs(&params.srs)?;
+    let keys = keygen_circuit(&srs, &circuit, "UBV");
+    save_vk::<UniversalBatchVerifyCircuit<_, G1Affine>>(
+        &verification_key,
+        keys.pk.get_vk(),
+    )?;
+    save_break_points(&break_points_file, &keys.break_points)?;
+    save_protocol(&protocol_file, &keys.protocol)?;
+    save_pk::<UniversalBatchVerifyCircuit<_, G1Affine>>(
+        &proving_key,
+        &keys.pk,
+    )?;
+
+    if let Some(manifest) = &manifest {
+        KeygenDirManifest::new(UBV_CIRCUIT_NAME, &upa_config).save(manifest)?;
+    }
+
+    Ok(())
 }
@@ -1,26 +1,428 @@
-use clap::{Parser, Subcommand};
+use crate::{
+    error::ProverError,
+    file_utils::{load_json_file, open_file_for_read},
+};
+use circuits::{
+    batch_verify::{
+        common::{
+            native::json::{iter_jsonl, JsonVerificationKey},
+            types::{Proof, PublicInputs, VerificationKey, VkError},
+        },
+        universal::{
+            native::json::{
+                Base64UniversalBatchVerifierInput,
+                JsonSnarkjsUniversalBatchVerifierInput,
+                JsonSnarkjsUniversalBatchVerifierInputs,
+                JsonUniversalBatchVerifierInput,
+                JsonUniversalBatchVerifierInputs,
+            },
+            types::{
+                UniversalBatchVerifierInput, UniversalBatchVerifierInputs,
+            },
+        },
+    },
+    utils::base64::Base64DecodeError,
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+use log::info;
+use serde::de::DeserializeOwned;
 
+pub(crate) use self::prove::check_num_public_inputs;
 pub use self::{
     compute_circuit_id::{compute_circuit_id, ComputeCircuitIDParams},
     compute_proof_id::{compute_proof_id, ComputeProofIDParams},
     compute_submission_id::{compute_submission_id, ComputeSubmissionIdParams},
+    describe_instance::{describe_instance, DescribeInstanceParams},
     keygen::{keygen, KeygenParams},
     prove::{prove, ProveParams},
+    sample_inputs::{sample_inputs, SampleInputsParams},
     verify::{verify, VerifyParams},
+    vk_hash::{vk_hash, VkHashParams},
 };
 
 mod compute_circuit_id;
 mod compute_proof_id;
 mod compute_submission_id;
+mod describe_instance;
 mod keygen;
 mod prove;
+mod sample_inputs;
 mod verify;
+mod vk_hash;
+
+/// Output format of the `compute-*` subcommands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// A single `0x`-prefixed hex string
+    Hex,
+    /// A JSON object containing the id and related metadata
+    Json,
+}
+
+/// Format of the app vk, proof and inputs files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// Proofs in this crate's JSON format
+    Native,
+    /// Proofs in the snarkjs `proof.json` format, with the inputs holding the
+    /// contents of the snarkjs `public.json` file
+    Snarkjs,
+}
+
+impl InputFormat {
+    /// Loads a single app vk, proof and inputs file in this format.
+    pub(crate) fn load_app_vk_proof_inputs(
+        self,
+        path: &str,
+    ) -> Result<JsonUniversalBatchVerifierInput, ProverError> {
+        info!("loading app vk, proof, inputs ({self:?}): {path}");
+        match self {
+            InputFormat::Native => load_json_file(path),
+            InputFormat::Snarkjs => {
+                let json: JsonSnarkjsUniversalBatchVerifierInput =
+                    load_json_file(path)?;
+                Ok((&json).into())
+            }
+        }
+    }
+
+    /// Loads a batch of app vk, proof and inputs in this format.
+    pub(crate) fn load_app_vk_proof_inputs_batch(
+        self,
+        path: &str,
+    ) -> Result<JsonUniversalBatchVerifierInputs, ProverError> {
+        info!("loading app vk, proof, inputs batch ({self:?}): {path}");
+        match self {
+            InputFormat::Native => load_json_file(path),
+            InputFormat::Snarkjs => {
+                let json: JsonSnarkjsUniversalBatchVerifierInputs =
+                    load_json_file(path)?;
+                Ok((&json).into())
+            }
+        }
+    }
+}
+
+/// Encoding of the vks, proofs and inputs read by the `compute-*`
+/// subcommands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    /// JSON objects with the coordinates as strings (see `--input-format`)
+    Json,
+    /// Unpadded base64 blobs of 32-byte big-endian words.  Input files hold
+    /// JSON objects with `vk`, `proof` and `inputs` blob fields, and vk files
+    /// hold a single blob.
+    Base64,
+}
 
-/// Setting for the Poseidon transcript used by inner proofs.  0 for most
-/// specs.  See documentation for `snark_verifier::util::hash::Poseidon::new`.
-// TODO: move this to the `circuit` lib.  Should be defined once for all inner
-// circuits, with matching value used in the outer circuit.
-pub const SECURE_MDS: usize = 0;
+impl Encoding {
+    /// Loads an application vk in this encoding.
+    pub(crate) fn load_vk(
+        self,
+        path: &str,
+    ) -> Result<VerificationKey, ProverError> {
+        let vk = match self {
+            Encoding::Json => {
+                let vk_json: JsonVerificationKey = load_json_file(path)?;
+                VerificationKey::from(&vk_json)
+            }
+            Encoding::Base64 => {
+                info!("loading base64 vk: {path}");
+                let blob = std::fs::read_to_string(path)
+                    .map_err(|e| ProverError::io(path, e))?;
+                VerificationKey::from_base64(&blob)
+                    .map_err(|e| ProverError::decode(path, "base64 vk", e))?
+            }
+        };
+        vk.validate()
+            .map_err(|e| ProverError::decode(path, "vk", e))?;
+        Ok(vk)
+    }
+
+    /// Checks that `input_format` is supported by this encoding: base64
+    /// inputs must use the native input format.
+    pub(crate) fn check_input_format(
+        self,
+        input_format: InputFormat,
+    ) -> Result<(), ProverError> {
+        match (self, input_format) {
+            (Encoding::Base64, InputFormat::Snarkjs) => {
+                Err(ProverError::InvalidInput(format!(
+                    "--encoding base64 requires the native input format, \
+                     got {input_format:?}"
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Loads a single app vk, proof and inputs file in this encoding.  Fails
+    /// if `input_format` is not supported (see [`Self::check_input_format`]).
+    pub(crate) fn load_app_vk_proof_inputs(
+        self,
+        input_format: InputFormat,
+        path: &str,
+    ) -> Result<UniversalBatchVerifierInput<Fr>, ProverError> {
+        self.check_input_format(input_format)?;
+        match self {
+            Encoding::Json => ubv_input_from_json(
+                path,
+                "",
+                &input_format.load_app_vk_proof_inputs(path)?,
+            ),
+            Encoding::Base64 => {
+                info!("loading base64 app vk, proof, inputs: {path}");
+                let blobs: Base64UniversalBatchVerifierInput =
+                    load_json_file(path)?;
+                ubv_input_from_base64(path, "", &blobs)
+            }
+        }
+    }
+
+    /// Loads a batch of app vk, proof and inputs in this encoding.  Fails if
+    /// `input_format` is not supported (see [`Self::check_input_format`]).
+    pub(crate) fn load_app_vk_proof_inputs_batch(
+        self,
+        input_format: InputFormat,
+        path: &str,
+    ) -> Result<UniversalBatchVerifierInputs<Fr>, ProverError> {
+        self.check_input_format(input_format)?;
+        match self {
+            Encoding::Json => Ok(UniversalBatchVerifierInputs(
+                input_format
+                    .load_app_vk_proof_inputs_batch(path)?
+                    .0
+                    .iter()
+                    .enumerate()
+                    .map(|(i, json)| {
+                        ubv_input_from_json(path, &format!("input {i}: "), json)
+                    })
+                    .collect::<Result<_, _>>()?,
+            )),
+            Encoding::Base64 => {
+                info!("loading base64 app vk, proof, inputs batch: {path}");
+                let blobs: Vec<Base64UniversalBatchVerifierInput> =
+                    load_json_file(path)?;
+                Ok(UniversalBatchVerifierInputs(
+                    blobs
+                        .iter()
+                        .enumerate()
+                        .map(|(i, blobs)| {
+                            ubv_input_from_base64(
+                                path,
+                                &format!("input {i}: "),
+                                blobs,
+                            )
+                        })
+                        .collect::<Result<_, _>>()?,
+                ))
+            }
+        }
+    }
+
+    /// Streams a JSONL batch of app vk, proof and inputs (one entry per line)
+    /// in this encoding, reading each entry only as the iterator is consumed.
+    /// Fails unless `input_format` is native (see
+    /// [`check_jsonl_input_format`]).
+    pub(crate) fn iter_app_vk_proof_inputs_jsonl(
+        self,
+        input_format: InputFormat,
+        path: &str,
+    ) -> Result<
+        Box<
+            dyn Iterator<
+                    Item = Result<UniversalBatchVerifierInput<Fr>, ProverError>,
+                > + '_,
+        >,
+        ProverError,
+    > {
+        check_jsonl_input_format(input_format)?;
+        info!(
+            "streaming app vk, proof, inputs batch ({self:?}, jsonl): {path}"
+        );
+        Ok(match self {
+            Encoding::Json => Box::new(
+                iter_jsonl_file::<JsonUniversalBatchVerifierInput>(path)?
+                    .enumerate()
+                    .map(move |(i, json)| {
+                        ubv_input_from_json(
+                            path,
+                            &format!("input {i}: "),
+                            &json?,
+                        )
+                    }),
+            ),
+            Encoding::Base64 => Box::new(
+                iter_jsonl_file::<Base64UniversalBatchVerifierInput>(path)?
+                    .enumerate()
+                    .map(move |(i, blobs)| {
+                        ubv_input_from_base64(
+                            path,
+                            &format!("input {i}: "),
+                            &blobs?,
+                        )
+                    }),
+            ),
+        })
+    }
+}
+
+/// Checks that a `--jsonl` batch uses the native `input_format`, which is
+/// the only format with a JSONL variant.
+pub(crate) fn check_jsonl_input_format(
+    input_format: InputFormat,
+) -> Result<(), ProverError> {
+    match input_format {
+        InputFormat::Native => Ok(()),
+        _ => Err(ProverError::InvalidInput(format!(
+            "--jsonl requires the native input format, got {input_format:?}"
+        ))),
+    }
+}
+
+/// Returns an iterator over the values of the JSONL file at `path` (one JSON
+/// value per line), deserialized one at a time.
+pub(crate) fn iter_jsonl_file<T: DeserializeOwned>(
+    path: &str,
+) -> Result<impl Iterator<Item = Result<T, ProverError>> + '_, ProverError> {
+    Ok(iter_jsonl(open_file_for_read(path)?)
+        .map(move |value| value.map_err(|e| ProverError::json(path, e))))
+}
+
+/// Replaces the public inputs of `input` with those of `path`: a JSON array
+/// of public inputs as `0x`-prefixed big-endian hex strings (the EVM
+/// `uint256[]` encoding), as given by `--inputs-hex`.  Fails if their number
+/// does not match the vk of `input`.
+pub(crate) fn replace_inputs_hex(
+    path: &str,
+    input: &mut UniversalBatchVerifierInput<Fr>,
+) -> Result<(), ProverError> {
+    let hex_strings: Vec<String> = load_json_file(path)?;
+    input.inputs = parse_inputs_hex(path, "", &hex_strings, &input.vk)?;
+    Ok(())
+}
+
+/// Replaces the public inputs of each entry of `inputs` with those of
+/// `path`: a JSON array holding, for each entry, an array of hex public
+/// inputs (see [`replace_inputs_hex`]).
+pub(crate) fn replace_inputs_hex_batch(
+    path: &str,
+    inputs: &mut [UniversalBatchVerifierInput<Fr>],
+) -> Result<(), ProverError> {
+    let hex_strings: Vec<Vec<String>> = load_json_file(path)?;
+    if hex_strings.len() != inputs.len() {
+        return Err(ProverError::InvalidInput(format!(
+            "{path}: expected inputs for {} entries, got {}",
+            inputs.len(),
+            hex_strings.len()
+        )));
+    }
+    for (i, (input, hex_strings)) in
+        inputs.iter_mut().zip(&hex_strings).enumerate()
+    {
+        let entry = format!("entry {i}: ");
+        input.inputs = parse_inputs_hex(path, &entry, hex_strings, &input.vk)?;
+    }
+    Ok(())
+}
+
+/// Parses hex public inputs, checking that there is one per public input of
+/// `vk` (excluding the commitment hash, if any).  Errors are prefixed with
+/// `entry`.
+fn parse_inputs_hex(
+    path: &str,
+    entry: &str,
+    hex_strings: &[String],
+    vk: &VerificationKey,
+) -> Result<PublicInputs, ProverError> {
+    let num_vk_inputs = vk.s.len().saturating_sub(1 + vk.h1.len());
+    if hex_strings.len() != num_vk_inputs {
+        return Err(ProverError::InvalidInput(format!(
+            "{path}: {entry}{} hex inputs, but the vk has {num_vk_inputs} \
+             public inputs",
+            hex_strings.len()
+        )));
+    }
+    PublicInputs::from_hex_vec(hex_strings).map_err(|e| {
+        ProverError::decode(path, "hex inputs", format!("{entry}{e}"))
+    })
+}
+
+/// Checks that `vk`, read from `path`, is valid (see
+/// [`VerificationKey::validate`]), as required to compute its circuit id.
+/// Errors are prefixed with `entry`.
+pub(crate) fn check_vk(
+    path: &str,
+    entry: &str,
+    vk: &VerificationKey,
+) -> Result<(), ProverError> {
+    vk.validate()
+        .map_err(|e| ProverError::InvalidInput(format!("{path}: {entry}{e}")))
+}
+
+/// Returns the [`UniversalBatchVerifierInput`] made of `vk`, `proof` and
+/// `inputs`, read from `path`, failing if the vk is invalid (see
+/// [`check_vk`]) or the input is not well formed.  Errors are prefixed with
+/// `entry`.
+fn checked_ubv_input(
+    path: &str,
+    entry: &str,
+    vk: VerificationKey,
+    proof: Proof,
+    inputs: PublicInputs,
+) -> Result<UniversalBatchVerifierInput<Fr>, ProverError> {
+    check_vk(path, entry, &vk)?;
+    let input = UniversalBatchVerifierInput { vk, proof, inputs };
+    input.check_well_formed().map_err(|e| {
+        ProverError::InvalidInput(format!("{path}: {entry}{e}"))
+    })?;
+    Ok(input)
+}
+
+/// Converts the JSON entry `json` of `path` (see [`checked_ubv_input`]).
+fn ubv_input_from_json(
+    path: &str,
+    entry: &str,
+    json: &JsonUniversalBatchVerifierInput,
+) -> Result<UniversalBatchVerifierInput<Fr>, ProverError> {
+    // Checked before conversion, which panics on mismatched lengths.
+    let (h1_len, h2_len) = (json.vk.h1.len(), json.vk.h2.len());
+    if h1_len != h2_len {
+        let e = VkError::InvalidCommitmentKey { h1_len, h2_len };
+        return Err(ProverError::InvalidInput(format!("{path}: {entry}{e}")));
+    }
+    checked_ubv_input(
+        path,
+        entry,
+        VerificationKey::from(&json.vk),
+        Proof::from(&json.proof),
+        PublicInputs::from(&json.inputs),
+    )
+}
+
+/// Decodes the base64 entry `blobs` of `path` (see [`checked_ubv_input`]).
+fn ubv_input_from_base64(
+    path: &str,
+    entry: &str,
+    blobs: &Base64UniversalBatchVerifierInput,
+) -> Result<UniversalBatchVerifierInput<Fr>, ProverError> {
+    let decode = |e: Base64DecodeError| {
+        ProverError::decode(path, "base64 input", format!("{entry}{e}"))
+    };
+    checked_ubv_input(
+        path,
+        entry,
+        VerificationKey::from_base64(&blobs.vk).map_err(decode)?,
+        Proof::from_base64(&blobs.proof).map_err(decode)?,
+        PublicInputs::from_base64(&blobs.inputs).map_err(decode)?,
+    )
+}
+
+/// Returns the `0x`-prefixed hex representation of `bytes`.
+pub(crate) fn bytes_to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
 
 #[derive(Debug, Parser)]
 pub struct UniversalBatchVerifierParams {
@@ -53,9 +455,24 @@ enum UniversalBatchVerifierCommand {
     /// Verify a batch proof
     #[command()]
     Verify(VerifyParams),
+
+    /// Generate a batch of valid sample inputs (for load-testing `prove`)
+    #[command()]
+    SampleInputs(SampleInputsParams),
+
+    /// Print the layout of the instance (the named segments of the public
+    /// inputs)
+    #[command()]
+    DescribeInstance(DescribeInstanceParams),
+
+    /// Print the hash of a VK, or of the VK embedded in a PK
+    #[command()]
+    VkHash(VkHashParams),
 }
 
-pub fn universal_batch_verifier(params: UniversalBatchVerifierParams) {
+pub fn universal_batch_verifier(
+    params: UniversalBatchVerifierParams,
+) -> Result<(), ProverError> {
     match params.command {
         UniversalBatchVerifierCommand::ComputeCircuitID(params) => {
             compute_circuit_id(params)
@@ -69,5 +486,12 @@ pub fn universal_batch_verifier(params: UniversalBatchVerifierParams) {
         UniversalBatchVerifierCommand::Keygen(params) => keygen(params),
         UniversalBatchVerifierCommand::Prove(params) => prove(params),
         UniversalBatchVerifierCommand::Verify(params) => verify(params),
+        UniversalBatchVerifierCommand::SampleInputs(params) => {
+            sample_inputs(params)
+        }
+        UniversalBatchVerifierCommand::DescribeInstance(params) => {
+            describe_instance(params)
+        }
+        UniversalBatchVerifierCommand::VkHash(params) => vk_hash(params),
     }
 }
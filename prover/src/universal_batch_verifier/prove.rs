@@ -1,34 +1,40 @@
 use crate::{
+    api::{ubv_check_inputs, ubv_prove_unchecked, ubv_verify},
     default_values::{UBV_GATE_CONFIG, UBV_PK, UBV_SRS, UPA_CONFIG},
+    error::ProverError,
     file_utils::{
-        break_points_file, instance_file, load_break_points, load_gate_config,
-        load_srs, open_file_for_read, panic_if_file_exists, save_instance,
-        save_proof,
+        break_points_file, fail_if_file_exists, instance_file,
+        load_break_points, load_gate_config, load_srs, load_upa_config,
+        open_file_for_read, save_instance, save_proof, write_json_pretty,
+    },
+    thread_pool::with_thread_pool,
+    universal_batch_verifier::{
+        check_jsonl_input_format, iter_jsonl_file, InputFormat,
     },
-    universal_batch_verifier::SECURE_MDS,
 };
 use circuits::{
     batch_verify::universal::{
-        native::json::load_app_vk_proof_and_inputs_batch,
-        types::UniversalBatchVerifierConfig, UniversalBatchVerifyCircuit,
+        native::{
+            json::{
+                ubv_input_from_json_unchecked, ubv_inputs_from_json_unchecked,
+                JsonUniversalBatchVerifierInput,
+            },
+            verify_batch,
+        },
+        types::{
+            BatchEntries, UniversalBatchVerifierConfig,
+            UniversalBatchVerifierInputs,
+        },
+        ubv_instance_len, UniversalBatchVerifyCircuit,
     },
     SafeCircuit,
 };
 use clap::Parser;
 use halo2_base::{
     gates::builder::FlexGateConfigParams,
-    halo2_proofs::{
-        halo2curves::bn256::{Bn256, Fr, G1Affine},
-        plonk::create_proof,
-        poly::kzg::{
-            commitment::KZGCommitmentScheme, multiopen::ProverSHPLONK,
-        },
-    },
+    halo2_proofs::halo2curves::bn256::{Fr, G1Affine},
 };
-use log::info;
-use rand_chacha::rand_core::OsRng;
-use snark_verifier::system::halo2::transcript::halo2::ChallengeScalar;
-use snark_verifier_sdk::{halo2::PoseidonTranscript, CircuitExt, NativeLoader};
+use log::{info, warn};
 use std::time::Instant;
 
 #[derive(Parser, Debug)]
@@ -44,7 +50,8 @@ pub struct ProveParams {
     pub(crate) proving_key: String,
 
     #[arg(short = 'g', long, value_name = "gate-config-file", default_value = UBV_GATE_CONFIG)]
-    /// Gate configuration file
+    /// Gate configuration file (the FlexGateConfigParams written by
+    /// `keygen --gate-config`)
     pub(crate) gate_config: String,
 
     #[arg(short = 'b', long, value_name = "app-vk-proof-batch-file")]
@@ -52,6 +59,17 @@ pub struct ProveParams {
     /// to be verified by the BatchVerifier circuit.
     pub(crate) app_vk_proof_batch: String,
 
+    #[arg(long, value_enum, default_value = "native")]
+    /// Format of the app_vk, proof, public input batch file.
+    pub(crate) input_format: InputFormat,
+
+    #[arg(long)]
+    /// The batch file is JSONL (one app_vk, proof, public input object per
+    /// line), parsed one entry at a time.  Requires the native input format.
+    /// Note that, unlike the compute commands, the whole batch is still
+    /// collected in memory, since the circuit is built from all entries.
+    pub(crate) jsonl: bool,
+
     #[arg(long, value_name = "proof-file")]
     /// Output proof file
     pub(crate) proof: String,
@@ -61,122 +79,495 @@ pub struct ProveParams {
     pub(crate) instance: Option<String>,
 
     #[arg(short = 'n', long)]
-    /// Load the circuit configs and exit.
+    /// Check the inputs against the config, verify the app proofs natively,
+    /// write the instance and exit. Does not load the proving key.
     pub(crate) dry_run: bool,
+
+    #[arg(long)]
+    /// Verify the proof against the proving key's VK before writing it,
+    /// failing if verification fails.
+    pub(crate) verify_after_prove: bool,
+
+    #[arg(long, value_name = "num-threads")]
+    /// Number of threads used for proving (defaults to all available cores)
+    pub(crate) threads: Option<usize>,
+
+    #[arg(long)]
+    /// Fail if two consecutive entries of the batch have the same vk, proof
+    /// and inputs (by default, this only logs a warning).
+    pub(crate) reject_duplicate_entries: bool,
+
+    #[arg(long, value_name = "ranges")]
+    /// Only prove these entries of the batch file (e.g. `0,2,5-7`), padding
+    /// the batch with valid dummy entries up to the inner batch size.  The
+    /// batch file index of the entry at each instance position (`null` for
+    /// padding) is written to <instance-file>.entries.
+    pub(crate) entries: Option<String>,
 }
 
 /// Entry point to the `prove` subcommand. Runs the prove process for the
 /// UniversalBatchVerifyCircuit.
-pub fn prove(params: ProveParams) {
+pub fn prove(params: ProveParams) -> Result<(), ProverError> {
+    with_thread_pool(params.threads, || run_prove(params))
+}
+
+fn run_prove(params: ProveParams) -> Result<(), ProverError> {
     let instance_file = instance_file(params.instance, &params.proof);
 
+    let entries = params
+        .entries
+        .as_deref()
+        .map(parse_entry_ranges)
+        .transpose()?;
+
     if !params.dry_run {
-        panic_if_file_exists(&params.proof);
-        panic_if_file_exists(&instance_file);
+        fail_if_file_exists(&params.proof)?;
+        fail_if_file_exists(&instance_file)?;
+        if entries.is_some() {
+            fail_if_file_exists(&entries_file(&instance_file))?;
+        }
     }
 
-    let bv_config: UniversalBatchVerifierConfig =
-        UniversalBatchVerifierConfig::from_upa_config_file(&params.config);
+    let bv_config =
+        UniversalBatchVerifierConfig::from(&load_upa_config(&params.config)?);
 
     if params.dry_run {
-        prove_dry_run(&bv_config, &params.app_vk_proof_batch, &instance_file);
-        return;
+        return prove_dry_run(
+            &bv_config,
+            &params.app_vk_proof_batch,
+            params.input_format,
+            params.jsonl,
+            entries.as_deref(),
+            &instance_file,
+            params.reject_duplicate_entries,
+        );
     }
 
+    // Load and check the inputs first, to fail before the (slow) loading of
+    // the proving key.
+    let (ubv_inputs, positions) = load_ubv_inputs(
+        &bv_config,
+        &params.app_vk_proof_batch,
+        params.input_format,
+        params.jsonl,
+        entries.as_deref(),
+    )?;
+    ubv_check_inputs(&bv_config, &ubv_inputs)?;
+    check_adjacent_duplicates(
+        &BatchEntries::from_ubv_inputs_and_config(&ubv_inputs, &bv_config),
+        positions.as_deref(),
+        params.reject_duplicate_entries,
+    )?;
+
     let gate_config: FlexGateConfigParams =
-        load_gate_config(&params.gate_config);
+        load_gate_config(&params.gate_config)?;
 
     info!("reading BV PK ...");
     let now = Instant::now();
     let pk = {
-        let mut buf = open_file_for_read(&params.proving_key);
+        let mut buf = open_file_for_read(&params.proving_key)?;
         UniversalBatchVerifyCircuit::<_, G1Affine>::read_proving_key(
             &bv_config,
             &gate_config,
             &mut buf,
         )
-        .unwrap_or_else(|e| panic!("error reading pk: {e}"))
+        .map_err(|e| {
+            ProverError::decode(&params.proving_key, "proving key", e)
+        })?
     };
     info!("Finished reading BV PK in {:?}", now.elapsed());
 
     let break_points = {
         let break_points_file = break_points_file(&params.proving_key);
-        load_break_points(&break_points_file)
+        load_break_points(&break_points_file)?
     };
 
-    // TODO: load this stuff first, and verify the batch?
+    let srs = load_srs(&params.srs)?;
+    // The inputs were checked above.
+    let (batch_proof, batch_proof_instance) = ubv_prove_unchecked(
+        &srs,
+        &pk,
+        &bv_config,
+        &gate_config,
+        break_points,
+        &ubv_inputs,
+    );
 
-    info!("Loading app VK, proofs and inputs ...");
-    let ubv_inputs =
-        load_app_vk_proof_and_inputs_batch(&params.app_vk_proof_batch);
+    if params.verify_after_prove {
+        ubv_verify(&srs, pk.get_vk(), &batch_proof, &batch_proof_instance)?;
+    }
 
-    // TODO: native verification
+    save_proof(&params.proof, &batch_proof)?;
+    save_instance(&instance_file, &batch_proof_instance)?;
+    save_positions(&instance_file, positions.as_deref())
+}
 
-    let srs = load_srs(&params.srs);
-    info!("Computing BV proof...");
-    let now = Instant::now();
-    let (batch_proof, batch_proof_instance): (Vec<u8>, Vec<Fr>) = {
-        let circuit = UniversalBatchVerifyCircuit::<_, G1Affine>::prover(
-            &bv_config,
-            &gate_config,
-            break_points,
-            &ubv_inputs,
-        );
+/// In dry-run mode, we check the inputs against `bv_config`, verify the app
+/// proofs natively (see [`dry_run_instance`]), and write out the instance
+/// (and the positions of the selected entries, if any).
+fn prove_dry_run(
+    bv_config: &UniversalBatchVerifierConfig,
+    app_vk_proof_inputs_file: &str,
+    input_format: InputFormat,
+    jsonl: bool,
+    entries: Option<&[usize]>,
+    instance_file: &str,
+    reject_duplicate_entries: bool,
+) -> Result<(), ProverError> {
+    info!("dry-run.  checking inputs and generating instance only.");
 
-        // TODO: better interface for instance.  Avoid copy when returning.
-
-        let instances = circuit.instances();
-
-        // TODO: sanity check these instance values against the app_vk and the
-        // public inputs.
-
-        // TODO: Better Rng than OsRng?
-
-        let mut transcript =
-            PoseidonTranscript::<NativeLoader, _>::new::<SECURE_MDS>(vec![]);
-        create_proof::<
-            KZGCommitmentScheme<Bn256>,
-            ProverSHPLONK<'_, Bn256>,
-            ChallengeScalar<G1Affine>,
-            _,
-            _,
-            _,
-        >(
-            &srs,
-            &pk,
-            &[circuit],
-            &[&[&instances[0]]],
-            OsRng,
-            &mut transcript,
-        )
-        .unwrap();
-        let proof = transcript.finalize();
+    let (ubv_inputs, positions) = load_ubv_inputs(
+        bv_config,
+        app_vk_proof_inputs_file,
+        input_format,
+        jsonl,
+        entries,
+    )?;
+    let bv_instance = dry_run_instance(
+        bv_config,
+        &ubv_inputs,
+        positions.as_deref(),
+        reject_duplicate_entries,
+    )?;
+    save_instance(instance_file, &bv_instance)?;
+    save_positions(instance_file, positions.as_deref())?;
+    info!("Inputs in {app_vk_proof_inputs_file} are valid");
+    Ok(())
+}
 
-        (proof, instances[0].clone())
-    };
-    info!("Finished computing BV proof in {:?}", now.elapsed());
+/// Checks `ubv_inputs` against `bv_config` and verifies the app proofs
+/// natively, returning the instance of the `UniversalBatchVerifyCircuit`.
+/// Any inconsistency is returned as an error.  See
+/// [`check_adjacent_duplicates`] for `positions`.
+fn dry_run_instance(
+    bv_config: &UniversalBatchVerifierConfig,
+    ubv_inputs: &UniversalBatchVerifierInputs<Fr>,
+    positions: Option<&[Option<usize>]>,
+    reject_duplicate_entries: bool,
+) -> Result<Vec<Fr>, ProverError> {
+    ubv_check_inputs(bv_config, ubv_inputs)?;
+    check_adjacent_duplicates(
+        &BatchEntries::from_ubv_inputs_and_config(ubv_inputs, bv_config),
+        positions,
+        reject_duplicate_entries,
+    )?;
 
-    // TODO: Verify the proof?
+    info!("Verifying app proofs natively ...");
+    verify_batch(ubv_inputs)
+        .map_err(|e| ProverError::InvalidInput(e.to_string()))?;
 
-    save_proof(&params.proof, &batch_proof);
-    save_instance(&instance_file, &batch_proof_instance);
+    let bv_instance = UniversalBatchVerifyCircuit::<Fr>::compute_instance(
+        bv_config, ubv_inputs,
+    );
+    let expected_instance_len = ubv_instance_len(bv_config);
+    if bv_instance.len() != expected_instance_len {
+        return Err(ProverError::InvalidInput(format!(
+            "instance length {} differs from expected {expected_instance_len}",
+            bv_instance.len()
+        )));
+    }
+    Ok(bv_instance)
 }
 
-/// In dry-run mode, we just compute and write out the instances.
-fn prove_dry_run(
+/// Loads the batch in `app_vk_proof_inputs_file`.  If `entries` is given,
+/// selects these entries and pads the batch to the inner batch size (see
+/// [`UniversalBatchVerifierInputs::select_with_padding`]), also returning
+/// the batch file index of each entry (`None` for padding).  A `jsonl`
+/// batch is parsed one entry at a time, but all entries are collected.
+#[allow(clippy::type_complexity)]
+fn load_ubv_inputs(
     bv_config: &UniversalBatchVerifierConfig,
     app_vk_proof_inputs_file: &str,
+    input_format: InputFormat,
+    jsonl: bool,
+    entries: Option<&[usize]>,
+) -> Result<
+    (UniversalBatchVerifierInputs<Fr>, Option<Vec<Option<usize>>>),
+    ProverError,
+> {
+    info!("Loading app VK, proofs and inputs ...");
+    let ubv_inputs = if jsonl {
+        check_jsonl_input_format(input_format)?;
+        UniversalBatchVerifierInputs(
+            iter_jsonl_file::<JsonUniversalBatchVerifierInput>(
+                app_vk_proof_inputs_file,
+            )?
+            .map(|json| Ok(ubv_input_from_json_unchecked(&json?)))
+            .collect::<Result<_, ProverError>>()?,
+        )
+    } else {
+        ubv_inputs_from_json_unchecked(
+            &input_format
+                .load_app_vk_proof_inputs_batch(app_vk_proof_inputs_file)?,
+        )
+    };
+    let entries = match entries {
+        Some(entries) => entries,
+        None => return Ok((ubv_inputs, None)),
+    };
+    let (ubv_inputs, positions) = ubv_inputs
+        .select_with_padding(entries, bv_config)
+        .map_err(ProverError::InvalidInput)?;
+    info!(
+        "Selected {} entries, with {} padding entries",
+        entries.len(),
+        positions.len() - entries.len()
+    );
+    Ok((ubv_inputs, Some(positions)))
+}
+
+/// Writes `positions` (if any), the batch file index of each entry of the
+/// instance in `instance_file`, to its [`entries_file`].
+fn save_positions(
     instance_file: &str,
-) {
-    info!("dry-run.  generating instance only.");
+    positions: Option<&[Option<usize>]>,
+) -> Result<(), ProverError> {
+    match positions {
+        Some(positions) => {
+            write_json_pretty(&entries_file(instance_file), &positions)
+        }
+        None => Ok(()),
+    }
+}
 
-    info!("Loading app VK, proofs and inputs ...");
-    let ubv_inputs =
-        load_app_vk_proof_and_inputs_batch(app_vk_proof_inputs_file);
+/// Returns the file recording the batch file index of each entry of the
+/// instance in `instance_file`, when proving a selection of entries.
+fn entries_file(instance_file: &str) -> String {
+    format!("{instance_file}.entries")
+}
 
-    let bv_instance = UniversalBatchVerifyCircuit::<Fr>::compute_instance(
-        bv_config,
-        &ubv_inputs,
-    );
-    save_instance(instance_file, &bv_instance);
+/// Parses a comma-separated list of entry indices and inclusive ranges of
+/// indices, e.g. `0,2,5-7`.
+fn parse_entry_ranges(ranges: &str) -> Result<Vec<usize>, ProverError> {
+    let invalid = |range: &str| {
+        ProverError::InvalidInput(format!(
+            "invalid entry range \"{range}\" in --entries"
+        ))
+    };
+    let mut entries = Vec::new();
+    for range in ranges.split(',').map(str::trim) {
+        let parse = |idx: &str| idx.trim().parse::<usize>();
+        match range.split_once('-') {
+            Some((start, end)) => {
+                let start = parse(start).map_err(|_| invalid(range))?;
+                let end = parse(end).map_err(|_| invalid(range))?;
+                if start > end {
+                    return Err(invalid(range));
+                }
+                entries.extend(start..=end);
+            }
+            None => entries.push(parse(range).map_err(|_| invalid(range))?),
+        }
+    }
+    Ok(entries)
+}
+
+/// Checks that the public inputs of each entry of `ubv_inputs`, followed by
+/// its commitment hash (if any), fit in `max_num_public_inputs`.  The circuit
+/// would otherwise only detect this by panicking during witness generation.
+pub(crate) fn check_num_public_inputs(
+    ubv_inputs: &UniversalBatchVerifierInputs<Fr>,
+    bv_config: &UniversalBatchVerifierConfig,
+) -> Result<(), ProverError> {
+    let max_num_public_inputs = bv_config.max_num_public_inputs as usize;
+    for (i, input) in ubv_inputs.0.iter().enumerate() {
+        let num_inputs = input.inputs.0.len();
+        let num_commitments = input.vk.h1.len();
+        if num_inputs + num_commitments > max_num_public_inputs {
+            let commitments = match num_commitments {
+                0 => String::new(),
+                1 => " (+commitment)".to_string(),
+                n => format!(" (+{n} commitments)"),
+            };
+            return Err(ProverError::InvalidInput(format!(
+                "entry {i} has {num_inputs} inputs{commitments}, exceeds max \
+                 {max_num_public_inputs}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Warns about consecutive identical entries in `entries` and, if `reject` is
+/// set, fails if there are any.  Padding entries (those whose `positions`
+/// are `None`, when a selection of the batch is proven) are identical by
+/// construction and are ignored.
+fn check_adjacent_duplicates(
+    entries: &BatchEntries<Fr>,
+    positions: Option<&[Option<usize>]>,
+    reject: bool,
+) -> Result<(), ProverError> {
+    let is_padding = |idx: usize| {
+        positions.map_or(false, |positions| positions[idx].is_none())
+    };
+    let duplicates = entries
+        .adjacent_duplicates()
+        .into_iter()
+        .filter(|idx| !is_padding(*idx))
+        .collect::<Vec<_>>();
+    for idx in &duplicates {
+        warn!("batch entry {idx} is identical to entry {}", idx - 1);
+    }
+    if reject && !duplicates.is_empty() {
+        return Err(ProverError::InvalidInput(format!(
+            "batch entries {duplicates:?} are identical to the preceding \
+             entries"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_adjacent_duplicates, check_num_public_inputs, dry_run_instance,
+        load_ubv_inputs, parse_entry_ranges,
+    };
+    use crate::{
+        error::ProverError, test_utils::test_ubv_config,
+        universal_batch_verifier::InputFormat,
+    };
+    use circuits::batch_verify::universal::{
+        types::{BatchEntries, UniversalBatchVerifierInputs},
+        ubv_instance_len,
+    };
+    use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+    /// Checks the parsing of `--entries` ranges.
+    ///
+    /// CMD: `cargo test --package prover -- universal_batch_verifier::prove::tests::entry_ranges --exact --nocapture`
+    #[test]
+    fn entry_ranges() {
+        assert_eq!(parse_entry_ranges("3").unwrap(), vec![3]);
+        assert_eq!(parse_entry_ranges("0,2,5-7").unwrap(), vec![0, 2, 5, 6, 7]);
+        assert_eq!(parse_entry_ranges(" 4 - 4 , 1").unwrap(), vec![4, 1]);
+        for invalid in ["", "1,", "a", "3-1", "1-", "-2", "1-2-3"] {
+            assert!(parse_entry_ranges(invalid).is_err(), "{invalid}");
+        }
+        assert_eq!(
+            parse_entry_ranges("0,3-1").unwrap_err().to_string(),
+            "invalid entry range \"3-1\" in --entries"
+        );
+    }
+
+    /// Checks that entries with too many public inputs are reported, with
+    /// their index and input count, before any circuit is built.
+    ///
+    /// CMD: `cargo test --package prover -- universal_batch_verifier::prove::tests::too_many_public_inputs --exact --nocapture`
+    #[test]
+    fn too_many_public_inputs() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(1);
+        let config = test_ubv_config(2, 10);
+        let mut ubv_inputs =
+            UniversalBatchVerifierInputs::sample(&config, true, rng);
+        assert!(check_num_public_inputs(&ubv_inputs, &config).is_ok());
+
+        let num_inputs = 10 - ubv_inputs.0[1].vk.h1.len();
+        ubv_inputs.0[1]
+            .inputs
+            .0
+            .resize(num_inputs + 2, Default::default());
+        let err = check_num_public_inputs(&ubv_inputs, &config)
+            .unwrap_err()
+            .to_string();
+        let commitment = match ubv_inputs.0[1].vk.h1.is_empty() {
+            true => "",
+            false => " (+commitment)",
+        };
+        assert_eq!(
+            err,
+            format!(
+                "entry 1 has {} inputs{commitment}, exceeds max 10",
+                num_inputs + 2
+            )
+        );
+    }
+
+    /// Checks that the dry run returns the instance for a valid batch, and
+    /// an error (rather than exiting) if an app proof does not verify.
+    ///
+    /// CMD: `cargo test --package prover -- universal_batch_verifier::prove::tests::dry_run_invalid_proof --exact --nocapture`
+    #[test]
+    fn dry_run_invalid_proof() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(2);
+        let config = test_ubv_config(2, 4);
+        let mut ubv_inputs =
+            UniversalBatchVerifierInputs::sample(&config, true, rng);
+        let instance =
+            dry_run_instance(&config, &ubv_inputs, None, false).unwrap();
+        assert_eq!(instance.len(), ubv_instance_len(&config));
+
+        ubv_inputs.0[1].inputs.0[0] += Fr::from(1);
+        assert!(matches!(
+            dry_run_instance(&config, &ubv_inputs, None, false),
+            Err(ProverError::InvalidInput(_))
+        ));
+    }
+
+    /// Checks that the padding entries of a selection, which are identical,
+    /// are not reported as duplicates, while selected duplicates are.
+    ///
+    /// CMD: `cargo test --package prover -- universal_batch_verifier::prove::tests::padding_not_duplicate --exact --nocapture`
+    #[test]
+    fn padding_not_duplicate() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(3);
+        let config = test_ubv_config(4, 4);
+        let batch = UniversalBatchVerifierInputs::sample(&config, true, rng);
+        let check = |entries: &[usize], reject: bool| {
+            let (ubv_inputs, positions) =
+                batch.select_with_padding(entries, &config).unwrap();
+            check_adjacent_duplicates(
+                &BatchEntries::from_ubv_inputs_and_config(&ubv_inputs, &config),
+                Some(&positions),
+                reject,
+            )
+        };
+        check(&[2], true).unwrap();
+
+        let (padded, _) = batch.select_with_padding(&[2], &config).unwrap();
+        assert!(check_adjacent_duplicates(
+            &BatchEntries::from_ubv_inputs_and_config(&padded, &config),
+            None,
+            true,
+        )
+        .is_err());
+
+        let mut duplicated = batch.clone();
+        duplicated.0[1] = duplicated.0[0].clone();
+        let (ubv_inputs, positions) =
+            duplicated.select_with_padding(&[0, 1], &config).unwrap();
+        assert!(check_adjacent_duplicates(
+            &BatchEntries::from_ubv_inputs_and_config(&ubv_inputs, &config),
+            Some(&positions),
+            true,
+        )
+        .is_err());
+    }
+
+    /// Checks that `--jsonl` with a non-native input format is rejected
+    /// with an error, before the batch file is opened.
+    ///
+    /// CMD: `cargo test --package prover -- universal_batch_verifier::prove::tests::jsonl_requires_native_format --exact --nocapture`
+    #[test]
+    fn jsonl_requires_native_format() {
+        let config = test_ubv_config(2, 10);
+        let err = load_ubv_inputs(
+            &config,
+            "missing-batch.jsonl",
+            InputFormat::Snarkjs,
+            true,
+            None,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                ProverError::InvalidInput(msg)
+                    if msg == "--jsonl requires the native input format, \
+                               got Snarkjs"
+            ),
+            "{err}"
+        );
+    }
 }
@@ -0,0 +1,60 @@
+use crate::{
+    default_values::UPA_CONFIG,
+    error::ProverError,
+    file_utils::{fail_if_file_exists, load_upa_config, save_pretty_json_file},
+    groth16::rng_from_seed,
+};
+use circuits::batch_verify::universal::{
+    native::json::JsonUniversalBatchVerifierInputs,
+    types::{UniversalBatchVerifierConfig, UniversalBatchVerifierInputs},
+};
+use clap::Parser;
+use log::info;
+
+#[derive(Debug, Parser)]
+pub struct SampleInputsParams {
+    /// UPA configuration file
+    #[arg(short = 'c', long, value_name = "config-file", default_value = UPA_CONFIG)]
+    pub(crate) config: String,
+
+    /// Output file into which the sampled batch (of `inner_batch_size`
+    /// app vk, proof, inputs triples) will be written, in the native JSON
+    /// format accepted by `prove`.
+    #[arg(short = 'o', long, value_name = "sample-inputs-file")]
+    pub(crate) sample_inputs: String,
+
+    /// Fraction of the entries with a Pedersen commitment.
+    #[arg(long, value_name = "fraction", default_value = "0.5")]
+    pub(crate) commitment_fraction: f64,
+
+    /// Seed for the RNG.  If given, the same seed always gives the same
+    /// output.  Otherwise, the system RNG is used.
+    #[arg(long, value_name = "seed")]
+    pub(crate) seed: Option<u64>,
+}
+
+pub fn sample_inputs(params: SampleInputsParams) -> Result<(), ProverError> {
+    if !(0.0..=1.0).contains(&params.commitment_fraction) {
+        return Err(ProverError::InvalidInput(
+            "--commitment-fraction must be in [0, 1]".to_string(),
+        ));
+    }
+    fail_if_file_exists(&params.sample_inputs)?;
+    let upa_config = load_upa_config(&params.config)?;
+    let ubv_config = UniversalBatchVerifierConfig::from(&upa_config);
+
+    info!(
+        "sampling {} inputs ({} with commitment)",
+        ubv_config.inner_batch_size, params.commitment_fraction
+    );
+    let rng = &mut rng_from_seed(params.seed);
+    let inputs = UniversalBatchVerifierInputs::sample_with_commitment_fraction(
+        &ubv_config,
+        params.commitment_fraction,
+        rng,
+    );
+    save_pretty_json_file(
+        &params.sample_inputs,
+        &JsonUniversalBatchVerifierInputs::from(&inputs),
+    )
+}
@@ -1,99 +1,231 @@
+use super::bytes_to_hex;
 use crate::{
-    default_values::{UBV_GATE_CONFIG, UBV_SRS, UBV_VK},
+    api::ubv_verify,
+    default_values::{UBV_GATE_CONFIG, UBV_SRS, UBV_VK, UPA_CONFIG},
+    error::ProverError,
     file_utils::{
-        instance_file, load_gate_config, load_instance, load_proof, load_srs,
-        open_file_for_read,
+        instance_file, load_circuit_ids, load_gate_config, load_instance,
+        load_proof, load_srs, load_upa_config, open_file_for_read,
     },
 };
 use circuits::{
-    batch_verify::universal::UniversalBatchVerifyCircuit, SafeCircuit,
+    batch_verify::universal::{
+        native::compute_circuit_id_with_domain_version,
+        types::{DomainTagVersion, UniversalBatchVerifierConfig},
+        ubv_instance_len, UniversalBatchVerifyCircuit,
+    },
+    keccak::utils::keccak_inputs_from_ubv_instances,
+    SafeCircuit,
 };
 use clap::Parser;
 use halo2_base::{
     gates::builder::FlexGateConfigParams,
-    halo2_proofs::{
-        halo2curves::bn256::{Bn256, Fr},
-        plonk::verify_proof,
-        poly::{
-            commitment::ParamsProver,
-            kzg::{multiopen::VerifierSHPLONK, strategy::SingleStrategy},
-        },
-    },
+    halo2_proofs::halo2curves::bn256::{Fr, G1Affine},
 };
 use log::info;
-use snark_verifier::system::halo2::transcript::halo2::ChallengeScalar;
-use snark_verifier_sdk::{
-    halo2::{PoseidonTranscript, POSEIDON_SPEC},
-    NativeLoader,
-};
-use std::time::Instant;
+use std::iter;
 
 #[derive(Debug, Parser)]
 pub struct VerifyParams {
     #[arg(short = 's', long, value_name = "srs-file", default_value = UBV_SRS)]
-    srs: String,
+    pub(crate) srs: String,
 
     #[arg(short = 'v', long, value_name = "verification-key-file", default_value = UBV_VK)]
     /// verification key for the UniversalBatchVerifier circuit.
-    verification_key: String,
+    pub(crate) verification_key: String,
 
     #[arg(short = 'g', long, value_name = "gate-config-file", default_value = UBV_GATE_CONFIG)]
     /// Gate configuration file
-    gate_config: String,
+    pub(crate) gate_config: String,
 
     #[arg(long, value_name = "proof-file")]
     /// Proof file for a batch
-    proof: String,
+    pub(crate) proof: String,
 
     #[arg(long, value_name = "instance-file")]
     /// Instance file for a batch (defaults to <proof-file>.instance if not given)
-    instance: Option<String>,
+    pub(crate) instance: Option<String>,
 
     #[arg(short = 'n', long)]
     /// Load the circuit configs and exit.
-    dry_run: bool,
+    pub(crate) dry_run: bool,
+
+    #[arg(long, value_name = "circuit-ids-file")]
+    /// JSON array of hex circuit ids.  If given, after verifying the proof,
+    /// fail unless the circuit id of each application vk in the instance is
+    /// in this list.
+    pub(crate) allowed_circuit_ids: Option<String>,
+
+    #[arg(short = 'c', long, value_name = "config-file", default_value = UPA_CONFIG)]
+    /// UPA configuration file (used to parse the instance for
+    /// `--allowed-circuit-ids`)
+    pub(crate) config: String,
+
+    /// Protocol version of the circuit id domain tags (currently only 1.0.0)
+    #[arg(long, value_name = "version", default_value = "1.0.0")]
+    pub(crate) domain_version: DomainTagVersion,
 }
 
-pub fn verify(params: VerifyParams) {
+pub fn verify(params: VerifyParams) -> Result<(), ProverError> {
     let instance_file = instance_file(params.instance, &params.proof);
 
     let gate_config: FlexGateConfigParams =
-        load_gate_config(&params.gate_config);
+        load_gate_config(&params.gate_config)?;
 
     if params.dry_run {
         info!("dry-run.  not attempting to load VK");
-        return;
+        return Ok(());
     }
 
     info!("loading vk ...");
     let vk = {
-        let mut buf = open_file_for_read(&params.verification_key);
+        let mut buf = open_file_for_read(&params.verification_key)?;
         UniversalBatchVerifyCircuit::read_verifying_key(&gate_config, &mut buf)
-            .unwrap_or_else(|e| panic!("error reading vk: {e}"))
+            .map_err(|e| {
+                ProverError::decode(
+                    &params.verification_key,
+                    "verification key",
+                    e,
+                )
+            })?
     };
 
-    let instance: Vec<Fr> = load_instance(&instance_file);
-    let proof = load_proof(&params.proof);
+    let instance: Vec<Fr> = load_instance(&instance_file)?;
+    let proof = load_proof(&params.proof)?;
     // TODO: Load only the verifier part of the SRS
-    let srs = load_srs(&params.srs);
+    let srs = load_srs(&params.srs)?;
+
+    ubv_verify(&srs, &vk, &proof, &instance)?;
+
+    if let Some(allowed_circuit_ids) = &params.allowed_circuit_ids {
+        let bv_config = UniversalBatchVerifierConfig::from(&load_upa_config(
+            &params.config,
+        )?);
+        let allowed_circuit_ids = load_circuit_ids(allowed_circuit_ids)?;
+        let circuit_ids = circuit_ids_from_instance(
+            &bv_config,
+            &instance,
+            params.domain_version,
+        )?;
+        check_allowed_circuit_ids(&circuit_ids, &allowed_circuit_ids)?;
+        info!("all {} circuit ids are allowed", circuit_ids.len());
+    }
+
+    println!("Verified");
+    Ok(())
+}
+
+/// Returns the circuit id of the application vk of each entry in the UBV
+/// `instance`.
+fn circuit_ids_from_instance(
+    bv_config: &UniversalBatchVerifierConfig,
+    instance: &[Fr],
+    domain_version: DomainTagVersion,
+) -> Result<Vec<[u8; 32]>, ProverError> {
+    let expected_instance_len = ubv_instance_len(bv_config);
+    if instance.len() != expected_instance_len {
+        return Err(ProverError::InvalidInput(format!(
+            "instance length {} differs from expected {expected_instance_len}",
+            instance.len()
+        )));
+    }
+    Ok(keccak_inputs_from_ubv_instances(
+        iter::once(instance),
+        bv_config.max_num_public_inputs as usize,
+        bv_config.inner_batch_size as usize,
+    )
+    .iter()
+    .map(|input| {
+        compute_circuit_id_with_domain_version(&input.app_vk, domain_version)
+    })
+    .collect())
+}
 
-    info!("Verifying UBV proof...");
-    let now = Instant::now();
+/// Fails with the first of `circuit_ids` which is not in
+/// `allowed_circuit_ids`.
+fn check_allowed_circuit_ids(
+    circuit_ids: &[[u8; 32]],
+    allowed_circuit_ids: &[[u8; 32]],
+) -> Result<(), ProverError> {
+    match circuit_ids
+        .iter()
+        .enumerate()
+        .find(|(_, circuit_id)| !allowed_circuit_ids.contains(circuit_id))
     {
-        let mut transcript = PoseidonTranscript::<NativeLoader, _>::from_spec(
-            &proof[..],
-            POSEIDON_SPEC.clone(),
-        );
-        verify_proof::<_, VerifierSHPLONK<'_, Bn256>, ChallengeScalar<_>, _, _>(
-            srs.verifier_params(),
-            &vk,
-            SingleStrategy::new(&srs),
-            &[&[instance.as_slice()]],
-            &mut transcript,
-        )
-        .unwrap_or_else(|e| panic!("proof verification failed: {e}"));
+        Some((i, circuit_id)) => Err(ProverError::InvalidInput(format!(
+            "entry {i} has circuit id {}, which is not allowed",
+            bytes_to_hex(circuit_id)
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_allowed_circuit_ids, circuit_ids_from_instance};
+    use crate::test_utils::test_ubv_config;
+    use circuits::{
+        batch_verify::universal::{
+            native::compute_circuit_id_with_domain_version,
+            types::{DomainTagVersion, UniversalBatchVerifierInputs},
+            UniversalBatchVerifyCircuit,
+        },
+        SafeCircuit,
     };
-    info!("Finished verifying UBV proof in {:?}", now.elapsed());
+    use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
 
-    println!("Verified");
+    /// Checks that the circuit ids are recovered from a UBV instance, and
+    /// that an allow-list missing one of them is rejected.
+    ///
+    /// CMD: `cargo test --package prover -- universal_batch_verifier::verify::tests::allowed_circuit_ids --exact --nocapture`
+    #[test]
+    fn allowed_circuit_ids() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(1);
+        let config = test_ubv_config(3, 4);
+        let domain_version = DomainTagVersion::default();
+        let ubv_inputs =
+            UniversalBatchVerifierInputs::sample_mixed(&config, rng);
+        let instance = UniversalBatchVerifyCircuit::<Fr>::compute_instance(
+            &config,
+            &ubv_inputs,
+        );
+        let expected_circuit_ids: Vec<[u8; 32]> = ubv_inputs
+            .0
+            .iter()
+            .map(|input| {
+                compute_circuit_id_with_domain_version(
+                    &input.vk,
+                    domain_version,
+                )
+            })
+            .collect();
+
+        let circuit_ids =
+            circuit_ids_from_instance(&config, &instance, domain_version)
+                .unwrap();
+        assert_eq!(circuit_ids, expected_circuit_ids);
+        assert!(
+            check_allowed_circuit_ids(&circuit_ids, &expected_circuit_ids)
+                .is_ok()
+        );
+
+        // Exclude the circuit id of entry 1.  Each sampled entry has its own
+        // vk, so no other entry is affected.
+        let allowed: Vec<[u8; 32]> = expected_circuit_ids
+            .iter()
+            .filter(|circuit_id| **circuit_id != expected_circuit_ids[1])
+            .copied()
+            .collect();
+        let err = check_allowed_circuit_ids(&circuit_ids, &allowed)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            format!(
+                "entry 1 has circuit id 0x{}, which is not allowed",
+                hex::encode(expected_circuit_ids[1])
+            )
+        );
+    }
 }
@@ -0,0 +1,39 @@
+use crate::{
+    default_values::UBV_GATE_CONFIG,
+    error::ProverError,
+    file_utils::{load_gate_config, load_upa_config},
+    vk_hash::VkHashArgs,
+};
+use circuits::batch_verify::universal::{
+    types::UniversalBatchVerifierConfig, UniversalBatchVerifyCircuit,
+};
+use clap::Parser;
+use halo2_base::{
+    gates::builder::FlexGateConfigParams,
+    halo2_proofs::halo2curves::bn256::G1Affine,
+};
+
+#[derive(Debug, Parser)]
+pub struct VkHashParams {
+    #[command(flatten)]
+    pub(crate) args: VkHashArgs,
+
+    #[arg(short = 'g', long, value_name = "gate-config-file", default_value = UBV_GATE_CONFIG)]
+    /// Gate configuration file (the FlexGateConfigParams written by
+    /// `keygen --gate-config`)
+    pub(crate) gate_config: String,
+}
+
+pub fn vk_hash(params: VkHashParams) -> Result<(), ProverError> {
+    let bv_config = UniversalBatchVerifierConfig::from(&load_upa_config(
+        &params.args.config,
+    )?);
+    let gate_config: FlexGateConfigParams =
+        load_gate_config(&params.gate_config)?;
+    params
+        .args
+        .print_vk_hash::<UniversalBatchVerifyCircuit<_, G1Affine>>(
+            &bv_config,
+            &gate_config,
+        )
+}
@@ -0,0 +1,24 @@
+use super::UniversalOuterCircuit;
+use crate::{
+    default_values::UPA_CONFIG, error::ProverError, file_utils::load_upa_config,
+};
+use circuits::{format_instance_layout, SafeCircuit};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct DescribeInstanceParams {
+    /// UPA configuration file
+    #[arg(short = 'c', long, value_name = "config-file", default_value = UPA_CONFIG)]
+    pub(crate) config: String,
+}
+
+/// Prints the layout of the full outer instance (the KZG accumulator
+/// followed by the final digest), as found in the calldata.
+pub fn describe_instance(
+    params: DescribeInstanceParams,
+) -> Result<(), ProverError> {
+    let outer_config = load_upa_config(&params.config)?;
+    let layout = UniversalOuterCircuit::describe_instance(&outer_config);
+    print!("{}", format_instance_layout(&layout));
+    Ok(())
+}
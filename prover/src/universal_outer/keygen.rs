@@ -1,18 +1,32 @@
-use super::UniversalOuterConfig;
+use super::{
+    manifest::KeygenManifest, Scheme, UniversalOuterCircuit,
+    UniversalOuterConfig,
+};
 use crate::{
+    api::{outer_keygen, OuterKeys},
     default_values::{
-        BV_SRS, KECCAK_SRS, OUTER_GATE_CONFIG, OUTER_INSTANCE_SIZE, OUTER_PK,
-        OUTER_PROTOCOL, OUTER_SRS, OUTER_VK, UPA_CONFIG,
+        BV_SRS, KECCAK_SRS, OUTER_CIRCUIT_NAME, OUTER_GATE_CONFIG,
+        OUTER_INSTANCE_SIZE, OUTER_PK, OUTER_PROTOCOL, OUTER_SRS, OUTER_VK,
+        UPA_CONFIG,
     },
+    error::ProverError,
     file_utils::{
-        break_points_file, load_srs, panic_if_file_exists, save_break_points,
-        save_gate_config, save_json_file, save_pk, save_protocol, save_vk,
+        break_points_file, fail_if_file_exists, load_json_file, load_srs,
+        load_upa_config, save_break_points, save_gate_config, save_json_file,
+        save_pk, save_protocol, save_vk, write_json_pretty, KeygenOutputs,
+        OUTPUT_DIR_GATE_CONFIG, OUTPUT_DIR_MANIFEST, OUTPUT_DIR_NUM_INSTANCE,
+        OUTPUT_DIR_PK, OUTPUT_DIR_PROTOCOL, OUTPUT_DIR_VK,
     },
+    thread_pool::with_thread_pool,
 };
-use circuits::outer::{
-    universal,
-    utils::{gen_outer_pk, gen_outer_vk},
-    OuterGateConfig, OuterKeygenInputs,
+use circuits::{
+    outer::{
+        universal,
+        utils::{gen_outer_pk_from_vk, gen_outer_vk},
+        OuterCircuit, OuterGateConfig, OuterKeygenInputs, MINIMUM_ROWS,
+    },
+    utils::check_degree_rows,
+    SafeCircuit,
 };
 use clap::Parser;
 use halo2_base::{
@@ -20,13 +34,17 @@ use halo2_base::{
     halo2_proofs::{
         halo2curves::bn256::{Bn256, G1Affine},
         plonk::VerifyingKey,
-        poly::kzg::{
-            commitment::ParamsKZG,
-            multiopen::{ProverSHPLONK, VerifierSHPLONK},
+        poly::{
+            commitment::Params,
+            kzg::{
+                commitment::ParamsKZG,
+                multiopen::{ProverSHPLONK, VerifierSHPLONK},
+            },
         },
     },
 };
 use log::{info, warn};
+use serde::{Deserialize, Serialize};
 use snark_verifier::system::halo2::{compile, Config};
 use snark_verifier_sdk::{
     halo2::aggregation::AggregationCircuit, CircuitExt, SHPLONK,
@@ -51,48 +69,176 @@ pub struct KeygenParams {
     #[arg(long, value_name = "keccak-srs-file", default_value = KECCAK_SRS)]
     pub(crate) keccak_srs: String,
 
-    /// Output proving key file
-    #[arg(short = 'p', long, value_name = "proving-key-file", default_value = OUTER_PK)]
-    pub(crate) proving_key: String,
+    /// Output directory.  If given, output files default to canonical names
+    /// in a subdirectory named after the circuit and a hash of the UPA
+    /// config, and a manifest is always written.
+    #[arg(long, value_name = "output-dir")]
+    pub(crate) output_dir: Option<String>,
 
-    /// Output verification key file
-    #[arg(short = 'v', long, value_name = "verification-key-file", default_value = OUTER_VK)]
-    pub(crate) verification_key: String,
+    /// Output proving key file [default: outer.pk, or pk.bin in
+    /// --output-dir]
+    #[arg(short = 'p', long, value_name = "proving-key-file")]
+    pub(crate) proving_key: Option<String>,
 
-    /// Output protocol file
-    #[arg(short = 'r', long, value_name = "protocol-file", default_value = OUTER_PROTOCOL)]
-    pub(crate) protocol: String,
+    /// Output verification key file [default: outer.vk, or vk.bin in
+    /// --output-dir]
+    #[arg(short = 'v', long, value_name = "verification-key-file")]
+    pub(crate) verification_key: Option<String>,
 
-    /// Output gate config file
-    #[arg(short = 'g', long, value_name = "gate-config-file", default_value = OUTER_GATE_CONFIG)]
-    pub(crate) gate_config: String,
+    /// Output protocol file [default: outer.protocol, or protocol.bin in
+    /// --output-dir]
+    #[arg(short = 'r', long, value_name = "protocol-file")]
+    pub(crate) protocol: Option<String>,
 
-    /// Output num instance file
-    #[arg(long, value_name = "num_instance", default_value = OUTER_INSTANCE_SIZE)]
-    pub(crate) num_instance: String,
+    /// Output gate config file [default: outer.specs, or gate_config.json in
+    /// --output-dir]
+    #[arg(short = 'g', long, value_name = "gate-config-file")]
+    pub(crate) gate_config: Option<String>,
+
+    /// Output num instance file [default: outer.instance_size, or
+    /// num_instance.json in --output-dir]
+    #[arg(long, value_name = "num_instance")]
+    pub(crate) num_instance: Option<String>,
+
+    /// Optional output manifest file, describing the generated keys (UPA
+    /// config, gate config, SRS degree and VK hash) [default: manifest.json
+    /// in --output-dir, if given]
+    #[arg(long, value_name = "manifest-file")]
+    pub(crate) manifest: Option<String>,
+
+    /// Multi-open scheme to be used for outer proofs.  The keys do not
+    /// depend on it, but it is recorded in the manifest (if any) so that
+    /// `prove` and `verify` can check against it.
+    #[arg(long, value_enum, default_value_t = Scheme::Shplonk)]
+    pub(crate) scheme: Scheme,
 
     /// Compute only VK, protocol, and gate config. Skip PK generation.
     #[arg(long, value_name = "vk-only")]
     pub(crate) vk_only: bool,
 
+    /// Checkpoint file.  If given, the VK, gate config and break points are
+    /// written to this file as soon as they are generated, before the (much
+    /// longer) PK generation.
+    #[arg(long, value_name = "checkpoint-file")]
+    pub(crate) checkpoint: Option<String>,
+
+    /// Resume an interrupted keygen from the `--checkpoint` file, skipping
+    /// VK generation.
+    #[arg(long, requires = "checkpoint")]
+    pub(crate) resume: bool,
+
     /// show circuit stats and exit.  do not write files.
     #[arg(short = 'n', long)]
     pub(crate) dry_run: bool,
+
+    /// Number of threads used for key generation (defaults to all available
+    /// cores)
+    #[arg(long, value_name = "num-threads")]
+    pub(crate) threads: Option<usize>,
 }
 
 /// Entry point to the `keygen` subcommand.  Runs the keygen process for the
 /// `OuterCircuit`.
-pub fn keygen(params: KeygenParams) {
+pub fn keygen(params: KeygenParams) -> Result<(), ProverError> {
+    with_thread_pool(params.threads, || run_keygen(params))
+}
+
+/// Resolved output files of the `keygen` subcommand.
+pub(crate) struct KeygenFiles {
+    pub(crate) proving_key: String,
+    pub(crate) verification_key: String,
+    pub(crate) protocol: String,
+    pub(crate) gate_config: String,
+    pub(crate) num_instance: String,
+    pub(crate) manifest: Option<String>,
+}
+
+impl KeygenFiles {
+    /// Resolves the output files of `params` for `config`, creating the
+    /// output directory if one is given.
+    pub(crate) fn new(
+        params: &KeygenParams,
+        config: &UniversalOuterConfig,
+    ) -> Result<Self, ProverError> {
+        let outputs = KeygenOutputs::new(
+            params.output_dir.as_deref(),
+            OUTER_CIRCUIT_NAME,
+            config,
+        )?;
+        Ok(Self {
+            proving_key: outputs.path(
+                &params.proving_key,
+                OUTPUT_DIR_PK,
+                OUTER_PK,
+            ),
+            verification_key: outputs.path(
+                &params.verification_key,
+                OUTPUT_DIR_VK,
+                OUTER_VK,
+            ),
+            protocol: outputs.path(
+                &params.protocol,
+                OUTPUT_DIR_PROTOCOL,
+                OUTER_PROTOCOL,
+            ),
+            gate_config: outputs.path(
+                &params.gate_config,
+                OUTPUT_DIR_GATE_CONFIG,
+                OUTER_GATE_CONFIG,
+            ),
+            num_instance: outputs.path(
+                &params.num_instance,
+                OUTPUT_DIR_NUM_INSTANCE,
+                OUTER_INSTANCE_SIZE,
+            ),
+            manifest: params
+                .manifest
+                .clone()
+                .or_else(|| outputs.in_dir(OUTPUT_DIR_MANIFEST)),
+        })
+    }
+}
+
+fn run_keygen(params: KeygenParams) -> Result<(), ProverError> {
+    let config = load_upa_config(&params.config)?;
+    let files = KeygenFiles::new(&params, &config)?;
+
     // Fail if any of the output paths exist
-    let break_points_file = break_points_file(&params.proving_key);
+    let break_points_file = break_points_file(&files.proving_key);
     if !params.dry_run {
-        panic_if_file_exists(&params.verification_key);
-        panic_if_file_exists(&break_points_file);
-        panic_if_file_exists(&params.protocol);
-        panic_if_file_exists(&params.proving_key);
-        panic_if_file_exists(&params.gate_config);
+        fail_if_file_exists(&files.verification_key)?;
+        fail_if_file_exists(&break_points_file)?;
+        fail_if_file_exists(&files.protocol)?;
+        fail_if_file_exists(&files.proving_key)?;
+        fail_if_file_exists(&files.gate_config)?;
+        if let Some(manifest) = &files.manifest {
+            fail_if_file_exists(manifest)?;
+        }
+        if let (Some(checkpoint), false) = (&params.checkpoint, params.resume) {
+            fail_if_file_exists(checkpoint)?;
+        }
     }
 
+    // Check the checkpoint before any SRS is loaded, let alone a VK built.
+    let checkpoint = match (&params.checkpoint, params.resume) {
+        (Some(checkpoint_file), true) => {
+            Some(load_checkpoint(checkpoint_file, &config)?)
+        }
+        _ => None,
+    };
+
+    // Fail early, rather than panicking during keygen, if the lookup table
+    // or instance do not fit in the degree.  The `OuterGateConfig` itself
+    // can only be computed from snarks generated with the BV and Keccak
+    // SRSs, so only the degree is checked here.
+    check_degree_rows(
+        config.outer_config.degree_bits,
+        config.outer_config.lookup_bits,
+        universal::UniversalOuterCircuit::instance_len(&config),
+        MINIMUM_ROWS,
+    )
+    .map_err(|e| ProverError::InvalidInput(e.to_string()))?;
+
     // TODO: This early-out is only done this early because the circuit
     // currently requires an srs.  Once this is fixed (to use a dummy SRS) move
     // the --dry-run early-out further down.  We should be able to save the
@@ -100,91 +246,254 @@ pub fn keygen(params: KeygenParams) {
 
     // Early-out if --dry-run was specified.
     if params.dry_run {
-        return;
+        return Ok(());
     }
 
-    let config = UniversalOuterConfig::from_file(&params.config);
-
-    let outer_params = load_srs(&params.outer_srs);
+    let outer_params = load_srs(&params.outer_srs)?;
 
-    let bv_params = load_srs(&params.bv_srs);
+    let bv_params = load_srs(&params.bv_srs)?;
 
-    let keccak_params = load_srs(&params.keccak_srs);
+    let keccak_params = load_srs(&params.keccak_srs)?;
     let keygen_inputs =
         OuterKeygenInputs::new(&bv_params, &keccak_params, &outer_params);
 
+    if params.vk_only || params.checkpoint.is_some() {
+        return run_staged_keygen(
+            &params,
+            &files,
+            &config,
+            &keygen_inputs,
+            &break_points_file,
+            checkpoint,
+        );
+    }
+
+    let OuterKeys {
+        pk,
+        gate_config,
+        break_points,
+        num_instance,
+    } = outer_keygen(&config, &keygen_inputs)?;
+    save_vk_and_auxiliary_files(
+        &params,
+        &files,
+        &config,
+        pk.get_vk(),
+        &break_points_file,
+        &break_points,
+        &gate_config,
+        num_instance,
+        &outer_params,
+    )?;
+    info!("Writing PK to file...");
+    save_pk::<UniversalOuterCircuit>(&files.proving_key, &pk)
+}
+
+/// Output of the VK stage of keygen, written to the `--checkpoint` file so
+/// that an interrupted keygen can be resumed at the PK stage.
+#[derive(Debug, Deserialize, Serialize)]
+struct KeygenCheckpoint {
+    /// The config the VK was generated for
+    config: UniversalOuterConfig,
+    /// Hex-encoded VK, in the format of the VK file
+    vk: String,
+    gate_config: OuterGateConfig,
+    break_points: MultiPhaseThreadBreakPoints,
+    num_instance: usize,
+}
+
+/// Loads the `--checkpoint` file to be resumed, checking that it was written
+/// for `config`.
+fn load_checkpoint(
+    checkpoint_file: &str,
+    config: &UniversalOuterConfig,
+) -> Result<KeygenCheckpoint, ProverError> {
+    let checkpoint: KeygenCheckpoint = load_json_file(checkpoint_file)?;
+    check_checkpoint(
+        checkpoint_file,
+        &checkpoint.config,
+        checkpoint.num_instance,
+        config,
+    )?;
+    Ok(checkpoint)
+}
+
+/// Checks that a checkpoint holding `checkpoint_config` and
+/// `checkpoint_num_instance` can be resumed for `config`.
+fn check_checkpoint(
+    checkpoint_file: &str,
+    checkpoint_config: &UniversalOuterConfig,
+    checkpoint_num_instance: usize,
+    config: &UniversalOuterConfig,
+) -> Result<(), ProverError> {
+    let diffs = checkpoint_config.diff(config);
+    if !diffs.is_empty() {
+        let diffs: Vec<String> =
+            diffs.iter().map(|diff| diff.to_string()).collect();
+        return Err(ProverError::InvalidInput(format!(
+            "checkpoint {checkpoint_file} was written for another config ({})",
+            diffs.join(", ")
+        )));
+    }
+    let num_instance = universal::UniversalOuterCircuit::instance_len(config);
+    if checkpoint_num_instance != num_instance {
+        return Err(ProverError::InvalidInput(format!(
+            "checkpoint {checkpoint_file}: num_instance \
+             {checkpoint_num_instance}, expected {num_instance}"
+        )));
+    }
+    Ok(())
+}
+
+/// Runs keygen in two stages: first the VK, gate config and break points are
+/// generated (or taken from `checkpoint`, loaded by `--resume`), and saved to
+/// the checkpoint file if any.  Then, unless `--vk-only` is given, the PK is
+/// generated from the VK.
+fn run_staged_keygen(
+    params: &KeygenParams,
+    files: &KeygenFiles,
+    config: &UniversalOuterConfig,
+    keygen_inputs: &OuterKeygenInputs,
+    break_points_file: &str,
+    checkpoint: Option<KeygenCheckpoint>,
+) -> Result<(), ProverError> {
+    let (vk, gate_config, break_points, num_instance) = match checkpoint {
+        Some(checkpoint) => {
+            let checkpoint_file =
+                params.checkpoint.as_deref().unwrap_or_default();
+            info!("Resuming from checkpoint {checkpoint_file} ...");
+            let vk_bytes = hex::decode(&checkpoint.vk).map_err(|e| {
+                ProverError::decode(checkpoint_file, "checkpoint vk", e)
+            })?;
+            let vk = UniversalOuterCircuit::read_verifying_key(
+                &checkpoint.gate_config,
+                &mut vk_bytes.as_slice(),
+            )
+            .map_err(|e| {
+                ProverError::decode(checkpoint_file, "checkpoint vk", e)
+            })?;
+            (
+                vk,
+                checkpoint.gate_config,
+                checkpoint.break_points,
+                checkpoint.num_instance,
+            )
+        }
+        None => {
+            info!("Generating Outer VK ...");
+            let now = Instant::now();
+            let (vk, gate_config, break_points, num_instance) =
+                gen_outer_vk::<
+                    SHPLONK,
+                    universal::UniversalOuterCircuit,
+                    ProverSHPLONK<Bn256>,
+                    VerifierSHPLONK<Bn256>,
+                >(config, keygen_inputs)
+                .unwrap_or_else(|e| panic!("failed to generate outer VK: {e}"));
+            info!("Finished Outer VK gen in {:?}", now.elapsed());
+            if let Some(checkpoint_file) = &params.checkpoint {
+                info!("writing checkpoint: {checkpoint_file}");
+                let mut vk_bytes = Vec::new();
+                UniversalOuterCircuit::write_verifying_key(&vk, &mut vk_bytes)
+                    .map_err(|e| ProverError::io(checkpoint_file, e))?;
+                save_json_file(
+                    checkpoint_file,
+                    &KeygenCheckpoint {
+                        config: *config,
+                        vk: hex::encode(vk_bytes),
+                        gate_config: gate_config.clone(),
+                        break_points: break_points.clone(),
+                        num_instance,
+                    },
+                )?;
+            }
+            (vk, gate_config, break_points, num_instance)
+        }
+    };
+
+    let outer_params = keygen_inputs.outer_params;
     if params.vk_only {
         warn!("Skipping PK generation!");
-        info!("Generating Outer VK ...");
-        let now = Instant::now();
-        let (vk, gate_config, break_points, num_instance) =
-            gen_outer_vk::<
-                SHPLONK,
-                universal::UniversalOuterCircuit,
-                ProverSHPLONK<Bn256>,
-                VerifierSHPLONK<Bn256>,
-            >(&config, &keygen_inputs)
-            .unwrap_or_else(|e| panic!("failed to generate outer VK: {e}"));
-        info!("Finished Outer VK gen in {:?}", now.elapsed());
-        save_vk_and_auxiliary_files(
-            &params,
+        return save_vk_and_auxiliary_files(
+            params,
+            files,
+            config,
             &vk,
-            &break_points_file,
+            break_points_file,
             &break_points,
             &gate_config,
             num_instance,
-            &outer_params,
+            outer_params,
         );
-        return;
     }
 
-    info!("Generating Outer PK ...");
+    info!("Generating Outer PK from VK ...");
     let now = Instant::now();
-    let (pk, gate_config, break_points, num_instance) =
-        gen_outer_pk::<
+    let (pk, pk_gate_config, pk_break_points, pk_num_instance) =
+        gen_outer_pk_from_vk::<
             SHPLONK,
             universal::UniversalOuterCircuit,
             ProverSHPLONK<Bn256>,
             VerifierSHPLONK<Bn256>,
-        >(&config, &keygen_inputs)
+        >(config, keygen_inputs, vk)
         .unwrap_or_else(|e| panic!("failed to generate outer PK: {e}"));
     info!("Finished Outer PK gen in {:?}", now.elapsed());
+
+    // The circuit built for the PK must match the one the VK (possibly
+    // from an earlier run) was generated from.
+    let gate_config_json = |gate_config: &OuterGateConfig| {
+        serde_json::to_value(gate_config)
+            .unwrap_or_else(|e| panic!("failed to serialize: {e}"))
+    };
+    if gate_config_json(&pk_gate_config) != gate_config_json(&gate_config)
+        || pk_break_points != break_points
+        || pk_num_instance != num_instance
+    {
+        return Err(ProverError::InvalidInput(format!(
+            "checkpoint {} does not match the circuit for config {}",
+            params.checkpoint.as_deref().unwrap_or_default(),
+            params.config
+        )));
+    }
+
     save_vk_and_auxiliary_files(
-        &params,
+        params,
+        files,
+        config,
         pk.get_vk(),
-        &break_points_file,
+        break_points_file,
         &break_points,
         &gate_config,
         num_instance,
-        &outer_params,
-    );
+        outer_params,
+    )?;
     info!("Writing PK to file...");
-    save_pk(&params.proving_key, &pk);
+    save_pk::<UniversalOuterCircuit>(&files.proving_key, &pk)
 }
 
-/// Save the VK, break points, gate config, protocol, and num instance to files.
+/// Save the VK, break points, gate config, protocol, num instance and
+/// (optionally) the manifest to files.
+#[allow(clippy::too_many_arguments)]
 fn save_vk_and_auxiliary_files(
     params: &KeygenParams,
+    files: &KeygenFiles,
+    config: &UniversalOuterConfig,
     vk: &VerifyingKey<G1Affine>,
     break_points_file: &str,
     break_points: &MultiPhaseThreadBreakPoints,
     gate_config: &OuterGateConfig,
     num_instance: usize,
     outer_params: &ParamsKZG<Bn256>,
-) {
+) -> Result<(), ProverError> {
     // Write gate config (FlexGateConfigParams)
-    save_gate_config(&params.gate_config, gate_config);
+    save_gate_config(&files.gate_config, gate_config)?;
     // Write num_instance (needed to generate EVM verifier)
-    save_json_file(
-        &params.num_instance,
-        &num_instance,
-        "outer circuit num instances",
-    );
+    write_json_pretty(&files.num_instance, &num_instance)?;
 
     info!("Writing VK to file...");
-    save_vk(&params.verification_key, vk);
+    save_vk::<UniversalOuterCircuit>(&files.verification_key, vk)?;
 
-    save_break_points(break_points_file, break_points);
+    save_break_points(break_points_file, break_points)?;
 
     info!("compiling VK to Protocol ...");
     let protocol =
@@ -197,5 +506,64 @@ fn save_vk_and_auxiliary_files(
                     AggregationCircuit::accumulator_indices(),
                 ),
         );
-    save_protocol(&params.protocol, &protocol);
+    save_protocol(&files.protocol, &protocol)?;
+
+    if let Some(manifest) = &files.manifest {
+        KeygenManifest::new(
+            config,
+            gate_config,
+            outer_params.k(),
+            vk,
+            params.scheme,
+        )
+        .save(manifest)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_checkpoint;
+    use crate::{error::ProverError, test_utils::test_upa_config};
+    use circuits::{
+        outer::{universal::UniversalOuterCircuit, OuterCircuit},
+        utils::upa_config::UpaConfig,
+    };
+
+    /// Checks that a checkpoint is only resumed for the config it was
+    /// written for.
+    ///
+    /// CMD: `cargo test --package prover -- universal_outer::keygen::tests::checkpoint_config_checked --exact --nocapture`
+    #[test]
+    fn checkpoint_config_checked() {
+        let config = test_upa_config();
+        let num_instance = UniversalOuterCircuit::instance_len(&config);
+        check_checkpoint("checkpoint.json", &config, num_instance, &config)
+            .unwrap();
+
+        let other_config = UpaConfig {
+            outer_batch_size: config.outer_batch_size + 1,
+            ..config
+        };
+        match check_checkpoint(
+            "checkpoint.json",
+            &other_config,
+            num_instance,
+            &config,
+        ) {
+            Err(ProverError::InvalidInput(e)) => {
+                assert!(e.contains("outer_batch_size"), "{e}")
+            }
+            result => panic!("expected InvalidInput, got {result:?}"),
+        }
+        assert!(matches!(
+            check_checkpoint(
+                "checkpoint.json",
+                &config,
+                num_instance + 1,
+                &config
+            ),
+            Err(ProverError::InvalidInput(_))
+        ));
+    }
 }
@@ -0,0 +1,129 @@
+use super::Scheme;
+use crate::{
+    error::ProverError,
+    file_utils::{load_json_file, write_json_pretty},
+};
+use circuits::{
+    outer::OuterGateConfig,
+    utils::{field_element_hex, upa_config::UpaConfig},
+};
+use halo2_base::halo2_proofs::{
+    halo2curves::bn256::{Fr, G1Affine},
+    plonk::VerifyingKey,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+/// Version of the tool that generated the keys.
+const TOOL_VERSION: &str =
+    concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+
+/// Describes the key set produced by `universal_outer keygen`, so that
+/// downstream tools can check that a given set of keys is the expected one.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct KeygenManifest {
+    /// UPA configuration used for keygen
+    upa_config: UpaConfig,
+    /// Outer circuit gate configuration produced by keygen
+    gate_config: OuterGateConfig,
+    /// Degree of the outer circuit SRS
+    srs_degree: u32,
+    /// Hash of the outer circuit verifying key (its transcript
+    /// representation)
+    #[serde(with = "field_element_hex")]
+    vk_hash: Fr,
+    /// Multi-open scheme to be used for outer proofs
+    #[serde(default)]
+    scheme: Scheme,
+    /// Version of the tool that generated the keys
+    tool_version: String,
+}
+
+impl KeygenManifest {
+    pub(crate) fn new(
+        upa_config: &UpaConfig,
+        gate_config: &OuterGateConfig,
+        srs_degree: u32,
+        vk: &VerifyingKey<G1Affine>,
+        scheme: Scheme,
+    ) -> Self {
+        Self {
+            upa_config: *upa_config,
+            gate_config: gate_config.clone(),
+            srs_degree,
+            vk_hash: vk.transcript_repr(),
+            scheme,
+            tool_version: TOOL_VERSION.to_string(),
+        }
+    }
+
+    pub(crate) fn save(&self, path: &str) -> Result<(), ProverError> {
+        info!("writing keygen manifest: {path}");
+        write_json_pretty(path, self)
+    }
+
+    pub(crate) fn load(path: &str) -> Result<Self, ProverError> {
+        info!("loading keygen manifest: {path}");
+        load_json_file(path)
+    }
+
+    /// Fails if `upa_config` differs from the one in `self`.
+    pub(crate) fn check_upa_config(
+        &self,
+        upa_config: &UpaConfig,
+    ) -> Result<(), ProverError> {
+        check_matches(&self.upa_config == upa_config, "UPA config")
+    }
+
+    /// Fails if `srs_degree` differs from the one in `self`.
+    pub(crate) fn check_srs_degree(
+        &self,
+        srs_degree: u32,
+    ) -> Result<(), ProverError> {
+        check_matches(self.srs_degree == srs_degree, "SRS degree")
+    }
+
+    /// Fails if `gate_config` differs from the one in `self`.
+    pub(crate) fn check_gate_config(
+        &self,
+        gate_config: &OuterGateConfig,
+    ) -> Result<(), ProverError> {
+        // `OuterGateConfig` cannot be compared directly, so compare the
+        // serialized values.
+        let to_json = |gate_config: &OuterGateConfig| {
+            serde_json::to_value(gate_config)
+                .expect("failed to serialize OuterGateConfig")
+        };
+        check_matches(
+            to_json(&self.gate_config) == to_json(gate_config),
+            "gate config",
+        )
+    }
+
+    /// Fails if `scheme` differs from the one in `self`.
+    pub(crate) fn check_scheme(
+        &self,
+        scheme: Scheme,
+    ) -> Result<(), ProverError> {
+        check_matches(self.scheme == scheme, "scheme")
+    }
+
+    /// Fails if the hash of `vk` differs from the one in `self`.
+    pub(crate) fn check_vk(
+        &self,
+        vk: &VerifyingKey<G1Affine>,
+    ) -> Result<(), ProverError> {
+        check_matches(self.vk_hash == vk.transcript_repr(), "verifying key")
+    }
+}
+
+/// Returns an error reporting that `what` does not match the manifest,
+/// unless `matches`.
+fn check_matches(matches: bool, what: &str) -> Result<(), ProverError> {
+    if !matches {
+        return Err(ProverError::InvalidInput(format!(
+            "{what} does not match manifest"
+        )));
+    }
+    Ok(())
+}
@@ -1,27 +1,36 @@
+use crate::error::ProverError;
 use circuits::{
     outer::{
         universal, OuterCircuitInputs, OuterCircuitWrapper, OuterInstanceInputs,
     },
     utils::upa_config::UpaConfig,
 };
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use halo2_base::halo2_proofs::{
     halo2curves::bn256::Bn256,
     poly::kzg::multiopen::{ProverSHPLONK, VerifierSHPLONK},
 };
+use serde::{Deserialize, Serialize};
 use snark_verifier_sdk::SHPLONK;
 
 pub use self::{
-    keygen::{keygen, KeygenParams},
+    describe_instance::{describe_instance, DescribeInstanceParams},
+    keygen::{keygen, KeygenFiles, KeygenParams},
     prove::{prove, ProveParams},
+    recurse::{recurse, RecurseParams},
     verifier_yul_code::{generate_evm_verifier, GenerateVerifierParams},
     verify::{verify, VerifyParams},
+    vk_hash::{vk_hash, VkHashParams},
 };
 
+mod describe_instance;
 mod keygen;
+mod manifest;
 mod prove;
+mod recurse;
 mod verifier_yul_code;
 mod verify;
+mod vk_hash;
 
 /// Type alias for specifying whether prover uses Shplonk/GWC
 pub type UniversalOuterCircuit<'a> = OuterCircuitWrapper<
@@ -32,6 +41,29 @@ pub type UniversalOuterCircuit<'a> = OuterCircuitWrapper<
     VerifierSHPLONK<'a, Bn256>,
 >;
 pub type UniversalOuterConfig = UpaConfig;
+/// Multi-open scheme used for outer proofs and the EVM verifier.  Note that
+/// this does not affect the circuit itself (and hence the keys), which always
+/// aggregates SHPLONK proofs from the BV and Keccak circuits.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    Eq,
+    PartialEq,
+    Serialize,
+    ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    /// SHPLONK (BDFG21)
+    #[default]
+    Shplonk,
+    /// GWC19
+    Gwc,
+}
+
 pub type UniversalOuterCircuitInputs =
     OuterCircuitInputs<universal::UniversalOuterCircuit>;
 pub type UniversalOuterInstanceInputs =
@@ -60,11 +92,27 @@ enum UniversalOuterCommand {
     /// Read call-data and verify locally
     #[command()]
     Verify(VerifyParams),
+
+    /// Recursively aggregate outer proofs into a single proof
+    #[command()]
+    Recurse(RecurseParams),
+
+    /// Print the layout of the instance (the named segments of the public
+    /// inputs, including the KZG accumulator)
+    #[command()]
+    DescribeInstance(DescribeInstanceParams),
+
+    /// Print the hash of a VK, or of the VK embedded in a PK (compare with
+    /// the `vk_hash` of the keygen manifest)
+    #[command()]
+    VkHash(VkHashParams),
 }
 
 /// Entry point to the `outer` series of subcommands.  See description in
 /// main.rs.
-pub fn universal_outer(params: UniversalOuterParams) {
+pub fn universal_outer(
+    params: UniversalOuterParams,
+) -> Result<(), ProverError> {
     match params.command {
         UniversalOuterCommand::Keygen(params) => keygen(params),
         UniversalOuterCommand::GenerateVerifier(params) => {
@@ -72,5 +120,10 @@ pub fn universal_outer(params: UniversalOuterParams) {
         }
         UniversalOuterCommand::Prove(params) => prove(params),
         UniversalOuterCommand::Verify(params) => verify(params),
+        UniversalOuterCommand::Recurse(params) => recurse(params),
+        UniversalOuterCommand::DescribeInstance(params) => {
+            describe_instance(params)
+        }
+        UniversalOuterCommand::VkHash(params) => vk_hash(params),
     }
 }
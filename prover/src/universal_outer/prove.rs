@@ -1,33 +1,40 @@
 use super::{
-    UniversalOuterCircuit, UniversalOuterCircuitInputs,
-    UniversalOuterInstanceInputs,
+    manifest::KeygenManifest, Scheme, UniversalOuterCircuit,
+    UniversalOuterCircuitInputs, UniversalOuterInstanceInputs,
 };
 use crate::{
+    api::{
+        outer_prove, outer_prove_recursive, outer_verify,
+        outer_verify_recursive,
+    },
     default_values::{
         KECCAK_PROTOCOL, OUTER_GATE_CONFIG, OUTER_PK, OUTER_SRS, UBV_PROTOCOL,
         UPA_CONFIG,
     },
+    error::ProverError,
     file_utils::{
-        break_points_file, calldata_file, instance_file, load_break_points,
-        load_instance, load_proof, load_protocol, load_srs, open_file_for_read,
-        panic_if_file_exists, save_calldata, save_instance, save_proof,
+        break_points_file, calldata_file, fail_if_file_exists, instance_file,
+        load_break_points, load_gate_config, load_instance, load_pk_and_inputs,
+        load_proof, load_protocol, load_srs, load_upa_config, save_calldata,
+        save_instance, save_proof,
     },
+    thread_pool::with_thread_pool,
 };
 use circuits::{
     self,
-    outer::{universal, utils::prove_outer, OuterGateConfig},
-    utils::{file::load_json, upa_config::UpaConfig},
+    outer::{
+        outer_instance_len, utils::abi_encode_calldata, OuterGateConfig,
+        ACCUMULATOR_LEN,
+    },
+    utils::upa_config::UpaConfig,
     SafeCircuit,
 };
 use clap::Parser;
-use core::iter;
 use halo2_base::halo2_proofs::{
-    halo2curves::bn256::{Bn256, Fr},
-    poly::kzg::multiopen::{ProverSHPLONK, VerifierSHPLONK},
+    halo2curves::bn256::Fr, poly::commitment::Params,
 };
 use log::info;
-use snark_verifier_sdk::{evm::encode_calldata, Snark, SHPLONK};
-use std::time::Instant;
+use snark_verifier_sdk::{evm::encode_calldata, Snark};
 
 #[derive(Debug, Parser)]
 pub struct ProveParams {
@@ -83,133 +90,287 @@ pub struct ProveParams {
     #[arg(long, value_name = "calldata")]
     pub(crate) calldata: Option<String>,
 
+    /// Write the calldata as a call to the function with the given
+    /// `--selector` and signature `f(bytes proof, uint256[] instances)`,
+    /// as produced by Solidity's `abi.encodeWithSelector`, instead of the
+    /// calldata expected by the UPA verifier.
+    #[arg(long, requires = "selector")]
+    pub(crate) abi_encode: bool,
+
+    /// 4-byte function selector (hex) used by `--abi-encode`
+    #[arg(long, value_name = "4-byte-hex", requires = "abi_encode")]
+    pub(crate) selector: Option<String>,
+
+    /// Memory-map the proving key file instead of reading it into a
+    /// buffer.  The file must not be modified while proving.
+    #[arg(long)]
+    pub(crate) mmap_pk: bool,
+
+    /// Load the BV and Keccak proofs and protocols on a separate thread
+    /// while the proving key is read
+    #[arg(long)]
+    pub(crate) concurrent_load: bool,
+
+    /// Keygen manifest file.  If given, the UPA config, SRS, gate config and
+    /// proving key are checked against it before proving.
+    #[arg(long, value_name = "manifest-file")]
+    pub(crate) manifest: Option<String>,
+
+    /// Generate a proof to be aggregated by `universal-outer recurse`, using
+    /// a Poseidon transcript.  Such proofs cannot be verified on-chain, so no
+    /// calldata is written.
+    #[arg(long)]
+    pub(crate) recursive: bool,
+
+    /// Multi-open scheme of the outer proof.  The EVM verifier must be
+    /// generated with the same scheme.
+    #[arg(long, value_enum, default_value_t = Scheme::Shplonk)]
+    pub(crate) scheme: Scheme,
+
+    /// Verify the proof against the proving key's VK before writing it,
+    /// including the deferred pairing check on the KZG accumulator (as
+    /// performed by the EVM verifier).  Fails if verification fails.
+    #[arg(long)]
+    pub(crate) verify_after_prove: bool,
+
     /// show circuit stats and exit.  do not write files.
     #[arg(short = 'n', long)]
     pub(crate) dry_run: bool,
+
+    /// Number of threads used for proving (defaults to all available cores)
+    #[arg(long, value_name = "num-threads")]
+    pub(crate) threads: Option<usize>,
 }
 
 /// Compute EVM proof. Write proof, inputs, and calldata
 /// to separate files. Calldata consists of the proofs and
 /// inputs serialized for the verifier contract.
-pub fn prove(params: ProveParams) {
+pub fn prove(params: ProveParams) -> Result<(), ProverError> {
+    with_thread_pool(params.threads, || run_prove(params))
+}
+
+fn run_prove(params: ProveParams) -> Result<(), ProverError> {
     let outer_instance_file = instance_file(params.instance, &params.proof);
     let calldata_file = calldata_file(params.calldata, &params.proof);
+    // `--selector` and `--abi-encode` require each other
+    let selector =
+        params.selector.as_deref().map(parse_selector).transpose()?;
 
     if !params.dry_run {
-        panic_if_file_exists(&params.proof);
-        panic_if_file_exists(&outer_instance_file);
-        panic_if_file_exists(&calldata_file);
+        fail_if_file_exists(&params.proof)?;
+        fail_if_file_exists(&outer_instance_file)?;
+        if !params.recursive {
+            fail_if_file_exists(&calldata_file)?;
+        }
     }
 
     // Parse the (optional) instance files list
-    let bv_instance_files: Vec<String> =
-        parse_optional_instance_files(params.ubv_instances, &params.ubv_proofs);
+    let bv_instance_files: Vec<String> = parse_optional_instance_files(
+        params.ubv_instances,
+        &params.ubv_proofs,
+    )?;
     let keccak_instance_file =
         instance_file(params.keccak_instance, &params.keccak_proof);
 
     // Instances
     let (bv_instances, keccak_instance) =
-        load_inner_instances(&bv_instance_files, &keccak_instance_file);
+        load_inner_instances(&bv_instance_files, &keccak_instance_file)?;
+
+    // Fail early, before loading the proving key and proofs, if the
+    // instances are inconsistent (e.g. a mismatched keccak proof).
+    let config = load_upa_config(&params.config)?;
+    UniversalOuterCircuitInputs::check_consistency(
+        &config,
+        &bv_instances,
+        &keccak_instance,
+    )
+    .map_err(|e| {
+        ProverError::InvalidInput(format!(
+            "{keccak_instance_file}: inconsistent with BV instances: {e}"
+        ))
+    })?;
 
     if params.dry_run {
-        prove_dry_run(
-            &params.config,
+        return prove_dry_run(
+            &config,
             bv_instances,
             keccak_instance,
+            selector,
             &calldata_file,
         );
-        return;
     }
 
-    let config = UpaConfig::from_file(&params.config);
-    let outer_params = load_srs(&params.srs);
-    let gate_config: OuterGateConfig = load_json(&params.gate_config);
-    info!("reading Outer PK ...");
-    let now = Instant::now();
-    let pk = {
-        let mut buf = open_file_for_read(&params.proving_key);
-        UniversalOuterCircuit::read_proving_key(&config, &gate_config, &mut buf)
-            .unwrap_or_else(|e| panic!("error reading pk: {e}"))
-    };
-    info!("Finished reading Outer PK in {:?}", now.elapsed());
+    let outer_params = load_srs(&params.srs)?;
+    let gate_config: OuterGateConfig = load_gate_config(&params.gate_config)?;
+    let manifest = params
+        .manifest
+        .as_deref()
+        .map(KeygenManifest::load)
+        .transpose()?;
+    if let Some(manifest) = &manifest {
+        manifest.check_upa_config(&config)?;
+        manifest.check_srs_degree(outer_params.k())?;
+        manifest.check_gate_config(&gate_config)?;
+        manifest.check_scheme(params.scheme)?;
+    }
+    // Outer inputs.  Loading them only deserializes the snarks, and does not
+    // construct any circuit, so may run concurrently with reading the PK.
+    let load_outer_inputs =
+        || -> Result<UniversalOuterCircuitInputs, ProverError> {
+            let (bv_proofs, keccak_proof) =
+                load_inner_proofs(&params.ubv_proofs, &params.keccak_proof)?;
+
+            let bv_protocol = load_protocol(&params.bv_protocol)?;
+            let bv_snarks: Vec<Snark> = bv_proofs
+                .into_iter()
+                .zip(bv_instances.into_iter())
+                .map(|(p, i)| Snark::new(bv_protocol.clone(), vec![i], p))
+                .collect();
+            let keccak_protocol = load_protocol(&params.keccak_protocol)?;
+            let keccak_snark = Snark::new(
+                keccak_protocol,
+                vec![keccak_instance],
+                keccak_proof,
+            );
+
+            Ok(UniversalOuterCircuitInputs::new(
+                &config,
+                &config.keccak_config(),
+                bv_snarks,
+                keccak_snark,
+            ))
+        };
+    let (pk, outer_inputs) = load_pk_and_inputs::<UniversalOuterCircuit, _>(
+        &config,
+        &gate_config,
+        &params.proving_key,
+        params.mmap_pk,
+        params.concurrent_load,
+        load_outer_inputs,
+    )?;
+    if let Some(manifest) = &manifest {
+        manifest.check_vk(pk.get_vk())?;
+    }
     let break_points = {
         let break_points_file = break_points_file(&params.proving_key);
-        load_break_points(&break_points_file)
+        load_break_points(&break_points_file)?
     };
 
-    // Outer inputs
-    let outer_inputs = {
-        let (bv_proofs, keccak_proof) =
-            load_inner_proofs(&params.ubv_proofs, &params.keccak_proof);
-
-        let bv_protocol = load_protocol(&params.bv_protocol);
-        let bv_snarks: Vec<Snark> = bv_proofs
-            .into_iter()
-            .zip(bv_instances.into_iter())
-            .map(|(p, i)| Snark::new(bv_protocol.clone(), vec![i], p))
-            .collect();
-        let keccak_protocol = load_protocol(&params.keccak_protocol);
-        let keccak_snark =
-            Snark::new(keccak_protocol, vec![keccak_instance], keccak_proof);
-
-        UniversalOuterCircuitInputs::new(&config, bv_snarks, keccak_snark)
-    };
+    if params.recursive {
+        // `RecursiveOuterCircuit` aggregates SHPLONK proofs.
+        assert_eq!(
+            params.scheme,
+            Scheme::Shplonk,
+            "--recursive requires the shplonk scheme"
+        );
+        let snark = outer_prove_recursive(
+            &outer_params,
+            &pk,
+            &config,
+            &gate_config,
+            break_points,
+            outer_inputs,
+        )?;
+        if params.verify_after_prove {
+            outer_verify_recursive(
+                &outer_params,
+                pk.get_vk(),
+                &config,
+                &snark,
+            )?;
+        }
+        save_proof(&params.proof, &snark.proof)?;
+        return save_instance(&outer_instance_file, &snark.instances[0]);
+    }
 
-    info!("Computing Outer proof...");
-    let now = Instant::now();
-    let (proof, instances) = prove_outer::<
-        SHPLONK,
-        universal::UniversalOuterCircuit,
-        ProverSHPLONK<Bn256>,
-        VerifierSHPLONK<Bn256>,
-    >(
+    let (proof, instances) = outer_prove(
+        &outer_params,
+        &pk,
         &config,
         &gate_config,
-        &pk,
         break_points,
         outer_inputs,
-        &outer_params,
-    );
-    info!("Finished computing Outer proof in {:?}", now.elapsed());
-    let calldata = encode_calldata(&[instances.clone()], &proof);
+        params.scheme,
+    )?;
+    if params.verify_after_prove {
+        outer_verify(
+            &outer_params,
+            pk.get_vk(),
+            &config,
+            params.scheme,
+            &proof,
+            &instances,
+        )?;
+    }
+    let calldata = encode_outer_calldata(&instances, &proof, selector);
     info!("Calldata size: {:?} bytes", calldata.len());
 
-    save_proof(&params.proof, &proof);
-    save_instance(&outer_instance_file, &instances);
-    save_calldata(&calldata_file, &calldata);
+    save_proof(&params.proof, &proof)?;
+    save_instance(&outer_instance_file, &instances)?;
+    save_calldata(&calldata_file, &calldata)
+}
+
+/// Parses a 4-byte function selector given in hex (optionally
+/// `0x`-prefixed).
+fn parse_selector(selector: &str) -> Result<[u8; 4], ProverError> {
+    let bytes =
+        hex::decode(selector.trim_start_matches("0x")).map_err(|e| {
+            ProverError::InvalidInput(format!(
+                "invalid selector {selector}: {e}"
+            ))
+        })?;
+    bytes.try_into().map_err(|_| {
+        ProverError::InvalidInput(format!(
+            "selector {selector} must be exactly 4 bytes"
+        ))
+    })
+}
+
+/// Encodes the outer `instances` and `proof` as calldata, either for the
+/// UPA verifier or, if a `selector` is given, for a call to that function
+/// (see [`abi_encode_calldata`]).
+fn encode_outer_calldata(
+    instances: &[Fr],
+    proof: &[u8],
+    selector: Option<[u8; 4]>,
+) -> Vec<u8> {
+    match selector {
+        Some(selector) => abi_encode_calldata(selector, proof, instances),
+        None => encode_calldata(&[instances.to_vec()], proof),
+    }
 }
 
 /// Assembles `OuterCircuitInputs` from provided files.
 pub(crate) fn load_inner_instances(
     bv_instance_files: &[String],
     keccak_instance_file: &str,
-) -> (Vec<Vec<Fr>>, Vec<Fr>) {
+) -> Result<(Vec<Vec<Fr>>, Vec<Fr>), ProverError> {
     let mut bv_instances = Vec::<Vec<Fr>>::new();
 
     // Read each BV instance from file
     for bv_instance_file in bv_instance_files.iter() {
-        bv_instances.push(load_instance(bv_instance_file));
+        bv_instances.push(load_instance(bv_instance_file)?);
     }
     // Read Keccak instance from file
-    let keccak_instances = load_instance(keccak_instance_file);
+    let keccak_instances = load_instance(keccak_instance_file)?;
 
-    (bv_instances, keccak_instances)
+    Ok((bv_instances, keccak_instances))
 }
 
 pub(crate) fn load_inner_proofs(
     bv_proof_files: &Vec<String>,
     keccak_proof_file: &str,
-) -> (Vec<Vec<u8>>, Vec<u8>) {
+) -> Result<(Vec<Vec<u8>>, Vec<u8>), ProverError> {
     let mut bv_proofs = Vec::<Vec<u8>>::new();
 
     // Read each BV proof from file
     for bv_proof_file in bv_proof_files {
-        bv_proofs.push(load_proof(bv_proof_file));
+        bv_proofs.push(load_proof(bv_proof_file)?);
     }
     // Read Keccak proof from file
-    let keccak_proof = load_proof(keccak_proof_file);
+    let keccak_proof = load_proof(keccak_proof_file)?;
 
-    (bv_proofs, keccak_proof)
+    Ok((bv_proofs, keccak_proof))
 }
 
 /// Parses the `Option<Vec<String>>` of optionally provided
@@ -217,20 +378,21 @@ pub(crate) fn load_inner_proofs(
 pub(crate) fn parse_optional_instance_files(
     optional_instance_files: Option<Vec<String>>,
     bv_proof_files: &[String],
-) -> Vec<String> {
+) -> Result<Vec<String>, ProverError> {
     match optional_instance_files {
         Some(bv_instances) => {
-            assert_eq!(
-                bv_instances.len(),
-                bv_proof_files.len(),
-                "if present, --bv-instances must be given for all proofs"
-            );
-            bv_instances
+            if bv_instances.len() != bv_proof_files.len() {
+                return Err(ProverError::InvalidInput(
+                    "if present, --bv-instances must be given for all proofs"
+                        .to_string(),
+                ));
+            }
+            Ok(bv_instances)
         }
-        None => bv_proof_files
+        None => Ok(bv_proof_files
             .iter()
             .map(|pf_file| instance_file(None, pf_file))
-            .collect(),
+            .collect()),
     }
 }
 
@@ -238,23 +400,18 @@ pub(crate) fn do_prove_dry_run(
     config: &UpaConfig,
     inputs: &UniversalOuterInstanceInputs,
 ) -> (Vec<Fr>, Vec<u8>) {
-    let final_digest_field_elements =
-        UniversalOuterCircuit::compute_instance(config, inputs);
-    assert!(final_digest_field_elements.len() == 2);
-
-    // OuterCircuit::compute_instance returns just the final_digest, without the
-    // leading KZG accumulator, hence we must fill this in, in front of the
-    // final digest. It consists of 2 arbitrary group points, represented as 12
-    // scalar field elements (2 group points x 2 base field elements each x 3
-    // limbs each).
+    let outputs = UniversalOuterCircuit::compute_instance(config, inputs);
+
+    // OuterCircuit::compute_instance returns just the outputs (the final
+    // digest, preceded by num_proof_ids if public), without the leading KZG
+    // accumulator, hence we must fill this in, in front of the outputs. It
+    // consists of 2 arbitrary group points, represented as 12 scalar field
+    // elements (2 group points x 2 base field elements each x 3 limbs each).
     //
     // As long as the elements are non-zero the dummy EVM verifier will accept
     // them.
-    let instance: Vec<Fr> = iter::once(Fr::from(1))
-        .cycle()
-        .take(4 * config.outer_config.num_limbs)
-        .chain(final_digest_field_elements)
-        .collect();
+    let instance = [vec![Fr::from(1); ACCUMULATOR_LEN], outputs].concat();
+    assert_eq!(instance.len(), outer_instance_len(config));
     let calldata = encode_calldata(&[instance.clone()], &[]);
 
     (instance, calldata)
@@ -263,18 +420,23 @@ pub(crate) fn do_prove_dry_run(
 /// Compute the final digest from the bv_instance files, and create dummy call
 /// data with the final digest in the correct place.
 fn prove_dry_run(
-    config_file: &str,
+    config: &UpaConfig,
     bv_instances: Vec<Vec<Fr>>,
     keccak_instance: Vec<Fr>,
+    selector: Option<[u8; 4]>,
     outer_calldata_file: &str,
-) {
+) -> Result<(), ProverError> {
     info!("dry-run.  generating calldata only");
-    let config = UpaConfig::from_file(config_file);
     let instance_inputs = UniversalOuterInstanceInputs::new(
-        &config,
+        config,
+        &config.keccak_config(),
         bv_instances,
         keccak_instance,
     );
-    let (_instance, calldata) = do_prove_dry_run(&config, &instance_inputs);
-    save_calldata(outer_calldata_file, &calldata);
+    let (instance, calldata) = do_prove_dry_run(config, &instance_inputs);
+    let calldata = match selector {
+        Some(selector) => abi_encode_calldata(selector, &[], &instance),
+        None => calldata,
+    };
+    save_calldata(outer_calldata_file, &calldata)
 }
@@ -0,0 +1,340 @@
+use crate::{
+    default_values::{
+        OUTER_PROTOCOL, RECURSIVE_CIRCUIT_NAME, RECURSIVE_CONFIG,
+        RECURSIVE_GATE_CONFIG, RECURSIVE_INSTANCE_SIZE, RECURSIVE_PK,
+        RECURSIVE_SRS, RECURSIVE_VK, UPA_CONFIG,
+    },
+    error::ProverError,
+    file_utils::{
+        break_points_file, calldata_file, fail_if_file_exists, instance_file,
+        load_break_points, load_gate_config, load_instance, load_json_file,
+        load_proof, load_protocol, load_srs, load_upa_config,
+        open_file_for_read, save_break_points, save_calldata, save_gate_config,
+        save_instance, save_pk, save_proof, save_vk, write_json_pretty,
+        KeygenDirManifest, KeygenOutputs, OUTPUT_DIR_CONFIG,
+        OUTPUT_DIR_GATE_CONFIG, OUTPUT_DIR_MANIFEST, OUTPUT_DIR_NUM_INSTANCE,
+        OUTPUT_DIR_PK, OUTPUT_DIR_VK,
+    },
+    thread_pool::with_thread_pool,
+};
+use circuits::{
+    outer::{
+        recursive::{
+            RecursiveOuterCircuit, RecursiveOuterCircuitInputs,
+            RecursiveOuterConfig, RecursiveOuterKeygenInputs,
+        },
+        utils::{gen_outer_pk, prove_outer},
+        OuterCircuitWrapper, OuterGateConfig,
+    },
+    CircuitWithLimbsConfig, SafeCircuit,
+};
+use clap::{Parser, Subcommand};
+use halo2_base::halo2_proofs::{
+    halo2curves::bn256::Bn256,
+    poly::kzg::multiopen::{ProverSHPLONK, VerifierSHPLONK},
+};
+use log::info;
+use snark_verifier_sdk::{evm::encode_calldata, Snark, SHPLONK};
+use std::time::Instant;
+
+/// Type alias for specifying whether prover uses Shplonk/GWC
+type RecursiveCircuit<'a> = OuterCircuitWrapper<
+    'a,
+    SHPLONK,
+    RecursiveOuterCircuit,
+    ProverSHPLONK<'a, Bn256>,
+    VerifierSHPLONK<'a, Bn256>,
+>;
+
+#[derive(Debug, Parser)]
+pub struct RecurseParams {
+    #[command(subcommand)]
+    command: RecurseCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum RecurseCommand {
+    /// Generate PK and VK for the recursive circuit
+    #[command()]
+    Keygen(RecurseKeygenParams),
+
+    /// Aggregate outer proofs into a single proof (as EVM calldata)
+    #[command()]
+    Prove(RecurseProveParams),
+}
+
+#[derive(Debug, Parser)]
+pub struct RecurseKeygenParams {
+    /// UPA configuration file of the aggregated outer proofs
+    #[arg(long, value_name = "config-file", default_value = UPA_CONFIG)]
+    config: String,
+
+    /// Number of outer proofs aggregated by each recursive proof
+    #[arg(long, value_name = "num-children")]
+    num_children: u32,
+
+    /// Log-2 degree of the recursive circuit
+    #[arg(short = 'k', long, value_name = "degree-bits")]
+    degree_bits: u32,
+
+    /// Outer circuit protocol file
+    #[arg(long, value_name = "outer-protocol-file", default_value = OUTER_PROTOCOL)]
+    outer_protocol: String,
+
+    /// Recursive circuit SRS file
+    #[arg(short = 's', long, value_name = "srs-file", default_value = RECURSIVE_SRS)]
+    srs: String,
+
+    /// Output directory.  If given, output files default to canonical names
+    /// in a subdirectory named after the circuit and a hash of the recursive
+    /// circuit config, together with a manifest.json.
+    #[arg(long, value_name = "output-dir")]
+    output_dir: Option<String>,
+
+    /// Output recursive circuit config file [default: recursive_config.json,
+    /// or config.json in --output-dir]
+    #[arg(long, value_name = "recursive-config-file")]
+    recursive_config: Option<String>,
+
+    /// Output proving key file [default: recursive.pk, or pk.bin in
+    /// --output-dir]
+    #[arg(short = 'p', long, value_name = "proving-key-file")]
+    proving_key: Option<String>,
+
+    /// Output verification key file [default: recursive.vk, or vk.bin in
+    /// --output-dir]
+    #[arg(short = 'v', long, value_name = "verification-key-file")]
+    verification_key: Option<String>,
+
+    /// Output gate config file [default: recursive.specs, or
+    /// gate_config.json in --output-dir]
+    #[arg(short = 'g', long, value_name = "gate-config-file")]
+    gate_config: Option<String>,
+
+    /// Output num instance file [default: recursive.instance_size, or
+    /// num_instance.json in --output-dir]
+    #[arg(long, value_name = "num_instance")]
+    num_instance: Option<String>,
+
+    /// Number of threads used for key generation (defaults to all available
+    /// cores)
+    #[arg(long, value_name = "num-threads")]
+    threads: Option<usize>,
+}
+
+#[derive(Debug, Parser)]
+pub struct RecurseProveParams {
+    /// Recursive circuit config file
+    #[arg(long, value_name = "recursive-config-file", default_value = RECURSIVE_CONFIG)]
+    recursive_config: String,
+
+    /// Outer circuit protocol file
+    #[arg(long, value_name = "outer-protocol-file", default_value = OUTER_PROTOCOL)]
+    outer_protocol: String,
+
+    /// Recursive circuit SRS file
+    #[arg(short = 's', long, value_name = "srs-file", default_value = RECURSIVE_SRS)]
+    srs: String,
+
+    /// Recursive circuit gate config file
+    #[arg(short = 'g', long, value_name = "gate-config-file", default_value = RECURSIVE_GATE_CONFIG)]
+    gate_config: String,
+
+    /// Recursive circuit proving key file
+    #[arg(short = 'p', long, value_name = "proving-key-file", default_value = RECURSIVE_PK)]
+    proving_key: String,
+
+    /// Outer proof files, generated by `universal-outer prove --recursive`
+    #[arg(long, value_name = "outer-proof-files")]
+    outer_proofs: Vec<String>,
+
+    /// Outer instance files
+    #[arg(long, value_name = "outer-instance-files")]
+    outer_instances: Option<Vec<String>>,
+
+    /// Output proof file
+    #[arg(long, value_name = "proof-file")]
+    proof: String,
+
+    /// Output public inputs file
+    #[arg(long, value_name = "instance-file")]
+    instance: Option<String>,
+
+    /// Output calldata file (proofs and public inputs as calldata)
+    #[arg(long, value_name = "calldata")]
+    calldata: Option<String>,
+
+    /// Number of threads used for proving (defaults to all available cores)
+    #[arg(long, value_name = "num-threads")]
+    threads: Option<usize>,
+}
+
+/// Entry point to the `recurse` series of subcommands.
+pub fn recurse(params: RecurseParams) -> Result<(), ProverError> {
+    match params.command {
+        RecurseCommand::Keygen(params) => {
+            with_thread_pool(params.threads, || recurse_keygen(params))
+        }
+        RecurseCommand::Prove(params) => {
+            with_thread_pool(params.threads, || recurse_prove(params))
+        }
+    }
+}
+
+fn recurse_keygen(params: RecurseKeygenParams) -> Result<(), ProverError> {
+    let config = RecursiveOuterConfig::new(
+        load_upa_config(&params.config)?,
+        params.num_children,
+        CircuitWithLimbsConfig::from_degree_bits(params.degree_bits),
+    );
+
+    let outputs = KeygenOutputs::new(
+        params.output_dir.as_deref(),
+        RECURSIVE_CIRCUIT_NAME,
+        &config,
+    )?;
+    let recursive_config = outputs.path(
+        &params.recursive_config,
+        OUTPUT_DIR_CONFIG,
+        RECURSIVE_CONFIG,
+    );
+    let proving_key =
+        outputs.path(&params.proving_key, OUTPUT_DIR_PK, RECURSIVE_PK);
+    let verification_key =
+        outputs.path(&params.verification_key, OUTPUT_DIR_VK, RECURSIVE_VK);
+    let gate_config_file = outputs.path(
+        &params.gate_config,
+        OUTPUT_DIR_GATE_CONFIG,
+        RECURSIVE_GATE_CONFIG,
+    );
+    let num_instance_file = outputs.path(
+        &params.num_instance,
+        OUTPUT_DIR_NUM_INSTANCE,
+        RECURSIVE_INSTANCE_SIZE,
+    );
+    let manifest = outputs.in_dir(OUTPUT_DIR_MANIFEST);
+
+    let break_points_file = break_points_file(&proving_key);
+    fail_if_file_exists(&recursive_config)?;
+    fail_if_file_exists(&proving_key)?;
+    fail_if_file_exists(&verification_key)?;
+    fail_if_file_exists(&break_points_file)?;
+    fail_if_file_exists(&gate_config_file)?;
+    fail_if_file_exists(&num_instance_file)?;
+    if let Some(manifest) = &manifest {
+        fail_if_file_exists(manifest)?;
+    }
+    let outer_protocol = load_protocol(&params.outer_protocol)?;
+    let recursive_params = load_srs(&params.srs)?;
+    let keygen_inputs =
+        RecursiveOuterKeygenInputs::new(&outer_protocol, &recursive_params);
+
+    info!("Generating Recursive PK ...");
+    let now = Instant::now();
+    let (pk, gate_config, break_points, num_instance) =
+        gen_outer_pk::<
+            SHPLONK,
+            RecursiveOuterCircuit,
+            ProverSHPLONK<Bn256>,
+            VerifierSHPLONK<Bn256>,
+        >(&config, &keygen_inputs)
+        .unwrap_or_else(|e| panic!("failed to generate recursive PK: {e}"));
+    info!("Finished Recursive PK gen in {:?}", now.elapsed());
+
+    write_json_pretty(&recursive_config, &config)?;
+    save_gate_config(&gate_config_file, &gate_config)?;
+    write_json_pretty(&num_instance_file, &num_instance)?;
+    save_break_points(&break_points_file, &break_points)?;
+    save_vk::<RecursiveCircuit>(&verification_key, pk.get_vk())?;
+    info!("Writing PK to file...");
+    save_pk::<RecursiveCircuit>(&proving_key, &pk)?;
+
+    if let Some(manifest) = &manifest {
+        KeygenDirManifest::new(RECURSIVE_CIRCUIT_NAME, &config)
+            .save(manifest)?;
+    }
+    Ok(())
+}
+
+fn recurse_prove(params: RecurseProveParams) -> Result<(), ProverError> {
+    let recursive_instance_file = instance_file(params.instance, &params.proof);
+    let calldata_file = calldata_file(params.calldata, &params.proof);
+    fail_if_file_exists(&params.proof)?;
+    fail_if_file_exists(&recursive_instance_file)?;
+    fail_if_file_exists(&calldata_file)?;
+
+    let config: RecursiveOuterConfig =
+        load_json_file(&params.recursive_config)?;
+    let outer_instance_files: Vec<String> = match params.outer_instances {
+        Some(outer_instances) => {
+            if outer_instances.len() != params.outer_proofs.len() {
+                return Err(ProverError::InvalidInput(
+                    "if present, --outer-instances must be given for all \
+                     proofs"
+                        .to_string(),
+                ));
+            }
+            outer_instances
+        }
+        None => params
+            .outer_proofs
+            .iter()
+            .map(|pf_file| instance_file(None, pf_file))
+            .collect(),
+    };
+
+    let recursive_params = load_srs(&params.srs)?;
+    let gate_config: OuterGateConfig = load_gate_config(&params.gate_config)?;
+    info!("reading Recursive PK ...");
+    let now = Instant::now();
+    let pk = {
+        let mut buf = open_file_for_read(&params.proving_key)?;
+        RecursiveCircuit::read_proving_key(&config, &gate_config, &mut buf)
+            .map_err(|e| {
+                ProverError::decode(&params.proving_key, "proving key", e)
+            })?
+    };
+    info!("Finished reading Recursive PK in {:?}", now.elapsed());
+    let break_points =
+        load_break_points(&break_points_file(&params.proving_key))?;
+
+    let inputs = {
+        let outer_protocol = load_protocol(&params.outer_protocol)?;
+        let outer_snarks: Vec<Snark> = params
+            .outer_proofs
+            .iter()
+            .zip(outer_instance_files.iter())
+            .map(|(proof_file, instance_file)| {
+                Ok(Snark::new(
+                    outer_protocol.clone(),
+                    vec![load_instance(instance_file)?],
+                    load_proof(proof_file)?,
+                ))
+            })
+            .collect::<Result<_, ProverError>>()?;
+        RecursiveOuterCircuitInputs::new(&config, outer_snarks)
+    };
+
+    info!("Computing Recursive proof...");
+    let now = Instant::now();
+    let (proof, instances) = prove_outer::<
+        SHPLONK,
+        RecursiveOuterCircuit,
+        ProverSHPLONK<Bn256>,
+        VerifierSHPLONK<Bn256>,
+    >(
+        &config,
+        &gate_config,
+        &pk,
+        break_points,
+        inputs,
+        &recursive_params,
+    );
+    info!("Finished computing Recursive proof in {:?}", now.elapsed());
+    let calldata = encode_calldata(&[instances.clone()], &proof);
+    info!("Calldata size: {:?} bytes", calldata.len());
+
+    save_proof(&params.proof, &proof)?;
+    save_instance(&recursive_instance_file, &instances)?;
+    save_calldata(&calldata_file, &calldata)
+}
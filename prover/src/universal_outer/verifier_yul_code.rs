@@ -1,19 +1,22 @@
-use super::UniversalOuterCircuit;
+use super::{Scheme, UniversalOuterCircuit};
 use crate::{
     default_values::{
         OUTER_GATE_CONFIG, OUTER_INSTANCE_SIZE, OUTER_SRS, OUTER_VERIFIER_YUL,
         OUTER_VK,
     },
-    file_utils::{load_gate_config, load_srs, panic_if_file_exists, save_yul},
+    error::ProverError,
+    file_utils::{
+        fail_if_file_exists, load_gate_config, load_json_file, load_srs,
+        open_file_for_read, save_yul,
+    },
 };
 use circuits::{
     outer::{utils::gen_outer_evm_verifier, OuterGateConfig},
-    utils::file::{load_json, open_file_for_read},
     SafeCircuit,
 };
 use clap::Parser;
 use log::info;
-use snark_verifier_sdk::SHPLONK;
+use snark_verifier_sdk::{GWC, SHPLONK};
 
 #[derive(Debug, Parser)]
 pub struct GenerateVerifierParams {
@@ -36,27 +39,46 @@ pub struct GenerateVerifierParams {
     /// Output yul code file
     #[arg(short = 'r', long, value_name = "yul-file", default_value = OUTER_VERIFIER_YUL)]
     pub(crate) yul: String,
+
+    /// Multi-open scheme of the proofs to be verified
+    #[arg(long, value_enum, default_value_t = Scheme::Shplonk)]
+    pub(crate) scheme: Scheme,
 }
 
-pub fn generate_evm_verifier(params: GenerateVerifierParams) {
-    panic_if_file_exists(&params.yul);
+pub fn generate_evm_verifier(
+    params: GenerateVerifierParams,
+) -> Result<(), ProverError> {
+    fail_if_file_exists(&params.yul)?;
 
-    let outer_params = load_srs(&params.outer_srs);
+    let outer_params = load_srs(&params.outer_srs)?;
 
     info!("Loading vk ...");
-    let gate_config: OuterGateConfig = load_gate_config(&params.gate_config);
+    let gate_config: OuterGateConfig = load_gate_config(&params.gate_config)?;
     let vk = {
-        let mut buf = open_file_for_read(&params.verification_key);
+        let mut buf = open_file_for_read(&params.verification_key)?;
         UniversalOuterCircuit::read_verifying_key(&gate_config, &mut buf)
-            .unwrap_or_else(|e| panic!("error reading vk: {e}"))
+            .map_err(|e| {
+                ProverError::decode(
+                    &params.verification_key,
+                    "verification key",
+                    e,
+                )
+            })?
     };
 
-    let num_instance: usize = load_json(&params.num_instance);
+    let num_instance: usize = load_json_file(&params.num_instance)?;
 
-    let yul_code = gen_outer_evm_verifier::<SHPLONK>(
-        &outer_params,
-        &vk,
-        vec![num_instance],
-    );
-    save_yul(&params.yul, &yul_code);
+    let yul_code = match params.scheme {
+        Scheme::Shplonk => gen_outer_evm_verifier::<SHPLONK>(
+            &outer_params,
+            &vk,
+            vec![num_instance],
+        ),
+        Scheme::Gwc => gen_outer_evm_verifier::<GWC>(
+            &outer_params,
+            &vk,
+            vec![num_instance],
+        ),
+    };
+    save_yul(&params.yul, &yul_code)
 }
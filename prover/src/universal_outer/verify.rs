@@ -1,16 +1,43 @@
+use super::{manifest::KeygenManifest, Scheme, UniversalOuterCircuit};
 use crate::{
-    default_values::{OUTER_PROOF, OUTER_VERIFIER_YUL},
+    default_values::{
+        OUTER_GATE_CONFIG, OUTER_PROOF, OUTER_SRS, OUTER_VERIFIER_YUL,
+        OUTER_VK, UPA_CONFIG,
+    },
+    error::ProverError,
     file_utils::{
-        calldata_file, instance_file, load_calldata, load_instance, load_proof,
-        load_yul,
+        calldata_file, instance_file, load_calldata, load_gate_config,
+        load_instance, load_proof, load_proof_ids, load_srs, load_upa_config,
+        load_yul, open_file_for_read,
+    },
+};
+use circuits::{
+    keccak::utils::{
+        compute_final_digest, compute_submission_id, digest_as_field_elements,
+    },
+    outer::{
+        onchain_vk::OnchainVerifyingKey,
+        outer_instance_len,
+        utils::{
+            verify_outer_onchain_equivalent, verify_outer_with_onchain_vk,
+        },
+        OuterGateConfig, OuterInstance,
     },
+    utils::upa_config::UpaConfig,
+    SafeCircuit,
 };
 use clap::Parser;
 use ethereum_types::Address;
-use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+use halo2_base::halo2_proofs::{
+    halo2curves::{
+        bn256::{Fr, G1Affine},
+        group::ff::PrimeField,
+    },
+    plonk::VerifyingKey,
+};
 use log::info;
 use snark_verifier::loader::evm::{compile_yul, ExecutorBuilder};
-use snark_verifier_sdk::evm::encode_calldata;
+use snark_verifier_sdk::{evm::encode_calldata, GWC, SHPLONK};
 use std::{
     process::{Command, Stdio},
     time::Instant,
@@ -30,10 +57,65 @@ pub struct VerifyParams {
     #[arg(short = 'i', long, value_name = "outer-instance-file")]
     pub(crate) instance: Option<String>,
 
-    /// Outer circuit calldata file
+    /// UPA configuration file, used to check the structure of the calldata
+    #[arg(long, value_name = "config-file", default_value = UPA_CONFIG)]
+    pub(crate) config: String,
+
+    /// Outer circuit calldata file (raw bytes or `0x`-prefixed hex), or `-`
+    /// to read from stdin.  If given, this calldata is verified directly
+    /// and the proof and instance files are not used.  Otherwise, the
+    /// calldata is computed from the proof and instance, and checked
+    /// against `<outer-proof-file>.calldata`.
     #[arg(short = 'c', long, value_name = "outer-calldata-file")]
     pub(crate) calldata: Option<String>,
 
+    /// JSON file containing an array of the 32-byte proof ids (hex) of the
+    /// aggregated proofs.  If given, the expected final digest (the
+    /// submission id if the config sets `output_submission_id`) is computed
+    /// from these and checked against the outer instance.
+    #[arg(long, value_name = "proof-ids-file")]
+    pub(crate) proof_ids: Option<String>,
+
+    /// Keygen manifest file.  If given, the gate config and verification
+    /// key are checked against it before verifying.
+    #[arg(long, value_name = "manifest-file")]
+    pub(crate) manifest: Option<String>,
+
+    /// Outer circuit verification key file (only used with --manifest or
+    /// --onchain-equivalent)
+    #[arg(long, value_name = "verification-key-file", default_value = OUTER_VK)]
+    pub(crate) verification_key: String,
+
+    /// Outer circuit gate config file (only used with --manifest or
+    /// --onchain-equivalent)
+    #[arg(long, value_name = "gate-config-file", default_value = OUTER_GATE_CONFIG)]
+    pub(crate) gate_config: String,
+
+    /// Outer circuit SRS file (only used with --onchain-equivalent)
+    #[arg(long, value_name = "outer-srs-file", default_value = OUTER_SRS)]
+    pub(crate) outer_srs: String,
+
+    /// Verify the proof natively, performing the same checks (including the
+    /// deferred pairing on the KZG accumulator) as the on-chain verifier,
+    /// instead of running the verifier Yul code in a simulated EVM.  This
+    /// does not require solc.
+    #[arg(long)]
+    pub(crate) onchain_equivalent: bool,
+
+    /// Verifier Yul code as deployed on-chain (as written by
+    /// `generate-verifier`).  With `--onchain-equivalent`, the verifying key
+    /// constants embedded in this code are extracted, and the proof is
+    /// verified against them instead of the commitments of the verification
+    /// key file, whose circuit structure is still used.
+    #[arg(long, value_name = "yul-file", requires = "onchain_equivalent")]
+    pub(crate) vk_onchain: Option<String>,
+
+    /// Multi-open scheme of the proof.  This must match the scheme used to
+    /// generate the verifier Yul code, and is checked against the manifest
+    /// (if given).
+    #[arg(long, value_enum, default_value_t = Scheme::Shplonk)]
+    pub(crate) scheme: Scheme,
+
     #[arg(short = 'n', long)]
     /// Load the circuit configs and exit.
     pub(crate) dry_run: bool,
@@ -68,33 +150,73 @@ pub fn check_solc_version() -> bool {
     false
 }
 
-/// Verify an Outer circuit proof in a simulated EVM.
-pub fn verify(params: VerifyParams) {
+/// Verify an Outer circuit proof in a simulated EVM, or natively with
+/// `--onchain-equivalent`.
+pub fn verify(params: VerifyParams) -> Result<(), ProverError> {
     if params.dry_run {
         info!("dry-run. Not attempting to load VK");
-        return;
+        return Ok(());
     }
 
-    assert!(check_solc_version());
+    if let Some(manifest) = &params.manifest {
+        check_keys_against_manifest(
+            manifest,
+            &params.gate_config,
+            &params.verification_key,
+            params.scheme,
+        )?;
+    }
+
+    if !params.onchain_equivalent && !check_solc_version() {
+        return Err(ProverError::InvalidInput(
+            "solc 0.8.17 is required to verify outer proofs".to_string(),
+        ));
+    }
+
+    let config = load_upa_config(&params.config)?;
+    let (calldata, outer_instance) = match &params.calldata {
+        Some(calldata_file) => load_checked_calldata(&config, calldata_file)?,
+        None => {
+            let proof = load_proof(&params.proof)?;
+            let instance_file =
+                instance_file(params.instance.clone(), &params.proof);
+            let instance: Vec<Fr> = load_instance(&instance_file)?;
+            let outer_instance = OuterInstance::from_flat(&config, &instance)
+                .map_err(|e| {
+                ProverError::decode(&instance_file, "outer instance", e)
+            })?;
+            let calldata = encode_calldata(&[outer_instance.to_flat()], &proof);
 
-    let calldata = {
-        let proof = load_proof(&params.proof);
-        let instance_file = instance_file(params.instance, &params.proof);
-        let instance: Vec<Fr> = load_instance(&instance_file);
-        encode_calldata(&[instance], &proof)
+            // Check that the calldata on file matches the proof/PIs
+            let calldata_file = calldata_file(None, &params.proof);
+            let (loaded_calldata, _) =
+                load_checked_calldata(&config, &calldata_file)?;
+            if calldata != loaded_calldata {
+                return Err(ProverError::InvalidInput(format!(
+                    "Calldata file {calldata_file} inconsistent with proof \
+                     and PIs"
+                )));
+            }
+            (calldata, outer_instance)
+        }
     };
 
-    // Check that the calldata on file matches the proof/PIs
-    {
-        let calldata_file = calldata_file(params.calldata, &params.proof);
-        let loaded_calldata = load_calldata(&calldata_file);
-        assert_eq!(
-            calldata, loaded_calldata,
-            "Calldata file inconsistent with proof and PIs"
-        );
+    if let Some(proof_ids_file) = &params.proof_ids {
+        check_final_digest(&config, proof_ids_file, &outer_instance)?;
     }
 
-    let yul_code = load_yul(&params.verifier_yul);
+    if params.onchain_equivalent {
+        let success =
+            verify_onchain_equivalent(&params, &calldata, &outer_instance)?;
+        if success {
+            println!("Proof is valid.")
+        } else {
+            println!("Proof is invalid.")
+        }
+        return Ok(());
+    }
+
+    let yul_code = load_yul(&params.verifier_yul)?;
     let byte_code = compile_yul(&yul_code);
     info!("Verifier contract size: {} bytes", byte_code.len());
 
@@ -124,4 +246,190 @@ pub fn verify(params: VerifyParams) {
     } else {
         println!("Proof is invalid.")
     }
+    Ok(())
+}
+
+/// Verifies the outer proof in `calldata`, whose public inputs are
+/// `outer_instance`, natively with [`verify_outer_onchain_equivalent`].
+/// Returns `true` if the proof would be accepted on-chain.
+fn verify_onchain_equivalent(
+    params: &VerifyParams,
+    calldata: &[u8],
+    outer_instance: &OuterInstance,
+) -> Result<bool, ProverError> {
+    let outer_srs = load_srs(&params.outer_srs)?;
+    let gate_config: OuterGateConfig = load_gate_config(&params.gate_config)?;
+    let vk = load_outer_vk(&gate_config, &params.verification_key)?;
+    let onchain_vk = params
+        .vk_onchain
+        .as_deref()
+        .map(load_onchain_vk)
+        .transpose()?;
+    let instance = outer_instance.to_flat();
+    let proof = &calldata[32 * instance.len()..];
+
+    info!("Verifying Outer Proof (on-chain equivalent)...");
+    let now = Instant::now();
+    let result = match (params.scheme, &onchain_vk) {
+        (Scheme::Shplonk, None) => verify_outer_onchain_equivalent::<SHPLONK>(
+            &outer_srs, &vk, proof, &instance,
+        ),
+        (Scheme::Gwc, None) => verify_outer_onchain_equivalent::<GWC>(
+            &outer_srs, &vk, proof, &instance,
+        ),
+        (Scheme::Shplonk, Some(onchain_vk)) => {
+            verify_outer_with_onchain_vk::<SHPLONK>(
+                &outer_srs, &vk, onchain_vk, proof, &instance,
+            )
+        }
+        (Scheme::Gwc, Some(onchain_vk)) => verify_outer_with_onchain_vk::<GWC>(
+            &outer_srs, &vk, onchain_vk, proof, &instance,
+        ),
+    };
+    info!("Finished verifying outer proof in {:?}", now.elapsed());
+    if let Err(e) = &result {
+        info!("Verification failed: {e:?}");
+    }
+    Ok(result.is_ok())
+}
+
+/// Extracts the [`OnchainVerifyingKey`] embedded in the verifier Yul code at
+/// `path`.
+fn load_onchain_vk(path: &str) -> Result<OnchainVerifyingKey, ProverError> {
+    let yul_code = load_yul(path)?;
+    OnchainVerifyingKey::from_yul(&yul_code)
+        .map_err(|e| ProverError::InvalidInput(format!("{path}: {e}")))
+}
+
+/// Loads calldata (see [`load_calldata`]) and checks that it consists of
+/// the public inputs of an outer proof for `config`, followed by a non-empty
+/// proof, all as 32-byte words.  Logs the final digest and returns the
+/// calldata and the outer instance.
+fn load_checked_calldata(
+    config: &UpaConfig,
+    calldata_file: &str,
+) -> Result<(Vec<u8>, OuterInstance), ProverError> {
+    let calldata = load_calldata(calldata_file)?;
+    let instance =
+        decode_calldata_instance(&calldata, outer_instance_len(config))
+            .map_err(|e| ProverError::decode(calldata_file, "calldata", e))?;
+    let outer_instance = OuterInstance::from_flat(config, &instance)
+        .map_err(|e| ProverError::decode(calldata_file, "outer instance", e))?;
+    info!("Final digest: {:?}", outer_instance.final_digest);
+    if let Some(num_proof_ids) = outer_instance.num_proof_ids {
+        info!("Num proof ids: {:?}", num_proof_ids);
+    }
+    if let Some(vk_digest) = outer_instance.vk_digest {
+        info!("VK digest: {:?}", vk_digest);
+    }
+    Ok((calldata, outer_instance))
+}
+
+/// Recomputes the final digest from the proof ids in `proof_ids_file` and
+/// checks that it equals the one in `outer_instance`, as well as
+/// `num_proof_ids` if it is public.
+fn check_final_digest(
+    config: &UpaConfig,
+    proof_ids_file: &str,
+    outer_instance: &OuterInstance,
+) -> Result<(), ProverError> {
+    let proof_ids = load_proof_ids(proof_ids_file)?;
+    if let Some(num_proof_ids) = outer_instance.num_proof_ids {
+        if num_proof_ids != Fr::from(proof_ids.len() as u64) {
+            return Err(ProverError::InvalidInput(format!(
+                "Public num proof ids does not match the {} given proof ids",
+                proof_ids.len()
+            )));
+        }
+    }
+    let total_batch_size =
+        (config.inner_batch_size * config.outer_batch_size) as usize;
+    let expected_digest = if config.output_submission_id {
+        // The submission id only covers the first `num_proof_ids` entries,
+        // so a partial batch may be given.
+        if proof_ids.is_empty() || proof_ids.len() > total_batch_size {
+            return Err(ProverError::InvalidInput(format!(
+                "Expected between 1 and {total_batch_size} proof ids"
+            )));
+        }
+        compute_submission_id(&proof_ids, proof_ids.len() as u64)
+    } else {
+        if proof_ids.len() != total_batch_size {
+            return Err(ProverError::InvalidInput(format!(
+                "Expected {total_batch_size} proof ids"
+            )));
+        }
+        compute_final_digest(&proof_ids)
+    };
+    if digest_as_field_elements(&expected_digest) != outer_instance.final_digest
+    {
+        return Err(ProverError::InvalidInput(
+            "Final digest does not match the given proof ids".to_string(),
+        ));
+    }
+    info!("Final digest matches the given proof ids");
+    Ok(())
+}
+
+/// Decodes the `instance_len` public inputs at the start of `calldata`,
+/// checking that the remaining bytes form a non-empty sequence of 32-byte
+/// words.
+fn decode_calldata_instance(
+    calldata: &[u8],
+    instance_len: usize,
+) -> Result<Vec<Fr>, String> {
+    if calldata.len() % 32 != 0 {
+        return Err(format!(
+            "length {} is not a multiple of 32 bytes",
+            calldata.len()
+        ));
+    }
+    let num_words = calldata.len() / 32;
+    if num_words <= instance_len {
+        return Err(format!(
+            "{num_words} words is too short for {instance_len} public inputs \
+             and a proof"
+        ));
+    }
+    calldata
+        .chunks_exact(32)
+        .take(instance_len)
+        .enumerate()
+        .map(|(i, word)| {
+            // Calldata words are big-endian
+            let mut repr = [0u8; 32];
+            repr.copy_from_slice(word);
+            repr.reverse();
+            Option::from(Fr::from_repr(repr)).ok_or_else(|| {
+                format!("public input {i} is not a valid field element")
+            })
+        })
+        .collect()
+}
+
+/// Check the gate config, VK and scheme against the keygen manifest.
+fn check_keys_against_manifest(
+    manifest_file: &str,
+    gate_config_file: &str,
+    vk_file: &str,
+    scheme: Scheme,
+) -> Result<(), ProverError> {
+    let manifest = KeygenManifest::load(manifest_file)?;
+    manifest.check_scheme(scheme)?;
+    let gate_config: OuterGateConfig = load_gate_config(gate_config_file)?;
+    manifest.check_gate_config(&gate_config)?;
+
+    let vk = load_outer_vk(&gate_config, vk_file)?;
+    manifest.check_vk(&vk)
+}
+
+/// Loads the outer circuit verification key in `vk_file`.
+fn load_outer_vk(
+    gate_config: &OuterGateConfig,
+    vk_file: &str,
+) -> Result<VerifyingKey<G1Affine>, ProverError> {
+    info!("Loading vk ...");
+    let mut buf = open_file_for_read(vk_file)?;
+    UniversalOuterCircuit::read_verifying_key(gate_config, &mut buf)
+        .map_err(|e| ProverError::decode(vk_file, "verification key", e))
 }
@@ -0,0 +1,27 @@
+use super::UniversalOuterCircuit;
+use crate::{
+    default_values::OUTER_GATE_CONFIG,
+    error::ProverError,
+    file_utils::{load_gate_config, load_upa_config},
+    vk_hash::VkHashArgs,
+};
+use circuits::outer::OuterGateConfig;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct VkHashParams {
+    #[command(flatten)]
+    pub(crate) args: VkHashArgs,
+
+    /// Circuit specs file (OuterGateConfig)
+    #[arg(short = 'g', long, value_name = "gate-config-file", default_value = OUTER_GATE_CONFIG)]
+    pub(crate) gate_config: String,
+}
+
+pub fn vk_hash(params: VkHashParams) -> Result<(), ProverError> {
+    let config = load_upa_config(&params.args.config)?;
+    let gate_config: OuterGateConfig = load_gate_config(&params.gate_config)?;
+    params
+        .args
+        .print_vk_hash::<UniversalOuterCircuit>(&config, &gate_config)
+}
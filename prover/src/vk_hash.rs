@@ -0,0 +1,54 @@
+//! Arguments shared by the `vk-hash` command of each circuit.
+
+use crate::{
+    default_values::UPA_CONFIG, error::ProverError, file_utils::load_vk_hash,
+};
+use circuits::SafeCircuit;
+use clap::Args;
+use halo2_base::halo2_proofs::halo2curves::bn256::{Fr, G1Affine};
+
+/// The UPA config and key file of a `vk-hash` command.  The gate config is
+/// an argument of each command, since its default file differs per circuit.
+#[derive(Debug, Args)]
+pub struct VkHashArgs {
+    /// UPA configuration file
+    #[arg(short = 'c', long, value_name = "config-file", default_value = UPA_CONFIG)]
+    pub(crate) config: String,
+
+    /// Verification key file.  The printed hash is the VK's transcript
+    /// representation: a digest of the domain, constraint system and fixed
+    /// and permutation commitments, computed when the VK is read, and
+    /// absorbed into every proof transcript.  It is therefore the same for
+    /// a VK file and the VK embedded in its PK, and independent of the
+    /// serialization format.
+    #[arg(
+        long = "vk",
+        value_name = "verification-key-file",
+        required_unless_present = "proving_key",
+        conflicts_with = "proving_key"
+    )]
+    pub(crate) verification_key: Option<String>,
+
+    /// Proving key file, whose embedded VK is hashed
+    #[arg(long = "pk", value_name = "proving-key-file")]
+    pub(crate) proving_key: Option<String>,
+}
+
+impl VkHashArgs {
+    /// Prints the hash of the VK or PK file of `self` (see
+    /// [`load_vk_hash`]), for the circuit `C`.
+    pub(crate) fn print_vk_hash<'a, C: SafeCircuit<'a, Fr, G1Affine>>(
+        &self,
+        circuit_config: &C::CircuitConfig,
+        gate_config: &C::GateConfig,
+    ) -> Result<(), ProverError> {
+        let vk_hash = load_vk_hash::<C>(
+            circuit_config,
+            gate_config,
+            self.verification_key.as_deref(),
+            self.proving_key.as_deref(),
+        )?;
+        println!("{vk_hash:?}");
+        Ok(())
+    }
+}